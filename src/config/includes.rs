@@ -0,0 +1,214 @@
+use super::security::ConfigSecurity;
+use super::{remote, Config, NetworkConfig};
+use anyhow::{Context, Result};
+use log::{debug, info};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use url::Url;
+
+/// Deepest chain of includes that is followed
+const MAX_DEPTH: usize = 8;
+
+/// Configuration layer read from a file or URL
+#[derive(Debug, Clone)]
+pub struct Layer {
+    /// File or URL the layer was read from
+    pub source: String,
+
+    /// Settings of the layer, without its includes
+    pub value: Value,
+}
+
+/// Configuration layers, base first, and the remote content fetched for them
+#[derive(Debug, Default)]
+pub struct Layers {
+    /// Layers in the order they are merged
+    pub layers: Vec<Layer>,
+
+    /// Remote layers, cached once the configuration has been loaded successfully
+    pub remote: Vec<(Url, remote::RemoteContent)>,
+}
+
+impl Layers {
+    /// Get the sources of the layers in the order they are merged
+    pub fn sources(&self) -> Vec<&str> {
+        self.layers.iter().map(|layer| layer.source.as_str()).collect()
+    }
+
+    /// Merge the layers, later layers overriding earlier ones
+    pub fn merge(&self) -> Value {
+        let mut merged = Value::Object(Default::default());
+        for layer in &self.layers {
+            super::profiles::merge(&mut merged, &layer.value);
+        }
+        merged
+    }
+
+    /// Find the first layer whose own settings fail a check
+    ///
+    /// Each layer is checked on top of the default configuration, so errors can name the layer
+    /// a bad value came from instead of only the merged result.
+    pub fn find_bad_layer(&self, check: impl Fn(&Config) -> Result<()>) -> Option<&str> {
+        let defaults = serde_json::to_value(super::default()).ok()?;
+        self.layers
+            .iter()
+            .find(|layer| {
+                let mut value = defaults.clone();
+                super::profiles::merge(&mut value, &layer.value);
+                serde_json::from_value::<Config>(value)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|config| check(&config))
+                    .is_err()
+            })
+            .map(|layer| layer.source.as_str())
+    }
+}
+
+/// Check whether a JSON configuration includes other configuration files
+pub fn has_includes(value: &Value) -> bool {
+    value
+        .get("includes")
+        .and_then(Value::as_array)
+        .is_some_and(|includes| !includes.is_empty())
+}
+
+/// Resolve the includes of a configuration into layers
+///
+/// Included files are merged in the order they are listed, each after its own includes, and
+/// the including configuration is merged last so its settings win. Included URLs are fetched
+/// through `network`, the settings of the configuration on disk.
+pub fn resolve(source: &str, value: Value, security: &ConfigSecurity, network: &NetworkConfig) -> Result<Layers> {
+    let mut layers = Layers::default();
+    collect(source, value, &mut layers, &mut Vec::new(), security, network)?;
+    Ok(layers)
+}
+
+/// Add the includes of a configuration and then the configuration itself to the layers
+fn collect(
+    source: &str,
+    mut value: Value,
+    layers: &mut Layers,
+    chain: &mut Vec<String>,
+    security: &ConfigSecurity,
+    network: &NetworkConfig,
+) -> Result<()> {
+    if chain.iter().any(|parent| parent == source) {
+        anyhow::bail!("Configuration include cycle: {} -> {}", chain.join(" -> "), source);
+    }
+    if chain.len() >= MAX_DEPTH {
+        anyhow::bail!("Configuration includes are nested more than {} levels deep at {}", MAX_DEPTH, source);
+    }
+
+    let includes = match value.as_object_mut().and_then(|object| object.remove("includes")) {
+        None => Vec::new(),
+        Some(Value::Array(includes)) => includes,
+        Some(_) => anyhow::bail!("'includes' in {} must be a list of files or URLs", source),
+    };
+
+    chain.push(source.to_string());
+    for include in includes {
+        let include = include
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("'includes' in {} must only contain strings", source))?;
+        let location = locate(source, include)?;
+        debug!("Including configuration {} from {}", location, source);
+
+        let content = read(&location, layers, security, network)?;
+        let included: Value = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse configuration {} included from {}", location, source))?;
+        collect(&location, included, layers, chain, security, network)?;
+    }
+    chain.pop();
+
+    layers.layers.push(Layer { source: source.to_string(), value });
+    Ok(())
+}
+
+/// Get the location of an include, relative to the configuration that includes it
+fn locate(source: &str, include: &str) -> Result<String> {
+    let include = crate::utils::expand_env_vars(include)?;
+    let is_url = |location: &str| {
+        Url::parse(location).is_ok_and(|url| matches!(url.scheme(), "http" | "https" | "file"))
+    };
+
+    if is_url(&include) || include.starts_with("\\\\") || Path::new(&include).is_absolute() {
+        return Ok(include);
+    }
+
+    if is_url(source) {
+        let base = Url::parse(source)?;
+        let url = base
+            .join(&include.replace('\\', "/"))
+            .with_context(|| format!("Invalid include {} in {}", include, source))?;
+        return Ok(url.to_string());
+    }
+
+    let directory = Path::new(source).parent().unwrap_or_else(|| Path::new(""));
+    Ok(directory.join(include).to_string_lossy().into_owned())
+}
+
+/// Read an included configuration from a file or URL, checking its signature
+fn read(location: &str, layers: &mut Layers, security: &ConfigSecurity, network: &NetworkConfig) -> Result<String> {
+    let content = match Url::parse(location) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => {
+            info!("Loading included configuration from HTTP(S) URL: {}", url);
+            security.check_url(&url)?;
+            let mut fetched = remote::fetch(&url, network)?;
+            fetched.signature = security.check_signature(location, &fetched.content, fetched.signature.as_deref())?;
+            let content = fetched.content.clone();
+            layers.remote.push((url, fetched));
+            return Ok(content);
+        }
+        Ok(url) if url.scheme() == "file" => {
+            let path = url.to_file_path().map_err(|_| anyhow::anyhow!("Invalid file URL: {}", url))?;
+            fs::read_to_string(path).with_context(|| format!("Failed to read included configuration {}", location))?
+        }
+        _ => {
+            info!("Loading included configuration from file: {}", location);
+            fs::read_to_string(location).with_context(|| format!("Failed to read included configuration {}", location))?
+        }
+    };
+
+    security.check_signature(location, &content, None)?;
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_resolve_layers() {
+        let directory = std::env::temp_dir().join(format!("rebootreminder-includes-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("base.json"), r#"{"logging": {"level": "info", "maxFiles": 7}}"#).unwrap();
+        fs::write(directory.join("site.json"), r#"{"logging": {"level": "debug"}}"#).unwrap();
+
+        let main = directory.join("config.json");
+        let value = json!({
+            "includes": ["base.json", "site.json"],
+            "logging": {"maxFiles": 3}
+        });
+        assert!(has_includes(&value));
+
+        let layers = resolve(&main.to_string_lossy(), value, &ConfigSecurity::default(), &NetworkConfig::default()).unwrap();
+        assert_eq!(layers.layers.len(), 3);
+        assert!(layers.sources()[0].ends_with("base.json"));
+        assert!(layers.sources()[2].ends_with("config.json"));
+
+        // Later layers win, and values they don't set come from earlier layers
+        let merged = layers.merge();
+        assert_eq!(merged["logging"]["level"], "debug");
+        assert_eq!(merged["logging"]["maxFiles"], 3);
+        assert!(merged.get("includes").is_none());
+
+        // A file including itself is a cycle
+        fs::write(directory.join("loop.json"), r#"{"includes": ["loop.json"]}"#).unwrap();
+        let looping = json!({"includes": ["loop.json"]});
+        assert!(resolve(&main.to_string_lossy(), looping, &ConfigSecurity::default(), &NetworkConfig::default()).is_err());
+
+        let _ = fs::remove_dir_all(&directory);
+    }
+}
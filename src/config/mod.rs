@@ -1,11 +1,15 @@
+mod includes;
 pub mod models;
+pub mod policy;
+pub mod profiles;
+mod remote;
+pub mod security;
 
 use anyhow::{Context, Result};
 use log::{debug, info, warn};
-use reqwest::blocking::Client;
+use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
-use std::time::Duration;
 use url::Url;
 
 use crate::utils::expand_env_vars;
@@ -18,6 +22,12 @@ pub fn load<P: AsRef<Path>>(path: P) -> Result<Config> {
     let path = path.as_ref();
     debug!("Loading configuration from {:?}", path);
 
+    // Remote configuration is cached once it has been loaded successfully
+    let mut remote_content: Option<(Url, remote::RemoteContent)> = None;
+
+    // Restrictions on the configuration sources set by policy
+    let security = security::ConfigSecurity::from_policy();
+
     let content = if is_url(path.to_string_lossy().as_ref()) {
         let path_str = path.to_string_lossy();
 
@@ -32,24 +42,13 @@ pub fn load<P: AsRef<Path>>(path: P) -> Result<Config> {
                 "http" | "https" => {
                     // Load from HTTP/HTTPS URL
                     info!("Loading configuration from HTTP(S) URL: {}", url);
-                    let client = Client::builder()
-                        .timeout(Duration::from_secs(30))
-                        .build()
-                        .context("Failed to create HTTP client")?;
-
-                    let response = client
-                        .get(url.as_str())
-                        .send()
-                        .context("Failed to fetch configuration from URL")?;
-
-                    if !response.status().is_success() {
-                        return Err(anyhow::anyhow!(
-                            "Failed to fetch configuration from URL: HTTP {}",
-                            response.status()
-                        ));
-                    }
-
-                    response.text().context("Failed to read configuration from URL")?
+                    security.check_url(&url)?;
+                    // The last good copy is the only local configuration there is
+                    let cached = remote::cached(&url).and_then(|cached| serde_json::from_str::<serde_json::Value>(&cached).ok());
+                    let fetched = remote::fetch(&url, &network_settings(cached.as_ref()))?;
+                    let content = fetched.content.clone();
+                    remote_content = Some((url.clone(), fetched));
+                    content
                 },
                 "file" => {
                     // Load from file:// URL
@@ -73,8 +72,36 @@ pub fn load<P: AsRef<Path>>(path: P) -> Result<Config> {
         fs::read_to_string(path).context("Failed to read configuration file")?
     };
 
+    // Refuse configurations that were modified or aren't signed by the trusted key
+    security.check_pin(&content)?;
+    let cached_signature = remote_content.as_ref().and_then(|(_, fetched)| fetched.signature.clone());
+    let signature = security.check_signature(&path.to_string_lossy(), &content, cached_signature.as_deref())?;
+    if let Some((_, fetched)) = remote_content.as_mut() {
+        fetched.signature = signature;
+    }
+
+    // Merge the layers of a configuration that includes other files
+    let mut layers = includes::Layers::default();
+    let layered = serde_json::from_str::<serde_json::Value>(&content)
+        .ok()
+        .filter(includes::has_includes);
+
     // Determine format based on file extension or content
-    let mut config = if path.extension().map_or(false, |ext| ext == "json") || is_json(&content) {
+    let mut config = if let Some(value) = layered {
+        let network = network_settings(Some(&value));
+        layers = includes::resolve(&path.to_string_lossy(), value, &security, &network)?;
+        info!("Merging configuration layers: {}", layers.sources().join(", "));
+        match serde_json::from_value::<Config>(layers.merge()) {
+            Ok(config) => config,
+            Err(e) => {
+                let e = anyhow::Error::from(e);
+                return Err(match layers.find_bad_layer(|_| Ok(())) {
+                    Some(source) => e.context(format!("Invalid value in configuration layer {}", source)),
+                    None => e.context("Failed to parse merged configuration layers"),
+                });
+            }
+        }
+    } else if path.extension().map_or(false, |ext| ext == "json") || is_json(&content) {
         // Parse JSON
         debug!("Parsing JSON configuration");
         serde_json::from_str::<Config>(&content).context("Failed to parse JSON configuration")?
@@ -95,6 +122,14 @@ pub fn load<P: AsRef<Path>>(path: P) -> Result<Config> {
         }
     };
 
+    // Apply the profile for this type of machine
+    profiles::apply(&mut config).context("Failed to apply configuration profile")?;
+
+    // Settings set by Group Policy take precedence over the file
+    if let Err(e) = policy::apply(&mut config) {
+        warn!("Failed to apply policy settings: {:#}", e);
+    }
+
     // Log the loaded configuration
     info!("Loaded configuration: {}", format_config_summary(&config));
 
@@ -110,13 +145,53 @@ pub fn load<P: AsRef<Path>>(path: P) -> Result<Config> {
     info!("  Logging path: {}", config.logging.path);
     info!("  Icon path: {}", config.notification.branding.icon_path);
 
-    // Validate configuration
-    validate_config(&config).context("Invalid configuration")?;
+    // Validate configuration, naming the layer a bad value came from
+    if let Err(e) = validate_config(&config) {
+        return Err(match layers.find_bad_layer(validate_config) {
+            Some(source) => e.context(format!("Invalid configuration in layer {}", source)),
+            None => e.context("Invalid configuration"),
+        });
+    }
+
+    // Use the network settings for HTTP clients created from now on
+    crate::utils::http::set_network_config(&config.network);
+
+    // Apply the privacy settings and per-module levels of the logging configuration
+    crate::logging::set_policy(&config.logging);
+
+    // Keep the last good remote configuration for when the URL is unreachable
+    for (url, fetched) in remote_content.iter().chain(&layers.remote) {
+        if let Err(e) = remote::store(url, fetched) {
+            warn!("Failed to cache remote configuration: {}", e);
+        }
+    }
 
     debug!("Configuration loaded successfully");
     Ok(config)
 }
 
+/// Get the network settings to fetch remote configuration with, from a configuration that hasn't
+/// been loaded yet
+///
+/// Remote configuration is fetched before the configuration it's part of is loaded, so the
+/// `network` section is read from the JSON on disk. Without one, the settings of the last loaded
+/// configuration are used.
+fn network_settings(value: Option<&serde_json::Value>) -> NetworkConfig {
+    let network = value
+        .and_then(|value| value.get("network"))
+        .and_then(|network| serde_json::from_value::<NetworkConfig>(network.clone()).ok());
+    let Some(mut network) = network else {
+        return crate::utils::http::network_config();
+    };
+
+    if let Some(path) = network.ca_bundle_path.as_mut() {
+        if let Ok(expanded) = expand_env_vars(path) {
+            *path = expanded;
+        }
+    }
+    network
+}
+
 /// Save configuration to a file
 pub fn save<P: AsRef<Path>>(config: &Config, path: P) -> Result<()> {
     let path = path.as_ref();
@@ -153,6 +228,22 @@ pub fn save<P: AsRef<Path>>(config: &Config, path: P) -> Result<()> {
     Ok(())
 }
 
+/// Generate the JSON Schema for the configuration file
+pub fn schema() -> Result<String> {
+    let schema = schemars::schema_for!(Config);
+    serde_json::to_string_pretty(&schema).context("Failed to generate configuration schema")
+}
+
+/// Get a short hash of the configuration in effect, to tell which computers run the same one
+///
+/// Keys are sorted before hashing, so the hash only changes when a value does.
+pub fn config_hash(config: &Config) -> String {
+    let value = serde_json::to_value(config).unwrap_or_default();
+    let mut hash = crate::update::sha256_hex(value.to_string().as_bytes());
+    hash.truncate(16);
+    hash
+}
+
 /// Get default configuration
 pub fn default() -> Config {
     Config {
@@ -161,6 +252,10 @@ pub fn default() -> Config {
             display_name: "Reboot Reminder Service".to_string(),
             description: "Provides notifications when system reboots are necessary".to_string(),
             config_refresh_minutes: 60,
+            dry_run: false,
+            time_zone: None,
+            registry_status: true,
+            heartbeat_interval: "1h".to_string(),
         },
         notification: NotificationConfig {
             notification_type: Some(NotificationType::Both),
@@ -171,26 +266,47 @@ pub fn default() -> Config {
                 title: "Reboot Reminder".to_string(),
                 icon_path: "icon.ico".to_string(),
                 company: "IT Department".to_string(),
+                state_icons: StateIconsConfig::default(),
+                subtitle_template: None,
+                asset_tag_registry_key: None,
+                asset_tag_registry_value: "AssetTag".to_string(),
             },
             messages: MessagesConfig {
                 reboot_required: "Your computer requires a reboot to complete recent updates.".to_string(),
                 reboot_recommended: "It is recommended to reboot your computer to apply recent updates.".to_string(),
-                reboot_scheduled: "Your computer is scheduled to reboot at %s.".to_string(),
-                reboot_in_progress: "Your computer will reboot in %s.".to_string(),
+                reboot_scheduled: "Your computer is scheduled to reboot at {deadline}.".to_string(),
+                reboot_in_progress: "{computer_name} will reboot shortly.".to_string(),
                 reboot_cancelled: "The scheduled reboot has been cancelled.".to_string(),
-                reboot_postponed: "The reboot has been postponed for %s.".to_string(),
+                reboot_postponed: "The reboot has been postponed until {next_reminder}.".to_string(),
                 reboot_completed: "Your computer has been successfully rebooted.".to_string(),
                 action_required: "Reboot is required. Click to schedule.".to_string(),
                 action_recommended: "Reboot is recommended. Click for options.".to_string(),
                 action_not_required: "No reboot is required at this time.".to_string(),
                 action_not_available: "Reboot options are not available at this time.".to_string(),
+                reboot_deferred_power: "The restart has been deferred until your computer is connected to power.".to_string(),
+                reboot_required_deadline: "Your computer requires a reboot to complete recent updates. It will restart automatically at {deadline}.".to_string(),
+                reboot_countdown: "Your computer will restart in {minutes_remaining} minutes to complete recent updates. Please save your work.".to_string(),
+                reboot_required_upgrade: "A Windows feature update is ready to install. Restarting will take longer than usual, up to an hour, so save your work and restart when you can leave your computer on.".to_string(),
             },
+            locales: HashMap::new(),
             quiet_hours: QuietHoursConfig {
                 enabled: true,
                 start_time: "22:00".to_string(),
                 end_time: "08:00".to_string(),
                 days_of_week: vec![0, 1, 2, 3, 4, 5, 6],
+                ranges: Vec::new(),
+                working_hours: None,
             },
+            post_resume_grace: Some("10m".to_string()),
+            respect_user_availability: true,
+            weekly_summary: None,
+            suppressed_accounts: Vec::new(),
+            delivery_retry: DeliveryRetryConfig::default(),
+            max_idle_time: None,
+            duplicate_interval: models::default_duplicate_interval(),
+            message_box: MessageBoxConfig::default(),
+            acknowledgment: AcknowledgmentConfig::default(),
+            window_placement: WindowPlacement::default(),
         },
         reboot: RebootConfig {
             timeframes: vec![
@@ -202,7 +318,10 @@ pub fn default() -> Config {
                     reminder_interval_hours: Some(4),
                     reminder_interval_minutes: None,
                     reminder_interval: Some("4h".to_string()),
-                    deferrals: vec!["1h".to_string(), "4h".to_string(), "8h".to_string(), "24h".to_string()],
+                    deferrals: vec!["1h".into(), "4h".into(), "8h".into(), "24h".into()],
+                    severity: NotificationSeverity::Normal,
+                    sound: Some(ToastSound::Silent),
+                    priority: ToastPriority::Normal,
                 },
                 TimeframeConfig {
                     min_hours: Some(49),
@@ -212,7 +331,10 @@ pub fn default() -> Config {
                     reminder_interval_hours: Some(2),
                     reminder_interval_minutes: None,
                     reminder_interval: Some("2h".to_string()),
-                    deferrals: vec!["1h".to_string(), "2h".to_string(), "4h".to_string()],
+                    deferrals: vec!["1h".into(), "2h".into(), "4h".into()],
+                    severity: NotificationSeverity::High,
+                    sound: None,
+                    priority: ToastPriority::High,
                 },
                 TimeframeConfig {
                     min_hours: Some(73),
@@ -222,7 +344,10 @@ pub fn default() -> Config {
                     reminder_interval_hours: None,
                     reminder_interval_minutes: Some(30),
                     reminder_interval: Some("30m".to_string()),
-                    deferrals: vec!["30m".to_string(), "1h".to_string()],
+                    deferrals: vec!["30m".into(), "1h".into()],
+                    severity: NotificationSeverity::Critical,
+                    sound: None,
+                    priority: ToastPriority::Urgent,
                 },
             ],
             detection_methods: DetectionMethodsConfig {
@@ -230,19 +355,55 @@ pub fn default() -> Config {
                 sccm: true,
                 registry: true,
                 pending_file_operations: true,
+                installer_operations: false,
+                os_upgrade: true,
+                timeout: None,
+                user_registry_keys: Vec::new(),
+                source_severities: HashMap::new(),
+                debounce_checks: HashMap::new(),
             },
             system_reboot: default_system_reboot_config(),
+            max_deferrals: None,
+            deadline: None,
+            upgrade_deadline: None,
+            use_wmi: true,
+            maintenance_windows: Vec::new(),
+            snooze_until_times: Vec::new(),
+            check_interval: "15m".to_string(),
+            startup_check_delay: "30s".to_string(),
+            idle_reboot_after: None,
+            auto_reboot_when_no_sessions: false,
+            no_sessions_grace: "15m".to_string(),
+            unattended_reboot_after: "1h".to_string(),
         },
         database: DatabaseConfig {
             path: "rebootreminder.db".to_string(),
+            retention: RetentionConfig::default(),
+            busy_timeout_ms: 5000,
+            max_connections: 4,
+            min_idle: None,
+            connection_timeout_seconds: 30,
         },
         logging: LoggingConfig {
             path: "logs/rebootreminder.log".to_string(),
             level: "info".to_string(),
             max_files: 7,
             max_size: 10,
+            log_sql: false,
+            redact_usernames: false,
+            log_level_overrides: HashMap::new(),
         },
         watchdog: WatchdogConfig::default(),
+        network: NetworkConfig::default(),
+        update: UpdateConfig::default(),
+        profiles: Vec::new(),
+        hooks: Vec::new(),
+        admin_alerts: None,
+        status_page: None,
+        intune: None,
+        log_analytics: None,
+        report_sinks: Vec::new(),
+        includes: Vec::new(),
     }
 }
 
@@ -310,6 +471,12 @@ fn format_config_summary(config: &Config) -> String {
     if config.reboot.detection_methods.pending_file_operations {
         summary.push_str("FileOps ");
     }
+    if config.reboot.detection_methods.installer_operations {
+        summary.push_str("Installers ");
+    }
+    if config.reboot.detection_methods.os_upgrade {
+        summary.push_str("Upgrade ");
+    }
 
     summary
 }
@@ -322,6 +489,9 @@ fn log_config_details(config: &Config) {
     info!("  Display Name: {}", config.service.display_name);
     info!("  Description: {}", config.service.description);
     info!("  Config Refresh Minutes: {}", config.service.config_refresh_minutes);
+    info!("  Dry Run: {}", config.service.dry_run);
+    info!("  Registry Status: {}", config.service.registry_status);
+    info!("  Time Zone: {}", config.service.time_zone.as_deref().unwrap_or("local"));
 
     // Notification configuration
     info!("Notification Configuration:");
@@ -332,6 +502,8 @@ fn log_config_details(config: &Config) {
     info!("    Title: {}", config.notification.branding.title);
     info!("    Icon Path: {}", config.notification.branding.icon_path);
     info!("    Company: {}", config.notification.branding.company);
+    info!("    Subtitle Template: {:?}", config.notification.branding.subtitle_template);
+    info!("    Asset Tag Registry Key: {:?}", config.notification.branding.asset_tag_registry_key);
 
     // Messages
     info!("  Messages:");
@@ -346,13 +518,42 @@ fn log_config_details(config: &Config) {
     info!("    Action Recommended: {}", config.notification.messages.action_recommended);
     info!("    Action Not Required: {}", config.notification.messages.action_not_required);
     info!("    Action Not Available: {}", config.notification.messages.action_not_available);
+    info!("    Reboot Deferred (Power): {}", config.notification.messages.reboot_deferred_power);
+    info!("    Reboot Required (Deadline): {}", config.notification.messages.reboot_required_deadline);
+    info!("    Reboot Countdown: {}", config.notification.messages.reboot_countdown);
+    info!("    Reboot Required (Upgrade): {}", config.notification.messages.reboot_required_upgrade);
 
     // Quiet Hours
     info!("  Quiet Hours:");
     info!("    Enabled: {}", config.notification.quiet_hours.enabled);
-    info!("    Start Time: {}", config.notification.quiet_hours.start_time);
-    info!("    End Time: {}", config.notification.quiet_hours.end_time);
-    info!("    Days of Week: {:?}", config.notification.quiet_hours.days_of_week);
+    for range in config.notification.quiet_hours.effective_ranges() {
+        info!("    Range: {}-{} starting on days {:?}", range.start, range.end, range.days);
+    }
+    if let Some(working_hours) = &config.notification.quiet_hours.working_hours {
+        info!("    Working Hours Registry Key: {:?}", working_hours.registry_key);
+        info!("    Working Hours Registry Value: {}", working_hours.registry_value);
+        info!("    Working Hours Environment Variable: {:?}", working_hours.environment_variable);
+    }
+    info!("  Post-Resume Grace: {}", config.notification.post_resume_grace.as_deref().unwrap_or("None"));
+    info!("  Respect User Availability: {}", config.notification.respect_user_availability);
+    info!("  Max Idle Time: {}", config.notification.max_idle_time.as_deref().unwrap_or("None"));
+    info!("  Duplicate Interval: {}", config.notification.duplicate_interval.as_deref().unwrap_or("None"));
+    info!("  Message Box: timeout={}, capture response={}",
+          config.notification.message_box.timeout.as_deref().unwrap_or("None"),
+          config.notification.message_box.capture_response);
+    info!("  Acknowledgment: required={}, reprompt interval={}",
+          config.notification.acknowledgment.required,
+          config.notification.acknowledgment.reprompt_interval);
+    info!("  Window Placement: {:?}", config.notification.window_placement);
+    info!("  Suppressed Accounts: {:?}", config.notification.suppressed_accounts);
+    info!("  Delivery Retry: {} attempts, {} to {} apart",
+          config.notification.delivery_retry.max_attempts,
+          config.notification.delivery_retry.initial_delay,
+          config.notification.delivery_retry.max_delay);
+    match &config.notification.weekly_summary {
+        Some(summary) => info!("  Weekly Summary: day {} at {}", summary.day_of_week, summary.time),
+        None => info!("  Weekly Summary: Disabled"),
+    }
 
     // Reboot configuration
     info!("Reboot Configuration:");
@@ -390,7 +591,37 @@ fn log_config_details(config: &Config) {
         }
 
         info!("    Deferrals: {:?}", timeframe.deferrals);
+        info!("    Severity: {:?}", timeframe.severity);
+        info!("    Sound: {:?}", timeframe.sound.unwrap_or_else(|| ToastSound::for_severity(timeframe.severity)));
+        info!("    Priority: {:?}", timeframe.priority);
+    }
+
+    match config.reboot.max_deferrals {
+        Some(max) => info!("  Max Deferrals: {}", max),
+        None => info!("  Max Deferrals: None (unlimited)"),
+    }
+
+    match &config.reboot.deadline {
+        Some(deadline) => info!("  Deadline: {}", deadline),
+        None => info!("  Deadline: None"),
+    }
+    if let Some(deadline) = &config.reboot.upgrade_deadline {
+        info!("  Upgrade Deadline: {}", deadline);
+    }
+    for window in &config.reboot.maintenance_windows {
+        info!("  Maintenance Window: {} - {} on days {:?}", window.start_time, window.end_time, window.days_of_week);
+    }
+    if !config.reboot.snooze_until_times.is_empty() {
+        info!("  Snooze Until Times: {:?}", config.reboot.snooze_until_times);
     }
+    info!("  Check Interval: {}", config.reboot.check_interval);
+    info!("  Startup Check Delay: {}", config.reboot.startup_check_delay);
+    info!("  Use WMI: {}", config.reboot.use_wmi);
+    info!("  Idle Reboot After: {}", config.reboot.idle_reboot_after.as_deref().unwrap_or("None"));
+    info!("  Auto Reboot When No Sessions: {} (after {}, no sessions for {})",
+          config.reboot.auto_reboot_when_no_sessions,
+          config.reboot.unattended_reboot_after,
+          config.reboot.no_sessions_grace);
 
     // Detection Methods
     info!("  Detection Methods:");
@@ -398,6 +629,10 @@ fn log_config_details(config: &Config) {
     info!("    SCCM: {}", config.reboot.detection_methods.sccm);
     info!("    Registry: {}", config.reboot.detection_methods.registry);
     info!("    Pending File Operations: {}", config.reboot.detection_methods.pending_file_operations);
+    info!("    Installer Operations: {}", config.reboot.detection_methods.installer_operations);
+    info!("    OS Upgrade: {}", config.reboot.detection_methods.os_upgrade);
+    info!("    Timeout: {}", config.reboot.detection_methods.timeout.as_deref().unwrap_or("30s (default)"));
+    info!("    Debounce Checks: {:?}", config.reboot.detection_methods.debounce_checks);
 
     // System Reboot
     info!("  System Reboot:");
@@ -415,10 +650,35 @@ fn log_config_details(config: &Config) {
     info!("    Show Confirmation: {}", config.reboot.system_reboot.show_confirmation);
     info!("    Confirmation Message: {}", config.reboot.system_reboot.confirmation_message);
     info!("    Confirmation Title: {}", config.reboot.system_reboot.confirmation_title);
+    info!("    Confirmation Timeout: {}", config.reboot.system_reboot.confirmation_timeout);
+    info!("    Require AC Power: {}", config.reboot.system_reboot.require_ac_power);
+    info!("    Min Battery Percent: {:?}", config.reboot.system_reboot.min_battery_percent);
+    info!("    Blocking Processes: {:?}", config.reboot.system_reboot.blocking_processes);
+    info!("    Verify Window: {}", config.reboot.system_reboot.verify_window);
+    info!("    Blocked Reboot Delay: {}", config.reboot.system_reboot.blocked_reboot_delay);
+    info!("    Strategy: {:?}", config.reboot.system_reboot.strategy);
+    info!("    Shutdown Message: {}", config.reboot.system_reboot.shutdown_message);
+    info!("    Force Apps Closed: {}", config.reboot.system_reboot.force_apps_closed);
+    info!("    List Open Applications: {}", config.reboot.system_reboot.list_open_applications);
+    info!("    Restart Applications: {}", config.reboot.system_reboot.restart_applications);
+    info!("    Reason Code: {}", config.reboot.system_reboot.reason_code);
 
     // Database configuration
     info!("Database Configuration:");
     info!("  Path: {}", config.database.path);
+    info!("  Notification Retention: {} days", config.database.retention.notification_days);
+    info!("  Max Reboot History: {}", config.database.retention.max_reboot_history);
+    match config.database.retention.max_size_mb {
+        Some(size) => info!("  Max Size: {} MB", size),
+        None => info!("  Max Size: None (unlimited)"),
+    }
+    info!("  Maintenance Interval: {}", config.database.retention.interval);
+    info!("  Busy Timeout: {} ms", config.database.busy_timeout_ms);
+    info!("  Max Connections: {}", config.database.max_connections);
+    if let Some(min_idle) = config.database.min_idle {
+        info!("  Min Idle Connections: {}", min_idle);
+    }
+    info!("  Connection Timeout: {} seconds", config.database.connection_timeout_seconds);
 
     // Logging configuration
     info!("Logging Configuration:");
@@ -426,6 +686,11 @@ fn log_config_details(config: &Config) {
     info!("  Level: {}", config.logging.level);
     info!("  Max Files: {}", config.logging.max_files);
     info!("  Max Size: {} MB", config.logging.max_size);
+    info!("  Log SQL: {}", config.logging.log_sql);
+    info!("  Redact User Names: {}", config.logging.redact_usernames);
+    for (module, level) in &config.logging.log_level_overrides {
+        info!("  Level Override: {} = {}", module, level);
+    }
 
     // Watchdog configuration
     info!("Watchdog Configuration:");
@@ -453,6 +718,79 @@ fn log_config_details(config: &Config) {
 
     info!("  Service Path: {}", config.watchdog.service_path);
     info!("  Service Name: {}", config.watchdog.service_name);
+    info!("  Restore Start Type: {}", config.watchdog.restore_start_type);
+
+    // Network configuration
+    info!("Network Configuration:");
+    info!("  Proxy URL: {}", config.network.proxy_url.as_deref().unwrap_or("None"));
+    info!("  Proxy Username: {}", config.network.proxy_username.as_deref().unwrap_or("None"));
+    info!("  Proxy Password: {}", if config.network.proxy_password.is_some() { "<set>" } else { "None" });
+    info!("  CA Bundle Path: {}", config.network.ca_bundle_path.as_deref().unwrap_or("None"));
+    info!("  Verify TLS: {}", config.network.verify_tls);
+
+    // Update configuration
+    info!("Update Configuration:");
+    info!("  Enabled: {}", config.update.enabled);
+    info!("  Manifest URL: {}", config.update.manifest_url.as_deref().unwrap_or("None"));
+    info!("  Check Interval: {}", config.update.check_interval);
+    info!("  Manifest Public Key: {}", if config.update.public_key.is_some() { "Configured" } else { "None" });
+    info!("  Require Signature: {}", config.update.require_signature);
+    info!("  Signer Thumbprints: {}", config.update.signer_thumbprints.join(", "));
+
+    // Profiles
+    info!("Profiles: {} defined", config.profiles.len());
+    for profile in &config.profiles {
+        info!("  Profile {}: {:?}", profile.name, profile.criteria);
+    }
+
+    // Admin alerts
+    match &config.admin_alerts {
+        Some(alerts) => info!(
+            "Admin Alerts: {:?} webhook, pending threshold {}, after {} failed reboots",
+            alerts.format, alerts.pending_threshold, alerts.max_failed_reboots
+        ),
+        None => info!("Admin Alerts: Disabled"),
+    }
+
+    // Status page
+    match &config.status_page {
+        Some(status_page) => info!("Status Page: http://localhost:{}/", status_page.port),
+        None => info!("Status Page: Disabled"),
+    }
+
+    // Intune reporting
+    match &config.intune {
+        Some(intune) => info!(
+            "Intune: tenant {}, client {}, extension attribute {}, overdue after {:?}, refresh every {}",
+            intune.tenant_id, intune.client_id, intune.extension_attribute, intune.overdue_after, intune.refresh_interval
+        ),
+        None => info!("Intune: Disabled"),
+    }
+
+    // Log Analytics
+    match &config.log_analytics {
+        Some(log_analytics) => info!(
+            "Log Analytics: workspace {}, log {}, events {:?}, batches of {} every {}",
+            log_analytics.workspace_id,
+            log_analytics.log_type,
+            log_analytics.events,
+            log_analytics.batch_size,
+            log_analytics.batch_interval
+        ),
+        None => info!("Log Analytics: Disabled"),
+    }
+
+    // Report sinks
+    info!("Report Sinks: {} defined, heartbeat every {}", config.report_sinks.len(), config.service.heartbeat_interval);
+    for sink in &config.report_sinks {
+        info!("  {:?}: events {:?}, batches of {} every {}", sink.sink, sink.events, sink.batch_size, sink.batch_interval);
+    }
+
+    // Hooks
+    info!("Hooks: {} defined", config.hooks.len());
+    for hook in &config.hooks {
+        info!("  Hook for {:?}: command={:?}, url={:?}", hook.events, hook.command, hook.url);
+    }
 }
 
 /// Validate configuration
@@ -467,6 +805,7 @@ fn validate_config(config: &Config) -> Result<()> {
     if config.service.config_refresh_minutes == 0 {
         return Err(anyhow::anyhow!("Config refresh minutes must be greater than 0"));
     }
+    crate::utils::schedule::ScheduleZone::parse(config.service.time_zone.as_deref())?;
 
     // Validate notification configuration
     if config.notification.branding.title.is_empty() {
@@ -475,30 +814,215 @@ fn validate_config(config: &Config) -> Result<()> {
     if config.notification.branding.icon_path.is_empty() {
         return Err(anyhow::anyhow!("Notification icon path cannot be empty"));
     }
+    if config.notification.branding.asset_tag_registry_key.is_some()
+        && config.notification.branding.asset_tag_registry_value.is_empty()
+    {
+        return Err(anyhow::anyhow!("Asset tag registry value cannot be empty"));
+    }
 
     // Validate quiet hours
     if config.notification.quiet_hours.enabled {
-        // Validate time format (HH:MM)
-        if !is_valid_time_format(&config.notification.quiet_hours.start_time) {
+        for range in config.notification.quiet_hours.effective_ranges() {
+            // Validate time format (HH:MM)
+            if !is_valid_time_format(&range.start) {
+                return Err(anyhow::anyhow!(
+                    "Invalid quiet hours start time format: {}. Expected HH:MM",
+                    range.start
+                ));
+            }
+            if !is_valid_time_format(&range.end) {
+                return Err(anyhow::anyhow!(
+                    "Invalid quiet hours end time format: {}. Expected HH:MM",
+                    range.end
+                ));
+            }
+
+            // Validate days of week (0-6)
+            for day in &range.days {
+                if *day > 6 {
+                    return Err(anyhow::anyhow!(
+                        "Invalid day of week: {}. Expected 0-6",
+                        day
+                    ));
+                }
+            }
+        }
+    }
+
+    // Validate working hours sources
+    if let Some(working_hours) = &config.notification.quiet_hours.working_hours {
+        if working_hours.registry_key.is_none() && working_hours.environment_variable.is_none() {
+            return Err(anyhow::anyhow!(
+                "Working hours require a registry key or an environment variable"
+            ));
+        }
+        if working_hours.registry_key.is_some() && working_hours.registry_value.is_empty() {
+            return Err(anyhow::anyhow!("Working hours registry value cannot be empty"));
+        }
+    }
+
+    // Validate weekly summary
+    if let Some(summary) = &config.notification.weekly_summary {
+        if summary.day_of_week > 6 {
             return Err(anyhow::anyhow!(
-                "Invalid quiet hours start time format: {}. Expected HH:MM",
-                config.notification.quiet_hours.start_time
+                "Invalid weekly summary day of week: {}. Expected 0-6",
+                summary.day_of_week
             ));
         }
-        if !is_valid_time_format(&config.notification.quiet_hours.end_time) {
+        if !is_valid_time_format(&summary.time) {
             return Err(anyhow::anyhow!(
-                "Invalid quiet hours end time format: {}. Expected HH:MM",
-                config.notification.quiet_hours.end_time
+                "Invalid weekly summary time format: {}. Expected HH:MM",
+                summary.time
             ));
         }
+    }
 
-        // Validate days of week (0-6)
-        for day in &config.notification.quiet_hours.days_of_week {
-            if *day > 6 {
-                return Err(anyhow::anyhow!(
-                    "Invalid day of week: {}. Expected 0-6",
-                    day
-                ));
+    // Validate suppressed account patterns
+    if config.notification.suppressed_accounts.iter().any(|pattern| pattern.trim().is_empty()) {
+        return Err(anyhow::anyhow!("Suppressed account patterns cannot be empty"));
+    }
+
+    // Validate delivery retries
+    let retry = &config.notification.delivery_retry;
+    if retry.max_attempts == 0 {
+        return Err(anyhow::anyhow!("Delivery retry max attempts must be at least 1"));
+    }
+    for delay in [&retry.initial_delay, &retry.max_delay] {
+        if let Err(e) = crate::utils::timespan::parse_timespan(delay) {
+            return Err(anyhow::anyhow!("Invalid delivery retry delay '{}': {}", delay, e));
+        }
+    }
+
+    // Validate post-resume grace period
+    if let Some(grace) = &config.notification.post_resume_grace {
+        if let Err(e) = crate::utils::timespan::parse_timespan(grace) {
+            return Err(anyhow::anyhow!("Invalid post-resume grace timespan '{}': {}", grace, e));
+        }
+    }
+
+    // Validate idle times
+    for (name, timespan) in [
+        ("max idle time", &config.notification.max_idle_time),
+        ("duplicate interval", &config.notification.duplicate_interval),
+        ("message box timeout", &config.notification.message_box.timeout),
+        ("acknowledgment reprompt", &Some(config.notification.acknowledgment.reprompt_interval.clone())),
+        ("idle reboot", &config.reboot.idle_reboot_after),
+        ("no sessions grace", &Some(config.reboot.no_sessions_grace.clone())),
+        ("unattended reboot", &Some(config.reboot.unattended_reboot_after.clone())),
+        ("reboot verify window", &Some(config.reboot.system_reboot.verify_window.clone())),
+        ("reboot confirmation timeout", &Some(config.reboot.system_reboot.confirmation_timeout.clone())),
+    ] {
+        if let Some(timespan) = timespan {
+            if let Err(e) = crate::utils::timespan::parse_timespan(timespan) {
+                return Err(anyhow::anyhow!("Invalid {} timespan '{}': {}", name, timespan, e));
+            }
+        }
+    }
+
+    // Validate configuration profiles
+    profiles::validate(&config.profiles)?;
+
+    // Validate admin alerts
+    if let Some(alerts) = &config.admin_alerts {
+        if let Err(e) = Url::parse(alerts.webhook_url.expose()) {
+            return Err(anyhow::anyhow!("Invalid admin alert webhook URL: {}", e));
+        }
+        if let Err(e) = crate::utils::timespan::parse_timespan(&alerts.pending_threshold) {
+            return Err(anyhow::anyhow!(
+                "Invalid admin alert pending threshold '{}': {}",
+                alerts.pending_threshold, e
+            ));
+        }
+        if alerts.max_failed_reboots == 0 {
+            return Err(anyhow::anyhow!("Admin alert max failed reboots must be at least 1"));
+        }
+    }
+
+    // Validate Intune reporting
+    if let Some(intune) = &config.intune {
+        if intune.tenant_id.trim().is_empty() || intune.client_id.trim().is_empty() {
+            return Err(anyhow::anyhow!("Intune tenant ID and client ID cannot be empty"));
+        }
+        if !(1..=15).contains(&intune.extension_attribute) {
+            return Err(anyhow::anyhow!(
+                "Intune extension attribute must be between 1 and 15, got {}",
+                intune.extension_attribute
+            ));
+        }
+        for timespan in intune.overdue_after.iter().chain(std::iter::once(&intune.refresh_interval)) {
+            if let Err(e) = crate::utils::timespan::parse_timespan(timespan) {
+                return Err(anyhow::anyhow!("Invalid Intune timespan '{}': {}", timespan, e));
+            }
+        }
+    }
+
+    // Validate report sinks, including the Log Analytics section
+    for (i, sink) in config.configured_report_sinks().iter().enumerate() {
+        match &sink.sink {
+            ReportSinkKind::Webhook { url, .. } => {
+                if let Err(e) = Url::parse(url) {
+                    return Err(anyhow::anyhow!("Report sink {}: invalid URL '{}': {}", i, url, e));
+                }
+            }
+            ReportSinkKind::LogAnalytics { workspace_id, log_type, .. } => {
+                if workspace_id.trim().is_empty() {
+                    return Err(anyhow::anyhow!("Report sink {}: Log Analytics workspace ID cannot be empty", i));
+                }
+                if !log_type.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') || log_type.is_empty() {
+                    return Err(anyhow::anyhow!(
+                        "Report sink {}: Log Analytics log type '{}' may only contain letters, digits and underscores",
+                        i, log_type
+                    ));
+                }
+            }
+            ReportSinkKind::File { path } => {
+                if path.trim().is_empty() {
+                    return Err(anyhow::anyhow!("Report sink {}: file path cannot be empty", i));
+                }
+            }
+            ReportSinkKind::EventLog => {}
+        }
+        if sink.batch_size == 0 {
+            return Err(anyhow::anyhow!("Report sink {}: batch size must be at least 1", i));
+        }
+        if let Err(e) = crate::utils::timespan::parse_timespan(&sink.batch_interval) {
+            return Err(anyhow::anyhow!("Report sink {}: invalid batch interval '{}': {}", i, sink.batch_interval, e));
+        }
+    }
+    if let Err(e) = crate::utils::timespan::parse_timespan(&config.service.heartbeat_interval) {
+        return Err(anyhow::anyhow!("Invalid heartbeat interval '{}': {}", config.service.heartbeat_interval, e));
+    }
+
+    // Validate the status page
+    if config.status_page.as_ref().is_some_and(|status_page| status_page.port == 0) {
+        return Err(anyhow::anyhow!("Status page port cannot be 0"));
+    }
+
+    // Validate crash reports
+    if let Some(crash_report) = &config.watchdog.crash_report {
+        if let Err(e) = Url::parse(&crash_report.url) {
+            return Err(anyhow::anyhow!("Invalid crash report URL '{}': {}", crash_report.url, e));
+        }
+        for (name, value) in [("window", &crash_report.window), ("minimum interval", &crash_report.min_interval)] {
+            if let Err(e) = crate::utils::timespan::parse_timespan(value) {
+                return Err(anyhow::anyhow!("Invalid crash report {} '{}': {}", name, value, e));
+            }
+        }
+    }
+
+    // Validate hooks
+    for (i, hook) in config.hooks.iter().enumerate() {
+        if hook.command.is_none() && hook.url.is_none() {
+            return Err(anyhow::anyhow!("Hook {}: a command or a URL is required", i));
+        }
+        if let Some(url) = &hook.url {
+            if let Err(e) = Url::parse(url) {
+                return Err(anyhow::anyhow!("Hook {}: invalid URL '{}': {}", i, url, e));
+            }
+        }
+        if let Some(timeout) = &hook.timeout {
+            if let Err(e) = crate::utils::timespan::parse_timespan(timeout) {
+                return Err(anyhow::anyhow!("Hook {}: invalid timeout '{}': {}", i, timeout, e));
             }
         }
     }
@@ -565,20 +1089,132 @@ fn validate_config(config: &Config) -> Result<()> {
             ));
         }
         for deferral in &timeframe.deferrals {
-            if !is_valid_duration_format(deferral) {
-                return Err(anyhow::anyhow!(
-                    "Timeframe {}: Invalid deferral format: {}. Expected format: 1h, 30m, etc.",
-                    i,
-                    deferral
-                ));
+            let (duration, until_time) = match deferral {
+                DeferralOption::Duration(duration) => (Some(duration), None),
+                DeferralOption::Preset(preset) => {
+                    if preset.label.trim().is_empty() {
+                        return Err(anyhow::anyhow!("Timeframe {}: Deferral presets need a label", i));
+                    }
+                    if preset.duration.is_some() == preset.until_time.is_some() {
+                        return Err(anyhow::anyhow!(
+                            "Timeframe {}: Deferral preset '{}' needs either a duration or an until time",
+                            i,
+                            preset.label
+                        ));
+                    }
+                    (preset.duration.as_ref(), preset.until_time.as_ref())
+                }
+            };
+            if let Some(duration) = duration {
+                if !is_valid_duration_format(duration) {
+                    return Err(anyhow::anyhow!(
+                        "Timeframe {}: Invalid deferral format: {}. Expected format: 1h, 30m, etc.",
+                        i,
+                        duration
+                    ));
+                }
+            }
+            if let Some(time) = until_time {
+                if !is_valid_time_format(time) {
+                    return Err(anyhow::anyhow!(
+                        "Timeframe {}: Invalid deferral until time: {}. Expected HH:MM",
+                        i,
+                        time
+                    ));
+                }
             }
         }
     }
 
+    // Validate system reboot power requirements
+    if let Some(percent) = config.reboot.system_reboot.min_battery_percent {
+        if percent > 100 {
+            return Err(anyhow::anyhow!("Invalid minimum battery percent: {}. Expected 0-100", percent));
+        }
+    }
+
+    // Validate blocked reboot delay
+    if crate::utils::timespan::parse_timespan(&config.reboot.system_reboot.blocked_reboot_delay).is_err() {
+        return Err(anyhow::anyhow!(
+            "Invalid blocked reboot delay: {}. Expected format: 1h, 30m, etc.",
+            config.reboot.system_reboot.blocked_reboot_delay
+        ));
+    }
+
+    // Validate the shutdown reason code
+    if let Err(e) = crate::reboot::system::parse_reason_code(&config.reboot.system_reboot.reason_code) {
+        return Err(anyhow::anyhow!(
+            "Invalid shutdown reason code '{}': {}",
+            config.reboot.system_reboot.reason_code,
+            e
+        ));
+    }
+
+    // Validate the detection timeout
+    if let Some(timeout) = &config.reboot.detection_methods.timeout {
+        if let Err(e) = crate::utils::timespan::parse_timespan(timeout) {
+            return Err(anyhow::anyhow!("Invalid detection timeout '{}': {}", timeout, e));
+        }
+    }
+    if let Some((name, _)) = config.reboot.detection_methods.debounce_checks.iter().find(|(_, checks)| **checks == 0) {
+        return Err(anyhow::anyhow!("Debounce checks of source '{}' must be at least 1", name));
+    }
+
+    // Validate the reboot deadline and maintenance windows
+    if let Some(deadline) = &config.reboot.deadline {
+        if let Err(e) = crate::utils::timespan::parse_timespan(deadline) {
+            return Err(anyhow::anyhow!("Invalid reboot deadline '{}': {}", deadline, e));
+        }
+    }
+    if let Some(deadline) = &config.reboot.upgrade_deadline {
+        if let Err(e) = crate::utils::timespan::parse_timespan(deadline) {
+            return Err(anyhow::anyhow!("Invalid upgrade deadline '{}': {}", deadline, e));
+        }
+    }
+    for (i, window) in config.reboot.maintenance_windows.iter().enumerate() {
+        if !is_valid_time_format(&window.start_time) || !is_valid_time_format(&window.end_time) {
+            return Err(anyhow::anyhow!(
+                "Maintenance window {}: Invalid time format {} - {}. Expected HH:MM",
+                i, window.start_time, window.end_time
+            ));
+        }
+        if window.days_of_week.iter().any(|day| *day > 6) {
+            return Err(anyhow::anyhow!(
+                "Maintenance window {}: Invalid day of week {:?}. Expected 0-6",
+                i, window.days_of_week
+            ));
+        }
+    }
+    for time in &config.reboot.snooze_until_times {
+        if !is_valid_time_format(time) {
+            return Err(anyhow::anyhow!("Invalid snooze until time: {}. Expected HH:MM", time));
+        }
+    }
+    match crate::utils::timespan::parse_timespan(&config.reboot.check_interval) {
+        Ok(interval) if interval.as_secs() >= 60 => {}
+        Ok(_) => return Err(anyhow::anyhow!("Reboot check interval must be at least 1 minute")),
+        Err(e) => return Err(anyhow::anyhow!("Invalid reboot check interval {}: {}", config.reboot.check_interval, e)),
+    }
+    if let Err(e) = crate::utils::timespan::parse_timespan(&config.reboot.startup_check_delay) {
+        return Err(anyhow::anyhow!("Invalid startup check delay {}: {}", config.reboot.startup_check_delay, e));
+    }
+
     // Validate database configuration
     if config.database.path.is_empty() {
         return Err(anyhow::anyhow!("Database path cannot be empty"));
     }
+    if config.database.max_connections == 0 {
+        return Err(anyhow::anyhow!("Max database connections must be greater than 0"));
+    }
+    if config.database.min_idle.is_some_and(|min_idle| min_idle > config.database.max_connections) {
+        return Err(anyhow::anyhow!("Min idle database connections cannot exceed max connections"));
+    }
+    if crate::utils::timespan::parse_timespan(&config.database.retention.interval).is_err() {
+        return Err(anyhow::anyhow!(
+            "Invalid database maintenance interval: {}. Expected format: 24h, 12h, etc.",
+            config.database.retention.interval
+        ));
+    }
 
     // Validate logging configuration
     if config.logging.path.is_empty() {
@@ -596,6 +1232,55 @@ fn validate_config(config: &Config) -> Result<()> {
             config.logging.level
         ));
     }
+    for (module, level) in &config.logging.log_level_overrides {
+        if module.trim().is_empty() {
+            return Err(anyhow::anyhow!("Log level override module cannot be empty"));
+        }
+        if crate::logging::parse_level(level).is_none() {
+            return Err(anyhow::anyhow!(
+                "Invalid log level for {}: {}. Expected off, trace, debug, info, warn, or error",
+                module,
+                level
+            ));
+        }
+    }
+
+    // Validate update configuration
+    if config.update.enabled {
+        match &config.update.manifest_url {
+            Some(url) => match Url::parse(url) {
+                Ok(parsed) if parsed.scheme() != "https" => {
+                    return Err(anyhow::anyhow!("Update manifest URL '{}' must use HTTPS", url));
+                }
+                Ok(_) => {}
+                Err(e) => return Err(anyhow::anyhow!("Invalid update manifest URL '{}': {}", url, e)),
+            },
+            None => return Err(anyhow::anyhow!("An update manifest URL is required when updates are enabled")),
+        }
+        if config.update.public_key.as_deref().map_or(true, |key| key.trim().is_empty()) {
+            return Err(anyhow::anyhow!("A public key for the update manifest signature is required when updates are enabled"));
+        }
+        if config.update.require_signature && config.update.signer_thumbprints.is_empty() {
+            return Err(anyhow::anyhow!(
+                "At least one signer thumbprint is required when updates must be signed"
+            ));
+        }
+        for thumbprint in &config.update.signer_thumbprints {
+            let hex = crate::update::signature::normalize_thumbprint(thumbprint);
+            if hex.len() != 64 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(anyhow::anyhow!(
+                    "Invalid signer thumbprint '{}'. Expected a SHA-256 thumbprint of 64 hex characters",
+                    thumbprint
+                ));
+            }
+        }
+    }
+    if crate::utils::timespan::parse_timespan(&config.update.check_interval).is_err() {
+        return Err(anyhow::anyhow!(
+            "Invalid update check interval: {}. Expected format: 24h, 12h, etc.",
+            config.update.check_interval
+        ));
+    }
 
     Ok(())
 }
@@ -615,35 +1300,51 @@ fn is_url(s: &str) -> bool {
     }
 }
 
-/// Expand environment variables in configuration paths
+/// Expand environment variables in every string of the configuration
+///
+/// The configuration is walked as JSON, so message templates, URLs and other settings can use
+/// `%VARIABLE%` references as well as paths. Strings without a `%` are left untouched.
 fn expand_env_vars_in_config(config: &mut Config) -> Result<()> {
-    debug!("Expanding environment variables in configuration paths");
-
-    // Expand database path
-    if config.database.path.contains('%') {
-        config.database.path = expand_env_vars(&config.database.path)?;
-        debug!("Expanded database path: {}", config.database.path);
-    }
+    debug!("Expanding environment variables in configuration");
 
-    // Expand logging path
-    if config.logging.path.contains('%') {
-        config.logging.path = expand_env_vars(&config.logging.path)?;
-        debug!("Expanded logging path: {}", config.logging.path);
+    let mut value = serde_json::to_value(&*config).context("Failed to serialize configuration")?;
+    if expand_env_vars_in_value(&mut value, "")? > 0 {
+        *config = serde_json::from_value(value).context("Failed to read expanded configuration")?;
     }
 
-    // Expand notification icon path
-    if config.notification.branding.icon_path.contains('%') {
-        config.notification.branding.icon_path = expand_env_vars(&config.notification.branding.icon_path)?;
-        debug!("Expanded icon path: {}", config.notification.branding.icon_path);
-    }
+    Ok(())
+}
 
-    // Expand watchdog service path if it's not empty
-    if !config.watchdog.service_path.is_empty() && config.watchdog.service_path.contains('%') {
-        config.watchdog.service_path = expand_env_vars(&config.watchdog.service_path)?;
-        debug!("Expanded watchdog service path: {}", config.watchdog.service_path);
+/// Expand environment variables in the strings of a JSON value, returning how many changed
+///
+/// Only the paths of expanded settings are logged, since secrets are strings too.
+fn expand_env_vars_in_value(value: &mut serde_json::Value, path: &str) -> Result<usize> {
+    use serde_json::Value;
+
+    match value {
+        Value::String(text) if text.contains('%') => {
+            let expanded = expand_env_vars(text).with_context(|| format!("Failed to expand {}", path))?;
+            if expanded == *text {
+                return Ok(0);
+            }
+            debug!("Expanded environment variables in {}", path);
+            *text = expanded;
+            Ok(1)
+        }
+        Value::Array(items) => items
+            .iter_mut()
+            .enumerate()
+            .map(|(index, item)| expand_env_vars_in_value(item, &format!("{}[{}]", path, index)))
+            .sum(),
+        Value::Object(fields) => fields
+            .iter_mut()
+            .map(|(key, field)| {
+                let field_path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                expand_env_vars_in_value(field, &field_path)
+            })
+            .sum(),
+        _ => Ok(0),
     }
-
-    Ok(())
 }
 
 /// Check if content is JSON
@@ -707,6 +1408,10 @@ mod tests {
                 display_name: "Test Service".to_string(),
                 description: "Test service description".to_string(),
                 config_refresh_minutes: 60,
+                dry_run: false,
+                time_zone: None,
+                registry_status: true,
+                heartbeat_interval: "1h".to_string(),
             },
             notification: NotificationConfig {
                 notification_type: Some(NotificationType::Both),
@@ -717,23 +1422,58 @@ mod tests {
                     title: "Test Title".to_string(),
                     icon_path: "%WINDIR%\\System32\\test.ico".to_string(),
                     company: "Test Company".to_string(),
+                    state_icons: StateIconsConfig::default(),
+                    subtitle_template: None,
+                    asset_tag_registry_key: None,
+                    asset_tag_registry_value: "AssetTag".to_string(),
                 },
                 messages: MessagesConfig::default(),
+                locales: HashMap::new(),
                 quiet_hours: QuietHoursConfig::default(),
+                post_resume_grace: None,
+                respect_user_availability: true,
+                weekly_summary: None,
+                suppressed_accounts: Vec::new(),
+                delivery_retry: DeliveryRetryConfig::default(),
+                max_idle_time: None,
+                duplicate_interval: models::default_duplicate_interval(),
+                message_box: MessageBoxConfig::default(),
+                acknowledgment: AcknowledgmentConfig::default(),
+                window_placement: WindowPlacement::default(),
             },
             reboot: RebootConfig {
                 timeframes: vec![],
                 detection_methods: DetectionMethodsConfig::default(),
                 system_reboot: models::default_system_reboot_config(),
+                max_deferrals: None,
+                deadline: None,
+                upgrade_deadline: None,
+                use_wmi: true,
+                maintenance_windows: Vec::new(),
+                snooze_until_times: Vec::new(),
+                check_interval: "15m".to_string(),
+                startup_check_delay: "30s".to_string(),
+                idle_reboot_after: None,
+                auto_reboot_when_no_sessions: false,
+                no_sessions_grace: "15m".to_string(),
+                unattended_reboot_after: "1h".to_string(),
             },
             database: DatabaseConfig {
                 path: "%PROGRAMDATA%\\TestApp\\test.db".to_string(),
+                retention: RetentionConfig::default(),
+                busy_timeout_ms: 5000,
+                max_connections: 4,
+                min_idle: None,
+                connection_timeout_seconds: 30,
             },
             logging: LoggingConfig {
                 path: "%TEMP%\\TestApp\\logs\\test.log".to_string(),
                 level: "info".to_string(),
                 max_files: 5,
                 max_size: 10,
+                log_sql: false,
+                redact_usernames: false,
+                log_level_overrides: HashMap::new(),
             },
             watchdog: WatchdogConfig {
                 enabled: true,
@@ -744,9 +1484,24 @@ mod tests {
                 restart_delay: Some("10s".to_string()),
                 service_path: "%PROGRAMFILES%\\TestApp\\test.exe".to_string(),
                 service_name: "TestService".to_string(),
+                restore_start_type: false,
+                crash_report: None,
             },
+            network: NetworkConfig::default(),
+            update: UpdateConfig::default(),
+            profiles: Vec::new(),
+            hooks: Vec::new(),
+            admin_alerts: None,
+            status_page: None,
+            intune: None,
+            log_analytics: None,
+            report_sinks: Vec::new(),
+            includes: Vec::new(),
         };
 
+        config.notification.messages.reboot_required = "Restart %COMPUTERNAME% to finish updates".to_string();
+        config.hooks.push(serde_json::from_str(r#"{"url": "https://reports.contoso.com/%COMPUTERNAME%"}"#).unwrap());
+
         // Expand environment variables
         let result = expand_env_vars_in_config(&mut config);
         assert!(result.is_ok());
@@ -756,6 +1511,70 @@ mod tests {
         assert!(!config.logging.path.contains("%TEMP%"));
         assert!(!config.notification.branding.icon_path.contains("%WINDIR%"));
         assert!(!config.watchdog.service_path.contains("%PROGRAMFILES%"));
+
+        // Every string is expanded, not only paths
+        assert!(!config.notification.messages.reboot_required.contains("%COMPUTERNAME%"));
+        assert!(!serde_json::to_string(&config.hooks).unwrap().contains("%COMPUTERNAME%"));
+    }
+
+    #[test]
+    fn test_schema() {
+        let schema: serde_json::Value = serde_json::from_str(&schema().unwrap()).unwrap();
+
+        // Properties use the same camelCase names as the configuration files
+        let properties = &schema["properties"];
+        assert!(properties.get("service").is_some());
+        assert!(properties.get("notification").is_some());
+        assert!(properties.get("reboot").is_some());
+        assert!(schema["definitions"]["NotificationConfig"]["properties"].get("showToast").is_some());
+    }
+
+    #[test]
+    fn test_config_hash() {
+        let mut config = default();
+        let hash = config_hash(&config);
+        assert_eq!(hash.len(), 16);
+        assert_eq!(hash, config_hash(&config.clone()));
+
+        config.service.dry_run = !config.service.dry_run;
+        assert_ne!(hash, config_hash(&config));
+    }
+
+    #[test]
+    fn test_network_settings() {
+        let value = serde_json::json!({
+            "network": { "proxyUrl": "http://proxy.contoso.com:8080", "verifyTls": false }
+        });
+        let network = network_settings(Some(&value));
+        assert_eq!(network.proxy_url.as_deref(), Some("http://proxy.contoso.com:8080"));
+        assert!(!network.verify_tls);
+
+        // Without a network section, the settings of the last load are used
+        crate::utils::http::set_network_config(&NetworkConfig::default());
+        assert!(network_settings(Some(&serde_json::json!({}))).verify_tls);
+        assert!(network_settings(None).proxy_url.is_none());
+    }
+
+    #[test]
+    fn test_validate_update_config() {
+        let mut config = default();
+        config.update.enabled = true;
+        config.update.manifest_url = Some("https://updates.contoso.com/manifest.json".to_string());
+        config.update.public_key = Some("RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3".to_string());
+        config.update.signer_thumbprints = vec!["AB".repeat(32)];
+        assert!(validate_config(&config).is_ok());
+
+        // Plain HTTP manifests, unsigned manifests and unpinned signers are refused
+        config.update.manifest_url = Some("http://updates.contoso.com/manifest.json".to_string());
+        assert!(validate_config(&config).is_err());
+        config.update.manifest_url = Some("https://updates.contoso.com/manifest.json".to_string());
+        config.update.public_key = None;
+        assert!(validate_config(&config).is_err());
+        config.update.public_key = Some("RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3".to_string());
+        config.update.signer_thumbprints.clear();
+        assert!(validate_config(&config).is_err());
+        config.update.signer_thumbprints = vec!["not a thumbprint".to_string()];
+        assert!(validate_config(&config).is_err());
     }
 
     #[test]
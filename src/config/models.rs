@@ -1,8 +1,11 @@
+use crate::utils::secrets::Secret;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::str::FromStr;
 
 /// Log level
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum LogLevel {
     /// Error level
@@ -34,7 +37,7 @@ impl FromStr for LogLevel {
 }
 
 /// Main configuration structure
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct Config {
     /// Service configuration
@@ -55,10 +58,402 @@ pub struct Config {
     /// Watchdog configuration
     #[serde(default)]
     pub watchdog: WatchdogConfig,
+
+    /// Network configuration for HTTP clients
+    #[serde(default)]
+    pub network: NetworkConfig,
+
+    /// Self-update configuration
+    #[serde(default)]
+    pub update: UpdateConfig,
+
+    /// Profiles that adjust the configuration by machine type; the first matching profile is used
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub profiles: Vec<ProfileConfig>,
+
+    /// Commands and webhooks run when a reboot becomes required or is no longer required
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hooks: Vec<HookConfig>,
+
+    /// Alerts sent to a Teams or Slack channel when a computer needs attention from IT
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin_alerts: Option<AdminAlertsConfig>,
+
+    /// Read-only status page served on localhost by the service
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status_page: Option<StatusPageConfig>,
+
+    /// Reboot status reported to the Entra ID device object through Microsoft Graph
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub intune: Option<IntuneConfig>,
+
+    /// Detections, notifications and reboots sent to an Azure Log Analytics workspace; the same
+    /// as a `logAnalytics` entry in `report_sinks`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_analytics: Option<LogAnalyticsConfig>,
+
+    /// Destinations reporting events are sent to, each with its own queue
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub report_sinks: Vec<ReportSinkConfig>,
+
+    /// Configuration files or URLs merged under this one in order, e.g. a global base and a site delta (JSON only)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub includes: Vec<String>,
+}
+
+impl Config {
+    /// Get the report sinks, including the `log_analytics` section
+    pub fn configured_report_sinks(&self) -> Vec<ReportSinkConfig> {
+        self.log_analytics.iter().map(ReportSinkConfig::from).chain(self.report_sinks.iter().cloned()).collect()
+    }
+}
+
+/// Local status page configuration
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusPageConfig {
+    /// Port the page is served on, on the loopback interface only
+    #[serde(default = "default_status_page_port")]
+    pub port: u16,
+}
+
+/// Default port of the status page
+fn default_status_page_port() -> u16 {
+    8765
+}
+
+/// Events sent to Azure Log Analytics through the HTTP Data Collector API
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LogAnalyticsConfig {
+    /// ID of the Log Analytics workspace
+    pub workspace_id: String,
+
+    /// Primary or secondary key of the workspace, plain text or DPAPI protected
+    pub shared_key: Secret,
+
+    /// Name of the custom log the events are stored in; Log Analytics appends `_CL`
+    #[serde(default = "default_log_type")]
+    pub log_type: String,
+
+    /// Events that are sent
+    #[serde(default = "default_report_events")]
+    pub events: Vec<ReportEventKind>,
+
+    /// Number of events sent in one request
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+
+    /// How long events are collected before they're sent, as a timespan string
+    #[serde(default = "default_batch_interval")]
+    pub batch_interval: String,
+}
+
+impl From<&LogAnalyticsConfig> for ReportSinkConfig {
+    fn from(config: &LogAnalyticsConfig) -> Self {
+        Self {
+            sink: ReportSinkKind::LogAnalytics {
+                workspace_id: config.workspace_id.clone(),
+                shared_key: config.shared_key.clone(),
+                log_type: config.log_type.clone(),
+            },
+            events: config.events.clone(),
+            batch_size: config.batch_size,
+            batch_interval: config.batch_interval.clone(),
+        }
+    }
+}
+
+/// Destination reporting events are sent to
+///
+/// Each sink has its own queue, so one that can't be reached doesn't hold up the others.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportSinkConfig {
+    /// Where the events go
+    #[serde(flatten)]
+    pub sink: ReportSinkKind,
+
+    /// Events that are sent
+    #[serde(default = "default_report_events")]
+    pub events: Vec<ReportEventKind>,
+
+    /// Number of events sent at once
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+
+    /// How long events are collected before they're sent, as a timespan string
+    #[serde(default = "default_batch_interval")]
+    pub batch_interval: String,
+}
+
+/// Kind of report sink, selected by its `type`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ReportSinkKind {
+    /// Events posted as a JSON array to a URL
+    #[serde(rename_all = "camelCase")]
+    Webhook {
+        /// URL the events are posted to
+        url: String,
+
+        /// Bearer token sent with the request, plain text or DPAPI protected
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        bearer_token: Option<Secret>,
+    },
+
+    /// Events written to the Application event log
+    EventLog,
+
+    /// Events sent to an Azure Log Analytics workspace through the HTTP Data Collector API
+    #[serde(rename_all = "camelCase")]
+    LogAnalytics {
+        /// ID of the Log Analytics workspace
+        workspace_id: String,
+
+        /// Primary or secondary key of the workspace, plain text or DPAPI protected
+        shared_key: Secret,
+
+        /// Name of the custom log the events are stored in; Log Analytics appends `_CL`
+        #[serde(default = "default_log_type")]
+        log_type: String,
+    },
+
+    /// Events appended to a file, one JSON object per line
+    File {
+        /// Path of the file
+        path: String,
+    },
+}
+
+/// What a reported event is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum ReportEventKind {
+    /// Result of a reboot check
+    Detection,
+
+    /// A notification was shown, suppressed or skipped
+    Notification,
+
+    /// The computer rebooted, or a reboot failed
+    Reboot,
+
+    /// A reboot became required, or is no longer required
+    StateChange,
+
+    /// The service is running, sent periodically
+    Heartbeat,
+}
+
+impl std::fmt::Display for ReportEventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Detection => write!(f, "detection"),
+            Self::Notification => write!(f, "notification"),
+            Self::Reboot => write!(f, "reboot"),
+            Self::StateChange => write!(f, "stateChange"),
+            Self::Heartbeat => write!(f, "heartbeat"),
+        }
+    }
+}
+
+/// Custom log the events are stored in by default
+fn default_log_type() -> String {
+    "RebootReminder".to_string()
+}
+
+/// Send every kind of event by default
+fn default_report_events() -> Vec<ReportEventKind> {
+    vec![
+        ReportEventKind::Detection,
+        ReportEventKind::Notification,
+        ReportEventKind::Reboot,
+        ReportEventKind::StateChange,
+        ReportEventKind::Heartbeat,
+    ]
+}
+
+/// Send up to 100 events in one request
+fn default_batch_size() -> usize {
+    100
+}
+
+/// Send the collected events every five minutes
+fn default_batch_interval() -> String {
+    "5m".to_string()
+}
+
+/// Reporting of the reboot status to Intune through Microsoft Graph
+///
+/// The status is written to an extension attribute of the device in Entra ID, where dynamic
+/// groups, compliance reports and the Intune console can use it.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IntuneConfig {
+    /// Directory (tenant) ID of the app registration
+    pub tenant_id: String,
+
+    /// Application (client) ID of the app registration
+    pub client_id: String,
+
+    /// Client secret of the app registration, plain text or DPAPI protected
+    pub client_secret: Secret,
+
+    /// Number (1-15) of the extension attribute the status is written to
+    #[serde(default = "default_extension_attribute")]
+    pub extension_attribute: u8,
+
+    /// How long a reboot may be pending before the device counts as overdue, as a timespan
+    /// string (None = only once the deadline has passed)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overdue_after: Option<String>,
+
+    /// How often the status is reported again when it hasn't changed, as a timespan string
+    #[serde(default = "default_intune_refresh_interval")]
+    pub refresh_interval: String,
+}
+
+/// Default extension attribute the status is written to
+fn default_extension_attribute() -> u8 {
+    10
+}
+
+/// Report the status again once a day
+fn default_intune_refresh_interval() -> String {
+    "24h".to_string()
+}
+
+/// Alerts for administrators sent to a chat webhook
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AdminAlertsConfig {
+    /// Incoming webhook URL, plain text or DPAPI protected
+    pub webhook_url: Secret,
+
+    /// Message format of the chat service behind the webhook
+    #[serde(default)]
+    pub format: AdminAlertFormat,
+
+    /// How long a reboot may be pending before an alert is sent, as a timespan string
+    #[serde(default = "default_pending_threshold")]
+    pub pending_threshold: String,
+
+    /// Number of forced reboot attempts that don't restart the computer before an alert is sent
+    #[serde(default = "default_max_failed_reboots")]
+    pub max_failed_reboots: u32,
+}
+
+/// Chat service an admin alert webhook belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum AdminAlertFormat {
+    /// Microsoft Teams incoming webhook
+    #[default]
+    Teams,
+
+    /// Slack incoming webhook
+    Slack,
+}
+
+/// Alert when a reboot has been pending for a week
+fn default_pending_threshold() -> String {
+    "168h".to_string()
+}
+
+/// Alert after three forced reboot attempts
+fn default_max_failed_reboots() -> u32 {
+    3
+}
+
+/// Command or webhook run when the reboot requirement changes
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct HookConfig {
+    /// Command run with `cmd /C`, given the event as JSON on its standard input
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+
+    /// URL the event is posted to as JSON
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+
+    /// Bearer token sent to the webhook, plain text or DPAPI protected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bearer_token: Option<Secret>,
+
+    /// Events the hook runs for
+    #[serde(default = "default_hook_events")]
+    pub events: Vec<HookEvent>,
+
+    /// How long the hook may run as a timespan string (default "30s")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<String>,
+}
+
+/// Change of the reboot requirement
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum HookEvent {
+    /// A reboot became required
+    RebootRequired,
+
+    /// A reboot is no longer required
+    RebootCleared,
+}
+
+impl std::fmt::Display for HookEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::RebootRequired => write!(f, "rebootRequired"),
+            Self::RebootCleared => write!(f, "rebootCleared"),
+        }
+    }
+}
+
+/// Hooks run for both events by default
+fn default_hook_events() -> Vec<HookEvent> {
+    vec![HookEvent::RebootRequired, HookEvent::RebootCleared]
+}
+
+/// Configuration profile for the computers that meet its criteria
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileConfig {
+    /// Profile name, used in the logs
+    pub name: String,
+
+    /// Criteria a computer must meet for the profile to apply
+    #[serde(default)]
+    pub criteria: ProfileCriteria,
+
+    /// Settings that replace the base configuration, in the same format as the configuration
+    pub overrides: serde_json::Value,
+}
+
+/// Profile selection criteria; all criteria that are set must match
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileCriteria {
+    /// Regular expression the computer name must match, ignoring case
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname_pattern: Option<String>,
+
+    /// Distinguished name of the OU the computer must be in, directly or in a child OU
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub organizational_unit: Option<String>,
+
+    /// Chassis kinds the computer must be one of: laptop, desktop, server or other
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chassis_types: Vec<String>,
+
+    /// Whether the computer must (true) or must not (false) be a virtual machine
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub virtual_machine: Option<bool>,
 }
 
 /// Service configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ServiceConfig {
     /// Service name
@@ -72,10 +467,27 @@ pub struct ServiceConfig {
 
     /// Configuration refresh interval in minutes
     pub config_refresh_minutes: u32,
+
+    /// Run detection, scheduling and notifications without showing anything to users or rebooting
+    #[serde(default)]
+    pub dry_run: bool,
+
+    /// IANA time zone quiet hours and maintenance windows are evaluated in (None = the time zone
+    /// of the computer)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_zone: Option<String>,
+
+    /// Whether to mirror the reboot state to HKLM\\SOFTWARE\\RebootReminder\\Status for inventory tools
+    #[serde(default = "default_registry_status")]
+    pub registry_status: bool,
+
+    /// How often a heartbeat is sent to the report sinks, as a timespan string
+    #[serde(default = "default_heartbeat_interval")]
+    pub heartbeat_interval: String,
 }
 
 /// Notification configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct NotificationConfig {
     /// Notification type (tray, toast, or both) - Legacy field
@@ -100,12 +512,203 @@ pub struct NotificationConfig {
     /// Message templates
     pub messages: MessagesConfig,
 
+    /// Strings per locale (e.g., "de-DE"), chosen by the user's UI language
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub locales: HashMap<String, LocaleStrings>,
+
     /// Quiet hours configuration
     pub quiet_hours: QuietHoursConfig,
+
+    /// Grace period after resuming from sleep during which notifications are suppressed (e.g., "10m")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_resume_grace: Option<String>,
+
+    /// Hold notifications back while the user is presenting or running a full-screen application
+    #[serde(default = "default_respect_user_availability")]
+    pub respect_user_availability: bool,
+
+    /// Weekly summary of the pending reboot, shown while a reboot is required
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weekly_summary: Option<WeeklySummaryConfig>,
+
+    /// Accounts whose sessions never get notifications, as user name patterns with `*` and `?`
+    /// wildcards (e.g., "adm-*")
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub suppressed_accounts: Vec<String>,
+
+    /// Retries of notifications whose delivery failed
+    #[serde(default)]
+    pub delivery_retry: DeliveryRetryConfig,
+
+    /// Hold reminders back until a user has been active within this timespan (e.g., "10m")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_idle_time: Option<String>,
+
+    /// Minimum time before the same notification is shown again in a session (e.g., "5m"; null = no limit)
+    #[serde(default = "default_duplicate_interval")]
+    pub duplicate_interval: Option<String>,
+
+    /// Session message boxes used by the `messagebox` type and when no other notification can be shown
+    #[serde(default)]
+    pub message_box: MessageBoxConfig,
+
+    /// Require users to acknowledge reboot reminders
+    #[serde(default)]
+    pub acknowledgment: AcknowledgmentConfig,
+
+    /// Monitors the critical notification window is shown on
+    #[serde(default)]
+    pub window_placement: WindowPlacement,
+}
+
+/// Monitors a notification window is placed on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum WindowPlacement {
+    /// The primary monitor
+    #[default]
+    Primary,
+
+    /// The monitor with the foreground window, or with the mouse cursor
+    Active,
+
+    /// Every monitor
+    All,
+}
+
+/// Reminder acknowledgment configuration
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcknowledgmentConfig {
+    /// Show reboot reminders in a dialog the user must confirm, repeating it until they do
+    #[serde(default)]
+    pub required: bool,
+
+    /// How long to wait for an acknowledgment before showing the reminder again (e.g., "1h")
+    #[serde(default = "default_acknowledgment_reprompt_interval")]
+    pub reprompt_interval: String,
+
+    /// Text appended to the message, asking the user to confirm it
+    #[serde(default = "default_acknowledgment_prompt")]
+    pub prompt: String,
+}
+
+impl Default for AcknowledgmentConfig {
+    fn default() -> Self {
+        Self {
+            required: false,
+            reprompt_interval: default_acknowledgment_reprompt_interval(),
+            prompt: default_acknowledgment_prompt(),
+        }
+    }
+}
+
+/// Default time before an unacknowledged reminder is shown again
+fn default_acknowledgment_reprompt_interval() -> String {
+    "1h".to_string()
+}
+
+/// Default text asking the user to acknowledge a reminder
+fn default_acknowledgment_prompt() -> String {
+    "Select OK to confirm that you have read and understood this message.".to_string()
+}
+
+/// Session message box configuration
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageBoxConfig {
+    /// How long the message box stays up as a timespan string (e.g., "5m"); until closed if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<String>,
+
+    /// Ask the user whether to restart now and act on the answer
+    #[serde(default)]
+    pub capture_response: bool,
+
+    /// Question appended to the message when the response is captured
+    #[serde(default = "default_message_box_restart_prompt")]
+    pub restart_prompt: String,
+}
+
+impl Default for MessageBoxConfig {
+    fn default() -> Self {
+        Self {
+            timeout: None,
+            capture_response: false,
+            restart_prompt: default_message_box_restart_prompt(),
+        }
+    }
+}
+
+/// Default question asked in message boxes that capture the response
+fn default_message_box_restart_prompt() -> String {
+    "Do you want to restart now?".to_string()
+}
+
+/// Notification delivery retry configuration
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DeliveryRetryConfig {
+    /// Number of delivery attempts before a notification is given up on
+    #[serde(default = "default_delivery_max_attempts")]
+    pub max_attempts: u32,
+
+    /// Delay before the first retry as a timespan string (e.g., "1m"); doubled after each failure
+    #[serde(default = "default_delivery_initial_delay")]
+    pub initial_delay: String,
+
+    /// Longest delay between retries as a timespan string (e.g., "1h")
+    #[serde(default = "default_delivery_max_delay")]
+    pub max_delay: String,
+}
+
+impl Default for DeliveryRetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_delivery_max_attempts(),
+            initial_delay: default_delivery_initial_delay(),
+            max_delay: default_delivery_max_delay(),
+        }
+    }
+}
+
+/// Default number of delivery attempts
+fn default_delivery_max_attempts() -> u32 {
+    5
+}
+
+/// Default delay before the first delivery retry
+fn default_delivery_initial_delay() -> String {
+    "1m".to_string()
+}
+
+/// Default longest delay between delivery retries
+fn default_delivery_max_delay() -> String {
+    "1h".to_string()
+}
+
+/// Weekly summary notification configuration
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WeeklySummaryConfig {
+    /// Day of the week the summary is shown on (0 = Sunday, 6 = Saturday)
+    pub day_of_week: u8,
+
+    /// Time of day the summary is shown at (HH:MM, local time)
+    pub time: String,
+
+    /// Summary message
+    #[serde(default = "default_weekly_summary_message")]
+    pub message: String,
+}
+
+/// Default weekly summary message
+fn default_weekly_summary_message() -> String {
+    "Your computer has needed a restart for {days_pending} days and {source_count} components are waiting for it. Please restart when convenient.".to_string()
 }
 
 /// Notification type
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum NotificationType {
     /// Tray notifications only
@@ -116,10 +719,13 @@ pub enum NotificationType {
 
     /// Both tray and toast notifications
     Both,
+
+    /// Message boxes sent to the user's session, for desktops where toasts and the tray don't work
+    MessageBox,
 }
 
 /// Branding configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct BrandingConfig {
     /// Notification title
@@ -130,10 +736,53 @@ pub struct BrandingConfig {
 
     /// Company name
     pub company: String,
+
+    /// Tray icons for each reboot state; states without an icon use `iconPath`
+    #[serde(default)]
+    pub state_icons: StateIconsConfig,
+
+    /// Line identifying the computer and user, shown below the message in toasts and
+    /// notification windows (e.g., "{computer_name} - {asset_tag} - {user_name}")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subtitle_template: Option<String>,
+
+    /// Registry key under HKLM holding the asset tag for the `{asset_tag}` placeholder
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub asset_tag_registry_key: Option<String>,
+
+    /// Name of the registry value holding the asset tag
+    #[serde(default = "default_asset_tag_value")]
+    pub asset_tag_registry_value: String,
+}
+
+/// Default name of the asset tag registry value
+fn default_asset_tag_value() -> String {
+    "AssetTag".to_string()
+}
+
+/// Tray icons shown for the reboot states
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StateIconsConfig {
+    /// Icon shown when no reboot is needed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub no_reboot: Option<String>,
+
+    /// Icon shown when a reboot is recommended
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reboot_recommended: Option<String>,
+
+    /// Icon shown when a reboot is required
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reboot_required: Option<String>,
+
+    /// Icon shown when a scheduled or forced reboot is less than an hour away
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reboot_imminent: Option<String>,
 }
 
 /// Message templates
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct MessagesConfig {
     /// Message shown when a reboot is required
@@ -166,29 +815,264 @@ pub struct MessagesConfig {
     /// Action message when no reboot is required
     pub action_not_required: String,
 
-    /// Action message when reboot options are not available
-    pub action_not_available: String,
+    /// Action message when reboot options are not available
+    pub action_not_available: String,
+
+    /// Message shown when a reboot is deferred because of the power state
+    #[serde(default = "default_reboot_deferred_power_message")]
+    pub reboot_deferred_power: String,
+
+    /// Message shown when a reboot is required and will be forced at a deadline
+    #[serde(default = "default_reboot_required_deadline_message")]
+    pub reboot_required_deadline: String,
+
+    /// Message shown repeatedly during the last hour before a forced reboot
+    #[serde(default = "default_reboot_countdown_message")]
+    pub reboot_countdown: String,
+
+    /// Message shown when a Windows feature update is waiting for its reboot
+    #[serde(default = "default_reboot_required_upgrade_message")]
+    pub reboot_required_upgrade: String,
+}
+
+impl MessagesConfig {
+    /// Apply the overrides for a locale
+    pub fn localized(&self, overrides: &LocalizedMessages) -> MessagesConfig {
+        let pick = |value: &Option<String>, default: &String| value.clone().unwrap_or_else(|| default.clone());
+
+        MessagesConfig {
+            reboot_required: pick(&overrides.reboot_required, &self.reboot_required),
+            reboot_recommended: pick(&overrides.reboot_recommended, &self.reboot_recommended),
+            reboot_scheduled: pick(&overrides.reboot_scheduled, &self.reboot_scheduled),
+            reboot_in_progress: pick(&overrides.reboot_in_progress, &self.reboot_in_progress),
+            reboot_cancelled: pick(&overrides.reboot_cancelled, &self.reboot_cancelled),
+            reboot_postponed: pick(&overrides.reboot_postponed, &self.reboot_postponed),
+            reboot_completed: pick(&overrides.reboot_completed, &self.reboot_completed),
+            action_required: pick(&overrides.action_required, &self.action_required),
+            action_recommended: pick(&overrides.action_recommended, &self.action_recommended),
+            action_not_required: pick(&overrides.action_not_required, &self.action_not_required),
+            action_not_available: pick(&overrides.action_not_available, &self.action_not_available),
+            reboot_deferred_power: pick(&overrides.reboot_deferred_power, &self.reboot_deferred_power),
+            reboot_required_deadline: pick(&overrides.reboot_required_deadline, &self.reboot_required_deadline),
+            reboot_countdown: pick(&overrides.reboot_countdown, &self.reboot_countdown),
+            reboot_required_upgrade: pick(&overrides.reboot_required_upgrade, &self.reboot_required_upgrade),
+        }
+    }
+}
+
+/// Message overrides for a locale; messages that are not set use the default text
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LocalizedMessages {
+    /// Message shown when a reboot is required
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reboot_required: Option<String>,
+
+    /// Message shown when a reboot is recommended
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reboot_recommended: Option<String>,
+
+    /// Message shown when a reboot is scheduled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reboot_scheduled: Option<String>,
+
+    /// Message shown when a reboot is in progress
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reboot_in_progress: Option<String>,
+
+    /// Message shown when a reboot is cancelled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reboot_cancelled: Option<String>,
+
+    /// Message shown when a reboot is postponed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reboot_postponed: Option<String>,
+
+    /// Message shown when a reboot is completed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reboot_completed: Option<String>,
+
+    /// Action message for required reboots
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action_required: Option<String>,
+
+    /// Action message for recommended reboots
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action_recommended: Option<String>,
+
+    /// Action message when no reboot is required
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action_not_required: Option<String>,
+
+    /// Action message when reboot options are not available
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action_not_available: Option<String>,
+
+    /// Message shown when a reboot is deferred because of the power state
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reboot_deferred_power: Option<String>,
+
+    /// Message shown when a reboot is required and will be forced at a deadline
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reboot_required_deadline: Option<String>,
+
+    /// Message shown repeatedly during the last hour before a forced reboot
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reboot_countdown: Option<String>,
+
+    /// Message shown when a Windows feature update is waiting for its reboot
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reboot_required_upgrade: Option<String>,
+}
+
+/// Tray menu labels for a locale; labels that are not set use the default text
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TrayLabels {
+    /// Label of the reboot item
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reboot_now: Option<String>,
+
+    /// Label of the postpone item
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub postpone: Option<String>,
+
+    /// Label of the quit item
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quit: Option<String>,
+
+    /// Label of the item that shows why a reboot is needed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<String>,
+
+    /// Label of the submenu listing the user's recent notifications
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recent_notifications: Option<String>,
+}
+
+/// User-facing strings for one locale (e.g., "de-DE")
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LocaleStrings {
+    /// Notification title
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+
+    /// Message overrides
+    #[serde(default)]
+    pub messages: LocalizedMessages,
+
+    /// Reboot confirmation dialog message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confirmation_message: Option<String>,
+
+    /// Reboot confirmation dialog title
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confirmation_title: Option<String>,
+
+    /// Tray menu labels
+    #[serde(default)]
+    pub tray: TrayLabels,
+}
+
+/// Quiet hours configuration
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct QuietHoursConfig {
+    /// Whether quiet hours are enabled
+    pub enabled: bool,
+
+    /// Start time of quiet hours (HH:MM), when no ranges are configured
+    #[serde(default)]
+    pub start_time: String,
+
+    /// End time of quiet hours (HH:MM), when no ranges are configured
+    #[serde(default)]
+    pub end_time: String,
+
+    /// Days of the week on which quiet hours start (0 = Sunday, 6 = Saturday), when no ranges
+    /// are configured; quiet hours spanning midnight continue into the next day
+    #[serde(default)]
+    pub days_of_week: Vec<u8>,
+
+    /// Quiet hours with their own days, replacing startTime, endTime and daysOfWeek
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ranges: Vec<QuietHoursRange>,
+
+    /// Per-user working hours; outside a user's working hours notifications are quiet
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub working_hours: Option<WorkingHoursConfig>,
+}
+
+impl QuietHoursConfig {
+    /// Get the quiet hours ranges, or the single range of startTime, endTime and daysOfWeek
+    pub fn effective_ranges(&self) -> Vec<QuietHoursRange> {
+        if !self.ranges.is_empty() {
+            return self.ranges.clone();
+        }
+
+        vec![QuietHoursRange {
+            days: self.days_of_week.clone(),
+            start: self.start_time.clone(),
+            end: self.end_time.clone(),
+        }]
+    }
+}
+
+/// Quiet hours on some days of the week
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct QuietHoursRange {
+    /// Days of the week on which the range starts (0 = Sunday, 6 = Saturday)
+    pub days: Vec<u8>,
+
+    /// Start time (HH:MM)
+    pub start: String,
+
+    /// End time (HH:MM); a range ending before it starts spans midnight, and one ending when it
+    /// starts lasts the whole day
+    pub end: String,
+}
+
+/// Sources of per-user working hours, written as "HH:MM-HH:MM" (e.g., "22:00-06:30")
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkingHoursConfig {
+    /// Registry key holding the working hours, read from the session user's hive unless it
+    /// starts with HKLM\
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registry_key: Option<String>,
+
+    /// Name of the registry value holding the working hours
+    #[serde(default = "default_working_hours_value")]
+    pub registry_value: String,
+
+    /// Environment variable of the session user holding the working hours
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment_variable: Option<String>,
+}
+
+/// Default name of the working hours registry value
+fn default_working_hours_value() -> String {
+    "WorkingHours".to_string()
 }
 
-/// Quiet hours configuration
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+/// Maintenance window in which forced reboots may run
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
-pub struct QuietHoursConfig {
-    /// Whether quiet hours are enabled
-    pub enabled: bool,
-
-    /// Start time of quiet hours (HH:MM)
+pub struct MaintenanceWindowConfig {
+    /// Start time of the window (HH:MM, local time)
     pub start_time: String,
 
-    /// End time of quiet hours (HH:MM)
+    /// End time of the window (HH:MM, local time); windows may span midnight
     pub end_time: String,
 
-    /// Days of the week when quiet hours are active (0 = Sunday, 6 = Saturday)
+    /// Days of the week on which the window starts (0 = Sunday, 6 = Saturday)
     pub days_of_week: Vec<u8>,
 }
 
 /// Reboot configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct RebootConfig {
     /// Reboot timeframes
@@ -197,13 +1081,63 @@ pub struct RebootConfig {
     /// Reboot detection methods
     pub detection_methods: DetectionMethodsConfig,
 
+    /// Whether to query WMI; without it the boot time and system information come from Win32
+    /// APIs and the registry, for servers where WMI is broken or blocked
+    #[serde(default = "default_use_wmi")]
+    pub use_wmi: bool,
+
     /// System reboot options
     #[serde(default = "default_system_reboot_config")]
     pub system_reboot: SystemRebootConfig,
+
+    /// Maximum number of times a reboot can be postponed (None = no limit)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_deferrals: Option<u32>,
+
+    /// Time after detection by which the reboot must happen, as a timespan string (e.g., "120h")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deadline: Option<String>,
+
+    /// Deadline used instead of `deadline` while a Windows feature update is pending (e.g., "336h")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upgrade_deadline: Option<String>,
+
+    /// Windows in which a reboot forced by the deadline may run (empty = any time)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub maintenance_windows: Vec<MaintenanceWindowConfig>,
+
+    /// Times of day the user can snooze reminders until (HH:MM, local time), offered with the deferrals
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub snooze_until_times: Vec<String>,
+
+    /// How often to check whether a reboot is required, as a timespan string (e.g., "15m")
+    #[serde(default = "default_check_interval")]
+    pub check_interval: String,
+
+    /// Delay before the first check after the service starts, as a timespan string (e.g., "30s")
+    #[serde(default = "default_startup_check_delay")]
+    pub startup_check_delay: String,
+
+    /// Reboot once every signed-in user has been idle this long while a reboot is required (e.g., "4h")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_reboot_after: Option<String>,
+
+    /// Reboot automatically while nobody is signed in, once the reboot has been required for
+    /// `unattendedRebootAfter` and no session has existed for `noSessionsGrace`
+    #[serde(default)]
+    pub auto_reboot_when_no_sessions: bool,
+
+    /// How long nobody must be signed in before an unattended reboot, as a timespan string
+    #[serde(default = "default_no_sessions_grace")]
+    pub no_sessions_grace: String,
+
+    /// How long a reboot must have been required before an unattended reboot, as a timespan string
+    #[serde(default = "default_unattended_reboot_after")]
+    pub unattended_reboot_after: String,
 }
 
 /// Timeframe configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct TimeframeConfig {
     /// Minimum hours since reboot required (legacy)
@@ -234,12 +1168,140 @@ pub struct TimeframeConfig {
     #[serde(rename = "reminderInterval", default, skip_serializing_if = "Option::is_none")]
     pub reminder_interval: Option<String>,
 
-    /// Deferral options (e.g., "1h", "30m")
-    pub deferrals: Vec<String>,
+    /// Deferral options, as timespan strings (e.g., "1h", "30m") or labelled presets
+    pub deferrals: Vec<DeferralOption>,
+
+    /// How prominently reminders are shown in this timeframe
+    #[serde(default)]
+    pub severity: NotificationSeverity,
+
+    /// Sound of reminder toasts in this timeframe (None = by severity)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sound: Option<ToastSound>,
+
+    /// Priority of reminder toasts in this timeframe
+    #[serde(default)]
+    pub priority: ToastPriority,
+}
+
+/// Option offered to the user to postpone reminders
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum DeferralOption {
+    /// Postpone by a timespan string (e.g., "4h")
+    Duration(String),
+
+    /// Preset with a label, postponing by a timespan or until a time of day
+    Preset(DeferralPreset),
+}
+
+impl DeferralOption {
+    /// Get the identifier recorded when the option is chosen
+    ///
+    /// Presets without an id are identified by their duration or time of day.
+    pub fn id(&self) -> &str {
+        match self {
+            Self::Duration(duration) => duration,
+            Self::Preset(preset) => preset
+                .id
+                .as_deref()
+                .or(preset.duration.as_deref())
+                .or(preset.until_time.as_deref())
+                .unwrap_or(&preset.label),
+        }
+    }
+
+    /// Get the text shown to the user
+    pub fn label(&self) -> &str {
+        match self {
+            Self::Duration(duration) => duration,
+            Self::Preset(preset) => &preset.label,
+        }
+    }
+}
+
+impl From<&str> for DeferralOption {
+    fn from(duration: &str) -> Self {
+        Self::Duration(duration.to_string())
+    }
+}
+
+/// Deferral preset with a label (e.g., "Tomorrow morning")
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DeferralPreset {
+    /// Identifier recorded with the user's choice
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    /// Text shown to the user
+    pub label: String,
+
+    /// How long to postpone as a timespan string (e.g., "4h")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<String>,
+
+    /// Time of day to postpone until in 24-hour format (e.g., "08:00")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until_time: Option<String>,
+}
+
+/// How prominently a reminder is shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum NotificationSeverity {
+    /// Regular toast
+    #[default]
+    Normal,
+
+    /// Long toast with looping alarm sound
+    High,
+
+    /// Window that stays on top of all other windows
+    Critical,
+}
+
+/// Sound a toast plays
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum ToastSound {
+    /// No sound
+    Silent,
+
+    /// The default notification sound
+    Default,
+
+    /// Looping alarm sound
+    Alarm,
+}
+
+impl ToastSound {
+    /// Get the sound toasts of a severity play when none is configured
+    pub fn for_severity(severity: NotificationSeverity) -> Self {
+        match severity {
+            NotificationSeverity::Normal => Self::Default,
+            NotificationSeverity::High | NotificationSeverity::Critical => Self::Alarm,
+        }
+    }
+}
+
+/// How insistently a toast is shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum ToastPriority {
+    /// Regular toast that moves to the action center after a while
+    #[default]
+    Normal,
+
+    /// Reminder toast that stays on screen until the user acts on it
+    High,
+
+    /// Alarm toast that stays on screen until the user acts on it
+    Urgent,
 }
 
 /// Detection methods configuration
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct DetectionMethodsConfig {
     /// Whether to check Windows Update for pending reboots
@@ -253,18 +1315,159 @@ pub struct DetectionMethodsConfig {
 
     /// Whether to check for pending file operations
     pub pending_file_operations: bool,
+
+    /// Whether to check for reboots left pending by installers (MSI, Chocolatey)
+    #[serde(default)]
+    pub installer_operations: bool,
+
+    /// Whether to check for a Windows feature update (in-place upgrade) waiting for its reboot
+    #[serde(default = "default_os_upgrade")]
+    pub os_upgrade: bool,
+
+    /// How long each detection method may run before it's abandoned, as a timespan string (default "30s")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<String>,
+
+    /// Locations in the registry hives of signed-in users that indicate a pending reboot for that user
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub user_registry_keys: Vec<UserRegistryKeyConfig>,
+
+    /// Severity of sources by source name (e.g., "pending_file_operations"), overriding "required"
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub source_severities: HashMap<String, SourceSeverity>,
+
+    /// Consecutive checks a source must be found in before it counts, and missing from before it
+    /// stops counting, by source name (e.g., "pending_file_operations": 3)
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub debounce_checks: HashMap<String, u32>,
+}
+
+/// Whether a reboot source requires or only recommends a reboot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum SourceSeverity {
+    /// The source requires a reboot
+    #[default]
+    Required,
+
+    /// The source only recommends a reboot
+    Recommended,
+}
+
+impl SourceSeverity {
+    /// Get the severity as stored on reboot sources
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Required => "required",
+            Self::Recommended => "recommended",
+        }
+    }
+}
+
+/// Per-user registry location checked for a pending reboot
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UserRegistryKeyConfig {
+    /// Key path relative to HKEY_CURRENT_USER (e.g., Software\Contoso\RebootPending)
+    pub key_path: String,
+
+    /// Value that must exist and not be empty; when not set the key existing is enough
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value_name: Option<String>,
 }
 
 /// Database configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct DatabaseConfig {
     /// Path to database file
     pub path: String,
+
+    /// Retention limits and maintenance schedule
+    #[serde(default)]
+    pub retention: RetentionConfig,
+
+    /// How long a connection waits for a lock held by another process, in milliseconds
+    #[serde(default = "default_busy_timeout_ms")]
+    pub busy_timeout_ms: u32,
+
+    /// Maximum number of pooled connections
+    #[serde(default = "default_max_connections")]
+    pub max_connections: u32,
+
+    /// Minimum number of idle pooled connections (None = same as the maximum)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_idle: Option<u32>,
+
+    /// How long to wait for a pooled connection, in seconds
+    #[serde(default = "default_connection_timeout_seconds")]
+    pub connection_timeout_seconds: u32,
+}
+
+/// Default busy timeout for database connections
+fn default_busy_timeout_ms() -> u32 {
+    5000
+}
+
+/// Default maximum number of pooled database connections
+fn default_max_connections() -> u32 {
+    4
+}
+
+/// Default timeout for getting a pooled database connection
+fn default_connection_timeout_seconds() -> u32 {
+    30
+}
+
+/// Database retention configuration
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RetentionConfig {
+    /// Number of days notifications and their interactions are kept
+    #[serde(default = "default_notification_days")]
+    pub notification_days: u32,
+
+    /// Number of reboot history entries kept
+    #[serde(default = "default_max_reboot_history")]
+    pub max_reboot_history: u32,
+
+    /// Maximum size of the database file in megabytes; the oldest notifications are deleted beyond it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_size_mb: Option<u32>,
+
+    /// How often maintenance runs as a timespan string (e.g., "24h")
+    #[serde(default = "default_maintenance_interval")]
+    pub interval: String,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            notification_days: default_notification_days(),
+            max_reboot_history: default_max_reboot_history(),
+            max_size_mb: None,
+            interval: default_maintenance_interval(),
+        }
+    }
+}
+
+/// Default number of days notifications are kept
+fn default_notification_days() -> u32 {
+    90
+}
+
+/// Default number of reboot history entries kept
+fn default_max_reboot_history() -> u32 {
+    50
+}
+
+/// Default interval between database maintenance runs
+fn default_maintenance_interval() -> String {
+    "24h".to_string()
 }
 
 /// Logging configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct LoggingConfig {
     /// Path to log file
@@ -278,10 +1481,136 @@ pub struct LoggingConfig {
 
     /// Maximum size of each log file in MB
     pub max_size: u32,
+
+    /// Whether to log SQL statements (at debug level)
+    #[serde(default)]
+    pub log_sql: bool,
+
+    /// Whether to replace user names in log messages with a hash
+    #[serde(default)]
+    pub redact_usernames: bool,
+
+    /// Log levels for individual modules, e.g. "reboot_reminder::database" = "warn"
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub log_level_overrides: HashMap<String, String>,
+}
+
+/// Network configuration
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkConfig {
+    /// Proxy URL (e.g., "http://proxy.example.com:8080")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy_url: Option<String>,
+
+    /// Proxy user name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy_username: Option<String>,
+
+    /// Proxy password (plain text or "dpapi:" protected)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy_password: Option<Secret>,
+
+    /// Path to a PEM bundle of additional trusted root certificates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_bundle_path: Option<String>,
+
+    /// Whether to verify TLS certificates
+    #[serde(default = "default_verify_tls")]
+    pub verify_tls: bool,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            proxy_url: None,
+            proxy_username: None,
+            proxy_password: None,
+            ca_bundle_path: None,
+            verify_tls: default_verify_tls(),
+        }
+    }
+}
+
+/// Default value for TLS verification
+fn default_verify_tls() -> bool {
+    true
+}
+
+/// Self-update configuration
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateConfig {
+    /// Whether to check for and install updates
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// HTTPS URL of the release manifest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manifest_url: Option<String>,
+
+    /// Minisign public key the release manifest must be signed with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+
+    /// SHA-256 thumbprints of the certificates allowed to sign the executable
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub signer_thumbprints: Vec<String>,
+
+    /// How often to check for updates as a timespan string (e.g., "24h")
+    #[serde(default = "default_update_check_interval")]
+    pub check_interval: String,
+
+    /// Whether updates must carry a trusted Authenticode signature
+    #[serde(default = "default_require_signature")]
+    pub require_signature: bool,
+}
+
+impl Default for UpdateConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            manifest_url: None,
+            public_key: None,
+            signer_thumbprints: Vec::new(),
+            check_interval: default_update_check_interval(),
+            require_signature: default_require_signature(),
+        }
+    }
+}
+
+/// Default interval between reboot checks
+pub fn default_check_interval() -> String {
+    "15m".to_string()
+}
+
+/// Default delay before the first reboot check after the service starts
+pub fn default_startup_check_delay() -> String {
+    "30s".to_string()
+}
+
+/// Default time nobody must be signed in before an unattended reboot
+pub fn default_no_sessions_grace() -> String {
+    "15m".to_string()
+}
+
+/// Default time a reboot must have been required before an unattended reboot
+pub fn default_unattended_reboot_after() -> String {
+    "1h".to_string()
+}
+
+/// Default interval between update checks
+fn default_update_check_interval() -> String {
+    "24h".to_string()
+}
+
+/// Default value for requiring signed updates
+fn default_require_signature() -> bool {
+    true
 }
 
 /// Watchdog configuration
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct WatchdogConfig {
     /// Whether the watchdog is enabled
@@ -315,6 +1644,53 @@ pub struct WatchdogConfig {
     /// Name of the main service
     #[serde(default = "default_watchdog_service_name")]
     pub service_name: String,
+
+    /// Whether to set the service back to automatic start if its start type was changed
+    #[serde(default)]
+    pub restore_start_type: bool,
+
+    /// Diagnostics uploaded when the service keeps restarting
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crash_report: Option<CrashReportConfig>,
+}
+
+/// Upload of diagnostics when the watchdog keeps restarting the service
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CrashReportConfig {
+    /// URL the report is posted to as JSON
+    pub url: String,
+
+    /// Bearer token sent with the report, plain text or DPAPI protected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bearer_token: Option<Secret>,
+
+    /// Number of restarts within the window that triggers an upload when exceeded
+    #[serde(default = "default_crash_restart_threshold")]
+    pub restart_threshold: u32,
+
+    /// Window restarts are counted in, as a timespan string
+    #[serde(default = "default_crash_window")]
+    pub window: String,
+
+    /// Minimum time between uploads, as a timespan string
+    #[serde(default = "default_crash_min_interval")]
+    pub min_interval: String,
+}
+
+/// Upload after more than three restarts
+fn default_crash_restart_threshold() -> u32 {
+    3
+}
+
+/// Count restarts within an hour
+fn default_crash_window() -> String {
+    "1h".to_string()
+}
+
+/// Upload at most once a day
+fn default_crash_min_interval() -> String {
+    "24h".to_string()
 }
 
 /// Default value for watchdog enabled
@@ -355,7 +1731,7 @@ fn default_watchdog_service_name() -> String {
 }
 
 /// System reboot configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct SystemRebootConfig {
     /// Whether to allow users to initiate system reboots
@@ -381,6 +1757,70 @@ pub struct SystemRebootConfig {
     /// Confirmation title
     #[serde(default = "default_system_reboot_title")]
     pub confirmation_title: String,
+
+    /// How long users have to answer the confirmation before it counts as declined (e.g., "5m")
+    #[serde(default = "default_confirmation_timeout")]
+    pub confirmation_timeout: String,
+
+    /// Whether the system must be running on AC power before a reboot is initiated
+    #[serde(default)]
+    pub require_ac_power: bool,
+
+    /// Minimum battery charge in percent required before a reboot is initiated on battery power
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_battery_percent: Option<u8>,
+
+    /// Process names (e.g., "robocopy.exe") that block automatic reboots while running
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub blocking_processes: Vec<String>,
+
+    /// How far to push an automatic reboot forward when a blocking process is running (e.g., "30m")
+    #[serde(default = "default_blocked_reboot_delay")]
+    pub blocked_reboot_delay: String,
+
+    /// How the reboot is executed
+    #[serde(default)]
+    pub strategy: RebootStrategy,
+
+    /// Message shown by Windows while a reboot is pending
+    #[serde(default = "default_shutdown_message")]
+    pub shutdown_message: String,
+
+    /// Whether applications are closed without asking users to save their work (like `shutdown /f`)
+    #[serde(default)]
+    pub force_apps_closed: bool,
+
+    /// Whether the applications a reboot would close are listed in the confirmation
+    #[serde(default = "default_list_open_applications")]
+    pub list_open_applications: bool,
+
+    /// Text shown above the list of applications a reboot would close
+    #[serde(default = "default_open_applications_message")]
+    pub open_applications_message: String,
+
+    /// Whether applications registered for restart are started again after the user signs in
+    #[serde(default)]
+    pub restart_applications: bool,
+
+    /// Shutdown reason recorded by Windows, in the `shutdown /d [p|u:]xx:yy` format
+    #[serde(default = "default_shutdown_reason_code")]
+    pub reason_code: String,
+
+    /// How long after initiating a reboot the computer must have restarted (e.g., "10m")
+    #[serde(default = "default_reboot_verify_window")]
+    pub verify_window: String,
+}
+
+/// Reboot execution strategy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RebootStrategy {
+    /// Schedule the reboot through InitiateSystemShutdownExW, falling back to shutdown.exe
+    #[default]
+    Api,
+
+    /// Schedule the reboot with shutdown.exe
+    Shutdown,
 }
 
 /// Default value for system reboot config
@@ -392,9 +1832,57 @@ pub fn default_system_reboot_config() -> SystemRebootConfig {
         show_confirmation: true,
         confirmation_message: "The system needs to restart. Do you want to restart now?".to_string(),
         confirmation_title: "System Restart Required".to_string(),
+        confirmation_timeout: default_confirmation_timeout(),
+        require_ac_power: false,
+        min_battery_percent: None,
+        blocking_processes: Vec::new(),
+        blocked_reboot_delay: default_blocked_reboot_delay(),
+        strategy: RebootStrategy::Api,
+        shutdown_message: default_shutdown_message(),
+        force_apps_closed: false,
+        list_open_applications: default_list_open_applications(),
+        open_applications_message: default_open_applications_message(),
+        restart_applications: false,
+        reason_code: default_shutdown_reason_code(),
+        verify_window: default_reboot_verify_window(),
     }
 }
 
+/// Default time users have to answer the reboot confirmation
+fn default_confirmation_timeout() -> String {
+    "5m".to_string()
+}
+
+/// Open applications are listed in the reboot confirmation by default
+fn default_list_open_applications() -> bool {
+    true
+}
+
+/// Default text shown above the applications a reboot would close
+fn default_open_applications_message() -> String {
+    "These applications will be closed:".to_string()
+}
+
+/// Default time a computer has to restart after a reboot is initiated
+fn default_reboot_verify_window() -> String {
+    "10m".to_string()
+}
+
+/// Default value for shutdown message
+fn default_shutdown_message() -> String {
+    "This computer will restart to finish applying updates. Please save your work.".to_string()
+}
+
+/// Default shutdown reason: planned, operating system, hot fix
+fn default_shutdown_reason_code() -> String {
+    crate::reboot::system::DEFAULT_REASON_CODE.to_string()
+}
+
+/// Default value for blocked reboot delay
+fn default_blocked_reboot_delay() -> String {
+    "30m".to_string()
+}
+
 /// Default value for system reboot enabled
 fn default_system_reboot_enabled() -> bool {
     true
@@ -421,6 +1909,46 @@ fn default_system_reboot_message() -> String {
     "The system needs to restart. Do you want to restart now?".to_string()
 }
 
+/// Default value for the power deferral message
+fn default_reboot_deferred_power_message() -> String {
+    "The restart has been deferred until your computer is connected to power.".to_string()
+}
+
+/// Default message for required reboots with a deadline
+fn default_reboot_required_deadline_message() -> String {
+    "Your computer requires a reboot to complete recent updates. It will restart automatically at {deadline}.".to_string()
+}
+
+/// Default message for the countdown before a forced reboot
+fn default_reboot_countdown_message() -> String {
+    "Your computer will restart in {minutes_remaining} minutes to complete recent updates. Please save your work.".to_string()
+}
+
+/// Default message for a Windows feature update waiting for its reboot
+fn default_reboot_required_upgrade_message() -> String {
+    "A Windows feature update is ready to install. Restarting will take longer than usual, up to an hour, so save your work and restart when you can leave your computer on.".to_string()
+}
+
+/// Default value for querying WMI
+fn default_use_wmi() -> bool {
+    true
+}
+
+/// Default value for mirroring the reboot state to the registry
+fn default_registry_status() -> bool {
+    true
+}
+
+/// Send a heartbeat every hour by default
+fn default_heartbeat_interval() -> String {
+    "1h".to_string()
+}
+
+/// Default value for checking for pending Windows feature updates
+fn default_os_upgrade() -> bool {
+    true
+}
+
 /// Default value for system reboot title
 fn default_system_reboot_title() -> String {
     "System Restart Required".to_string()
@@ -445,3 +1973,13 @@ pub fn default_show_tray() -> bool {
 pub fn default_show_balloon() -> bool {
     false
 }
+
+/// Default respect user availability
+pub fn default_respect_user_availability() -> bool {
+    true
+}
+
+/// Default minimum time before the same notification is shown again
+pub fn default_duplicate_interval() -> Option<String> {
+    Some("5m".to_string())
+}
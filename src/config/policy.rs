@@ -0,0 +1,444 @@
+use super::Config;
+use crate::utils::registry;
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use serde_json::Value;
+use windows::Win32::System::Registry::HKEY_LOCAL_MACHINE;
+
+/// Registry key Group Policy writes the settings to
+pub const POLICY_KEY: &str = "SOFTWARE\\Policies\\RebootReminder";
+
+/// Type of a policy setting and of its registry value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyKind {
+    /// Text in a REG_SZ value
+    Text,
+
+    /// Number in a REG_DWORD value
+    Number,
+
+    /// On (1) or off (0) in a REG_DWORD value
+    Boolean,
+
+    /// JSON in a REG_SZ value, for settings that are lists or objects
+    Json,
+}
+
+/// Category a policy setting is shown in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyCategory {
+    Notifications,
+    Reboot,
+}
+
+impl PolicyCategory {
+    fn name(self) -> &'static str {
+        match self {
+            PolicyCategory::Notifications => "Notifications",
+            PolicyCategory::Reboot => "Reboot",
+        }
+    }
+}
+
+/// Configuration setting that can be managed by Group Policy
+#[derive(Debug, Clone, Copy)]
+pub struct Policy {
+    /// Name of the registry value under the policy key, also the policy name in the ADMX
+    pub value_name: &'static str,
+
+    /// Path of the setting in the JSON configuration
+    pub path: &'static [&'static str],
+
+    /// Type of the setting
+    pub kind: PolicyKind,
+
+    /// Category the setting is shown in
+    pub category: PolicyCategory,
+
+    /// Name shown in the Group Policy editor
+    pub display_name: &'static str,
+
+    /// Help text shown in the Group Policy editor
+    pub explain: &'static str,
+}
+
+impl Policy {
+    /// Get the configuration path as a dotted string (e.g., "notification.branding.title")
+    pub fn setting(&self) -> String {
+        self.path.join(".")
+    }
+}
+
+/// Settings that can be managed by Group Policy
+pub const POLICIES: &[Policy] = &[
+    Policy {
+        value_name: "Title",
+        path: &["notification", "branding", "title"],
+        kind: PolicyKind::Text,
+        category: PolicyCategory::Notifications,
+        display_name: "Notification title",
+        explain: "Sets the title shown on reboot notifications.",
+    },
+    Policy {
+        value_name: "Company",
+        path: &["notification", "branding", "company"],
+        kind: PolicyKind::Text,
+        category: PolicyCategory::Notifications,
+        display_name: "Company name",
+        explain: "Sets the company name shown on reboot notifications.",
+    },
+    Policy {
+        value_name: "RebootRequiredMessage",
+        path: &["notification", "messages", "rebootRequired"],
+        kind: PolicyKind::Text,
+        category: PolicyCategory::Notifications,
+        display_name: "Reboot required message",
+        explain: "Sets the message shown when a reboot is required. Placeholders such as {hours_pending} and {deadline} are supported.",
+    },
+    Policy {
+        value_name: "RebootRecommendedMessage",
+        path: &["notification", "messages", "rebootRecommended"],
+        kind: PolicyKind::Text,
+        category: PolicyCategory::Notifications,
+        display_name: "Reboot recommended message",
+        explain: "Sets the message shown when a reboot is recommended.",
+    },
+    Policy {
+        value_name: "RebootRequiredDeadlineMessage",
+        path: &["notification", "messages", "rebootRequiredDeadline"],
+        kind: PolicyKind::Text,
+        category: PolicyCategory::Notifications,
+        display_name: "Reboot deadline message",
+        explain: "Sets the message shown when a reboot is required and a deadline is configured.",
+    },
+    Policy {
+        value_name: "RebootRequiredUpgradeMessage",
+        path: &["notification", "messages", "rebootRequiredUpgrade"],
+        kind: PolicyKind::Text,
+        category: PolicyCategory::Notifications,
+        display_name: "Feature update message",
+        explain: "Sets the message shown when a Windows feature update is waiting for the reboot.",
+    },
+    Policy {
+        value_name: "ShowToast",
+        path: &["notification", "showToast"],
+        kind: PolicyKind::Boolean,
+        category: PolicyCategory::Notifications,
+        display_name: "Show toast notifications",
+        explain: "Enabled shows toast notifications, disabled turns them off.",
+    },
+    Policy {
+        value_name: "ShowTray",
+        path: &["notification", "showTray"],
+        kind: PolicyKind::Boolean,
+        category: PolicyCategory::Notifications,
+        display_name: "Show tray notifications",
+        explain: "Enabled shows tray notifications, disabled turns them off.",
+    },
+    Policy {
+        value_name: "QuietHoursEnabled",
+        path: &["notification", "quietHours", "enabled"],
+        kind: PolicyKind::Boolean,
+        category: PolicyCategory::Notifications,
+        display_name: "Quiet hours",
+        explain: "Enabled suppresses notifications during quiet hours, disabled shows them at any time.",
+    },
+    Policy {
+        value_name: "QuietHoursStart",
+        path: &["notification", "quietHours", "startTime"],
+        kind: PolicyKind::Text,
+        category: PolicyCategory::Notifications,
+        display_name: "Quiet hours start time",
+        explain: "Sets the start of quiet hours in 24-hour format (e.g., 22:00).",
+    },
+    Policy {
+        value_name: "QuietHoursEnd",
+        path: &["notification", "quietHours", "endTime"],
+        kind: PolicyKind::Text,
+        category: PolicyCategory::Notifications,
+        display_name: "Quiet hours end time",
+        explain: "Sets the end of quiet hours in 24-hour format (e.g., 08:00).",
+    },
+    Policy {
+        value_name: "Timeframes",
+        path: &["reboot", "timeframes"],
+        kind: PolicyKind::Json,
+        category: PolicyCategory::Reboot,
+        display_name: "Reminder timeframes",
+        explain: "Sets the reminder timeframes as a JSON array, in the same format as the timeframes in the configuration file.",
+    },
+    Policy {
+        value_name: "MaxDeferrals",
+        path: &["reboot", "maxDeferrals"],
+        kind: PolicyKind::Number,
+        category: PolicyCategory::Reboot,
+        display_name: "Maximum number of deferrals",
+        explain: "Sets how many times users can postpone a reboot.",
+    },
+    Policy {
+        value_name: "Deadline",
+        path: &["reboot", "deadline"],
+        kind: PolicyKind::Text,
+        category: PolicyCategory::Reboot,
+        display_name: "Reboot deadline",
+        explain: "Sets the time after detection by which the reboot is forced, as a timespan (e.g., 120h).",
+    },
+    Policy {
+        value_name: "UpgradeDeadline",
+        path: &["reboot", "upgradeDeadline"],
+        kind: PolicyKind::Text,
+        category: PolicyCategory::Reboot,
+        display_name: "Feature update deadline",
+        explain: "Sets the deadline used instead of the reboot deadline while a Windows feature update is pending, as a timespan (e.g., 336h).",
+    },
+    Policy {
+        value_name: "SystemRebootEnabled",
+        path: &["reboot", "systemReboot", "enabled"],
+        kind: PolicyKind::Boolean,
+        category: PolicyCategory::Reboot,
+        display_name: "Allow restarting from notifications",
+        explain: "Enabled lets users and the deadline restart the computer, disabled only shows reminders.",
+    },
+    Policy {
+        value_name: "RebootCountdown",
+        path: &["reboot", "systemReboot", "countdown"],
+        kind: PolicyKind::Text,
+        category: PolicyCategory::Reboot,
+        display_name: "Restart countdown",
+        explain: "Sets the countdown before a restart, as a timespan (e.g., 30s).",
+    },
+    Policy {
+        value_name: "ShowConfirmation",
+        path: &["reboot", "systemReboot", "showConfirmation"],
+        kind: PolicyKind::Boolean,
+        category: PolicyCategory::Reboot,
+        display_name: "Confirm restarts",
+        explain: "Enabled asks users to confirm a restart, disabled restarts without asking.",
+    },
+];
+
+/// Apply the settings set by Group Policy to a configuration
+///
+/// Policy values take precedence over the configuration file. Returns the settings that are
+/// managed by policy.
+pub fn apply(config: &mut Config) -> Result<Vec<String>> {
+    let overrides: Vec<(&Policy, Value)> = POLICIES
+        .iter()
+        .filter_map(|policy| read_policy(policy).map(|value| (policy, value)))
+        .collect();
+
+    if overrides.is_empty() {
+        debug!("No settings are managed by policy");
+        return Ok(Vec::new());
+    }
+
+    *config = override_config(config, &overrides)?;
+
+    let managed: Vec<String> = overrides.iter().map(|(policy, _)| policy.setting()).collect();
+    for setting in &managed {
+        info!("{} is managed by policy", setting);
+    }
+    Ok(managed)
+}
+
+/// Replace settings in a configuration with policy values
+fn override_config(config: &Config, overrides: &[(&Policy, Value)]) -> Result<Config> {
+    let mut value = serde_json::to_value(config).context("Failed to serialize configuration")?;
+    for (policy, policy_value) in overrides {
+        set_path(&mut value, policy.path, policy_value.clone());
+    }
+    serde_json::from_value(value).context("Invalid policy settings")
+}
+
+/// Set a value in a JSON object, creating the objects along the path
+fn set_path(target: &mut Value, path: &[&str], value: Value) {
+    let (last, parents) = match path.split_last() {
+        Some(split) => split,
+        None => return,
+    };
+
+    let mut current = target;
+    for key in parents {
+        if !current.get(*key).is_some_and(Value::is_object) {
+            current[*key] = Value::Object(Default::default());
+        }
+        current = &mut current[*key];
+    }
+    current[*last] = value;
+}
+
+/// Read the value of a policy from the registry, if it's set
+fn read_policy(policy: &Policy) -> Option<Value> {
+    let result = match policy.kind {
+        PolicyKind::Text | PolicyKind::Json => {
+            registry::get_string_value(HKEY_LOCAL_MACHINE, POLICY_KEY, policy.value_name)
+                .map(|value| value.map(Value::String))
+        }
+        PolicyKind::Number => registry::get_dword_value(HKEY_LOCAL_MACHINE, POLICY_KEY, policy.value_name)
+            .map(|value| value.map(Value::from)),
+        PolicyKind::Boolean => registry::get_dword_value(HKEY_LOCAL_MACHINE, POLICY_KEY, policy.value_name)
+            .map(|value| value.map(|value| Value::Bool(value != 0))),
+    };
+
+    match result {
+        Ok(Some(Value::String(text))) if policy.kind == PolicyKind::Json => match serde_json::from_str(&text) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                warn!("Ignoring policy {} because it isn't valid JSON: {}", policy.value_name, e);
+                None
+            }
+        },
+        Ok(value) => value,
+        Err(e) => {
+            warn!("Failed to read policy {}: {}", policy.value_name, e);
+            None
+        }
+    }
+}
+
+/// Generate the ADMX template for the policy settings
+pub fn admx() -> String {
+    let mut policies = String::new();
+    for policy in POLICIES {
+        let name = policy.value_name;
+        let common = format!(
+            "    <policy name=\"{name}\" class=\"Machine\" displayName=\"$(string.{name})\" explainText=\"$(string.{name}_Help)\"",
+        );
+        let parents = format!(
+            "      <parentCategory ref=\"{}\" />\n      <supportedOn ref=\"SUPPORTED_RebootReminder\" />\n",
+            policy.category.name()
+        );
+
+        let body = match policy.kind {
+            PolicyKind::Boolean => format!(
+                "{common} key=\"{key}\" valueName=\"{name}\">\n{parents}      <enabledValue><decimal value=\"1\" /></enabledValue>\n      <disabledValue><decimal value=\"0\" /></disabledValue>\n",
+                key = POLICY_KEY,
+            ),
+            PolicyKind::Number => format!(
+                "{common} presentation=\"$(presentation.{name})\" key=\"{key}\">\n{parents}      <elements>\n        <decimal id=\"{name}\" valueName=\"{name}\" minValue=\"0\" maxValue=\"1000\" />\n      </elements>\n",
+                key = POLICY_KEY,
+            ),
+            PolicyKind::Text | PolicyKind::Json => format!(
+                "{common} presentation=\"$(presentation.{name})\" key=\"{key}\">\n{parents}      <elements>\n        <text id=\"{name}\" valueName=\"{name}\" required=\"true\"{length} />\n      </elements>\n",
+                key = POLICY_KEY,
+                length = if policy.kind == PolicyKind::Json { " maxLength=\"8192\"" } else { "" },
+            ),
+        };
+        policies.push_str(&body);
+        policies.push_str("    </policy>\n");
+    }
+
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<policyDefinitions xmlns:xsd="http://www.w3.org/2001/XMLSchema" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" revision="1.0" schemaVersion="1.0" xmlns="http://schemas.microsoft.com/GroupPolicy/2006/07/PolicyDefinitions">
+  <policyNamespaces>
+    <target prefix="rebootreminder" namespace="RebootReminder.Policies" />
+  </policyNamespaces>
+  <resources minRequiredRevision="1.0" />
+  <supportedOn>
+    <definitions>
+      <definition name="SUPPORTED_RebootReminder" displayName="$(string.SUPPORTED_RebootReminder)" />
+    </definitions>
+  </supportedOn>
+  <categories>
+    <category name="RebootReminder" displayName="$(string.RebootReminder)" />
+    <category name="Notifications" displayName="$(string.Notifications)">
+      <parentCategory ref="RebootReminder" />
+    </category>
+    <category name="Reboot" displayName="$(string.Reboot)">
+      <parentCategory ref="RebootReminder" />
+    </category>
+  </categories>
+  <policies>
+{policies}  </policies>
+</policyDefinitions>
+"#
+    )
+}
+
+/// Generate the English ADML resources for the ADMX template
+pub fn adml() -> String {
+    let mut strings = String::new();
+    let mut presentations = String::new();
+    for policy in POLICIES {
+        let name = policy.value_name;
+        strings.push_str(&format!("      <string id=\"{}\">{}</string>\n", name, escape_xml(policy.display_name)));
+        strings.push_str(&format!("      <string id=\"{}_Help\">{}</string>\n", name, escape_xml(policy.explain)));
+
+        let label = escape_xml(policy.display_name);
+        match policy.kind {
+            PolicyKind::Boolean => {}
+            PolicyKind::Number => presentations.push_str(&format!(
+                "      <presentation id=\"{name}\">\n        <decimalTextBox refId=\"{name}\">{label}</decimalTextBox>\n      </presentation>\n"
+            )),
+            PolicyKind::Text | PolicyKind::Json => presentations.push_str(&format!(
+                "      <presentation id=\"{name}\">\n        <textBox refId=\"{name}\">\n          <label>{label}</label>\n        </textBox>\n      </presentation>\n"
+            )),
+        }
+    }
+
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<policyDefinitionResources xmlns:xsd="http://www.w3.org/2001/XMLSchema" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" revision="1.0" schemaVersion="1.0" xmlns="http://schemas.microsoft.com/GroupPolicy/2006/07/PolicyDefinitions">
+  <displayName>Reboot Reminder</displayName>
+  <description>Settings for the Reboot Reminder service</description>
+  <resources>
+    <stringTable>
+      <string id="RebootReminder">Reboot Reminder</string>
+      <string id="Notifications">Notifications</string>
+      <string id="Reboot">Reboot</string>
+      <string id="SUPPORTED_RebootReminder">Reboot Reminder</string>
+{strings}    </stringTable>
+    <presentationTable>
+{presentations}    </presentationTable>
+  </resources>
+</policyDefinitionResources>
+"#
+    )
+}
+
+/// Escape text for XML
+pub(crate) fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn policy(value_name: &str) -> &'static Policy {
+        POLICIES.iter().find(|policy| policy.value_name == value_name).unwrap()
+    }
+
+    #[test]
+    fn test_override_config() {
+        let config = crate::config::default();
+        let overrides = vec![
+            (policy("Title"), json!("Managed Title")),
+            (policy("MaxDeferrals"), json!(2)),
+            (policy("ShowToast"), json!(false)),
+            (policy("Timeframes"), json!([{ "min": "24h", "reminderInterval": "4h", "deferrals": ["1h"] }])),
+        ];
+
+        let config = override_config(&config, &overrides).unwrap();
+        assert_eq!(config.notification.branding.title, "Managed Title");
+        assert_eq!(config.reboot.max_deferrals, Some(2));
+        assert!(!config.notification.show_toast);
+        assert_eq!(config.reboot.timeframes.len(), 1);
+        assert_eq!(config.reboot.timeframes[0].deferrals, vec![crate::config::DeferralOption::from("1h")]);
+    }
+
+    #[test]
+    fn test_templates_cover_policies() {
+        let admx = admx();
+        let adml = adml();
+        for policy in POLICIES {
+            assert!(admx.contains(&format!("<policy name=\"{}\"", policy.value_name)));
+            assert!(adml.contains(&format!("<string id=\"{}_Help\">", policy.value_name)));
+        }
+    }
+}
@@ -0,0 +1,312 @@
+use super::{Config, ProfileConfig, ProfileCriteria};
+use crate::reboot::detector::{is_virtual_model, BIOS_KEY};
+use crate::reboot::providers::{with_wmi_connection, CIMV2_NAMESPACE};
+use crate::utils::registry;
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use once_cell::sync::OnceCell;
+use regex::RegexBuilder;
+use serde_derive::Deserialize;
+use serde_json::Value;
+use windows::Win32::System::Power::{GetSystemPowerStatus, SYSTEM_POWER_STATUS};
+use windows::Win32::System::Registry::HKEY_LOCAL_MACHINE;
+
+/// Chassis kinds profiles can select on
+pub const CHASSIS_KINDS: &[&str] = &["laptop", "desktop", "server", "other"];
+
+/// Registry key Group Policy records the computer's distinguished name in
+const GROUP_POLICY_STATE_KEY: &str = "SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\Group Policy\\State\\Machine";
+
+/// Key holding whether Windows is a workstation or a server edition
+const PRODUCT_OPTIONS_KEY: &str = "SYSTEM\\CurrentControlSet\\Control\\ProductOptions";
+
+/// Battery flag meaning the computer has no system battery
+const NO_SYSTEM_BATTERY: u8 = 128;
+
+/// Facts about this computer, queried once since they don't change while the service runs
+static MACHINE_FACTS: OnceCell<MachineFacts> = OnceCell::new();
+
+/// Facts about a computer that profiles are selected by
+#[derive(Debug, Clone, Default)]
+pub struct MachineFacts {
+    /// Computer name
+    pub hostname: String,
+
+    /// Distinguished name of the computer account (e.g., "CN=PC1,OU=Laptops,DC=contoso,DC=com")
+    pub distinguished_name: Option<String>,
+
+    /// Chassis kind: laptop, desktop, server or other
+    pub chassis: String,
+
+    /// Whether the computer is a virtual machine
+    pub is_virtual_machine: bool,
+}
+
+impl MachineFacts {
+    /// Query the facts about this computer, from WMI or, when it's not used, from Win32 APIs and
+    /// the registry
+    fn query(use_wmi: bool) -> Self {
+        #[derive(Debug, Deserialize)]
+        struct SystemEnclosure {
+            #[serde(rename = "ChassisTypes")]
+            chassis_types: Option<Vec<u16>>,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct ComputerSystem {
+            #[serde(rename = "Model")]
+            model: Option<String>,
+        }
+
+        let hostname = std::env::var("COMPUTERNAME").unwrap_or_default();
+        let distinguished_name = registry::get_string_value(HKEY_LOCAL_MACHINE, GROUP_POLICY_STATE_KEY, "Distinguished-Name")
+            .unwrap_or_else(|e| {
+                debug!("Failed to read the computer's distinguished name: {}", e);
+                None
+            });
+
+        if !use_wmi {
+            let facts = Self {
+                hostname,
+                distinguished_name,
+                chassis: read_chassis().to_string(),
+                is_virtual_machine: registry::get_string_value(HKEY_LOCAL_MACHINE, BIOS_KEY, "SystemProductName")
+                    .ok()
+                    .flatten()
+                    .is_some_and(|model| is_virtual_model(&model)),
+            };
+            debug!("Machine facts for profile selection, without WMI: {:?}", facts);
+            return facts;
+        }
+
+        let wmi = with_wmi_connection(CIMV2_NAMESPACE, |wmi_con| {
+            let enclosures: Vec<SystemEnclosure> = wmi_con.raw_query("SELECT ChassisTypes FROM Win32_SystemEnclosure")?;
+            let systems: Vec<ComputerSystem> = wmi_con.raw_query("SELECT Model FROM Win32_ComputerSystem")?;
+            Ok((enclosures, systems))
+        });
+        let (chassis, is_virtual_machine) = match wmi {
+            Ok((enclosures, systems)) => {
+                let chassis_type = enclosures
+                    .into_iter()
+                    .filter_map(|enclosure| enclosure.chassis_types)
+                    .flatten()
+                    .next();
+                let model = systems.into_iter().next().and_then(|system| system.model).unwrap_or_default();
+                (chassis_kind(chassis_type).to_string(), is_virtual_model(&model))
+            }
+            Err(e) => {
+                warn!("Failed to query WMI for the chassis type: {}", e);
+                ("other".to_string(), false)
+            }
+        };
+
+        let facts = Self {
+            hostname,
+            distinguished_name,
+            chassis,
+            is_virtual_machine,
+        };
+        debug!("Machine facts for profile selection: {:?}", facts);
+        facts
+    }
+}
+
+/// Tell the chassis kind without WMI: servers by their Windows edition, laptops by their battery
+fn read_chassis() -> &'static str {
+    let product_type = registry::get_string_value(HKEY_LOCAL_MACHINE, PRODUCT_OPTIONS_KEY, "ProductType").ok().flatten();
+    if product_type.is_some_and(|product_type| !product_type.eq_ignore_ascii_case("WinNT")) {
+        return "server";
+    }
+
+    let mut status = SYSTEM_POWER_STATUS::default();
+    match unsafe { GetSystemPowerStatus(&mut status) } {
+        Ok(()) if status.BatteryFlag != NO_SYSTEM_BATTERY => "laptop",
+        Ok(()) => "desktop",
+        Err(_) => "other",
+    }
+}
+
+/// Map an SMBIOS chassis type to a chassis kind
+fn chassis_kind(chassis_type: Option<u16>) -> &'static str {
+    match chassis_type {
+        Some(8..=12 | 14 | 18 | 21 | 30..=32) => "laptop",
+        Some(3..=7 | 13 | 15 | 16 | 24 | 34..=36) => "desktop",
+        Some(17 | 23 | 25 | 28) => "server",
+        _ => "other",
+    }
+}
+
+/// Apply the first profile that matches this computer to a configuration
+///
+/// Returns the name of the applied profile.
+pub fn apply(config: &mut Config) -> Result<Option<String>> {
+    if config.profiles.is_empty() {
+        return Ok(None);
+    }
+    let use_wmi = config.reboot.use_wmi;
+    apply_for(config, MACHINE_FACTS.get_or_init(|| MachineFacts::query(use_wmi)))
+}
+
+/// Apply the first profile that matches a computer to a configuration
+fn apply_for(config: &mut Config, facts: &MachineFacts) -> Result<Option<String>> {
+    let mut selected = None;
+    for profile in &config.profiles {
+        if matches(&profile.criteria, facts)? {
+            selected = Some(profile.clone());
+            break;
+        }
+    }
+
+    let profile = match selected {
+        Some(profile) => profile,
+        None => {
+            info!("No configuration profile matches this computer");
+            return Ok(None);
+        }
+    };
+
+    info!("Using configuration profile: {}", profile.name);
+    let mut value = serde_json::to_value(&*config).context("Failed to serialize configuration")?;
+    merge(&mut value, &profile.overrides);
+    *config = serde_json::from_value(value)
+        .with_context(|| format!("Invalid overrides in configuration profile {}", profile.name))?;
+
+    Ok(Some(profile.name))
+}
+
+/// Check if a computer meets the criteria of a profile; criteria that aren't set always match
+pub fn matches(criteria: &ProfileCriteria, facts: &MachineFacts) -> Result<bool> {
+    if let Some(pattern) = &criteria.hostname_pattern {
+        let regex = RegexBuilder::new(pattern)
+            .case_insensitive(true)
+            .build()
+            .with_context(|| format!("Invalid hostname pattern: {}", pattern))?;
+        if !regex.is_match(&facts.hostname) {
+            return Ok(false);
+        }
+    }
+
+    if let Some(ou) = &criteria.organizational_unit {
+        let in_ou = facts
+            .distinguished_name
+            .as_ref()
+            .is_some_and(|dn| dn.to_lowercase().ends_with(&format!(",{}", ou.to_lowercase())));
+        if !in_ou {
+            return Ok(false);
+        }
+    }
+
+    if !criteria.chassis_types.is_empty()
+        && !criteria.chassis_types.iter().any(|chassis| chassis.eq_ignore_ascii_case(&facts.chassis))
+    {
+        return Ok(false);
+    }
+
+    if criteria.virtual_machine.is_some_and(|virtual_machine| virtual_machine != facts.is_virtual_machine) {
+        return Ok(false);
+    }
+
+    Ok(true)
+}
+
+/// Merge overrides into a JSON value; objects are merged key by key, anything else is replaced
+pub(super) fn merge(base: &mut Value, overrides: &Value) {
+    match (base, overrides) {
+        (Value::Object(base), Value::Object(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides.clone(),
+    }
+}
+
+/// Check the profiles for invalid criteria
+pub fn validate(profiles: &[ProfileConfig]) -> Result<()> {
+    for profile in profiles {
+        if let Some(pattern) = &profile.criteria.hostname_pattern {
+            regex::Regex::new(pattern)
+                .with_context(|| format!("Invalid hostname pattern in profile {}: {}", profile.name, pattern))?;
+        }
+        for chassis in &profile.criteria.chassis_types {
+            if !CHASSIS_KINDS.iter().any(|kind| kind.eq_ignore_ascii_case(chassis)) {
+                return Err(anyhow::anyhow!(
+                    "Invalid chassis type in profile {}: {}. Expected one of {:?}",
+                    profile.name, chassis, CHASSIS_KINDS
+                ));
+            }
+        }
+        if !profile.overrides.is_object() {
+            return Err(anyhow::anyhow!("Overrides of profile {} must be an object", profile.name));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn laptop() -> MachineFacts {
+        MachineFacts {
+            hostname: "LT-12345".to_string(),
+            distinguished_name: Some("CN=LT-12345,OU=Laptops,OU=Workstations,DC=contoso,DC=com".to_string()),
+            chassis: "laptop".to_string(),
+            is_virtual_machine: false,
+        }
+    }
+
+    #[test]
+    fn test_matches() {
+        let criteria = ProfileCriteria {
+            hostname_pattern: Some("^lt-".to_string()),
+            organizational_unit: Some("OU=Workstations,DC=contoso,DC=com".to_string()),
+            chassis_types: vec!["Laptop".to_string()],
+            virtual_machine: Some(false),
+        };
+        assert!(matches(&criteria, &laptop()).unwrap());
+        assert!(matches(&ProfileCriteria::default(), &laptop()).unwrap());
+
+        let vdi = ProfileCriteria {
+            virtual_machine: Some(true),
+            ..Default::default()
+        };
+        assert!(!matches(&vdi, &laptop()).unwrap());
+    }
+
+    #[test]
+    fn test_apply_profile() {
+        let mut config = crate::config::default();
+        config.profiles = vec![
+            ProfileConfig {
+                name: "servers".to_string(),
+                criteria: ProfileCriteria {
+                    chassis_types: vec!["server".to_string()],
+                    ..Default::default()
+                },
+                overrides: json!({ "notification": { "showToast": false } }),
+            },
+            ProfileConfig {
+                name: "laptops".to_string(),
+                criteria: ProfileCriteria {
+                    chassis_types: vec!["laptop".to_string()],
+                    ..Default::default()
+                },
+                overrides: json!({ "notification": { "branding": { "title": "Laptop Reminder" } }, "reboot": { "maxDeferrals": 5 } }),
+            },
+        ];
+        let company = config.notification.branding.company.clone();
+
+        assert_eq!(apply_for(&mut config, &laptop()).unwrap().as_deref(), Some("laptops"));
+        assert_eq!(config.notification.branding.title, "Laptop Reminder");
+        assert_eq!(config.notification.branding.company, company);
+        assert_eq!(config.reboot.max_deferrals, Some(5));
+        assert!(config.notification.show_toast);
+    }
+}
@@ -0,0 +1,249 @@
+use super::NetworkConfig;
+use crate::utils::http;
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use reqwest::blocking::Client;
+use reqwest::header::{HeaderName, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+use url::Url;
+
+/// Number of attempts made to fetch a remote configuration
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Delay before the first retry, doubled after every failed attempt
+const INITIAL_BACKOFF: Duration = Duration::from_secs(2);
+
+/// Remote configuration content
+#[derive(Debug, Clone)]
+pub struct RemoteContent {
+    /// Configuration file content
+    pub content: String,
+
+    /// ETag returned by the server
+    pub etag: Option<String>,
+
+    /// Last-Modified date returned by the server
+    pub last_modified: Option<String>,
+
+    /// Whether the content came from the local cache
+    pub from_cache: bool,
+
+    /// Minisign signature of the content, kept with the cached copy so it can be verified again
+    /// when the URL can't be reached
+    pub signature: Option<String>,
+}
+
+/// Validators stored next to the cached configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CacheMetadata {
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    #[serde(default)]
+    signature: Option<String>,
+}
+
+/// Local copy of the last good configuration fetched from a URL
+struct RemoteConfigCache {
+    content_path: PathBuf,
+    metadata_path: PathBuf,
+}
+
+impl RemoteConfigCache {
+    /// Get the cache for a URL
+    fn for_url(url: &Url) -> Self {
+        let mut dir = std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(|parent| parent.to_path_buf()))
+            .unwrap_or_else(|| PathBuf::from("."));
+        dir.push("cache");
+
+        let name = cache_file_name(url);
+        Self {
+            content_path: dir.join(format!("{}.cache", name)),
+            metadata_path: dir.join(format!("{}.meta.json", name)),
+        }
+    }
+
+    /// Load the cached content and its validators
+    fn load(&self) -> Option<(String, CacheMetadata)> {
+        let content = fs::read_to_string(&self.content_path).ok()?;
+        let metadata = fs::read_to_string(&self.metadata_path)
+            .ok()
+            .and_then(|metadata| serde_json::from_str(&metadata).ok())
+            .unwrap_or_default();
+        Some((content, metadata))
+    }
+
+    /// Save content and its validators
+    fn save(&self, content: &str, metadata: &CacheMetadata) -> Result<()> {
+        if let Some(parent) = self.content_path.parent() {
+            fs::create_dir_all(parent).context("Failed to create configuration cache directory")?;
+        }
+
+        fs::write(&self.content_path, content).context("Failed to write cached configuration")?;
+        let metadata = serde_json::to_string_pretty(metadata).context("Failed to serialize cache metadata")?;
+        fs::write(&self.metadata_path, metadata).context("Failed to write cache metadata")?;
+        Ok(())
+    }
+}
+
+/// Get the last good copy of the configuration fetched from a URL
+pub fn cached(url: &Url) -> Option<String> {
+    RemoteConfigCache::for_url(url)
+        .load()
+        .filter(|(_, metadata)| metadata.url == url.as_str())
+        .map(|(content, _)| content)
+}
+
+/// Fetch a configuration from a URL through the given network settings
+///
+/// Sends conditional requests using the cached ETag/Last-Modified values, retries with backoff,
+/// and falls back to the last good cached copy when the URL cannot be reached.
+pub fn fetch(url: &Url, network: &NetworkConfig) -> Result<RemoteContent> {
+    let cache = RemoteConfigCache::for_url(url);
+    let cached = cache.load().filter(|(_, metadata)| metadata.url == url.as_str());
+
+    let client = http::client_with(network, Duration::from_secs(30))?;
+
+    let mut backoff = INITIAL_BACKOFF;
+    let mut last_error = None;
+
+    for attempt in 1..=MAX_ATTEMPTS {
+        match fetch_once(&client, url, cached.as_ref().map(|(_, metadata)| metadata)) {
+            Ok(Some(content)) => return Ok(content),
+            Ok(None) => {
+                // Not modified, so the cached copy is current
+                if let Some((content, metadata)) = &cached {
+                    info!("Remote configuration not modified, using cached copy");
+                    return Ok(RemoteContent {
+                        content: content.clone(),
+                        etag: metadata.etag.clone(),
+                        last_modified: metadata.last_modified.clone(),
+                        from_cache: true,
+                        signature: metadata.signature.clone(),
+                    });
+                }
+                last_error = Some(anyhow::anyhow!("Server reported the configuration as not modified but no cached copy exists"));
+            }
+            Err(e) => {
+                warn!("Attempt {}/{} to fetch configuration from {} failed: {:#}", attempt, MAX_ATTEMPTS, url, e);
+                last_error = Some(e);
+            }
+        }
+
+        if attempt < MAX_ATTEMPTS {
+            debug!("Retrying in {} seconds", backoff.as_secs());
+            thread::sleep(backoff);
+            backoff *= 2;
+        }
+    }
+
+    let error = last_error.unwrap_or_else(|| anyhow::anyhow!("Failed to fetch configuration from URL"));
+    match cached {
+        Some((content, metadata)) => {
+            warn!("Using cached copy of the remote configuration: {:#}", error);
+            Ok(RemoteContent {
+                content,
+                etag: metadata.etag,
+                last_modified: metadata.last_modified,
+                from_cache: true,
+                signature: metadata.signature,
+            })
+        }
+        None => Err(error.context("Failed to fetch configuration from URL and no cached copy exists")),
+    }
+}
+
+/// Persist a configuration fetched from a URL as the last good copy
+pub fn store(url: &Url, remote: &RemoteContent) -> Result<()> {
+    if remote.from_cache {
+        return Ok(());
+    }
+
+    let metadata = CacheMetadata {
+        url: url.to_string(),
+        etag: remote.etag.clone(),
+        last_modified: remote.last_modified.clone(),
+        signature: remote.signature.clone(),
+    };
+
+    RemoteConfigCache::for_url(url).save(&remote.content, &metadata)?;
+    debug!("Cached remote configuration from {}", url);
+    Ok(())
+}
+
+/// Send a single request, returning None if the server reports the content as not modified
+fn fetch_once(client: &Client, url: &Url, cached: Option<&CacheMetadata>) -> Result<Option<RemoteContent>> {
+    let mut request = client.get(url.as_str());
+    if let Some(metadata) = cached {
+        if let Some(etag) = &metadata.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &metadata.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    let response = request.send().context("Failed to fetch configuration from URL")?;
+
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(None);
+    }
+
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!(
+            "Failed to fetch configuration from URL: HTTP {}",
+            response.status()
+        ));
+    }
+
+    let header = |name: HeaderName| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string())
+    };
+    let etag = header(ETAG);
+    let last_modified = header(LAST_MODIFIED);
+
+    let content = response.text().context("Failed to read configuration from URL")?;
+    Ok(Some(RemoteContent {
+        content,
+        etag,
+        last_modified,
+        from_cache: false,
+        signature: None,
+    }))
+}
+
+/// Build a file name for the cached copy of a URL
+fn cache_file_name(url: &Url) -> String {
+    let name: String = format!("{}{}", url.host_str().unwrap_or("remote"), url.path())
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+        .collect();
+
+    name.chars().take(100).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_file_name() {
+        let url = Url::parse("https://example.com/configs/reboot.json?version=2").unwrap();
+        assert_eq!(cache_file_name(&url), "example.com_configs_reboot.json");
+
+        let long_url = Url::parse(&format!("https://example.com/{}", "a".repeat(200))).unwrap();
+        assert_eq!(cache_file_name(&long_url).len(), 100);
+    }
+}
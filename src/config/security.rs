@@ -0,0 +1,253 @@
+use super::policy::POLICY_KEY;
+use crate::utils::{http, registry};
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use blake2::{Blake2b512, Digest};
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use log::{debug, info, warn};
+use std::fs;
+use std::time::Duration;
+use url::Url;
+use windows::Win32::System::Registry::HKEY_LOCAL_MACHINE;
+
+/// Extension of the minisign signature stored next to a configuration file
+pub const SIGNATURE_EXTENSION: &str = ".minisig";
+
+/// Restrictions on where configurations are loaded from and what content is accepted
+///
+/// They are read from the policy registry key rather than the configuration, since a
+/// configuration can't vouch for itself.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigSecurity {
+    /// Refuse configurations and includes from plain HTTP URLs
+    pub require_https: bool,
+
+    /// SHA-256 the content of the configuration file must have, in hex
+    pub content_sha256: Option<String>,
+
+    /// Minisign public key every configuration layer must be signed with
+    pub public_key: Option<String>,
+}
+
+impl ConfigSecurity {
+    /// Read the configuration security settings set by Group Policy
+    pub fn from_policy() -> Self {
+        let text = |name: &str| {
+            registry::get_string_value(HKEY_LOCAL_MACHINE, POLICY_KEY, name)
+                .unwrap_or_else(|e| {
+                    warn!("Failed to read policy value {}: {}", name, e);
+                    None
+                })
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+
+        Self {
+            require_https: registry::get_dword_value(HKEY_LOCAL_MACHINE, POLICY_KEY, "RequireHttpsConfig")
+                .ok()
+                .flatten()
+                .is_some_and(|value| value != 0),
+            content_sha256: text("ConfigSha256"),
+            public_key: text("ConfigPublicKey"),
+        }
+    }
+
+    /// Check that a configuration may be loaded from a URL
+    pub fn check_url(&self, url: &Url) -> Result<()> {
+        if self.require_https && url.scheme() == "http" {
+            anyhow::bail!("Configuration URL {} is not HTTPS, which is required by policy", url);
+        }
+        Ok(())
+    }
+
+    /// Check the content of the configuration file against the pinned SHA-256
+    pub fn check_pin(&self, content: &str) -> Result<()> {
+        let Some(expected) = &self.content_sha256 else {
+            return Ok(());
+        };
+
+        let hash = crate::update::sha256_hex(content.as_bytes());
+        if !hash.eq_ignore_ascii_case(expected) {
+            anyhow::bail!("Configuration SHA-256 {} doesn't match the pinned {}", hash, expected);
+        }
+        debug!("Configuration matches the pinned SHA-256");
+        Ok(())
+    }
+
+    /// Check the signature of a configuration layer when a public key is configured
+    ///
+    /// The signature is read from the file or URL of the layer with `.minisig` appended, unless
+    /// the content came from the local cache with its signature. Cached content is verified like
+    /// any other, since the cache could have been changed since. Returns the verified signature,
+    /// to cache with the content.
+    pub fn check_signature(&self, location: &str, content: &str, cached_signature: Option<&str>) -> Result<Option<String>> {
+        let Some(public_key) = &self.public_key else {
+            return Ok(None);
+        };
+
+        let signature = match cached_signature {
+            Some(signature) => {
+                debug!("Verifying cached configuration from {} with its cached signature", location);
+                signature.to_string()
+            }
+            None => read_signature(location)
+                .with_context(|| format!("Configuration {} is not signed, which is required by policy", location))?,
+        };
+        verify_minisign(public_key, content.as_bytes(), &signature)
+            .with_context(|| format!("Signature of configuration {} is invalid", location))?;
+        info!("Verified the signature of configuration {}", location);
+        Ok(Some(signature))
+    }
+}
+
+/// Read the minisign signature of a configuration file or URL
+fn read_signature(location: &str) -> Result<String> {
+    let signature_location = format!("{}{}", location, SIGNATURE_EXTENSION);
+    match Url::parse(&signature_location) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => {
+            let response = http::client(Duration::from_secs(30))?
+                .get(url.as_str())
+                .send()
+                .with_context(|| format!("Failed to fetch {}", url))?;
+            if !response.status().is_success() {
+                anyhow::bail!("Failed to fetch {}: HTTP {}", url, response.status());
+            }
+            response.text().with_context(|| format!("Failed to read {}", url))
+        }
+        Ok(url) if url.scheme() == "file" => {
+            let path = url.to_file_path().map_err(|_| anyhow::anyhow!("Invalid file URL: {}", url))?;
+            fs::read_to_string(path).with_context(|| format!("Failed to read {}", signature_location))
+        }
+        _ => fs::read_to_string(&signature_location).with_context(|| format!("Failed to read {}", signature_location)),
+    }
+}
+
+/// Verify a minisign signature of some content
+///
+/// Both legacy (`Ed`) and prehashed (`ED`) signatures are accepted. The public key can be the
+/// base64 key alone or the content of a `.pub` file.
+pub fn verify_minisign(public_key: &str, content: &[u8], signature_file: &str) -> Result<()> {
+    let public_key = decode_line(public_key, 42).context("Invalid minisign public key")?;
+    if &public_key[..2] != b"Ed" {
+        anyhow::bail!("Unsupported public key algorithm");
+    }
+    let key = VerifyingKey::from_bytes(public_key[10..].try_into()?).context("Invalid minisign public key")?;
+
+    let lines: Vec<&str> = signature_file
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("untrusted comment:"))
+        .collect();
+    let [signature, trusted_comment, global_signature] = lines.as_slice() else {
+        anyhow::bail!("Signature file is not in the minisign format");
+    };
+
+    let signature = decode_line(signature, 74).context("Invalid signature")?;
+    if signature[2..10] != public_key[2..10] {
+        anyhow::bail!("Signature was made with a different key");
+    }
+    let message = match &signature[..2] {
+        b"Ed" => content.to_vec(),
+        b"ED" => Blake2b512::digest(content).to_vec(),
+        _ => anyhow::bail!("Unsupported signature algorithm"),
+    };
+    key.verify(&message, &Signature::from_slice(&signature[10..])?)
+        .map_err(|_| anyhow::anyhow!("Content doesn't match the signature"))?;
+
+    // The trusted comment is signed together with the signature
+    let comment = trusted_comment
+        .strip_prefix("trusted comment: ")
+        .ok_or_else(|| anyhow::anyhow!("Signature file has no trusted comment"))?;
+    let global_signature = decode_line(global_signature, 64).context("Invalid trusted comment signature")?;
+    key.verify(&[&signature[10..], comment.as_bytes()].concat(), &Signature::from_slice(&global_signature)?)
+        .map_err(|_| anyhow::anyhow!("Trusted comment doesn't match its signature"))?;
+
+    Ok(())
+}
+
+/// Decode the base64 line of a minisign key or signature, checking its length
+fn decode_line(text: &str, length: usize) -> Result<Vec<u8>> {
+    let line = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("untrusted comment:"))
+        .last()
+        .unwrap_or_default();
+    let bytes = BASE64.decode(line).context("Not valid base64")?;
+    if bytes.len() != length {
+        anyhow::bail!("Expected {} bytes, got {}", length, bytes.len());
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    /// Sign content the way `minisign -S` does
+    fn sign(key: &SigningKey, key_id: &[u8; 8], content: &[u8]) -> String {
+        let signature = key.sign(&Blake2b512::digest(content)).to_bytes();
+        let comment = "timestamp:1700000000\tfile:config.json";
+        let global = key.sign(&[&signature[..], comment.as_bytes()].concat()).to_bytes();
+        format!(
+            "untrusted comment: signature from minisign secret key\n{}\ntrusted comment: {}\n{}\n",
+            BASE64.encode([&b"ED"[..], key_id, &signature].concat()),
+            comment,
+            BASE64.encode(global)
+        )
+    }
+
+    #[test]
+    fn test_verify_minisign() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let key_id = [1, 2, 3, 4, 5, 6, 7, 8];
+        let public_key = BASE64.encode([&b"Ed"[..], &key_id, key.verifying_key().as_bytes()].concat());
+        let content = br#"{"reboot": {"deadline": "24h"}}"#;
+        let signature = sign(&key, &key_id, content);
+
+        assert!(verify_minisign(&public_key, content, &signature).is_ok());
+        assert!(verify_minisign(&format!("untrusted comment: key\n{}", public_key), content, &signature).is_ok());
+
+        // Modified content and signatures from other keys are refused
+        assert!(verify_minisign(&public_key, br#"{"reboot": {"deadline": "1h"}}"#, &signature).is_err());
+        let other = SigningKey::from_bytes(&[9; 32]);
+        assert!(verify_minisign(&public_key, content, &sign(&other, &key_id, content)).is_err());
+        assert!(verify_minisign(&public_key, content, "not a signature").is_err());
+    }
+
+    #[test]
+    fn test_cached_content_is_verified() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let key_id = [1, 2, 3, 4, 5, 6, 7, 8];
+        let security = ConfigSecurity {
+            public_key: Some(BASE64.encode([&b"Ed"[..], &key_id, key.verifying_key().as_bytes()].concat())),
+            ..Default::default()
+        };
+        let content = r#"{"reboot": {"deadline": "24h"}}"#;
+        let signature = sign(&key, &key_id, content.as_bytes());
+        let location = "https://contoso.com/config.json";
+
+        let verified = security.check_signature(location, content, Some(&signature)).unwrap();
+        assert_eq!(verified.as_deref(), Some(signature.as_str()));
+
+        // A cached copy that was changed after it was cached is refused
+        let tampered = r#"{"reboot": {"deadline": "1m"}}"#;
+        assert!(security.check_signature(location, tampered, Some(&signature)).is_err());
+    }
+
+    #[test]
+    fn test_check_pin_and_url() {
+        let mut security = ConfigSecurity::default();
+        assert!(security.check_pin("{}").is_ok());
+        assert!(security.check_url(&Url::parse("http://contoso.com/config.json").unwrap()).is_ok());
+
+        security.content_sha256 = Some(crate::update::sha256_hex(b"{}").to_uppercase());
+        security.require_https = true;
+        assert!(security.check_pin("{}").is_ok());
+        assert!(security.check_pin("{ }").is_err());
+        assert!(security.check_url(&Url::parse("http://contoso.com/config.json").unwrap()).is_err());
+        assert!(security.check_url(&Url::parse("https://contoso.com/config.json").unwrap()).is_ok());
+    }
+}
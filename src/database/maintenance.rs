@@ -0,0 +1,205 @@
+use super::{DateTimeUtc, DbPool};
+use crate::config::{DatabaseConfig, RetentionConfig};
+use anyhow::{Context, Result};
+use chrono::{Duration, Utc};
+use log::{debug, info, warn};
+use rusqlite::{params, Connection};
+use std::path::Path;
+
+/// Maximum number of trimming passes made to get the database under its size cap
+const MAX_TRIM_PASSES: u32 = 10;
+
+/// Outcome of a database maintenance run
+#[derive(Debug, Clone, Default)]
+pub struct MaintenanceReport {
+    /// Number of notifications deleted
+    pub notifications_deleted: usize,
+
+    /// Number of notification interactions deleted
+    pub interactions_deleted: usize,
+
+    /// Number of reboot history entries deleted
+    pub history_deleted: usize,
+
+    /// Number of applications to restart after a reboot deleted
+    pub restart_applications_deleted: usize,
+
+    /// Number of inactive user sessions deleted
+    pub sessions_deleted: usize,
+
+    /// Number of heartbeats deleted
+    pub heartbeats_deleted: usize,
+
+    /// Size of the database file before maintenance, in bytes
+    pub size_before: u64,
+
+    /// Size of the database file after maintenance, in bytes
+    pub size_after: u64,
+}
+
+/// Delete rows older than the retention limits, compact the database and enforce the size cap
+pub fn run_maintenance(pool: &DbPool, config: &DatabaseConfig) -> Result<MaintenanceReport> {
+    info!("Running database maintenance");
+    let retention = &config.retention;
+    let conn = pool.get().context("Failed to get database connection")?;
+
+    let mut report = MaintenanceReport {
+        size_before: file_size(&config.path),
+        ..Default::default()
+    };
+
+    let cutoff = DateTimeUtc::from(Utc::now() - Duration::days(retention.notification_days as i64));
+
+    report.interactions_deleted += conn.execute(
+        "DELETE FROM notification_interactions
+         WHERE timestamp < ?1 OR notification_id IN (SELECT id FROM notifications WHERE timestamp < ?1)",
+        params![cutoff],
+    ).context("Failed to delete old notification interactions")?;
+
+    report.notifications_deleted += conn.execute(
+        "DELETE FROM notifications WHERE timestamp < ?",
+        params![cutoff],
+    ).context("Failed to delete old notifications")?;
+
+    report.history_deleted = conn.execute(
+        "DELETE FROM reboot_history WHERE id NOT IN
+         (SELECT id FROM reboot_history ORDER BY reboot_time DESC LIMIT ?)",
+        params![retention.max_reboot_history],
+    ).context("Failed to delete old reboot history")?;
+
+    report.restart_applications_deleted = conn.execute(
+        "DELETE FROM restart_applications WHERE initiated_at < ?",
+        params![cutoff],
+    ).context("Failed to delete old restart applications")?;
+
+    report.sessions_deleted = conn.execute(
+        "DELETE FROM user_sessions WHERE is_active = 0 AND last_activity < ?",
+        params![cutoff],
+    ).context("Failed to delete old user sessions")?;
+
+    report.heartbeats_deleted = conn.execute(
+        "DELETE FROM heartbeats WHERE timestamp < ?",
+        params![cutoff],
+    ).context("Failed to delete old heartbeats")?;
+
+    compact(&conn)?;
+
+    // Trim the oldest notifications until the file fits under the size cap
+    if let Some(max_size_mb) = retention.max_size_mb {
+        let max_size = max_size_mb as u64 * 1024 * 1024;
+        let mut passes = 0;
+
+        while file_size(&config.path) > max_size && passes < MAX_TRIM_PASSES {
+            passes += 1;
+            let (notifications, interactions) = trim_oldest_notifications(&conn)?;
+            if notifications == 0 {
+                warn!("Database is larger than {} MB but there are no notifications left to delete", max_size_mb);
+                break;
+            }
+
+            debug!("Trimmed {} notifications to enforce the database size cap", notifications);
+            report.notifications_deleted += notifications;
+            report.interactions_deleted += interactions;
+            compact(&conn)?;
+        }
+    }
+
+    report.size_after = file_size(&config.path);
+    info!(
+        "Database maintenance complete: deleted {} notifications, {} interactions, {} history entries, {} restart applications, {} sessions, {} heartbeats; size {} -> {} bytes",
+        report.notifications_deleted,
+        report.interactions_deleted,
+        report.history_deleted,
+        report.restart_applications_deleted,
+        report.sessions_deleted,
+        report.heartbeats_deleted,
+        report.size_before,
+        report.size_after
+    );
+
+    Ok(report)
+}
+
+/// Delete the oldest tenth of the notifications and their interactions
+fn trim_oldest_notifications(conn: &Connection) -> Result<(usize, usize)> {
+    let count: i64 = conn.query_row("SELECT COUNT(*) FROM notifications", [], |row| row.get(0))?;
+    let limit = (count / 10).max(1);
+
+    let oldest = "SELECT id FROM notifications ORDER BY timestamp ASC LIMIT ?";
+    let interactions = conn.execute(
+        &format!("DELETE FROM notification_interactions WHERE notification_id IN ({})", oldest),
+        params![limit],
+    ).context("Failed to delete notification interactions")?;
+    let notifications = conn.execute(
+        &format!("DELETE FROM notifications WHERE id IN ({})", oldest),
+        params![limit],
+    ).context("Failed to delete notifications")?;
+
+    Ok((notifications, interactions))
+}
+
+/// Reclaim free space and refresh the query planner statistics
+fn compact(conn: &Connection) -> Result<()> {
+    conn.execute_batch("VACUUM; PRAGMA wal_checkpoint(TRUNCATE); PRAGMA optimize;")
+        .context("Failed to compact database")
+}
+
+/// Get the size of the database file, or 0 if it can't be read
+fn file_size(path: &str) -> u64 {
+    std::fs::metadata(Path::new(path)).map(|metadata| metadata.len()).unwrap_or(0)
+}
+
+/// Get the interval between scheduled maintenance runs
+pub fn maintenance_interval(retention: &RetentionConfig) -> Duration {
+    crate::utils::timespan::parse_timespan(&retention.interval)
+        .ok()
+        .and_then(|interval| Duration::from_std(interval).ok())
+        .unwrap_or_else(|| Duration::hours(24))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{add_notification, add_notification_interaction, add_reboot_history, add_restart_applications, get_notifications, get_reboot_history};
+    use crate::database::{Notification, NotificationInteraction, RebootHistory, RestartApplication};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_run_maintenance() {
+        let dir = tempdir().unwrap();
+        let retention = RetentionConfig {
+            notification_days: 30,
+            max_reboot_history: 2,
+            ..Default::default()
+        };
+        let (config, pool) = crate::database::init_file(dir.path(), retention).unwrap();
+
+        let mut old = Notification::new("reboot_required", "old", None);
+        old.timestamp = Utc::now() - Duration::days(31);
+        add_notification(&pool, &old).unwrap();
+        add_notification_interaction(&pool, &NotificationInteraction::new(old.id, "dismiss")).unwrap();
+
+        let recent = Notification::new("reboot_required", "recent", None);
+        add_notification(&pool, &recent).unwrap();
+
+        for days in 0..4 {
+            add_reboot_history(&pool, &RebootHistory::new(Utc::now() - Duration::days(days), true)).unwrap();
+        }
+
+        add_restart_applications(&pool, &[
+            RestartApplication::new(Utc::now() - Duration::days(31), "notepad.exe", 100, 1),
+            RestartApplication::new(Utc::now(), "winword.exe", 200, 1),
+        ]).unwrap();
+
+        let report = run_maintenance(&pool, &config).unwrap();
+        assert_eq!(report.notifications_deleted, 1);
+        assert_eq!(report.interactions_deleted, 1);
+        assert_eq!(report.history_deleted, 2);
+        assert_eq!(report.restart_applications_deleted, 1);
+
+        let notifications = get_notifications(&pool, None).unwrap();
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].id, recent.id);
+        assert_eq!(get_reboot_history(&pool, None).unwrap().len(), 2);
+    }
+}
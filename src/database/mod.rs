@@ -1,20 +1,33 @@
+mod maintenance;
 mod models;
+mod portable;
 
 use anyhow::{Context, Result};
 use log::{debug, info};
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
-use rusqlite::{params, Connection, OptionalExtension, types::{FromSql, FromSqlResult, ToSql, ToSqlOutput, ValueRef}};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, types::{FromSql, FromSqlResult, ToSql, ToSqlOutput, ValueRef}};
 use chrono::{DateTime, Utc, TimeZone};
 use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 use uuid::Uuid;
 
+pub use maintenance::{maintenance_interval, run_maintenance, MaintenanceReport};
 pub use models::*;
+pub use portable::{export_to_file, import_from_file, DatabaseExport, ImportReport};
 
 /// Database connection pool
 pub type DbPool = Arc<Pool<SqliteConnectionManager>>;
 
+/// Number of times saving the reboot state is tried when other writers keep changing it
+const STATE_SAVE_ATTEMPTS: u32 = 5;
+
+/// Error saving a reboot state that another writer has saved since it was read
+#[derive(Debug, thiserror::Error)]
+#[error("The reboot state was changed by another writer since it was read")]
+pub struct StateConflict;
+
 // Define a wrapper type for DateTime<Utc> to implement FromSql and ToSql
 #[derive(Debug, Clone)]
 pub struct DateTimeUtc(pub DateTime<Utc>);
@@ -106,6 +119,16 @@ fn table_exists(conn: &Connection, table_name: &str) -> Result<bool> {
     Ok(exists.is_some())
 }
 
+/// Check if a column exists in a table
+fn column_exists(conn: &Connection, table_name: &str, column_name: &str) -> Result<bool> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table_name))?;
+    let columns = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(columns.iter().any(|column| column.eq_ignore_ascii_case(column_name)))
+}
+
 /// Initialize the database
 pub fn init(config: &crate::config::DatabaseConfig) -> Result<DbPool> {
     let db_path = &config.path;
@@ -122,12 +145,27 @@ pub fn init(config: &crate::config::DatabaseConfig) -> Result<DbPool> {
     }
 
     // Create connection manager
+    // WAL lets the tray agent, CLI and service read while another process writes, and the
+    // busy timeout makes writers wait for each other instead of failing with "database is locked"
     info!("Creating SQLite connection manager for {}", db_path);
-    let manager = SqliteConnectionManager::file(db_path);
+    let busy_timeout = Duration::from_millis(config.busy_timeout_ms as u64);
+    let manager = SqliteConnectionManager::file(db_path).with_init(move |conn| {
+        conn.busy_timeout(busy_timeout)?;
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             PRAGMA synchronous = NORMAL;
+             PRAGMA foreign_keys = ON;",
+        )
+    });
 
     // Create connection pool
-    info!("Creating database connection pool");
-    let pool = Pool::new(manager).context("Failed to create database connection pool")?;
+    info!("Creating database connection pool (max {} connections)", config.max_connections);
+    let pool = Pool::builder()
+        .max_size(config.max_connections)
+        .min_idle(config.min_idle)
+        .connection_timeout(Duration::from_secs(config.connection_timeout_seconds as u64))
+        .build(manager)
+        .context("Failed to create database connection pool")?;
 
     // Initialize database schema
     info!("Getting database connection from pool");
@@ -140,6 +178,62 @@ pub fn init(config: &crate::config::DatabaseConfig) -> Result<DbPool> {
     Ok(Arc::new(pool))
 }
 
+/// Open an existing database for reading only
+///
+/// Signed-in users can only read the database directory, so the tray and the `details` command
+/// fall back to this when [`init`] can't open the database for writing. The schema isn't touched.
+pub fn open_read_only(config: &crate::config::DatabaseConfig) -> Result<DbPool> {
+    info!("Opening database at {} for reading only", config.path);
+    let busy_timeout = Duration::from_millis(config.busy_timeout_ms as u64);
+    let manager = SqliteConnectionManager::file(&config.path)
+        .with_flags(OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_URI | OpenFlags::SQLITE_OPEN_NO_MUTEX)
+        .with_init(move |conn| conn.busy_timeout(busy_timeout));
+    let pool = Pool::builder()
+        .max_size(config.max_connections)
+        .connection_timeout(Duration::from_secs(config.connection_timeout_seconds as u64))
+        .build(manager)
+        .context("Failed to open database for reading")?;
+    Ok(Arc::new(pool))
+}
+
+/// Initialize a database that only lives in memory, for the service test harness
+///
+/// Connections to a named in-memory database with a shared cache all see the same data, which
+/// is dropped with the last connection, so the pool keeps one open.
+#[cfg(any(test, feature = "test-harness"))]
+pub fn init_in_memory() -> Result<DbPool> {
+    let uri = format!("file:rebootreminder-{}?mode=memory&cache=shared", Uuid::new_v4());
+    let manager = SqliteConnectionManager::file(uri)
+        .with_init(|conn| conn.execute_batch("PRAGMA foreign_keys = ON;"));
+    let pool = Pool::builder()
+        .max_size(4)
+        .min_idle(Some(1))
+        .build(manager)
+        .context("Failed to create in-memory database connection pool")?;
+
+    let conn = pool.get().context("Failed to get database connection")?;
+    init_schema(&conn).context("Failed to initialize database schema")?;
+    Ok(Arc::new(pool))
+}
+
+/// Initialize a database file in a directory, for tests that depend on WAL mode or the file
+/// on disk
+///
+/// Returns the configuration the database was created with along with its pool.
+#[cfg(test)]
+pub(crate) fn init_file(dir: &Path, retention: crate::config::RetentionConfig) -> Result<(crate::config::DatabaseConfig, DbPool)> {
+    let config = crate::config::DatabaseConfig {
+        path: dir.join("test.db").to_string_lossy().to_string(),
+        retention,
+        busy_timeout_ms: 5000,
+        max_connections: 2,
+        min_idle: None,
+        connection_timeout_seconds: 30,
+    };
+    let pool = init(&config)?;
+    Ok((config, pool))
+}
+
 /// Initialize database schema
 fn init_schema(conn: &Connection) -> Result<()> {
     info!("Initializing database schema");
@@ -157,16 +251,22 @@ fn init_schema(conn: &Connection) -> Result<()> {
         user_name TEXT,
         computer_name TEXT,
         success INTEGER NOT NULL,
-        duration INTEGER
+        duration INTEGER,
+        closed_applications TEXT
     )";
 
     // Check if table exists before creating
     let exists = table_exists(conn, "reboot_history")?;
     if !exists {
-        info!("Creating reboot_history table with query: {}", query);
+        info!("Creating reboot_history table");
+        crate::logging::sql(query);
         conn.execute(query, [])?;
     } else {
         debug!("reboot_history table already exists");
+        if !column_exists(conn, "reboot_history", "closed_applications")? {
+            info!("Adding closed_applications column to reboot_history table");
+            conn.execute("ALTER TABLE reboot_history ADD COLUMN closed_applications TEXT", [])?;
+        }
     }
 
     // Create reboot_state table
@@ -182,16 +282,32 @@ fn init_schema(conn: &Connection) -> Result<()> {
         scheduled_reboot_time TEXT,
         reboot_reason TEXT,
         created_at TEXT NOT NULL,
-        updated_at TEXT NOT NULL
+        updated_at TEXT NOT NULL,
+        reboot_initiated_at TEXT,
+        version INTEGER NOT NULL DEFAULT 0,
+        lifecycle TEXT
     )";
 
     // Check if table exists before creating
     let exists = table_exists(conn, "reboot_state")?;
     if !exists {
-        info!("Creating reboot_state table with query: {}", query);
+        info!("Creating reboot_state table");
+        crate::logging::sql(query);
         conn.execute(query, [])?;
     } else {
         debug!("reboot_state table already exists");
+        if !column_exists(conn, "reboot_state", "reboot_initiated_at")? {
+            info!("Adding reboot_initiated_at column to reboot_state table");
+            conn.execute("ALTER TABLE reboot_state ADD COLUMN reboot_initiated_at TEXT", [])?;
+        }
+        if !column_exists(conn, "reboot_state", "version")? {
+            info!("Adding version column to reboot_state table");
+            conn.execute("ALTER TABLE reboot_state ADD COLUMN version INTEGER NOT NULL DEFAULT 0", [])?;
+        }
+        if !column_exists(conn, "reboot_state", "lifecycle")? {
+            info!("Adding lifecycle column to reboot_state table");
+            conn.execute("ALTER TABLE reboot_state ADD COLUMN lifecycle TEXT", [])?;
+        }
     }
 
     // Create reboot_sources table
@@ -202,18 +318,29 @@ fn init_schema(conn: &Connection) -> Result<()> {
         description TEXT,
         severity TEXT NOT NULL,
         detected_at TEXT NOT NULL,
+        last_seen TEXT,
         expires_at TEXT,
         details TEXT,
+        user_name TEXT,
         FOREIGN KEY (reboot_state_id) REFERENCES reboot_state (id) ON DELETE CASCADE
     )";
 
     // Check if table exists before creating
     let exists = table_exists(conn, "reboot_sources")?;
     if !exists {
-        info!("Creating reboot_sources table with query: {}", query);
+        info!("Creating reboot_sources table");
+        crate::logging::sql(query);
         conn.execute(query, [])?;
     } else {
         debug!("reboot_sources table already exists");
+        if !column_exists(conn, "reboot_sources", "user_name")? {
+            info!("Adding user_name column to reboot_sources table");
+            conn.execute("ALTER TABLE reboot_sources ADD COLUMN user_name TEXT", [])?;
+        }
+        if !column_exists(conn, "reboot_sources", "last_seen")? {
+            info!("Adding last_seen column to reboot_sources table");
+            conn.execute("ALTER TABLE reboot_sources ADD COLUMN last_seen TEXT", [])?;
+        }
     }
 
     // Create notifications table
@@ -225,16 +352,45 @@ fn init_schema(conn: &Connection) -> Result<()> {
         user_name TEXT,
         dismissed INTEGER NOT NULL,
         action TEXT,
-        created_at TEXT NOT NULL
+        created_at TEXT NOT NULL,
+        suppression_reason TEXT,
+        delivery_status TEXT NOT NULL DEFAULT 'delivered',
+        delivery_attempts INTEGER NOT NULL DEFAULT 0,
+        next_attempt_time TEXT,
+        delivery_error TEXT,
+        session_id TEXT,
+        message_hash TEXT
     )";
 
     // Check if table exists before creating
     let exists = table_exists(conn, "notifications")?;
     if !exists {
-        info!("Creating notifications table with query: {}", query);
+        info!("Creating notifications table");
+        crate::logging::sql(query);
         conn.execute(query, [])?;
     } else {
         debug!("notifications table already exists");
+        if !column_exists(conn, "notifications", "suppression_reason")? {
+            info!("Adding suppression_reason column to notifications table");
+            conn.execute("ALTER TABLE notifications ADD COLUMN suppression_reason TEXT", [])?;
+        }
+        if !column_exists(conn, "notifications", "delivery_status")? {
+            // Notifications from before delivery tracking were shown or lost, never retried
+            info!("Adding delivery columns to notifications table");
+            conn.execute_batch(
+                "ALTER TABLE notifications ADD COLUMN delivery_status TEXT NOT NULL DEFAULT 'delivered';
+                 ALTER TABLE notifications ADD COLUMN delivery_attempts INTEGER NOT NULL DEFAULT 0;
+                 ALTER TABLE notifications ADD COLUMN next_attempt_time TEXT;
+                 ALTER TABLE notifications ADD COLUMN delivery_error TEXT;",
+            )?;
+        }
+        if !column_exists(conn, "notifications", "message_hash")? {
+            info!("Adding duplicate detection columns to notifications table");
+            conn.execute_batch(
+                "ALTER TABLE notifications ADD COLUMN session_id TEXT;
+                 ALTER TABLE notifications ADD COLUMN message_hash TEXT;",
+            )?;
+        }
     }
 
     // Create notification_interactions table
@@ -246,16 +402,22 @@ fn init_schema(conn: &Connection) -> Result<()> {
         user_name TEXT,
         session_id TEXT,
         details TEXT,
+        deferral_id TEXT,
         FOREIGN KEY (notification_id) REFERENCES notifications (id) ON DELETE CASCADE
     )";
 
     // Check if table exists before creating
     let exists = table_exists(conn, "notification_interactions")?;
     if !exists {
-        info!("Creating notification_interactions table with query: {}", query);
+        info!("Creating notification_interactions table");
+        crate::logging::sql(query);
         conn.execute(query, [])?;
     } else {
         debug!("notification_interactions table already exists");
+        if !column_exists(conn, "notification_interactions", "deferral_id")? {
+            info!("Adding deferral_id column to notification_interactions table");
+            conn.execute("ALTER TABLE notification_interactions ADD COLUMN deferral_id TEXT", [])?;
+        }
     }
 
     // Create user_sessions table
@@ -278,12 +440,107 @@ fn init_schema(conn: &Connection) -> Result<()> {
     // Check if table exists before creating
     let exists = table_exists(conn, "user_sessions")?;
     if !exists {
-        info!("Creating user_sessions table with query: {}", query);
+        info!("Creating user_sessions table");
+        crate::logging::sql(query);
         conn.execute(query, [])?;
     } else {
         debug!("user_sessions table already exists");
     }
 
+    // Create reboot_periods table
+    let query = "CREATE TABLE IF NOT EXISTS reboot_periods (
+        id TEXT PRIMARY KEY,
+        required_since TEXT NOT NULL,
+        resolved_at TEXT
+    )";
+
+    // Check if table exists before creating
+    let exists = table_exists(conn, "reboot_periods")?;
+    if !exists {
+        info!("Creating reboot_periods table");
+        crate::logging::sql(query);
+        conn.execute(query, [])?;
+    } else {
+        debug!("reboot_periods table already exists");
+    }
+
+    // Create restart_applications table
+    let query = "CREATE TABLE IF NOT EXISTS restart_applications (
+        id TEXT PRIMARY KEY,
+        initiated_at TEXT NOT NULL,
+        name TEXT NOT NULL,
+        process_id INTEGER NOT NULL,
+        session_id INTEGER NOT NULL
+    )";
+
+    // Check if table exists before creating
+    let exists = table_exists(conn, "restart_applications")?;
+    if !exists {
+        info!("Creating restart_applications table");
+        crate::logging::sql(query);
+        conn.execute(query, [])?;
+    } else {
+        debug!("restart_applications table already exists");
+    }
+
+    // Create source_debounce table
+    let query = "CREATE TABLE IF NOT EXISTS source_debounce (
+        name TEXT PRIMARY KEY,
+        seen INTEGER NOT NULL,
+        missing INTEGER NOT NULL,
+        counted INTEGER NOT NULL,
+        flaps INTEGER NOT NULL,
+        sources TEXT NOT NULL
+    )";
+
+    // Check if table exists before creating
+    let exists = table_exists(conn, "source_debounce")?;
+    if !exists {
+        info!("Creating source_debounce table");
+        crate::logging::sql(query);
+        conn.execute(query, [])?;
+    } else {
+        debug!("source_debounce table already exists");
+    }
+
+    // Create report_cursors table
+    let query = "CREATE TABLE IF NOT EXISTS report_cursors (
+        sink TEXT PRIMARY KEY,
+        reported_until TEXT NOT NULL
+    )";
+
+    // Check if table exists before creating
+    let exists = table_exists(conn, "report_cursors")?;
+    if !exists {
+        info!("Creating report_cursors table");
+        crate::logging::sql(query);
+        conn.execute(query, [])?;
+    } else {
+        debug!("report_cursors table already exists");
+    }
+
+    // Create heartbeats table
+    let query = "CREATE TABLE IF NOT EXISTS heartbeats (
+        id TEXT PRIMARY KEY,
+        timestamp TEXT NOT NULL,
+        version TEXT NOT NULL,
+        config_hash TEXT NOT NULL,
+        reboot_required INTEGER NOT NULL,
+        lifecycle TEXT NOT NULL,
+        sources TEXT NOT NULL,
+        postpone_count INTEGER NOT NULL
+    )";
+
+    // Check if table exists before creating
+    let exists = table_exists(conn, "heartbeats")?;
+    if !exists {
+        info!("Creating heartbeats table");
+        crate::logging::sql(query);
+        conn.execute(query, [])?;
+    } else {
+        debug!("heartbeats table already exists");
+    }
+
     info!("Database schema initialized successfully");
     Ok(())
 }
@@ -291,18 +548,20 @@ fn init_schema(conn: &Connection) -> Result<()> {
 /// Get the current reboot state
 pub fn get_reboot_state(pool: &DbPool) -> Result<Option<RebootState>> {
     info!("Getting current reboot state from database");
+    let _timer = crate::metrics::Timer::start("db.get_reboot_state");
     let conn = pool.get().context("Failed to get database connection")?;
 
     let query = "SELECT id, reboot_required, reboot_recommended, last_check_time, reboot_required_since, last_reboot_time,
          postpone_count, next_reminder_time, scheduled_reboot_time, reboot_reason,
-         created_at, updated_at FROM reboot_state ORDER BY created_at DESC LIMIT 1";
+         created_at, updated_at, reboot_initiated_at, version, lifecycle FROM reboot_state ORDER BY created_at DESC LIMIT 1";
 
-    info!("Executing query: {}", query);
+    crate::logging::sql(query);
     let state = conn.query_row(
         query,
         [],
         |row| {
-            Ok(RebootState {
+            let lifecycle: Option<String> = row.get(14)?;
+            let mut state = RebootState {
                 id: row.get::<_, UuidWrapper>(0)?.into(),
                 reboot_required: row.get(1)?,
                 reboot_recommended: row.get(2)?,
@@ -313,10 +572,20 @@ pub fn get_reboot_state(pool: &DbPool) -> Result<Option<RebootState>> {
                 next_reminder_time: row.get::<_, Option<DateTimeUtc>>(7)?.map(Into::into),
                 scheduled_reboot_time: row.get::<_, Option<DateTimeUtc>>(8)?.map(Into::into),
                 reboot_reason: row.get(9)?,
+                reboot_initiated_at: row.get::<_, Option<DateTimeUtc>>(12)?.map(Into::into),
                 sources: Vec::new(), // Will be populated separately
                 created_at: row.get::<_, DateTimeUtc>(10)?.into(),
                 updated_at: row.get::<_, DateTimeUtc>(11)?.into(),
-            })
+                version: row.get(13)?,
+                lifecycle: RebootLifecycle::default(),
+            };
+
+            // States saved before the lifecycle was recorded get it from their other fields
+            state.lifecycle = match lifecycle.as_deref().map(str::parse) {
+                Some(Ok(lifecycle)) => lifecycle,
+                _ => state.derive_lifecycle(),
+            };
+            Ok(state)
         },
     ).optional().context(format!("Failed to execute query: {}", query))?;
 
@@ -328,22 +597,26 @@ pub fn get_reboot_state(pool: &DbPool) -> Result<Option<RebootState>> {
 
     // If we found a state, get its sources
     if let Some(mut state) = state {
-        let sources_query = "SELECT id, name, description, severity, detected_at, expires_at, details
-             FROM reboot_sources WHERE reboot_state_id = ?";
+        let sources_query = "SELECT id, name, description, severity, detected_at, expires_at, details, user_name, last_seen
+             FROM reboot_sources WHERE reboot_state_id = ? ORDER BY detected_at";
 
-        info!("Executing query: {} with params: [{}]", sources_query, state.id);
+        crate::logging::sql(sources_query);
         let mut stmt = conn.prepare(sources_query)
             .context(format!("Failed to prepare query: {}", sources_query))?;
 
         let sources = stmt.query_map([&UuidWrapper::from(state.id)], |row| {
+            let detected_at: DateTime<Utc> = row.get::<_, DateTimeUtc>(4)?.into();
             Ok(RebootSource {
                 id: row.get::<_, UuidWrapper>(0)?.into(),
                 name: row.get(1)?,
                 description: row.get(2)?,
                 severity: row.get(3)?,
-                detected_at: row.get::<_, DateTimeUtc>(4)?.into(),
+                detected_at,
+                // Sources saved before the last check time was recorded were last seen when found
+                last_seen: row.get::<_, Option<DateTimeUtc>>(8)?.map(Into::into).unwrap_or(detected_at),
                 expires_at: row.get::<_, Option<DateTimeUtc>>(5)?.map(Into::into),
                 details: row.get(6)?,
+                user_name: row.get(7)?,
             })
         })?
         .collect::<Result<Vec<_>, _>>()?;
@@ -356,26 +629,41 @@ pub fn get_reboot_state(pool: &DbPool) -> Result<Option<RebootState>> {
 }
 
 /// Save a reboot state
+///
+/// The state is only saved if it hasn't been saved since it was read, otherwise this fails with
+/// `StateConflict`. The saved version is one more than the version of `state`.
 pub fn save_reboot_state(pool: &DbPool, state: &RebootState) -> Result<()> {
     info!("Saving reboot state to database: id={}, required={}", state.id, state.reboot_required);
+    let _timer = crate::metrics::Timer::start("db.save_reboot_state");
     let mut conn = pool.get().context("Failed to get database connection")?;
 
     // Begin transaction
     info!("Beginning database transaction");
     let tx = conn.transaction()?;
+    write_reboot_state(&tx, state)?;
+
+    // Commit transaction
+    info!("Committing database transaction");
+    tx.commit()?;
 
-    // Insert or update reboot state
-    let state_query = "INSERT OR REPLACE INTO reboot_state (
-            id, reboot_required, reboot_recommended, last_check_time, reboot_required_since, last_reboot_time,
-            postpone_count, next_reminder_time, scheduled_reboot_time, reboot_reason,
-            created_at, updated_at
-        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)";
+    info!("Reboot state and sources saved successfully");
+    Ok(())
+}
 
-    info!("Executing query to save reboot state: {}", state_query);
-    tx.execute(
-        state_query,
+/// Write a reboot state and its sources on a connection inside a transaction, failing with
+/// `StateConflict` when the state was saved since it was read
+fn write_reboot_state(tx: &Connection, state: &RebootState) -> Result<()> {
+    // Update the reboot state if nobody else has saved it since it was read
+    let update_query = "UPDATE reboot_state SET
+            reboot_required = ?, reboot_recommended = ?, last_check_time = ?, reboot_required_since = ?,
+            last_reboot_time = ?, postpone_count = ?, next_reminder_time = ?, scheduled_reboot_time = ?,
+            reboot_reason = ?, updated_at = ?, reboot_initiated_at = ?, version = ?, lifecycle = ?
+        WHERE id = ? AND version = ?";
+
+    crate::logging::sql(update_query);
+    let updated = tx.execute(
+        update_query,
         params![
-            UuidWrapper::from(state.id),
             state.reboot_required,
             state.reboot_recommended,
             DateTimeUtc::from(state.last_check_time),
@@ -385,32 +673,89 @@ pub fn save_reboot_state(pool: &DbPool, state: &RebootState) -> Result<()> {
             state.next_reminder_time.map(DateTimeUtc::from),
             state.scheduled_reboot_time.map(DateTimeUtc::from),
             state.reboot_reason,
-            DateTimeUtc::from(state.created_at),
             DateTimeUtc::from(state.updated_at),
+            state.reboot_initiated_at.map(DateTimeUtc::from),
+            state.version + 1,
+            state.lifecycle.as_str(),
+            UuidWrapper::from(state.id),
+            state.version,
         ],
     )?;
 
-    info!("Reboot state saved successfully");
+    if updated == 0 {
+        let exists_query = "SELECT version FROM reboot_state WHERE id = ?";
+        crate::logging::sql(exists_query);
+        let current: Option<u32> = tx
+            .query_row(exists_query, [&UuidWrapper::from(state.id)], |row| row.get(0))
+            .optional()?;
+        if let Some(current) = current {
+            debug!("Reboot state is at version {}, expected {}", current, state.version);
+            return Err(StateConflict.into());
+        }
 
-    // Delete existing sources
-    let delete_query = "DELETE FROM reboot_sources WHERE reboot_state_id = ?";
-    info!("Executing query to delete existing reboot sources: {}", delete_query);
-    let deleted_rows = tx.execute(
-        delete_query,
-        [&UuidWrapper::from(state.id)],
-    )?;
-    info!("Deleted {} existing reboot sources", deleted_rows);
+        // Insert a new reboot state
+        let insert_query = "INSERT INTO reboot_state (
+                id, reboot_required, reboot_recommended, last_check_time, reboot_required_since, last_reboot_time,
+                postpone_count, next_reminder_time, scheduled_reboot_time, reboot_reason,
+                created_at, updated_at, reboot_initiated_at, version, lifecycle
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)";
+
+        crate::logging::sql(insert_query);
+        tx.execute(
+            insert_query,
+            params![
+                UuidWrapper::from(state.id),
+                state.reboot_required,
+                state.reboot_recommended,
+                DateTimeUtc::from(state.last_check_time),
+                state.reboot_required_since.map(DateTimeUtc::from),
+                state.last_reboot_time.map(DateTimeUtc::from),
+                state.postpone_count,
+                state.next_reminder_time.map(DateTimeUtc::from),
+                state.scheduled_reboot_time.map(DateTimeUtc::from),
+                state.reboot_reason,
+                DateTimeUtc::from(state.created_at),
+                DateTimeUtc::from(state.updated_at),
+                state.reboot_initiated_at.map(DateTimeUtc::from),
+                state.version + 1,
+                state.lifecycle.as_str(),
+            ],
+        )?;
+    }
+
+    info!("Reboot state saved successfully");
 
-    // Insert new sources
-    info!("Inserting {} new reboot sources", state.sources.len());
-    let insert_query = "INSERT INTO reboot_sources (
-                id, reboot_state_id, name, description, severity, detected_at, expires_at, details
-            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?)";
+    // Delete the sources that were cleared, keeping the rows of the sources that are still found
+    let existing_query = "SELECT id FROM reboot_sources WHERE reboot_state_id = ?";
+    crate::logging::sql(existing_query);
+    let existing = tx
+        .prepare(existing_query)?
+        .query_map([&UuidWrapper::from(state.id)], |row| Ok(Uuid::from(row.get::<_, UuidWrapper>(0)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
 
+    let delete_query = "DELETE FROM reboot_sources WHERE id = ?";
+    crate::logging::sql(delete_query);
+    let mut deleted_rows = 0;
+    for id in existing.iter().filter(|id| !state.sources.iter().any(|source| source.id == **id)) {
+        deleted_rows += tx.execute(delete_query, [&UuidWrapper::from(*id)])?;
+    }
+    info!("Deleted {} cleared reboot sources", deleted_rows);
+
+    // Insert new sources and update the ones that are still found
+    info!("Saving {} reboot sources", state.sources.len());
+    let upsert_query = "INSERT INTO reboot_sources (
+                id, reboot_state_id, name, description, severity, detected_at, expires_at, details, user_name, last_seen
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT(id) DO UPDATE SET
+                reboot_state_id = excluded.reboot_state_id, name = excluded.name, description = excluded.description,
+                severity = excluded.severity, detected_at = excluded.detected_at, expires_at = excluded.expires_at,
+                details = excluded.details, user_name = excluded.user_name, last_seen = excluded.last_seen";
+
+    crate::logging::sql(upsert_query);
     for source in &state.sources {
-        info!("Inserting reboot source: id={}, name={}", source.id, source.name);
+        debug!("Saving reboot source: id={}, name={}", source.id, source.name);
         tx.execute(
-            insert_query,
+            upsert_query,
             params![
                 UuidWrapper::from(source.id),
                 UuidWrapper::from(state.id),
@@ -420,28 +765,117 @@ pub fn save_reboot_state(pool: &DbPool, state: &RebootState) -> Result<()> {
                 DateTimeUtc::from(source.detected_at),
                 source.expires_at.map(DateTimeUtc::from),
                 source.details,
+                source.user_name,
+                DateTimeUtc::from(source.last_seen),
             ],
         )?;
     }
 
-    // Commit transaction
-    info!("Committing database transaction");
-    tx.commit()?;
-
-    info!("Reboot state and sources saved successfully");
     Ok(())
 }
 
+/// Read, change and save the reboot state, making the change again on the latest state when
+/// another writer saved it in the meantime
+///
+/// A change that fails, e.g. because the latest state doesn't allow it, isn't saved and its
+/// error is returned. Returns the saved state, or None when there is no reboot state yet.
+pub fn update_reboot_state<F>(pool: &DbPool, mut change: F) -> Result<Option<RebootState>>
+where
+    F: FnMut(&mut RebootState) -> Result<()>,
+{
+    for _ in 0..STATE_SAVE_ATTEMPTS {
+        let mut state = match get_reboot_state(pool)? {
+            Some(state) => state,
+            None => return Ok(None),
+        };
+        change(&mut state)?;
+
+        match save_reboot_state(pool, &state) {
+            Ok(()) => {
+                state.version += 1;
+                return Ok(Some(state));
+            }
+            Err(e) if e.is::<StateConflict>() => info!("Reboot state was changed while updating it, trying again"),
+            Err(e) => return Err(e),
+        }
+    }
+
+    Err(StateConflict.into())
+}
+
+/// Read, change and save the reboot state together with the notification interaction that
+/// changed it, in one transaction, so the interaction is only recorded when the change is saved
+///
+/// The change is made again on the latest state when another writer saved it in the meantime.
+/// When the change fails, neither it nor the interaction is saved. Returns the saved state, or
+/// None when there is no reboot state yet.
+pub fn update_reboot_state_with_interaction<F>(
+    pool: &DbPool,
+    interaction: &NotificationInteraction,
+    mut change: F,
+) -> Result<Option<RebootState>>
+where
+    F: FnMut(&mut RebootState) -> Result<()>,
+{
+    for _ in 0..STATE_SAVE_ATTEMPTS {
+        let mut state = match get_reboot_state(pool)? {
+            Some(state) => state,
+            None => return Ok(None),
+        };
+        change(&mut state)?;
+
+        let mut conn = pool.get().context("Failed to get database connection")?;
+        let tx = conn.transaction()?;
+        match write_reboot_state(&tx, &state) {
+            Ok(()) => {
+                insert_notification_interaction(&tx, interaction)?;
+                tx.commit()?;
+                state.version += 1;
+                return Ok(Some(state));
+            }
+            Err(e) if e.is::<StateConflict>() => info!("Reboot state was changed while updating it, trying again"),
+            Err(e) => return Err(e),
+        }
+    }
+
+    Err(StateConflict.into())
+}
+
+/// Save a reboot state that was changed from `base`, merging in the changes other writers saved
+/// since `base` was read
+///
+/// For changes that can't simply be made again, like the outcome of a reboot check or a reboot.
+/// Returns the saved state.
+pub fn save_reboot_state_merged(pool: &DbPool, base: &RebootState, state: &RebootState) -> Result<RebootState> {
+    let mut merged = state.clone();
+    for _ in 0..STATE_SAVE_ATTEMPTS {
+        match save_reboot_state(pool, &merged) {
+            Ok(()) => {
+                merged.version += 1;
+                return Ok(merged);
+            }
+            Err(e) if e.is::<StateConflict>() => {
+                let latest = get_reboot_state(pool)?.ok_or(e)?;
+                info!("Reboot state was changed by another writer, merging the changes");
+                merged = state.merge(base, &latest);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    Err(StateConflict.into())
+}
+
 /// Add a reboot history entry
 pub fn add_reboot_history(pool: &DbPool, history: &RebootHistory) -> Result<()> {
     info!("Adding reboot history entry to database: id={}, time={}", history.id, history.reboot_time);
     let conn = pool.get().context("Failed to get database connection")?;
 
     let query = "INSERT INTO reboot_history (
-            id, reboot_time, reason, source, user_name, computer_name, success, duration
-        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?)";
+            id, reboot_time, reason, source, user_name, computer_name, success, duration, closed_applications
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)";
 
-    info!("Executing query: {} with params: [id={}, time={}]", query, history.id, history.reboot_time);
+    crate::logging::sql(query);
     conn.execute(
         query,
         params![
@@ -453,6 +887,9 @@ pub fn add_reboot_history(pool: &DbPool, history: &RebootHistory) -> Result<()>
             history.computer_name,
             history.success,
             history.duration,
+            (!history.closed_applications.is_empty())
+                .then(|| serde_json::to_string(&history.closed_applications))
+                .transpose()?,
         ],
     ).context(format!("Failed to execute query: {}", query))?;
 
@@ -468,46 +905,307 @@ pub fn get_reboot_history(pool: &DbPool, limit: Option<u32>) -> Result<Vec<Reboo
     let limit_clause = limit.map_or(String::from(""), |l| format!("LIMIT {}", l));
 
     let query = format!(
-        "SELECT id, reboot_time, reason, source, user_name, computer_name, success, duration
-         FROM reboot_history ORDER BY reboot_time DESC {}",
-        limit_clause
+        "SELECT {} FROM reboot_history ORDER BY reboot_time DESC {}",
+        REBOOT_HISTORY_COLUMNS, limit_clause
     );
 
-    info!("Executing query: {}", query);
+    crate::logging::sql(&query);
     let mut stmt = conn.prepare(&query)
         .context(format!("Failed to prepare query: {}", query))?;
 
-    let history = stmt.query_map([], |row| {
-        Ok(RebootHistory {
-            id: row.get::<_, UuidWrapper>(0)?.into(),
-            reboot_time: row.get::<_, DateTimeUtc>(1)?.into(),
-            reason: row.get(2)?,
-            source: row.get(3)?,
-            user_name: row.get(4)?,
-            computer_name: row.get(5)?,
-            success: row.get(6)?,
-            duration: row.get(7)?,
-        })
-    })?
-    .collect::<Result<Vec<_>, _>>()?;
+    let history = stmt.query_map([], reboot_history_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
 
     Ok(history)
 }
 
+/// Get the reboots recorded after a time, oldest first
+pub fn get_reboot_history_after(pool: &DbPool, after: DateTime<Utc>) -> Result<Vec<RebootHistory>> {
+    let conn = pool.get().context("Failed to get database connection")?;
+
+    let query = format!(
+        "SELECT {} FROM reboot_history WHERE reboot_time > ? ORDER BY reboot_time",
+        REBOOT_HISTORY_COLUMNS
+    );
+
+    crate::logging::sql(&query);
+    let mut stmt = conn.prepare(&query)
+        .context(format!("Failed to prepare query: {}", query))?;
+
+    let history = stmt
+        .query_map(params![DateTimeUtc::from(after)], reboot_history_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(history)
+}
+
+/// Columns of the reboot history read by `reboot_history_from_row`
+const REBOOT_HISTORY_COLUMNS: &str =
+    "id, reboot_time, reason, source, user_name, computer_name, success, duration, closed_applications";
+
+/// Read a reboot history entry selected with `REBOOT_HISTORY_COLUMNS`
+fn reboot_history_from_row(row: &rusqlite::Row) -> rusqlite::Result<RebootHistory> {
+    Ok(RebootHistory {
+        id: row.get::<_, UuidWrapper>(0)?.into(),
+        reboot_time: row.get::<_, DateTimeUtc>(1)?.into(),
+        reason: row.get(2)?,
+        source: row.get(3)?,
+        user_name: row.get(4)?,
+        computer_name: row.get(5)?,
+        success: row.get(6)?,
+        duration: row.get(7)?,
+        closed_applications: row
+            .get::<_, Option<String>>(8)?
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default(),
+    })
+}
+
+/// Record that a reboot became required
+pub fn open_reboot_period(pool: &DbPool, required_since: DateTime<Utc>) -> Result<()> {
+    let conn = pool.get().context("Failed to get database connection")?;
+    let period = RebootPeriod::new(required_since);
+
+    conn.execute(
+        "INSERT INTO reboot_periods (id, required_since, resolved_at) VALUES (?, ?, NULL)",
+        params![UuidWrapper::from(period.id), DateTimeUtc::from(period.required_since)],
+    )
+    .context("Failed to add reboot period")?;
+
+    Ok(())
+}
+
+/// Add a reboot period as is, e.g. from an export
+pub fn add_reboot_period(pool: &DbPool, period: &RebootPeriod) -> Result<()> {
+    let conn = pool.get().context("Failed to get database connection")?;
+
+    conn.execute(
+        "INSERT INTO reboot_periods (id, required_since, resolved_at) VALUES (?, ?, ?)",
+        params![
+            UuidWrapper::from(period.id),
+            DateTimeUtc::from(period.required_since),
+            period.resolved_at.map(DateTimeUtc::from),
+        ],
+    )
+    .context("Failed to add reboot period")?;
+
+    Ok(())
+}
+
+/// Record the applications registered to restart after a reboot
+pub fn add_restart_applications(pool: &DbPool, applications: &[RestartApplication]) -> Result<()> {
+    let mut conn = pool.get().context("Failed to get database connection")?;
+    let tx = conn.transaction()?;
+
+    for application in applications {
+        tx.execute(
+            "INSERT INTO restart_applications (id, initiated_at, name, process_id, session_id) VALUES (?, ?, ?, ?, ?)",
+            params![
+                UuidWrapper::from(application.id),
+                DateTimeUtc::from(application.initiated_at),
+                application.name,
+                application.process_id,
+                application.session_id,
+            ],
+        )
+        .context("Failed to add restart application")?;
+    }
+
+    tx.commit()?;
+    Ok(())
+}
+
+/// Get the applications registered to restart after the most recent reboot that had any
+pub fn get_last_restart_applications(pool: &DbPool) -> Result<Vec<RestartApplication>> {
+    let conn = pool.get().context("Failed to get database connection")?;
+    let mut stmt = conn.prepare(
+        "SELECT id, initiated_at, name, process_id, session_id FROM restart_applications
+         WHERE initiated_at = (SELECT MAX(initiated_at) FROM restart_applications)
+         ORDER BY name",
+    )?;
+
+    let applications = stmt
+        .query_map([], |row| {
+            Ok(RestartApplication {
+                id: row.get::<_, UuidWrapper>(0)?.into(),
+                initiated_at: row.get::<_, DateTimeUtc>(1)?.into(),
+                name: row.get(2)?,
+                process_id: row.get(3)?,
+                session_id: row.get(4)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(applications)
+}
+
+/// Get the debounce counters of the reboot sources
+pub fn get_source_debounce(pool: &DbPool) -> Result<Vec<SourceDebounce>> {
+    let conn = pool.get().context("Failed to get database connection")?;
+    let mut stmt = conn.prepare("SELECT name, seen, missing, counted, flaps, sources FROM source_debounce ORDER BY name")?;
+
+    let counters = stmt
+        .query_map([], |row| {
+            let sources: String = row.get(5)?;
+            Ok(SourceDebounce {
+                name: row.get(0)?,
+                seen: row.get(1)?,
+                missing: row.get(2)?,
+                counted: row.get(3)?,
+                flaps: row.get(4)?,
+                sources: serde_json::from_str(&sources).unwrap_or_default(),
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(counters)
+}
+
+/// Replace the debounce counters of the reboot sources
+pub fn save_source_debounce(pool: &DbPool, counters: &[SourceDebounce]) -> Result<()> {
+    let mut conn = pool.get().context("Failed to get database connection")?;
+    let tx = conn.transaction()?;
+
+    tx.execute("DELETE FROM source_debounce", [])?;
+    for counter in counters {
+        tx.execute(
+            "INSERT INTO source_debounce (name, seen, missing, counted, flaps, sources) VALUES (?, ?, ?, ?, ?, ?)",
+            params![
+                counter.name,
+                counter.seen,
+                counter.missing,
+                counter.counted,
+                counter.flaps,
+                serde_json::to_string(&counter.sources)?,
+            ],
+        )
+        .context("Failed to save source debounce counters")?;
+    }
+
+    tx.commit()?;
+    Ok(())
+}
+
+/// Get the time up to which the notifications and reboots were reported to a sink
+pub fn get_report_cursor(pool: &DbPool, sink: &str) -> Result<Option<DateTime<Utc>>> {
+    let conn = pool.get().context("Failed to get database connection")?;
+    let reported_until = conn
+        .query_row("SELECT reported_until FROM report_cursors WHERE sink = ?", [sink], |row| {
+            row.get::<_, DateTimeUtc>(0)
+        })
+        .optional()
+        .context("Failed to get report cursor")?;
+
+    Ok(reported_until.map(Into::into))
+}
+
+/// Record the time up to which the notifications and reboots were reported to a sink
+pub fn save_report_cursor(pool: &DbPool, sink: &str, reported_until: DateTime<Utc>) -> Result<()> {
+    let conn = pool.get().context("Failed to get database connection")?;
+    conn.execute(
+        "INSERT INTO report_cursors (sink, reported_until) VALUES (?, ?)
+         ON CONFLICT(sink) DO UPDATE SET reported_until = excluded.reported_until",
+        params![sink, DateTimeUtc::from(reported_until)],
+    )
+    .context("Failed to save report cursor")?;
+
+    Ok(())
+}
+
+/// Save a heartbeat
+pub fn save_heartbeat(pool: &DbPool, heartbeat: &Heartbeat) -> Result<()> {
+    let conn = pool.get().context("Failed to get database connection")?;
+    conn.execute(
+        "INSERT INTO heartbeats (id, timestamp, version, config_hash, reboot_required, lifecycle, sources, postpone_count)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        params![
+            UuidWrapper::from(heartbeat.id),
+            DateTimeUtc::from(heartbeat.timestamp),
+            heartbeat.version,
+            heartbeat.config_hash,
+            heartbeat.reboot_required,
+            heartbeat.lifecycle.as_str(),
+            heartbeat.sources,
+            heartbeat.postpone_count,
+        ],
+    )
+    .context("Failed to save heartbeat")?;
+
+    Ok(())
+}
+
+/// Get the most recent heartbeat
+pub fn get_last_heartbeat(pool: &DbPool) -> Result<Option<Heartbeat>> {
+    let conn = pool.get().context("Failed to get database connection")?;
+    conn.query_row(
+        "SELECT id, timestamp, version, config_hash, reboot_required, lifecycle, sources, postpone_count
+         FROM heartbeats ORDER BY timestamp DESC LIMIT 1",
+        [],
+        |row| {
+            let lifecycle: String = row.get(5)?;
+            Ok(Heartbeat {
+                id: row.get::<_, UuidWrapper>(0)?.into(),
+                timestamp: row.get::<_, DateTimeUtc>(1)?.into(),
+                version: row.get(2)?,
+                config_hash: row.get(3)?,
+                reboot_required: row.get(4)?,
+                lifecycle: lifecycle.parse().unwrap_or_default(),
+                sources: row.get(6)?,
+                postpone_count: row.get(7)?,
+            })
+        },
+    )
+    .optional()
+    .context("Failed to get the last heartbeat")
+}
+
+/// Record that the open reboot periods ended
+pub fn close_reboot_periods(pool: &DbPool, resolved_at: DateTime<Utc>) -> Result<()> {
+    let conn = pool.get().context("Failed to get database connection")?;
+
+    conn.execute(
+        "UPDATE reboot_periods SET resolved_at = ? WHERE resolved_at IS NULL",
+        params![DateTimeUtc::from(resolved_at)],
+    )
+    .context("Failed to close reboot periods")?;
+
+    Ok(())
+}
+
+/// Get the reboot periods, oldest first
+pub fn get_reboot_periods(pool: &DbPool) -> Result<Vec<RebootPeriod>> {
+    let conn = pool.get().context("Failed to get database connection")?;
+
+    let mut stmt = conn.prepare("SELECT id, required_since, resolved_at FROM reboot_periods ORDER BY required_since")?;
+    let periods = stmt
+        .query_map([], |row| {
+            Ok(RebootPeriod {
+                id: row.get::<_, UuidWrapper>(0)?.into(),
+                required_since: row.get::<_, DateTimeUtc>(1)?.into(),
+                resolved_at: row.get::<_, Option<DateTimeUtc>>(2)?.map(Into::into),
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(periods)
+}
+
 /// Add a notification
 pub fn add_notification(pool: &DbPool, notification: &Notification) -> Result<()> {
     info!("Adding notification to database: id={}, type={}, user={}",
           notification.id,
           notification.notification_type,
-          notification.user_name.as_deref().unwrap_or("<unknown>"));
+          crate::logging::user_opt(notification.user_name.as_deref()));
 
+    let _timer = crate::metrics::Timer::start("db.add_notification");
     let conn = pool.get().context("Failed to get database connection")?;
 
     let query = "INSERT INTO notifications (
-            id, timestamp, type, message, user_name, dismissed, action, created_at
-        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?)";
+            id, timestamp, type, message, user_name, dismissed, action, created_at, suppression_reason,
+            delivery_status, delivery_attempts, next_attempt_time, delivery_error, session_id, message_hash
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)";
 
-    info!("Executing query: {}", query);
+    crate::logging::sql(query);
     conn.execute(
         query,
         params![
@@ -519,6 +1217,13 @@ pub fn add_notification(pool: &DbPool, notification: &Notification) -> Result<()
             notification.dismissed,
             notification.action,
             DateTimeUtc::from(notification.created_at),
+            notification.suppression_reason,
+            notification.delivery_status.as_str(),
+            notification.delivery_attempts,
+            notification.next_attempt_time.map(DateTimeUtc::from),
+            notification.delivery_error,
+            notification.session_id,
+            message_hash(&notification.message),
         ],
     )?;
 
@@ -533,28 +1238,199 @@ pub fn get_notifications(pool: &DbPool, limit: Option<u32>) -> Result<Vec<Notifi
     let limit_clause = limit.map_or(String::from(""), |l| format!("LIMIT {}", l));
 
     let mut stmt = conn.prepare(&format!(
-        "SELECT id, timestamp, type, message, user_name, dismissed, action, created_at
-         FROM notifications ORDER BY timestamp DESC {}",
-        limit_clause
+        "SELECT {} FROM notifications ORDER BY timestamp DESC {}",
+        NOTIFICATION_COLUMNS, limit_clause
     ))?;
 
-    let notifications = stmt.query_map([], |row| {
-        Ok(Notification {
-            id: row.get::<_, UuidWrapper>(0)?.into(),
-            timestamp: row.get::<_, DateTimeUtc>(1)?.into(),
-            notification_type: row.get(2)?,
-            message: row.get(3)?,
-            user_name: row.get(4)?,
-            dismissed: row.get(5)?,
-            action: row.get(6)?,
-            created_at: row.get::<_, DateTimeUtc>(7)?.into(),
-        })
-    })?
-    .collect::<Result<Vec<_>, _>>()?;
+    let notifications = stmt.query_map([], notification_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(notifications)
+}
+
+/// Get the notifications recorded after a time, oldest first
+pub fn get_notifications_after(pool: &DbPool, after: DateTime<Utc>) -> Result<Vec<Notification>> {
+    let conn = pool.get().context("Failed to get database connection")?;
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM notifications WHERE timestamp > ? ORDER BY timestamp",
+        NOTIFICATION_COLUMNS
+    ))?;
+
+    let notifications = stmt
+        .query_map(params![DateTimeUtc::from(after)], notification_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(notifications)
+}
+
+/// Get the latest notification with the same type and message shown in a session since a time
+///
+/// Notifications that were skipped or failed don't count, since the user never saw them.
+pub fn get_recent_duplicate(
+    pool: &DbPool,
+    notification_type: &str,
+    message: &str,
+    session_id: &str,
+    since: DateTime<Utc>,
+) -> Result<Option<Notification>> {
+    let conn = pool.get().context("Failed to get database connection")?;
+
+    let notification = conn
+        .query_row(
+            &format!(
+                "SELECT {} FROM notifications
+                 WHERE type = ? AND message_hash = ? AND session_id = ? AND timestamp >= ?
+                 AND delivery_status IN ('pending', 'delivered')
+                 ORDER BY timestamp DESC LIMIT 1",
+                NOTIFICATION_COLUMNS
+            ),
+            params![notification_type, message_hash(message), session_id, DateTimeUtc::from(since)],
+            notification_from_row,
+        )
+        .optional()?;
+
+    Ok(notification)
+}
+
+/// Hash a notification message, so identical notifications can be found without comparing text
+fn message_hash(message: &str) -> String {
+    use sha2::{Digest, Sha256};
+    Sha256::digest(message.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Get the notifications whose next delivery attempt is due, oldest first
+pub fn get_due_deliveries(pool: &DbPool, now: DateTime<Utc>) -> Result<Vec<Notification>> {
+    let conn = pool.get().context("Failed to get database connection")?;
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM notifications
+         WHERE delivery_status = 'pending' AND next_attempt_time IS NOT NULL AND next_attempt_time <= ?
+         ORDER BY timestamp ASC",
+        NOTIFICATION_COLUMNS
+    ))?;
+
+    let notifications = stmt.query_map(params![DateTimeUtc::from(now)], notification_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
 
     Ok(notifications)
 }
 
+/// Save the delivery state of a notification
+pub fn update_notification_delivery(pool: &DbPool, notification: &Notification) -> Result<()> {
+    let conn = pool.get().context("Failed to get database connection")?;
+
+    conn.execute(
+        "UPDATE notifications
+         SET delivery_status = ?, delivery_attempts = ?, next_attempt_time = ?, delivery_error = ?
+         WHERE id = ?",
+        params![
+            notification.delivery_status.as_str(),
+            notification.delivery_attempts,
+            notification.next_attempt_time.map(DateTimeUtc::from),
+            notification.delivery_error,
+            UuidWrapper::from(notification.id),
+        ],
+    )
+    .context("Failed to update notification delivery state")?;
+
+    Ok(())
+}
+
+/// Columns read into a notification, in the order `notification_from_row` expects
+const NOTIFICATION_COLUMNS: &str = "id, timestamp, type, message, user_name, dismissed, action, created_at, \
+    suppression_reason, delivery_status, delivery_attempts, next_attempt_time, delivery_error, session_id";
+
+/// Read a notification from a row selected with `NOTIFICATION_COLUMNS`
+fn notification_from_row(row: &rusqlite::Row) -> rusqlite::Result<Notification> {
+    let status: String = row.get(9)?;
+    Ok(Notification {
+        id: row.get::<_, UuidWrapper>(0)?.into(),
+        timestamp: row.get::<_, DateTimeUtc>(1)?.into(),
+        notification_type: row.get(2)?,
+        message: row.get(3)?,
+        user_name: row.get(4)?,
+        dismissed: row.get(5)?,
+        action: row.get(6)?,
+        created_at: row.get::<_, DateTimeUtc>(7)?.into(),
+        suppression_reason: row.get(8)?,
+        delivery_status: DeliveryStatus::parse(&status).unwrap_or(DeliveryStatus::Delivered),
+        delivery_attempts: row.get(10)?,
+        next_attempt_time: row.get::<_, Option<DateTimeUtc>>(11)?.map(Into::into),
+        delivery_error: row.get(12)?,
+        session_id: row.get(13)?,
+    })
+}
+
+/// Get the time of the most recent notification of a type
+pub fn get_last_notification_time(pool: &DbPool, notification_type: &str) -> Result<Option<DateTime<Utc>>> {
+    let conn = pool.get().context("Failed to get database connection")?;
+
+    let time = conn
+        .query_row(
+            "SELECT timestamp FROM notifications WHERE type = ? ORDER BY timestamp DESC LIMIT 1",
+            params![notification_type],
+            |row| row.get::<_, DateTimeUtc>(0),
+        )
+        .optional()?;
+
+    Ok(time.map(Into::into))
+}
+
+/// Get the most recent notification of a type
+pub fn get_last_notification(pool: &DbPool, notification_type: &str) -> Result<Option<Notification>> {
+    let conn = pool.get().context("Failed to get database connection")?;
+
+    let notification = conn
+        .query_row(
+            &format!("SELECT {} FROM notifications WHERE type = ? ORDER BY timestamp DESC LIMIT 1", NOTIFICATION_COLUMNS),
+            params![notification_type],
+            notification_from_row,
+        )
+        .optional()?;
+
+    Ok(notification)
+}
+
+/// Check whether a user has acknowledged a notification
+pub fn is_notification_acknowledged(pool: &DbPool, notification_id: Uuid) -> Result<bool> {
+    let conn = pool.get().context("Failed to get database connection")?;
+
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM notification_interactions WHERE notification_id = ? AND action = ?",
+        params![UuidWrapper::from(notification_id), crate::notification::ACKNOWLEDGE_ACTION],
+        |row| row.get(0),
+    )?;
+
+    Ok(count > 0)
+}
+
+/// Get all notification interactions, oldest first
+pub fn get_notification_interactions(pool: &DbPool) -> Result<Vec<NotificationInteraction>> {
+    let conn = pool.get().context("Failed to get database connection")?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id, notification_id, timestamp, action, user_name, session_id, details, deferral_id
+         FROM notification_interactions ORDER BY timestamp",
+    )?;
+    let interactions = stmt
+        .query_map([], |row| {
+            Ok(NotificationInteraction {
+                id: row.get::<_, UuidWrapper>(0)?.into(),
+                notification_id: row.get::<_, UuidWrapper>(1)?.into(),
+                timestamp: row.get::<_, DateTimeUtc>(2)?.into(),
+                action: row.get(3)?,
+                user_name: row.get(4)?,
+                session_id: row.get(5)?,
+                details: row.get(6)?,
+                deferral_id: row.get(7)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(interactions)
+}
+
 /// Add a notification interaction
 pub fn add_notification_interaction(pool: &DbPool, interaction: &NotificationInteraction) -> Result<()> {
     info!("Adding notification interaction to database: id={}, notification_id={}, action={}",
@@ -563,7 +1439,7 @@ pub fn add_notification_interaction(pool: &DbPool, interaction: &NotificationInt
           interaction.action);
 
     info!("Interaction details: user={}, session={}, time={}",
-          interaction.user_name.as_deref().unwrap_or("<unknown>"),
+          crate::logging::user_opt(interaction.user_name.as_deref()),
           interaction.session_id.as_deref().unwrap_or("<unknown>"),
           interaction.timestamp);
 
@@ -572,12 +1448,21 @@ pub fn add_notification_interaction(pool: &DbPool, interaction: &NotificationInt
     }
 
     let conn = pool.get().context("Failed to get database connection")?;
+    insert_notification_interaction(&conn, interaction)?;
+
+    info!("Notification interaction added successfully: {} by {}",
+          interaction.action,
+          crate::logging::user_opt(interaction.user_name.as_deref()));
+    Ok(())
+}
 
+/// Insert a notification interaction on a connection, which may be inside a transaction
+fn insert_notification_interaction(conn: &Connection, interaction: &NotificationInteraction) -> Result<()> {
     let query = "INSERT INTO notification_interactions (
-            id, notification_id, timestamp, action, user_name, session_id, details
-        ) VALUES (?, ?, ?, ?, ?, ?, ?)";
+            id, notification_id, timestamp, action, user_name, session_id, details, deferral_id
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?)";
 
-    info!("Executing query: {}", query);
+    crate::logging::sql(query);
     conn.execute(
         query,
         params![
@@ -588,19 +1473,16 @@ pub fn add_notification_interaction(pool: &DbPool, interaction: &NotificationInt
             interaction.user_name,
             interaction.session_id,
             interaction.details,
+            interaction.deferral_id,
         ],
     )?;
-
-    info!("Notification interaction added successfully: {} by {}",
-          interaction.action,
-          interaction.user_name.as_deref().unwrap_or("<unknown>"));
     Ok(())
 }
 
 /// Save a user session
 pub fn save_user_session(pool: &DbPool, session: &UserSession) -> Result<()> {
     info!("Saving user session to database: id={}, user={}, session_id={}",
-          session.id, session.user_name, session.session_id);
+          session.id, crate::logging::user(&session.user_name), session.session_id);
     let conn = pool.get().context("Failed to get database connection")?;
 
     let query = "INSERT OR REPLACE INTO user_sessions (
@@ -608,8 +1490,7 @@ pub fn save_user_session(pool: &DbPool, session: &UserSession) -> Result<()> {
             client_name, client_ip, display_name, last_activity, created_at, updated_at
         ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)";
 
-    info!("Executing query: {} with params: [id={}, user={}, session_id={}]",
-          query, session.id, session.user_name, session.session_id);
+    crate::logging::sql(query);
     conn.execute(
         query,
         params![
@@ -645,7 +1526,7 @@ pub fn get_active_user_sessions(pool: &DbPool) -> Result<Vec<UserSession>> {
          client_name, client_ip, display_name, last_activity, created_at, updated_at
          FROM user_sessions WHERE is_active = 1 ORDER BY logon_time DESC";
 
-    info!("Executing query: {}", query);
+    crate::logging::sql(query);
     let mut stmt = conn.prepare(query)
         .context(format!("Failed to prepare query: {}", query))?;
 
@@ -673,3 +1554,102 @@ pub fn get_active_user_sessions(pool: &DbPool) -> Result<Vec<UserSession>> {
 
     Ok(sessions)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_reboot_state_conflicts() {
+        let pool = init_in_memory().unwrap();
+
+        save_reboot_state(&pool, &RebootState::new(true, false)).unwrap();
+        let base = get_reboot_state(&pool).unwrap().unwrap();
+        assert_eq!(base.version, 1);
+
+        // A postpone from the command line is saved while the check is running
+        let postponed = update_reboot_state(&pool, |state| {
+            state.postpone_count += 1;
+            Ok(())
+        })
+        .unwrap()
+        .unwrap();
+        assert_eq!(postponed.version, 2);
+
+        // The check's state is stale now, so saving it as is fails
+        let mut checked = base.clone();
+        checked.reboot_required = false;
+        checked.reboot_required_since = None;
+        let error = save_reboot_state(&pool, &checked).unwrap_err();
+        assert!(error.is::<StateConflict>());
+
+        // Merging keeps both changes
+        let merged = save_reboot_state_merged(&pool, &base, &checked).unwrap();
+        let saved = get_reboot_state(&pool).unwrap().unwrap();
+        assert_eq!(saved.version, 3);
+        assert_eq!(merged.version, saved.version);
+        assert!(!saved.reboot_required);
+        assert_eq!(saved.postpone_count, 1);
+    }
+
+    #[test]
+    fn test_update_reboot_state_with_interaction() {
+        let pool = init_in_memory().unwrap();
+        let notification = Notification::new("reboot_required", "Please restart", Some("user"));
+        add_notification(&pool, &notification).unwrap();
+        let interaction = NotificationInteraction::new(notification.id, "postpone:4h");
+
+        // Without a reboot state there is nothing to postpone, and the interaction isn't recorded
+        assert!(update_reboot_state_with_interaction(&pool, &interaction, |state| {
+            state.postpone_count += 1;
+            Ok(())
+        })
+        .unwrap()
+        .is_none());
+        assert!(get_notification_interactions(&pool).unwrap().is_empty());
+
+        save_reboot_state(&pool, &RebootState::new(true, false)).unwrap();
+        let next_reminder_time = Utc::now() + chrono::Duration::hours(4);
+        let postponed = update_reboot_state_with_interaction(&pool, &interaction, |state| {
+            state.next_reminder_time = Some(next_reminder_time);
+            state.postpone_count += 1;
+            Ok(())
+        })
+        .unwrap()
+        .unwrap();
+
+        let saved = get_reboot_state(&pool).unwrap().unwrap();
+        assert_eq!(saved.version, postponed.version);
+        assert_eq!(saved.postpone_count, 1);
+        assert_eq!(saved.next_reminder_time.map(|time| time.timestamp()), Some(next_reminder_time.timestamp()));
+        let interactions = get_notification_interactions(&pool).unwrap();
+        assert_eq!(interactions.len(), 1);
+        assert_eq!(interactions[0].action, "postpone:4h");
+
+        // A refused change saves neither the state nor the interaction
+        let refused = NotificationInteraction::new(notification.id, "postpone:8h");
+        assert!(update_reboot_state_with_interaction(&pool, &refused, |_| Err(anyhow::anyhow!("refused"))).is_err());
+        assert_eq!(get_reboot_state(&pool).unwrap().unwrap().version, postponed.version);
+        assert_eq!(get_notification_interactions(&pool).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_get_recent_duplicate() {
+        let pool = init_in_memory().unwrap();
+        let since = Utc::now() - chrono::Duration::minutes(5);
+
+        let mut shown = Notification::new("reboot_required", "Please restart", Some("user"));
+        shown.session_id = Some("1".to_string());
+        add_notification(&pool, &shown).unwrap();
+
+        let duplicate = get_recent_duplicate(&pool, "reboot_required", "Please restart", "1", since).unwrap();
+        assert_eq!(duplicate.map(|notification| notification.id), Some(shown.id));
+
+        // Other messages, other sessions and notifications the user never saw don't count
+        assert!(get_recent_duplicate(&pool, "reboot_required", "Please restart now", "1", since).unwrap().is_none());
+        assert!(get_recent_duplicate(&pool, "reboot_required", "Please restart", "2", since).unwrap().is_none());
+        shown.delivery_status = DeliveryStatus::Failed;
+        update_notification_delivery(&pool, &shown).unwrap();
+        assert!(get_recent_duplicate(&pool, "reboot_required", "Please restart", "1", since).unwrap().is_none());
+    }
+}
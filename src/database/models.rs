@@ -1,7 +1,110 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
 use uuid::Uuid;
 
+/// Stage of the reboot lifecycle a reboot state is in
+///
+/// A reboot becomes pending when it is detected, reminders make it active, a deadline schedules
+/// it and initiating it makes it imminent. It ends completed, or failed when an initiated reboot
+/// didn't happen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RebootLifecycle {
+    /// No reboot has been required yet
+    #[default]
+    NoRebootNeeded,
+
+    /// A reboot is required but nobody has been reminded yet
+    RebootPending,
+
+    /// Reminders are being shown
+    ReminderActive,
+
+    /// A forced reboot has been scheduled
+    Scheduled,
+
+    /// A reboot has been initiated
+    Imminent,
+
+    /// The reboot happened and no reboot is required anymore
+    Completed,
+
+    /// An initiated reboot didn't happen
+    Failed,
+}
+
+impl RebootLifecycle {
+    /// Get the name the stage is stored under
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::NoRebootNeeded => "no_reboot_needed",
+            Self::RebootPending => "reboot_pending",
+            Self::ReminderActive => "reminder_active",
+            Self::Scheduled => "scheduled",
+            Self::Imminent => "imminent",
+            Self::Completed => "completed",
+            Self::Failed => "failed",
+        }
+    }
+
+    /// Check whether a reboot is outstanding in this stage
+    pub fn is_required(self) -> bool {
+        !matches!(self, Self::NoRebootNeeded | Self::Completed)
+    }
+
+    /// Check whether reminders may be shown in this stage
+    ///
+    /// Not once a reboot has been initiated, nor after it has completed.
+    pub fn allows_reminders(self) -> bool {
+        matches!(self, Self::RebootPending | Self::ReminderActive | Self::Scheduled | Self::Failed)
+    }
+
+    /// Check whether the lifecycle may move from this stage to another
+    pub fn can_transition_to(self, next: RebootLifecycle) -> bool {
+        use RebootLifecycle::*;
+        if self == next {
+            return true;
+        }
+        match (self, next) {
+            (NoRebootNeeded | Completed, RebootPending) => true,
+            (RebootPending, ReminderActive | Scheduled | Imminent) => true,
+            (ReminderActive, Scheduled | Imminent) => true,
+            (Scheduled, Imminent) => true,
+            (Imminent, Failed) => true,
+            (Failed, ReminderActive | Scheduled | Imminent) => true,
+            (from, Completed) => from.is_required(),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for RebootLifecycle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for RebootLifecycle {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        [
+            Self::NoRebootNeeded,
+            Self::RebootPending,
+            Self::ReminderActive,
+            Self::Scheduled,
+            Self::Imminent,
+            Self::Completed,
+            Self::Failed,
+        ]
+        .into_iter()
+        .find(|stage| stage.as_str() == value)
+        .ok_or_else(|| format!("Unknown reboot lifecycle stage '{}'", value))
+    }
+}
+
 /// Reboot state
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RebootState {
@@ -35,6 +138,10 @@ pub struct RebootState {
     /// Reason for reboot
     pub reboot_reason: Option<String>,
 
+    /// Time a reboot was initiated that hasn't been verified yet
+    #[serde(default)]
+    pub reboot_initiated_at: Option<DateTime<Utc>>,
+
     /// Reboot sources
     pub sources: Vec<RebootSource>,
 
@@ -43,6 +150,14 @@ pub struct RebootState {
 
     /// Last update time
     pub updated_at: DateTime<Utc>,
+
+    /// Number of times the state has been saved, to detect concurrent writers
+    #[serde(default)]
+    pub version: u32,
+
+    /// Stage of the reboot lifecycle
+    #[serde(default)]
+    pub lifecycle: RebootLifecycle,
 }
 
 impl RebootState {
@@ -60,15 +175,94 @@ impl RebootState {
             next_reminder_time: None,
             scheduled_reboot_time: None,
             reboot_reason: None,
+            reboot_initiated_at: None,
             sources: Vec::new(),
             created_at: now,
             updated_at: now,
+            version: 0,
+            lifecycle: if reboot_required { RebootLifecycle::RebootPending } else { RebootLifecycle::NoRebootNeeded },
+        }
+    }
+
+    /// Work out the lifecycle stage of a state saved before stages were recorded
+    pub fn derive_lifecycle(&self) -> RebootLifecycle {
+        if self.reboot_initiated_at.is_some() {
+            RebootLifecycle::Imminent
+        } else if !self.reboot_required {
+            match self.last_reboot_time {
+                Some(_) => RebootLifecycle::Completed,
+                None => RebootLifecycle::NoRebootNeeded,
+            }
+        } else if self.scheduled_reboot_time.is_some() {
+            RebootLifecycle::Scheduled
+        } else if self.next_reminder_time.is_some() {
+            RebootLifecycle::ReminderActive
+        } else {
+            RebootLifecycle::RebootPending
+        }
+    }
+
+    /// Check that this state may move to another stage of the reboot lifecycle, without moving it
+    pub fn check_transition(&self, next: RebootLifecycle) -> anyhow::Result<()> {
+        if !self.lifecycle.can_transition_to(next) {
+            return Err(anyhow::anyhow!("Invalid reboot lifecycle transition from {} to {}", self.lifecycle, next));
         }
+        Ok(())
+    }
+
+    /// Move to another stage of the reboot lifecycle, failing when the transition isn't valid
+    pub fn transition(&mut self, next: RebootLifecycle) -> anyhow::Result<()> {
+        self.check_transition(next)?;
+        self.lifecycle = next;
+        Ok(())
+    }
+
+    /// Apply the changes made to this state since `base` to `latest`, saved by another writer
+    ///
+    /// Fields this state didn't change keep the value of `latest`, and postponements made by
+    /// both writers add up. When both moved the next reminder, the later one is kept, so a
+    /// postponement saved by the other writer isn't undone.
+    pub fn merge(&self, base: &RebootState, latest: &RebootState) -> RebootState {
+        let mut merged = latest.clone();
+        macro_rules! take_changed {
+            ($($field:ident),*) => {
+                $(
+                    if self.$field != base.$field {
+                        merged.$field = self.$field.clone();
+                    }
+                )*
+            };
+        }
+        take_changed!(
+            reboot_required,
+            reboot_recommended,
+            last_check_time,
+            reboot_required_since,
+            last_reboot_time,
+            scheduled_reboot_time,
+            reboot_reason,
+            reboot_initiated_at,
+            sources,
+            lifecycle
+        );
+
+        if self.next_reminder_time != base.next_reminder_time {
+            merged.next_reminder_time = match (self.next_reminder_time, latest.next_reminder_time) {
+                (Some(ours), Some(theirs)) if latest.next_reminder_time != base.next_reminder_time => Some(ours.max(theirs)),
+                (ours, _) => ours,
+            };
+        }
+
+        // Both changes to the count apply, whether postponements or a reset
+        let postpone_count = i64::from(self.postpone_count) + i64::from(latest.postpone_count) - i64::from(base.postpone_count);
+        merged.postpone_count = postpone_count.clamp(0, i64::from(u32::MAX)) as u32;
+        merged.updated_at = self.updated_at.max(latest.updated_at);
+        merged
     }
 }
 
 /// Reboot source
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct RebootSource {
     /// Unique identifier
     pub id: Uuid,
@@ -82,14 +276,21 @@ pub struct RebootSource {
     /// Severity (required, recommended, optional)
     pub severity: String,
 
-    /// Time when the source was detected
+    /// Time when the source was first detected, kept while it's found in every check
     pub detected_at: DateTime<Utc>,
 
+    /// Time when the source was last found by a check
+    #[serde(default = "Utc::now")]
+    pub last_seen: DateTime<Utc>,
+
     /// Time when the source expires
     pub expires_at: Option<DateTime<Utc>>,
 
     /// Additional details
     pub details: Option<String>,
+
+    /// User the source applies to, for sources found in a user's registry hive
+    pub user_name: Option<String>,
 }
 
 impl RebootSource {
@@ -102,10 +303,17 @@ impl RebootSource {
             description: description.map(|s| s.to_string()),
             severity: severity.to_string(),
             detected_at: now,
+            last_seen: now,
             expires_at: None,
             details: None,
+            user_name: None,
         }
     }
+
+    /// Whether this is the same source as `other`: the same name, for the same user
+    pub fn same_source(&self, other: &RebootSource) -> bool {
+        self.name == other.name && self.user_name == other.user_name
+    }
 }
 
 /// Reboot history
@@ -134,6 +342,10 @@ pub struct RebootHistory {
 
     /// Duration of reboot in seconds
     pub duration: Option<i64>,
+
+    /// Applications that were open when the reboot was forced
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub closed_applications: Vec<String>,
 }
 
 impl RebootHistory {
@@ -148,6 +360,134 @@ impl RebootHistory {
             computer_name: None,
             success,
             duration: None,
+            closed_applications: Vec::new(),
+        }
+    }
+}
+
+/// Debounce counters of a reboot source, kept across checks
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SourceDebounce {
+    /// Source name
+    pub name: String,
+
+    /// Consecutive checks the source was found in
+    pub seen: u32,
+
+    /// Consecutive checks the source was missing from
+    pub missing: u32,
+
+    /// Whether the source counts towards the reboot state
+    pub counted: bool,
+
+    /// Times the source appeared or disappeared for fewer checks than needed to change whether it counts
+    pub flaps: u32,
+
+    /// Sources last found under the name, reported while the source counts
+    pub sources: Vec<RebootSource>,
+}
+
+/// Application registered to be restarted by Windows after a reboot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RestartApplication {
+    /// Unique identifier
+    pub id: Uuid,
+
+    /// Time the reboot was initiated
+    pub initiated_at: DateTime<Utc>,
+
+    /// Name of the application
+    pub name: String,
+
+    /// ID of the process
+    pub process_id: u32,
+
+    /// ID of the session the application ran in
+    pub session_id: u32,
+}
+
+impl RestartApplication {
+    /// Create a new restart application entry
+    pub fn new(initiated_at: DateTime<Utc>, name: &str, process_id: u32, session_id: u32) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            initiated_at,
+            name: name.to_string(),
+            process_id,
+            session_id,
+        }
+    }
+}
+
+/// Period during which a reboot was required
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RebootPeriod {
+    /// Unique identifier
+    pub id: Uuid,
+
+    /// Time the reboot became required
+    pub required_since: DateTime<Utc>,
+
+    /// Time the reboot was no longer required, if it has happened
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+impl RebootPeriod {
+    /// Create a period that is still open
+    pub fn new(required_since: DateTime<Utc>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            required_since,
+            resolved_at: None,
+        }
+    }
+}
+
+/// Record that the service was running, written periodically
+///
+/// Telling a quiet computer apart from one whose agent stopped only needs the time of the last
+/// heartbeat; the rest shows what the agent was running with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Heartbeat {
+    /// Unique identifier
+    pub id: Uuid,
+
+    /// Time of the heartbeat
+    pub timestamp: DateTime<Utc>,
+
+    /// Version of the service
+    pub version: String,
+
+    /// Hash of the configuration in effect
+    pub config_hash: String,
+
+    /// Whether a reboot was required
+    pub reboot_required: bool,
+
+    /// Lifecycle of the reboot state
+    pub lifecycle: RebootLifecycle,
+
+    /// Comma-separated names of the reboot sources
+    pub sources: String,
+
+    /// Number of times the reboot was postponed
+    pub postpone_count: u32,
+}
+
+impl Heartbeat {
+    /// Create a heartbeat summarizing a reboot state
+    pub fn new(timestamp: DateTime<Utc>, config_hash: &str, state: &RebootState) -> Self {
+        let sources: Vec<&str> = state.sources.iter().map(|source| source.name.as_str()).collect();
+        Self {
+            id: Uuid::new_v4(),
+            timestamp,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            config_hash: config_hash.to_string(),
+            reboot_required: state.reboot_required,
+            lifecycle: state.lifecycle,
+            sources: sources.join(","),
+            postpone_count: state.postpone_count,
         }
     }
 }
@@ -178,6 +518,65 @@ pub struct Notification {
 
     /// Creation time
     pub created_at: DateTime<Utc>,
+
+    /// Why the notification was recorded without being shown
+    pub suppression_reason: Option<String>,
+
+    /// Delivery state
+    pub delivery_status: DeliveryStatus,
+
+    /// Number of delivery attempts made
+    pub delivery_attempts: u32,
+
+    /// Time of the next delivery attempt while delivery is pending
+    pub next_attempt_time: Option<DateTime<Utc>>,
+
+    /// Error of the last failed delivery attempt
+    pub delivery_error: Option<String>,
+
+    /// Session the notification was shown in
+    #[serde(default)]
+    pub session_id: Option<String>,
+}
+
+/// Delivery state of a notification
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    /// Not delivered yet, or waiting for a retry after a failed attempt
+    Pending,
+
+    /// Shown to the user
+    Delivered,
+
+    /// Given up on after the maximum number of attempts
+    Failed,
+
+    /// Recorded without being shown (dry run or suppressed account)
+    Skipped,
+}
+
+impl DeliveryStatus {
+    /// Get the name stored in the database
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Delivered => "delivered",
+            Self::Failed => "failed",
+            Self::Skipped => "skipped",
+        }
+    }
+
+    /// Parse a name stored in the database
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "pending" => Some(Self::Pending),
+            "delivered" => Some(Self::Delivered),
+            "failed" => Some(Self::Failed),
+            "skipped" => Some(Self::Skipped),
+            _ => None,
+        }
+    }
 }
 
 impl Notification {
@@ -193,6 +592,12 @@ impl Notification {
             dismissed: false,
             action: None,
             created_at: now,
+            suppression_reason: None,
+            delivery_status: DeliveryStatus::Pending,
+            delivery_attempts: 0,
+            next_attempt_time: None,
+            delivery_error: None,
+            session_id: None,
         }
     }
 }
@@ -220,6 +625,9 @@ pub struct NotificationInteraction {
 
     /// Additional details
     pub details: Option<String>,
+
+    /// Identifier of the deferral option the user chose
+    pub deferral_id: Option<String>,
 }
 
 impl NotificationInteraction {
@@ -233,6 +641,7 @@ impl NotificationInteraction {
             user_name: None,
             session_id: None,
             details: None,
+            deferral_id: None,
         }
     }
 
@@ -247,6 +656,7 @@ impl NotificationInteraction {
             user_name: user_name.map(|s| s.to_string()),
             session_id: session_id.map(|s| s.to_string()),
             details: details.map(|s| s.to_string()),
+            deferral_id: None,
         }
     }
 
@@ -333,3 +743,61 @@ impl UserSession {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reboot_lifecycle() {
+        let mut state = RebootState::new(true, false);
+        assert_eq!(state.lifecycle, RebootLifecycle::RebootPending);
+
+        state.transition(RebootLifecycle::ReminderActive).unwrap();
+        state.transition(RebootLifecycle::Imminent).unwrap();
+        assert!(!state.lifecycle.allows_reminders());
+        assert!(state.transition(RebootLifecycle::ReminderActive).is_err());
+        state.transition(RebootLifecycle::Completed).unwrap();
+        assert!(state.transition(RebootLifecycle::Scheduled).is_err());
+        state.transition(RebootLifecycle::RebootPending).unwrap();
+
+        assert_eq!("reminder_active".parse(), Ok(RebootLifecycle::ReminderActive));
+        assert!("rebooting".parse::<RebootLifecycle>().is_err());
+
+        // States saved before the lifecycle was recorded
+        let mut state = RebootState::new(true, false);
+        state.scheduled_reboot_time = Some(Utc::now());
+        assert_eq!(state.derive_lifecycle(), RebootLifecycle::Scheduled);
+        state.reboot_initiated_at = Some(Utc::now());
+        assert_eq!(state.derive_lifecycle(), RebootLifecycle::Imminent);
+    }
+
+    #[test]
+    fn test_merge_keeps_concurrent_postponement() {
+        let now = Utc::now();
+        let mut base = RebootState::new(true, false);
+        base.next_reminder_time = Some(now);
+        base.postpone_count = 2;
+
+        // The user postponed by four hours while the check was rescheduling the reminder
+        let mut latest = base.clone();
+        latest.next_reminder_time = Some(now + chrono::Duration::hours(4));
+        latest.postpone_count = 3;
+        let mut checked = base.clone();
+        checked.next_reminder_time = Some(now + chrono::Duration::hours(1));
+        checked.reboot_recommended = true;
+
+        let merged = checked.merge(&base, &latest);
+        assert_eq!(merged.next_reminder_time, latest.next_reminder_time);
+        assert_eq!(merged.postpone_count, 3);
+        assert!(merged.reboot_recommended);
+
+        // A reset of the count keeps the postponement made since
+        let mut reset = base.clone();
+        reset.postpone_count = 0;
+        reset.next_reminder_time = None;
+        let merged = reset.merge(&base, &latest);
+        assert_eq!(merged.postpone_count, 1);
+        assert_eq!(merged.next_reminder_time, None);
+    }
+}
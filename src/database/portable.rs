@@ -0,0 +1,207 @@
+use super::{
+    add_notification, add_notification_interaction, add_reboot_history, add_reboot_period, get_notification_interactions,
+    get_notifications, get_reboot_history, get_reboot_periods, get_reboot_state, save_reboot_state, DbPool,
+    Notification, NotificationInteraction, RebootHistory, RebootPeriod, RebootState,
+};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Version of the export format, raised when it changes incompatibly
+const FORMAT_VERSION: u32 = 1;
+
+/// Portable copy of the database, for carrying state over when a machine is re-imaged or the
+/// database moves
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatabaseExport {
+    /// Version of the export format
+    pub format_version: u32,
+
+    /// Time of the export
+    pub exported_at: DateTime<Utc>,
+
+    /// Name of the computer the export was made on
+    pub computer_name: String,
+
+    /// Current reboot state, with its sources
+    pub reboot_state: Option<RebootState>,
+
+    /// Reboot history, newest first
+    #[serde(default)]
+    pub reboot_history: Vec<RebootHistory>,
+
+    /// Periods during which a reboot was required, oldest first
+    #[serde(default)]
+    pub reboot_periods: Vec<RebootPeriod>,
+
+    /// Notifications, newest first
+    #[serde(default)]
+    pub notifications: Vec<Notification>,
+
+    /// Interactions with the notifications, oldest first
+    #[serde(default)]
+    pub notification_interactions: Vec<NotificationInteraction>,
+}
+
+/// Number of rows an import added, by table
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    /// Whether the reboot state was replaced
+    pub state_imported: bool,
+
+    /// Reboot history entries added
+    pub history: usize,
+
+    /// Reboot periods added
+    pub periods: usize,
+
+    /// Notifications added
+    pub notifications: usize,
+
+    /// Notification interactions added
+    pub interactions: usize,
+}
+
+/// Read the reboot state, history and notifications into a portable export
+pub fn export(pool: &DbPool) -> Result<DatabaseExport> {
+    Ok(DatabaseExport {
+        format_version: FORMAT_VERSION,
+        exported_at: Utc::now(),
+        computer_name: std::env::var("COMPUTERNAME").unwrap_or_default(),
+        reboot_state: get_reboot_state(pool)?,
+        reboot_history: get_reboot_history(pool, None)?,
+        reboot_periods: get_reboot_periods(pool)?,
+        notifications: get_notifications(pool, None)?,
+        notification_interactions: get_notification_interactions(pool)?,
+    })
+}
+
+/// Write a portable export of the database to a JSON file
+pub fn export_to_file(pool: &DbPool, path: &Path) -> Result<DatabaseExport> {
+    let export = export(pool)?;
+    let json = serde_json::to_string_pretty(&export).context("Failed to serialize database export")?;
+    std::fs::write(path, json).with_context(|| format!("Failed to write database export to {:?}", path))?;
+
+    info!(
+        "Exported {} history entries and {} notifications to {:?}",
+        export.reboot_history.len(),
+        export.notifications.len(),
+        path
+    );
+    Ok(export)
+}
+
+/// Read a portable export from a JSON file and import it
+pub fn import_from_file(pool: &DbPool, path: &Path) -> Result<ImportReport> {
+    let json = std::fs::read_to_string(path).with_context(|| format!("Failed to read database export {:?}", path))?;
+    let export: DatabaseExport = serde_json::from_str(&json).with_context(|| format!("Invalid database export {:?}", path))?;
+    import(pool, &export)
+}
+
+/// Import a portable export into the database
+///
+/// The exported reboot state replaces the current one. History, periods, notifications and
+/// interactions are merged, skipping rows that are already in the database, so importing the
+/// same file twice changes nothing.
+pub fn import(pool: &DbPool, export: &DatabaseExport) -> Result<ImportReport> {
+    if export.format_version > FORMAT_VERSION {
+        anyhow::bail!(
+            "The export has format version {}, but this version only reads up to {}",
+            export.format_version,
+            FORMAT_VERSION
+        );
+    }
+    info!(
+        "Importing database export from {} made at {}",
+        export.computer_name, export.exported_at
+    );
+
+    let mut report = ImportReport::default();
+
+    if let Some(state) = &export.reboot_state {
+        // Take over the identity and version of the current state, so the import replaces it
+        // instead of being saved next to it
+        let mut state = state.clone();
+        match get_reboot_state(pool)? {
+            Some(current) => {
+                state.id = current.id;
+                state.version = current.version;
+            }
+            None => state.version = 0,
+        }
+        save_reboot_state(pool, &state).context("Failed to import reboot state")?;
+        report.state_imported = true;
+    }
+
+    let existing: HashSet<_> = get_reboot_history(pool, None)?.into_iter().map(|history| history.id).collect();
+    for history in export.reboot_history.iter().rev().filter(|history| !existing.contains(&history.id)) {
+        add_reboot_history(pool, history)?;
+        report.history += 1;
+    }
+
+    let existing: HashSet<_> = get_reboot_periods(pool)?.into_iter().map(|period| period.id).collect();
+    for period in export.reboot_periods.iter().filter(|period| !existing.contains(&period.id)) {
+        add_reboot_period(pool, period)?;
+        report.periods += 1;
+    }
+
+    // Notifications go first, since interactions refer to them
+    let existing: HashSet<_> = get_notifications(pool, None)?.into_iter().map(|notification| notification.id).collect();
+    for notification in export.notifications.iter().rev().filter(|notification| !existing.contains(&notification.id)) {
+        add_notification(pool, notification)?;
+        report.notifications += 1;
+    }
+
+    let existing: HashSet<_> = get_notification_interactions(pool)?.into_iter().map(|interaction| interaction.id).collect();
+    for interaction in export.notification_interactions.iter().filter(|interaction| !existing.contains(&interaction.id)) {
+        add_notification_interaction(pool, interaction)?;
+        report.interactions += 1;
+    }
+
+    info!("Database export imported: {:?}", report);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::init_in_memory;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_export_import() {
+        let dir = tempdir().unwrap();
+        let source = init_in_memory().unwrap();
+
+        let mut state = RebootState::new(true, false);
+        state.postpone_count = 2;
+        save_reboot_state(&source, &state).unwrap();
+        let notification = Notification::new("reboot_required", "Please restart", None);
+        add_notification(&source, &notification).unwrap();
+        add_notification_interaction(&source, &NotificationInteraction::new(notification.id, "defer:4h")).unwrap();
+
+        let path = dir.path().join("export.json");
+        export_to_file(&source, &path).unwrap();
+
+        // The target already has a state of its own, which the import replaces
+        let target = init_in_memory().unwrap();
+        save_reboot_state(&target, &RebootState::new(false, false)).unwrap();
+
+        let report = import_from_file(&target, &path).unwrap();
+        assert!(report.state_imported);
+        assert_eq!((report.notifications, report.interactions), (1, 1));
+
+        let imported = get_reboot_state(&target).unwrap().unwrap();
+        assert!(imported.reboot_required);
+        assert_eq!(imported.postpone_count, 2);
+        assert_eq!(get_notifications(&target, None).unwrap()[0].id, notification.id);
+
+        // Importing again only replaces the state
+        let report = import_from_file(&target, &path).unwrap();
+        assert_eq!((report.notifications, report.interactions), (0, 0));
+    }
+}
@@ -1,9 +1,15 @@
+pub mod session;
+
+pub use session::{SessionCommand, SessionExit, SessionToken};
+
 use crate::database::UserSession;
+use crate::utils::to_wide;
 use anyhow::Result;
 use log::{debug, warn};
 use std::ffi::OsString;
 use std::os::windows::ffi::OsStringExt;
 use std::ptr;
+use std::time::Duration;
 use windows::Win32::System::RemoteDesktop::WTSEnumerateSessionsW;
 use windows::Win32::System::RemoteDesktop::WTSFreeMemory;
 use windows::Win32::System::RemoteDesktop::WTSQuerySessionInformationW;
@@ -12,6 +18,12 @@ use windows::Win32::System::RemoteDesktop::WTS_CURRENT_SERVER_HANDLE;
 use windows::Win32::System::RemoteDesktop::WTS_SESSION_INFOW;
 use windows::Win32::System::RemoteDesktop::WTSActive;
 use windows::Win32::System::RemoteDesktop::WTSConnected;
+use windows::Win32::System::RemoteDesktop::WTSDisconnected;
+use windows::Win32::System::RemoteDesktop::{ProcessIdToSessionId, WTSSendMessageW, WTSSessionInfo, WTSINFOW};
+use windows::Win32::System::SystemInformation::GetTickCount;
+use windows::Win32::System::Threading::GetCurrentProcessId;
+use windows::Win32::UI::Input::KeyboardAndMouse::{GetLastInputInfo, LASTINPUTINFO};
+use windows::Win32::UI::WindowsAndMessaging::{MESSAGEBOX_RESULT, MESSAGEBOX_STYLE};
 use windows::Win32::System::Services::{OpenServiceW, SERVICE_QUERY_STATUS, CloseServiceHandle, OpenSCManagerW, SC_MANAGER_CONNECT};
 use windows::core::{PCWSTR, PWSTR};
 
@@ -28,6 +40,19 @@ impl Impersonator {
 
     /// Get all active user sessions
     pub fn get_active_sessions(&self) -> Result<Vec<UserSession>> {
+        self.enumerate_sessions(false)
+    }
+
+    /// Get the sessions of all signed-in users, including disconnected ones
+    ///
+    /// A user who disconnected from a remote session is still signed in, with their programs and
+    /// unsaved work, but can't see notifications until they reconnect.
+    pub fn get_signed_in_sessions(&self) -> Result<Vec<UserSession>> {
+        self.enumerate_sessions(true)
+    }
+
+    /// Enumerate user sessions, with or without disconnected ones
+    fn enumerate_sessions(&self, include_disconnected: bool) -> Result<Vec<UserSession>> {
         let mut sessions = Vec::new();
 
         unsafe {
@@ -92,8 +117,9 @@ impl Impersonator {
                 // Free the buffer
                 WTSFreeMemory(buffer_ptr as *mut _);
 
-                // Skip disconnected sessions
-                if state != WTSActive.0 as u32 && state != WTSConnected.0 as u32 {
+                // Skip disconnected sessions unless asked for them, and listeners and the like always
+                let disconnected = state == WTSDisconnected.0 as u32;
+                if state != WTSActive.0 as u32 && state != WTSConnected.0 as u32 && !(include_disconnected && disconnected) {
                     continue;
                 }
 
@@ -161,12 +187,13 @@ impl Impersonator {
                 };
 
                 // Create a new session
-                let session = UserSession::new(
+                let mut session = UserSession::new(
                     &username,
                     &session_id.to_string(),
                     state == WTSConnected.0 as u32,
                     state == WTSActive.0 as u32
                 );
+                session.is_active = !disconnected;
 
                 // Update client name if available
                 if let Some(name) = client_name {
@@ -185,6 +212,116 @@ impl Impersonator {
         Ok(sessions)
     }
 
+    /// Get how long the user of a session has been idle, if Windows reports it
+    ///
+    /// Remote Desktop Services reports the last input time of remote sessions. For the session
+    /// this process runs in the idle time is read with `GetLastInputInfo` instead; other sessions
+    /// whose last input time isn't reported are unknown.
+    pub fn session_idle_time(&self, session_id: &str) -> Option<Duration> {
+        let session_id: u32 = session_id.parse().ok()?;
+
+        unsafe {
+            let mut buffer_ptr: *mut u16 = ptr::null_mut();
+            let mut bytes_returned: u32 = 0;
+            let result = WTSQuerySessionInformationW(
+                Some(WTS_CURRENT_SERVER_HANDLE),
+                session_id,
+                WTSSessionInfo,
+                &mut buffer_ptr as *mut *mut u16 as *mut PWSTR,
+                &mut bytes_returned,
+            );
+
+            if result.is_ok() && !buffer_ptr.is_null() && bytes_returned as usize >= std::mem::size_of::<WTSINFOW>() {
+                let info = &*(buffer_ptr as *const WTSINFOW);
+                let (last_input, current) = (info.LastInputTime, info.CurrentTime);
+                WTSFreeMemory(buffer_ptr as *mut _);
+
+                // The times are in 100-nanosecond intervals
+                if last_input > 0 && current >= last_input {
+                    return Some(Duration::from_millis(((current - last_input) / 10_000) as u64));
+                }
+            } else if !buffer_ptr.is_null() {
+                WTSFreeMemory(buffer_ptr as *mut _);
+            }
+
+            let mut current_session = 0;
+            if ProcessIdToSessionId(GetCurrentProcessId(), &mut current_session).is_ok() && current_session == session_id {
+                let mut input = LASTINPUTINFO {
+                    cbSize: std::mem::size_of::<LASTINPUTINFO>() as u32,
+                    dwTime: 0,
+                };
+                if GetLastInputInfo(&mut input).as_bool() {
+                    return Some(Duration::from_millis(GetTickCount().wrapping_sub(input.dwTime) as u64));
+                }
+            }
+        }
+
+        debug!("Idle time of session {} is unknown", session_id);
+        None
+    }
+
+    /// Get the ID of the session this process runs in
+    pub fn current_session_id(&self) -> Option<u32> {
+        let mut session_id = 0;
+        unsafe { ProcessIdToSessionId(GetCurrentProcessId(), &mut session_id) }.ok()?;
+        Some(session_id)
+    }
+
+    /// Show a message box on the desktop of a session
+    ///
+    /// Unlike `MessageBoxW`, this works from the service session, whose desktop users never see.
+    /// With `wait`, the call returns the button the user clicked, or `IDTIMEOUT` when the box
+    /// closed by itself after `timeout`; a zero timeout waits forever. Without it, `IDASYNC` is returned.
+    pub fn send_message(
+        &self,
+        session_id: &str,
+        title: &str,
+        message: &str,
+        style: MESSAGEBOX_STYLE,
+        timeout: Duration,
+        wait: bool,
+    ) -> Result<MESSAGEBOX_RESULT> {
+        let session: u32 = session_id
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid session ID: {}", session_id))?;
+        let title: Vec<u16> = title.encode_utf16().collect();
+        let message: Vec<u16> = message.encode_utf16().collect();
+        let mut response = MESSAGEBOX_RESULT(0);
+
+        // The lengths are in bytes, without a terminating null
+        unsafe {
+            WTSSendMessageW(
+                Some(WTS_CURRENT_SERVER_HANDLE),
+                session,
+                PCWSTR::from_raw(title.as_ptr()),
+                (title.len() * 2) as u32,
+                PCWSTR::from_raw(message.as_ptr()),
+                (message.len() * 2) as u32,
+                style,
+                timeout.as_secs() as u32,
+                &mut response,
+                wait,
+            )
+        }
+        .map_err(|e| anyhow::anyhow!("Failed to send a message to session {}: {}", session_id, e))?;
+
+        debug!("Message box in session {} returned {:?}", session_id, response);
+        Ok(response)
+    }
+
+    /// Get the shortest idle time of the users of the sessions
+    ///
+    /// None when there are no sessions or the idle time of any of them is unknown, so callers
+    /// treat users as present unless every one of them is known to be away.
+    pub fn shortest_idle_time(&self, sessions: &[UserSession]) -> Option<Duration> {
+        sessions
+            .iter()
+            .map(|session| self.session_idle_time(&session.session_id))
+            .collect::<Option<Vec<_>>>()?
+            .into_iter()
+            .min()
+    }
+
     /// Check if SCCM client is installed
     pub fn is_sccm_client_installed(&self) -> Result<bool> {
         unsafe {
@@ -201,7 +338,7 @@ impl Impersonator {
             };
 
             // Try to open the CCMExec service
-            let service_name = "CCMExec\0".encode_utf16().collect::<Vec<u16>>();
+            let service_name = to_wide("CCMExec");
             let service = match OpenServiceW(
                 sc_manager,
                 PCWSTR::from_raw(service_name.as_ptr()),
@@ -225,14 +362,4 @@ impl Impersonator {
             Ok(true)
         }
     }
-
-    /// Show a toast notification in the user's session
-    pub fn show_toast_notification(&self, session: &UserSession, _notification: &str) -> Result<()> {
-        debug!("Showing toast notification to user {}", session.user_name);
-
-        // This is a placeholder for the actual implementation
-        // You'll need to implement this based on your notification system
-
-        Ok(())
-    }
 }
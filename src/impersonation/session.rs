@@ -0,0 +1,318 @@
+use super::Impersonator;
+use crate::utils::to_wide;
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use std::ffi::c_void;
+use std::path::PathBuf;
+use std::time::Duration;
+use windows::core::{PCWSTR, PWSTR};
+use windows::Win32::Foundation::{CloseHandle, HANDLE, WAIT_OBJECT_0, WAIT_TIMEOUT};
+use windows::Win32::Security::{
+    DuplicateTokenEx, ImpersonateLoggedOnUser, RevertToSelf, SecurityImpersonation, TokenPrimary, TOKEN_ALL_ACCESS,
+};
+use windows::Win32::System::Environment::{CreateEnvironmentBlock, DestroyEnvironmentBlock};
+use windows::Win32::System::RemoteDesktop::WTSQueryUserToken;
+use windows::Win32::System::Threading::{
+    CreateProcessAsUserW, GetExitCodeProcess, TerminateProcess, WaitForSingleObject, CREATE_NO_WINDOW,
+    CREATE_UNICODE_ENVIRONMENT, PROCESS_INFORMATION, STARTF_USESHOWWINDOW, STARTUPINFOW,
+};
+use windows::Win32::UI::WindowsAndMessaging::{SW_HIDE, SW_SHOWNORMAL};
+
+/// Desktop of the interactive window station, where the user sees windows
+const INTERACTIVE_DESKTOP: &str = "winsta0\\default";
+
+/// Exit code given to a process killed after its timeout
+const TIMEOUT_EXIT_CODE: u32 = 1460; // ERROR_TIMEOUT
+
+/// Primary token of the user logged on to a session, closed when dropped
+///
+/// Only services running as LocalSystem can get the token of another session's user.
+pub struct SessionToken(HANDLE);
+
+impl SessionToken {
+    /// Get a primary token of the user logged on to a session
+    pub fn query(session_id: &str) -> Result<Self> {
+        let session: u32 = session_id
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid session ID: {}", session_id))?;
+
+        unsafe {
+            let mut token = HANDLE::default();
+            WTSQueryUserToken(session, &mut token)
+                .with_context(|| format!("Failed to get user token for session {}", session_id))?;
+            let token = Self(token);
+
+            // Duplicate the token so it can both start processes and be impersonated
+            let mut primary = HANDLE::default();
+            DuplicateTokenEx(token.0, TOKEN_ALL_ACCESS, None, SecurityImpersonation, TokenPrimary, &mut primary)
+                .with_context(|| format!("Failed to duplicate user token for session {}", session_id))?;
+            Ok(Self(primary))
+        }
+    }
+
+    /// Get the handle of the token, valid while the token lives
+    pub fn handle(&self) -> HANDLE {
+        self.0
+    }
+}
+
+impl Drop for SessionToken {
+    fn drop(&mut self) {
+        let _ = unsafe { CloseHandle(self.0) };
+    }
+}
+
+/// Reverts the thread to the service account when dropped, even if the impersonated code panics
+struct RevertGuard;
+
+impl Drop for RevertGuard {
+    fn drop(&mut self) {
+        if let Err(e) = unsafe { RevertToSelf() } {
+            warn!("Failed to stop impersonating: {}", e);
+        }
+    }
+}
+
+/// Program to run in a user session
+#[derive(Debug, Clone)]
+pub struct SessionCommand {
+    /// Path of the program
+    pub program: PathBuf,
+
+    /// Arguments of the program
+    pub args: Vec<String>,
+
+    /// Directory to start in (None = the user's profile)
+    pub working_dir: Option<PathBuf>,
+
+    /// Desktop to show windows on
+    pub desktop: String,
+
+    /// How long to wait for the program to exit before killing it (None = don't wait)
+    pub timeout: Option<Duration>,
+
+    /// Whether the program's windows are shown
+    pub visible: bool,
+}
+
+impl SessionCommand {
+    /// Create a command that runs a program hidden on the interactive desktop, without waiting for it
+    pub fn new(program: impl Into<PathBuf>) -> Self {
+        Self {
+            program: program.into(),
+            args: Vec::new(),
+            working_dir: None,
+            desktop: INTERACTIVE_DESKTOP.to_string(),
+            timeout: None,
+            visible: false,
+        }
+    }
+
+    /// Create a command that runs a copy of this program, for showing its UI in a user's session
+    ///
+    /// Toasts, balloons, windows and checks of the user's desktop only work from the user's
+    /// session, so the service runs them through a hidden subcommand of its own executable.
+    pub fn this_program<I, S>(args: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let program = std::env::current_exe().context("Failed to get the path of this program")?;
+        Ok(Self::new(program).args(args))
+    }
+
+    /// Add arguments
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Wait for the program to exit, killing it after the timeout
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Show the program's windows
+    pub fn visible(mut self) -> Self {
+        self.visible = true;
+        self
+    }
+
+    /// Build the command line, quoting the program and arguments where needed
+    pub fn command_line(&self) -> String {
+        std::iter::once(self.program.to_string_lossy().into_owned())
+            .chain(self.args.iter().cloned())
+            .map(|arg| quote_argument(&arg))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// How a program run in a user session ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionExit {
+    /// The program was started without waiting for it
+    Started,
+
+    /// The program exited with an exit code
+    Exited(u32),
+
+    /// The program didn't exit within the timeout and was killed
+    TimedOut,
+}
+
+impl SessionExit {
+    /// Check whether the program was started, or exited with exit code 0
+    pub fn success(&self) -> bool {
+        matches!(self, Self::Started | Self::Exited(0))
+    }
+}
+
+impl Impersonator {
+    /// Run a program as the user logged on to a session
+    ///
+    /// The program gets the user's own environment and runs on the command's desktop, so it can
+    /// show toasts and dialogs and read the user's registry hive. Requires running as LocalSystem.
+    pub fn run_in_session(&self, session_id: &str, command: &SessionCommand) -> Result<SessionExit> {
+        let token = SessionToken::query(session_id)?;
+        let mut command_line = to_wide(&command.command_line());
+        let mut desktop = to_wide(&command.desktop);
+        let working_dir = command.working_dir.as_ref().map(|dir| to_wide(&dir.to_string_lossy()));
+        debug!("Running in session {}: {}", session_id, command.command_line());
+
+        unsafe {
+            let mut environment: *mut c_void = std::ptr::null_mut();
+            CreateEnvironmentBlock(&mut environment, Some(token.handle()), false)
+                .with_context(|| format!("Failed to create the user environment for session {}", session_id))?;
+
+            let startup_info = STARTUPINFOW {
+                cb: std::mem::size_of::<STARTUPINFOW>() as u32,
+                lpDesktop: PWSTR(desktop.as_mut_ptr()),
+                dwFlags: STARTF_USESHOWWINDOW,
+                wShowWindow: if command.visible { SW_SHOWNORMAL.0 as u16 } else { SW_HIDE.0 as u16 },
+                ..Default::default()
+            };
+            let mut creation_flags = CREATE_UNICODE_ENVIRONMENT;
+            if !command.visible {
+                creation_flags |= CREATE_NO_WINDOW;
+            }
+
+            let mut process = PROCESS_INFORMATION::default();
+            let created = CreateProcessAsUserW(
+                Some(token.handle()),
+                PCWSTR::null(),
+                Some(PWSTR(command_line.as_mut_ptr())),
+                None,
+                None,
+                false,
+                creation_flags,
+                Some(environment as *const c_void),
+                working_dir.as_ref().map_or(PCWSTR::null(), |dir| PCWSTR::from_raw(dir.as_ptr())),
+                &startup_info,
+                &mut process,
+            );
+            let _ = DestroyEnvironmentBlock(environment);
+            created.with_context(|| format!("Failed to start {:?} in session {}", command.program, session_id))?;
+            let _ = CloseHandle(process.hThread);
+
+            let exit = match command.timeout {
+                None => Ok(SessionExit::Started),
+                Some(timeout) => wait_for_exit(process.hProcess, timeout),
+            };
+            let _ = CloseHandle(process.hProcess);
+
+            let exit = exit?;
+            debug!("Program in session {} ended: {:?}", session_id, exit);
+            Ok(exit)
+        }
+    }
+
+    /// Run a closure on this thread while impersonating the user logged on to a session
+    ///
+    /// Useful for reading the user's settings without starting a process, e.g. their UI
+    /// language. HKEY_CURRENT_USER stays mapped to the service account's hive while impersonating,
+    /// so open the user's hive under HKEY_USERS instead.
+    pub fn with_session_user<T>(&self, session_id: &str, f: impl FnOnce() -> T) -> Result<T> {
+        let token = SessionToken::query(session_id)?;
+
+        unsafe { ImpersonateLoggedOnUser(token.handle()) }
+            .with_context(|| format!("Failed to impersonate user of session {}", session_id))?;
+        let _revert = RevertGuard;
+        Ok(f())
+    }
+}
+
+/// Wait for a process to exit, killing it after the timeout
+unsafe fn wait_for_exit(process: HANDLE, timeout: Duration) -> Result<SessionExit> {
+    let milliseconds = timeout.as_millis().min(u32::MAX as u128 - 1) as u32;
+    match WaitForSingleObject(process, milliseconds) {
+        WAIT_OBJECT_0 => {
+            let mut exit_code = 0;
+            GetExitCodeProcess(process, &mut exit_code).context("Failed to get the exit code")?;
+            Ok(SessionExit::Exited(exit_code))
+        }
+        WAIT_TIMEOUT => {
+            if let Err(e) = TerminateProcess(process, TIMEOUT_EXIT_CODE) {
+                warn!("Failed to kill program after {:?}: {}", timeout, e);
+            }
+            Ok(SessionExit::TimedOut)
+        }
+        _ => Err(anyhow::anyhow!("Failed to wait for the program: {}", windows::core::Error::from_win32())),
+    }
+}
+
+/// Quote a command line argument so the C runtime parses it back unchanged
+fn quote_argument(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '\n', '"']) {
+        return arg.to_string();
+    }
+
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                // Backslashes before a quote are escaped, as is the quote itself
+                quoted.extend(std::iter::repeat('\\').take(backslashes * 2 + 1));
+                quoted.push('"');
+                backslashes = 0;
+            }
+            _ => {
+                quoted.extend(std::iter::repeat('\\').take(backslashes));
+                quoted.push(c);
+                backslashes = 0;
+            }
+        }
+    }
+
+    // Backslashes before the closing quote are escaped too
+    quoted.extend(std::iter::repeat('\\').take(backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_line() {
+        let command = SessionCommand::new(r"C:\Program Files\RebootReminder\reboot_reminder.exe")
+            .args(["show-toast", r#"{"title":"Restart"}"#, "", r"C:\My Temp\", r"C:\Temp"]);
+        assert_eq!(
+            command.command_line(),
+            r#""C:\Program Files\RebootReminder\reboot_reminder.exe" show-toast "{\"title\":\"Restart\"}" "" "C:\My Temp\\" C:\Temp"#
+        );
+
+        assert_eq!(quote_argument(r#"a\\"b"#), r#""a\\\\\"b""#);
+        assert!(SessionExit::Started.success());
+        assert!(!SessionExit::Exited(1).success());
+        assert!(!SessionExit::TimedOut.success());
+    }
+}
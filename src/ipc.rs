@@ -0,0 +1,236 @@
+use crate::utils::to_wide;
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::windows::io::{AsRawHandle, FromRawHandle};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{LocalFree, ERROR_PIPE_CONNECTED, HANDLE, HLOCAL};
+use windows::Win32::Security::Authorization::{ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1};
+use windows::Win32::Security::{PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES};
+use windows::Win32::Storage::FileSystem::{FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_DUPLEX};
+use windows::Win32::System::Pipes::{
+    ConnectNamedPipe, CreateNamedPipeW, DisconnectNamedPipe, GetNamedPipeClientSessionId, PeekNamedPipe,
+    PIPE_READMODE_MESSAGE, PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_MESSAGE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+};
+
+/// Pipe processes in the user sessions send requests to the service through
+pub const PIPE_NAME: &str = r"\\.\pipe\RebootReminder";
+
+/// Full access for SYSTEM and administrators, read and write for interactively signed-in users
+const PIPE_SECURITY: &str = "D:(A;;GA;;;SY)(A;;GA;;;BA)(A;;GRGW;;;IU)";
+
+/// Largest request or response, in bytes
+const MESSAGE_SIZE: usize = 64 * 1024;
+
+/// How often a client tries to reach a busy pipe
+const CONNECT_ATTEMPTS: u32 = 10;
+
+/// How long a connected client has to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Request sent to the service
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IpcRequest {
+    /// `rebootreminder:` link to act on
+    pub uri: String,
+}
+
+/// Answer from the service
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IpcResponse {
+    /// Whether the request was carried out
+    pub ok: bool,
+
+    /// What was done, or why it failed
+    pub message: String,
+}
+
+/// Serve requests on the pipe in the background
+///
+/// Each client is answered on its own thread while the next instance of the pipe waits for the
+/// next client, so a client that connects and stays quiet can't hold up the others. The handler
+/// gets the request and the ID of the session the client runs in, as reported by Windows, so
+/// clients can't act on behalf of other sessions.
+pub fn start<F>(handler: F) -> Result<()>
+where
+    F: Fn(&IpcRequest, u32) -> Result<String> + Send + Sync + 'static,
+{
+    // Create the first instance up front so a failure is reported to the caller, and so another
+    // process that created the pipe first is noticed instead of answering our clients
+    let mut pipe = create_pipe(true)?;
+    info!("Listening for requests on {}", PIPE_NAME);
+
+    let handler = Arc::new(handler);
+    thread::Builder::new()
+        .name("ipc".to_string())
+        .spawn(move || loop {
+            if let Err(e) = wait_for_client(&pipe) {
+                debug!("{:#}", e);
+                let _ = unsafe { DisconnectNamedPipe(HANDLE(pipe.as_raw_handle())) };
+                continue;
+            }
+
+            // Create the next instance before handing this one over, so the pipe never goes away
+            let next = match create_pipe(false) {
+                Ok(pipe) => pipe,
+                Err(e) => {
+                    warn!("Stopped listening for requests: {:#}", e);
+                    break;
+                }
+            };
+            let connected = std::mem::replace(&mut pipe, next);
+            let handler = handler.clone();
+            let spawned = thread::Builder::new().name("ipc-client".to_string()).spawn(move || {
+                if let Err(e) = serve(&connected, handler.as_ref()) {
+                    debug!("Failed to answer request: {:#}", e);
+                }
+                let _ = unsafe { DisconnectNamedPipe(HANDLE(connected.as_raw_handle())) };
+            });
+            if let Err(e) = spawned {
+                warn!("Failed to start a thread for an IPC client: {}", e);
+            }
+        })
+        .context("Failed to start the IPC thread")?;
+
+    Ok(())
+}
+
+/// Wait for a client to connect to an instance of the pipe
+fn wait_for_client(pipe: &File) -> Result<()> {
+    if let Err(e) = unsafe { ConnectNamedPipe(HANDLE(pipe.as_raw_handle()), None) } {
+        // The client connected between creating the pipe and waiting for it
+        if e.code() != ERROR_PIPE_CONNECTED.to_hresult() {
+            return Err(e).context("Failed to wait for a client");
+        }
+    }
+    Ok(())
+}
+
+/// Answer the request of a connected client
+fn serve<F>(mut pipe: &File, handler: &F) -> Result<()>
+where
+    F: Fn(&IpcRequest, u32) -> Result<String>,
+{
+    let handle = HANDLE(pipe.as_raw_handle());
+    let mut session_id = 0;
+    unsafe { GetNamedPipeClientSessionId(handle, &mut session_id) }.context("Failed to get the session of the client")?;
+
+    wait_for_request(handle)?;
+    let mut buffer = vec![0u8; MESSAGE_SIZE];
+    let len = pipe.read(&mut buffer).context("Failed to read request")?;
+    let response = match serde_json::from_slice::<IpcRequest>(&buffer[..len]) {
+        Ok(request) => {
+            debug!("Request from session {}: {}", session_id, request.uri);
+            match handler(&request, session_id) {
+                Ok(message) => IpcResponse { ok: true, message },
+                Err(e) => {
+                    warn!("Failed to carry out request from session {}: {:#}", session_id, e);
+                    IpcResponse { ok: false, message: format!("{:#}", e) }
+                }
+            }
+        }
+        Err(e) => IpcResponse { ok: false, message: format!("Invalid request: {}", e) },
+    };
+
+    pipe.write_all(&serde_json::to_vec(&response)?).context("Failed to write response")?;
+    pipe.flush()?;
+    Ok(())
+}
+
+/// Wait until the client has sent its request, giving up after `REQUEST_TIMEOUT`
+fn wait_for_request(handle: HANDLE) -> Result<()> {
+    let deadline = Instant::now() + REQUEST_TIMEOUT;
+    loop {
+        let mut available = 0;
+        unsafe { PeekNamedPipe(handle, None, 0, None, Some(&mut available as *mut u32), None) }
+            .context("Failed to wait for the request")?;
+        if available > 0 {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            anyhow::bail!("The client didn't send a request within {:?}", REQUEST_TIMEOUT);
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+/// Create an instance of the pipe that signed-in users can connect to
+///
+/// The first instance fails if the pipe already exists, so no other process can sit on the name
+/// and read what clients send to the service.
+fn create_pipe(first: bool) -> Result<File> {
+    let name = to_wide(PIPE_NAME);
+    let security = to_wide(PIPE_SECURITY);
+
+    unsafe {
+        let mut descriptor = PSECURITY_DESCRIPTOR::default();
+        ConvertStringSecurityDescriptorToSecurityDescriptorW(
+            PCWSTR::from_raw(security.as_ptr()),
+            SDDL_REVISION_1,
+            &mut descriptor,
+            None,
+        )
+        .context("Failed to create the pipe security descriptor")?;
+        let attributes = SECURITY_ATTRIBUTES {
+            nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+            lpSecurityDescriptor: descriptor.0,
+            bInheritHandle: false.into(),
+        };
+
+        let open_mode = if first {
+            PIPE_ACCESS_DUPLEX | FILE_FLAG_FIRST_PIPE_INSTANCE
+        } else {
+            PIPE_ACCESS_DUPLEX
+        };
+        let pipe = CreateNamedPipeW(
+            PCWSTR::from_raw(name.as_ptr()),
+            open_mode,
+            PIPE_TYPE_MESSAGE | PIPE_READMODE_MESSAGE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+            PIPE_UNLIMITED_INSTANCES,
+            MESSAGE_SIZE as u32,
+            MESSAGE_SIZE as u32,
+            0,
+            Some(&attributes),
+        );
+        let _ = LocalFree(Some(HLOCAL(descriptor.0)));
+
+        if pipe.is_invalid() {
+            return Err(windows::core::Error::from_win32()).context("Failed to create the pipe");
+        }
+        Ok(File::from_raw_handle(pipe.0))
+    }
+}
+
+/// Send a request to the running service and wait for its answer
+pub fn send(request: &IpcRequest) -> Result<IpcResponse> {
+    let mut pipe = connect()?;
+    pipe.write_all(&serde_json::to_vec(request)?).context("Failed to send request")?;
+
+    let mut buffer = vec![0u8; MESSAGE_SIZE];
+    let len = pipe.read(&mut buffer).context("Failed to read response")?;
+    serde_json::from_slice(&buffer[..len]).context("Invalid response from the service")
+}
+
+/// Open the pipe, waiting while the service is answering another client
+fn connect() -> Result<File> {
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        match OpenOptions::new().read(true).write(true).open(PIPE_NAME) {
+            Ok(pipe) => return Ok(pipe),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(anyhow::anyhow!("The Reboot Reminder service is not running"));
+            }
+            Err(e) if attempt < CONNECT_ATTEMPTS => {
+                debug!("Pipe not available yet: {}", e);
+                thread::sleep(Duration::from_millis(200));
+            }
+            Err(e) => return Err(e).context("Failed to connect to the service"),
+        }
+    }
+}
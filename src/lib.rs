@@ -0,0 +1,24 @@
+//! Reboot Reminder library
+//!
+//! Exposes the reboot detection, configuration, database and timespan code so other tools can
+//! reuse it. The Windows service itself is only built with the `service` feature (enabled by default).
+
+pub mod config;
+pub mod database;
+pub mod impersonation;
+pub mod ipc;
+pub mod logging;
+pub mod metrics;
+pub mod notification;
+pub mod reboot;
+pub mod reporting;
+#[cfg(feature = "service")]
+pub mod service;
+pub mod support;
+pub mod update;
+pub mod utils;
+pub mod watchdog;
+
+pub use config::Config;
+pub use reboot::detector::RebootDetector;
+pub use utils::timespan;
@@ -1,8 +1,9 @@
+use crate::config::LoggingConfig;
 use anyhow::{Context, Result};
-use log::{debug, LevelFilter};
+use log::{debug, warn, LevelFilter};
 use log4rs::{
     append::{
-        console::ConsoleAppender,
+        console::{ConsoleAppender, Target},
         rolling_file::{
             policy::compound::{
                 roll::fixed_window::FixedWindowRoller, trigger::size::SizeTrigger, CompoundPolicy,
@@ -10,19 +11,52 @@ use log4rs::{
             RollingFileAppender,
         },
     },
-    config::{Appender, Config, Root},
+    config::{Appender, Config, Logger, Root},
     encode::pattern::PatternEncoder,
     filter::threshold::ThresholdFilter,
+    Handle,
 };
+use once_cell::sync::{Lazy, OnceCell};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::path::Path;
+use std::sync::{Mutex, RwLock};
+
+/// What may be written to the log, from the most recently loaded configuration
+static POLICY: Lazy<RwLock<LogPolicy>> = Lazy::new(|| RwLock::new(LogPolicy::default()));
+
+/// The logger set up by `init`, kept to apply per-module levels once the configuration is loaded
+static LOGGER: OnceCell<LoggerSetup> = OnceCell::new();
+
+/// Privacy settings for log messages
+#[derive(Debug, Clone, Default)]
+struct LogPolicy {
+    log_sql: bool,
+    redact_usernames: bool,
+}
+
+/// Handle of the running logger and the settings it was built with
+struct LoggerSetup {
+    handle: Handle,
+    level: LevelFilter,
+    console_to_stderr: bool,
+    log_path: String,
+
+    /// Per-module levels currently applied
+    overrides: Mutex<Vec<(String, LevelFilter)>>,
+}
 
 /// Initialize logging
-pub fn init(debug: bool) -> Result<()> {
+///
+/// With `console_to_stderr` console output goes to standard error, leaving standard output free
+/// for machine-readable output.
+pub fn init(debug: bool, console_to_stderr: bool) -> Result<()> {
     // Create a console appender
     let stdout = ConsoleAppender::builder()
         .encoder(Box::new(PatternEncoder::new(
             "{d(%Y-%m-%d %H:%M:%S)} [{l}] {m}{n}",
         )))
+        .target(if console_to_stderr { Target::Stderr } else { Target::Stdout })
         .build();
 
     // Create a default file appender
@@ -46,8 +80,6 @@ pub fn init(debug: bool) -> Result<()> {
         "logs/rebootreminder.log".to_string()
     };
 
-    let file_appender = create_rolling_file_appender(&log_path, 10, 7)?;
-
     // Set log level based on debug flag
     let level = if debug {
         LevelFilter::Debug
@@ -56,30 +88,160 @@ pub fn init(debug: bool) -> Result<()> {
     };
 
     // Build the logging configuration
-    let config = Config::builder()
+    let config = build_config(stdout, &log_path, level, &[])?;
+
+    // Initialize the logger
+    let handle = log4rs::init_config(config).context("Failed to initialize logger")?;
+    let _ = LOGGER.set(LoggerSetup {
+        handle,
+        level,
+        console_to_stderr,
+        log_path,
+        overrides: Mutex::new(Vec::new()),
+    });
+
+    debug!("Logging initialized with level: {:?}", level);
+    Ok(())
+}
+
+/// Build the logger configuration with optional per-module levels
+fn build_config(
+    stdout: ConsoleAppender,
+    log_path: &str,
+    level: LevelFilter,
+    overrides: &[(String, LevelFilter)],
+) -> Result<Config> {
+    let file_appender = create_rolling_file_appender(log_path, 10, 7)?;
+
+    // Let messages of modules logging more than the default level through to the appenders
+    let threshold = overrides
+        .iter()
+        .map(|(_, module_level)| *module_level)
+        .fold(level, std::cmp::max);
+
+    let mut builder = Config::builder()
         .appender(
             Appender::builder()
-                .filter(Box::new(ThresholdFilter::new(level)))
+                .filter(Box::new(ThresholdFilter::new(threshold)))
                 .build("stdout", Box::new(stdout)),
         )
         .appender(
             Appender::builder()
-                .filter(Box::new(ThresholdFilter::new(level)))
+                .filter(Box::new(ThresholdFilter::new(threshold)))
                 .build("file", Box::new(file_appender)),
-        )
+        );
+    for (module, module_level) in overrides {
+        builder = builder.logger(Logger::builder().build(module.as_str(), *module_level));
+    }
+
+    builder
         .build(
             Root::builder()
                 .appender("stdout")
                 .appender("file")
                 .build(level),
         )
-        .context("Failed to build logging configuration")?;
+        .context("Failed to build logging configuration")
+}
 
-    // Initialize the logger
-    log4rs::init_config(config).context("Failed to initialize logger")?;
+/// Apply the privacy settings and per-module levels of the logging configuration
+///
+/// Called whenever the configuration is loaded. The logger is only rebuilt when the per-module
+/// levels changed.
+pub fn set_policy(config: &LoggingConfig) {
+    match POLICY.write() {
+        Ok(mut policy) => {
+            *policy = LogPolicy {
+                log_sql: config.log_sql,
+                redact_usernames: config.redact_usernames,
+            }
+        }
+        Err(e) => warn!("Failed to update logging policy: {}", e),
+    }
 
-    debug!("Logging initialized with level: {:?}", level);
-    Ok(())
+    let Some(setup) = LOGGER.get() else {
+        return;
+    };
+
+    let mut overrides: Vec<(String, LevelFilter)> = config
+        .log_level_overrides
+        .iter()
+        .filter_map(|(module, level)| parse_level(level).map(|level| (module.clone(), level)))
+        .collect();
+    overrides.sort();
+
+    let mut applied = match setup.overrides.lock() {
+        Ok(applied) => applied,
+        Err(e) => {
+            warn!("Failed to read applied log levels: {}", e);
+            return;
+        }
+    };
+    if *applied == overrides {
+        return;
+    }
+
+    let stdout = ConsoleAppender::builder()
+        .encoder(Box::new(PatternEncoder::new(
+            "{d(%Y-%m-%d %H:%M:%S)} [{l}] {m}{n}",
+        )))
+        .target(if setup.console_to_stderr { Target::Stderr } else { Target::Stdout })
+        .build();
+    match build_config(stdout, &setup.log_path, setup.level, &overrides) {
+        Ok(logger_config) => {
+            setup.handle.set_config(logger_config);
+            debug!("Applied per-module log levels: {:?}", overrides);
+            *applied = overrides;
+        }
+        Err(e) => warn!("Failed to apply per-module log levels: {:#}", e),
+    }
+}
+
+/// Parse a log level name, including `off`
+pub fn parse_level(level: &str) -> Option<LevelFilter> {
+    match level.to_lowercase().as_str() {
+        "off" => Some(LevelFilter::Off),
+        "trace" => Some(LevelFilter::Trace),
+        "debug" => Some(LevelFilter::Debug),
+        "info" => Some(LevelFilter::Info),
+        "warn" => Some(LevelFilter::Warn),
+        "error" => Some(LevelFilter::Error),
+        _ => None,
+    }
+}
+
+fn policy() -> LogPolicy {
+    POLICY.read().map(|policy| policy.clone()).unwrap_or_default()
+}
+
+/// Log an SQL statement at debug level, if SQL logging is enabled
+pub fn sql(query: &str) {
+    if policy().log_sql {
+        debug!(target: "reboot_reminder::database", "Executing query: {}", query);
+    }
+}
+
+/// Get a user name as it may appear in the log
+///
+/// With `redactUsernames` the name is replaced by a short hash, which is the same for every
+/// message so the entries of one user can still be followed.
+pub fn user(name: &str) -> String {
+    if policy().redact_usernames {
+        redact(name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Get an optional user name as it may appear in the log
+pub fn user_opt(name: Option<&str>) -> String {
+    name.map(user).unwrap_or_else(|| "<unknown>".to_string())
+}
+
+fn redact(name: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    name.to_lowercase().hash(&mut hasher);
+    format!("user-{:08x}", hasher.finish() as u32)
 }
 
 /// Initialize logging with configuration
@@ -177,3 +339,18 @@ fn create_rolling_file_appender(
 
     Ok(appender)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact() {
+        assert_eq!(redact("alice"), redact("ALICE"));
+        assert_ne!(redact("alice"), redact("bob"));
+        assert!(!redact("alice").contains("alice"));
+        assert_eq!(parse_level("WARN"), Some(LevelFilter::Warn));
+        assert_eq!(parse_level("off"), Some(LevelFilter::Off));
+        assert_eq!(parse_level("verbose"), None);
+    }
+}
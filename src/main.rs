@@ -1,17 +1,11 @@
-pub mod config;
-pub mod database;
-pub mod impersonation;
-pub mod logging;
-pub mod notification;
-pub mod reboot;
-pub mod service;
-pub mod utils;
-pub mod watchdog;
-
 use anyhow::Result;
 use clap::{Parser, Subcommand};
-use log::{error, info};
+use log::{error, info, warn};
+use reboot_reminder::impersonation::Impersonator;
+use reboot_reminder::notification::{self, template::TemplateContext, NotificationManager};
+use reboot_reminder::{config, database, ipc, logging, metrics, reboot, service, support, update, utils};
 use std::path::PathBuf;
+use std::sync::Arc;
 
 /// Reboot Reminder - A cross-platform reboot reminder system
 #[derive(Parser, Debug)]
@@ -25,6 +19,10 @@ struct Args {
     #[arg(short, long)]
     debug: bool,
 
+    /// Run without showing notifications or rebooting, logging what would have happened
+    #[arg(long)]
+    dry_run: bool,
+
     /// Subcommands
     #[command(subcommand)]
     command: Option<Commands>,
@@ -45,29 +43,340 @@ enum Commands {
         /// Service description
         #[arg(short, long, default_value = "Provides notifications when system reboots are necessary")]
         description: String,
+
+        /// Account the service runs as (e.g., "NT AUTHORITY\LocalService" or "DOMAIN\svc-reboot$"), LocalSystem by default
+        #[arg(long)]
+        account: Option<String>,
+
+        /// Password of the account, plain text or DPAPI protected
+        #[arg(long, requires = "account")]
+        password: Option<String>,
+
+        /// Start the service shortly after boot instead of with the other automatic services
+        #[arg(long)]
+        delayed_start: bool,
+
+        /// Configuration file the service loads, instead of config.json next to the executable
+        #[arg(long = "config", value_name = "FILE")]
+        service_config: Option<PathBuf>,
+
+        /// Delays before restarting the service after the first, second and later failures
+        #[arg(long, value_name = "TIMESPANS", value_delimiter = ',', default_value = "1m,1m,1m", value_parser = parse_duration)]
+        restart_delays: Vec<std::time::Duration>,
+
+        /// Time without failures after which the failure count is reset
+        #[arg(long, value_name = "TIMESPAN", default_value = "24h", value_parser = parse_duration)]
+        reset_period: std::time::Duration,
+
+        /// Register a scheduled task that runs `run --once` at logon and periodically instead of a service
+        #[arg(long, conflicts_with_all = ["account", "password", "delayed_start"])]
+        as_task: bool,
+
+        /// How often the scheduled task runs besides at logon
+        #[arg(long, value_name = "TIMESPAN", default_value = "1h", value_parser = parse_duration, requires = "as_task")]
+        task_interval: std::time::Duration,
     },
     /// Uninstall the service
-    Uninstall,
+    Uninstall {
+        /// Also remove the database, logs and registry keys
+        #[arg(long)]
+        purge: bool,
+
+        /// Delete the scheduled task installed with `install --as-task` instead of the service
+        #[arg(long)]
+        as_task: bool,
+
+        /// Name the scheduled task was installed with
+        #[arg(short, long, default_value = "RebootReminder", requires = "as_task")]
+        name: String,
+
+        /// Keep the database, logs and registry keys (the default)
+        #[arg(long, conflicts_with = "purge")]
+        keep_data: bool,
+    },
     /// Run the service
-    Run,
-    /// Check if the system requires a reboot
-    Check,
+    Run {
+        /// Run a single reboot check cycle and exit instead of running continuously
+        #[arg(long)]
+        once: bool,
+    },
+    /// Check if the system requires a reboot; exits with 0 if not, 2 if it does and 1 on errors
+    Check {
+        /// Print the result as JSON
+        #[arg(long)]
+        json: bool,
+
+        /// Check a remote computer through its remote registry, administrative shares and WMI
+        #[arg(long, value_name = "NAME")]
+        computer: Option<String>,
+
+        /// User to connect to the remote computer as (e.g., CONTOSO\helpdesk) instead of the current user;
+        /// the password is read from REBOOT_REMINDER_PASSWORD or prompted for
+        #[arg(long, value_name = "USER", requires = "computer")]
+        user: Option<String>,
+    },
+    /// Remove stale pending reboot markers, only showing what would change unless --force is given
+    ClearPending {
+        /// Source whose stale markers are removed: pending_file_operations, windows_update or installer_operations
+        source: reboot::clear::StaleMarker,
+
+        /// Modify the registry instead of only showing the changes
+        #[arg(long)]
+        force: bool,
+    },
+    /// Show the stored reboot state, including the reboot deadline
+    Status {
+        /// Also show the service's counters and timings
+        #[arg(short, long)]
+        verbose: bool,
+    },
+    /// Check that the reminder system itself works, printing JSON; exits with 0 (ok), 1 (warning) or 2 (critical)
+    Health {
+        /// Age of the last reboot check after which detection counts as stalled (default: three check intervals)
+        #[arg(long, value_name = "TIMESPAN", value_parser = parse_duration)]
+        max_check_age: Option<std::time::Duration>,
+    },
+    /// Open a page explaining why a reboot is needed and what happens next
+    Details,
+    /// Delete old rows and compact the database
+    DbMaintenance,
+    /// Export or import the reboot state, history and notifications, e.g. when re-imaging a machine
+    Db {
+        #[command(subcommand)]
+        command: DbCommands,
+    },
+    /// Package the configuration, logs, database and status into a zip file for support
+    ExportState {
+        /// Path of the zip file to create
+        #[arg(value_name = "FILE")]
+        output: PathBuf,
+
+        /// Number of log files to include
+        #[arg(long, default_value_t = 5)]
+        log_files: usize,
+    },
+    /// Show a notification with the configured branding and messages without changing the reboot state
+    NotifyTest {
+        /// Message to show: required, recommended, deadline, countdown, scheduled or postponed
+        #[arg(default_value = "required")]
+        kind: String,
+
+        /// Show this text instead of the configured message (placeholders are supported)
+        #[arg(short, long)]
+        message: Option<String>,
+
+        /// Show the notification even during quiet hours or while the user is busy
+        #[arg(long)]
+        force: bool,
+    },
+    /// Report reboot compliance per period as CSV or JSON, for example for Power BI
+    Report {
+        /// Output format: csv or json
+        #[arg(long, default_value = "csv")]
+        format: support::report::ReportFormat,
+
+        /// Length of the periods: day, week or month
+        #[arg(long, default_value = "week")]
+        period: support::report::ReportPeriod,
+
+        /// Days a reboot may be pending before it counts as an SLA breach
+        #[arg(long, default_value_t = 7)]
+        sla_days: u32,
+
+        /// Write the report to a file instead of standard output
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Print an audit trail of who was notified when, what they chose and when the reboot happened
+    Audit {
+        /// Start of the trail: a timespan back from now (e.g., 720h), a date or an RFC 3339 time
+        #[arg(long)]
+        since: Option<String>,
+
+        /// Output format: csv or json
+        #[arg(long, default_value = "json")]
+        format: support::report::ReportFormat,
+
+        /// Write the audit trail to a file instead of standard output
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Encrypt a secret with the DPAPI machine key for use in the configuration file
+    ProtectSecret {
+        /// Value to encrypt (read from standard input if omitted)
+        value: Option<String>,
+    },
+    /// Replace the installed executable with this one and restart the service (used by the updater)
+    #[command(hide = true)]
+    ApplyUpdate {
+        /// Name of the service to restart
+        #[arg(long)]
+        service_name: String,
+
+        /// Path of the executable to replace
+        #[arg(long, value_name = "FILE")]
+        target: PathBuf,
+    },
+    /// Show a toast notification as the user running this program (used by the service)
+    #[command(hide = true)]
+    ShowToast {
+        /// Toast notification as JSON
+        toast: String,
+    },
+    /// Show a balloon or window as the user running this program until it's closed (used by the service)
+    #[command(hide = true)]
+    ShowUi {
+        /// Notification UI as JSON
+        ui: String,
+    },
+    /// Exit with a code telling whether the user running this program is presenting or busy (used by the service)
+    #[command(hide = true)]
+    CheckAvailability,
+    /// Ask the user running this program to confirm a reboot, exiting with 0 if they do (used by the service)
+    #[command(hide = true)]
+    ConfirmReboot {
+        /// Title of the question
+        title: String,
+
+        /// Question to ask
+        message: String,
+    },
+    /// Hand the action of a rebootreminder: link to the service (registered as the protocol handler)
+    #[command(hide = true)]
+    OpenUrl {
+        /// Link to act on, e.g. rebootreminder:postpone?duration=4h
+        url: String,
+    },
+    /// Write ADMX and ADML templates for managing the configuration with Group Policy
+    GenerateAdmx {
+        /// Directory to write RebootReminder.admx and en-US\RebootReminder.adml to
+        #[arg(default_value = ".", value_name = "DIR")]
+        output: PathBuf,
+    },
+    /// Print the JSON Schema for the configuration file
+    Schema {
+        /// Write the schema to a file instead of standard output
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum DbCommands {
+    /// Write the reboot state, history and notifications to a portable JSON file
+    Export {
+        /// Path of the JSON file to create
+        #[arg(short, long, value_name = "FILE")]
+        file: PathBuf,
+    },
+    /// Import a file written by `db export`, replacing the reboot state and adding missing history and notifications
+    Import {
+        /// Path of the JSON file to read
+        #[arg(short, long, value_name = "FILE")]
+        file: PathBuf,
+    },
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
 
-    // Initialize logging
-    if let Err(e) = logging::init(args.debug) {
+    // The schema is written before logging starts so console output stays valid JSON
+    if let Some(Commands::Schema { output }) = &args.command {
+        let schema = config::schema()?;
+        match output {
+            Some(path) => std::fs::write(path, schema)
+                .map_err(|e| anyhow::anyhow!("Failed to write schema to {:?}: {}", path, e))?,
+            None => println!("{}", schema),
+        }
+        return Ok(());
+    }
+
+    // Policy templates don't need logging or a configuration
+    if let Some(Commands::GenerateAdmx { output }) = &args.command {
+        let adml_dir = output.join("en-US");
+        std::fs::create_dir_all(&adml_dir)
+            .map_err(|e| anyhow::anyhow!("Failed to create {:?}: {}", adml_dir, e))?;
+        std::fs::write(output.join("RebootReminder.admx"), config::policy::admx())
+            .map_err(|e| anyhow::anyhow!("Failed to write ADMX template: {}", e))?;
+        std::fs::write(adml_dir.join("RebootReminder.adml"), config::policy::adml())
+            .map_err(|e| anyhow::anyhow!("Failed to write ADML template: {}", e))?;
+        println!("Policy templates written to {:?}", output);
+        return Ok(());
+    }
+
+    // Protected secrets are also written before logging starts so they can be piped
+    if let Some(Commands::ProtectSecret { value }) = &args.command {
+        let value = match value {
+            Some(value) => value.clone(),
+            None => {
+                let mut line = String::new();
+                std::io::stdin()
+                    .read_line(&mut line)
+                    .map_err(|e| anyhow::anyhow!("Failed to read secret from standard input: {}", e))?;
+                line.trim_end_matches(['\r', '\n']).to_string()
+            }
+        };
+        println!("{}", utils::secrets::protect(&value)?);
+        return Ok(());
+    }
+
+    // Toasts are shown from the user's session, where the service's log files aren't writable
+    if let Some(Commands::ShowToast { toast }) = &args.command {
+        let toast: notification::toast::ToastNotification = serde_json::from_str(toast)
+            .map_err(|e| anyhow::anyhow!("Invalid toast notification: {}", e))?;
+        return toast.show();
+    }
+
+    // Balloons, windows, reboot confirmations and the availability check run in the user's session the same way
+    if let Some(Commands::ShowUi { ui }) = &args.command {
+        let ui: notification::session_ui::SessionUi = serde_json::from_str(ui)
+            .map_err(|e| anyhow::anyhow!("Invalid notification UI: {}", e))?;
+        return ui.show_and_wait();
+    }
+
+    if let Some(Commands::CheckAvailability) = &args.command {
+        std::process::exit(notification::availability::check().map_or(0, |unavailable| unavailable.exit_code() as i32));
+    }
+
+    if let Some(Commands::ConfirmReboot { title, message }) = &args.command {
+        std::process::exit(if reboot::system::ask_confirmation(title, message) { 0 } else { 1 });
+    }
+
+    // Links are opened from the user's session too, and only forwarded to the service
+    if let Some(Commands::OpenUrl { url }) = &args.command {
+        let response = ipc::send(&ipc::IpcRequest { uri: url.clone() })?;
+        if !response.ok {
+            return Err(anyhow::anyhow!("The service couldn't carry out {}: {}", url, response.message));
+        }
+        println!("{}", response.message);
+        return Ok(());
+    }
+
+    // Initialize logging, keeping standard output for the JSON check result
+    let json_output = matches!(
+        &args.command,
+        Some(Commands::Check { json: true, .. }) | Some(Commands::Health { .. }) | Some(Commands::Report { output: None, .. })
+            | Some(Commands::Audit { output: None, .. })
+    );
+    if let Err(e) = logging::init(args.debug, json_output) {
         // Can't use log macros yet since logging isn't initialized
         eprintln!("Failed to initialize logging: {}", e);
         return Err(anyhow::anyhow!("Failed to initialize logging: {}", e));
     }
     info!("Starting Reboot Reminder");
 
+    // The updater runs from the staging directory, away from the configuration file
+    if let Some(Commands::ApplyUpdate { service_name, target }) = &args.command {
+        if let Err(e) = update::apply_update(service_name, target) {
+            error!("Failed to apply update: {:#}", e);
+            return Err(anyhow::anyhow!("Failed to apply update: {}", e));
+        }
+        return Ok(());
+    }
+
     // Check if running with administrative privileges for commands that require it
     let admin_required = matches!(&args.command,
-        Some(Commands::Install {..}) | Some(Commands::Uninstall) | Some(Commands::Run)
+        Some(Commands::Install {..}) | Some(Commands::Uninstall { .. }) | Some(Commands::Run { .. }) | Some(Commands::ClearPending { .. }) | Some(Commands::Status { .. }) | Some(Commands::DbMaintenance) | Some(Commands::Db { .. }) | Some(Commands::ExportState { .. }) | Some(Commands::Report { .. }) | Some(Commands::Audit { .. })
     );
 
     if admin_required && !is_running_as_admin() {
@@ -76,6 +385,7 @@ fn main() -> Result<()> {
     }
 
     // Load configuration
+    let config_arg = args.config.clone();
     let config_path = args.config.unwrap_or_else(|| {
         let mut path = match std::env::current_exe() {
             Ok(exe_path) => {
@@ -104,14 +414,29 @@ fn main() -> Result<()> {
     info!("Using configuration file: {:?}", config_path);
 
     // Set the config path for the service
-    if let Some(Commands::Run) = &args.command {
+    if let Some(Commands::Run { once: false }) = &args.command {
         unsafe {
             service::set_config_path(config_path.clone());
         }
     }
 
+    if args.dry_run {
+        info!("Dry-run mode requested on the command line");
+        unsafe {
+            service::set_dry_run(true);
+        }
+    }
+
+    // The health check reports a broken configuration or database instead of failing on it
+    if let Some(Commands::Health { max_check_age }) = &args.command {
+        let report = service::health::check(&config_path, *max_check_age);
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        std::process::exit(report.status.exit_code());
+    }
+
     let config = match config::load(&config_path) {
-        Ok(cfg) => {
+        Ok(mut cfg) => {
+            cfg.service.dry_run |= args.dry_run;
             info!("Configuration loaded successfully from {:?}", config_path);
             cfg
         },
@@ -127,6 +452,14 @@ fn main() -> Result<()> {
             info!("Database initialized successfully at {}", config.database.path);
             pool
         },
+        Err(e) if std::path::Path::new(&config.database.path).exists() => {
+            // Users can only read the database the service protects
+            warn!("Failed to initialize database, opening it for reading only: {}", e);
+            database::open_read_only(&config.database).map_err(|e| {
+                error!("Failed to open database: {}", e);
+                anyhow::anyhow!("Failed to open database: {}", e)
+            })?
+        }
         Err(e) => {
             error!("Failed to initialize database: {}", e);
             return Err(anyhow::anyhow!("Failed to initialize database: {}", e));
@@ -139,9 +472,38 @@ fn main() -> Result<()> {
             name,
             display_name,
             description,
+            account,
+            password,
+            delayed_start,
+            service_config,
+            restart_delays,
+            reset_period,
+            as_task,
+            task_interval,
         }) => {
+            if as_task {
+                let options = service::task::TaskOptions {
+                    config_path: service_config.or(config_arg),
+                    interval: task_interval,
+                };
+                if let Err(e) = service::task::install(&name, &description, &config, &options) {
+                    error!("Failed to install scheduled task: {}", e);
+                    return Err(anyhow::anyhow!("Failed to install scheduled task: {}", e));
+                }
+                return Ok(());
+            }
+
             info!("Installing service: {}", name);
-            match service::install(&name, &display_name, &description) {
+            let options = service::InstallOptions {
+                account,
+                password,
+                delayed_start,
+                // Default to the configuration this command was given
+                config_path: service_config.or(config_arg),
+                restart_delays,
+                reset_period,
+            };
+            match service::install(&name, &display_name, &description, &config, &options) {
                 Ok(_) => info!("Service installed successfully"),
                 Err(e) => {
                     error!("Failed to install service: {}", e);
@@ -149,17 +511,37 @@ fn main() -> Result<()> {
                 }
             }
         }
-        Some(Commands::Uninstall) => {
+        Some(Commands::Uninstall { purge, as_task, name, .. }) => {
             info!("Uninstalling service");
-            match service::uninstall() {
+            let result = if as_task {
+                service::task::uninstall(&name)
+            } else {
+                service::uninstall()
+            };
+            match result {
                 Ok(_) => info!("Service uninstalled successfully"),
                 Err(e) => {
                     error!("Failed to uninstall service: {}", e);
                     return Err(anyhow::anyhow!("Failed to uninstall service: {}", e));
                 }
             }
+            if purge {
+                // Close the database so its file can be deleted
+                drop(db);
+                service::purge(&config)?;
+            }
         }
-        Some(Commands::Run) => {
+        Some(Commands::Run { once: true }) => {
+            match service::run_once(config, db) {
+                Ok(true) => std::process::exit(support::EXIT_REBOOT_REQUIRED),
+                Ok(false) => {}
+                Err(e) => {
+                    error!("Failed to run reboot check cycle: {}", e);
+                    return Err(anyhow::anyhow!("Failed to run reboot check cycle: {}", e));
+                }
+            }
+        }
+        Some(Commands::Run { once: false }) => {
             info!("Running service");
             match service::run(config, db) {
                 Ok(_) => info!("Service completed successfully"),
@@ -169,9 +551,21 @@ fn main() -> Result<()> {
                 }
             }
         }
-        Some(Commands::Check) => {
-            info!("Checking if the system requires a reboot");
-            let detector = reboot::detector::RebootDetector::new(&config.reboot);
+        Some(Commands::Check { json, computer, user }) => {
+            let detector = match &computer {
+                Some(computer) => {
+                    info!("Checking if {} requires a reboot", computer);
+                    let host = remote_host(computer, user.as_deref())?;
+                    reboot::remote::detector(&config.reboot, host).map_err(|e| {
+                        error!("Failed to connect to {}: {:#}", computer, e);
+                        anyhow::anyhow!("Failed to connect to {}: {}", computer, e)
+                    })?
+                }
+                None => {
+                    info!("Checking if the system requires a reboot");
+                    reboot::detector::RebootDetector::new(&config.reboot)
+                }
+            };
             match detector.check_reboot_required() {
                 Ok((required, sources)) => {
                     if required {
@@ -179,6 +573,16 @@ fn main() -> Result<()> {
                     } else {
                         info!("No reboot is required");
                     }
+
+                    if json {
+                        let system_info = detector.get_system_info().ok();
+                        let report = support::CheckReport::new(required, &sources, system_info.as_ref());
+                        println!("{}", serde_json::to_string_pretty(&report)?);
+                    }
+
+                    if required {
+                        std::process::exit(support::EXIT_REBOOT_REQUIRED);
+                    }
                 }
                 Err(e) => {
                     error!("Failed to check if reboot is required: {}", e);
@@ -186,6 +590,100 @@ fn main() -> Result<()> {
                 }
             }
         }
+        Some(Commands::ClearPending { source, force }) => {
+            if let Err(e) = clear_pending(&config, source, force) {
+                error!("Failed to clear stale {} markers: {}", source, e);
+                return Err(anyhow::anyhow!("Failed to clear stale {} markers: {}", source, e));
+            }
+        }
+        Some(Commands::Status { verbose }) => {
+            if let Err(e) = print_status(&config, &db, verbose) {
+                error!("Failed to show status: {}", e);
+                return Err(anyhow::anyhow!("Failed to show status: {}", e));
+            }
+        }
+        Some(Commands::Details) => {
+            if let Err(e) = notification::details::show(&config.notification.branding.title, &db) {
+                error!("Failed to show reboot details: {}", e);
+                return Err(anyhow::anyhow!("Failed to show reboot details: {}", e));
+            }
+        }
+        Some(Commands::DbMaintenance) => {
+            info!("Running database maintenance");
+            match database::run_maintenance(&db, &config.database) {
+                Ok(report) => info!("Database maintenance completed: {:?}", report),
+                Err(e) => {
+                    error!("Failed to run database maintenance: {}", e);
+                    return Err(anyhow::anyhow!("Failed to run database maintenance: {}", e));
+                }
+            }
+        }
+        Some(Commands::Db { command: DbCommands::Export { file } }) => {
+            match database::export_to_file(&db, &file) {
+                Ok(export) => println!(
+                    "Exported the reboot state, {} history entries and {} notifications to {}",
+                    export.reboot_history.len(),
+                    export.notifications.len(),
+                    file.display()
+                ),
+                Err(e) => {
+                    error!("Failed to export database: {}", e);
+                    return Err(anyhow::anyhow!("Failed to export database: {}", e));
+                }
+            }
+        }
+        Some(Commands::Db { command: DbCommands::Import { file } }) => {
+            match database::import_from_file(&db, &file) {
+                Ok(report) => println!(
+                    "Imported {}{} history entries, {} reboot periods, {} notifications and {} interactions from {}",
+                    if report.state_imported { "the reboot state, " } else { "" },
+                    report.history,
+                    report.periods,
+                    report.notifications,
+                    report.interactions,
+                    file.display()
+                ),
+                Err(e) => {
+                    error!("Failed to import database: {}", e);
+                    return Err(anyhow::anyhow!("Failed to import database: {}", e));
+                }
+            }
+        }
+        Some(Commands::NotifyTest { kind, message, force }) => {
+            if let Err(e) = notify_test(&config, db, &kind, message.as_deref(), force) {
+                error!("Failed to show test notification: {}", e);
+                return Err(anyhow::anyhow!("Failed to show test notification: {}", e));
+            }
+        }
+        Some(Commands::ExportState { output, log_files }) => {
+            match support::export_state(&config, &db, &output, log_files) {
+                Ok(_) => println!("Support bundle written to {}", output.display()),
+                Err(e) => {
+                    error!("Failed to export state: {}", e);
+                    return Err(anyhow::anyhow!("Failed to export state: {}", e));
+                }
+            }
+        }
+        Some(Commands::Report { format, period, sla_days, output }) => {
+            if let Err(e) = print_report(&db, format, period, sla_days, output.as_deref()) {
+                error!("Failed to create report: {}", e);
+                return Err(anyhow::anyhow!("Failed to create report: {}", e));
+            }
+        }
+        Some(Commands::Audit { since, format, output }) => {
+            if let Err(e) = print_audit(&db, since.as_deref(), format, output.as_deref()) {
+                error!("Failed to create audit trail: {}", e);
+                return Err(anyhow::anyhow!("Failed to create audit trail: {}", e));
+            }
+        }
+        Some(Commands::Schema { .. }) | Some(Commands::ProtectSecret { .. }) | Some(Commands::ShowToast { .. })
+        | Some(Commands::ShowUi { .. }) | Some(Commands::ConfirmReboot { .. }) | Some(Commands::CheckAvailability)
+        | Some(Commands::OpenUrl { .. }) | Some(Commands::GenerateAdmx { .. }) => {
+            // Handled before logging is initialized
+        }
+        Some(Commands::ApplyUpdate { .. }) | Some(Commands::Health { .. }) => {
+            // Handled before the configuration is loaded
+        }
         None => {
             // Default to running the service
             info!("No command specified, running service");
@@ -203,6 +701,163 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+/// Print the stored reboot state
+fn print_status(config: &config::Config, db: &database::DbPool, verbose: bool) -> Result<()> {
+    print!("{}", support::status_report(config, db)?);
+
+    if verbose {
+        let path = metrics::snapshot_path(&config.database.path);
+        match metrics::load_snapshot(&path)? {
+            Some(snapshot) => print!("\n{}", snapshot.report()),
+            None => println!("\nNo metrics have been recorded by the service yet"),
+        }
+    }
+    Ok(())
+}
+
+/// Write the reboot compliance report to a file or standard output
+fn print_report(
+    db: &database::DbPool,
+    format: support::report::ReportFormat,
+    period: support::report::ReportPeriod,
+    sla_days: u32,
+    output: Option<&std::path::Path>,
+) -> Result<()> {
+    let stats = support::report::build(db, period, chrono::Duration::days(sla_days as i64), chrono::Utc::now())?;
+    let report = support::report::render(&stats, format)?;
+    match output {
+        Some(path) => {
+            std::fs::write(path, report).map_err(|e| anyhow::anyhow!("Failed to write report to {:?}: {}", path, e))?;
+            println!("Report written to {}", path.display());
+        }
+        None => print!("{}", report),
+    }
+    Ok(())
+}
+
+/// Write the audit trail to a file or standard output
+fn print_audit(
+    db: &database::DbPool,
+    since: Option<&str>,
+    format: support::report::ReportFormat,
+    output: Option<&std::path::Path>,
+) -> Result<()> {
+    let since = since.map(|since| support::audit::parse_since(since, chrono::Utc::now())).transpose()?;
+    let events = support::audit::build(db, since)?;
+    let audit = support::audit::render(&events, format)?;
+    match output {
+        Some(path) => {
+            std::fs::write(path, audit).map_err(|e| anyhow::anyhow!("Failed to write audit trail to {:?}: {}", path, e))?;
+            println!("Audit trail with {} events written to {}", events.len(), path.display());
+        }
+        None => print!("{}", audit),
+    }
+    Ok(())
+}
+
+/// Show a notification through the normal notification pipeline to preview it
+fn notify_test(config: &config::Config, db: database::DbPool, kind: &str, message: Option<&str>, force: bool) -> Result<()> {
+    if message.is_none() && notification::preview_message(&config.notification.messages, kind).is_none() {
+        return Err(anyhow::anyhow!(
+            "Unknown message kind '{}', expected one of: {}",
+            kind,
+            notification::PREVIEW_KINDS.join(", ")
+        ));
+    }
+
+    // Fill the placeholders from the stored state, which is only read
+    let state = database::get_reboot_state(&db)?.unwrap_or_else(|| database::RebootState::new(true, false));
+    let mut context = TemplateContext::new(&state, None, config.reboot.max_deferrals);
+    context.set("minutes_remaining", reboot::DEADLINE_COUNTDOWN_MINUTES.to_string());
+
+    let mut manager = NotificationManager::new(config, db, Arc::new(Impersonator::new()));
+    manager.set_force(force);
+    manager.initialize()?;
+    manager.set_template_context(context);
+
+    let notification_type = format!("test:{}", kind);
+    match message {
+        Some(message) => manager.show_notification(&notification_type, message, None)?,
+        None => manager.show_message(
+            &notification_type,
+            |messages| notification::preview_message(messages, kind).unwrap_or(&messages.reboot_required),
+            None,
+        )?,
+    }
+
+    // Keep the tray icon up long enough to be seen
+    if config.notification.show_tray {
+        std::thread::sleep(std::time::Duration::from_secs(10));
+    }
+
+    Ok(())
+}
+
+/// Show the stale markers of a source and remove them when forced
+fn clear_pending(config: &config::Config, source: reboot::clear::StaleMarker, force: bool) -> Result<()> {
+    let last_boot_time = reboot::detector::RebootDetector::new(&config.reboot).get_last_boot_time()?;
+    let plan = reboot::clear::plan(source, last_boot_time)?;
+
+    print!("{}", plan);
+    if plan.is_empty() {
+        println!("No stale {} markers found", source);
+    } else if force {
+        plan.apply()?;
+        println!("Removed {} stale {} markers", plan.removed.len(), source);
+    } else {
+        println!("Dry run: nothing was changed, run again with --force to remove the lines marked with -");
+    }
+    Ok(())
+}
+
+/// Build the remote host to check, asking for the password when connecting as another user
+fn remote_host(computer: &str, user: Option<&str>) -> Result<reboot::providers::RemoteHost> {
+    let credentials = match user {
+        Some(user) => {
+            let password = match std::env::var("REBOOT_REMINDER_PASSWORD") {
+                Ok(password) => password,
+                Err(_) => read_password(&format!("Password for {}: ", user))?,
+            };
+            Some(reboot::providers::Credentials {
+                user: user.to_string(),
+                password: utils::secrets::Secret::new(&password)?,
+            })
+        }
+        None => None,
+    };
+    Ok(reboot::providers::RemoteHost::new(computer, credentials))
+}
+
+/// Read a password from standard input, without echoing it when typed at a console
+fn read_password(prompt: &str) -> Result<String> {
+    use windows::Win32::System::Console::{
+        GetConsoleMode, GetStdHandle, SetConsoleMode, CONSOLE_MODE, ENABLE_ECHO_INPUT, STD_INPUT_HANDLE,
+    };
+
+    eprint!("{}", prompt);
+    let mut line = String::new();
+    unsafe {
+        let input = GetStdHandle(STD_INPUT_HANDLE)?;
+        let mut mode = CONSOLE_MODE::default();
+        let console = GetConsoleMode(input, &mut mode).is_ok();
+        if console {
+            let _ = SetConsoleMode(input, mode & !ENABLE_ECHO_INPUT);
+        }
+        let result = std::io::stdin().read_line(&mut line);
+        if console {
+            let _ = SetConsoleMode(input, mode);
+            eprintln!();
+        }
+        result.map_err(|e| anyhow::anyhow!("Failed to read password: {}", e))?;
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Parse a timespan command line argument (e.g., "30s", "5m")
+fn parse_duration(value: &str) -> std::result::Result<std::time::Duration, String> {
+    utils::timespan::parse_timespan(value).map_err(|e| e.to_string())
+}
+
 /// Check if the application is running with administrative privileges
 fn is_running_as_admin() -> bool {
     use windows::Win32::UI::Shell::IsUserAnAdmin;
@@ -0,0 +1,259 @@
+//! Counters and timings for diagnosing a slow service
+//!
+//! Values are kept in memory for the lifetime of the process. The service writes a snapshot next
+//! to the database so `status --verbose` can show it from another process.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Upper bounds of the timing histogram buckets in milliseconds; a last bucket counts the rest
+pub const BUCKET_BOUNDS_MS: &[u64] = &[10, 50, 100, 500, 1_000, 5_000, 30_000];
+
+/// Name of the snapshot file written next to the database
+const SNAPSHOT_FILE_NAME: &str = "metrics.json";
+
+/// Metrics of this process
+static REGISTRY: Lazy<Registry> = Lazy::new(Registry::default);
+
+/// Counters and timings by name
+#[derive(Default)]
+struct Registry {
+    counters: Mutex<BTreeMap<String, Arc<AtomicU64>>>,
+    timings: Mutex<BTreeMap<String, Arc<Timing>>>,
+}
+
+/// Distribution of the durations of an operation
+struct Timing {
+    count: AtomicU64,
+    total_ms: AtomicU64,
+    max_ms: AtomicU64,
+    last_ms: AtomicU64,
+    buckets: Vec<AtomicU64>,
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Self {
+            count: AtomicU64::new(0),
+            total_ms: AtomicU64::new(0),
+            max_ms: AtomicU64::new(0),
+            last_ms: AtomicU64::new(0),
+            buckets: (0..=BUCKET_BOUNDS_MS.len()).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+}
+
+impl Registry {
+    fn counter(&self, name: &str) -> Arc<AtomicU64> {
+        let mut counters = self.counters.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        counters.entry(name.to_string()).or_default().clone()
+    }
+
+    fn timing(&self, name: &str) -> Arc<Timing> {
+        let mut timings = self.timings.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        timings.entry(name.to_string()).or_default().clone()
+    }
+}
+
+/// Increase a counter by one
+pub fn increment(name: &str) {
+    REGISTRY.counter(name).fetch_add(1, Ordering::Relaxed);
+}
+
+/// Record how long an operation took
+pub fn record(name: &str, duration: Duration) {
+    let timing = REGISTRY.timing(name);
+    let ms = duration.as_millis() as u64;
+    let bucket = BUCKET_BOUNDS_MS
+        .iter()
+        .position(|bound| ms <= *bound)
+        .unwrap_or(BUCKET_BOUNDS_MS.len());
+
+    timing.count.fetch_add(1, Ordering::Relaxed);
+    timing.total_ms.fetch_add(ms, Ordering::Relaxed);
+    timing.max_ms.fetch_max(ms, Ordering::Relaxed);
+    timing.last_ms.store(ms, Ordering::Relaxed);
+    timing.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+}
+
+/// Run an operation and record how long it took
+pub fn time<T>(name: &str, operation: impl FnOnce() -> T) -> T {
+    let _timer = Timer::start(name);
+    operation()
+}
+
+/// Records the time from its creation until it's dropped
+pub struct Timer {
+    name: String,
+    started: Instant,
+}
+
+impl Timer {
+    /// Start timing an operation
+    pub fn start(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            started: Instant::now(),
+        }
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        record(&self.name, self.started.elapsed());
+    }
+}
+
+/// Point-in-time copy of the metrics
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetricsSnapshot {
+    /// When the snapshot was taken
+    pub taken_at: DateTime<Utc>,
+
+    /// Process ID of the process the metrics belong to
+    pub process_id: u32,
+
+    /// Counter values by name
+    pub counters: BTreeMap<String, u64>,
+
+    /// Timings by name
+    pub timings: BTreeMap<String, TimingSnapshot>,
+}
+
+/// Point-in-time copy of a timing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimingSnapshot {
+    pub count: u64,
+    pub total_ms: u64,
+    pub max_ms: u64,
+    pub last_ms: u64,
+
+    /// Number of durations in each bucket of `BUCKET_BOUNDS_MS`, plus the overflow bucket
+    pub buckets: Vec<u64>,
+}
+
+impl TimingSnapshot {
+    /// Get the mean duration in milliseconds
+    pub fn mean_ms(&self) -> u64 {
+        self.total_ms.checked_div(self.count).unwrap_or(0)
+    }
+}
+
+/// Take a snapshot of the metrics of this process
+pub fn snapshot() -> MetricsSnapshot {
+    let counters = REGISTRY.counters.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let timings = REGISTRY.timings.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    MetricsSnapshot {
+        taken_at: Utc::now(),
+        process_id: std::process::id(),
+        counters: counters
+            .iter()
+            .map(|(name, value)| (name.clone(), value.load(Ordering::Relaxed)))
+            .collect(),
+        timings: timings
+            .iter()
+            .map(|(name, timing)| {
+                (name.clone(), TimingSnapshot {
+                    count: timing.count.load(Ordering::Relaxed),
+                    total_ms: timing.total_ms.load(Ordering::Relaxed),
+                    max_ms: timing.max_ms.load(Ordering::Relaxed),
+                    last_ms: timing.last_ms.load(Ordering::Relaxed),
+                    buckets: timing.buckets.iter().map(|bucket| bucket.load(Ordering::Relaxed)).collect(),
+                })
+            })
+            .collect(),
+    }
+}
+
+/// Get the path of the snapshot file for a database
+pub fn snapshot_path(database_path: &str) -> PathBuf {
+    Path::new(database_path).with_file_name(SNAPSHOT_FILE_NAME)
+}
+
+/// Write a snapshot of the metrics of this process to a file
+pub fn save_snapshot(path: &Path) -> Result<()> {
+    let json = serde_json::to_string_pretty(&snapshot()).context("Failed to serialize metrics")?;
+    std::fs::write(path, json).with_context(|| format!("Failed to write metrics to {:?}", path))
+}
+
+/// Read a snapshot written by the service, if there is one
+pub fn load_snapshot(path: &Path) -> Result<Option<MetricsSnapshot>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let json = std::fs::read_to_string(path).with_context(|| format!("Failed to read metrics from {:?}", path))?;
+    Ok(Some(serde_json::from_str(&json).context("Failed to parse metrics")?))
+}
+
+impl MetricsSnapshot {
+    /// Format the snapshot as a human-readable report
+    pub fn report(&self) -> String {
+        let mut report = format!(
+            "Metrics of process {} at {}\n",
+            self.process_id,
+            crate::reboot::format_time(self.taken_at)
+        );
+
+        if !self.counters.is_empty() {
+            report.push_str("Counters:\n");
+            for (name, value) in &self.counters {
+                report.push_str(&format!("  {:<40} {}\n", name, value));
+            }
+        }
+
+        if !self.timings.is_empty() {
+            report.push_str(&format!(
+                "Timings (ms):\n  {:<40} {:>8} {:>8} {:>8} {:>8}\n",
+                "", "count", "mean", "max", "last"
+            ));
+            for (name, timing) in &self.timings {
+                report.push_str(&format!(
+                    "  {:<40} {:>8} {:>8} {:>8} {:>8}\n",
+                    name,
+                    timing.count,
+                    timing.mean_ms(),
+                    timing.max_ms,
+                    timing.last_ms
+                ));
+            }
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        increment("test.counter");
+        increment("test.counter");
+        record("test.timing", Duration::from_millis(5));
+        record("test.timing", Duration::from_millis(700));
+        record("test.timing", Duration::from_secs(60));
+
+        let snapshot = snapshot();
+        assert_eq!(snapshot.counters["test.counter"], 2);
+
+        let timing = &snapshot.timings["test.timing"];
+        assert_eq!(timing.count, 3);
+        assert_eq!(timing.max_ms, 60_000);
+        assert_eq!(timing.last_ms, 60_000);
+        assert_eq!(timing.buckets[0], 1);
+        assert_eq!(timing.buckets[4], 1);
+        assert_eq!(timing.buckets[BUCKET_BOUNDS_MS.len()], 1);
+        assert!(snapshot.report().contains("test.timing"));
+    }
+}
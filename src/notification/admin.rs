@@ -0,0 +1,170 @@
+use crate::config::{AdminAlertFormat, AdminAlertsConfig};
+use crate::database::{self, DbPool, DeliveryStatus, Notification, RebootState};
+use crate::reboot;
+use crate::utils::http;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use log::{debug, info};
+use serde_json::{json, Value};
+
+/// Notification type alerts about a long pending reboot are recorded under
+pub const PENDING_ALERT_TYPE: &str = "admin_alert:pending";
+
+/// Notification type alerts about failed forced reboots are recorded under
+pub const REBOOT_FAILED_ALERT_TYPE: &str = "admin_alert:reboot_failed";
+
+/// How long the webhook may take to respond
+const WEBHOOK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Alert for administrators about a computer that needs attention
+#[derive(Debug, Clone)]
+pub struct AdminAlert {
+    /// Notification type the alert is recorded under
+    pub alert_type: &'static str,
+
+    /// Headline of the alert
+    pub title: String,
+
+    /// What happened
+    pub text: String,
+
+    /// Name and value pairs describing the computer and the pending reboot
+    pub facts: Vec<(String, String)>,
+}
+
+impl AdminAlert {
+    /// Create an alert about a reboot state, listing the computer, its users and the sources
+    pub fn new(alert_type: &'static str, text: &str, state: &RebootState, users: &[String]) -> Self {
+        let computer_name = std::env::var("COMPUTERNAME").unwrap_or_default();
+        let since = state
+            .reboot_required_since
+            .map(reboot::format_time)
+            .unwrap_or_else(|| "-".to_string());
+        let sources = state
+            .sources
+            .iter()
+            .map(|source| match &source.details {
+                Some(details) => format!("{} ({})", source.name, details),
+                None => source.name.clone(),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        Self {
+            alert_type,
+            title: format!("Reboot pending on {}", computer_name),
+            text: text.to_string(),
+            facts: vec![
+                ("Computer".to_string(), computer_name),
+                ("Users".to_string(), if users.is_empty() { "-".to_string() } else { users.join(", ") }),
+                ("Reboot required since".to_string(), since),
+                ("Sources".to_string(), if sources.is_empty() { "-".to_string() } else { sources }),
+            ],
+        }
+    }
+
+    /// Build the webhook body in the format of the chat service
+    pub fn body(&self, format: AdminAlertFormat) -> Value {
+        match format {
+            AdminAlertFormat::Teams => json!({
+                "@type": "MessageCard",
+                "@context": "https://schema.org/extensions",
+                "summary": self.title,
+                "title": self.title,
+                "text": self.text,
+                "sections": [{
+                    "facts": self.facts.iter().map(|(name, value)| json!({ "name": name, "value": value })).collect::<Vec<_>>(),
+                }],
+            }),
+            AdminAlertFormat::Slack => {
+                let mut text = format!("*{}*\n{}", self.title, self.text);
+                for (name, value) in &self.facts {
+                    text.push_str(&format!("\n*{}:* {}", name, value));
+                }
+                json!({ "text": text })
+            }
+        }
+    }
+}
+
+/// Check if the pending reboot has passed the alert threshold and hasn't been alerted yet
+pub fn pending_alert_due(
+    config: &AdminAlertsConfig,
+    state: &RebootState,
+    last_alert: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> bool {
+    let since = match state.reboot_required_since {
+        Some(since) if state.reboot_required => since,
+        _ => return false,
+    };
+
+    let threshold = crate::utils::timespan::parse_timespan(&config.pending_threshold)
+        .ok()
+        .and_then(|threshold| Duration::from_std(threshold).ok())
+        .unwrap_or_else(|| Duration::days(7));
+
+    now - since >= threshold && last_alert.map_or(true, |last| last < since)
+}
+
+/// Send an alert to the webhook and record it so it isn't sent again
+///
+/// In dry-run mode the alert is only logged and recorded as skipped.
+pub fn send(config: &AdminAlertsConfig, db_pool: &DbPool, alert: &AdminAlert, dry_run: bool) -> Result<()> {
+    let mut notification = Notification::new(alert.alert_type, &alert.text, None);
+
+    if dry_run {
+        info!("[dry-run] Would send admin alert: {}", alert.text);
+        notification.delivery_status = DeliveryStatus::Skipped;
+    } else {
+        info!("Sending admin alert: {}", alert.text);
+        let client = http::client(WEBHOOK_TIMEOUT)?;
+        let response = client
+            .post(config.webhook_url.expose())
+            .json(&alert.body(config.format))
+            .send()
+            .context("Failed to call admin alert webhook")?;
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("Admin alert webhook returned HTTP {}", response.status()));
+        }
+        debug!("Admin alert sent");
+        crate::metrics::increment("admin_alert.sent");
+        notification.delivery_status = DeliveryStatus::Delivered;
+    }
+
+    database::add_notification(db_pool, &notification)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::RebootSource;
+    use crate::utils::secrets::Secret;
+
+    #[test]
+    fn test_pending_alert_due() {
+        let config = AdminAlertsConfig {
+            webhook_url: Secret::new("https://hooks.slack.com/services/T000/B000/XXXX").unwrap(),
+            format: AdminAlertFormat::Slack,
+            pending_threshold: "168h".to_string(),
+            max_failed_reboots: 3,
+        };
+        let now = Utc::now();
+        let mut state = RebootState::new(true, false);
+        state.reboot_required_since = Some(now - Duration::days(8));
+        state.sources.push(RebootSource::new("windows_update", Some("Windows Update"), "required"));
+
+        assert!(pending_alert_due(&config, &state, None, now));
+        assert!(pending_alert_due(&config, &state, Some(now - Duration::days(30)), now));
+        assert!(!pending_alert_due(&config, &state, Some(now - Duration::days(1)), now));
+
+        state.reboot_required_since = Some(now - Duration::days(6));
+        assert!(!pending_alert_due(&config, &state, None, now));
+
+        let alert = AdminAlert::new(PENDING_ALERT_TYPE, "Reboot pending for 8 days", &state, &["alice".to_string()]);
+        let body = alert.body(AdminAlertFormat::Slack);
+        assert!(body["text"].as_str().unwrap().contains("*Users:* alice"));
+        let body = alert.body(AdminAlertFormat::Teams);
+        assert_eq!(body["sections"][0]["facts"][3]["value"], "windows_update");
+    }
+}
@@ -0,0 +1,137 @@
+use log::debug;
+use windows::Win32::Foundation::RECT;
+use windows::Win32::UI::Shell::{
+    SHQueryUserNotificationState, QUNS_BUSY, QUNS_PRESENTATION_MODE, QUNS_QUIET_TIME,
+    QUNS_RUNNING_D3D_FULL_SCREEN,
+};
+use windows::Win32::UI::WindowsAndMessaging::{
+    GetDesktopWindow, GetForegroundWindow, GetShellWindow, GetSystemMetrics, GetWindowRect,
+    SM_CXSCREEN, SM_CYSCREEN,
+};
+
+/// Why the user should not be interrupted right now
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unavailable {
+    /// A full-screen application or game is running
+    FullScreenApplication,
+
+    /// Presentation mode is turned on
+    Presentation,
+
+    /// Focus Assist quiet time is active
+    QuietTime,
+
+    /// The foreground window covers the whole screen
+    FullScreenWindow,
+}
+
+impl Unavailable {
+    /// All the reasons, in the order of their exit codes
+    const ALL: [Self; 4] = [Self::FullScreenApplication, Self::Presentation, Self::QuietTime, Self::FullScreenWindow];
+
+    /// Exit code of the first reason; `check-availability` exits with 0 when the user is available
+    const FIRST_EXIT_CODE: u32 = 10;
+
+    /// Get the description of the reason
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::FullScreenApplication => "A full-screen application is running",
+            Self::Presentation => "Presentation mode is turned on",
+            Self::QuietTime => "Focus Assist quiet time is active",
+            Self::FullScreenWindow => "A full-screen application is in the foreground",
+        }
+    }
+
+    /// Get the exit code `check-availability` reports the reason with
+    pub fn exit_code(&self) -> u32 {
+        Self::FIRST_EXIT_CODE + Self::ALL.iter().position(|reason| reason == self).unwrap_or_default() as u32
+    }
+
+    /// Get the reason reported by the exit code of `check-availability`, if any
+    pub fn from_exit_code(code: u32) -> Option<Self> {
+        let index = code.checked_sub(Self::FIRST_EXIT_CODE)?;
+        Self::ALL.get(index as usize).copied()
+    }
+}
+
+/// Get the reason the user should not be interrupted right now, if any
+///
+/// Checks the shell's notification state (presentation mode, Focus Assist, full-screen
+/// Direct3D applications) and whether the foreground window covers the whole screen. Both only
+/// describe the desktop of this process's session, so the service runs `check-availability`
+/// in the user's session instead.
+pub fn unavailable_reason() -> Option<String> {
+    check().map(|unavailable| unavailable.reason().to_string())
+}
+
+/// Check whether the user of this process's session should not be interrupted right now
+pub fn check() -> Option<Unavailable> {
+    if let Some(unavailable) = notification_state() {
+        return Some(unavailable);
+    }
+
+    if is_foreground_full_screen() {
+        return Some(Unavailable::FullScreenWindow);
+    }
+
+    None
+}
+
+/// Check the shell's user notification state
+fn notification_state() -> Option<Unavailable> {
+    let state = match unsafe { SHQueryUserNotificationState() } {
+        Ok(state) => state,
+        Err(e) => {
+            debug!("Failed to query user notification state: {}", e);
+            return None;
+        }
+    };
+
+    debug!("User notification state: {}", state.0);
+
+    if state == QUNS_BUSY || state == QUNS_RUNNING_D3D_FULL_SCREEN {
+        Some(Unavailable::FullScreenApplication)
+    } else if state == QUNS_PRESENTATION_MODE {
+        Some(Unavailable::Presentation)
+    } else if state == QUNS_QUIET_TIME {
+        Some(Unavailable::QuietTime)
+    } else {
+        None
+    }
+}
+
+/// Check if the foreground window covers the whole primary screen
+fn is_foreground_full_screen() -> bool {
+    unsafe {
+        let hwnd = GetForegroundWindow();
+        if hwnd.is_invalid() || hwnd == GetDesktopWindow() || hwnd == GetShellWindow() {
+            return false;
+        }
+
+        let mut rect = RECT::default();
+        if GetWindowRect(hwnd, &mut rect).is_err() {
+            return false;
+        }
+
+        let screen_width = GetSystemMetrics(SM_CXSCREEN);
+        let screen_height = GetSystemMetrics(SM_CYSCREEN);
+
+        rect.left <= 0 && rect.top <= 0 && rect.right >= screen_width && rect.bottom >= screen_height
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_codes() {
+        for reason in Unavailable::ALL {
+            assert_ne!(reason.exit_code(), 0);
+            assert_eq!(Unavailable::from_exit_code(reason.exit_code()), Some(reason));
+        }
+        assert_eq!(Unavailable::from_exit_code(0), None);
+        assert_eq!(Unavailable::from_exit_code(1), None);
+        assert_eq!(Unavailable::from_exit_code(1460), None);
+    }
+}
@@ -0,0 +1,131 @@
+use crate::utils::{copy_wide, to_wide};
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use std::path::Path;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use windows::core::{w, PCWSTR};
+use windows::Win32::Foundation::{HWND, HWND_MESSAGE};
+use windows::Win32::UI::Shell::{
+    Shell_NotifyIconW, NIF_ICON, NIF_INFO, NIF_TIP, NIIF_INFO, NIIF_WARNING, NIM_ADD, NIM_DELETE,
+    NOTIFYICONDATAW,
+};
+use windows::Win32::UI::WindowsAndMessaging::{
+    CreateWindowExW, DestroyIcon, DestroyWindow, LoadIconW, LoadImageW, HICON, IDI_INFORMATION,
+    IMAGE_ICON, LR_DEFAULTSIZE, LR_LOADFROMFILE, WINDOW_EX_STYLE, WINDOW_STYLE,
+};
+
+/// How long the balloon's notification area icon stays up
+const DISPLAY_TIME: Duration = Duration::from_secs(15);
+
+/// Identifier of the notification area icon within its window
+const ICON_ID: u32 = 1;
+
+/// Show a balloon notification from a temporary notification area icon
+///
+/// Balloons use Shell_NotifyIcon, which works where toasts are disabled by policy. They can only
+/// be shown from a process in the user's session. The icon is added and removed on its own
+/// thread, so this returns once the balloon is up, with the thread that removes the icon again.
+pub fn show(title: &str, message: &str, icon_path: &Path, warning: bool) -> Result<JoinHandle<()>> {
+    let title = title.to_string();
+    let message = message.to_string();
+    let icon_path = icon_path.to_path_buf();
+    let (sender, receiver) = std::sync::mpsc::channel();
+
+    let handle = thread::spawn(move || {
+        let result = unsafe { add_icon(&title, &message, &icon_path, warning) };
+        match result {
+            Ok((window, icon, data)) => {
+                let _ = sender.send(Ok(()));
+                thread::sleep(DISPLAY_TIME);
+                unsafe {
+                    let _ = Shell_NotifyIconW(NIM_DELETE, &data);
+                    if let Some(icon) = icon {
+                        let _ = DestroyIcon(icon);
+                    }
+                    let _ = DestroyWindow(window);
+                }
+                debug!("Balloon notification icon removed");
+            }
+            Err(e) => {
+                let _ = sender.send(Err(e));
+            }
+        }
+    });
+
+    receiver
+        .recv()
+        .context("Balloon notification thread exited unexpectedly")??;
+    Ok(handle)
+}
+
+/// Add the notification area icon with its balloon
+///
+/// Returns the window owning the icon, the icon loaded from the file and the icon data needed to
+/// remove it again.
+unsafe fn add_icon(
+    title: &str,
+    message: &str,
+    icon_path: &Path,
+    warning: bool,
+) -> Result<(HWND, Option<HICON>, NOTIFYICONDATAW)> {
+    // A message-only window is enough to own the icon
+    let window = CreateWindowExW(
+        WINDOW_EX_STYLE::default(),
+        w!("STATIC"),
+        w!("RebootReminderBalloon"),
+        WINDOW_STYLE::default(),
+        0,
+        0,
+        0,
+        0,
+        Some(HWND_MESSAGE),
+        None,
+        None,
+        None,
+    )
+    .context("Failed to create window for balloon notification")?;
+
+    let path = to_wide(&icon_path.to_string_lossy());
+    let loaded = LoadImageW(
+        None,
+        PCWSTR::from_raw(path.as_ptr()),
+        IMAGE_ICON,
+        0,
+        0,
+        LR_LOADFROMFILE | LR_DEFAULTSIZE,
+    )
+    .ok()
+    .map(|handle| HICON(handle.0));
+    if loaded.is_none() {
+        warn!("Failed to load balloon icon {:?}, using the default icon", icon_path);
+    }
+    let icon = match loaded {
+        Some(icon) => icon,
+        None => LoadIconW(None, IDI_INFORMATION).unwrap_or_default(),
+    };
+
+    let mut data = NOTIFYICONDATAW {
+        cbSize: std::mem::size_of::<NOTIFYICONDATAW>() as u32,
+        hWnd: window,
+        uID: ICON_ID,
+        uFlags: NIF_ICON | NIF_TIP | NIF_INFO,
+        hIcon: icon,
+        dwInfoFlags: if warning { NIIF_WARNING } else { NIIF_INFO },
+        ..Default::default()
+    };
+    copy_wide(&mut data.szTip, title);
+    copy_wide(&mut data.szInfoTitle, title);
+    copy_wide(&mut data.szInfo, message);
+
+    if !Shell_NotifyIconW(NIM_ADD, &data).as_bool() {
+        if let Some(icon) = loaded {
+            let _ = DestroyIcon(icon);
+        }
+        let _ = DestroyWindow(window);
+        return Err(anyhow::anyhow!("Failed to add notification area icon for balloon notification"));
+    }
+
+    debug!("Balloon notification shown: {}", title);
+    Ok((window, loaded, data))
+}
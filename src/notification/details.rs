@@ -0,0 +1,153 @@
+use crate::database::{self, DbPool, RebootSource, RebootState};
+use crate::reboot;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, Utc};
+use log::{debug, info};
+use std::path::PathBuf;
+use std::process::Command;
+use uuid::Uuid;
+
+/// Prefix of the names of the generated details pages in the temp directory
+const DETAILS_FILE_PREFIX: &str = "RebootReminder-details-";
+
+/// Get a description of a reboot source a user can understand
+pub fn friendly_description(source: &RebootSource) -> String {
+    match source.name.as_str() {
+        "windows_update" => "Windows Update installed updates that finish installing when the computer restarts.".to_string(),
+        "sccm" => "Software or updates deployed by your IT department need a restart to finish installing.".to_string(),
+        "registry" => "Windows components were updated and are waiting for a restart.".to_string(),
+        "pending_file_operations" => "Files that were in use have been updated and are replaced when the computer restarts.".to_string(),
+        _ => source.description.clone().unwrap_or_else(|| source.name.clone()),
+    }
+}
+
+/// Describe the next thing that will happen about the pending reboot
+pub fn next_action(state: &RebootState) -> String {
+    if !state.reboot_required {
+        return "No restart is needed.".to_string();
+    }
+
+    match (state.scheduled_reboot_time, state.next_reminder_time) {
+        (Some(scheduled), _) => format!("Your computer will restart automatically at {}.", format_local(scheduled)),
+        (None, Some(reminder)) => format!("You will be reminded again at {}.", format_local(reminder)),
+        (None, None) => "You will be reminded again later.".to_string(),
+    }
+}
+
+/// Render the details page for a reboot state
+pub fn render_html(title: &str, state: &RebootState) -> String {
+    let now = Utc::now();
+    let mut rows = String::new();
+    for source in &state.sources {
+        let pending = reboot::format_duration(now.signed_duration_since(source.detected_at));
+        rows.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            escape(&friendly_description(source)),
+            escape(&source.severity),
+            escape(&pending)
+        ));
+    }
+    if rows.is_empty() {
+        rows.push_str("<tr><td colspan=\"3\">No pending changes were found.</td></tr>\n");
+    }
+
+    let since = state
+        .reboot_required_since
+        .map(|since| format!("<p>A restart has been needed since {}.</p>\n", format_local(since)))
+        .unwrap_or_default();
+
+    format!(
+        "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>{title}</title>
+<style>
+body {{ font-family: 'Segoe UI', sans-serif; margin: 2em; color: #222; }}
+table {{ border-collapse: collapse; width: 100%; }}
+th, td {{ text-align: left; padding: 0.5em; border-bottom: 1px solid #ddd; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+<h2>Why does my computer need to restart?</h2>
+{since}<table>
+<tr><th>Reason</th><th>Importance</th><th>Pending for</th></tr>
+{rows}</table>
+<h2>What happens next</h2>
+<p>{next}</p>
+</body>
+</html>
+",
+        title = escape(title),
+        since = since,
+        rows = rows,
+        next = escape(&next_action(state)),
+    )
+}
+
+/// Write the details page for the stored reboot state and open it in the default browser
+pub fn show(title: &str, db_pool: &DbPool) -> Result<PathBuf> {
+    let state = database::get_reboot_state(db_pool)?
+        .unwrap_or_else(|| RebootState::new(false, false));
+    open(&render_html(title, &state))
+}
+
+/// Write a rendered details page to the temp directory and open it in the default browser
+///
+/// Each page gets a new name, so it can't be swapped for another file in a shared temp directory
+/// before it's opened. This runs as the user whose browser opens it.
+pub fn open(html: &str) -> Result<PathBuf> {
+    let path = std::env::temp_dir().join(format!("{}{}.html", DETAILS_FILE_PREFIX, Uuid::new_v4()));
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .and_then(|mut file| std::io::Write::write_all(&mut file, html.as_bytes()))
+        .with_context(|| format!("Failed to write details page to {:?}", path))?;
+    debug!("Details page written to {:?}", path);
+
+    Command::new("cmd")
+        .args(["/C", "start", ""])
+        .arg(&path)
+        .spawn()
+        .context("Failed to open details page")?;
+
+    info!("Opened reboot details page");
+    Ok(path)
+}
+
+/// Format a time in the local time zone
+fn format_local(time: DateTime<Utc>) -> String {
+    time.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string()
+}
+
+/// Escape text for HTML
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_html() {
+        let mut state = RebootState::new(true, false);
+        state.sources = vec![
+            RebootSource::new("windows_update", Some("Windows Update requires a reboot"), "required"),
+            RebootSource::new("custom", Some("<Custom> check"), "recommended"),
+        ];
+
+        let html = render_html("Reboot Reminder", &state);
+        assert!(html.contains("Windows Update installed updates"));
+        assert!(html.contains("&lt;Custom&gt; check"));
+        assert!(html.contains("You will be reminded again"));
+
+        state.scheduled_reboot_time = Some(Utc::now());
+        assert!(next_action(&state).starts_with("Your computer will restart automatically"));
+    }
+}
@@ -0,0 +1,83 @@
+use crate::impersonation::Impersonator;
+use log::debug;
+use std::collections::HashMap;
+use windows::Win32::Globalization::{GetUserDefaultUILanguage, LCIDToLocaleName};
+
+/// Maximum length of a locale name, including the terminating null
+const LOCALE_NAME_MAX_LENGTH: usize = 85;
+
+/// Get the UI language of the current user (e.g., "de-DE")
+pub fn user_ui_language() -> Option<String> {
+    let language_id = unsafe { GetUserDefaultUILanguage() };
+    language_id_to_name(language_id)
+}
+
+/// Get the UI language of the user logged on to a session
+///
+/// When running as a service the session user is impersonated while the language is read;
+/// if that is not possible the language of the current user is used instead.
+pub fn session_ui_language(session_id: &str, running_as_service: bool) -> Option<String> {
+    if !running_as_service {
+        return user_ui_language();
+    }
+
+    Impersonator::new()
+        .with_session_user(session_id, user_ui_language)
+        .unwrap_or_else(|e| {
+            debug!("{:#}", e);
+            user_ui_language()
+        })
+}
+
+/// Convert a Windows language identifier to a locale name
+fn language_id_to_name(language_id: u16) -> Option<String> {
+    let mut buffer = [0u16; LOCALE_NAME_MAX_LENGTH];
+    let len = unsafe { LCIDToLocaleName(language_id as u32, Some(&mut buffer), 0) };
+    if len <= 1 {
+        return None;
+    }
+
+    Some(String::from_utf16_lossy(&buffer[..len as usize - 1]))
+}
+
+/// Find the strings for a language
+///
+/// Tries the exact locale (e.g., "de-DE") first, then the neutral language (e.g., "de").
+/// Locale names are compared case-insensitively.
+pub fn resolve_locale<'a, T>(locales: &'a HashMap<String, T>, language: &str) -> Option<&'a T> {
+    let find = |name: &str| {
+        locales
+            .iter()
+            .find(|(locale, _)| locale.eq_ignore_ascii_case(name))
+            .map(|(_, strings)| strings)
+    };
+
+    find(language).or_else(|| {
+        let neutral = language.split('-').next()?;
+        find(neutral)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::LocaleStrings;
+
+    #[test]
+    fn test_resolve_locale() {
+        let mut locales = HashMap::new();
+        locales.insert("de".to_string(), LocaleStrings {
+            title: Some("Neustart erforderlich".to_string()),
+            ..LocaleStrings::default()
+        });
+        locales.insert("fr-FR".to_string(), LocaleStrings {
+            title: Some("Redémarrage requis".to_string()),
+            ..LocaleStrings::default()
+        });
+
+        assert_eq!(resolve_locale(&locales, "de-AT").and_then(|s| s.title.as_deref()), Some("Neustart erforderlich"));
+        assert_eq!(resolve_locale(&locales, "FR-fr").and_then(|s| s.title.as_deref()), Some("Redémarrage requis"));
+        assert!(resolve_locale(&locales, "fr-CA").is_none());
+        assert!(resolve_locale(&locales, "en-US").is_none());
+    }
+}
@@ -1,17 +1,112 @@
+pub mod admin;
+pub mod availability;
+mod balloon;
+pub mod details;
+pub mod localization;
+pub mod protocol;
+pub mod retry;
+pub mod session_ui;
+pub mod summary;
+pub mod template;
 pub mod toast;
 mod tray;
-
-use crate::config::{Config, NotificationConfig, SystemRebootConfig};
-use crate::database::{DbPool, Notification, NotificationInteraction, UserSession};
-use crate::impersonation::Impersonator;
-use crate::service;
+mod urgent;
+mod window;
+pub mod working_hours;
+
+use crate::config::{Config, DeferralOption, LocaleStrings, MessagesConfig, NotificationConfig, NotificationSeverity, NotificationType, QuietHoursConfig, SystemRebootConfig};
+use crate::database::{DbPool, DeliveryStatus, Notification, NotificationInteraction, RebootState, UserSession};
+use crate::impersonation::{Impersonator, SessionCommand, SessionExit};
+use crate::utils::clock::{self, Clock};
+use crate::utils::schedule::{self, ScheduleZone};
+use session_ui::SessionUi;
 use anyhow::{Context, Result};
-use chrono::{Datelike, NaiveTime, Utc, Weekday};
+use chrono::{DateTime, NaiveTime, Utc};
 use log::{debug, info, warn, error};
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use windows::Win32::UI::WindowsAndMessaging::{
+    IDNO, IDOK, IDTIMEOUT, IDYES, MB_ICONQUESTION, MB_ICONWARNING, MB_OK, MB_SETFOREGROUND, MB_TOPMOST, MB_YESNO,
+    MESSAGEBOX_RESULT,
+};
 // use uuid::Uuid;
 
+#[cfg(feature = "service")]
+use crate::service::is_running_as_service;
+
+/// Without the service there is never a service session to avoid
+#[cfg(not(feature = "service"))]
+fn is_running_as_service() -> bool {
+    false
+}
+
+/// How long a toast shown in a user's session may take before it's given up on
+const TOAST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long checking whether a user is busy may take in their session
+const AVAILABILITY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Action recorded when a user acknowledges a reminder
+pub const ACKNOWLEDGE_ACTION: &str = "acknowledge";
+
+/// Action recorded when a user opens the details of a reminder
+pub const DETAILS_ACTION: &str = "details";
+
+/// Prefix of the actions that snooze reminders until a time of day (e.g., "snooze-until:17:30")
+pub const SNOOZE_UNTIL_ACTION_PREFIX: &str = "snooze-until:";
+
+/// Prefix of the actions that postpone reminders with a deferral option (e.g., "defer:tomorrow")
+pub const DEFER_ACTION_PREFIX: &str = "defer:";
+
+/// Prefix of the actions that postpone reminders for a timespan (e.g., "postpone:4h")
+pub const POSTPONE_ACTION_PREFIX: &str = "postpone:";
+
+/// Kinds of configured message that can be previewed
+pub const PREVIEW_KINDS: &[&str] = &["required", "recommended", "deadline", "upgrade", "countdown", "scheduled", "postponed"];
+
+/// Get the configured message for a preview kind
+pub fn preview_message<'a>(messages: &'a MessagesConfig, kind: &str) -> Option<&'a String> {
+    match kind {
+        "required" => Some(&messages.reboot_required),
+        "recommended" => Some(&messages.reboot_recommended),
+        "deadline" => Some(&messages.reboot_required_deadline),
+        "upgrade" => Some(&messages.reboot_required_upgrade),
+        "countdown" => Some(&messages.reboot_countdown),
+        "scheduled" => Some(&messages.reboot_scheduled),
+        "postponed" => Some(&messages.reboot_postponed),
+        _ => None,
+    }
+}
+
+/// Notification held back until the user becomes available
+#[derive(Debug, Clone)]
+struct PendingNotification {
+    notification_type: String,
+    message: LocalizedMessage,
+    action: Option<String>,
+}
+
+/// A message with its text for each configured locale, so it can be shown in the language of
+/// the session it ends up in
+#[derive(Debug, Clone, Default)]
+struct LocalizedMessage {
+    text: String,
+    translations: HashMap<String, String>,
+}
+
+impl LocalizedMessage {
+    /// A message that is shown as is in every language
+    fn plain(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+            translations: HashMap::new(),
+        }
+    }
+}
+
 /// Notification manager
 pub struct NotificationManager {
     config: NotificationConfig,
@@ -19,6 +114,19 @@ pub struct NotificationManager {
     db_pool: DbPool,
     impersonator: Arc<Impersonator>,
     tray_manager: Option<Arc<Mutex<tray::TrayManager>>>,
+    pending_notification: Mutex<Option<PendingNotification>>,
+    template_context: Mutex<template::TemplateContext>,
+    dry_run: bool,
+    force: bool,
+    working_hours: Vec<Box<dyn working_hours::WorkingHoursProvider>>,
+    affected_users: Mutex<Vec<String>>,
+    severity: Mutex<NotificationSeverity>,
+    toast_style: Mutex<toast::ToastStyle>,
+    deferral_options: Mutex<Vec<DeferralOption>>,
+    max_deferrals: Option<u32>,
+    link_actions: bool,
+    time_zone: ScheduleZone,
+    clock: Arc<dyn Clock>,
 }
 
 impl NotificationManager {
@@ -34,23 +142,77 @@ impl NotificationManager {
             db_pool,
             impersonator,
             tray_manager: None,
+            pending_notification: Mutex::new(None),
+            template_context: Mutex::new(template::TemplateContext::default()),
+            dry_run: config.service.dry_run,
+            force: false,
+            working_hours: config
+                .notification
+                .quiet_hours
+                .working_hours
+                .as_ref()
+                .map(|working_hours| working_hours::providers(working_hours, is_running_as_service()))
+                .unwrap_or_default(),
+            affected_users: Mutex::new(Vec::new()),
+            severity: Mutex::new(NotificationSeverity::default()),
+            toast_style: Mutex::new(toast::ToastStyle::default()),
+            deferral_options: Mutex::new(Vec::new()),
+            max_deferrals: config.reboot.max_deferrals,
+            link_actions: true,
+            time_zone: ScheduleZone::from_config(&config.service),
+            clock: clock::system(),
         }
     }
 
+    /// Take the time from a clock other than the system's, e.g. the test harness's
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Leave out toast buttons, whose `rebootreminder:` links are carried out by the service
+    ///
+    /// Used when checks run from the scheduled task, where no service is listening for them.
+    pub fn disable_link_actions(&mut self) {
+        self.link_actions = false;
+    }
+
+    /// Show notifications even during quiet hours or while the user is busy
+    pub fn set_force(&mut self, force: bool) {
+        self.force = force;
+    }
+
     /// Initialize the notification manager
     pub fn initialize(&mut self) -> Result<()> {
         debug!("Initializing notification manager");
 
         // Initialize tray if needed and not running as a service
-        if self.config.show_tray && !service::is_running_as_service() {
+        if self.dry_run {
+            info!("Dry-run mode, skipping tray initialization");
+        } else if self.config.show_tray && !is_running_as_service() {
             debug!("Initializing tray manager");
             let icon_path = self.resolve_icon_path(&self.config.branding.icon_path)?;
+            let locale = localization::user_ui_language()
+                .and_then(|language| localization::resolve_locale(&self.config.locales, &language));
+            let title = locale
+                .and_then(|locale| locale.title.as_deref())
+                .unwrap_or(&self.config.branding.title);
+            let labels = locale.map(|locale| locale.tray.clone()).unwrap_or_default();
             match tray::TrayManager::new(
-                &self.config.branding.title,
+                title,
                 &icon_path,
                 self.db_pool.clone(),
+                self.clock.clone(),
+                labels,
             ) {
-                Ok(tray_manager) => {
+                Ok(mut tray_manager) => {
+                    let details_title = title.to_string();
+                    let details_pool = self.db_pool.clone();
+                    if let Err(e) = tray_manager.add_details_item(move || {
+                        details::show(&details_title, &details_pool).map(|_| ())
+                    }) {
+                        warn!("Failed to add details item to tray menu: {}", e);
+                    }
+
                     self.tray_manager = Some(Arc::new(Mutex::new(tray_manager)));
                     info!("Tray manager initialized successfully");
                 },
@@ -58,7 +220,7 @@ impl NotificationManager {
                     warn!("Failed to initialize tray manager, continuing without tray: {}", e);
                 }
             }
-        } else if service::is_running_as_service() {
+        } else if is_running_as_service() {
             info!("Running as a service, skipping tray initialization");
         }
 
@@ -72,17 +234,47 @@ impl NotificationManager {
         notification_type: &str,
         message: &str,
         action: Option<&str>,
+    ) -> Result<()> {
+        self.show_localized(notification_type, &LocalizedMessage::plain(message), action)
+    }
+
+    /// Show a notification in the language of the session it is shown in
+    fn show_localized(
+        &self,
+        notification_type: &str,
+        localized: &LocalizedMessage,
+        action: Option<&str>,
     ) -> Result<()> {
         info!("Preparing to show notification: type={}, action={:?}", notification_type, action);
+        let _timer = crate::metrics::Timer::start("notification.show");
+        let message = &self.render(&localized.text);
         info!("Notification message: {}", message);
 
         // Check if we should show notifications (quiet hours)
-        if self.is_quiet_hours() {
+        if !self.force && self.is_quiet_now() {
             info!("Not showing notification during quiet hours");
-            info!("Quiet hours configuration: start={}, end={}, days={:?}",
-                  self.config.quiet_hours.start_time,
-                  self.config.quiet_hours.end_time,
-                  self.config.quiet_hours.days_of_week);
+            return Ok(());
+        }
+
+        // Hold the notification back if the user is presenting or otherwise busy
+        if self.config.respect_user_availability && !self.force {
+            if let Some(reason) = self.unavailable_reason() {
+                info!("Holding notification until the user is available: {}", reason);
+                self.hold_back(notification_type, localized, action);
+                return Ok(());
+            }
+        }
+
+        // Record what would have been shown without showing anything in dry-run mode
+        if self.dry_run {
+            let mut notification = Notification::new(&format!("dry_run:{}", notification_type), message, None);
+            notification.action = action.map(|a| a.to_string());
+            notification.delivery_status = DeliveryStatus::Skipped;
+            info!("[dry-run] Would show notification: type={}, action={:?}, message={}",
+                  notification_type, action, message);
+            if let Err(e) = crate::database::add_notification(&self.db_pool, &notification) {
+                warn!("Failed to save dry-run notification to database: {}", e);
+            }
             return Ok(());
         }
 
@@ -93,6 +285,54 @@ impl NotificationManager {
             return Ok(());
         }
 
+        // Never show notifications in the sessions of suppressed accounts
+        let (suppressed, sessions): (Vec<UserSession>, Vec<UserSession>) = sessions
+            .into_iter()
+            .partition(|session| is_suppressed_account(&self.config.suppressed_accounts, &session.user_name));
+        for session in &suppressed {
+            info!("Suppressing notification in session {} of account {}", session.session_id, crate::logging::user(&session.user_name));
+            crate::metrics::increment("notification.suppressed");
+            let message = self.text_for(localized, session);
+            let mut notification = Notification::new(notification_type, &message, Some(&session.user_name));
+            notification.action = action.map(|a| a.to_string());
+            notification.session_id = Some(session.session_id.clone());
+            notification.suppression_reason = Some(format!("Account {} is suppressed", session.user_name));
+            notification.delivery_status = DeliveryStatus::Skipped;
+            if let Err(e) = crate::database::add_notification(&self.db_pool, &notification) {
+                warn!("Failed to save suppressed notification to database: {}", e);
+            }
+        }
+        if sessions.is_empty() {
+            info!("All active sessions belong to suppressed accounts, not showing notification");
+            return Ok(());
+        }
+
+        // Only notify the users a per-user reboot applies to
+        let affected_users = self.affected_users.lock().map(|users| users.clone()).unwrap_or_default();
+        let sessions: Vec<UserSession> = if affected_users.is_empty() {
+            sessions
+        } else {
+            sessions
+                .into_iter()
+                .filter(|session| affected_users.iter().any(|user| user.eq_ignore_ascii_case(&session.user_name)))
+                .collect()
+        };
+        if sessions.is_empty() {
+            info!("None of the affected users {:?} has an active session, not showing notification", affected_users);
+            return Ok(());
+        }
+
+        // Hold the notification back until a user is at the keyboard
+        if let Some(max_idle) = self.config.max_idle_time.as_deref().and_then(|idle| crate::utils::timespan::parse_timespan(idle).ok()) {
+            if !self.force {
+                if let Some(idle) = self.impersonator.shortest_idle_time(&sessions).filter(|idle| *idle > max_idle) {
+                    info!("Holding notification until a user is active, idle for {:?}", idle);
+                    self.hold_back(notification_type, localized, action);
+                    return Ok(());
+                }
+            }
+        }
+
         info!("Found {} active user sessions", sessions.len());
         for (i, session) in sessions.iter().enumerate() {
             info!("Session {}: user={}, id={}, type={}",
@@ -102,6 +342,18 @@ impl NotificationManager {
                   if session.is_console { "console" } else if session.is_rdp { "rdp" } else { "other" });
         }
 
+        // Show the message in the language of the session it is shown in
+        let message = &self.text_for(localized, &sessions[0]);
+
+        // Don't show the same notification again right away, e.g. when the service keeps restarting
+        if !self.force {
+            if let Some(original) = self.recent_duplicate(notification_type, message, &sessions[0]) {
+                info!("Not showing notification again, the same one was shown at {} ({})", original.timestamp, original.id);
+                crate::metrics::increment("notification.duplicate");
+                return Ok(());
+            }
+        }
+
         // Create notification record
         let notification = Notification::new(
             notification_type,
@@ -116,6 +368,7 @@ impl NotificationManager {
 
         // Set action if provided
         let mut notification = notification;
+        notification.session_id = Some(sessions[0].session_id.clone());
         if let Some(action_str) = action {
             notification.action = Some(action_str.to_string());
             info!("Added action to notification: {}", action_str);
@@ -131,24 +384,389 @@ impl NotificationManager {
             }
         };
 
-        // Show notifications based on configuration
+        // Show the notification, scheduling a retry if it can't be delivered
+        if !self.attempt_delivery(&mut notification, &sessions[0]) {
+            return Ok(());
+        }
+
+        crate::metrics::increment("notification.shown");
+        info!("Notification successfully shown to user: {}", crate::logging::user(&sessions[0].user_name));
+        info!("Notification content: {}", message);
+        Ok(())
+    }
+
+    /// Show a saved notification and record the outcome of the attempt
+    ///
+    /// Returns whether the notification was delivered.
+    fn attempt_delivery(&self, notification: &mut Notification, session: &UserSession) -> bool {
+        let delivered = match self.deliver(notification, session) {
+            Ok(()) => {
+                retry::record_success(notification);
+                true
+            }
+            Err(e) => {
+                warn!("Failed to deliver notification {}: {:#}", notification.id, e);
+                crate::metrics::increment("notification.delivery_failed");
+                let error = format!("{:#}", e);
+                if retry::record_failure(&self.config.delivery_retry, notification, &error, self.clock.now()) {
+                    crate::metrics::increment("notification.abandoned");
+                }
+                false
+            }
+        };
+
+        if let Err(e) = crate::database::update_notification_delivery(&self.db_pool, notification) {
+            warn!("Failed to save delivery state of notification {}: {}", notification.id, e);
+        }
+
+        delivered
+    }
+
+    /// Show a notification in a session using the configured notification types
+    ///
+    /// The tray only shows the message in its tooltip, so a tray failure only fails the
+    /// delivery when toasts and balloons are disabled. A balloon is shown in place of a toast
+    /// that fails, for example because toasts are disabled by policy. Critical notifications
+    /// are shown in a window on top of all others instead, falling back to a toast. When nothing
+    /// else can be shown, a message box is sent to the session. Reboot reminders that must be
+    /// acknowledged are shown in a dialog the user confirms.
+    fn deliver(&self, notification: &Notification, session: &UserSession) -> Result<()> {
+        if self.config.acknowledgment.required && notification.notification_type == "reboot_required" {
+            return self.request_acknowledgment(notification, session);
+        }
+
+        if self.config.notification_type == Some(NotificationType::MessageBox) {
+            return self.show_message_box(notification, session);
+        }
+
+        if self.severity() == NotificationSeverity::Critical {
+            match self.show_urgent_notification(session, notification) {
+                Ok(()) => return Ok(()),
+                Err(e) => warn!("Failed to show critical notification window, falling back to a toast: {}", e),
+            }
+        }
+
         if self.config.show_tray {
-            if let Err(e) = self.show_tray_notification(&notification, &sessions[0]) {
+            if let Err(e) = self.show_tray_notification(notification, session) {
+                if !self.config.show_toast && !self.config.show_balloon {
+                    return self.fall_back_to_message_box(notification, session, e.context("Failed to show tray notification"));
+                }
                 warn!("Failed to show tray notification: {}", e);
             }
         }
 
+        let mut shown = false;
+        let mut error = None;
+
         if self.config.show_toast {
-            if let Err(e) = self.show_toast_notification(&notification, &sessions[0]) {
-                warn!("Failed to show toast notification: {}", e);
+            match self.show_toast_notification(notification, session) {
+                Ok(()) => shown = true,
+                Err(e) => {
+                    warn!("Failed to show toast notification, falling back to a balloon: {}", e);
+                    error = Some(e.context("Failed to show toast notification"));
+                }
+            }
+        }
+
+        if self.config.show_balloon || error.is_some() {
+            match self.show_balloon_notification(notification, session) {
+                Ok(()) => shown = true,
+                Err(e) => {
+                    warn!("Failed to show balloon notification: {}", e);
+                    error.get_or_insert(e.context("Failed to show balloon notification"));
+                }
             }
         }
 
-        // Balloon notifications are handled by the tray manager
-        // and are currently not implemented separately
+        match error {
+            Some(e) if !shown => self.fall_back_to_message_box(notification, session, e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Show a message box after the configured notification types failed
+    ///
+    /// Returns the original error if the message box can't be shown either.
+    fn fall_back_to_message_box(&self, notification: &Notification, session: &UserSession, error: anyhow::Error) -> Result<()> {
+        warn!("{:#}, falling back to a message box", error);
+        self.show_message_box(notification, session).map_err(|e| {
+            warn!("Failed to show message box: {:#}", e);
+            error
+        })
+    }
+
+    /// Retry the deliveries of notifications whose next attempt is due
+    ///
+    /// Each notification is shown in the session of the user it was meant for, or in the first
+    /// active session if that user has signed out.
+    pub fn retry_failed_deliveries(&self) -> Result<()> {
+        let due = crate::database::get_due_deliveries(&self.db_pool, self.clock.now())?;
+        if due.is_empty() {
+            return Ok(());
+        }
+
+        if !self.force && self.is_quiet_now() {
+            debug!("Not retrying {} notification deliveries during quiet hours", due.len());
+            return Ok(());
+        }
+
+        let sessions: Vec<UserSession> = self
+            .impersonator
+            .get_active_sessions()?
+            .into_iter()
+            .filter(|session| !is_suppressed_account(&self.config.suppressed_accounts, &session.user_name))
+            .collect();
+        if sessions.is_empty() {
+            debug!("No interactive sessions, not retrying {} notification deliveries", due.len());
+            return Ok(());
+        }
+
+        info!("Retrying delivery of {} notifications", due.len());
+        for mut notification in due {
+            let session = sessions
+                .iter()
+                .find(|session| notification.user_name.as_deref() == Some(session.user_name.as_str()))
+                .unwrap_or(&sessions[0]);
+            if self.attempt_delivery(&mut notification, session) {
+                crate::metrics::increment("notification.shown");
+                info!("Delivered notification {} on attempt {}", notification.id, notification.delivery_attempts);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the names of the users with an active session
+    pub fn active_user_names(&self) -> Vec<String> {
+        match self.impersonator.get_active_sessions() {
+            Ok(sessions) => sessions.into_iter().map(|session| session.user_name).collect(),
+            Err(e) => {
+                debug!("Failed to get active sessions: {}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Show one of the configured messages in the language of the user it is shown to
+    pub fn show_message<F>(&self, notification_type: &str, select: F, action: Option<&str>) -> Result<()>
+    where
+        F: Fn(&MessagesConfig) -> &String,
+    {
+        let translations = self
+            .config
+            .locales
+            .iter()
+            .map(|(locale, strings)| (locale.clone(), select(&self.config.messages.localized(&strings.messages)).clone()))
+            .collect();
+        let message = LocalizedMessage {
+            text: select(&self.config.messages).clone(),
+            translations,
+        };
+
+        self.show_localized(notification_type, &message, action)
+    }
+
+    /// Switch the tray icon and tooltip to match a reboot state, and rebuild the tray menu for it
+    pub fn update_tray_state(&self, state: &RebootState, deadline: Option<DateTime<Utc>>) -> Result<()> {
+        let tray_manager = match &self.tray_manager {
+            Some(tray_manager) => tray_manager,
+            None => return Ok(()),
+        };
+
+        let tray_state = tray::TrayState::from_reboot_state(state, deadline, self.clock.now());
+        let icons = &self.config.branding.state_icons;
+        let icon = match tray_state {
+            tray::TrayState::NoReboot => icons.no_reboot.as_deref(),
+            tray::TrayState::RebootRecommended => icons.reboot_recommended.as_deref(),
+            tray::TrayState::RebootRequired => icons.reboot_required.as_deref(),
+            tray::TrayState::RebootImminent => icons.reboot_imminent.as_deref(),
+        };
+        let icon_path = self.resolve_icon_path(icon.unwrap_or(&self.config.branding.icon_path))?;
+
+        let mut tray = tray_manager
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire lock on tray manager: {}", e))?;
+        tray.set_state(tray_state, &icon_path)?;
+        tray.rebuild_menu(state)
+    }
+
+    /// Set how prominently the following notifications are shown
+    pub fn set_severity(&self, severity: NotificationSeverity) {
+        match self.severity.lock() {
+            Ok(mut current) => *current = severity,
+            Err(e) => warn!("Failed to acquire lock on notification severity: {}", e),
+        }
+    }
+
+    /// Get how prominently notifications are shown
+    pub(crate) fn severity(&self) -> NotificationSeverity {
+        self.severity.lock().map(|severity| *severity).unwrap_or_default()
+    }
+
+    /// Set the sound and priority of the toasts shown next, from the current timeframe
+    pub fn set_toast_style(&self, style: toast::ToastStyle) {
+        match self.toast_style.lock() {
+            Ok(mut current) => *current = style,
+            Err(e) => warn!("Failed to acquire lock on toast style: {}", e),
+        }
+    }
+
+    /// Set the users whose sessions are notified; an empty list notifies every session
+    pub fn set_affected_users(&self, users: Vec<String>) {
+        match self.affected_users.lock() {
+            Ok(mut current) => *current = users,
+            Err(e) => warn!("Failed to acquire lock on affected users: {}", e),
+        }
+    }
+
+    /// Set the values used for the placeholders in messages
+    pub fn set_template_context(&self, context: template::TemplateContext) {
+        match self.template_context.lock() {
+            Ok(mut current) => *current = context,
+            Err(e) => warn!("Failed to acquire lock on template context: {}", e),
+        }
+    }
+
+    /// Replace the placeholders in a message with the current values
+    fn render(&self, message: &str) -> String {
+        match self.template_context.lock() {
+            Ok(context) => template::render(message, &context),
+            Err(_) => message.to_string(),
+        }
+    }
+
+    /// Render the branding subtitle for a session, if one is configured
+    ///
+    /// Besides the message placeholders, the subtitle can contain `{asset_tag}` and `{user_name}`.
+    fn subtitle(&self, session: &UserSession) -> Option<String> {
+        let branding = &self.config.branding;
+        let subtitle_template = branding.subtitle_template.as_deref()?;
+
+        let mut context = self.template_context.lock().map(|context| context.clone()).unwrap_or_default();
+        if context.get("computer_name").is_none() {
+            context.set("computer_name", std::env::var("COMPUTERNAME").unwrap_or_default());
+        }
+        context.set("asset_tag", template::read_asset_tag(branding).unwrap_or_default());
+        context.set("user_name", session.user_name.clone());
+
+        Some(template::render(subtitle_template, &context))
+    }
+
+    /// Get the strings for the UI language of a session's user, if any are configured
+    fn locale_for(&self, session: &UserSession) -> Option<&LocaleStrings> {
+        if self.config.locales.is_empty() {
+            return None;
+        }
+
+        let language = self.session_language(session)?;
+        localization::resolve_locale(&self.config.locales, &language)
+    }
+
+    /// Get the UI language of a session's user
+    fn session_language(&self, session: &UserSession) -> Option<String> {
+        let language = localization::session_ui_language(&session.session_id, is_running_as_service())?;
+        debug!("UI language of session {}: {}", session.session_id, language);
+        Some(language)
+    }
+
+    /// Render a message in the UI language of a session's user
+    fn text_for(&self, message: &LocalizedMessage, session: &UserSession) -> String {
+        if message.translations.is_empty() {
+            return self.render(&message.text);
+        }
+
+        let text = self
+            .session_language(session)
+            .and_then(|language| localization::resolve_locale(&message.translations, &language))
+            .unwrap_or(&message.text);
+        self.render(text)
+    }
+
+    /// Find the same notification shown in a session within the duplicate interval
+    fn recent_duplicate(&self, notification_type: &str, message: &str, session: &UserSession) -> Option<Notification> {
+        let interval = self
+            .config
+            .duplicate_interval
+            .as_deref()
+            .and_then(|interval| crate::utils::timespan::parse_timespan(interval).ok())?;
+        let since = self.clock.now() - chrono::Duration::from_std(interval).ok()?;
+
+        match crate::database::get_recent_duplicate(&self.db_pool, notification_type, message, &session.session_id, since) {
+            Ok(duplicate) => duplicate,
+            Err(e) => {
+                warn!("Failed to look for duplicate notifications: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Get the reason the signed-in users should not be interrupted right now, if any
+    ///
+    /// The service can't see the users' desktops from session 0, so it runs the check in each
+    /// active session and holds notifications back while any user is busy.
+    fn unavailable_reason(&self) -> Option<String> {
+        if !is_running_as_service() {
+            return availability::unavailable_reason();
+        }
+
+        let sessions = match self.impersonator.get_active_sessions() {
+            Ok(sessions) => sessions,
+            Err(e) => {
+                warn!("Failed to get sessions to check the users' availability: {}", e);
+                return None;
+            }
+        };
+        let command = match SessionCommand::this_program(["check-availability"]) {
+            Ok(command) => command.timeout(AVAILABILITY_TIMEOUT),
+            Err(e) => {
+                warn!("Failed to check the users' availability: {}", e);
+                return None;
+            }
+        };
+
+        sessions.iter().find_map(|session| {
+            match self.impersonator.run_in_session(&session.session_id, &command) {
+                Ok(SessionExit::Exited(code)) => availability::Unavailable::from_exit_code(code)
+                    .map(|unavailable| format!("{} in session {}", unavailable.reason(), session.session_id)),
+                Ok(exit) => {
+                    debug!("Availability check in session {} ended with {:?}", session.session_id, exit);
+                    None
+                }
+                Err(e) => {
+                    debug!("Failed to check availability in session {}: {}", session.session_id, e);
+                    None
+                }
+            }
+        })
+    }
+
+    /// Keep a notification to deliver once the user is available
+    fn hold_back(&self, notification_type: &str, message: &LocalizedMessage, action: Option<&str>) {
+        if let Ok(mut pending) = self.pending_notification.lock() {
+            *pending = Some(PendingNotification {
+                notification_type: notification_type.to_string(),
+                message: message.clone(),
+                action: action.map(|a| a.to_string()),
+            });
+        }
+    }
+
+    /// Deliver a notification that was held back, if the user has become available
+    pub fn deliver_pending_notification(&self) -> Result<()> {
+        let pending = match self.pending_notification.lock() {
+            Ok(mut pending) => pending.take(),
+            Err(e) => {
+                warn!("Failed to acquire lock on pending notification: {}", e);
+                return Ok(());
+            }
+        };
+
+        if let Some(pending) = pending {
+            debug!("Retrying held back notification: type={}", pending.notification_type);
+            // show_notification holds it back again if the user is still unavailable
+            self.show_localized(&pending.notification_type, &pending.message, pending.action.as_deref())?;
+        }
 
-        info!("Notification successfully shown to user: {}", sessions[0].user_name);
-        info!("Notification content: {}", message);
         Ok(())
     }
 
@@ -182,15 +800,252 @@ impl NotificationManager {
 
         // Create toast notification
         let icon_path = self.resolve_icon_path(&self.config.branding.icon_path)?;
-        let toast = toast::ToastNotification::new_with_icon(
-            &self.config.branding.title,
+        let title = self
+            .locale_for(session)
+            .and_then(|locale| locale.title.as_deref())
+            .unwrap_or(&self.config.branding.title);
+        let mut toast = toast::ToastNotification::new_with_icon(
+            title,
             &notification.message,
             &icon_path,
             notification.id.clone(),
         );
+        toast.severity = self.severity();
+        toast.style = self.toast_style.lock().map(|style| *style).unwrap_or_default();
+        toast.subtitle = self.subtitle(session);
+
+        // Buttons open rebootreminder: links, which hand the action to the service
+        if self.link_actions {
+            if notification.action.as_deref().is_some_and(|action| action.starts_with("reboot:")) {
+                if let Some(uri) = protocol::uri("reboot:now", Some(notification.id)) {
+                    toast.buttons.push(toast::ToastButton { label: "Restart now".to_string(), uri });
+                }
+            }
+            if let Ok(options) = self.deferral_options.lock() {
+                for option in options.iter() {
+                    let action = format!("{}{}", DEFER_ACTION_PREFIX, option.id());
+                    if let Some(uri) = protocol::uri(&action, Some(notification.id)) {
+                        toast.buttons.push(toast::ToastButton { label: option.label().to_string(), uri });
+                    }
+                }
+            }
+            if let Some(uri) = protocol::uri(DETAILS_ACTION, Some(notification.id)) {
+                toast.buttons.push(toast::ToastButton { label: "Details".to_string(), uri });
+            }
+        }
+
+        if !is_running_as_service() {
+            return toast.show();
+        }
 
-        // Show notification using impersonation
-        self.impersonator.show_toast_notification(session, &toast.message)
+        // Toasts from session 0 never reach the user, so a copy of this program shows it as the user
+        debug!("Showing toast notification to user {}", crate::logging::user(&session.user_name));
+        let payload = serde_json::to_string(&toast).context("Failed to serialize toast notification")?;
+        let command = SessionCommand::this_program(["show-toast", &payload])?.timeout(TOAST_TIMEOUT);
+        match self.impersonator.run_in_session(&session.session_id, &command)? {
+            SessionExit::Started | SessionExit::Exited(0) => Ok(()),
+            exit => Err(anyhow::anyhow!("Failed to show toast notification in session {}: {:?}", session.session_id, exit)),
+        }
+    }
+
+    /// Show a balloon notification
+    ///
+    /// Balloons come from a notification area icon, so from the service they're shown by a copy
+    /// of this program in the user's session.
+    fn show_balloon_notification(
+        &self,
+        notification: &Notification,
+        session: &UserSession,
+    ) -> Result<()> {
+        debug!("Showing balloon notification");
+
+        let title = self
+            .locale_for(session)
+            .and_then(|locale| locale.title.as_deref())
+            .unwrap_or(&self.config.branding.title);
+        let ui = SessionUi::Balloon {
+            title: title.to_string(),
+            message: notification.message.clone(),
+            icon_path: self.resolve_icon_path(&self.config.branding.icon_path)?,
+            warning: notification.notification_type.starts_with("reboot_required"),
+        };
+        self.show_session_ui(session, &ui)
+    }
+
+    /// Show a critical notification in a window on top of all others
+    ///
+    /// Like balloons, the window is shown from the user's session, where the monitors and their
+    /// DPI are the ones the user sees.
+    fn show_urgent_notification(&self, session: &UserSession, notification: &Notification) -> Result<()> {
+        debug!("Showing critical notification window");
+
+        let title = self
+            .locale_for(session)
+            .and_then(|locale| locale.title.as_deref())
+            .unwrap_or(&self.config.branding.title);
+
+        let message = match self.subtitle(session) {
+            Some(subtitle) => format!("{}\n\n{}", notification.message, subtitle),
+            None => notification.message.clone(),
+        };
+        let ui = SessionUi::Urgent {
+            title: title.to_string(),
+            message,
+            placement: self.config.window_placement,
+        };
+        self.show_session_ui(session, &ui)
+    }
+
+    /// Show notification UI, from a copy of this program in the user's session when running as
+    /// the service
+    ///
+    /// The copy keeps the UI up until it's closed, so it's started without waiting for it.
+    fn show_session_ui(&self, session: &UserSession, ui: &SessionUi) -> Result<()> {
+        if !is_running_as_service() {
+            return ui.show();
+        }
+
+        debug!("Showing notification UI to user {}", crate::logging::user(&session.user_name));
+        let payload = serde_json::to_string(ui).context("Failed to serialize notification UI")?;
+        let command = SessionCommand::this_program(["show-ui", &payload])?;
+        self.impersonator.run_in_session(&session.session_id, &command)?;
+        Ok(())
+    }
+
+    /// Show a notification in a message box on the desktop of a session
+    ///
+    /// The box is sent with WTSSendMessageW, so it also reaches the user from the service session
+    /// on locked-down desktops where toasts and the tray don't work. When the response is captured,
+    /// the user is asked whether to restart now, and the answer is recorded as an interaction
+    /// once the box closes.
+    fn show_message_box(&self, notification: &Notification, session: &UserSession) -> Result<()> {
+        debug!("Showing message box in session {}", session.session_id);
+
+        let settings = &self.config.message_box;
+        let title = self
+            .locale_for(session)
+            .and_then(|locale| locale.title.as_deref())
+            .unwrap_or(&self.config.branding.title)
+            .to_string();
+        let timeout = settings
+            .timeout
+            .as_deref()
+            .and_then(|timeout| crate::utils::timespan::parse_timespan(timeout).ok())
+            .unwrap_or_default();
+
+        if !settings.capture_response || !self.system_reboot_config.enabled {
+            self.impersonator.send_message(
+                &session.session_id,
+                &title,
+                &notification.message,
+                MB_OK | MB_ICONWARNING | MB_TOPMOST | MB_SETFOREGROUND,
+                timeout,
+                false,
+            )?;
+            return Ok(());
+        }
+
+        // Wait for the answer on another thread, so an unanswered box doesn't hold up the service
+        let message = format!("{}\n\n{}", notification.message, settings.restart_prompt);
+        let notification_id = notification.id;
+        let session = session.clone();
+        let db_pool = self.db_pool.clone();
+        let clock = self.clock.clone();
+        let mut reboot_config = crate::reboot::system::RebootConfig::from(&self.system_reboot_config);
+        reboot_config.dry_run = self.dry_run;
+        reboot_config.show_confirmation = false;
+
+        thread::Builder::new()
+            .name("message-box".to_string())
+            .spawn(move || {
+                let answer = Impersonator::new().send_message(
+                    &session.session_id,
+                    &title,
+                    &message,
+                    MB_YESNO | MB_ICONQUESTION | MB_TOPMOST | MB_SETFOREGROUND,
+                    timeout,
+                    true,
+                );
+                if let Err(e) = answer.and_then(|answer| handle_message_box_answer(&db_pool, notification_id, &session, answer, &reboot_config, clock.now())) {
+                    warn!("Failed to handle message box answer: {:#}", e);
+                }
+            })
+            .context("Failed to start a thread for the message box")?;
+
+        Ok(())
+    }
+
+    /// Show a reminder in a message box the user confirms with OK
+    ///
+    /// The answer is waited for on another thread and recorded as an acknowledgment of the
+    /// notification. Unless a message box timeout is set, the box closes when the reminder is
+    /// shown again, so unanswered boxes don't pile up.
+    fn request_acknowledgment(&self, notification: &Notification, session: &UserSession) -> Result<()> {
+        debug!("Asking for acknowledgment of notification {} in session {}", notification.id, session.session_id);
+
+        let settings = &self.config.acknowledgment;
+        let title = self
+            .locale_for(session)
+            .and_then(|locale| locale.title.as_deref())
+            .unwrap_or(&self.config.branding.title)
+            .to_string();
+        let timeout = self
+            .config
+            .message_box
+            .timeout
+            .as_deref()
+            .unwrap_or(&settings.reprompt_interval);
+        let timeout = crate::utils::timespan::parse_timespan(timeout).unwrap_or_default();
+        let message = format!("{}\n\n{}", notification.message, settings.prompt);
+        let notification_id = notification.id;
+        let session = session.clone();
+        let db_pool = self.db_pool.clone();
+
+        thread::Builder::new()
+            .name("acknowledgment".to_string())
+            .spawn(move || {
+                let answer = Impersonator::new().send_message(
+                    &session.session_id,
+                    &title,
+                    &message,
+                    MB_OK | MB_ICONWARNING | MB_TOPMOST | MB_SETFOREGROUND,
+                    timeout,
+                    true,
+                );
+                if let Err(e) = answer.and_then(|answer| handle_acknowledgment_answer(&db_pool, notification_id, &session, answer)) {
+                    warn!("Failed to handle acknowledgment answer: {:#}", e);
+                }
+            })
+            .context("Failed to start a thread for the acknowledgment dialog")?;
+
+        Ok(())
+    }
+
+    /// Show the last reboot reminder again if it hasn't been acknowledged within the reprompt interval
+    pub fn reprompt_unacknowledged(&self) -> Result<()> {
+        let settings = &self.config.acknowledgment;
+        if !settings.required {
+            return Ok(());
+        }
+
+        let Some(last) = crate::database::get_last_notification(&self.db_pool, "reboot_required")? else {
+            return Ok(());
+        };
+        // Failed deliveries are retried on their own schedule
+        if last.delivery_status != DeliveryStatus::Delivered
+            || crate::database::is_notification_acknowledged(&self.db_pool, last.id)?
+        {
+            return Ok(());
+        }
+
+        let interval = crate::utils::timespan::parse_timespan(&settings.reprompt_interval)?;
+        if self.clock.now() - last.timestamp < chrono::Duration::from_std(interval)? {
+            return Ok(());
+        }
+
+        info!("Reboot reminder {} hasn't been acknowledged since {}, showing it again", last.id, last.timestamp);
+        crate::metrics::increment("notification.reprompted");
+        self.show_notification("reboot_required", &last.message, last.action.as_deref())
     }
 
     /// Record a notification interaction
@@ -201,7 +1056,7 @@ impl NotificationManager {
         session: &UserSession,
     ) -> Result<()> {
         info!("Recording notification interaction: {} - {}", notification_id, action);
-        info!("User: {}, Session: {}", session.user_name, session.session_id);
+        info!("User: {}, Session: {}", crate::logging::user(&session.user_name), session.session_id);
 
         // Create interaction record
         let mut interaction = NotificationInteraction::new(notification_id, action);
@@ -230,18 +1085,120 @@ impl NotificationManager {
             return Ok(());
         }
 
+        // Check if this is one of the deferral options
+        if let Some(id) = action.strip_prefix(DEFER_ACTION_PREFIX) {
+            let option = self
+                .deferral_options
+                .lock()
+                .ok()
+                .and_then(|options| options.iter().find(|option| option.id() == id).cloned())
+                .ok_or_else(|| anyhow::anyhow!("Unknown deferral option: {}", id))?;
+            let next_reminder_time = crate::reboot::deferral_end(&option, self.clock.now())?;
+            interaction.deferral_id = Some(option.id().to_string());
+            interaction.details = Some(format!("Reminders postponed until {} ({}) by user {}",
+                                               next_reminder_time, option.label(), session.user_name));
+            self.postpone(next_reminder_time, &interaction)?;
+
+            info!("Notification interaction recorded: {} - {}", notification_id, action);
+            return Ok(());
+        }
+
+        // Check if this is a postponement for a timespan
+        if let Some(duration) = action.strip_prefix(POSTPONE_ACTION_PREFIX) {
+            let next_reminder_time = self.postpone_end(duration)?;
+            interaction.details = Some(format!("Reminders postponed until {} ({}) by user {}",
+                                               next_reminder_time, duration, session.user_name));
+            self.postpone(next_reminder_time, &interaction)?;
+
+            info!("Notification interaction recorded: {} - {}", notification_id, action);
+            return Ok(());
+        }
+
+        // Check if this is a snooze until a time of day
+        if let Some(time) = action.strip_prefix(SNOOZE_UNTIL_ACTION_PREFIX) {
+            let next_reminder_time = crate::reboot::next_time_of_day(time, self.clock.now())?;
+            info!("Snoozing reminders until {}", next_reminder_time);
+            interaction.details = Some(format!("Reminders snoozed until {} by user {}",
+                                               next_reminder_time, session.user_name));
+            self.postpone(next_reminder_time, &interaction)?;
+
+            info!("Notification interaction recorded: {} - {}", notification_id, action);
+            return Ok(());
+        }
+
+        if action == ACKNOWLEDGE_ACTION {
+            interaction.details = Some(format!("Reminder acknowledged by user {}", session.user_name));
+        }
+
         // Save to database
         crate::database::add_notification_interaction(&self.db_pool, &interaction)
             .context("Failed to save notification interaction to database")?;
 
         info!("Notification interaction recorded: {} - {}", notification_id, action);
+
+        if action == DETAILS_ACTION {
+            self.show_details(session)?;
+        }
+        Ok(())
+    }
+
+    /// Show the details page of the stored reboot state to the user of a session
+    ///
+    /// From the service, the page is rendered here and written to the user's temp directory by a
+    /// copy of this program in their session, which also opens it there.
+    pub fn show_details(&self, session: &UserSession) -> Result<()> {
+        let title = self
+            .locale_for(session)
+            .and_then(|locale| locale.title.as_deref())
+            .unwrap_or(&self.config.branding.title);
+        let state = crate::database::get_reboot_state(&self.db_pool)?
+            .unwrap_or_else(|| RebootState::new(false, false));
+        let ui = SessionUi::Details {
+            html: details::render_html(title, &state),
+        };
+        self.show_session_ui(session, &ui)
+    }
+
+    /// Get the time reminders are postponed until for a timespan
+    ///
+    /// Postponing can't take longer than the longest deferral option offered with the last
+    /// reminder, so links can't postpone reminders further than the menus allow.
+    fn postpone_end(&self, duration: &str) -> Result<DateTime<Utc>> {
+        let now = self.clock.now();
+        let next_reminder_time = now + chrono::Duration::from_std(crate::utils::timespan::parse_timespan(duration)?)?;
+
+        let longest = self
+            .deferral_options
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Deferral options lock is poisoned"))?
+            .iter()
+            .filter_map(|option| crate::reboot::deferral_end(option, now).ok())
+            .max()
+            .ok_or_else(|| anyhow::anyhow!("Reminders can't be postponed: no deferral options are offered"))?;
+        if next_reminder_time > longest {
+            return Err(anyhow::anyhow!("Can't postpone reminders for {}: the longest deferral option ends at {}", duration, longest));
+        }
+
+        Ok(next_reminder_time)
+    }
+
+    /// Postpone reminders, recording the interaction that postponed them, and let the user know
+    /// when they will be reminded again
+    fn postpone(&self, next_reminder_time: DateTime<Utc>, interaction: &NotificationInteraction) -> Result<()> {
+        let state = postpone_reminders(&self.db_pool, next_reminder_time, Some(interaction), self.max_deferrals, self.clock.now())?;
+
+        self.set_template_context(template::TemplateContext::new(&state, None, self.max_deferrals));
+        if let Err(e) = self.show_message("reboot_postponed", |messages| &messages.reboot_postponed, None) {
+            warn!("Failed to show postpone confirmation: {}", e);
+        }
+
         Ok(())
     }
 
     /// Handle a reboot action
     fn handle_reboot_action(&self, action: &str, session: &UserSession) -> Result<()> {
         info!("Handling reboot action: {}", action);
-        info!("Initiated by user: {} (session: {})", session.user_name, session.session_id);
+        info!("Initiated by user: {} (session: {})", crate::logging::user(&session.user_name), session.session_id);
 
         // Parse the action to get parameters
         let parts: Vec<&str> = action.split(':').collect();
@@ -254,27 +1211,19 @@ impl NotificationManager {
         info!("Reboot type: {}", reboot_type);
 
         // Create reboot configuration
-        let countdown_seconds = if let Some(countdown) = &self.system_reboot_config.countdown {
-            // Parse the timespan string
-            match crate::utils::timespan::parse_timespan(countdown) {
-                Ok(duration) => duration.as_secs() as u32,
-                Err(e) => {
-                    warn!("Failed to parse countdown timespan: {}", e);
-                    // Fall back to the legacy value or default
-                    self.system_reboot_config.countdown_seconds.unwrap_or(30)
-                }
-            }
-        } else {
-            // Use the legacy value or default
-            self.system_reboot_config.countdown_seconds.unwrap_or(30)
-        };
+        let mut reboot_config = crate::reboot::system::RebootConfig::from(&self.system_reboot_config);
+        reboot_config.dry_run = self.dry_run;
 
-        let reboot_config = crate::reboot::system::RebootConfig {
-            countdown_seconds: countdown_seconds,
-            show_confirmation: self.system_reboot_config.show_confirmation,
-            confirmation_message: self.system_reboot_config.confirmation_message.clone(),
-            confirmation_title: self.system_reboot_config.confirmation_title.clone(),
-        };
+        // Show the confirmation dialog in the user's language
+        if let Some(locale) = self.locale_for(session) {
+            if let Some(message) = &locale.confirmation_message {
+                reboot_config.confirmation_message = message.clone();
+            }
+            if let Some(title) = &locale.confirmation_title {
+                reboot_config.confirmation_title = title.clone();
+            }
+        }
+        reboot_config.confirmation_message = self.render(&reboot_config.confirmation_message);
 
         // Check if system reboots are enabled
         if !self.system_reboot_config.enabled {
@@ -282,12 +1231,27 @@ impl NotificationManager {
             return Err(anyhow::anyhow!("System reboot feature is disabled"));
         }
 
+        // Defer the reboot and let the user know if the power status doesn't allow it
+        if let Some(reason) = crate::reboot::system::check_power_conditions(&reboot_config) {
+            warn!("Deferring system reboot: {}", reason);
+            if let Err(e) = self.show_message("reboot_deferred", |messages| &messages.reboot_deferred_power, None) {
+                warn!("Failed to show reboot deferral notification: {}", e);
+            }
+            return Ok(());
+        }
+
         // Initiate the reboot
         info!("Initiating system reboot with countdown: {} seconds", reboot_config.countdown_seconds);
         match crate::reboot::system::reboot_system(&reboot_config) {
-            Ok(confirmed) => {
-                if confirmed {
+            Ok(attempt) => {
+                if attempt.proceeded {
                     info!("System reboot initiated successfully");
+                    attempt.record_restart_applications(&reboot_config, &self.db_pool);
+                    if !reboot_config.dry_run {
+                        if let Err(e) = crate::reboot::verify::mark_initiated(&self.db_pool, self.clock.now()) {
+                            warn!("Failed to record the initiated reboot: {}", e);
+                        }
+                    }
                     Ok(())
                 } else {
                     info!("System reboot was cancelled by user");
@@ -301,58 +1265,67 @@ impl NotificationManager {
         }
     }
 
-    /// Check if the current time is within quiet hours
-    fn is_quiet_hours(&self) -> bool {
-        if !self.config.quiet_hours.enabled {
-            return false;
+    /// Check if notifications should be held back right now
+    ///
+    /// When the working hours of the active users are known, it's quiet while none of them is
+    /// within their own; users whose working hours aren't known follow the static quiet hours.
+    fn is_quiet_now(&self) -> bool {
+        let working_hours = self.session_working_hours();
+        if working_hours.iter().all(|(_, hours)| hours.is_none()) {
+            let quiet = self.is_quiet_hours();
+            if quiet {
+                info!("Quiet hours configuration: start={}, end={}, days={:?}",
+                      self.config.quiet_hours.start_time,
+                      self.config.quiet_hours.end_time,
+                      self.config.quiet_hours.days_of_week);
+            }
+            return quiet;
         }
 
-        let now = Utc::now();
-        let current_day = match now.weekday() {
-            Weekday::Mon => 1,
-            Weekday::Tue => 2,
-            Weekday::Wed => 3,
-            Weekday::Thu => 4,
-            Weekday::Fri => 5,
-            Weekday::Sat => 6,
-            Weekday::Sun => 0,
-        };
-
-        // Check if current day is in quiet hours days
-        let day_included = self.config.quiet_hours.days_of_week.contains(&(current_day as u8));
-        if !day_included {
-            return false;
+        let (day, time) = self.time_zone.day_and_time(self.clock.now());
+        let quiet = working_hours.iter().all(|(session, hours)| match hours {
+            Some(hours) => {
+                let working = hours.contains(day, time);
+                if !working {
+                    info!("Outside the working hours of session {} ({})", session.session_id, hours);
+                }
+                !working
+            }
+            None => self.is_quiet_hours(),
+        });
+        if quiet {
+            info!("Every active user is outside their working hours or in quiet hours");
         }
+        quiet
+    }
 
-        // Parse quiet hours times
-        let start_time = match NaiveTime::parse_from_str(&self.config.quiet_hours.start_time, "%H:%M") {
-            Ok(time) => time,
-            Err(e) => {
-                warn!("Failed to parse quiet hours start time: {}", e);
-                return false;
-            }
-        };
+    /// Get the working hours of the user in each active session from the first provider that
+    /// knows them
+    fn session_working_hours(&self) -> Vec<(UserSession, Option<working_hours::WorkingHours>)> {
+        if self.working_hours.is_empty() {
+            return Vec::new();
+        }
 
-        let end_time = match NaiveTime::parse_from_str(&self.config.quiet_hours.end_time, "%H:%M") {
-            Ok(time) => time,
+        let sessions = match self.impersonator.get_active_sessions() {
+            Ok(sessions) => sessions,
             Err(e) => {
-                warn!("Failed to parse quiet hours end time: {}", e);
-                return false;
+                debug!("Failed to get active sessions for working hours: {}", e);
+                return Vec::new();
             }
         };
 
-        // Get current time in local timezone
-        let local_now = chrono::Local::now();
-        let current_time = local_now.time();
+        sessions
+            .into_iter()
+            .map(|session| {
+                let hours = self.working_hours.iter().find_map(|provider| provider.working_hours(&session));
+                (session, hours)
+            })
+            .collect()
+    }
 
-        // Handle overnight quiet hours
-        if start_time > end_time {
-            // Quiet hours span midnight
-            current_time >= start_time || current_time < end_time
-        } else {
-            // Normal quiet hours
-            current_time >= start_time && current_time < end_time
-        }
+    /// Check if the current time is within quiet hours
+    fn is_quiet_hours(&self) -> bool {
+        is_quiet_at(&self.config.quiet_hours, self.time_zone, self.clock.now())
     }
 
     /// Resolve an icon path
@@ -387,7 +1360,7 @@ impl NotificationManager {
     pub fn update_tray_status(&self, status: &str) -> Result<()> {
         debug!("Updating tray status: {}", status);
 
-        if service::is_running_as_service() {
+        if is_running_as_service() {
             debug!("Running as a service, skipping tray status update");
             return Ok(());
         }
@@ -416,7 +1389,7 @@ impl NotificationManager {
     pub fn enable_reboot_option(&self, enable: bool) -> Result<()> {
         debug!("Setting reboot option enabled: {}", enable);
 
-        if service::is_running_as_service() {
+        if is_running_as_service() {
             debug!("Running as a service, skipping reboot option update");
             return Ok(());
         }
@@ -430,9 +1403,10 @@ impl NotificationManager {
                         } else {
                             debug!("Reboot option enabled successfully");
                         }
+                    } else if let Err(e) = tray.disable_reboot_item() {
+                        warn!("Failed to disable reboot option: {}", e);
                     } else {
-                        // TODO: Implement disable functionality when tray menu supports it
-                        debug!("Disabling reboot option not implemented yet");
+                        debug!("Reboot option disabled successfully");
                     }
                 },
                 Err(e) => {
@@ -447,10 +1421,10 @@ impl NotificationManager {
     }
 
     /// Enable or disable the postpone option
-    pub fn enable_postpone_option(&self, _enable: bool) -> Result<()> {
-        debug!("Setting postpone option enabled");
+    pub fn enable_postpone_option(&self, enable: bool) -> Result<()> {
+        debug!("Setting postpone option enabled: {}", enable);
 
-        if service::is_running_as_service() {
+        if is_running_as_service() {
             debug!("Running as a service, skipping postpone option update");
             return Ok(());
         }
@@ -458,10 +1432,16 @@ impl NotificationManager {
         if let Some(tray_manager) = &self.tray_manager {
             match tray_manager.lock() {
                 Ok(mut tray) => {
-                    if let Err(e) = tray.enable_postpone_item() {
-                        warn!("Failed to enable postpone option: {}", e);
+                    if enable {
+                        if let Err(e) = tray.enable_postpone_item() {
+                            warn!("Failed to enable postpone option: {}", e);
+                        } else {
+                            debug!("Postpone option enabled successfully");
+                        }
+                    } else if let Err(e) = tray.disable_postpone_item() {
+                        warn!("Failed to disable postpone option: {}", e);
                     } else {
-                        debug!("Postpone option enabled successfully");
+                        debug!("Postpone option disabled successfully");
                     }
                 },
                 Err(e) => {
@@ -475,43 +1455,51 @@ impl NotificationManager {
         Ok(())
     }
 
-    /// Set the available deferral options
-    pub fn set_deferral_options(&self, deferrals: &[String]) -> Result<()> {
+    /// Set the available deferral options and the times of day reminders can be snoozed until
+    pub fn set_deferral_options(&self, deferrals: &[DeferralOption], snooze_until_times: &[String]) -> Result<()> {
         info!("Setting deferral options for notifications");
 
-        if deferrals.is_empty() {
+        match self.deferral_options.lock() {
+            Ok(mut current) => *current = deferrals.to_vec(),
+            Err(e) => warn!("Failed to acquire lock on deferral options: {}", e),
+        }
+
+        if deferrals.is_empty() && snooze_until_times.is_empty() {
             info!("No deferral options provided");
             return Ok(());
         }
 
-        info!("Available deferral options: {}", deferrals.join(", "));
+        let labels: Vec<&str> = deferrals.iter().map(|deferral| deferral.label()).collect();
+        info!("Available deferral options: {}", labels.join(", "));
 
         // Log each deferral option in detail
+        let now = self.clock.now();
         for (i, deferral) in deferrals.iter().enumerate() {
-            info!("Deferral option {}: {}", i + 1, deferral);
+            match crate::reboot::deferral_end(deferral, now) {
+                Ok(until) => info!("Deferral option {}: {} (action {}{}, until {})",
+                                   i + 1, deferral.label(), DEFER_ACTION_PREFIX, deferral.id(), until),
+                Err(e) => warn!("  Unable to parse deferral option {}: {}", deferral.id(), e),
+            }
+        }
 
-            // Parse the deferral to get the duration
-            if let Ok(duration) = crate::utils::timespan::parse_timespan(deferral) {
-                let total_seconds = duration.as_secs();
-                let hours = total_seconds / 3600;
-                let minutes = (total_seconds % 3600) / 60;
-                info!("  Duration: {} hours and {} minutes", hours, minutes);
-            } else {
-                warn!("  Unable to parse deferral timespan: {}", deferral);
+        for time in snooze_until_times {
+            match crate::reboot::next_time_of_day(time, now) {
+                Ok(next) => info!("Snooze option: until {} (action {}{}, next at {})",
+                                  time, SNOOZE_UNTIL_ACTION_PREFIX, time, next),
+                Err(e) => warn!("  Unable to parse snooze until time: {}", e),
             }
         }
 
-        if service::is_running_as_service() {
+        if is_running_as_service() {
             debug!("Running as a service, skipping deferral options update");
             return Ok(());
         }
 
         if let Some(tray_manager) = &self.tray_manager {
             match tray_manager.lock() {
-                Ok(_tray) => {
-                    debug!("Tray manager found, but tray doesn't support setting deferral options directly");
-                    // Tray doesn't support setting deferral options directly
-                    // We'll need to implement this functionality
+                Ok(mut tray) => {
+                    // The menu picks the options up when it's rebuilt for the next reboot state
+                    tray.set_deferral_options(deferrals, self.max_deferrals);
                 },
                 Err(e) => {
                     warn!("Failed to acquire lock on tray manager: {}", e);
@@ -527,3 +1515,287 @@ impl NotificationManager {
 
 
 }
+
+/// Postpone reminders until a time, counting it as a postponement made at `now`
+///
+/// The interaction that postponed them is saved in the same transaction as the reboot state, so
+/// the service loop never sees one without the other. Once the reboot has been postponed
+/// `max_deferrals` times, nothing is saved and an error is returned.
+fn postpone_reminders(
+    db_pool: &DbPool,
+    next_reminder_time: DateTime<Utc>,
+    interaction: Option<&NotificationInteraction>,
+    max_deferrals: Option<u32>,
+    now: DateTime<Utc>,
+) -> Result<RebootState> {
+    let postpone = |state: &mut RebootState| {
+        // Checked on the latest state, so concurrent postponements can't go over the limit
+        if crate::reboot::deferrals_exhausted(max_deferrals, state) {
+            anyhow::bail!(
+                "The reboot has already been postponed {} times, the most allowed",
+                state.postpone_count
+            );
+        }
+        state.next_reminder_time = Some(next_reminder_time);
+        state.postpone_count += 1;
+        state.updated_at = now;
+        Ok(())
+    };
+    match interaction {
+        Some(interaction) => crate::database::update_reboot_state_with_interaction(db_pool, interaction, postpone),
+        None => crate::database::update_reboot_state(db_pool, postpone),
+    }
+    .context("Failed to save reboot state")?
+    .ok_or_else(|| anyhow::anyhow!("No reboot state to postpone"))
+}
+
+/// Postpone reminders with a deferral option chosen from the tray menu at `now`
+///
+/// The choice is recorded against the most recent notification.
+fn defer_from_tray(db_pool: &DbPool, option: &DeferralOption, max_deferrals: Option<u32>, now: DateTime<Utc>) -> Result<()> {
+    let next_reminder_time = crate::reboot::deferral_end(option, now)?;
+    info!("Postponing reminders until {} from the tray ({})", next_reminder_time, option.label());
+
+    let interaction = crate::database::get_notifications(db_pool, Some(1))?.first().map(|notification| {
+        let mut interaction = NotificationInteraction::new(
+            notification.id,
+            &format!("{}{}", DEFER_ACTION_PREFIX, option.id()),
+        );
+        interaction.deferral_id = Some(option.id().to_string());
+        interaction.details = Some(format!("Reminders postponed until {} ({}) from the tray",
+                                           next_reminder_time, option.label()));
+        interaction
+    });
+    postpone_reminders(db_pool, next_reminder_time, interaction.as_ref(), max_deferrals, now)?;
+
+    Ok(())
+}
+
+/// Record the answer to a message box, restarting when the user asked for it
+fn handle_message_box_answer(
+    db_pool: &DbPool,
+    notification_id: uuid::Uuid,
+    session: &UserSession,
+    answer: MESSAGEBOX_RESULT,
+    reboot_config: &crate::reboot::system::RebootConfig,
+    now: DateTime<Utc>,
+) -> Result<()> {
+    let action = match answer {
+        IDYES => "messagebox:yes",
+        IDNO => "messagebox:no",
+        IDTIMEOUT => "messagebox:timeout",
+        _ => "messagebox:closed",
+    };
+    info!("Message box in session {} answered with {}", session.session_id, action);
+
+    let mut interaction = NotificationInteraction::new(notification_id, action);
+    interaction.user_name = Some(session.user_name.clone());
+    interaction.session_id = Some(session.session_id.clone());
+    if answer == IDYES {
+        interaction.details = Some(format!("Reboot initiated by user {} from a message box", session.user_name));
+    }
+    crate::database::add_notification_interaction(db_pool, &interaction)
+        .context("Failed to save notification interaction to database")?;
+
+    if answer != IDYES {
+        return Ok(());
+    }
+
+    let attempt = crate::reboot::system::reboot_system(reboot_config)?;
+    attempt.record_restart_applications(reboot_config, db_pool);
+    if attempt.proceeded && !reboot_config.dry_run {
+        crate::reboot::verify::mark_initiated(db_pool, now)?;
+    }
+    Ok(())
+}
+
+/// Record the answer to an acknowledgment dialog
+///
+/// Only OK acknowledges the reminder; a dialog that timed out is recorded so reports can show
+/// reminders that went unanswered.
+fn handle_acknowledgment_answer(
+    db_pool: &DbPool,
+    notification_id: uuid::Uuid,
+    session: &UserSession,
+    answer: MESSAGEBOX_RESULT,
+) -> Result<()> {
+    let action = match answer {
+        IDOK => ACKNOWLEDGE_ACTION,
+        IDTIMEOUT => "acknowledge:timeout",
+        _ => "acknowledge:closed",
+    };
+    info!("Acknowledgment dialog in session {} answered with {}", session.session_id, action);
+
+    let mut interaction = NotificationInteraction::new(notification_id, action);
+    interaction.user_name = Some(session.user_name.clone());
+    interaction.session_id = Some(session.session_id.clone());
+    if answer == IDOK {
+        crate::metrics::increment("notification.acknowledged");
+        interaction.details = Some(format!("Reminder acknowledged by user {}", session.user_name));
+    }
+    crate::database::add_notification_interaction(db_pool, &interaction)
+        .context("Failed to save notification interaction to database")
+}
+
+/// Check if a user name matches one of the suppressed account patterns
+///
+/// Patterns support `*` and `?` wildcards and ignore case. Session user names have no domain, so
+/// a `DOMAIN\` prefix in a pattern is ignored.
+fn is_suppressed_account(patterns: &[String], user_name: &str) -> bool {
+    patterns.iter().any(|pattern| {
+        let pattern = pattern.rsplit('\\').next().unwrap_or(pattern);
+        wildcard_match(&pattern.to_lowercase(), &user_name.to_lowercase())
+    })
+}
+
+/// Match text against a pattern with `*` (any characters) and `?` (one character) wildcards
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Check if a time is within one of the quiet hours ranges, evaluated in a time zone
+///
+/// Ranges that end before they start span midnight, and apply on the days they start.
+fn is_quiet_at(quiet_hours: &QuietHoursConfig, zone: ScheduleZone, now: DateTime<Utc>) -> bool {
+    if !quiet_hours.enabled {
+        return false;
+    }
+
+    let (day, time) = zone.day_and_time(now);
+    quiet_hours.effective_ranges().iter().any(|range| {
+        // Parse quiet hours times
+        let start_time = match NaiveTime::parse_from_str(&range.start, "%H:%M") {
+            Ok(time) => time,
+            Err(e) => {
+                warn!("Failed to parse quiet hours start time: {}", e);
+                return false;
+            }
+        };
+
+        let end_time = match NaiveTime::parse_from_str(&range.end, "%H:%M") {
+            Ok(time) => time,
+            Err(e) => {
+                warn!("Failed to parse quiet hours end time: {}", e);
+                return false;
+            }
+        };
+
+        schedule::in_range(&range.days, start_time, end_time, day, time)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_suppressed_account() {
+        let patterns = vec!["adm-*".to_string(), "CONTOSO\\breakglass?".to_string()];
+        assert!(is_suppressed_account(&patterns, "ADM-jdoe"));
+        assert!(is_suppressed_account(&patterns, "breakglass1"));
+        assert!(!is_suppressed_account(&patterns, "breakglass12"));
+        assert!(!is_suppressed_account(&patterns, "jdoe"));
+        assert!(wildcard_match("a*b*c", "axxbyyc"));
+        assert!(!wildcard_match("a*b", "axxc"));
+    }
+
+    #[test]
+    fn test_is_quiet_at() {
+        use chrono::TimeZone;
+
+        // Weeknights from 22:00 to 07:00 in New York, where the clocks went forward at 02:00 on
+        // Sunday 10 March 2024
+        let quiet_hours = QuietHoursConfig {
+            enabled: true,
+            start_time: "22:00".to_string(),
+            end_time: "07:00".to_string(),
+            days_of_week: vec![1, 2, 3, 4, 5],
+            ranges: Vec::new(),
+            working_hours: None,
+        };
+        let zone = ScheduleZone::parse(Some("America/New_York")).unwrap();
+        let utc = |day: u32, hour: u32| Utc.with_ymd_and_hms(2024, 3, day, hour, 0, 0).unwrap();
+
+        // Friday 23:00 EST is Saturday in UTC, and Saturday 06:00 EST still belongs to Friday night
+        assert!(is_quiet_at(&quiet_hours, zone, utc(9, 4)));
+        assert!(is_quiet_at(&quiet_hours, zone, utc(9, 11)));
+        assert!(!is_quiet_at(&quiet_hours, zone, utc(9, 12)));
+
+        // Saturday night isn't quiet, Monday night is, with the hours moved an hour earlier in
+        // UTC after the clocks went forward
+        assert!(!is_quiet_at(&quiet_hours, zone, utc(10, 4)));
+        assert!(is_quiet_at(&quiet_hours, zone, utc(12, 2)));
+        assert!(is_quiet_at(&quiet_hours, zone, utc(12, 10)));
+        assert!(!is_quiet_at(&quiet_hours, zone, utc(12, 11)));
+    }
+
+    #[test]
+    fn test_is_quiet_at_per_day() {
+        use chrono::TimeZone;
+
+        // startTime, endTime and daysOfWeek can be left out when ranges are configured
+        let quiet_hours: QuietHoursConfig = serde_json::from_value(serde_json::json!({
+            "enabled": true,
+            "ranges": [
+                { "days": [0, 6], "start": "00:00", "end": "00:00" },
+                { "days": [1, 2, 3, 4], "start": "18:00", "end": "08:00" }
+            ]
+        }))
+        .unwrap();
+        assert_eq!(quiet_hours.effective_ranges().len(), 2);
+
+        // 4 to 9 March 2024 runs from Monday to Saturday
+        let zone = ScheduleZone::parse(Some("UTC")).unwrap();
+        let utc = |day: u32, hour: u32| Utc.with_ymd_and_hms(2024, 3, day, hour, 0, 0).unwrap();
+        assert!(is_quiet_at(&quiet_hours, zone, utc(9, 12)));
+        assert!(is_quiet_at(&quiet_hours, zone, utc(4, 19)));
+        assert!(is_quiet_at(&quiet_hours, zone, utc(5, 7)));
+        assert!(!is_quiet_at(&quiet_hours, zone, utc(5, 12)));
+
+        // Thursday night lasts into Friday morning, but Friday evening isn't quiet until Saturday
+        assert!(!is_quiet_at(&quiet_hours, zone, utc(8, 20)));
+        assert!(is_quiet_at(&quiet_hours, zone, utc(8, 7)));
+    }
+
+    #[test]
+    fn test_postpone_reminders_stops_at_max_deferrals() {
+        use chrono::Duration;
+
+        let pool = crate::database::init_in_memory().unwrap();
+        crate::database::save_reboot_state(&pool, &RebootState::new(true, false)).unwrap();
+        let now = Utc::now();
+
+        for count in 1..=2 {
+            let state = postpone_reminders(&pool, now + Duration::hours(1), None, Some(2), now).unwrap();
+            assert_eq!(state.postpone_count, count);
+        }
+
+        // The third postponement is refused and nothing is saved
+        assert!(postpone_reminders(&pool, now + Duration::hours(4), None, Some(2), now).is_err());
+        let state = crate::database::get_reboot_state(&pool).unwrap().unwrap();
+        assert_eq!(state.postpone_count, 2);
+        assert!(crate::reboot::deferrals_exhausted(Some(2), &state));
+        assert!(!crate::reboot::deferrals_exhausted(None, &state));
+    }
+}
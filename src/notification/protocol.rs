@@ -0,0 +1,187 @@
+use super::{ACKNOWLEDGE_ACTION, DETAILS_ACTION, DEFER_ACTION_PREFIX, POSTPONE_ACTION_PREFIX, SNOOZE_UNTIL_ACTION_PREFIX};
+use crate::utils::registry;
+use anyhow::{Context, Result};
+use log::info;
+use std::path::Path;
+use uuid::Uuid;
+use windows::Win32::System::Registry::HKEY_LOCAL_MACHINE;
+
+/// URL scheme of the links toast buttons carry (e.g., "rebootreminder:postpone?duration=4h")
+pub const SCHEME: &str = "rebootreminder";
+
+/// Registry key the scheme is registered under for all users
+const PROTOCOL_KEY: &str = r"Software\Classes\rebootreminder";
+
+/// Action requested through a `rebootreminder:` link
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtocolAction {
+    /// Notification action, as recorded for notification interactions (e.g., "defer:4h")
+    pub action: String,
+
+    /// Notification the action was chosen on, if the link names one
+    pub notification_id: Option<Uuid>,
+}
+
+/// Parse a `rebootreminder:` link into the notification action it stands for
+///
+/// Supported links are `reboot`, `postpone?option=<id>` or `postpone?duration=<timespan>`,
+/// `snooze?until=<HH:MM>`, `acknowledge` and `details`, each optionally with `notification=<id>`.
+pub fn parse(uri: &str) -> Result<ProtocolAction> {
+    let rest = uri
+        .split_once(':')
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case(SCHEME))
+        .map(|(_, rest)| rest)
+        .ok_or_else(|| anyhow::anyhow!("Not a {} link: {}", SCHEME, uri))?;
+    let rest = rest.trim_start_matches('/');
+    let (command, query) = rest.split_once('?').unwrap_or((rest, ""));
+
+    let mut parameters = Vec::new();
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        parameters.push((key.to_lowercase(), percent_decode(value)?));
+    }
+    let parameter = |name: &str| {
+        parameters
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+            .filter(|value| !value.is_empty())
+    };
+
+    let action = match command.trim_end_matches('/').to_lowercase().as_str() {
+        "reboot" | "restart" => "reboot:now".to_string(),
+        "postpone" => match (parameter("option"), parameter("duration")) {
+            (Some(id), _) => format!("{}{}", DEFER_ACTION_PREFIX, id),
+            (None, Some(duration)) => format!("{}{}", POSTPONE_ACTION_PREFIX, duration),
+            (None, None) => anyhow::bail!("Postpone link without an option or duration: {}", uri),
+        },
+        "snooze" => {
+            let until = parameter("until").ok_or_else(|| anyhow::anyhow!("Snooze link without a time: {}", uri))?;
+            format!("{}{}", SNOOZE_UNTIL_ACTION_PREFIX, until)
+        }
+        "acknowledge" => ACKNOWLEDGE_ACTION.to_string(),
+        "details" => DETAILS_ACTION.to_string(),
+        other => anyhow::bail!("Unknown {} action '{}'", SCHEME, other),
+    };
+
+    let notification_id = parameter("notification")
+        .map(|id| Uuid::parse_str(id).with_context(|| format!("Invalid notification ID: {}", id)))
+        .transpose()?;
+
+    Ok(ProtocolAction { action, notification_id })
+}
+
+/// Build the `rebootreminder:` link for a notification action, the inverse of `parse`
+pub fn uri(action: &str, notification_id: Option<Uuid>) -> Option<String> {
+    let (command, mut query) = if action.starts_with("reboot:") {
+        ("reboot", Vec::new())
+    } else if let Some(id) = action.strip_prefix(DEFER_ACTION_PREFIX) {
+        ("postpone", vec![format!("option={}", percent_encode(id))])
+    } else if let Some(duration) = action.strip_prefix(POSTPONE_ACTION_PREFIX) {
+        ("postpone", vec![format!("duration={}", percent_encode(duration))])
+    } else if let Some(until) = action.strip_prefix(SNOOZE_UNTIL_ACTION_PREFIX) {
+        ("snooze", vec![format!("until={}", percent_encode(until))])
+    } else if action == ACKNOWLEDGE_ACTION {
+        ("acknowledge", Vec::new())
+    } else if action == DETAILS_ACTION {
+        ("details", Vec::new())
+    } else {
+        return None;
+    };
+
+    if let Some(id) = notification_id {
+        query.push(format!("notification={}", id));
+    }
+    Some(if query.is_empty() {
+        format!("{}:{}", SCHEME, command)
+    } else {
+        format!("{}:{}?{}", SCHEME, command, query.join("&"))
+    })
+}
+
+/// Register the scheme so Windows opens `rebootreminder:` links with this program
+pub fn register(program: &Path) -> Result<()> {
+    let command = format!("\"{}\" open-url \"%1\"", program.display());
+    registry::set_string_value(HKEY_LOCAL_MACHINE, PROTOCOL_KEY, "", "URL:Reboot Reminder")?;
+    registry::set_string_value(HKEY_LOCAL_MACHINE, PROTOCOL_KEY, "URL Protocol", "")?;
+    registry::set_string_value(HKEY_LOCAL_MACHINE, &format!(r"{}\shell\open\command", PROTOCOL_KEY), "", &command)
+        .context("Failed to register the protocol handler")?;
+
+    info!("Registered the {}: protocol handler", SCHEME);
+    Ok(())
+}
+
+/// Remove the scheme registration
+pub fn unregister() -> Result<()> {
+    if registry::delete_key_tree(HKEY_LOCAL_MACHINE, PROTOCOL_KEY)? {
+        info!("Removed the {}: protocol handler", SCHEME);
+    }
+    Ok(())
+}
+
+/// Decode %XX escapes in a link parameter
+fn percent_decode(value: &str) -> Result<String> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = value.get(i + 1..i + 3).ok_or_else(|| anyhow::anyhow!("Invalid escape in '{}'", value))?;
+            decoded.push(u8::from_str_radix(hex, 16).with_context(|| format!("Invalid escape in '{}'", value))?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(decoded).with_context(|| format!("Invalid text in '{}'", value))
+}
+
+/// Escape the characters that can't appear in a link parameter as is
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b':' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let action = parse("rebootreminder:postpone?duration=4h").unwrap();
+        assert_eq!(action.action, "postpone:4h");
+        assert_eq!(action.notification_id, None);
+        assert_eq!(parse("rebootreminder:postpone?option=tomorrow").unwrap().action, "defer:tomorrow");
+
+        let id = Uuid::new_v4();
+        let action = parse(&format!("RebootReminder://snooze/?until=17%3A30&notification={}", id)).unwrap();
+        assert_eq!(action.action, "snooze-until:17:30");
+        assert_eq!(action.notification_id, Some(id));
+
+        assert_eq!(parse("rebootreminder:reboot").unwrap().action, "reboot:now");
+        assert!(parse("rebootreminder:postpone").is_err());
+        assert!(parse("rebootreminder:format-disk").is_err());
+        assert!(parse("https://example.com/postpone?duration=4h").is_err());
+    }
+
+    #[test]
+    fn test_uri_round_trip() {
+        let id = Uuid::new_v4();
+        for action in ["reboot:now", "defer:end of day", "postpone:90m", "snooze-until:17:30", "acknowledge", "details"] {
+            let link = uri(action, Some(id)).unwrap();
+            let parsed = parse(&link).unwrap();
+            assert_eq!(parsed.action, action);
+            assert_eq!(parsed.notification_id, Some(id));
+        }
+
+        assert_eq!(uri("defer:4h", None).unwrap(), "rebootreminder:postpone?option=4h");
+        assert_eq!(uri("messagebox:yes", None), None);
+    }
+}
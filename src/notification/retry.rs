@@ -0,0 +1,99 @@
+use crate::config::DeliveryRetryConfig;
+use crate::database::{DeliveryStatus, Notification};
+use chrono::{DateTime, Duration, Utc};
+use log::{info, warn};
+
+/// Get the delay before the retry following a number of failed attempts
+///
+/// The initial delay doubles after each failure, up to the maximum delay.
+pub fn retry_delay(config: &DeliveryRetryConfig, failed_attempts: u32) -> Duration {
+    let initial = parse_delay(&config.initial_delay, Duration::minutes(1));
+    let max = parse_delay(&config.max_delay, Duration::hours(1));
+
+    let factor = 2i64.saturating_pow(failed_attempts.saturating_sub(1).min(32));
+    Duration::milliseconds(initial.num_milliseconds().saturating_mul(factor).min(max.num_milliseconds()))
+}
+
+/// Record a successful delivery attempt
+pub fn record_success(notification: &mut Notification) {
+    notification.delivery_attempts += 1;
+    notification.delivery_status = DeliveryStatus::Delivered;
+    notification.next_attempt_time = None;
+    notification.delivery_error = None;
+}
+
+/// Record a failed delivery attempt, scheduling a retry or giving up after the last attempt
+///
+/// Returns whether the notification was given up on.
+pub fn record_failure(
+    config: &DeliveryRetryConfig,
+    notification: &mut Notification,
+    error: &str,
+    now: DateTime<Utc>,
+) -> bool {
+    notification.delivery_attempts += 1;
+    notification.delivery_error = Some(error.to_string());
+
+    if notification.delivery_attempts >= config.max_attempts {
+        notification.delivery_status = DeliveryStatus::Failed;
+        notification.next_attempt_time = None;
+        warn!(
+            "Giving up on notification {} after {} failed delivery attempts: {}",
+            notification.id, notification.delivery_attempts, error
+        );
+        return true;
+    }
+
+    let next = now + retry_delay(config, notification.delivery_attempts);
+    notification.delivery_status = DeliveryStatus::Pending;
+    notification.next_attempt_time = Some(next);
+    info!(
+        "Delivery attempt {} of notification {} failed, retrying at {}",
+        notification.delivery_attempts, notification.id, next
+    );
+    false
+}
+
+/// Parse a retry delay, falling back to a default if it's invalid
+fn parse_delay(value: &str, default: Duration) -> Duration {
+    crate::utils::timespan::parse_timespan(value)
+        .ok()
+        .and_then(|delay| Duration::from_std(delay).ok())
+        .unwrap_or(default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_failure() {
+        let config = DeliveryRetryConfig {
+            max_attempts: 3,
+            initial_delay: "1m".to_string(),
+            max_delay: "3m".to_string(),
+        };
+        assert_eq!(retry_delay(&config, 1), Duration::minutes(1));
+        assert_eq!(retry_delay(&config, 2), Duration::minutes(2));
+        assert_eq!(retry_delay(&config, 3), Duration::minutes(3));
+        assert_eq!(retry_delay(&config, 40), Duration::minutes(3));
+
+        let now = Utc::now();
+        let mut notification = Notification::new("reboot_required", "Restart", None);
+        assert!(!record_failure(&config, &mut notification, "Impersonation failed", now));
+        assert_eq!(notification.delivery_status, DeliveryStatus::Pending);
+        assert_eq!(notification.next_attempt_time, Some(now + Duration::minutes(1)));
+
+        assert!(!record_failure(&config, &mut notification, "Impersonation failed", now));
+        assert_eq!(notification.next_attempt_time, Some(now + Duration::minutes(2)));
+
+        assert!(record_failure(&config, &mut notification, "Impersonation failed", now));
+        assert_eq!(notification.delivery_status, DeliveryStatus::Failed);
+        assert_eq!(notification.delivery_attempts, 3);
+        assert!(notification.next_attempt_time.is_none());
+
+        record_success(&mut notification);
+        assert_eq!(notification.delivery_status, DeliveryStatus::Delivered);
+        assert!(notification.delivery_error.is_none());
+    }
+}
@@ -0,0 +1,85 @@
+use super::{balloon, details, urgent};
+use crate::config::WindowPlacement;
+use anyhow::Result;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Notification UI shown by a copy of this program in a user's session
+///
+/// Windows and notification area icons of the service would only appear on the session 0
+/// desktop, so the service starts `show-ui` with this as JSON in the user's session instead,
+/// like it does for toasts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum SessionUi {
+    /// Balloon from a temporary notification area icon
+    #[serde(rename_all = "camelCase")]
+    Balloon {
+        title: String,
+        message: String,
+        icon_path: PathBuf,
+        warning: bool,
+    },
+
+    /// Critical notification window on top of all others
+    #[serde(rename_all = "camelCase")]
+    Urgent {
+        title: String,
+        message: String,
+        placement: WindowPlacement,
+    },
+
+    /// Details page, written to the user's temp directory and opened in their browser
+    Details {
+        html: String,
+    },
+}
+
+impl SessionUi {
+    /// Show the UI from this process, returning once it's up
+    pub fn show(&self) -> Result<()> {
+        self.start().map(|_| ())
+    }
+
+    /// Show the UI and wait until it's gone, so the process started in the user's session keeps
+    /// it up until then
+    pub fn show_and_wait(&self) -> Result<()> {
+        if let Some(handle) = self.start()? {
+            let _ = handle.join();
+            debug!("Notification UI closed");
+        }
+        Ok(())
+    }
+
+    /// Show the UI, returning the thread it lives on unless the browser shows it
+    fn start(&self) -> Result<Option<std::thread::JoinHandle<()>>> {
+        match self {
+            Self::Balloon { title, message, icon_path, warning } => balloon::show(title, message, icon_path, *warning).map(Some),
+            Self::Urgent { title, message, placement } => urgent::show(title, message, *placement).map(Some),
+            Self::Details { html } => details::open(html).map(|_| None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_ui_round_trip() {
+        let ui = SessionUi::Urgent {
+            title: "Restart required".to_string(),
+            message: "Your computer will restart at 17:00".to_string(),
+            placement: WindowPlacement::All,
+        };
+        let json = serde_json::to_string(&ui).unwrap();
+        assert!(json.contains(r#""kind":"urgent""#));
+        assert!(json.contains(r#""placement":"all""#));
+
+        let SessionUi::Urgent { placement, .. } = serde_json::from_str(&json).unwrap() else {
+            panic!("Expected an urgent window");
+        };
+        assert_eq!(placement, WindowPlacement::All);
+    }
+}
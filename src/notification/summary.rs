@@ -0,0 +1,87 @@
+use super::template::TemplateContext;
+use crate::config::WeeklySummaryConfig;
+use crate::database::{RebootHistory, RebootState};
+use crate::reboot;
+use chrono::{DateTime, Datelike, Duration, Local, NaiveTime, TimeZone, Utc};
+
+/// Notification type the weekly summary is recorded under
+pub const SUMMARY_NOTIFICATION_TYPE: &str = "weekly_summary";
+
+/// How long after its scheduled time a missed summary is still shown
+const SUMMARY_WINDOW_HOURS: i64 = 24;
+
+/// Get the most recent scheduled summary time at or before a time
+pub fn last_scheduled_time(config: &WeeklySummaryConfig, now: DateTime<Local>) -> Option<DateTime<Utc>> {
+    let time = NaiveTime::parse_from_str(&config.time, "%H:%M").ok()?;
+    let days_since = (now.weekday().num_days_from_sunday() as i64 - config.day_of_week as i64).rem_euclid(7);
+
+    let date = now.date_naive() - Duration::days(days_since);
+    let mut scheduled = Local.from_local_datetime(&date.and_time(time)).earliest()?;
+    if scheduled > now {
+        scheduled = Local
+            .from_local_datetime(&(date - Duration::days(7)).and_time(time))
+            .earliest()?;
+    }
+
+    Some(scheduled.with_timezone(&Utc))
+}
+
+/// Check if the summary is due: its scheduled time passed recently and it hasn't been shown since
+pub fn is_due(config: &WeeklySummaryConfig, last_shown: Option<DateTime<Utc>>, now: DateTime<Local>) -> bool {
+    let scheduled = match last_scheduled_time(config, now) {
+        Some(scheduled) => scheduled,
+        None => return false,
+    };
+
+    now.with_timezone(&Utc) - scheduled < Duration::hours(SUMMARY_WINDOW_HOURS)
+        && last_shown.map_or(true, |last| last < scheduled)
+}
+
+/// Add the `{reboots_last_week}` and `{last_reboot}` placeholders from the reboot history, as of `now`
+pub fn add_summary_values(context: &mut TemplateContext, state: &RebootState, history: &[RebootHistory], now: DateTime<Utc>) {
+    let week_ago = now - Duration::days(7);
+
+    let reboots = history.iter().filter(|entry| entry.reboot_time >= week_ago).count();
+    context.set("reboots_last_week", reboots.to_string());
+
+    let last_reboot = history
+        .iter()
+        .map(|entry| entry.reboot_time)
+        .max()
+        .or(state.last_reboot_time);
+    context.set("last_reboot", reboot::format_time_since_last_reboot(last_reboot));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn local(date: (i32, u32, u32), time: (u32, u32)) -> DateTime<Local> {
+        let naive = NaiveDate::from_ymd_opt(date.0, date.1, date.2)
+            .unwrap()
+            .and_hms_opt(time.0, time.1, 0)
+            .unwrap();
+        Local.from_local_datetime(&naive).earliest().unwrap()
+    }
+
+    #[test]
+    fn test_is_due() {
+        // Mondays at 09:00; 2025-05-05 is a Monday
+        let config = WeeklySummaryConfig {
+            day_of_week: 1,
+            time: "09:00".to_string(),
+            message: String::new(),
+        };
+        let scheduled = local((2025, 5, 5), (9, 0)).with_timezone(&Utc);
+
+        assert_eq!(last_scheduled_time(&config, local((2025, 5, 5), (10, 0))), Some(scheduled));
+        assert_eq!(last_scheduled_time(&config, local((2025, 5, 12), (8, 0))), Some(scheduled));
+
+        assert!(is_due(&config, None, local((2025, 5, 5), (9, 30))));
+        assert!(!is_due(&config, None, local((2025, 5, 5), (8, 30))));
+        assert!(!is_due(&config, None, local((2025, 5, 7), (9, 30))));
+        assert!(!is_due(&config, Some(scheduled + Duration::minutes(1)), local((2025, 5, 5), (9, 30))));
+        assert!(is_due(&config, Some(scheduled - Duration::days(7)), local((2025, 5, 6), (8, 0))));
+    }
+}
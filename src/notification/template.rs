@@ -0,0 +1,176 @@
+use crate::config::BrandingConfig;
+use crate::database::RebootState;
+use crate::reboot::detector::SystemInfo;
+use crate::utils::registry;
+use chrono::{DateTime, Local, Utc};
+use log::debug;
+use std::collections::HashMap;
+use windows::Win32::System::Registry::HKEY_LOCAL_MACHINE;
+
+/// Values for the named placeholders in notification messages
+///
+/// Placeholders are written as `{name}` in a message. Unknown placeholders are left as they are.
+#[derive(Debug, Clone, Default)]
+pub struct TemplateContext {
+    values: HashMap<String, String>,
+}
+
+impl TemplateContext {
+    /// Create a context from the current reboot state and system information
+    pub fn new(state: &RebootState, system_info: Option<&SystemInfo>, max_deferrals: Option<u32>) -> Self {
+        let now = Utc::now();
+        let mut context = Self::default();
+
+        let pending_since = state
+            .reboot_required_since
+            .or_else(|| state.sources.iter().map(|s| s.detected_at).min());
+        let hours_pending = pending_since
+            .map(|since| now.signed_duration_since(since).num_hours().max(0))
+            .unwrap_or(0);
+        context.set("hours_pending", hours_pending.to_string());
+        context.set("days_pending", (hours_pending / 24).to_string());
+
+        context.set("deadline", state.scheduled_reboot_time
+            .map(format_local_time)
+            .unwrap_or_else(|| "not scheduled".to_string()));
+
+        context.set("next_reminder", state.next_reminder_time
+            .map(format_local_time)
+            .unwrap_or_else(|| "not scheduled".to_string()));
+
+        let sources: Vec<&str> = state
+            .sources
+            .iter()
+            .map(|s| s.description.as_deref().unwrap_or(&s.name))
+            .collect();
+        context.set("sources", if sources.is_empty() { "none".to_string() } else { sources.join(", ") });
+        context.set("source_count", sources.len().to_string());
+
+        context.set("postpone_count", state.postpone_count.to_string());
+        context.set("deferrals_remaining", match max_deferrals {
+            Some(max) => max.saturating_sub(state.postpone_count).to_string(),
+            None => "unlimited".to_string(),
+        });
+
+        context.set("reboot_reason", state.reboot_reason.clone().unwrap_or_default());
+
+        match system_info {
+            Some(info) => {
+                context.set("computer_name", info.computer_name.clone());
+                context.set("os_version", info.os_version.clone());
+                context.set("domain", info.domain.clone());
+                context.set("uptime_hours", (info.uptime / 3600).to_string());
+            }
+            None => {
+                context.set("computer_name", std::env::var("COMPUTERNAME").unwrap_or_default());
+            }
+        }
+
+        context
+    }
+
+    /// Set the value of a placeholder
+    pub fn set(&mut self, name: &str, value: String) {
+        self.values.insert(name.to_string(), value);
+    }
+
+    /// Get the value of a placeholder
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(|value| value.as_str())
+    }
+}
+
+/// Read the asset tag for the `{asset_tag}` placeholder from the registry value set in the branding
+pub fn read_asset_tag(branding: &BrandingConfig) -> Option<String> {
+    let key_path = branding.asset_tag_registry_key.as_deref()?;
+    let key_path = super::working_hours::strip_hive(key_path, &["HKLM\\", "HKEY_LOCAL_MACHINE\\"]).unwrap_or(key_path);
+
+    match registry::get_string_value(HKEY_LOCAL_MACHINE, key_path, &branding.asset_tag_registry_value) {
+        Ok(value) => value.map(|value| value.trim().to_string()).filter(|value| !value.is_empty()),
+        Err(e) => {
+            debug!("Failed to read asset tag from {}\\{}: {}", key_path, branding.asset_tag_registry_value, e);
+            None
+        }
+    }
+}
+
+/// Replace the named placeholders in a message
+pub fn render(template: &str, context: &TemplateContext) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+
+        match after.find('}') {
+            Some(end) => {
+                let name = &after[..end];
+                match context.get(name) {
+                    Some(value) => output.push_str(value),
+                    None => {
+                        output.push('{');
+                        output.push_str(name);
+                        output.push('}');
+                    }
+                }
+                rest = &after[end + 1..];
+            }
+            None => {
+                output.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+
+    output.push_str(rest);
+    output
+}
+
+/// Format a time in the local time zone for display to the user
+fn format_local_time(time: DateTime<Utc>) -> String {
+    time.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::RebootSource;
+
+    #[test]
+    fn test_render() {
+        let mut context = TemplateContext::default();
+        context.set("computer_name", "PC-01".to_string());
+        context.set("hours_pending", "26".to_string());
+
+        assert_eq!(
+            render("{computer_name} has needed a reboot for {hours_pending} hours.", &context),
+            "PC-01 has needed a reboot for 26 hours."
+        );
+        assert_eq!(render("Unknown {placeholder} stays", &context), "Unknown {placeholder} stays");
+        assert_eq!(render("Unclosed {computer_name", &context), "Unclosed {computer_name");
+        assert_eq!(render("No placeholders", &context), "No placeholders");
+    }
+
+    #[test]
+    fn test_context_from_state() {
+        let mut state = RebootState::new(true, false);
+        state.reboot_required_since = Some(Utc::now() - chrono::Duration::hours(5));
+        state.postpone_count = 1;
+        state.sources = vec![
+            RebootSource::new("Windows Update", Some("Windows Update"), "required"),
+            RebootSource::new("PendingFileRenameOperations", None, "required"),
+        ];
+
+        let context = TemplateContext::new(&state, None, Some(3));
+        assert_eq!(context.get("hours_pending"), Some("5"));
+        assert_eq!(context.get("deferrals_remaining"), Some("2"));
+        assert_eq!(context.get("sources"), Some("Windows Update, PendingFileRenameOperations"));
+        assert_eq!(context.get("source_count"), Some("2"));
+        assert_eq!(context.get("days_pending"), Some("0"));
+        assert_eq!(context.get("deadline"), Some("not scheduled"));
+
+        let context = TemplateContext::new(&state, None, None);
+        assert_eq!(context.get("deferrals_remaining"), Some("unlimited"));
+    }
+}
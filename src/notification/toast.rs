@@ -1,8 +1,42 @@
+use super::details::escape;
+use crate::config::{NotificationSeverity, ToastPriority, ToastSound};
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
 use std::path::Path;
 
+/// App ID toasts are shown under; PowerShell's is registered on every Windows installation
+const POWERSHELL_APP_ID: &str = "{1AC14E77-02E7-4E5D-B744-2EB1AE5198B7}\\WindowsPowerShell\\v1.0\\powershell.exe";
+
+/// Most buttons a toast can show
+const MAX_BUTTONS: usize = 5;
+
+/// Sound and priority of toasts, set per timeframe
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToastStyle {
+    /// Sound to play (None = by severity)
+    pub sound: Option<ToastSound>,
+
+    /// How insistently the toast is shown
+    pub priority: ToastPriority,
+}
+
+/// Button on a toast, opening a `rebootreminder:` link when clicked
+///
+/// The link is handled by a new copy of this program, so buttons keep working after the
+/// process that showed the toast has exited.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToastButton {
+    /// Text of the button
+    pub label: String,
+
+    /// Link opened when the button is clicked
+    pub uri: String,
+}
+
 /// Toast notification
-#[derive(Debug, Clone)]
+///
+/// Serializable so the service can pass it to a copy of this program running in the user's session.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToastNotification {
     /// Title of the notification
     pub title: String,
@@ -13,11 +47,21 @@ pub struct ToastNotification {
     /// Path to the icon
     pub icon_path: String,
 
-    /// Action URI
-    pub action_uri: Option<String>,
-
     /// Unique identifier
     pub id: String,
+
+    /// How prominently the toast is shown
+    pub severity: NotificationSeverity,
+
+    /// Sound and priority of the toast
+    pub style: ToastStyle,
+
+    /// Buttons, shown in order (at most 5)
+    pub buttons: Vec<ToastButton>,
+
+    /// Line identifying the computer and user, shown below the message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subtitle: Option<String>,
 }
 
 impl ToastNotification {
@@ -27,8 +71,11 @@ impl ToastNotification {
             title: title.to_string(),
             message: message.to_string(),
             icon_path: String::new(),
-            action_uri: None,
             id: String::new(),
+            severity: NotificationSeverity::Normal,
+            style: ToastStyle::default(),
+            buttons: Vec::new(),
+            subtitle: None,
         }
     }
 
@@ -38,50 +85,117 @@ impl ToastNotification {
             title: title.to_string(),
             message: message.to_string(),
             icon_path: icon_path.to_string_lossy().to_string(),
-            action_uri: None,
             id: id.to_string(),
+            severity: NotificationSeverity::Normal,
+            style: ToastStyle::default(),
+            buttons: Vec::new(),
+            subtitle: None,
         }
     }
 
     /// Show the notification
     pub fn show(&self) -> Result<()> {
-        use winrt_notification::{Toast, Duration, Sound};
+        use windows::core::HSTRING;
+        use windows::Data::Xml::Dom::XmlDocument;
+        use windows::UI::Notifications::{ToastNotification as WinRtToast, ToastNotificationManager};
 
-        // Create a new toast notification
-        let mut toast = Toast::new(Toast::POWERSHELL_APP_ID);
-        toast = toast.title(&self.title);
-        toast = toast.text1(&self.message);
+        let xml = XmlDocument::new()?;
+        xml.LoadXml(&HSTRING::from(self.to_xml()))?;
+        let toast = WinRtToast::CreateToastNotification(&xml)?;
 
-        // Set longer duration for important notifications
-        toast = toast.duration(Duration::Long);
+        ToastNotificationManager::CreateToastNotifierWithId(&HSTRING::from(POWERSHELL_APP_ID))?.Show(&toast)?;
+        Ok(())
+    }
 
-        // Add a sound for better visibility
-        toast = toast.sound(Some(Sound::Default));
+    /// Build the toast XML
+    ///
+    /// Buttons use protocol activation, so clicking one opens its link instead of calling back
+    /// into this process.
+    pub fn to_xml(&self) -> String {
+        // Keep high-priority toasts on screen until the user acts on them
+        let scenario = match self.style.priority {
+            ToastPriority::Normal => "",
+            ToastPriority::High => " scenario=\"reminder\"",
+            ToastPriority::Urgent => " scenario=\"alarm\"",
+        };
 
-        // Add icon if it exists
-        let icon_path = Path::new(&self.icon_path);
-        if icon_path.exists() {
-            // Skip icon for now due to API compatibility issues
-            // toast = toast.icon(icon_path, IconCrop::Circle, "App Icon");
+        let mut xml = format!("<toast duration=\"long\"{}><visual><binding template=\"ToastGeneric\">", scenario);
+        xml.push_str(&format!("<text>{}</text><text>{}</text>", escape(&self.title), escape(&self.message)));
+        if let Some(subtitle) = &self.subtitle {
+            xml.push_str(&format!("<text placement=\"attribution\">{}</text>", escape(subtitle)));
+        }
+        if Path::new(&self.icon_path).exists() {
+            xml.push_str(&format!("<image placement=\"appLogoOverride\" src=\"{}\"/>", escape(&self.icon_path)));
         }
+        xml.push_str("</binding></visual>");
 
-        // Add action if provided
-        if let Some(action_uri) = &self.action_uri {
-            // For reboot action, add a clear button
-            if action_uri.starts_with("reboot:") {
-                toast = toast.text2("Click to restart your computer now");
-                // In a real implementation, we would use the button API
-                // toast = toast.button("Restart Now", action_uri);
-            } else {
-                // For other actions, just show the action text
-                let action_text = format!("Action: {}", action_uri);
-                toast = toast.text2(&action_text);
+        if !self.buttons.is_empty() {
+            xml.push_str("<actions>");
+            for button in self.buttons.iter().take(MAX_BUTTONS) {
+                xml.push_str(&format!(
+                    "<action content=\"{}\" arguments=\"{}\" activationType=\"protocol\"/>",
+                    escape(&button.label),
+                    escape(&button.uri)
+                ));
             }
+            xml.push_str("</actions>");
         }
 
-        // Show the notification
-        toast.show()?;
+        // Play the configured sound, by default looping an alarm for high-priority reminders
+        xml.push_str(match self.sound() {
+            ToastSound::Silent => "<audio silent=\"true\"/>",
+            ToastSound::Default => "<audio src=\"ms-winsoundevent:Notification.Default\"/>",
+            ToastSound::Alarm => "<audio src=\"ms-winsoundevent:Notification.Looping.Alarm\" loop=\"true\"/>",
+        });
+        xml.push_str("</toast>");
+        xml
+    }
 
-        Ok(())
+    /// Get the sound the toast plays
+    fn sound(&self) -> ToastSound {
+        self.style.sound.unwrap_or_else(|| ToastSound::for_severity(self.severity))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sound() {
+        let mut toast = ToastNotification::new("Restart", "Please restart");
+        assert_eq!(toast.sound(), ToastSound::Default);
+
+        toast.severity = NotificationSeverity::High;
+        assert_eq!(toast.sound(), ToastSound::Alarm);
+
+        toast.style.sound = Some(ToastSound::Silent);
+        assert_eq!(toast.sound(), ToastSound::Silent);
+    }
+
+    #[test]
+    fn test_to_xml() {
+        let mut toast = ToastNotification::new("Restart", "Save <your> work");
+        toast.style.priority = ToastPriority::Urgent;
+        toast.buttons = (0..7)
+            .map(|i| ToastButton {
+                label: format!("Option {}", i),
+                uri: format!("rebootreminder:postpone?option={}&notification=x", i),
+            })
+            .collect();
+
+        let xml = toast.to_xml();
+        assert!(xml.starts_with("<toast duration=\"long\" scenario=\"alarm\">"));
+        assert!(xml.contains("<text>Save &lt;your&gt; work</text>"));
+        assert!(xml.contains(
+            "<action content=\"Option 0\" arguments=\"rebootreminder:postpone?option=0&amp;notification=x\" activationType=\"protocol\"/>"
+        ));
+        assert_eq!(xml.matches("<action ").count(), MAX_BUTTONS);
+        assert!(xml.contains("ms-winsoundevent:Notification.Default"));
+        assert!(!xml.contains("appLogoOverride"));
+        assert!(!xml.contains("attribution"));
+
+        toast.subtitle = Some("PC-042 - A1234 - jdoe".to_string());
+        assert!(toast.to_xml().contains("<text placement=\"attribution\">PC-042 - A1234 - jdoe</text>"));
     }
 }
@@ -1,52 +1,267 @@
-use crate::database::DbPool;
+use super::{protocol, ACKNOWLEDGE_ACTION, DETAILS_ACTION, DEFER_ACTION_PREFIX, SNOOZE_UNTIL_ACTION_PREFIX};
+use crate::config::{DeferralOption, TrayLabels};
+use crate::database::{DbPool, DeliveryStatus, Notification, NotificationInteraction, RebootState};
+use crate::utils::clock::Clock;
+use crate::utils::{copy_wide, to_wide};
 use anyhow::{Context, Result};
-use log::debug;
+use chrono::{DateTime, Duration, Local, Utc};
+use log::{debug, warn};
 use std::path::Path;
-use systray::Application;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use windows::core::{w, PCWSTR};
+use windows::Win32::Foundation::{HINSTANCE, HWND, LPARAM, LRESULT, POINT, WPARAM};
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::UI::Shell::{
+    Shell_NotifyIconW, NIF_ICON, NIF_MESSAGE, NIF_TIP, NIM_ADD, NIM_DELETE, NIM_MODIFY, NOTIFYICONDATAW,
+};
+use windows::Win32::UI::WindowsAndMessaging::{
+    AppendMenuW, CreatePopupMenu, CreateWindowExW, DefWindowProcW, DestroyIcon, DestroyMenu, DestroyWindow,
+    DispatchMessageW, GetCursorPos, GetMessageW, GetWindowLongPtrW, LoadIconW, LoadImageW, PostMessageW,
+    PostQuitMessage, RegisterClassW, SetForegroundWindow, SetWindowLongPtrW, TrackPopupMenuEx, TranslateMessage,
+    GWLP_USERDATA, HICON, HMENU, IDI_APPLICATION, IMAGE_ICON, LR_DEFAULTSIZE, LR_LOADFROMFILE, MF_GRAYED, MF_POPUP,
+    MF_SEPARATOR, MF_STRING, MSG, TPM_RETURNCMD, TPM_RIGHTBUTTON, WINDOW_EX_STYLE, WINDOW_STYLE, WM_APP, WM_CLOSE,
+    WM_DESTROY, WM_LBUTTONUP, WM_NULL, WM_RBUTTONUP, WNDCLASSW,
+};
+
+/// Identifier of the notification area icon within its window
+const ICON_ID: u32 = 1;
+
+/// Message the notification area icon sends its window on mouse events
+const TRAY_MESSAGE: u32 = WM_APP + 1;
+
+/// Number of notifications listed under "Recent Notifications"
+const RECENT_NOTIFICATIONS: usize = 5;
+
+/// Number of notifications read to find the recent ones of the current user
+const RECENT_NOTIFICATIONS_SCAN: u32 = 100;
+
+/// Length notification messages are shortened to in the menu
+const RECENT_MESSAGE_LENGTH: usize = 40;
+
+/// Reboot state shown by the tray icon
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrayState {
+    NoReboot,
+    RebootRecommended,
+    RebootRequired,
+    RebootImminent,
+}
+
+impl TrayState {
+    /// Get the state to show for a reboot state
+    ///
+    /// A required reboot is imminent when the scheduled reboot or the deadline is less than
+    /// `DEADLINE_COUNTDOWN_MINUTES` away.
+    pub fn from_reboot_state(state: &RebootState, deadline: Option<DateTime<Utc>>, now: DateTime<Utc>) -> Self {
+        if !state.reboot_required {
+            return if state.reboot_recommended { Self::RebootRecommended } else { Self::NoReboot };
+        }
+
+        let countdown = Duration::minutes(crate::reboot::DEADLINE_COUNTDOWN_MINUTES);
+        let imminent = [state.scheduled_reboot_time, deadline]
+            .into_iter()
+            .flatten()
+            .any(|time| time - now <= countdown);
+        if imminent {
+            Self::RebootImminent
+        } else {
+            Self::RebootRequired
+        }
+    }
+
+    /// Get the text shown in the tooltip for the state
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::NoReboot => "No restart needed",
+            Self::RebootRecommended => "Restart recommended",
+            Self::RebootRequired => "Restart required",
+            Self::RebootImminent => "Restart imminent",
+        }
+    }
+}
+
+/// Command run when a menu item is selected
+type MenuCallback = Arc<Mutex<dyn FnMut() -> Result<()> + Send>>;
+
+/// Entries of a submenu, read each time the menu opens
+type MenuEntries = Arc<dyn Fn() -> Vec<String> + Send + Sync>;
+
+/// Item of the tray menu
+#[derive(Clone)]
+enum MenuItem {
+    /// Item running a command, closing the tray afterwards if `quit` is set
+    Command {
+        id: u32,
+        label: String,
+        enabled: bool,
+        quit: bool,
+        callback: MenuCallback,
+    },
+
+    /// Text without a command
+    Text(String),
+
+    /// Separator line
+    Separator,
+
+    /// Submenu of text entries
+    Submenu { label: String, entries: MenuEntries },
+}
+
+/// Part of the tray menu, from top to bottom
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    /// Title and status
+    Header,
+
+    /// Reboot and deferral items, rebuilt when the reboot state changes
+    Reboot,
+
+    /// Everything else
+    Other,
+}
+
+/// Items of the tray menu, from which the popup menu is built each time it opens
+#[derive(Default)]
+struct Menu {
+    header: Vec<MenuItem>,
+    reboot: Vec<MenuItem>,
+    other: Vec<MenuItem>,
+    next_id: u32,
+}
+
+impl Menu {
+    /// Get the items of a section
+    fn section(&mut self, section: Section) -> &mut Vec<MenuItem> {
+        match section {
+            Section::Header => &mut self.header,
+            Section::Reboot => &mut self.reboot,
+            Section::Other => &mut self.other,
+        }
+    }
+
+    /// Add an item running a command to a section and return its ID
+    fn add_command(&mut self, section: Section, label: &str, quit: bool, callback: MenuCallback) -> u32 {
+        self.next_id += 1;
+        let id = self.next_id;
+        self.section(section).push(MenuItem::Command {
+            id,
+            label: label.to_string(),
+            enabled: true,
+            quit,
+            callback,
+        });
+        id
+    }
+
+    /// Enable or disable the item with an ID
+    fn set_enabled(&mut self, item_id: u32, enable: bool) {
+        for item in self.header.iter_mut().chain(&mut self.reboot).chain(&mut self.other) {
+            if let MenuItem::Command { id, enabled, .. } = item {
+                if *id == item_id {
+                    *enabled = enable;
+                }
+            }
+        }
+    }
+
+    /// Remove the item with an ID
+    fn remove(&mut self, item_id: u32) {
+        for section in [&mut self.header, &mut self.reboot, &mut self.other] {
+            section.retain(|item| !matches!(item, MenuItem::Command { id, .. } if *id == item_id));
+        }
+    }
+
+    /// Get the items in the order they're shown, with separators between the sections
+    fn items(&self) -> Vec<MenuItem> {
+        let mut items = Vec::new();
+        for section in [&self.header, &self.reboot, &self.other] {
+            if section.is_empty() {
+                continue;
+            }
+            if !items.is_empty() {
+                items.push(MenuItem::Separator);
+            }
+            items.extend(section.iter().cloned());
+        }
+        items
+    }
+}
 
 /// Tray manager
+///
+/// The notification area icon is owned by a hidden window on its own thread. The popup menu is
+/// built from the current items each time it opens, so submenus like the recent notifications
+/// are always up to date.
 pub struct TrayManager {
-    app: Application,
+    /// Window owning the icon, kept as a number so the manager can move between threads
+    window: isize,
+
+    /// Icon loaded from a file, destroyed when replaced
+    icon: Option<isize>,
+
+    menu: Arc<Mutex<Menu>>,
+    db_pool: DbPool,
+    clock: Arc<dyn Clock>,
     title: String,
+    labels: TrayLabels,
     status_item_id: Option<u32>,
     reboot_item_id: Option<u32>,
     postpone_item_id: Option<u32>,
     #[allow(dead_code)]
     deferral_menu_id: Option<u32>,
-    #[allow(dead_code)]
     deferral_item_ids: Vec<u32>,
+    deferrals: Vec<DeferralOption>,
+    max_deferrals: Option<u32>,
+    state: Option<TrayState>,
 }
 
 impl TrayManager {
     /// Create a new tray manager
-    pub fn new<P: AsRef<Path>>(title: &str, icon_path: P, _db_pool: DbPool) -> Result<Self> {
+    pub fn new<P: AsRef<Path>>(
+        title: &str,
+        icon_path: P,
+        db_pool: DbPool,
+        clock: Arc<dyn Clock>,
+        labels: TrayLabels,
+    ) -> Result<Self> {
         debug!("Creating tray manager");
 
-        // Create application
-        let app = Application::new().context("Failed to create tray application")?;
-
-        // Set icon
-        let icon_path = icon_path.as_ref();
-        if icon_path.exists() {
-            app.set_icon_from_file(&icon_path.to_string_lossy())
-                .context("Failed to set tray icon")?;
-        }
-
-        // Set tooltip
-        app.set_tooltip(title)
-            .context("Failed to set tray tooltip")?;
+        let menu = Arc::new(Mutex::new(Menu::default()));
+        let window = start_window(menu.clone())?;
 
         // Create tray manager
         let mut tray = Self {
-            app,
+            window,
+            icon: None,
+            menu,
+            db_pool,
+            clock,
             title: title.to_string(),
+            labels,
             status_item_id: None,
             reboot_item_id: None,
             postpone_item_id: None,
             deferral_menu_id: None,
             deferral_item_ids: Vec::new(),
+            deferrals: Vec::new(),
+            max_deferrals: None,
+            state: None,
         };
 
+        // Add the icon with its tooltip
+        let icon = tray.load_icon(icon_path.as_ref());
+        let mut data = tray.icon_data();
+        data.uFlags = NIF_ICON | NIF_TIP | NIF_MESSAGE;
+        data.uCallbackMessage = TRAY_MESSAGE;
+        data.hIcon = icon.unwrap_or_else(|| unsafe { LoadIconW(None, IDI_APPLICATION).unwrap_or_default() });
+        copy_wide(&mut data.szTip, title);
+        if !unsafe { Shell_NotifyIconW(NIM_ADD, &data) }.as_bool() {
+            return Err(anyhow::anyhow!("Failed to add the notification area icon"));
+        }
+        tray.icon = icon.map(|icon| icon.0 as isize);
+
         // Initialize menu
         tray.init_menu()?;
 
@@ -57,18 +272,90 @@ impl TrayManager {
     fn init_menu(&mut self) -> Result<()> {
         debug!("Initializing tray menu");
 
+        let mut menu = self.lock_menu()?;
+
         // Add title
-        self.app
-            .add_menu_item(&self.title, |_| {
-                Ok::<(), systray::Error>(())
-            })
-            .context("Failed to add title to tray menu")?;
+        menu.header.push(MenuItem::Text(self.title.clone()));
+
+        // List what the user was shown and did, read again each time the menu opens
+        let db_pool = self.db_pool.clone();
+        let user_name = std::env::var("USERNAME").unwrap_or_default();
+        menu.other.push(MenuItem::Submenu {
+            label: self.labels.recent_notifications.clone().unwrap_or_else(|| "Recent Notifications".to_string()),
+            entries: Arc::new(move || match recent_notifications(&db_pool, &user_name) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    warn!("Failed to read recent notifications: {}", e);
+                    Vec::new()
+                }
+            }),
+        });
+
+        Ok(())
+    }
 
-        // Add separator
-        self.app
-            .add_menu_separator()
-            .context("Failed to add separator to tray menu")?;
+    /// Lock the menu items
+    fn lock_menu(&self) -> Result<std::sync::MutexGuard<'_, Menu>> {
+        self.menu
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire lock on tray menu: {}", e))
+    }
 
+    /// Add a menu item running a callback to a section
+    fn add_command<F>(&mut self, section: Section, label: &str, quit: bool, callback: F) -> Result<u32>
+    where
+        F: FnMut() -> Result<()> + Send + Sync + 'static,
+    {
+        let callback: MenuCallback = Arc::new(Mutex::new(callback));
+        Ok(self.lock_menu()?.add_command(section, label, quit, callback))
+    }
+
+    /// Enable or disable a menu item, if it exists
+    fn set_enabled(&self, id: Option<u32>, enable: bool) -> Result<()> {
+        if let Some(id) = id {
+            self.lock_menu()?.set_enabled(id, enable);
+        }
+        Ok(())
+    }
+
+    /// Get the data identifying the notification area icon
+    fn icon_data(&self) -> NOTIFYICONDATAW {
+        NOTIFYICONDATAW {
+            cbSize: std::mem::size_of::<NOTIFYICONDATAW>() as u32,
+            hWnd: HWND(self.window as *mut _),
+            uID: ICON_ID,
+            ..Default::default()
+        }
+    }
+
+    /// Load an icon from a file, or None if it doesn't exist or can't be loaded
+    fn load_icon(&self, icon_path: &Path) -> Option<HICON> {
+        if !icon_path.exists() {
+            debug!("Tray icon {:?} not found", icon_path);
+            return None;
+        }
+
+        let path = to_wide(&icon_path.to_string_lossy());
+        let icon = unsafe {
+            LoadImageW(None, PCWSTR::from_raw(path.as_ptr()), IMAGE_ICON, 0, 0, LR_LOADFROMFILE | LR_DEFAULTSIZE)
+        };
+        match icon {
+            Ok(handle) => Some(HICON(handle.0)),
+            Err(e) => {
+                warn!("Failed to load tray icon {:?}: {}", icon_path, e);
+                None
+            }
+        }
+    }
+
+    /// Set the tooltip of the icon
+    fn set_tooltip(&self, tooltip: &str) -> Result<()> {
+        let mut data = self.icon_data();
+        data.uFlags = NIF_TIP;
+        copy_wide(&mut data.szTip, tooltip);
+        if !unsafe { Shell_NotifyIconW(NIM_MODIFY, &data) }.as_bool() {
+            return Err(anyhow::anyhow!("Failed to set tray tooltip"));
+        }
         Ok(())
     }
 
@@ -78,19 +365,9 @@ impl TrayManager {
         debug!("Adding status item to tray menu");
 
         // Add status item
-        let status_id = self.app
-            .add_menu_item(&format!("Status: {}", status), |_| {
-                Ok::<(), systray::Error>(())
-            })
-            .context("Failed to add status item to tray menu")?;
-
+        let status_id = self.add_command(Section::Header, &format!("Status: {}", status), false, || Ok(()))?;
         self.status_item_id = Some(status_id);
 
-        // Add separator
-        self.app
-            .add_menu_separator()
-            .context("Failed to add separator to tray menu")?;
-
         Ok(())
     }
 
@@ -99,36 +376,52 @@ impl TrayManager {
         debug!("Updating status: {}", status);
 
         if let Some(_status_id) = self.status_item_id {
-            // The systray crate doesn't support updating menu items
-            // We'll need to remove the old one and add a new one
-            // But the crate doesn't support removing items either
-            // So we'll just leave it as is for now
+            // The status item isn't updated yet
+        }
+
+        Ok(())
+    }
+
+    /// Show a reboot state with its icon and in the tooltip
+    ///
+    /// Nothing changes if the state is already shown.
+    pub fn set_state(&mut self, state: TrayState, icon_path: &Path) -> Result<()> {
+        if self.state == Some(state) {
+            return Ok(());
+        }
+        debug!("Updating tray state: {:?}", state);
+
+        match self.load_icon(icon_path) {
+            Some(icon) => {
+                let mut data = self.icon_data();
+                data.uFlags = NIF_ICON;
+                data.hIcon = icon;
+                if !unsafe { Shell_NotifyIconW(NIM_MODIFY, &data) }.as_bool() {
+                    let _ = unsafe { DestroyIcon(icon) };
+                    return Err(anyhow::anyhow!("Failed to set tray icon"));
+                }
+                if let Some(previous) = self.icon.replace(icon.0 as isize) {
+                    let _ = unsafe { DestroyIcon(HICON(previous as *mut _)) };
+                }
+            }
+            None => debug!("Keeping the current tray icon"),
         }
 
+        self.set_tooltip(&format!("{} - {}", self.title, state.label()))?;
+
+        self.state = Some(state);
         Ok(())
     }
 
     /// Add a reboot item to the tray menu
-    #[allow(dead_code)]
     pub fn add_reboot_item<F>(&mut self, callback: F) -> Result<()>
     where
         F: FnMut() -> Result<()> + Send + Sync + 'static,
     {
         debug!("Adding reboot item to tray menu");
 
-        // Create a boxed callback
-        let mut callback = Box::new(callback);
-
-        // Add reboot item
-        let reboot_id = self.app
-            .add_menu_item("Reboot Now", move |_| {
-                match callback() {
-                    Ok(()) => Ok::<(), systray::Error>(()),
-                    Err(_) => Ok::<(), systray::Error>(()),
-                }
-            })
-            .context("Failed to add reboot item to tray menu")?;
-
+        let label = self.labels.reboot_now.clone().unwrap_or_else(|| "Reboot Now".to_string());
+        let reboot_id = self.add_command(Section::Reboot, &label, false, callback)?;
         self.reboot_item_id = Some(reboot_id);
 
         Ok(())
@@ -142,25 +435,27 @@ impl TrayManager {
     {
         debug!("Adding postpone item to tray menu");
 
-        // Create a boxed callback
-        let mut callback = Box::new(callback);
+        let label = self.labels.postpone.clone().unwrap_or_else(|| "Postpone Reboot".to_string());
+        let postpone_id = self.add_command(Section::Reboot, &label, false, callback)?;
+        self.postpone_item_id = Some(postpone_id);
 
-        // Add postpone item
-        let postpone_id = self.app
-            .add_menu_item("Postpone Reboot", move |_| {
-                match callback() {
-                    Ok(()) => Ok::<(), systray::Error>(()),
-                    Err(_) => Ok::<(), systray::Error>(()),
-                }
-            })
-            .context("Failed to add postpone item to tray menu")?;
+        Ok(())
+    }
 
-        self.postpone_item_id = Some(postpone_id);
+    /// Add an item that shows why a reboot is needed to the tray menu
+    pub fn add_details_item<F>(&mut self, callback: F) -> Result<()>
+    where
+        F: FnMut() -> Result<()> + Send + Sync + 'static,
+    {
+        debug!("Adding details item to tray menu");
+
+        let label = self.labels.details.clone().unwrap_or_else(|| "Why Restart?".to_string());
+        self.add_command(Section::Other, &label, false, callback)?;
 
         Ok(())
     }
 
-    /// Add a quit item to the tray menu
+    /// Add a quit item to the tray menu, which removes the icon once the callback succeeds
     #[allow(dead_code)]
     pub fn add_quit_item<F>(&mut self, callback: F) -> Result<()>
     where
@@ -168,21 +463,8 @@ impl TrayManager {
     {
         debug!("Adding quit item to tray menu");
 
-        // Create a boxed callback
-        let mut callback = Box::new(callback);
-
-        // Add quit item
-        self.app
-            .add_menu_item("Quit", move |app| {
-                match callback() {
-                    Ok(()) => {
-                        app.quit();
-                        Ok::<(), systray::Error>(())
-                    },
-                    Err(_) => Ok::<(), systray::Error>(()),
-                }
-            })
-            .context("Failed to add quit item to tray menu")?;
+        let label = self.labels.quit.clone().unwrap_or_else(|| "Quit".to_string());
+        self.add_command(Section::Other, &label, true, callback)?;
 
         Ok(())
     }
@@ -192,8 +474,7 @@ impl TrayManager {
     pub fn remove_menu_item(&mut self, id: u32) -> Result<()> {
         debug!("Removing menu item: {}", id);
 
-        // The systray crate doesn't support removing menu items
-        // We'll just leave it as is for now
+        self.lock_menu()?.remove(id);
 
         Ok(())
     }
@@ -202,49 +483,36 @@ impl TrayManager {
     pub fn enable_reboot_item(&mut self) -> Result<()> {
         debug!("Enabling reboot item");
 
-        if let Some(_) = self.reboot_item_id {
-            // The systray crate doesn't support enabling/disabling items
-            // We'll just leave it as is for now
-        }
-
-        Ok(())
+        self.set_enabled(self.reboot_item_id, true)
     }
 
     /// Disable reboot item
-    #[allow(dead_code)]
     pub fn disable_reboot_item(&mut self) -> Result<()> {
         debug!("Disabling reboot item");
 
-        if let Some(_id) = self.reboot_item_id {
-            // The systray crate doesn't support enabling/disabling items
-            // We'll just leave it as is for now
-        }
-
-        Ok(())
+        self.set_enabled(self.reboot_item_id, false)
     }
 
-    /// Enable postpone item
+    /// Enable the postpone item and the deferral items
     pub fn enable_postpone_item(&mut self) -> Result<()> {
         debug!("Enabling postpone item");
 
-        if let Some(_id) = self.postpone_item_id {
-            // The systray crate doesn't support enabling/disabling items
-            // We'll just leave it as is for now
-        }
-
-        Ok(())
+        self.set_postpone_enabled(true)
     }
 
-    /// Disable postpone item
-    #[allow(dead_code)]
+    /// Disable the postpone item and the deferral items
     pub fn disable_postpone_item(&mut self) -> Result<()> {
         debug!("Disabling postpone item");
 
-        if let Some(_id) = self.postpone_item_id {
-            // The systray crate doesn't support enabling/disabling items
-            // We'll just leave it as is for now
-        }
+        self.set_postpone_enabled(false)
+    }
 
+    /// Enable or disable the postpone item and the deferral items
+    fn set_postpone_enabled(&self, enable: bool) -> Result<()> {
+        let mut menu = self.lock_menu()?;
+        for id in self.postpone_item_id.iter().chain(&self.deferral_item_ids) {
+            menu.set_enabled(*id, enable);
+        }
         Ok(())
     }
 
@@ -253,46 +521,413 @@ impl TrayManager {
     pub fn add_deferral_menu(&mut self) -> Result<()> {
         debug!("Adding deferral menu");
 
-        // The systray crate doesn't support submenus
-        // We'll just add deferral items directly to the main menu
+        // Deferral items are added directly to the main menu
 
         Ok(())
     }
 
     /// Add a deferral item to the tray menu
-    #[allow(dead_code)]
     pub fn add_deferral_item<F>(&mut self, label: &str, callback: F) -> Result<u32>
     where
         F: FnMut() -> Result<()> + Send + Sync + 'static,
     {
         debug!("Adding deferral item to tray menu: {}", label);
 
-        // Create a boxed callback
-        let mut callback = Box::new(callback);
-
-        // Add deferral item
-        let id = self.app
-            .add_menu_item(label, move |_| {
-                match callback() {
-                    Ok(()) => Ok::<(), systray::Error>(()),
-                    Err(_) => Ok::<(), systray::Error>(()),
-                }
-            })
-            .context("Failed to add deferral item to tray menu")?;
-
+        let id = self.add_command(Section::Reboot, label, false, callback)?;
         self.deferral_item_ids.push(id);
 
         Ok(id)
     }
 
     /// Clear all deferral items
-    #[allow(dead_code)]
     pub fn clear_deferral_items(&mut self) -> Result<()> {
         debug!("Clearing deferral items");
 
-        // The systray crate doesn't support removing menu items
-        // We'll just leave them as is for now
+        for id in std::mem::take(&mut self.deferral_item_ids) {
+            self.remove_menu_item(id)?;
+        }
 
         Ok(())
     }
+
+    /// Set the deferral options offered in the menu from the next `rebuild_menu`, and how many
+    /// times a reboot may be postponed with them
+    pub fn set_deferral_options(&mut self, deferrals: &[DeferralOption], max_deferrals: Option<u32>) {
+        self.deferrals = deferrals.to_vec();
+        self.max_deferrals = max_deferrals;
+    }
+
+    /// Rebuild the reboot items of the menu for a reboot state
+    ///
+    /// The menu offers "Reboot Now" and the deferral options, which are only enabled while a
+    /// reboot is required. Rebooting goes through the service, like the toast's restart button.
+    /// Once the reboot has been postponed as often as allowed, the deferral options are left out.
+    pub fn rebuild_menu(&mut self, state: &RebootState) -> Result<()> {
+        debug!("Rebuilding tray menu, reboot required: {}", state.reboot_required);
+
+        self.lock_menu()?.reboot.clear();
+        self.reboot_item_id = None;
+        self.postpone_item_id = None;
+        self.deferral_item_ids.clear();
+
+        self.add_reboot_item(request_reboot)?;
+        let exhausted = crate::reboot::deferrals_exhausted(self.max_deferrals, state);
+        if !exhausted {
+            for deferral in self.deferrals.clone() {
+                let db_pool = self.db_pool.clone();
+                let clock = self.clock.clone();
+                let option = deferral.clone();
+                let max_deferrals = self.max_deferrals;
+                self.add_deferral_item(deferral.label(), move || {
+                    super::defer_from_tray(&db_pool, &option, max_deferrals, clock.now())
+                })?;
+            }
+        }
+
+        if state.reboot_required {
+            self.enable_reboot_item()?;
+            if exhausted {
+                self.disable_postpone_item()
+            } else {
+                self.enable_postpone_item()
+            }
+        } else {
+            self.disable_reboot_item()?;
+            self.disable_postpone_item()
+        }
+    }
+}
+
+impl Drop for TrayManager {
+    fn drop(&mut self) {
+        // Closing the window removes the icon and ends its thread
+        unsafe {
+            let _ = PostMessageW(Some(HWND(self.window as *mut _)), WM_CLOSE, WPARAM(0), LPARAM(0));
+            if let Some(icon) = self.icon {
+                let _ = DestroyIcon(HICON(icon as *mut _));
+            }
+        }
+    }
+}
+
+/// Create the hidden window owning the icon on its own thread, which runs until it's closed
+fn start_window(menu: Arc<Mutex<Menu>>) -> Result<isize> {
+    let (sender, receiver) = mpsc::channel();
+
+    thread::Builder::new()
+        .name("tray".to_string())
+        .spawn(move || unsafe {
+            let window = match create_window() {
+                Ok(window) => window,
+                Err(e) => {
+                    let _ = sender.send(Err(e));
+                    return;
+                }
+            };
+
+            // The window procedure finds the menu through the window; it stays alive on this
+            // thread until the window is gone
+            SetWindowLongPtrW(window, GWLP_USERDATA, Arc::as_ptr(&menu) as isize);
+            let _ = sender.send(Ok(window.0 as isize));
+
+            let mut msg = MSG::default();
+            while GetMessageW(&mut msg, None, 0, 0).as_bool() {
+                let _ = TranslateMessage(&msg);
+                DispatchMessageW(&msg);
+            }
+
+            SetWindowLongPtrW(window, GWLP_USERDATA, 0);
+            drop(menu);
+            debug!("Tray window closed");
+        })
+        .context("Failed to start a thread for the tray")?;
+
+    receiver.recv().context("Tray thread exited unexpectedly")?
+}
+
+/// Create the hidden window that receives the icon's mouse events
+unsafe fn create_window() -> Result<HWND> {
+    let instance: HINSTANCE = GetModuleHandleW(None).context("Failed to get module handle")?.into();
+    let class = WNDCLASSW {
+        lpfnWndProc: Some(window_proc),
+        hInstance: instance,
+        lpszClassName: w!("RebootReminderTray"),
+        ..Default::default()
+    };
+    // Registering again fails harmlessly once the class exists
+    RegisterClassW(&class);
+
+    // A hidden top-level window rather than a message-only one, so the popup menu closes when
+    // the user clicks elsewhere
+    CreateWindowExW(
+        WINDOW_EX_STYLE::default(),
+        w!("RebootReminderTray"),
+        w!("RebootReminderTray"),
+        WINDOW_STYLE::default(),
+        0,
+        0,
+        0,
+        0,
+        None,
+        None,
+        Some(instance),
+        None,
+    )
+    .context("Failed to create window for the tray")
+}
+
+/// Show the menu when the icon is clicked, and remove the icon when the window is destroyed
+unsafe extern "system" fn window_proc(window: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    match message {
+        TRAY_MESSAGE => {
+            let event = (lparam.0 & 0xFFFF) as u32;
+            if event == WM_RBUTTONUP || event == WM_LBUTTONUP {
+                let menu = GetWindowLongPtrW(window, GWLP_USERDATA) as *const Mutex<Menu>;
+                if !menu.is_null() {
+                    show_menu(window, &*menu);
+                }
+            }
+            LRESULT(0)
+        }
+        WM_DESTROY => {
+            let data = NOTIFYICONDATAW {
+                cbSize: std::mem::size_of::<NOTIFYICONDATAW>() as u32,
+                hWnd: window,
+                uID: ICON_ID,
+                ..Default::default()
+            };
+            let _ = Shell_NotifyIconW(NIM_DELETE, &data);
+            PostQuitMessage(0);
+            LRESULT(0)
+        }
+        _ => DefWindowProcW(window, message, wparam, lparam),
+    }
+}
+
+/// Build the popup menu from the current items, show it and run the selected command
+unsafe fn show_menu(window: HWND, menu: &Mutex<Menu>) {
+    // Work on a copy, so submenu entries are read and commands run without holding the lock
+    let items = match menu.lock() {
+        Ok(menu) => menu.items(),
+        Err(e) => {
+            warn!("Failed to acquire lock on tray menu: {}", e);
+            return;
+        }
+    };
+    let popup = match build_menu(&items) {
+        Ok(popup) => popup,
+        Err(e) => {
+            warn!("Failed to build tray menu: {}", e);
+            return;
+        }
+    };
+
+    let mut point = POINT::default();
+    let _ = GetCursorPos(&mut point);
+    // The menu only closes when clicking elsewhere if its window is in the foreground
+    let _ = SetForegroundWindow(window);
+    let command = TrackPopupMenuEx(popup, (TPM_RETURNCMD | TPM_RIGHTBUTTON).0, point.x, point.y, window, None).0 as u32;
+    let _ = PostMessageW(Some(window), WM_NULL, WPARAM(0), LPARAM(0));
+    let _ = DestroyMenu(popup);
+
+    let selected = items.iter().find_map(|item| match item {
+        MenuItem::Command { id, label, quit, callback, .. } if *id == command => Some((label, *quit, callback)),
+        _ => None,
+    });
+    if let Some((label, quit, callback)) = selected {
+        debug!("Tray menu item selected: {}", label);
+        let result = match callback.lock() {
+            Ok(mut callback) => (&mut *callback)(),
+            Err(e) => Err(anyhow::anyhow!("Failed to acquire lock on tray menu command: {}", e)),
+        };
+        match result {
+            Ok(()) if quit => {
+                let _ = DestroyWindow(window);
+            }
+            Ok(()) => {}
+            Err(e) => warn!("Tray menu item {} failed: {}", label, e),
+        }
+    }
+}
+
+/// Build a popup menu from menu items
+unsafe fn build_menu(items: &[MenuItem]) -> Result<HMENU> {
+    let menu = CreatePopupMenu().context("Failed to create tray menu")?;
+    for item in items {
+        match item {
+            MenuItem::Command { id, label, enabled, .. } => {
+                let flags = if *enabled { MF_STRING } else { MF_STRING | MF_GRAYED };
+                let label = to_wide(label);
+                AppendMenuW(menu, flags, *id as usize, PCWSTR::from_raw(label.as_ptr()))?;
+            }
+            MenuItem::Text(text) => {
+                let text = to_wide(text);
+                AppendMenuW(menu, MF_STRING, 0, PCWSTR::from_raw(text.as_ptr()))?;
+            }
+            MenuItem::Separator => AppendMenuW(menu, MF_SEPARATOR, 0, PCWSTR::null())?,
+            MenuItem::Submenu { label, entries } => {
+                let submenu = CreatePopupMenu().context("Failed to create tray submenu")?;
+                let entries = entries();
+                if entries.is_empty() {
+                    AppendMenuW(submenu, MF_STRING | MF_GRAYED, 0, w!("(none)"))?;
+                }
+                for entry in entries {
+                    let entry = to_wide(&entry);
+                    AppendMenuW(submenu, MF_STRING, 0, PCWSTR::from_raw(entry.as_ptr()))?;
+                }
+                let label = to_wide(label);
+                AppendMenuW(menu, MF_POPUP, submenu.0 as usize, PCWSTR::from_raw(label.as_ptr()))?;
+            }
+        }
+    }
+    Ok(menu)
+}
+
+/// Ask the service to restart the computer
+fn request_reboot() -> Result<()> {
+    let request = crate::ipc::IpcRequest {
+        uri: format!("{}:reboot", protocol::SCHEME),
+    };
+    let response = crate::ipc::send(&request)?;
+    if !response.ok {
+        anyhow::bail!("The service could not restart the computer: {}", response.message);
+    }
+    Ok(())
+}
+
+/// Describe the last notifications shown to a user and what they did with them, newest first
+fn recent_notifications(db_pool: &DbPool, user_name: &str) -> Result<Vec<String>> {
+    let notifications: Vec<Notification> = crate::database::get_notifications(db_pool, Some(RECENT_NOTIFICATIONS_SCAN))?
+        .into_iter()
+        .filter(|notification| notification.delivery_status != DeliveryStatus::Skipped)
+        .filter(|notification| {
+            notification
+                .user_name
+                .as_deref()
+                .is_some_and(|name| name.eq_ignore_ascii_case(user_name))
+        })
+        .take(RECENT_NOTIFICATIONS)
+        .collect();
+    if notifications.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let interactions = crate::database::get_notification_interactions(db_pool)?;
+    Ok(notifications
+        .iter()
+        .map(|notification| {
+            let taken: Vec<&NotificationInteraction> = interactions
+                .iter()
+                .filter(|interaction| interaction.notification_id == notification.id)
+                .collect();
+            history_label(notification, &taken)
+        })
+        .collect())
+}
+
+/// Describe a notification and the last action taken on it for the menu
+fn history_label(notification: &Notification, interactions: &[&NotificationInteraction]) -> String {
+    let time = notification.timestamp.with_timezone(&Local).format("%a %d %b %H:%M");
+    let mut message: String = notification.message.chars().take(RECENT_MESSAGE_LENGTH).collect();
+    if notification.message.chars().count() > RECENT_MESSAGE_LENGTH {
+        message.push('…');
+    }
+    let action = interactions
+        .iter()
+        .max_by_key(|interaction| interaction.timestamp)
+        .map(|interaction| action_label(&interaction.action))
+        .unwrap_or_else(|| "No action".to_string());
+
+    format!("{} - {} - {}", time, message, action)
+}
+
+/// Describe a notification action for users
+fn action_label(action: &str) -> String {
+    if action.starts_with("reboot:") {
+        "Restarted".to_string()
+    } else if let Some(option) = action.strip_prefix(DEFER_ACTION_PREFIX) {
+        format!("Postponed ({})", option)
+    } else if let Some(time) = action.strip_prefix(SNOOZE_UNTIL_ACTION_PREFIX) {
+        format!("Snoozed until {}", time)
+    } else if action == ACKNOWLEDGE_ACTION {
+        "Acknowledged".to_string()
+    } else if action == DETAILS_ACTION {
+        "Opened details".to_string()
+    } else {
+        action.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tray_state() {
+        let now = Utc::now();
+        assert_eq!(TrayState::from_reboot_state(&RebootState::new(false, false), None, now), TrayState::NoReboot);
+        assert_eq!(TrayState::from_reboot_state(&RebootState::new(false, true), None, now), TrayState::RebootRecommended);
+
+        let mut state = RebootState::new(true, false);
+        assert_eq!(TrayState::from_reboot_state(&state, None, now), TrayState::RebootRequired);
+        assert_eq!(
+            TrayState::from_reboot_state(&state, Some(now + Duration::hours(5)), now),
+            TrayState::RebootRequired
+        );
+        assert_eq!(
+            TrayState::from_reboot_state(&state, Some(now + Duration::minutes(30)), now),
+            TrayState::RebootImminent
+        );
+
+        state.scheduled_reboot_time = Some(now + Duration::minutes(10));
+        assert_eq!(TrayState::from_reboot_state(&state, None, now), TrayState::RebootImminent);
+    }
+
+    #[test]
+    fn test_menu_sections() {
+        let callback = || -> MenuCallback { Arc::new(Mutex::new(|| -> Result<()> { Ok(()) })) };
+        let mut menu = Menu::default();
+        menu.header.push(MenuItem::Text("Reboot Reminder".to_string()));
+        let details = menu.add_command(Section::Other, "Why Restart?", false, callback());
+        let reboot = menu.add_command(Section::Reboot, "Reboot Now", false, callback());
+
+        menu.set_enabled(reboot, false);
+        let labels: Vec<String> = menu
+            .items()
+            .iter()
+            .map(|item| match item {
+                MenuItem::Command { label, enabled, .. } => format!("{}{}", label, if *enabled { "" } else { " (disabled)" }),
+                MenuItem::Text(text) => text.clone(),
+                MenuItem::Separator => "-".to_string(),
+                MenuItem::Submenu { label, .. } => label.clone(),
+            })
+            .collect();
+        assert_eq!(labels, ["Reboot Reminder", "-", "Reboot Now (disabled)", "-", "Why Restart?"]);
+
+        // Without reboot items there's a single separator
+        menu.remove(reboot);
+        assert_eq!(menu.items().len(), 3);
+        menu.remove(details);
+        assert_eq!(menu.items().len(), 1);
+    }
+
+    #[test]
+    fn test_history_label() {
+        let notification = Notification::new("reboot_required", "Your computer needs to restart to finish installing updates", None);
+        let time = notification.timestamp.with_timezone(&Local).format("%a %d %b %H:%M");
+        assert_eq!(
+            history_label(&notification, &[]),
+            format!("{} - Your computer needs to restart to finish… - No action", time)
+        );
+
+        let mut postponed = NotificationInteraction::new(notification.id, "defer:4h");
+        postponed.timestamp = notification.timestamp + Duration::minutes(1);
+        let mut snoozed = NotificationInteraction::new(notification.id, "snooze-until:17:00");
+        snoozed.timestamp = notification.timestamp + Duration::minutes(2);
+        assert!(history_label(&notification, &[&snoozed, &postponed]).ends_with(" - Snoozed until 17:00"));
+
+        assert_eq!(action_label("reboot:now"), "Restarted");
+        assert_eq!(action_label("defer:end of day"), "Postponed (end of day)");
+        assert_eq!(action_label(ACKNOWLEDGE_ACTION), "Acknowledged");
+    }
 }
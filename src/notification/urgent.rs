@@ -0,0 +1,17 @@
+use super::window;
+use crate::config::WindowPlacement;
+use anyhow::Result;
+use log::debug;
+use std::thread::JoinHandle;
+
+/// Show a critical notification in a window that stays on top of all other windows
+///
+/// Used in the last timeframe, when a toast is too easy to miss. The window is shown as a banner
+/// at the top of the monitors selected by the placement, sized for their DPI. Like balloons, the
+/// window can only be shown from a process in the user's session. This returns once the window is
+/// up, with the thread that ends when the user closes it.
+pub fn show(title: &str, message: &str, placement: WindowPlacement) -> Result<JoinHandle<()>> {
+    let handle = window::show_banner(title, message, placement)?;
+    debug!("Critical notification window shown");
+    Ok(handle)
+}
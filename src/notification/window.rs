@@ -0,0 +1,330 @@
+use crate::config::WindowPlacement;
+use crate::utils::to_wide;
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use windows::core::{w, BOOL, PCWSTR};
+use windows::Win32::Foundation::{HINSTANCE, HWND, LPARAM, LRESULT, POINT, RECT, WPARAM};
+use windows::Win32::Graphics::Gdi::{
+    CreateFontIndirectW, DeleteObject, EnumDisplayMonitors, GetMonitorInfoW, GetSysColorBrush, MonitorFromPoint,
+    MonitorFromWindow, COLOR_BTNFACE, HDC, HFONT, HMONITOR, MONITORINFO, MONITOR_DEFAULTTONEAREST,
+    MONITOR_DEFAULTTOPRIMARY,
+};
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::UI::HiDpi::{
+    GetDpiForMonitor, SetThreadDpiAwarenessContext, SystemParametersInfoForDpi,
+    DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2, MDT_EFFECTIVE_DPI,
+};
+use windows::Win32::UI::WindowsAndMessaging::{
+    CreateWindowExW, DefWindowProcW, DestroyWindow, DispatchMessageW, GetCursorPos, GetForegroundWindow, GetMessageW,
+    LoadCursorW, PostQuitMessage, RegisterClassW, SendMessageW, SetForegroundWindow, TranslateMessage,
+    BS_DEFPUSHBUTTON, HMENU, IDC_ARROW, MSG, NONCLIENTMETRICSW, SPI_GETNONCLIENTMETRICS, WM_CLOSE, WM_COMMAND,
+    WM_SETFONT, WNDCLASSW, WS_BORDER, WS_CHILD, WS_EX_TOOLWINDOW, WS_EX_TOPMOST, WS_POPUP, WS_TABSTOP, WS_VISIBLE,
+    WINDOW_EX_STYLE, WINDOW_STYLE,
+};
+
+/// DPI that window sizes are given in
+const BASE_DPI: u32 = 96;
+
+/// Size of the banner and its parts at 96 DPI
+const BANNER_WIDTH: i32 = 640;
+const BANNER_HEIGHT: i32 = 200;
+const BANNER_MARGIN: i32 = 24;
+const PADDING: i32 = 16;
+const BUTTON_WIDTH: i32 = 96;
+const BUTTON_HEIGHT: i32 = 28;
+
+/// Control identifier of the OK button
+const OK_BUTTON_ID: usize = 1;
+
+/// Monitor a banner is shown on
+#[derive(Debug, Clone, Copy)]
+struct Monitor {
+    /// Work area of the monitor, without the taskbar, in physical pixels
+    work_area: RECT,
+
+    /// Effective DPI of the monitor
+    dpi: u32,
+}
+
+/// Show a banner window at the top of the monitors selected by the placement
+///
+/// The windows stay on top of all others and are sized for the DPI of their monitor. Closing
+/// any of them closes them all. Like the other windows, banners can only be shown from a process
+/// in the user's session. They're shown on their own thread, so this returns once they're up,
+/// with the thread that ends when they're closed.
+pub fn show_banner(title: &str, message: &str, placement: WindowPlacement) -> Result<JoinHandle<()>> {
+    let text = format!("{}\n\n{}", title, message);
+    let title = title.to_string();
+    let (sender, receiver) = mpsc::channel();
+
+    let handle = thread::Builder::new()
+        .name("banner-window".to_string())
+        .spawn(move || unsafe {
+            // Work in physical pixels, so sizes can be scaled for each monitor
+            SetThreadDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2);
+
+            let monitors = monitors(placement);
+            let mut windows = Vec::new();
+            let mut fonts = Vec::new();
+            let mut result = Ok(());
+            for monitor in &monitors {
+                match create_banner(&title, &text, monitor) {
+                    Ok((window, font)) => {
+                        windows.push(window);
+                        fonts.extend(font);
+                    }
+                    Err(e) => {
+                        result = Err(e);
+                        break;
+                    }
+                }
+            }
+
+            let shown = result.is_ok() && !windows.is_empty();
+            let _ = sender.send(result.and_then(|()| {
+                if shown {
+                    Ok(())
+                } else {
+                    Err(anyhow::anyhow!("No monitor found to show the banner window on"))
+                }
+            }));
+
+            if shown {
+                debug!("Banner window shown on {} monitor(s)", windows.len());
+                let _ = SetForegroundWindow(windows[0]);
+                let mut msg = MSG::default();
+                while GetMessageW(&mut msg, None, 0, 0).as_bool() {
+                    let _ = TranslateMessage(&msg);
+                    DispatchMessageW(&msg);
+                }
+            }
+
+            for window in windows {
+                let _ = DestroyWindow(window);
+            }
+            for font in fonts {
+                let _ = DeleteObject(font.into());
+            }
+            debug!("Banner window closed");
+        })
+        .context("Failed to start a thread for the banner window")?;
+
+    receiver.recv().context("Banner window thread exited unexpectedly")??;
+    Ok(handle)
+}
+
+/// Get the monitors selected by a placement
+///
+/// The active monitor is the one with the foreground window, or the one with the mouse cursor
+/// when no window has the focus.
+unsafe fn monitors(placement: WindowPlacement) -> Vec<Monitor> {
+    let handles = match placement {
+        WindowPlacement::Primary => vec![MonitorFromPoint(POINT::default(), MONITOR_DEFAULTTOPRIMARY)],
+        WindowPlacement::Active => {
+            let foreground = GetForegroundWindow();
+            if !foreground.is_invalid() {
+                vec![MonitorFromWindow(foreground, MONITOR_DEFAULTTONEAREST)]
+            } else {
+                let mut cursor = POINT::default();
+                let _ = GetCursorPos(&mut cursor);
+                vec![MonitorFromPoint(cursor, MONITOR_DEFAULTTONEAREST)]
+            }
+        }
+        WindowPlacement::All => {
+            let mut handles: Vec<HMONITOR> = Vec::new();
+            let _ = EnumDisplayMonitors(
+                None,
+                None,
+                Some(collect_monitor),
+                LPARAM(&mut handles as *mut Vec<HMONITOR> as isize),
+            );
+            handles
+        }
+    };
+
+    handles
+        .into_iter()
+        .filter_map(|handle| {
+            let mut info = MONITORINFO {
+                cbSize: std::mem::size_of::<MONITORINFO>() as u32,
+                ..Default::default()
+            };
+            if !GetMonitorInfoW(handle, &mut info).as_bool() {
+                warn!("Failed to get the work area of monitor {:?}", handle);
+                return None;
+            }
+
+            let (mut dpi, mut dpi_y) = (BASE_DPI, BASE_DPI);
+            if GetDpiForMonitor(handle, MDT_EFFECTIVE_DPI, &mut dpi, &mut dpi_y).is_err() {
+                dpi = BASE_DPI;
+            }
+            Some(Monitor { work_area: info.rcWork, dpi })
+        })
+        .collect()
+}
+
+/// Add a monitor found by EnumDisplayMonitors to the list passed in its data
+unsafe extern "system" fn collect_monitor(monitor: HMONITOR, _: HDC, _: *mut RECT, data: LPARAM) -> BOOL {
+    let handles = &mut *(data.0 as *mut Vec<HMONITOR>);
+    handles.push(monitor);
+    true.into()
+}
+
+/// Create a banner window on a monitor, with the message and an OK button
+///
+/// Returns the window and the font created for the DPI of the monitor, which is deleted once the
+/// window is gone.
+unsafe fn create_banner(title: &str, text: &str, monitor: &Monitor) -> Result<(HWND, Option<HFONT>)> {
+    let instance: HINSTANCE = GetModuleHandleW(None).context("Failed to get module handle")?.into();
+    let class = WNDCLASSW {
+        lpfnWndProc: Some(window_proc),
+        hInstance: instance,
+        hCursor: LoadCursorW(None, IDC_ARROW).unwrap_or_default(),
+        hbrBackground: GetSysColorBrush(COLOR_BTNFACE),
+        lpszClassName: w!("RebootReminderBanner"),
+        ..Default::default()
+    };
+    // Registering again fails harmlessly once the class exists
+    RegisterClassW(&class);
+
+    let rect = banner_rect(monitor.work_area, monitor.dpi);
+    let (width, height) = (rect.right - rect.left, rect.bottom - rect.top);
+    let title = to_wide(title);
+    let window = CreateWindowExW(
+        WS_EX_TOPMOST | WS_EX_TOOLWINDOW,
+        w!("RebootReminderBanner"),
+        PCWSTR::from_raw(title.as_ptr()),
+        WS_POPUP | WS_BORDER | WS_VISIBLE,
+        rect.left,
+        rect.top,
+        width,
+        height,
+        None,
+        None,
+        Some(instance),
+        None,
+    )
+    .context("Failed to create banner window")?;
+
+    let padding = scale(PADDING, monitor.dpi);
+    let (button_width, button_height) = (scale(BUTTON_WIDTH, monitor.dpi), scale(BUTTON_HEIGHT, monitor.dpi));
+    let text = to_wide(text);
+    let label = CreateWindowExW(
+        WINDOW_EX_STYLE::default(),
+        w!("STATIC"),
+        PCWSTR::from_raw(text.as_ptr()),
+        WS_CHILD | WS_VISIBLE,
+        padding,
+        padding,
+        width - 2 * padding,
+        height - 3 * padding - button_height,
+        Some(window),
+        None,
+        Some(instance),
+        None,
+    );
+    let button = CreateWindowExW(
+        WINDOW_EX_STYLE::default(),
+        w!("BUTTON"),
+        w!("OK"),
+        WS_CHILD | WS_VISIBLE | WS_TABSTOP | WINDOW_STYLE(BS_DEFPUSHBUTTON as u32),
+        width - padding - button_width,
+        height - padding - button_height,
+        button_width,
+        button_height,
+        Some(window),
+        Some(HMENU(OK_BUTTON_ID as *mut _)),
+        Some(instance),
+        None,
+    );
+
+    let font = message_font(monitor.dpi);
+    if let Some(font) = font {
+        for control in [label, button].into_iter().flatten() {
+            SendMessageW(control, WM_SETFONT, Some(WPARAM(font.0 as usize)), Some(LPARAM(1)));
+        }
+    }
+
+    Ok((window, font))
+}
+
+/// Create the message box font of the system, sized for a DPI
+unsafe fn message_font(dpi: u32) -> Option<HFONT> {
+    let mut metrics = NONCLIENTMETRICSW {
+        cbSize: std::mem::size_of::<NONCLIENTMETRICSW>() as u32,
+        ..Default::default()
+    };
+    SystemParametersInfoForDpi(
+        SPI_GETNONCLIENTMETRICS.0,
+        metrics.cbSize,
+        Some(&mut metrics as *mut _ as *mut _),
+        0,
+        dpi,
+    )
+    .ok()?;
+
+    let font = CreateFontIndirectW(&metrics.lfMessageFont);
+    (!font.is_invalid()).then_some(font)
+}
+
+/// Close the banners when the OK button is selected or a window is closed
+unsafe extern "system" fn window_proc(window: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    match message {
+        WM_COMMAND if wparam.0 & 0xFFFF == OK_BUTTON_ID => {
+            PostQuitMessage(0);
+            LRESULT(0)
+        }
+        WM_CLOSE => {
+            PostQuitMessage(0);
+            LRESULT(0)
+        }
+        _ => DefWindowProcW(window, message, wparam, lparam),
+    }
+}
+
+/// Get the position of a banner centered at the top of a monitor's work area
+///
+/// The banner is scaled for the DPI of the monitor and never larger than its work area.
+fn banner_rect(work_area: RECT, dpi: u32) -> RECT {
+    let available_width = work_area.right - work_area.left;
+    let available_height = work_area.bottom - work_area.top;
+    let margin = scale(BANNER_MARGIN, dpi).min(available_height / 4);
+    let width = scale(BANNER_WIDTH, dpi).min(available_width);
+    let height = scale(BANNER_HEIGHT, dpi).min(available_height - margin);
+
+    let left = work_area.left + (available_width - width) / 2;
+    let top = work_area.top + margin;
+    RECT { left, top, right: left + width, bottom: top + height }
+}
+
+/// Scale a size given at 96 DPI to a DPI
+fn scale(value: i32, dpi: u32) -> i32 {
+    (value as i64 * dpi as i64 / BASE_DPI as i64) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_banner_rect() {
+        // A 1080p monitor at 100% with the taskbar at the bottom
+        let work_area = RECT { left: 0, top: 0, right: 1920, bottom: 1040 };
+        let rect = banner_rect(work_area, 96);
+        assert_eq!((rect.left, rect.top, rect.right, rect.bottom), (640, 24, 1280, 224));
+
+        // A second monitor left of the primary at 150%
+        let work_area = RECT { left: -2560, top: 0, right: 0, bottom: 1400 };
+        let rect = banner_rect(work_area, 144);
+        assert_eq!(rect.right - rect.left, 960);
+        assert_eq!(rect.bottom - rect.top, 300);
+        assert_eq!(rect.left, -2560 + (2560 - 960) / 2);
+
+        // Never larger than a small work area
+        let work_area = RECT { left: 0, top: 0, right: 800, bottom: 200 };
+        let rect = banner_rect(work_area, 192);
+        assert!(rect.right <= 800 && rect.bottom <= 200);
+    }
+}
@@ -0,0 +1,230 @@
+use crate::config::WorkingHoursConfig;
+use crate::database::UserSession;
+use crate::utils::{registry, schedule, sid};
+use chrono::NaiveTime;
+use log::debug;
+use std::fmt;
+use windows::Win32::System::Registry::{HKEY, HKEY_LOCAL_MACHINE, HKEY_USERS};
+
+/// Names of the days of the week, starting with Sunday like the quiet hours days
+const DAY_NAMES: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+
+/// Working hours in local time on some days of the week; may span midnight for night shifts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkingHours {
+    /// Days the working hours start on (0 = Sunday, 6 = Saturday)
+    pub days: Vec<u8>,
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl WorkingHours {
+    /// Parse working hours written as "[days ]HH:MM-HH:MM" (e.g., "22:00-06:30" or
+    /// "Mon-Thu,Sat 08:30-17:00"); without days they apply every day
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let (days, hours) = value.split_at(value.find(|c: char| c.is_ascii_digit())?);
+        let days = match days.trim() {
+            "" => (0..7).collect(),
+            days => parse_days(days)?,
+        };
+
+        let (start, end) = hours.split_once('-')?;
+        let start = NaiveTime::parse_from_str(start.trim(), "%H:%M").ok()?;
+        let end = NaiveTime::parse_from_str(end.trim(), "%H:%M").ok()?;
+        Some(Self { days, start, end })
+    }
+
+    /// Check if a time on a day of the week (0 = Sunday) falls within the working hours
+    ///
+    /// Working hours spanning midnight belong to the day they start on.
+    pub fn contains(&self, day: u8, time: NaiveTime) -> bool {
+        schedule::in_range(&self.days, self.start, self.end, day, time)
+    }
+}
+
+impl fmt::Display for WorkingHours {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.days.len() < DAY_NAMES.len() {
+            let days: Vec<&str> = self.days.iter().filter_map(|day| DAY_NAMES.get(*day as usize).copied()).collect();
+            write!(f, "{} ", days.join(","))?;
+        }
+        write!(f, "{}-{}", self.start.format("%H:%M"), self.end.format("%H:%M"))
+    }
+}
+
+/// Parse days of the week written as names and ranges of names (e.g., "Mon-Thu,Sat")
+fn parse_days(value: &str) -> Option<Vec<u8>> {
+    let mut days = Vec::new();
+    for part in value.split(',') {
+        match part.split_once('-') {
+            Some((first, last)) => {
+                let (mut day, last) = (parse_day(first)?, parse_day(last)?);
+                days.push(day);
+                while day != last {
+                    day = (day + 1) % 7;
+                    days.push(day);
+                }
+            }
+            None => days.push(parse_day(part)?),
+        }
+    }
+    Some(days)
+}
+
+/// Parse the name of a day of the week, ignoring case
+fn parse_day(value: &str) -> Option<u8> {
+    DAY_NAMES
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case(value.trim()))
+        .map(|day| day as u8)
+}
+
+/// Source of the working hours of the user logged on to a session
+pub trait WorkingHoursProvider: Send + Sync {
+    /// Get the working hours of the user logged on to a session, if they are known
+    fn working_hours(&self, session: &UserSession) -> Option<WorkingHours>;
+}
+
+/// Working hours read from a registry value written by another tool
+///
+/// Keys starting with `HKLM\` apply to every user on the machine; other keys are read from the
+/// session user's hive under `HKEY_USERS\<SID>`.
+pub struct RegistryWorkingHours {
+    key_path: String,
+    value_name: String,
+    running_as_service: bool,
+}
+
+impl WorkingHoursProvider for RegistryWorkingHours {
+    fn working_hours(&self, session: &UserSession) -> Option<WorkingHours> {
+        let (hive, key_path) = match strip_hive(&self.key_path, &["HKLM\\", "HKEY_LOCAL_MACHINE\\"]) {
+            Some(key_path) => (HKEY_LOCAL_MACHINE, key_path.to_string()),
+            None => {
+                let key_path = strip_hive(&self.key_path, &["HKCU\\", "HKEY_CURRENT_USER\\"]).unwrap_or(&self.key_path);
+                let user_sid = sid::session_user_sid(&session.session_id, self.running_as_service)?;
+                (HKEY_USERS, format!("{}\\{}", user_sid, key_path))
+            }
+        };
+
+        read_working_hours(hive, &key_path, &self.value_name)
+    }
+}
+
+/// Working hours read from an environment variable of the session user
+///
+/// The variable is looked up in the user's volatile (per-session) environment first and then in
+/// their persistent environment.
+pub struct EnvironmentWorkingHours {
+    variable: String,
+    running_as_service: bool,
+}
+
+impl WorkingHoursProvider for EnvironmentWorkingHours {
+    fn working_hours(&self, session: &UserSession) -> Option<WorkingHours> {
+        let user_sid = sid::session_user_sid(&session.session_id, self.running_as_service)?;
+        let keys = [
+            format!("{}\\Volatile Environment\\{}", user_sid, session.session_id),
+            format!("{}\\Volatile Environment", user_sid),
+            format!("{}\\Environment", user_sid),
+        ];
+
+        keys.iter().find_map(|key_path| read_working_hours(HKEY_USERS, key_path, &self.variable))
+    }
+}
+
+/// Create the working hours providers for a configuration, in the order they are consulted
+pub fn providers(config: &WorkingHoursConfig, running_as_service: bool) -> Vec<Box<dyn WorkingHoursProvider>> {
+    let mut providers: Vec<Box<dyn WorkingHoursProvider>> = Vec::new();
+
+    if let Some(variable) = &config.environment_variable {
+        providers.push(Box::new(EnvironmentWorkingHours {
+            variable: variable.clone(),
+            running_as_service,
+        }));
+    }
+
+    if let Some(key_path) = &config.registry_key {
+        providers.push(Box::new(RegistryWorkingHours {
+            key_path: key_path.clone(),
+            value_name: config.registry_value.clone(),
+            running_as_service,
+        }));
+    }
+
+    providers
+}
+
+/// Read and parse a working hours registry value
+fn read_working_hours(hive: HKEY, key_path: &str, value_name: &str) -> Option<WorkingHours> {
+    let value = match registry::get_string_value(hive, key_path, value_name) {
+        Ok(value) => value?,
+        Err(e) => {
+            debug!("Failed to read working hours from {}\\{}: {}", key_path, value_name, e);
+            return None;
+        }
+    };
+
+    let hours = WorkingHours::parse(&value);
+    if hours.is_none() {
+        debug!("Ignoring invalid working hours '{}' in {}\\{}", value, key_path, value_name);
+    }
+    hours
+}
+
+/// Remove one of the given hive prefixes from a key path, ignoring case
+pub(super) fn strip_hive<'a>(key_path: &'a str, prefixes: &[&str]) -> Option<&'a str> {
+    prefixes.iter().find_map(|prefix| {
+        let head = key_path.get(..prefix.len())?;
+        head.eq_ignore_ascii_case(prefix).then(|| &key_path[prefix.len()..])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(value: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(value, "%H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_working_hours() {
+        let day_shift = WorkingHours::parse("08:30-17:00").unwrap();
+        assert!(day_shift.contains(0, time("12:00")));
+        assert!(!day_shift.contains(3, time("17:00")));
+        assert!(!day_shift.contains(3, time("03:00")));
+
+        let night_shift = WorkingHours::parse(" 22:00 - 06:30 ").unwrap();
+        assert!(night_shift.contains(2, time("23:00")));
+        assert!(night_shift.contains(2, time("03:00")));
+        assert!(!night_shift.contains(2, time("15:00")));
+        assert_eq!(night_shift.to_string(), "22:00-06:30");
+
+        assert!(WorkingHours::parse("22:00").is_none());
+        assert!(WorkingHours::parse("25:00-06:00").is_none());
+        assert!(WorkingHours::parse("Someday 08:00-17:00").is_none());
+    }
+
+    #[test]
+    fn test_working_hours_days() {
+        let weekdays = WorkingHours::parse("mon-fri 08:30-17:00").unwrap();
+        assert_eq!(weekdays.days, vec![1, 2, 3, 4, 5]);
+        assert!(weekdays.contains(5, time("12:00")));
+        assert!(!weekdays.contains(6, time("12:00")));
+        assert_eq!(weekdays.to_string(), "Mon,Tue,Wed,Thu,Fri 08:30-17:00");
+
+        // Night shifts run into the morning after the days they start on
+        let weekends = WorkingHours::parse("Fri-Sun,Wed 22:00-06:00").unwrap();
+        assert_eq!(weekends.days, vec![5, 6, 0, 3]);
+        assert!(weekends.contains(1, time("05:00")));
+        assert!(!weekends.contains(1, time("23:00")));
+        assert!(weekends.contains(4, time("05:00")));
+    }
+
+    #[test]
+    fn test_strip_hive() {
+        assert_eq!(strip_hive("hklm\\SOFTWARE\\Contoso", &["HKLM\\"]), Some("SOFTWARE\\Contoso"));
+        assert_eq!(strip_hive("SOFTWARE\\Contoso", &["HKLM\\"]), None);
+    }
+}
@@ -0,0 +1,204 @@
+use anyhow::{Context, Result};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use windows::core::PWSTR;
+use windows::Win32::Foundation::{CloseHandle, ERROR_MORE_DATA, FILETIME};
+use windows::Win32::System::Diagnostics::ToolHelp::{
+    CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W, TH32CS_SNAPPROCESS,
+};
+use windows::Win32::System::RemoteDesktop::ProcessIdToSessionId;
+use windows::Win32::System::RestartManager::{
+    RmConsole, RmEndSession, RmGetList, RmMainWindow, RmOtherWindow, RmRegisterResources, RmStartSession,
+    CCH_RM_SESSION_KEY, RM_PROCESS_INFO, RM_UNIQUE_PROCESS,
+};
+use windows::Win32::System::Threading::{GetProcessTimes, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION};
+
+/// Application a reboot would close
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OpenApplication {
+    /// Name of the application, usually the title of its main window
+    pub name: String,
+
+    /// ID of the process
+    pub process_id: u32,
+
+    /// ID of the session the application runs in
+    pub session_id: u32,
+
+    /// Whether the application seems to have unsaved documents
+    pub unsaved: bool,
+
+    /// Whether the application registered to be restarted after a reboot
+    #[serde(default)]
+    pub restartable: bool,
+}
+
+impl OpenApplication {
+    /// Describe the application for users, flagging unsaved documents
+    pub fn label(&self) -> String {
+        if self.unsaved {
+            format!("{} (unsaved changes)", self.name)
+        } else {
+            self.name.clone()
+        }
+    }
+}
+
+/// Find the applications users have open, which a reboot would close
+///
+/// Every process in a user session is registered with a Restart Manager session, which reports
+/// the ones with windows or consoles. Services and critical system processes are left out.
+pub fn open_applications() -> Result<Vec<OpenApplication>> {
+    let processes = user_processes()?;
+    if processes.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    unsafe {
+        let mut session = 0;
+        let mut key = [0u16; CCH_RM_SESSION_KEY as usize + 1];
+        RmStartSession(&mut session, None, PWSTR(key.as_mut_ptr()))
+            .ok()
+            .context("Failed to start a Restart Manager session")?;
+
+        let apps = list_applications(session, &processes);
+        let _ = RmEndSession(session);
+        apps
+    }
+}
+
+/// Register processes with a Restart Manager session and list the applications among them
+unsafe fn list_applications(session: u32, processes: &[RM_UNIQUE_PROCESS]) -> Result<Vec<OpenApplication>> {
+    RmRegisterResources(session, None, Some(processes), None)
+        .ok()
+        .context("Failed to register processes with Restart Manager")?;
+
+    // The list can grow between calls, so ask again until it fits
+    let mut infos: Vec<RM_PROCESS_INFO> = Vec::new();
+    loop {
+        let mut needed = 0;
+        let mut count = infos.len() as u32;
+        let mut reasons = 0;
+        let result = RmGetList(
+            session,
+            &mut needed,
+            &mut count,
+            (!infos.is_empty()).then(|| infos.as_mut_ptr()),
+            &mut reasons,
+        );
+        if result == ERROR_MORE_DATA {
+            infos.resize(needed as usize, RM_PROCESS_INFO::default());
+            continue;
+        }
+        result.ok().context("Failed to list applications with Restart Manager")?;
+        infos.truncate(count as usize);
+        break;
+    }
+
+    Ok(infos
+        .iter()
+        .filter(|info| [RmMainWindow, RmOtherWindow, RmConsole].contains(&info.ApplicationType))
+        .map(|info| {
+            let len = info.strAppName.iter().position(|&c| c == 0).unwrap_or(info.strAppName.len());
+            let name = String::from_utf16_lossy(&info.strAppName[..len]);
+            OpenApplication {
+                unsaved: looks_unsaved(&name),
+                name,
+                process_id: info.Process.dwProcessId,
+                session_id: info.TSSessionId,
+                restartable: info.bRestartable.as_bool(),
+            }
+        })
+        .collect())
+}
+
+/// Get the processes running in user sessions, identified by ID and start time
+fn user_processes() -> Result<Vec<RM_UNIQUE_PROCESS>> {
+    let snapshot = unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) }
+        .context("Failed to create process snapshot")?;
+
+    let mut processes = Vec::new();
+    let mut entry = PROCESSENTRY32W {
+        dwSize: std::mem::size_of::<PROCESSENTRY32W>() as u32,
+        ..Default::default()
+    };
+
+    unsafe {
+        let mut found = Process32FirstW(snapshot, &mut entry).is_ok();
+        while found {
+            let process_id = entry.th32ProcessID;
+            let mut session_id = 0;
+            if ProcessIdToSessionId(process_id, &mut session_id).is_ok() && session_id != 0 {
+                match start_time(process_id) {
+                    Some(start_time) => processes.push(RM_UNIQUE_PROCESS {
+                        dwProcessId: process_id,
+                        ProcessStartTime: start_time,
+                    }),
+                    None => debug!("Failed to get the start time of process {}", process_id),
+                }
+            }
+            found = Process32NextW(snapshot, &mut entry).is_ok();
+        }
+
+        let _ = CloseHandle(snapshot);
+    }
+
+    Ok(processes)
+}
+
+/// Get the time a process started, which tells it apart from later processes with the same ID
+unsafe fn start_time(process_id: u32) -> Option<FILETIME> {
+    let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, process_id).ok()?;
+    let (mut created, mut exited, mut kernel, mut user) =
+        (FILETIME::default(), FILETIME::default(), FILETIME::default(), FILETIME::default());
+    let result = GetProcessTimes(process, &mut created, &mut exited, &mut kernel, &mut user);
+    let _ = CloseHandle(process);
+    result.ok().map(|_| created)
+}
+
+/// Guess from a window title whether the document in it has unsaved changes
+///
+/// Most editors mark a modified document with an asterisk next to its name (e.g.,
+/// "*notes.txt - Notepad" or "report.docx* - Editor"), and new documents are "Untitled".
+pub fn looks_unsaved(title: &str) -> bool {
+    let document = title.split(" - ").next().unwrap_or(title).trim();
+    document.starts_with('*') || document.ends_with('*') || document.to_lowercase().starts_with("untitled")
+}
+
+/// List the applications for a confirmation dialog, one per line
+pub fn describe(apps: &[OpenApplication]) -> String {
+    let mut labels: Vec<String> = apps.iter().map(OpenApplication::label).collect();
+    labels.sort();
+    labels.dedup();
+    labels.iter().map(|label| format!("  - {}", label)).collect::<Vec<_>>().join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app(name: &str) -> OpenApplication {
+        OpenApplication {
+            name: name.to_string(),
+            process_id: 1,
+            session_id: 1,
+            unsaved: looks_unsaved(name),
+            restartable: false,
+        }
+    }
+
+    #[test]
+    fn test_looks_unsaved() {
+        assert!(looks_unsaved("*notes.txt - Notepad"));
+        assert!(looks_unsaved("report.docx* - Editor"));
+        assert!(looks_unsaved("Untitled - Paint"));
+        assert!(!looks_unsaved("Inbox - user@example.com - Outlook"));
+        assert!(!looks_unsaved("Calculator"));
+    }
+
+    #[test]
+    fn test_describe() {
+        let apps = [app("Outlook"), app("*notes.txt - Notepad"), app("Outlook")];
+        assert_eq!(describe(&apps), "  - *notes.txt - Notepad (unsaved changes)\n  - Outlook");
+    }
+}
@@ -0,0 +1,255 @@
+use crate::utils::registry;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use log::info;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use windows::Win32::System::Registry::HKEY_LOCAL_MACHINE;
+
+/// Key holding the file operations Windows carries out on the next boot
+const SESSION_MANAGER_KEY: &str = "SYSTEM\\CurrentControlSet\\Control\\Session Manager";
+
+/// Value listing the pending file operations, as pairs of source and destination paths
+const PENDING_FILE_RENAMES_VALUE: &str = "PendingFileRenameOperations";
+
+/// Key Windows Update keeps until the reboot it asked for has happened
+const WINDOWS_UPDATE_REBOOT_REQUIRED_KEY: &str =
+    "SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\WindowsUpdate\\Auto Update\\RebootRequired";
+
+/// Keys Windows Installer keeps until an installation has finished and its reboot has happened
+const INSTALLER_KEYS: &[&str] = &[
+    "SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\Installer\\RebootRequired",
+    "SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\Installer\\InProgress",
+];
+
+/// Pending reboot markers that can be cleared when they're stale, named after their sources
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StaleMarker {
+    /// Pending file operations whose source file no longer exists, e.g. left by uninstalled apps
+    PendingFileOperations,
+
+    /// Windows Update's RebootRequired key, when it was written before the last boot
+    WindowsUpdate,
+
+    /// Windows Installer's RebootRequired and InProgress keys, when they were written before the last boot
+    Installer,
+}
+
+impl FromStr for StaleMarker {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "pending_file_operations" => Ok(Self::PendingFileOperations),
+            "windows_update" => Ok(Self::WindowsUpdate),
+            "installer_operations" => Ok(Self::Installer),
+            _ => Err(format!(
+                "Unknown source '{}', expected pending_file_operations, windows_update or installer_operations",
+                value
+            )),
+        }
+    }
+}
+
+impl fmt::Display for StaleMarker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StaleMarker::PendingFileOperations => write!(f, "pending file operations"),
+            StaleMarker::WindowsUpdate => write!(f, "Windows Update"),
+            StaleMarker::Installer => write!(f, "Windows Installer"),
+        }
+    }
+}
+
+/// Registry change that clears a stale marker
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Change {
+    /// Replace the pending file operations with the ones to keep
+    SetFileOperations(Vec<String>),
+
+    /// Delete the pending file operations value
+    DeleteFileOperations,
+
+    /// Delete a key
+    DeleteKey(&'static str),
+}
+
+/// What clearing a marker would change, shown as a diff before anything is modified
+#[derive(Debug, Clone)]
+pub struct ClearPlan {
+    /// Marker being cleared
+    pub marker: StaleMarker,
+
+    /// Lines removed from the registry
+    pub removed: Vec<String>,
+
+    /// Lines kept, and why
+    pub kept: Vec<String>,
+
+    changes: Vec<Change>,
+}
+
+impl ClearPlan {
+    /// Whether there is nothing stale to clear
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Modify the registry as planned
+    pub fn apply(&self) -> Result<()> {
+        for change in &self.changes {
+            match change {
+                Change::SetFileOperations(operations) => {
+                    registry::set_multi_string_value(HKEY_LOCAL_MACHINE, SESSION_MANAGER_KEY, PENDING_FILE_RENAMES_VALUE, operations)?
+                }
+                Change::DeleteFileOperations => {
+                    registry::delete_value(HKEY_LOCAL_MACHINE, SESSION_MANAGER_KEY, PENDING_FILE_RENAMES_VALUE)?;
+                }
+                Change::DeleteKey(key_path) => {
+                    registry::delete_key_tree(HKEY_LOCAL_MACHINE, key_path)?;
+                }
+            }
+        }
+
+        for line in &self.removed {
+            info!("Cleared stale {} marker: {}", self.marker, line);
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ClearPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.removed {
+            writeln!(f, "- {}", line)?;
+        }
+        for line in &self.kept {
+            writeln!(f, "  {}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Find out what clearing a marker would change, given the last boot time
+///
+/// Keys are only stale when they were written before the last boot, since the reboot they ask for
+/// should have removed them; newer keys belong to a reboot that is still pending.
+pub fn plan(marker: StaleMarker, last_boot_time: DateTime<Utc>) -> Result<ClearPlan> {
+    let mut plan = ClearPlan {
+        marker,
+        removed: Vec::new(),
+        kept: Vec::new(),
+        changes: Vec::new(),
+    };
+
+    let key_paths: &[&'static str] = match marker {
+        StaleMarker::PendingFileOperations => {
+            let operations = registry::get_multi_string_value(HKEY_LOCAL_MACHINE, SESSION_MANAGER_KEY, PENDING_FILE_RENAMES_VALUE)
+                .context("Failed to read the pending file operations")?
+                .unwrap_or_default();
+            plan_file_operations(&mut plan, &operations, |path| path.exists());
+            return Ok(plan);
+        }
+        StaleMarker::WindowsUpdate => &[WINDOWS_UPDATE_REBOOT_REQUIRED_KEY],
+        StaleMarker::Installer => INSTALLER_KEYS,
+    };
+
+    for &key_path in key_paths {
+        let Some(written_at) = registry::key_last_write_time(HKEY_LOCAL_MACHINE, key_path)? else {
+            continue;
+        };
+
+        let line = format!("HKLM\\{} (written {})", key_path, written_at.format("%Y-%m-%d %H:%M:%S UTC"));
+        if written_at < last_boot_time {
+            plan.removed.push(line);
+            plan.changes.push(Change::DeleteKey(key_path));
+        } else {
+            plan.kept.push(format!("{}: written since the last boot, the reboot is still pending", line));
+        }
+    }
+    Ok(plan)
+}
+
+/// Plan removing the pending file operations whose source file no longer exists
+///
+/// Windows can't rename or delete a file that isn't there, so these operations do nothing but keep
+/// a reboot pending.
+fn plan_file_operations(plan: &mut ClearPlan, operations: &[String], exists: impl Fn(&Path) -> bool) {
+    let mut kept = Vec::new();
+    for pair in operations.chunks(2) {
+        let source = &pair[0];
+        let destination = pair.get(1).map(String::as_str).unwrap_or_default();
+        if source.is_empty() {
+            continue;
+        }
+
+        let line = match destination {
+            "" => format!("{} -> (delete)", source),
+            destination => format!("{} -> {}", source, destination),
+        };
+        if exists(Path::new(operation_path(source))) {
+            plan.kept.push(line);
+            kept.push(source.clone());
+            kept.push(destination.to_string());
+        } else {
+            plan.removed.push(format!("{}: the source file no longer exists", line));
+        }
+    }
+
+    if plan.removed.is_empty() {
+        return;
+    }
+    plan.changes.push(if kept.is_empty() {
+        Change::DeleteFileOperations
+    } else {
+        Change::SetFileOperations(kept)
+    });
+}
+
+/// Get the file system path of a pending file operation's path (e.g., `\??\C:\file.dll`)
+fn operation_path(path: &str) -> &str {
+    let path = path.strip_prefix('!').unwrap_or(path);
+    path.strip_prefix("\\??\\").unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_file_operations() {
+        let mut plan = ClearPlan {
+            marker: StaleMarker::PendingFileOperations,
+            removed: Vec::new(),
+            kept: Vec::new(),
+            changes: Vec::new(),
+        };
+        let operations = [
+            "\\??\\C:\\Program Files\\Removed\\old.dll",
+            "",
+            "\\??\\C:\\Windows\\Temp\\new.sys",
+            "!\\??\\C:\\Windows\\System32\\drivers\\new.sys",
+            "",
+        ]
+        .map(String::from);
+
+        plan_file_operations(&mut plan, &operations, |path| path.starts_with("C:\\Windows"));
+        assert_eq!(plan.removed, vec!["\\??\\C:\\Program Files\\Removed\\old.dll -> (delete): the source file no longer exists"]);
+        assert_eq!(
+            plan.changes,
+            vec![Change::SetFileOperations(vec![
+                "\\??\\C:\\Windows\\Temp\\new.sys".to_string(),
+                "!\\??\\C:\\Windows\\System32\\drivers\\new.sys".to_string(),
+            ])]
+        );
+
+        let mut plan = ClearPlan { removed: Vec::new(), kept: Vec::new(), changes: Vec::new(), ..plan };
+        plan_file_operations(&mut plan, &operations, |_| true);
+        assert!(plan.is_empty());
+
+        let mut plan = ClearPlan { removed: Vec::new(), kept: Vec::new(), changes: Vec::new(), ..plan };
+        plan_file_operations(&mut plan, &operations, |_| false);
+        assert_eq!(plan.changes, vec![Change::DeleteFileOperations]);
+    }
+}
@@ -0,0 +1,111 @@
+use crate::database::{RebootSource, SourceDebounce};
+use log::info;
+use std::collections::HashMap;
+
+/// Hold back reboot sources that come and go until they've lasted for enough checks
+///
+/// A source with more than one check in `checks` only counts once it was found in that many
+/// consecutive checks, and keeps counting, with the sources last found for it, until it was
+/// missing from that many. Appearances and disappearances that don't last long enough are
+/// counted as flaps and logged. Sources without debouncing are passed through as they are.
+pub fn apply(counters: &mut Vec<SourceDebounce>, checks: &HashMap<String, u32>, sources: Vec<RebootSource>) -> Vec<RebootSource> {
+    let mut names: Vec<(&String, u32)> = checks
+        .iter()
+        .filter(|(_, checks)| **checks > 1)
+        .map(|(name, checks)| (name, *checks))
+        .collect();
+    names.sort();
+    counters.retain(|counter| names.iter().any(|(name, _)| **name == counter.name));
+
+    let (debounced, mut result): (Vec<RebootSource>, Vec<RebootSource>) = sources
+        .into_iter()
+        .partition(|source| names.iter().any(|(name, _)| **name == source.name));
+
+    for (name, checks) in names {
+        let index = match counters.iter().position(|counter| counter.name == *name) {
+            Some(index) => index,
+            None => {
+                counters.push(SourceDebounce {
+                    name: name.clone(),
+                    ..Default::default()
+                });
+                counters.len() - 1
+            }
+        };
+        let counter = &mut counters[index];
+        let found: Vec<RebootSource> = debounced.iter().filter(|source| source.name == *name).cloned().collect();
+        observe(counter, found, checks);
+        if counter.counted {
+            result.extend(counter.sources.iter().cloned());
+        }
+    }
+
+    result
+}
+
+/// Update the counters of a source with the sources found for it in a check
+fn observe(counter: &mut SourceDebounce, found: Vec<RebootSource>, checks: u32) {
+    if !found.is_empty() {
+        if counter.counted && counter.missing > 0 {
+            flap(counter, "disappeared");
+        }
+        counter.seen += 1;
+        counter.missing = 0;
+        counter.sources = found;
+        if !counter.counted && counter.seen >= checks {
+            info!("Reboot source {} found in {} consecutive checks, counting it", counter.name, counter.seen);
+            counter.counted = true;
+        }
+    } else {
+        if !counter.counted && counter.seen > 0 {
+            flap(counter, "appeared");
+        }
+        counter.missing += 1;
+        counter.seen = 0;
+        if counter.counted && counter.missing >= checks {
+            info!("Reboot source {} missing from {} consecutive checks, no longer counting it", counter.name, counter.missing);
+            counter.counted = false;
+            counter.sources.clear();
+        }
+    }
+}
+
+/// Count and log a change of a source that didn't last
+fn flap(counter: &mut SourceDebounce, change: &str) {
+    counter.flaps += 1;
+    info!(
+        "Reboot source {} {} for fewer checks than needed and was ignored ({} flaps so far)",
+        counter.name, change, counter.flaps
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let checks = HashMap::from([("pending_file_operations".to_string(), 2)]);
+        let rename = || vec![RebootSource::new("pending_file_operations", None, "required")];
+        let update = || RebootSource::new("windows_update", None, "required");
+        let names = |sources: &[RebootSource]| sources.iter().map(|source| source.name.clone()).collect::<Vec<_>>();
+        let mut counters = Vec::new();
+
+        // Sources without debouncing pass through, a new source waits for a second check
+        let mut sources = rename();
+        sources.push(update());
+        assert_eq!(names(&apply(&mut counters, &checks, sources)), ["windows_update"]);
+
+        // Gone after one check: a flap
+        assert!(apply(&mut counters, &checks, Vec::new()).is_empty());
+        assert_eq!(counters[0].flaps, 1);
+
+        // Found twice in a row, it counts and keeps counting through one missed check
+        apply(&mut counters, &checks, rename());
+        assert_eq!(names(&apply(&mut counters, &checks, rename())), ["pending_file_operations"]);
+        assert_eq!(names(&apply(&mut counters, &checks, Vec::new())), ["pending_file_operations"]);
+        assert!(apply(&mut counters, &checks, Vec::new()).is_empty());
+        assert!(!counters[0].counted);
+        assert_eq!(counters[0].flaps, 1);
+    }
+}
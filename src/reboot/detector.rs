@@ -1,134 +1,314 @@
-use crate::config::RebootConfig;
+use crate::config::{RebootConfig, SourceSeverity};
 use crate::database::RebootSource;
+use crate::utils::clock::{self, Clock};
+use crate::reboot::providers::{
+    with_remote_wmi_connection, with_wmi_connection, FileSystemProvider, LocalFileSystem, RegistryProvider,
+    RemoteHost, UserRegistryProvider, WindowsRegistry, WindowsUserRegistry, WindowsWmi, WmiProvider, CIMV2_NAMESPACE,
+};
 use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
 use log::{debug, info, warn};
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use windows::core::PWSTR;
+use windows::Wdk::System::SystemServices::RtlGetVersion;
+use windows::Win32::System::SystemInformation::{
+    ComputerNameDnsDomain, ComputerNameNetBIOS, GetComputerNameExW, COMPUTER_NAME_FORMAT, OSVERSIONINFOW,
+};
 
-use windows::Win32::System::Registry::HKEY_LOCAL_MACHINE;
 // use std::time::SystemTime;
 // use uuid::Uuid;
-use wmi::{self, COMLibrary};
 use serde_derive::Deserialize;
 
+/// Key Windows Installer keeps until the reboot an installation asked for has happened
+const INSTALLER_REBOOT_REQUIRED_KEY: &str = "SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\Installer\\RebootRequired";
+
+/// Key Windows Installer keeps while an installation is unfinished
+const INSTALLER_IN_PROGRESS_KEY: &str = "SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\Installer\\InProgress";
+
+/// Key holding Chocolatey's pending reboot marker
+const CHOCOLATEY_KEY: &str = "SOFTWARE\\Chocolatey";
+
+/// Name of the source reported while a Windows feature update waits for its reboot
+pub const OS_UPGRADE_SOURCE: &str = "os_upgrade";
+
+/// Directories Windows Setup stages a feature update in, relative to the system drive
+const UPGRADE_DIRECTORIES: [&str; 2] = ["$WINDOWS.~BT", "$WINDOWS.~WS"];
+
+/// Log Windows Setup writes while it stages a feature update, relative to the system drive
+const UPGRADE_SETUP_LOG: &str = "$WINDOWS.~BT\\Sources\\Panther\\setupact.log";
+
+/// Volatile key Windows Setup keeps from staging a feature update until the next boot
+const UPGRADE_VOLATILE_KEY: &str = "SYSTEM\\Setup\\MoSetup\\Volatile";
+
+/// How long each detection method may run when no timeout is configured
+const DEFAULT_DETECTION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long the slowly-changing system information is cached
+const SYSTEM_INFO_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Key holding the Windows product name
+const CURRENT_VERSION_KEY: &str = "SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion";
+
+/// Key holding the system model reported by the firmware
+pub const BIOS_KEY: &str = "HARDWARE\\DESCRIPTION\\System\\BIOS";
+
+/// Key holding the version of the SCCM client
+const SMS_CLIENT_KEY: &str = "SOFTWARE\\Microsoft\\SMS\\Mobile Client";
+
+/// First build of Windows 11, whose product name in the registry still says Windows 10
+const WINDOWS_11_BUILD: u32 = 22000;
+
+/// A detection method, returning the sources that require a reboot
+type DetectionCheck = fn(&RebootDetector) -> Result<Vec<RebootSource>>;
+
+/// An enabled detection method with its label and the name of the sources it reports
+type EnabledCheck = (&'static str, &'static str, DetectionCheck);
+
+/// Outcome of running the detection methods
+#[derive(Debug, Clone, Default)]
+pub struct DetectionResult {
+    /// Whether any source requires a reboot
+    pub required: bool,
+
+    /// Whether there are only sources that recommend a reboot
+    pub recommended: bool,
+
+    /// Sources that require a reboot
+    pub sources: Vec<RebootSource>,
+
+    /// Methods that failed
+    pub failed: Vec<String>,
+
+    /// Methods that didn't finish within the timeout
+    pub timed_out: Vec<String>,
+
+    /// Names of the sources reported by the methods that failed or timed out, whose state is
+    /// unknown after this run
+    pub unchecked_sources: Vec<String>,
+}
+
 /// Reboot detector
+#[derive(Clone)]
 pub struct RebootDetector {
     config: RebootConfig,
+    registry: Arc<dyn RegistryProvider>,
+    wmi: Arc<dyn WmiProvider>,
+    file_system: Arc<dyn FileSystemProvider>,
+    user_registry: Arc<dyn UserRegistryProvider>,
+    remote_host: Option<RemoteHost>,
+    identity_cache: Arc<Mutex<Option<(Instant, SystemIdentity)>>>,
+    clock: Arc<dyn Clock>,
 }
 
 impl RebootDetector {
     /// Create a new reboot detector
     pub fn new(config: &RebootConfig) -> Self {
+        Self::with_providers(
+            config,
+            Arc::new(WindowsRegistry),
+            Arc::new(WindowsWmi),
+            Arc::new(LocalFileSystem),
+        )
+    }
+
+    /// Create a reboot detector that reads the system through the given providers
+    pub fn with_providers(
+        config: &RebootConfig,
+        registry: Arc<dyn RegistryProvider>,
+        wmi: Arc<dyn WmiProvider>,
+        file_system: Arc<dyn FileSystemProvider>,
+    ) -> Self {
         Self {
             config: config.clone(),
+            registry,
+            wmi,
+            file_system,
+            user_registry: Arc::new(WindowsUserRegistry),
+            remote_host: None,
+            identity_cache: Arc::new(Mutex::new(None)),
+            clock: clock::system(),
+        }
+    }
+
+    /// Read the registry hives of signed-in users through the given provider
+    pub fn with_user_registry(mut self, user_registry: Arc<dyn UserRegistryProvider>) -> Self {
+        self.user_registry = user_registry;
+        self
+    }
+
+    /// Read the current time, which the uptime and the estimated boot time are worked out from,
+    /// from a clock other than the system's, e.g. the check cycle's
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Query WMI on a remote computer for the system information instead of this one
+    ///
+    /// WMI is always used for a remote computer, since the fallbacks only work locally.
+    pub fn with_remote_host(mut self, host: RemoteHost) -> Self {
+        self.remote_host = Some(host);
+        self
+    }
+
+    /// Replace the configuration, keeping the cached system information unless WMI was switched
+    /// on or off
+    pub fn set_config(&mut self, config: &RebootConfig) {
+        if config.use_wmi != self.config.use_wmi {
+            if let Ok(mut cache) = self.identity_cache.lock() {
+                *cache = None;
+            }
         }
+        self.config = config.clone();
     }
 
     /// Check if a reboot is required
     pub fn check_reboot_required(&self) -> Result<(bool, Vec<RebootSource>)> {
+        let result = self.detect();
+        Ok((result.required, result.sources))
+    }
+
+    /// Run the enabled detection methods in parallel and collect their results
+    ///
+    /// Each method runs on its own thread. Methods that don't finish within the configured timeout
+    /// are abandoned and listed in `timed_out`; the thread is left to finish in the background.
+    pub fn detect(&self) -> DetectionResult {
         info!("Checking if reboot is required");
-        let mut sources = Vec::new();
-        let mut is_required = false;
-
-        // Check Windows Update
-        if self.config.detection_methods.windows_update {
-            info!("Checking Windows Update to determine if a reboot is required");
-            match self.check_windows_update() {
-                Ok((required, source)) => {
-                    if required {
-                        info!("Windows Update requires a reboot: {}", source.description.as_deref().unwrap_or("No details"));
-                        is_required = true;
-                        sources.push(source);
-                    } else {
-                        info!("Windows Update does not require a reboot");
-                    }
+        let _timer = crate::metrics::Timer::start("detection.total");
+        let checks = self.enabled_checks();
+        let timeout = self.detection_timeout();
+        let (sender, receiver) = mpsc::channel();
+
+        for (index, (name, _, check)) in checks.iter().enumerate() {
+            info!("Checking {} to determine if a reboot is required", name);
+            let check = *check;
+            let worker = {
+                let detector = self.clone();
+                let sender = sender.clone();
+                let metric = format!("detection.{}", name.to_lowercase().replace(' ', "_"));
+                move || {
+                    let outcome = crate::metrics::time(&metric, || check(&detector));
+                    let _ = sender.send((index, outcome));
                 }
-                Err(e) => {
-                    warn!("Failed to check Windows Update: {}", e);
-                }
-            }
-        } else {
-            debug!("Windows Update check is disabled");
-        }
+            };
 
-        // Check SCCM
-        if self.config.detection_methods.sccm {
-            info!("Checking SCCM to determine if a reboot is required");
-            match self.check_sccm() {
-                Ok((required, source)) => {
-                    if required {
-                        info!("SCCM requires a reboot: {}", source.description.as_deref().unwrap_or("No details"));
-                        is_required = true;
-                        sources.push(source);
-                    } else {
-                        info!("SCCM does not require a reboot");
-                    }
-                }
-                Err(e) => {
-                    warn!("Failed to check SCCM: {}", e);
-                }
+            if let Err(e) = thread::Builder::new().name(format!("detect-{}", index)).spawn(worker) {
+                warn!("Failed to start a thread for the {} check, running it inline: {}", name, e);
+                let _ = sender.send((index, check(self)));
             }
-        } else {
-            debug!("SCCM check is disabled");
         }
-
-        // Check registry
-        if self.config.detection_methods.registry {
-            info!("Checking registry to determine if a reboot is required");
-            match self.check_registry() {
-                Ok((required, source)) => {
-                    if required {
-                        info!("Registry indicates a reboot is required: {}", source.description.as_deref().unwrap_or("No details"));
-                        is_required = true;
-                        sources.push(source);
-                    } else {
-                        info!("Registry does not indicate a reboot is required");
-                    }
-                }
-                Err(e) => {
-                    warn!("Failed to check registry: {}", e);
-                }
+        drop(sender);
+
+        let deadline = Instant::now() + timeout;
+        let mut outcomes: Vec<Option<Result<Vec<RebootSource>>>> = checks.iter().map(|_| None).collect();
+        while outcomes.iter().any(Option::is_none) {
+            match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok((index, outcome)) => outcomes[index] = Some(outcome),
+                Err(_) => break,
             }
-        } else {
-            debug!("Registry check is disabled");
         }
 
-        // Check pending file operations
-        if self.config.detection_methods.pending_file_operations {
-            info!("Checking for pending file operations that require a reboot");
-            match self.check_pending_file_operations() {
-                Ok((required, source)) => {
-                    if required {
-                        info!("Pending file operations require a reboot: {}", source.description.as_deref().unwrap_or("No details"));
-                        is_required = true;
-                        sources.push(source);
-                    } else {
-                        info!("No pending file operations requiring a reboot");
+        let mut result = DetectionResult::default();
+        for ((name, source_name, _), outcome) in checks.iter().zip(outcomes) {
+            match outcome {
+                Some(Ok(sources)) if !sources.is_empty() => {
+                    for source in &sources {
+                        info!("{} requires a reboot: {}", name, source.description.as_deref().unwrap_or("No details"));
                     }
+                    result.sources.extend(sources);
+                }
+                Some(Ok(_)) => info!("{} does not require a reboot", name),
+                Some(Err(e)) => {
+                    warn!("Failed to check {}: {}", name, e);
+                    crate::metrics::increment("detection.failures");
+                    result.failed.push(name.to_string());
+                    result.unchecked_sources.push(source_name.to_string());
                 }
-                Err(e) => {
-                    warn!("Failed to check pending file operations: {}", e);
+                None => {
+                    warn!("The {} check did not finish within {:?} and was skipped", name, timeout);
+                    crate::metrics::increment("detection.timeouts");
+                    result.timed_out.push(name.to_string());
+                    result.unchecked_sources.push(source_name.to_string());
                 }
             }
-        } else {
-            debug!("Pending file operations check is disabled");
         }
 
-        debug!("Reboot required: {}, sources: {:?}", is_required, sources);
+        apply_source_severities(&mut result.sources, &self.config.detection_methods.source_severities);
+        (result.required, result.recommended) = aggregate_severity(&result.sources);
+
+        debug!("Reboot required: {}, recommended: {}, sources: {:?}", result.required, result.recommended, result.sources);
         // Log the final result
-        if is_required {
-            info!("Reboot is required. Found {} sources requiring reboot.", sources.len());
-            for (i, source) in sources.iter().enumerate() {
+        if result.required {
+            info!("Reboot is required. Found {} sources requiring reboot.", result.sources.len());
+            for (i, source) in result.sources.iter().enumerate() {
                 info!("  Source {}: {} - {} (detected at {})",
                       i + 1,
                       source.name,
                       source.description.as_deref().unwrap_or("No details"),
                       source.detected_at);
             }
+        } else if result.recommended {
+            info!("Reboot is recommended. Found {} sources recommending reboot.", result.sources.len());
         } else {
             info!("No reboot is required");
         }
 
-        Ok((is_required, sources))
+        result
+    }
+
+    /// Get the enabled detection methods in the order their sources are reported
+    fn enabled_checks(&self) -> Vec<EnabledCheck> {
+        let methods = &self.config.detection_methods;
+        let mut checks: Vec<EnabledCheck> = Vec::new();
+
+        if methods.windows_update {
+            checks.push(("Windows Update", "windows_update", |detector| required(detector.check_windows_update())));
+        } else {
+            debug!("Windows Update check is disabled");
+        }
+        if methods.sccm {
+            checks.push(("SCCM", "sccm", |detector| required(detector.check_sccm())));
+        } else {
+            debug!("SCCM check is disabled");
+        }
+        if methods.registry {
+            checks.push(("registry", "registry", |detector| required(detector.check_registry())));
+        } else {
+            debug!("Registry check is disabled");
+        }
+        if methods.pending_file_operations {
+            checks.push(("pending file operations", "pending_file_operations", |detector| required(detector.check_pending_file_operations())));
+        } else {
+            debug!("Pending file operations check is disabled");
+        }
+        if methods.installer_operations {
+            checks.push(("installer operations", "installer_operations", |detector| required(detector.check_installer_operations())));
+        } else {
+            debug!("Installer operations check is disabled");
+        }
+        if methods.os_upgrade {
+            checks.push(("OS upgrade", OS_UPGRADE_SOURCE, |detector| required(detector.check_os_upgrade())));
+        } else {
+            debug!("OS upgrade check is disabled");
+        }
+        if !methods.user_registry_keys.is_empty() {
+            checks.push(("user registry", "user_registry", Self::check_user_registry));
+        }
+
+        checks
+    }
+
+    /// Get how long each detection method may run
+    fn detection_timeout(&self) -> Duration {
+        self.config
+            .detection_methods
+            .timeout
+            .as_deref()
+            .and_then(|timeout| crate::utils::timespan::parse_timespan(timeout).ok())
+            .unwrap_or(DEFAULT_DETECTION_TIMEOUT)
     }
 
     /// Check Windows Update to determine if a reboot is required
@@ -143,8 +323,7 @@ impl RebootDetector {
         );
 
         // Check the registry key that indicates Windows Update requires a reboot
-        let required = crate::utils::registry::key_exists(
-            HKEY_LOCAL_MACHINE,
+        let required = self.registry.key_exists(
             "SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\WindowsUpdate\\Auto Update\\RebootRequired"
         )?;
 
@@ -170,8 +349,7 @@ impl RebootDetector {
         );
 
         // Check if SCCM client service is installed
-        let impersonator = crate::impersonation::Impersonator::new();
-        let sccm_installed = match impersonator.is_sccm_client_installed() {
+        let sccm_installed = match self.wmi.is_sccm_client_installed() {
             Ok(installed) => installed,
             Err(e) => {
                 warn!("Failed to check if SCCM client is installed: {}", e);
@@ -194,7 +372,7 @@ impl RebootDetector {
         ];
 
         for path in &registry_paths {
-            if crate::utils::registry::key_exists(HKEY_LOCAL_MACHINE, path)? {
+            if self.registry.key_exists(path)? {
                 source.details = Some(format!("SCCM registry key indicates a reboot is pending: {}", path));
                 debug!("SCCM requires a reboot (registry key: {})", path);
                 return Ok((true, source));
@@ -208,7 +386,7 @@ impl RebootDetector {
         ];
 
         for file_path in &ccm_reboot_files {
-            if std::path::Path::new(file_path).exists() {
+            if self.file_system.exists(std::path::Path::new(file_path)) {
                 source.details = Some(format!("SCCM reboot file exists: {}", file_path));
                 debug!("SCCM requires a reboot (file exists: {})", file_path);
                 return Ok((true, source));
@@ -231,8 +409,7 @@ impl RebootDetector {
         );
 
         // Check Component Based Servicing
-        if crate::utils::registry::key_exists(
-            HKEY_LOCAL_MACHINE,
+        if self.registry.key_exists(
             "SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\Component Based Servicing\\RebootPending"
         )? {
             source.details = Some("Component Based Servicing registry key indicates a reboot is pending".to_string());
@@ -241,8 +418,7 @@ impl RebootDetector {
         }
 
         // Check Session Manager
-        if let Some(pending_renames) = crate::utils::registry::get_string_value(
-            HKEY_LOCAL_MACHINE,
+        if let Some(pending_renames) = self.registry.get_string_value(
             "SYSTEM\\CurrentControlSet\\Control\\Session Manager",
             "PendingFileRenameOperations"
         )? {
@@ -254,14 +430,12 @@ impl RebootDetector {
         }
 
         // Check for pending computer rename
-        let active_name = crate::utils::registry::get_string_value(
-            HKEY_LOCAL_MACHINE,
+        let active_name = self.registry.get_string_value(
             "SYSTEM\\CurrentControlSet\\Control\\ComputerName\\ActiveComputerName",
             "ComputerName"
         )?;
 
-        let pending_name = crate::utils::registry::get_string_value(
-            HKEY_LOCAL_MACHINE,
+        let pending_name = self.registry.get_string_value(
             "SYSTEM\\CurrentControlSet\\Control\\ComputerName\\ComputerName",
             "ComputerName"
         )?;
@@ -278,6 +452,44 @@ impl RebootDetector {
         Ok((false, source))
     }
 
+    /// Check for reboots left pending by installers
+    ///
+    /// Windows Installer keeps the `InProgress` key while an installation is unfinished and
+    /// `RebootRequired` until the reboot an installation asked for has happened; winget installs
+    /// MSI packages through it. Chocolatey records a pending reboot in its `PendingReboot` value.
+    fn check_installer_operations(&self) -> Result<(bool, RebootSource)> {
+        debug!("Checking installers to determine if a reboot is required");
+
+        // Create a source object
+        let mut source = RebootSource::new(
+            "installer_operations",
+            Some("An installer requires a reboot"),
+            "required",
+        );
+
+        for (key_path, details) in [
+            (INSTALLER_REBOOT_REQUIRED_KEY, "Windows Installer requires a reboot to finish an installation"),
+            (INSTALLER_IN_PROGRESS_KEY, "A Windows Installer installation is in progress"),
+        ] {
+            if self.registry.key_exists(key_path)? {
+                source.details = Some(details.to_string());
+                debug!("{}", details);
+                return Ok((true, source));
+            }
+        }
+
+        if let Some(pending) = self.registry.get_string_value(CHOCOLATEY_KEY, "PendingReboot")? {
+            if !pending.is_empty() && pending != "0" && !pending.eq_ignore_ascii_case("false") {
+                source.details = Some(format!("Chocolatey requires a reboot ({})", pending));
+                debug!("Chocolatey requires a reboot");
+                return Ok((true, source));
+            }
+        }
+
+        debug!("Installers do not require a reboot");
+        Ok((false, source))
+    }
+
     /// Check for pending file operations that require a reboot
     fn check_pending_file_operations(&self) -> Result<(bool, RebootSource)> {
         debug!("Checking for pending file operations that require a reboot");
@@ -290,8 +502,7 @@ impl RebootDetector {
         );
 
         // Check for pending file rename operations in the registry
-        if let Some(pending_renames) = crate::utils::registry::get_string_value(
-            HKEY_LOCAL_MACHINE,
+        if let Some(pending_renames) = self.registry.get_string_value(
             "SYSTEM\\CurrentControlSet\\Control\\Session Manager",
             "PendingFileRenameOperations"
         )? {
@@ -302,290 +513,350 @@ impl RebootDetector {
             }
         }
 
-        // Check for Windows.~BT or Windows.~WS directories
-        let win_dir = std::env::var("WINDIR").unwrap_or_else(|_| "C:\\Windows".to_string());
-        let bt_dir = std::path::Path::new(&win_dir).join("Windows.~BT");
-        let ws_dir = std::path::Path::new(&win_dir).join("Windows.~WS");
+        debug!("No pending file operations detected");
+        Ok((false, source))
+    }
+
+    /// Check for a Windows feature update waiting for its reboot
+    ///
+    /// Windows Setup stages an in-place upgrade in the `$WINDOWS.~BT` or `$WINDOWS.~WS`
+    /// directories on the system drive, logs it to `setupact.log` under `$WINDOWS.~BT` and keeps
+    /// a volatile registry key until the next boot. The directories can stay behind after the
+    /// upgrade or a rollback, so they only count when they changed since the last boot. The reboot
+    /// installs the new version of Windows, which takes much longer than for other updates, so it's
+    /// a source of its own.
+    fn check_os_upgrade(&self) -> Result<(bool, RebootSource)> {
+        debug!("Checking for a pending Windows feature update");
+
+        // Create a source object
+        let mut source = RebootSource::new(
+            OS_UPGRADE_SOURCE,
+            Some("A Windows feature update requires a reboot"),
+            "required",
+        );
 
-        if bt_dir.exists() {
-            source.details = Some("Windows.~BT directory exists, indicating a pending Windows upgrade".to_string());
-            debug!("Windows.~BT directory exists, indicating a pending Windows upgrade");
+        if self.registry.key_exists(UPGRADE_VOLATILE_KEY)? {
+            let details = "Windows Setup has staged a feature update since the last boot";
+            source.details = Some(details.to_string());
+            debug!("{}", details);
             return Ok((true, source));
         }
 
-        if ws_dir.exists() {
-            source.details = Some("Windows.~WS directory exists, indicating a pending Windows upgrade".to_string());
-            debug!("Windows.~WS directory exists, indicating a pending Windows upgrade");
-            return Ok((true, source));
+        let system_drive = std::env::var("SystemDrive").unwrap_or_else(|_| "C:".to_string());
+        let root = std::path::PathBuf::from(format!("{}\\", system_drive));
+        let markers: Vec<_> = UPGRADE_DIRECTORIES
+            .iter()
+            .chain([UPGRADE_SETUP_LOG].iter())
+            .filter_map(|marker| self.file_system.modified(&root.join(marker)).map(|modified| (*marker, modified)))
+            .collect();
+        if markers.is_empty() {
+            debug!("No Windows feature update is pending");
+            return Ok((false, source));
         }
 
-        debug!("No pending file operations detected");
+        let last_boot_time = self.get_last_boot_time().context("Failed to get the last boot time")?;
+        for (marker, modified) in markers {
+            if modified > last_boot_time {
+                let details = format!("{} changed since the last boot, indicating a pending Windows upgrade", marker);
+                debug!("{}", details);
+                source.details = Some(details);
+                return Ok((true, source));
+            }
+            debug!("{} is left over from before the last boot ({}), ignoring it", marker, modified);
+        }
+
+        debug!("No Windows feature update is pending");
         Ok((false, source))
     }
 
-    /// Get the last boot time using WMI
-    pub fn get_last_boot_time(&self) -> Result<DateTime<Utc>> {
-        debug!("Getting last boot time using WMI");
+    /// Check the registry hives of signed-in users for pending reboot markers
+    ///
+    /// Each user with a marker gets their own source, attributed to them so only their sessions
+    /// are notified.
+    fn check_user_registry(&self) -> Result<Vec<RebootSource>> {
+        debug!("Checking user registry hives to determine if a reboot is required");
 
-        // Use WMI to get the last boot time
-        let wmi_con = wmi::WMIConnection::new(wmi::COMLibrary::new()?.into())
-            .context("Failed to connect to WMI")?;
+        let mut sources = Vec::new();
+        for (user_name, user_sid) in self.user_registry.active_users()? {
+            for key in &self.config.detection_methods.user_registry_keys {
+                let found = match &key.value_name {
+                    Some(value_name) => self
+                        .user_registry
+                        .get_string_value(&user_sid, &key.key_path, value_name)?
+                        .is_some_and(|value| !value.is_empty()),
+                    None => self.user_registry.key_exists(&user_sid, &key.key_path)?,
+                };
+                if !found {
+                    continue;
+                }
 
-        // Define a struct to hold the WMI query results
-        #[derive(Debug, Deserialize)]
-        struct OSInfo {
-            #[serde(rename = "LastBootUpTime")]
-            last_boot_up_time: String,
+                let location = match &key.value_name {
+                    Some(value_name) => format!("HKCU\\{} ({})", key.key_path, value_name),
+                    None => format!("HKCU\\{}", key.key_path),
+                };
+                debug!("User {} requires a reboot ({})", crate::logging::user(&user_name), location);
+                let mut source = RebootSource::new(
+                    "user_registry",
+                    Some(&format!("A pending operation for {} requires a reboot", user_name)),
+                    "required",
+                );
+                source.details = Some(format!("User registry indicates a reboot is pending: {}", location));
+                source.user_name = Some(user_name.clone());
+                sources.push(source);
+                break;
+            }
         }
 
-        // Query WMI for the last boot time
-        let results: Vec<OSInfo> = wmi_con.query()
-            .context("Failed to query WMI for last boot time")?;
-
-        if results.is_empty() {
-            return Err(anyhow::anyhow!("No OS information found in WMI"));
+        if sources.is_empty() {
+            debug!("User registry hives do not indicate a reboot is required");
         }
+        Ok(sources)
+    }
 
-        let last_boot_time = &results[0].last_boot_up_time;
-
-        // WMI returns the time in a format like: 20230101000000.000000+000
-        // We need to parse this into a DateTime<Utc>
+    /// Whether WMI is queried for the boot time and system information
+    fn use_wmi(&self) -> bool {
+        self.config.use_wmi || self.remote_host.is_some()
+    }
 
-        // Extract the date and time parts
-        if last_boot_time.len() < 14 {
-            return Err(anyhow::anyhow!("Invalid last boot time format: {}", last_boot_time));
+    /// Run queries against a WMI namespace of the computer being checked
+    fn with_wmi<T: Send + 'static>(
+        &self,
+        namespace: &str,
+        query: impl FnOnce(&wmi::WMIConnection) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        match &self.remote_host {
+            Some(host) => with_remote_wmi_connection(host, namespace, query),
+            None => with_wmi_connection(namespace, query),
         }
+    }
 
-        let year = &last_boot_time[0..4];
-        let month = &last_boot_time[4..6];
-        let day = &last_boot_time[6..8];
-        let hour = &last_boot_time[8..10];
-        let minute = &last_boot_time[10..12];
-        let second = &last_boot_time[12..14];
+    /// Get the last boot time using WMI, or from the tick count when WMI is disabled
+    pub fn get_last_boot_time(&self) -> Result<DateTime<Utc>> {
+        if !self.use_wmi() {
+            return self.get_last_boot_time_fallback();
+        }
+        debug!("Getting last boot time using WMI");
 
-        // Parse into DateTime<Utc>
-        let datetime_str = format!("{}-{}-{}T{}:{}:{}Z", year, month, day, hour, minute, second);
-        let datetime = DateTime::parse_from_rfc3339(&datetime_str)
-            .context("Failed to parse last boot time")?
-            .with_timezone(&Utc);
+        let last_boot_time = self.wmi.last_boot_up_time()?;
+        let datetime = parse_cim_datetime(&last_boot_time)?;
 
         debug!("Last boot time: {}", datetime);
         Ok(datetime)
     }
 
-    /// Get system information using WMI with optimized queries
+    /// Get system information
+    ///
+    /// Fields that rarely change (computer name, OS, domain, model and SCCM client) are cached for
+    /// an hour; the boot time and IP address are read on every call.
     pub fn get_system_info(&self) -> Result<SystemInfo> {
-        debug!("Getting system information using WMI");
+        debug!("Getting system information");
 
-        // Create a single WMI connection to reuse
-        let com_lib = match wmi::COMLibrary::new() {
-            Ok(lib) => lib,
+        let identity = match self.cached_identity() {
+            Ok(identity) => identity,
+            Err(e) if self.remote_host.is_some() => return Err(e),
             Err(e) => {
-                warn!("Failed to initialize COM library: {}", e);
-                return Err(anyhow::anyhow!("Failed to initialize COM library: {}", e));
+                warn!("Failed to query WMI for system information: {}", e);
+                // Try a simpler approach without WMI
+                warn!("Attempting fallback with separate queries");
+                return self.get_system_info_fallback();
             }
         };
 
-        let wmi_con = match wmi::WMIConnection::new(com_lib.into()) {
-            Ok(con) => con,
+        let last_boot_time = match self.get_last_boot_time() {
+            Ok(last_boot_time) => last_boot_time,
+            Err(e) if self.remote_host.is_some() => return Err(e),
             Err(e) => {
-                warn!("Failed to connect to WMI: {}", e);
-                return Err(anyhow::anyhow!("Failed to connect to WMI: {}", e));
+                warn!("Failed to get last boot time from WMI: {}", e);
+                self.get_last_boot_time_fallback()?
             }
         };
 
-        // Define a combined struct to hold the WMI query results
-        // This allows us to get multiple properties in a single query
+        // Continue without network adapter information if the query fails
+        let ip_address = if self.use_wmi() {
+            self.query_ip_address().unwrap_or_else(|e| {
+                warn!("Failed to query WMI for network adapter information: {}", e);
+                None
+            })
+        } else {
+            None
+        };
+
+        // Calculate uptime
+        let uptime = self.clock.now().signed_duration_since(last_boot_time).num_seconds();
+
+        let info = SystemInfo {
+            computer_name: identity.computer_name,
+            os_version: identity.os_version,
+            last_boot_time,
+            uptime,
+            ip_address,
+            domain: identity.domain,
+            is_virtual_machine: identity.is_virtual_machine,
+            sccm_client_installed: identity.sccm_client_installed,
+            sccm_client_version: identity.sccm_client_version,
+        };
+
+        debug!("System information: {:?}", info);
+        Ok(info)
+    }
+
+    /// Get the slowly-changing system information, querying WMI when the cached copy has expired
+    fn cached_identity(&self) -> Result<SystemIdentity> {
+        let mut cache = match self.identity_cache.lock() {
+            Ok(cache) => cache,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        if let Some((queried_at, identity)) = cache.as_ref() {
+            if queried_at.elapsed() < SYSTEM_INFO_TTL {
+                debug!("Using cached system information");
+                return Ok(identity.clone());
+            }
+        }
+
+        let identity = self.query_identity()?;
+        *cache = Some((Instant::now(), identity.clone()));
+        Ok(identity)
+    }
+
+    /// Query WMI for the slowly-changing system information
+    fn query_identity(&self) -> Result<SystemIdentity> {
+        if !self.use_wmi() {
+            return self.read_identity();
+        }
+
         #[derive(Debug, Deserialize)]
-        struct SystemInfoWMI {
-            // OS properties
+        struct OperatingSystem {
             #[serde(rename = "Caption")]
             caption: Option<String>,
             #[serde(rename = "CSName")]
             computer_name: Option<String>,
-            #[serde(rename = "LastBootUpTime")]
-            last_boot_up_time: Option<String>,
+        }
 
-            // ComputerSystem properties
+        #[derive(Debug, Deserialize)]
+        struct ComputerSystem {
             #[serde(rename = "Domain")]
             domain: Option<String>,
             #[serde(rename = "Model")]
             model: Option<String>,
         }
 
-        // Define network adapter info struct
-        #[derive(Debug, Deserialize)]
-        struct NetworkAdapterInfo {
-            #[serde(rename = "IPAddress")]
-            ip_address: Option<Vec<String>>,
-        }
-
-        // Query WMI for OS and ComputerSystem information in a single query
-        // This is more efficient than multiple queries
-        let query = "SELECT OS.Caption, OS.CSName, OS.LastBootUpTime, CS.Domain, CS.Model \
-                    FROM Win32_OperatingSystem AS OS, Win32_ComputerSystem AS CS";
+        let (os, cs) = self.with_wmi(CIMV2_NAMESPACE, |wmi_con| {
+            let os: Vec<OperatingSystem> = wmi_con.raw_query("SELECT Caption, CSName FROM Win32_OperatingSystem")?;
+            let cs: Vec<ComputerSystem> = wmi_con.raw_query("SELECT Domain, Model FROM Win32_ComputerSystem")?;
+            Ok((os, cs))
+        })?;
 
-        // Try a simpler query first to test the connection
-        let test_query = "SELECT Caption FROM Win32_OperatingSystem";
-        match wmi_con.raw_query::<serde_json::Value>(test_query) {
-            Ok(_) => debug!("WMI connection test successful"),
-            Err(e) => {
-                warn!("WMI connection test failed: {}", e);
-                // Try to reinitialize the COM library
-                let com_lib = match wmi::COMLibrary::new() {
-                    Ok(lib) => lib,
-                    Err(e) => {
-                        warn!("Failed to reinitialize COM library: {}", e);
-                        return Err(anyhow::anyhow!("Failed to reinitialize COM library: {}", e));
-                    }
-                };
+        let os = os.into_iter().next().ok_or_else(|| anyhow::anyhow!("No system information found in WMI"))?;
+        let cs = cs.into_iter().next();
 
-                let _wmi_con = match wmi::WMIConnection::new(com_lib.into()) {
-                    Ok(con) => con,
-                    Err(e) => {
-                        warn!("Failed to reconnect to WMI: {}", e);
-                        return Err(anyhow::anyhow!("Failed to reconnect to WMI: {}", e));
-                    }
-                };
-            }
-        }
+        // Extract values with defaults for missing data
+        let computer_name = os.computer_name.unwrap_or_else(|| "Unknown".to_string());
+        let os_version = os.caption.unwrap_or_else(|| "Unknown".to_string());
+        let domain = cs.as_ref().and_then(|cs| cs.domain.clone()).unwrap_or_else(|| "Unknown".to_string());
+        let model = cs.and_then(|cs| cs.model).unwrap_or_default().to_lowercase();
+        let is_virtual_machine = is_virtual_model(&model);
 
-        let results: Vec<SystemInfoWMI> = match wmi_con.raw_query(query) {
-            Ok(results) => results,
+        // Check if SCCM client is installed using the service check
+        let sccm_client_installed = match self.wmi.is_sccm_client_installed() {
+            Ok(installed) => installed,
             Err(e) => {
-                warn!("Failed to query WMI for system information: {}", e);
-                // Try a simpler approach with separate queries
-                warn!("Attempting fallback with separate queries");
-                return self.get_system_info_fallback();
+                warn!("Failed to check if SCCM client is installed: {}", e);
+                false
             }
         };
 
-        if results.is_empty() {
-            return Err(anyhow::anyhow!("No system information found in WMI"));
-        }
-
-        let system_info = &results[0];
-
-        // Extract values with defaults for missing data
-        let computer_name = system_info.computer_name.clone().unwrap_or_else(|| "Unknown".to_string());
-        let os_version = system_info.caption.clone().unwrap_or_else(|| "Unknown".to_string());
-        let domain = system_info.domain.clone().unwrap_or_else(|| "Unknown".to_string());
-        let model = system_info.model.clone().unwrap_or_default().to_lowercase();
-
-        // Parse last boot time if available
-        let last_boot_time = if let Some(boot_time_str) = &system_info.last_boot_up_time {
-            if boot_time_str.len() >= 14 {
-                let year = &boot_time_str[0..4];
-                let month = &boot_time_str[4..6];
-                let day = &boot_time_str[6..8];
-                let hour = &boot_time_str[8..10];
-                let minute = &boot_time_str[10..12];
-                let second = &boot_time_str[12..14];
-
-                let datetime_str = format!("{}-{}-{}T{}:{}:{}Z", year, month, day, hour, minute, second);
-                match DateTime::parse_from_rfc3339(&datetime_str) {
-                    Ok(dt) => dt.with_timezone(&Utc),
-                    Err(e) => {
-                        warn!("Failed to parse last boot time from WMI: {}", e);
-                        // Fall back to the dedicated method
-                        self.get_last_boot_time()?
-                    }
-                }
-            } else {
-                // Fall back to the dedicated method
-                self.get_last_boot_time()?
-            }
+        let sccm_client_version = if sccm_client_installed {
+            self.query_sccm_client_version().unwrap_or_else(|e| {
+                warn!("Failed to query WMI for SCCM client version: {}", e);
+                None
+            })
         } else {
-            // Fall back to the dedicated method
-            self.get_last_boot_time()?
+            None
         };
 
-        // Query WMI for network adapter information with a more efficient query
-        let query = "SELECT IPAddress FROM Win32_NetworkAdapterConfiguration WHERE IPEnabled = True";
-        let na_results: Vec<NetworkAdapterInfo> = match wmi_con.raw_query(query) {
-            Ok(results) => results,
-            Err(e) => {
-                warn!("Failed to query WMI for network adapter information: {}", e);
-                // Continue without network adapter information
-                Vec::new()
-            }
-        };
+        Ok(SystemIdentity {
+            computer_name,
+            os_version,
+            domain,
+            is_virtual_machine,
+            sccm_client_installed,
+            sccm_client_version,
+        })
+    }
 
-        let ip_address = na_results.iter()
-            .filter_map(|na| na.ip_address.as_ref())
-            .flat_map(|ips| ips.iter())
-            .find(|ip| ip.contains('.')) // Filter for IPv4 addresses
-            .map(|ip| ip.to_string());
+    /// Read the slowly-changing system information from Win32 APIs and the registry, for when
+    /// WMI is disabled
+    fn read_identity(&self) -> Result<SystemIdentity> {
+        let computer_name = computer_name(ComputerNameNetBIOS)
+            .or_else(|| std::env::var("COMPUTERNAME").ok())
+            .unwrap_or_else(|| "Unknown".to_string());
+        let domain = computer_name(ComputerNameDnsDomain)
+            .filter(|domain| !domain.is_empty())
+            .unwrap_or_else(|| "WORKGROUP".to_string());
 
-        // Calculate uptime
-        let now = Utc::now();
-        let uptime = now.signed_duration_since(last_boot_time).num_seconds();
+        let product_name = self.registry.get_string_value(CURRENT_VERSION_KEY, "ProductName")?;
+        let os_version = os_version(product_name, os_build());
 
-        let is_virtual_machine = model.contains("virtual") || model.contains("vmware") || model.contains("hyper-v");
+        let model = self.registry.get_string_value(BIOS_KEY, "SystemProductName")?.unwrap_or_default();
+        let is_virtual_machine = is_virtual_model(&model);
 
-        // Check if SCCM client is installed using the service check
-        let impersonator = crate::impersonation::Impersonator::new();
-        let sccm_client_installed = match impersonator.is_sccm_client_installed() {
+        let sccm_client_installed = match self.wmi.is_sccm_client_installed() {
             Ok(installed) => installed,
             Err(e) => {
                 warn!("Failed to check if SCCM client is installed: {}", e);
                 false
             }
         };
-
-        // Get SCCM client version using WMI
         let sccm_client_version = if sccm_client_installed {
-            // Use WMI to get the SCCM client version
-            match wmi::WMIConnection::new(wmi::COMLibrary::new()?.into()) {
-                Ok(_wmi_con) => {
-                    // Define a struct to hold the WMI query results
-                    #[derive(Debug, Deserialize)]
-                    struct CCMClientVersion {
-                        #[serde(rename = "ClientVersion")]
-                        client_version: Option<String>,
-                    }
-
-                    // Query WMI for the client version
-                    let query = "SELECT ClientVersion FROM CCM_InstalledComponent WHERE Name='SMS Client'";
-                    let wmi_con = wmi::WMIConnection::with_namespace_path("root\\ccm", COMLibrary::new()?.into())?;
-
-                    match wmi_con.raw_query::<CCMClientVersion>(query) {
-                        Ok(results) => {
-                            if let Some(result) = results.first() {
-                                result.client_version.clone()
-                            } else {
-                                None
-                            }
-                        },
-                        Err(e) => {
-                            warn!("Failed to query WMI for SCCM client version: {}", e);
-                            None
-                        }
-                    }
-                },
-                Err(e) => {
-                    warn!("Failed to connect to WMI: {}", e);
-                    None
-                }
-            }
+            self.registry.get_string_value(SMS_CLIENT_KEY, "ProductVersion")?
         } else {
             None
         };
 
-        let info = SystemInfo {
+        Ok(SystemIdentity {
             computer_name,
             os_version,
-            last_boot_time,
-            uptime,
-            ip_address,
             domain,
             is_virtual_machine,
             sccm_client_installed,
             sccm_client_version,
-        };
+        })
+    }
 
-        debug!("System information: {:?}", info);
-        Ok(info)
+    /// Get the first IPv4 address of the enabled network adapters
+    fn query_ip_address(&self) -> Result<Option<String>> {
+        #[derive(Debug, Deserialize)]
+        struct NetworkAdapterInfo {
+            #[serde(rename = "IPAddress")]
+            ip_address: Option<Vec<String>>,
+        }
+
+        let adapters: Vec<NetworkAdapterInfo> = self.with_wmi(CIMV2_NAMESPACE, |wmi_con| {
+            Ok(wmi_con.raw_query("SELECT IPAddress FROM Win32_NetworkAdapterConfiguration WHERE IPEnabled = True")?)
+        })?;
+
+        Ok(adapters
+            .iter()
+            .filter_map(|adapter| adapter.ip_address.as_ref())
+            .flat_map(|ips| ips.iter())
+            .find(|ip| ip.contains('.')) // Filter for IPv4 addresses
+            .map(|ip| ip.to_string()))
+    }
+
+    /// Get the version of the installed SCCM client
+    fn query_sccm_client_version(&self) -> Result<Option<String>> {
+        #[derive(Debug, Deserialize)]
+        struct CCMClientVersion {
+            #[serde(rename = "ClientVersion")]
+            client_version: Option<String>,
+        }
+
+        let results: Vec<CCMClientVersion> = self.with_wmi("root\\ccm", |wmi_con| {
+            Ok(wmi_con.raw_query("SELECT ClientVersion FROM CCM_InstalledComponent WHERE Name='SMS Client'")?)
+        })?;
+
+        Ok(results.into_iter().next().and_then(|result| result.client_version))
     }
 
     /// Fallback method to get system information when the optimized query fails
@@ -596,7 +867,7 @@ impl RebootDetector {
         let mut info = SystemInfo {
             computer_name: "Unknown".to_string(),
             os_version: "Unknown".to_string(),
-            last_boot_time: Utc::now(), // Default to current time
+            last_boot_time: self.clock.now(), // Default to current time
             uptime: 0,
             ip_address: None,
             domain: "Unknown".to_string(),
@@ -614,8 +885,7 @@ impl RebootDetector {
         match self.get_last_boot_time_fallback() {
             Ok(boot_time) => {
                 info.last_boot_time = boot_time;
-                let now = Utc::now();
-                info.uptime = now.signed_duration_since(boot_time).num_seconds();
+                info.uptime = self.clock.now().signed_duration_since(boot_time).num_seconds();
             },
             Err(e) => {
                 warn!("Failed to get last boot time using fallback method: {}", e);
@@ -635,7 +905,7 @@ impl RebootDetector {
             use windows::Win32::System::SystemInformation::GetTickCount64;
 
             let tick_count = GetTickCount64();
-            let now = Utc::now();
+            let now = self.clock.now();
             let duration = chrono::Duration::milliseconds(tick_count as i64);
             let boot_time = now - duration;
 
@@ -645,6 +915,106 @@ impl RebootDetector {
     }
 }
 
+/// Get the sources of a detection method that reports a single source
+fn required(outcome: Result<(bool, RebootSource)>) -> Result<Vec<RebootSource>> {
+    outcome.map(|(required, source)| if required { vec![source] } else { Vec::new() })
+}
+
+/// Set the configured severity on sources, by source name
+fn apply_source_severities(sources: &mut [RebootSource], severities: &HashMap<String, SourceSeverity>) {
+    for source in sources {
+        if let Some(severity) = severities.get(&source.name) {
+            source.severity = severity.as_str().to_string();
+        }
+    }
+}
+
+/// Get whether sources require a reboot and, if not, whether they recommend one
+///
+/// Any required source makes the reboot required; otherwise any source makes it recommended.
+pub fn aggregate_severity(sources: &[RebootSource]) -> (bool, bool) {
+    let required = sources.iter().any(|source| source.severity != SourceSeverity::Recommended.as_str());
+    (required, !required && !sources.is_empty())
+}
+
+/// Parse a CIM datetime as returned by WMI (e.g., "20230101000000.000000+000")
+fn parse_cim_datetime(value: &str) -> Result<DateTime<Utc>> {
+    // Extract the date and time parts
+    if value.len() < 14 || !value.is_char_boundary(14) {
+        return Err(anyhow::anyhow!("Invalid last boot time format: {}", value));
+    }
+
+    let year = &value[0..4];
+    let month = &value[4..6];
+    let day = &value[6..8];
+    let hour = &value[8..10];
+    let minute = &value[10..12];
+    let second = &value[12..14];
+
+    // Parse into DateTime<Utc>
+    let datetime_str = format!("{}-{}-{}T{}:{}:{}Z", year, month, day, hour, minute, second);
+    let datetime = DateTime::parse_from_rfc3339(&datetime_str)
+        .context("Failed to parse last boot time")?
+        .with_timezone(&Utc);
+
+    Ok(datetime)
+}
+
+/// Get the name of this computer in a format, or None if it can't be read
+fn computer_name(format: COMPUTER_NAME_FORMAT) -> Option<String> {
+    let mut size = 0u32;
+    unsafe {
+        // The first call gets the size of the buffer
+        let _ = GetComputerNameExW(format, None, &mut size);
+        let mut buffer = vec![0u16; size as usize];
+        GetComputerNameExW(format, Some(PWSTR(buffer.as_mut_ptr())), &mut size).ok()?;
+        Some(String::from_utf16_lossy(&buffer[..size as usize]))
+    }
+}
+
+/// Get the major, minor and build number of Windows from `RtlGetVersion`, which unlike
+/// `GetVersionEx` isn't affected by compatibility shims
+fn os_build() -> Option<(u32, u32, u32)> {
+    let mut info = OSVERSIONINFOW {
+        dwOSVersionInfoSize: std::mem::size_of::<OSVERSIONINFOW>() as u32,
+        ..Default::default()
+    };
+    let status = unsafe { RtlGetVersion(&mut info) };
+    status.is_ok().then_some((info.dwMajorVersion, info.dwMinorVersion, info.dwBuildNumber))
+}
+
+/// Describe the Windows version like the WMI caption, e.g. "Windows 11 Pro (10.0.22631)"
+fn os_version(product_name: Option<String>, build: Option<(u32, u32, u32)>) -> String {
+    let product_name = match (product_name, build) {
+        (Some(name), Some((_, _, build))) if build >= WINDOWS_11_BUILD => Some(name.replacen("Windows 10", "Windows 11", 1)),
+        (name, _) => name,
+    };
+
+    match (product_name, build) {
+        (Some(name), Some((major, minor, build))) => format!("{} ({}.{}.{})", name, major, minor, build),
+        (Some(name), None) => name,
+        (None, Some((major, minor, build))) => format!("Windows {}.{}.{}", major, minor, build),
+        (None, None) => "Unknown".to_string(),
+    }
+}
+
+/// Check if a computer model (from Win32_ComputerSystem) is a virtual machine
+pub fn is_virtual_model(model: &str) -> bool {
+    let model = model.to_lowercase();
+    model.contains("virtual") || model.contains("vmware") || model.contains("hyper-v")
+}
+
+/// System information that rarely changes
+#[derive(Debug, Clone)]
+struct SystemIdentity {
+    computer_name: String,
+    os_version: String,
+    domain: String,
+    is_virtual_machine: bool,
+    sccm_client_installed: bool,
+    sccm_client_version: Option<String>,
+}
+
 /// System information
 #[derive(Debug, Clone)]
 pub struct SystemInfo {
@@ -675,3 +1045,289 @@ pub struct SystemInfo {
     /// SCCM client version
     pub sccm_client_version: Option<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reboot::providers::mock::{MockFileSystem, MockRegistry, MockUserRegistry, MockWmi};
+
+    const WINDOWS_UPDATE_KEY: &str = "SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\WindowsUpdate\\Auto Update\\RebootRequired";
+    const SCCM_KEY: &str = "SOFTWARE\\Microsoft\\CCM\\ClientSDK\\RebootPending";
+    const SESSION_MANAGER_KEY: &str = "SYSTEM\\CurrentControlSet\\Control\\Session Manager";
+
+    fn detector(registry: MockRegistry, wmi: MockWmi, file_system: MockFileSystem) -> RebootDetector {
+        RebootDetector::with_providers(
+            &crate::config::default().reboot,
+            Arc::new(registry),
+            Arc::new(wmi),
+            Arc::new(file_system),
+        )
+    }
+
+    fn source_names(sources: &[RebootSource]) -> Vec<&str> {
+        sources.iter().map(|source| source.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_no_reboot_required() {
+        let detector = detector(MockRegistry::default(), MockWmi::default(), MockFileSystem::default());
+        let (required, sources) = detector.check_reboot_required().unwrap();
+        assert!(!required);
+        assert!(sources.is_empty());
+    }
+
+    #[test]
+    fn test_sources_are_aggregated() {
+        let registry = MockRegistry::default()
+            .with_key(WINDOWS_UPDATE_KEY)
+            .with_value(SESSION_MANAGER_KEY, "PendingFileRenameOperations", "\\??\\C:\\old.dll");
+        let detector = detector(registry, MockWmi::default(), MockFileSystem::default());
+
+        let (required, sources) = detector.check_reboot_required().unwrap();
+        assert!(required);
+        assert_eq!(source_names(&sources), vec!["windows_update", "registry", "pending_file_operations"]);
+        assert!(sources.iter().all(|source| source.severity == "required"));
+    }
+
+    #[test]
+    fn test_installer_operations() {
+        let mut config = crate::config::default().reboot;
+        config.detection_methods.installer_operations = true;
+        let check = |registry: MockRegistry| {
+            RebootDetector::with_providers(
+                &config,
+                Arc::new(registry),
+                Arc::new(MockWmi::default()),
+                Arc::new(MockFileSystem::default()),
+            )
+            .check_reboot_required()
+            .unwrap()
+        };
+
+        let (required, _) = check(MockRegistry::default());
+        assert!(!required);
+
+        let (required, sources) = check(MockRegistry::default().with_key(INSTALLER_IN_PROGRESS_KEY));
+        assert!(required);
+        assert_eq!(source_names(&sources), vec!["installer_operations"]);
+
+        let (required, _) = check(MockRegistry::default().with_value(CHOCOLATEY_KEY, "PendingReboot", "0"));
+        assert!(!required);
+        let (required, _) = check(MockRegistry::default().with_value(CHOCOLATEY_KEY, "PendingReboot", "1"));
+        assert!(required);
+    }
+
+    #[test]
+    fn test_source_severities() {
+        let mut config = crate::config::default().reboot;
+        config.detection_methods.registry = false;
+        config
+            .detection_methods
+            .source_severities
+            .insert("pending_file_operations".to_string(), SourceSeverity::Recommended);
+        let registry = || MockRegistry::default().with_value(SESSION_MANAGER_KEY, "PendingFileRenameOperations", "\\??\\C:\\old.dll");
+
+        let detector = RebootDetector::with_providers(
+            &config,
+            Arc::new(registry()),
+            Arc::new(MockWmi::default()),
+            Arc::new(MockFileSystem::default()),
+        );
+        let result = detector.detect();
+        assert!(!result.required);
+        assert!(result.recommended);
+        assert_eq!(result.sources[0].severity, "recommended");
+
+        let detector = RebootDetector::with_providers(
+            &config,
+            Arc::new(registry().with_key(WINDOWS_UPDATE_KEY)),
+            Arc::new(MockWmi::default()),
+            Arc::new(MockFileSystem::default()),
+        );
+        let result = detector.detect();
+        assert!(result.required);
+        assert!(!result.recommended);
+    }
+
+    #[test]
+    fn test_sccm_requires_client() {
+        let registry = MockRegistry::default().with_key(SCCM_KEY);
+        let detector_without_client = detector(registry, MockWmi::default(), MockFileSystem::default());
+        let (required, _) = detector_without_client.check_reboot_required().unwrap();
+        assert!(!required);
+
+        let registry = MockRegistry::default().with_key(SCCM_KEY);
+        let wmi = MockWmi { sccm_client_installed: true, ..Default::default() };
+        let detector_with_client = detector(registry, wmi, MockFileSystem::default());
+        let (required, sources) = detector_with_client.check_reboot_required().unwrap();
+        assert!(required);
+        assert_eq!(source_names(&sources), vec!["sccm"]);
+    }
+
+    #[test]
+    fn test_pending_computer_rename() {
+        let registry = MockRegistry::default()
+            .with_value("SYSTEM\\CurrentControlSet\\Control\\ComputerName\\ActiveComputerName", "ComputerName", "OLD-NAME")
+            .with_value("SYSTEM\\CurrentControlSet\\Control\\ComputerName\\ComputerName", "ComputerName", "NEW-NAME");
+        let detector = detector(registry, MockWmi::default(), MockFileSystem::default());
+
+        let (required, sources) = detector.check_reboot_required().unwrap();
+        assert!(required);
+        assert_eq!(sources[0].details.as_deref(), Some("Computer name change is pending"));
+    }
+
+    #[test]
+    fn test_pending_upgrade_directory() {
+        let system_drive = std::env::var("SystemDrive").unwrap_or_else(|_| "C:".to_string());
+        let root = std::path::PathBuf::from(format!("{}\\", system_drive));
+        let wmi = || MockWmi { last_boot_up_time: "20240301080000.000000+000".to_string(), ..Default::default() };
+        let boot = chrono::TimeZone::with_ymd_and_hms(&Utc, 2024, 3, 1, 8, 0, 0).unwrap();
+
+        // Staged since the last boot
+        let file_system = MockFileSystem::default().with_modified_path(root.join("$WINDOWS.~BT"), boot + chrono::Duration::days(2));
+        let (required, sources) = detector(MockRegistry::default(), wmi(), file_system).check_reboot_required().unwrap();
+        assert!(required);
+        assert_eq!(source_names(&sources), vec![OS_UPGRADE_SOURCE]);
+
+        // Left over from an upgrade that was installed or rolled back before the last boot
+        let file_system = MockFileSystem::default()
+            .with_modified_path(root.join("$WINDOWS.~WS"), boot - chrono::Duration::days(30))
+            .with_modified_path(root.join(UPGRADE_SETUP_LOG), boot - chrono::Duration::days(30));
+        let (required, _) = detector(MockRegistry::default(), wmi(), file_system).check_reboot_required().unwrap();
+        assert!(!required);
+
+        // Windows Setup's volatile key lasts until the next boot
+        let registry = MockRegistry::default().with_key(UPGRADE_VOLATILE_KEY);
+        let (_, sources) = detector(registry, MockWmi::default(), MockFileSystem::default()).check_reboot_required().unwrap();
+        assert_eq!(source_names(&sources), vec![OS_UPGRADE_SOURCE]);
+    }
+
+    #[test]
+    fn test_failed_and_disabled_checks() {
+        // A failing provider is logged and skipped rather than failing the whole check
+        let registry = MockRegistry { fail: true, ..Default::default() };
+        let detector = detector(registry, MockWmi::default(), MockFileSystem::default());
+        let (required, sources) = detector.check_reboot_required().unwrap();
+        assert!(!required);
+        assert!(sources.is_empty());
+
+        // Disabled methods are not consulted
+        let mut config = crate::config::default().reboot;
+        config.detection_methods.windows_update = false;
+        let detector = RebootDetector::with_providers(
+            &config,
+            Arc::new(MockRegistry::default().with_key(WINDOWS_UPDATE_KEY)),
+            Arc::new(MockWmi::default()),
+            Arc::new(MockFileSystem::default()),
+        );
+        let (required, _) = detector.check_reboot_required().unwrap();
+        assert!(!required);
+    }
+
+    #[test]
+    fn test_timed_out_methods() {
+        /// Registry that hangs like an unresponsive provider
+        struct SlowRegistry;
+
+        impl RegistryProvider for SlowRegistry {
+            fn key_exists(&self, _key_path: &str) -> Result<bool> {
+                thread::sleep(Duration::from_secs(5));
+                Ok(true)
+            }
+
+            fn get_string_value(&self, _key_path: &str, _value_name: &str) -> Result<Option<String>> {
+                thread::sleep(Duration::from_secs(5));
+                Ok(None)
+            }
+        }
+
+        let mut config = crate::config::default().reboot;
+        config.detection_methods.timeout = Some("1s".to_string());
+        let detector = RebootDetector::with_providers(
+            &config,
+            Arc::new(SlowRegistry),
+            Arc::new(MockWmi { sccm_client_installed: true, ..Default::default() }),
+            Arc::new(MockFileSystem::default()),
+        );
+
+        let started = Instant::now();
+        let result = detector.detect();
+        assert!(started.elapsed() < Duration::from_secs(4));
+        assert!(!result.required);
+        assert_eq!(result.timed_out, vec!["Windows Update", "SCCM", "registry", "pending file operations", "OS upgrade"]);
+        assert_eq!(
+            result.unchecked_sources,
+            vec!["windows_update", "sccm", "registry", "pending_file_operations", OS_UPGRADE_SOURCE]
+        );
+    }
+
+    #[test]
+    fn test_user_registry() {
+        let mut config = crate::config::default().reboot;
+        config.detection_methods.user_registry_keys = vec![
+            crate::config::UserRegistryKeyConfig {
+                key_path: "Software\\Contoso\\RebootPending".to_string(),
+                value_name: None,
+            },
+            crate::config::UserRegistryKeyConfig {
+                key_path: "Software\\Microsoft\\Windows\\CurrentVersion\\RunOnce".to_string(),
+                value_name: Some("ContosoSetup".to_string()),
+            },
+        ];
+        let user_registry = MockUserRegistry::default()
+            .with_user("alice", "S-1-5-21-1", MockRegistry::default().with_key("Software\\Contoso\\RebootPending"))
+            .with_user("bob", "S-1-5-21-2", MockRegistry::default())
+            .with_user(
+                "carol",
+                "S-1-5-21-3",
+                MockRegistry::default().with_value("Software\\Microsoft\\Windows\\CurrentVersion\\RunOnce", "ContosoSetup", "setup.exe /finish"),
+            );
+        let detector = RebootDetector::with_providers(
+            &config,
+            Arc::new(MockRegistry::default()),
+            Arc::new(MockWmi::default()),
+            Arc::new(MockFileSystem::default()),
+        )
+        .with_user_registry(Arc::new(user_registry));
+
+        let (required, sources) = detector.check_reboot_required().unwrap();
+        assert!(required);
+        assert_eq!(source_names(&sources), vec!["user_registry", "user_registry"]);
+        let users: Vec<_> = sources.iter().map(|source| source.user_name.as_deref()).collect();
+        assert_eq!(users, vec![Some("alice"), Some("carol")]);
+    }
+
+    #[test]
+    fn test_last_boot_time() {
+        let wmi = MockWmi { last_boot_up_time: "20240105083015.500000+000".to_string(), ..Default::default() };
+        let detector = detector(MockRegistry::default(), wmi, MockFileSystem::default());
+        assert_eq!(
+            detector.get_last_boot_time().unwrap(),
+            DateTime::parse_from_rfc3339("2024-01-05T08:30:15Z").unwrap().with_timezone(&Utc)
+        );
+
+        assert!(parse_cim_datetime("2024").is_err());
+    }
+
+    #[test]
+    fn test_identity_without_wmi() {
+        let registry = MockRegistry::default()
+            .with_value(CURRENT_VERSION_KEY, "ProductName", "Windows Server 2022 Standard")
+            .with_value(BIOS_KEY, "SystemProductName", "VMware7,1")
+            .with_value(SMS_CLIENT_KEY, "ProductVersion", "5.00.9122.1000");
+        let wmi = MockWmi { sccm_client_installed: true, ..Default::default() };
+        let mut detector = detector(registry, wmi, MockFileSystem::default());
+        let mut config = crate::config::default().reboot;
+        config.use_wmi = false;
+        detector.set_config(&config);
+
+        let identity = detector.query_identity().unwrap();
+        assert!(identity.os_version.starts_with("Windows Server 2022 Standard"));
+        assert!(identity.is_virtual_machine);
+        assert_eq!(identity.sccm_client_version.as_deref(), Some("5.00.9122.1000"));
+
+        assert_eq!(os_version(Some("Windows 10 Pro".to_string()), Some((10, 0, 22631))), "Windows 11 Pro (10.0.22631)");
+        assert_eq!(os_version(Some("Windows 10 Pro".to_string()), Some((10, 0, 19045))), "Windows 10 Pro (10.0.19045)");
+        assert_eq!(os_version(None, None), "Unknown");
+    }
+}
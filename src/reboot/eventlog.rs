@@ -0,0 +1,383 @@
+use crate::database::RebootHistory;
+use crate::utils::{sid, to_wide};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use log::{debug, warn};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::ffi::c_void;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+use uuid::Uuid;
+use windows::core::PCWSTR;
+use windows::Win32::System::EventLog::{
+    EvtClose, EvtNext, EvtQuery, EvtQueryChannelPath, EvtQueryReverseDirection, EvtRender,
+    EvtRenderEventXml, EvtSubscribe, EvtSubscribeActionDeliver, EvtSubscribeToFutureEvents,
+    EVT_HANDLE, EVT_SUBSCRIBE_NOTIFY_ACTION,
+};
+
+/// Shutdown or restart initiated by a process or user
+pub const EVENT_SHUTDOWN_INITIATED: u32 = 1074;
+
+/// Event log service started, logged at every startup
+pub const EVENT_LOG_STARTED: u32 = 6005;
+
+/// Event log service stopped, logged at every clean shutdown
+pub const EVENT_LOG_STOPPED: u32 = 6006;
+
+/// Previous shutdown was unexpected
+pub const EVENT_UNEXPECTED_SHUTDOWN: u32 = 6008;
+
+/// Event log channel holding the shutdown and startup events
+const CHANNEL: &str = "System";
+
+/// Query selecting the shutdown and startup events
+const QUERY: &str = "*[System[(EventID=1074 or EventID=6005 or EventID=6006 or EventID=6008)]]";
+
+/// Number of events fetched from a query at a time
+const BATCH_SIZE: usize = 16;
+
+/// Shutdown or startup event read from the event log
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ShutdownEvent {
+    /// Event ID
+    pub event_id: u32,
+
+    /// Time the event was logged
+    pub time_created: Option<DateTime<Utc>>,
+
+    /// Computer that logged the event
+    pub computer_name: Option<String>,
+
+    /// SID of the account the event was logged for
+    pub user_sid: Option<String>,
+
+    /// Event data values in the order they appear in the event
+    pub data: Vec<String>,
+}
+
+impl ShutdownEvent {
+    /// Parse an event rendered as XML
+    pub fn parse(xml: &str) -> Result<Self> {
+        let mut reader = Reader::from_str(xml);
+        reader.trim_text(true);
+
+        let mut event = Self::default();
+        let mut element = String::new();
+
+        loop {
+            match reader.read_event().context("Failed to parse event XML")? {
+                Event::Start(start) => {
+                    element = String::from_utf8_lossy(start.local_name().as_ref()).to_string();
+                    event.read_attributes(&element, &start)?;
+                    if element == "Data" {
+                        event.data.push(String::new());
+                    }
+                }
+                Event::Empty(start) => {
+                    let name = String::from_utf8_lossy(start.local_name().as_ref()).to_string();
+                    event.read_attributes(&name, &start)?;
+                    if name == "Data" {
+                        event.data.push(String::new());
+                    }
+                }
+                Event::Text(text) => {
+                    let text = text.unescape().context("Failed to parse event XML")?.to_string();
+                    match element.as_str() {
+                        "EventID" => event.event_id = text.trim().parse().unwrap_or_default(),
+                        "Computer" => event.computer_name = Some(text),
+                        "Data" => {
+                            if let Some(value) = event.data.last_mut() {
+                                *value = text;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                Event::End(_) => element.clear(),
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+
+        if event.event_id == 0 {
+            return Err(anyhow::anyhow!("Event XML has no event ID"));
+        }
+
+        Ok(event)
+    }
+
+    /// Read the attributes of the elements that carry event properties
+    fn read_attributes(&mut self, element: &str, start: &BytesStart) -> Result<()> {
+        for attribute in start.attributes() {
+            let attribute = attribute.context("Failed to parse event XML")?;
+            let value = attribute.unescape_value().context("Failed to parse event XML")?;
+            match (element, attribute.key.as_ref()) {
+                ("TimeCreated", b"SystemTime") => {
+                    self.time_created = DateTime::parse_from_rfc3339(&value)
+                        .map(|time| time.with_timezone(&Utc))
+                        .ok();
+                }
+                ("Security", b"UserID") => self.user_sid = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Get an event data value by position, if it isn't empty
+    fn data_value(&self, index: usize) -> Option<&str> {
+        self.data
+            .get(index)
+            .map(|value| value.trim())
+            .filter(|value| !value.is_empty())
+    }
+
+    /// Convert the event to a reboot history entry
+    ///
+    /// For event 1074 the reason describes the shutdown type, the initiating process and the
+    /// reason text. The user is resolved from the event's SID, falling back to the account
+    /// recorded in the event data.
+    pub fn to_history(&self) -> RebootHistory {
+        let (reason, user_name, success) = match self.event_id {
+            EVENT_SHUTDOWN_INITIATED => {
+                // Data: process, computer, reason text, reason code, shutdown type, comment, user
+                let kind = self.data_value(4).unwrap_or("shutdown");
+                let mut reason = format!(
+                    "{} initiated by {}",
+                    capitalize(kind),
+                    self.data_value(0).unwrap_or("an unknown process")
+                );
+                if let Some(text) = self.data_value(2) {
+                    reason.push_str(&format!(": {}", text));
+                }
+                if let Some(comment) = self.data_value(5) {
+                    reason.push_str(&format!(" ({})", comment));
+                }
+                (reason, self.data_value(6).map(str::to_string), true)
+            }
+            EVENT_LOG_STARTED => ("System started".to_string(), None, true),
+            EVENT_LOG_STOPPED => ("System shut down cleanly".to_string(), None, true),
+            EVENT_UNEXPECTED_SHUTDOWN => ("Unexpected shutdown".to_string(), None, false),
+            other => (format!("Event {}", other), None, true),
+        };
+
+        RebootHistory {
+            id: Uuid::new_v4(),
+            reboot_time: self.time_created.unwrap_or_else(Utc::now),
+            reason: Some(reason),
+            source: Some(format!("Event Log ({})", self.event_id)),
+            user_name: self.user_sid.as_deref().and_then(sid::account_name).or(user_name),
+            computer_name: self.computer_name.clone(),
+            success,
+            duration: None,
+            closed_applications: Vec::new(),
+        }
+    }
+}
+
+/// Read the most recent shutdown and startup events, newest first
+pub fn query_events(limit: usize) -> Result<Vec<ShutdownEvent>> {
+    let channel = to_wide(CHANNEL);
+    let query = to_wide(QUERY);
+    let mut events = Vec::new();
+
+    unsafe {
+        let query_handle = EvtQuery(
+            None,
+            PCWSTR::from_raw(channel.as_ptr()),
+            PCWSTR::from_raw(query.as_ptr()),
+            EvtQueryChannelPath.0 | EvtQueryReverseDirection.0,
+        )
+        .context("Failed to query the System event log")?;
+
+        while events.len() < limit {
+            let mut handles = [0isize; BATCH_SIZE];
+            let mut returned = 0;
+            if EvtNext(query_handle, &mut handles, u32::MAX, 0, &mut returned).is_err() || returned == 0 {
+                // No more events
+                break;
+            }
+
+            for raw in &handles[..returned as usize] {
+                let handle = EVT_HANDLE(*raw);
+                match render_event_xml(handle).and_then(|xml| ShutdownEvent::parse(&xml)) {
+                    Ok(event) => events.push(event),
+                    Err(e) => warn!("Failed to read event: {:#}", e),
+                }
+                let _ = EvtClose(handle);
+            }
+        }
+
+        let _ = EvtClose(query_handle);
+    }
+
+    events.truncate(limit);
+    debug!("Read {} shutdown and startup events from the event log", events.len());
+    Ok(events)
+}
+
+/// Live subscription to shutdown and startup events
+///
+/// Events are delivered on a system thread pool thread and forwarded to the receiver returned by
+/// `start`. Dropping the subscription closes it, which also disconnects the receiver.
+pub struct EventLogSubscription {
+    handle: EVT_HANDLE,
+    _sender: Box<Mutex<Sender<ShutdownEvent>>>,
+}
+
+impl EventLogSubscription {
+    /// Subscribe to shutdown and startup events logged from now on
+    pub fn start() -> Result<(Self, Receiver<ShutdownEvent>)> {
+        let (sender, receiver) = mpsc::channel();
+        let sender = Box::new(Mutex::new(sender));
+        let channel = to_wide(CHANNEL);
+        let query = to_wide(QUERY);
+
+        let handle = unsafe {
+            EvtSubscribe(
+                None,
+                None,
+                PCWSTR::from_raw(channel.as_ptr()),
+                PCWSTR::from_raw(query.as_ptr()),
+                None,
+                Some(&*sender as *const Mutex<Sender<ShutdownEvent>> as *const c_void),
+                Some(subscription_callback),
+                EvtSubscribeToFutureEvents.0,
+            )
+        }
+        .context("Failed to subscribe to the System event log")?;
+
+        debug!("Subscribed to shutdown and startup events");
+        Ok((Self { handle, _sender: sender }, receiver))
+    }
+}
+
+impl Drop for EventLogSubscription {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = EvtClose(self.handle);
+        }
+    }
+}
+
+/// Forward an event delivered by the subscription to its receiver
+unsafe extern "system" fn subscription_callback(
+    action: EVT_SUBSCRIBE_NOTIFY_ACTION,
+    context: *const c_void,
+    event: EVT_HANDLE,
+) -> u32 {
+    if action != EvtSubscribeActionDeliver {
+        // The event handle holds the error code for error notifications
+        warn!("Event log subscription error: {}", event.0 as u32);
+        return 0;
+    }
+
+    let sender = &*(context as *const Mutex<Sender<ShutdownEvent>>);
+    match render_event_xml(event).and_then(|xml| ShutdownEvent::parse(&xml)) {
+        Ok(event) => {
+            debug!("Received event {} from the event log", event.event_id);
+            if let Ok(sender) = sender.lock() {
+                let _ = sender.send(event);
+            }
+        }
+        Err(e) => warn!("Failed to read event: {:#}", e),
+    }
+
+    0
+}
+
+/// Render an event as XML
+unsafe fn render_event_xml(event: EVT_HANDLE) -> Result<String> {
+    let mut buffer_used = 0;
+    let mut property_count = 0;
+
+    // First call to get the buffer size
+    let _ = EvtRender(None, event, EvtRenderEventXml.0, 0, None, &mut buffer_used, &mut property_count);
+    if buffer_used == 0 {
+        return Err(anyhow::anyhow!("Failed to get the size of the event XML"));
+    }
+
+    let mut buffer = vec![0u16; (buffer_used as usize).div_ceil(2)];
+    EvtRender(
+        None,
+        event,
+        EvtRenderEventXml.0,
+        (buffer.len() * 2) as u32,
+        Some(buffer.as_mut_ptr() as *mut c_void),
+        &mut buffer_used,
+        &mut property_count,
+    )
+    .context("Failed to render event")?;
+
+    let len = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+    Ok(String::from_utf16_lossy(&buffer[..len]))
+}
+
+/// Capitalize the first letter of a string
+fn capitalize(value: &str) -> String {
+    let mut chars = value.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHUTDOWN_EVENT: &str = r#"<Event xmlns='http://schemas.microsoft.com/win/2004/08/events/event'>
+  <System>
+    <Provider Name='User32' Guid='{b0aa8734-56f7-41cc-b2f4-de228e98b946}' EventSourceName='User32'/>
+    <EventID Qualifiers='32768'>1074</EventID>
+    <TimeCreated SystemTime='2024-01-05T08:30:15.1234567Z'/>
+    <Channel>System</Channel>
+    <Computer>PC-01.example.com</Computer>
+    <Security UserID='S-1-5-21-1004336348-1177238915-682003330-1001'/>
+  </System>
+  <EventData>
+    <Data Name='param1'>C:\Windows\system32\shutdown.exe (PC-01)</Data>
+    <Data Name='param2'>PC-01</Data>
+    <Data Name='param3'>Other (Planned)</Data>
+    <Data Name='param4'>0x80000000</Data>
+    <Data Name='param5'>restart</Data>
+    <Data Name='param6'/>
+    <Data Name='param7'>EXAMPLE\jdoe</Data>
+  </EventData>
+</Event>"#;
+
+    #[test]
+    fn test_parse_shutdown_event() {
+        let event = ShutdownEvent::parse(SHUTDOWN_EVENT).unwrap();
+        assert_eq!(event.event_id, EVENT_SHUTDOWN_INITIATED);
+        assert_eq!(event.computer_name.as_deref(), Some("PC-01.example.com"));
+        assert_eq!(event.user_sid.as_deref(), Some("S-1-5-21-1004336348-1177238915-682003330-1001"));
+        assert_eq!(event.data.len(), 7);
+        assert_eq!(
+            event.time_created,
+            Some(DateTime::parse_from_rfc3339("2024-01-05T08:30:15.1234567Z").unwrap().with_timezone(&Utc))
+        );
+
+        let history = event.to_history();
+        assert_eq!(
+            history.reason.as_deref(),
+            Some("Restart initiated by C:\\Windows\\system32\\shutdown.exe (PC-01): Other (Planned)")
+        );
+        // The SID doesn't exist on the test machine, so the account from the event data is used
+        assert_eq!(history.user_name.as_deref(), Some("EXAMPLE\\jdoe"));
+        assert_eq!(history.source.as_deref(), Some("Event Log (1074)"));
+        assert!(history.success);
+    }
+
+    #[test]
+    fn test_unexpected_shutdown() {
+        let xml = "<Event><System><EventID>6008</EventID><TimeCreated SystemTime='2024-01-05T08:30:15Z'/></System>\
+                   <EventData><Data>08:12:01</Data><Data>05/01/2024</Data></EventData></Event>";
+        let history = ShutdownEvent::parse(xml).unwrap().to_history();
+        assert_eq!(history.reason.as_deref(), Some("Unexpected shutdown"));
+        assert!(!history.success);
+
+        assert!(ShutdownEvent::parse("<Event><System/></Event>").is_err());
+    }
+}
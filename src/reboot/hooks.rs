@@ -0,0 +1,185 @@
+use crate::config::{HookConfig, HookEvent};
+use crate::database::RebootState;
+use crate::support::CheckSource;
+use crate::utils::http;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use log::{debug, info, warn};
+use serde::Serialize;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a hook may run when no timeout is configured
+const DEFAULT_HOOK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Environment variable holding the event name for command hooks
+const EVENT_VARIABLE: &str = "REBOOT_REMINDER_EVENT";
+
+/// Body sent to webhooks and written to the standard input of commands
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HookPayload {
+    /// Transition that triggered the hook
+    pub event: HookEvent,
+
+    /// Computer the transition happened on
+    pub computer_name: String,
+
+    /// Time of the transition
+    pub timestamp: DateTime<Utc>,
+
+    /// Time since when a reboot has been required
+    pub reboot_required_since: Option<DateTime<Utc>>,
+
+    /// Reasons a reboot is required, empty once it's no longer required
+    pub sources: Vec<CheckSource>,
+}
+
+impl HookPayload {
+    /// Create the payload for a transition of a reboot state
+    pub fn new(event: HookEvent, state: &RebootState) -> Self {
+        Self {
+            event,
+            computer_name: std::env::var("COMPUTERNAME").unwrap_or_default(),
+            timestamp: Utc::now(),
+            reboot_required_since: state.reboot_required_since,
+            sources: state.sources.iter().map(CheckSource::from).collect(),
+        }
+    }
+}
+
+/// Get the event for a change of the reboot requirement, if it changed
+pub fn transition(was_required: bool, required: bool) -> Option<HookEvent> {
+    match (was_required, required) {
+        (false, true) => Some(HookEvent::RebootRequired),
+        (true, false) => Some(HookEvent::RebootCleared),
+        _ => None,
+    }
+}
+
+/// Run the hooks subscribed to an event in the background
+///
+/// Hooks run one after another on their own thread so a slow endpoint doesn't hold up the
+/// service loop. Failures are logged and don't affect the other hooks.
+pub fn fire(hooks: &[HookConfig], event: HookEvent, state: &RebootState, dry_run: bool) {
+    let hooks: Vec<HookConfig> = hooks.iter().filter(|hook| hook.events.contains(&event)).cloned().collect();
+    if hooks.is_empty() {
+        return;
+    }
+
+    let payload = HookPayload::new(event, state);
+    if dry_run {
+        info!("[dry-run] Would run {} hooks for {}", hooks.len(), event);
+        return;
+    }
+
+    info!("Running {} hooks for {}", hooks.len(), event);
+    let spawned = thread::Builder::new().name("hooks".to_string()).spawn(move || {
+        for hook in &hooks {
+            if let Err(e) = run(hook, &payload) {
+                warn!("Hook for {} failed: {:#}", payload.event, e);
+            }
+        }
+    });
+    if let Err(e) = spawned {
+        warn!("Failed to start a thread for the hooks: {}", e);
+    }
+}
+
+/// Run a hook's command and call its webhook
+pub fn run(hook: &HookConfig, payload: &HookPayload) -> Result<()> {
+    let body = serde_json::to_string(payload).context("Failed to serialize hook payload")?;
+    let timeout = hook
+        .timeout
+        .as_deref()
+        .and_then(|timeout| crate::utils::timespan::parse_timespan(timeout).ok())
+        .unwrap_or(DEFAULT_HOOK_TIMEOUT);
+
+    if let Some(command) = &hook.command {
+        run_command(command, payload.event, &body, timeout)?;
+    }
+
+    if let Some(url) = &hook.url {
+        post_webhook(url, hook, &body, timeout)?;
+    }
+
+    Ok(())
+}
+
+/// Run a command with the payload on its standard input, killing it after the timeout
+fn run_command(command: &str, event: HookEvent, body: &str, timeout: Duration) -> Result<()> {
+    debug!("Running hook command: {}", command);
+    let mut child = Command::new("cmd")
+        .args(["/C", command])
+        .env(EVENT_VARIABLE, event.to_string())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to start hook command: {}", command))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // The command may exit without reading its input
+        let _ = stdin.write_all(body.as_bytes());
+    }
+
+    let started = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait().context("Failed to wait for hook command")? {
+            if !status.success() {
+                return Err(anyhow::anyhow!("Hook command exited with {}: {}", status, command));
+            }
+            debug!("Hook command finished: {}", command);
+            return Ok(());
+        }
+
+        if started.elapsed() >= timeout {
+            let _ = child.kill();
+            return Err(anyhow::anyhow!("Hook command did not finish within {:?}: {}", timeout, command));
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// Post the payload to a webhook
+fn post_webhook(url: &str, hook: &HookConfig, body: &str, timeout: Duration) -> Result<()> {
+    debug!("Calling webhook: {}", url);
+    let client = http::client(timeout)?;
+    let mut request = client
+        .post(url)
+        .header("Content-Type", "application/json")
+        .body(body.to_string());
+    if let Some(token) = &hook.bearer_token {
+        request = request.bearer_auth(token.expose());
+    }
+
+    let response = request.send().with_context(|| format!("Failed to call webhook {}", url))?;
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!("Webhook {} returned HTTP {}", url, response.status()));
+    }
+
+    debug!("Webhook called: {}", url);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::RebootSource;
+
+    #[test]
+    fn test_payload() {
+        assert_eq!(transition(false, true), Some(HookEvent::RebootRequired));
+        assert_eq!(transition(true, false), Some(HookEvent::RebootCleared));
+        assert_eq!(transition(true, true), None);
+
+        let mut state = RebootState::new(true, false);
+        state.sources.push(RebootSource::new("windows_update", Some("Windows Update requires a reboot"), "required"));
+        let value = serde_json::to_value(HookPayload::new(HookEvent::RebootRequired, &state)).unwrap();
+        assert_eq!(value["event"], "rebootRequired");
+        assert_eq!(value["sources"][0]["name"], "windows_update");
+        assert!(value["rebootRequiredSince"].is_string());
+    }
+}
@@ -1,28 +1,41 @@
+pub mod apps;
+pub mod clear;
+pub mod debounce;
 pub mod detector;
+pub mod eventlog;
 pub mod history;
+pub mod hooks;
+pub mod providers;
+pub mod remote;
 pub mod system;
+pub mod verify;
 
-use crate::config::RebootConfig;
-use crate::database::RebootState;
+use crate::config::{DeferralOption, MaintenanceWindowConfig, RebootConfig};
+use crate::database::{RebootSource, RebootState};
+use crate::utils::schedule::{self, ScheduleZone};
 use crate::utils::timespan;
 use anyhow::Result;
-use chrono::Duration;
-use log::warn;
+use chrono::{Datelike, Duration, Local, NaiveTime, TimeZone};
+use log::{debug, warn};
 use chrono::{DateTime, Utc};
 
-/// Get the appropriate timeframe for a reboot state
-pub fn get_timeframe<'a>(config: &'a RebootConfig, state: &RebootState) -> Option<&'a crate::config::TimeframeConfig> {
+/// Length of the countdown shown before a forced reboot
+pub const DEADLINE_COUNTDOWN_MINUTES: i64 = 60;
+
+/// Get the appropriate timeframe for a reboot state at a time
+pub fn get_timeframe<'a>(config: &'a RebootConfig, state: &RebootState, now: DateTime<Utc>) -> Option<&'a crate::config::TimeframeConfig> {
     // If no reboot is required, return None
     if !state.reboot_required {
         return None;
     }
 
-    // Calculate hours since reboot required
-    let hours_since_required = match state.sources.iter().min_by_key(|s| s.detected_at) {
-        Some(source) => {
-            let now = Utc::now();
-            let duration = now.signed_duration_since(source.detected_at);
-            duration.num_hours() as u32
+    // Calculate hours since reboot required, from the source found first, which keeps the time
+    // it was first detected across checks
+    let required_since = state.sources.iter().map(|s| s.detected_at).min().or(state.reboot_required_since);
+    let hours_since_required = match required_since {
+        Some(required_since) => {
+            let duration = now.signed_duration_since(required_since);
+            duration.num_hours().max(0) as u32
         }
         None => 0,
     };
@@ -97,6 +110,45 @@ pub fn parse_deferral(deferral: &str) -> Result<Duration> {
     Ok(Duration::seconds(std_duration.as_secs() as i64))
 }
 
+/// Get the next occurrence of a time of day (HH:MM, local time), moving to the next day if it has passed
+pub fn next_time_of_day(time: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    let time = NaiveTime::parse_from_str(time, "%H:%M")
+        .map_err(|e| anyhow::anyhow!("Invalid time of day '{}': {}", time, e))?;
+    let local_now = now.with_timezone(&Local);
+
+    let mut date = local_now.date_naive();
+    loop {
+        // Skip times that don't exist on a day because of a daylight saving change
+        if let Some(next) = Local.from_local_datetime(&date.and_time(time)).earliest() {
+            if next > local_now {
+                return Ok(next.with_timezone(&Utc));
+            }
+        }
+        date += Duration::days(1);
+    }
+}
+
+/// Get the time reminders are postponed until when a deferral option is chosen
+pub fn deferral_end(option: &DeferralOption, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    let (duration, until_time) = match option {
+        DeferralOption::Duration(duration) => (Some(duration), None),
+        DeferralOption::Preset(preset) => (preset.duration.as_ref(), preset.until_time.as_ref()),
+    };
+
+    if let Some(time) = until_time {
+        return next_time_of_day(time, now);
+    }
+
+    let duration = duration.ok_or_else(|| anyhow::anyhow!("Deferral option {} has no duration", option.id()))?;
+    let duration = timespan::parse_timespan(duration)?;
+    Ok(now + Duration::from_std(duration)?)
+}
+
+/// Check whether a reboot may not be postponed any more
+pub fn deferrals_exhausted(max_deferrals: Option<u32>, state: &RebootState) -> bool {
+    max_deferrals.is_some_and(|max| state.postpone_count >= max)
+}
+
 /// Format a duration in a human-readable format
 pub fn format_duration(duration: Duration) -> String {
     // Convert chrono::Duration to std::time::Duration
@@ -161,3 +213,416 @@ pub fn format_time_since_last_reboot(last_reboot_time: Option<DateTime<Utc>>) ->
         None => "unknown".to_string(),
     }
 }
+
+/// Whether a Windows feature update is waiting for the reboot
+pub fn upgrade_pending(state: &RebootState) -> bool {
+    state.sources.iter().any(|source| source.name == detector::OS_UPGRADE_SOURCE)
+}
+
+/// Get the time by which a reboot must happen, if a deadline is configured
+///
+/// While a feature update is pending, the upgrade deadline applies instead when one is set.
+pub fn get_deadline(config: &RebootConfig, state: &RebootState) -> Option<DateTime<Utc>> {
+    if !state.reboot_required {
+        return None;
+    }
+
+    let deadline = match &config.upgrade_deadline {
+        Some(deadline) if upgrade_pending(state) => deadline.as_str(),
+        _ => config.deadline.as_deref()?,
+    };
+    let duration = match parse_deferral(deadline) {
+        Ok(duration) => duration,
+        Err(e) => {
+            warn!("Failed to parse reboot deadline: {}", e);
+            return None;
+        }
+    };
+
+    let required_since = state
+        .reboot_required_since
+        .or_else(|| state.sources.iter().map(|s| s.detected_at).min())?;
+
+    Some(required_since + duration)
+}
+
+/// Get how often to check whether a reboot is required
+pub fn check_interval(config: &RebootConfig) -> Duration {
+    timespan::parse_timespan(&config.check_interval)
+        .ok()
+        .and_then(|interval| Duration::from_std(interval).ok())
+        .unwrap_or_else(|| Duration::minutes(15))
+}
+
+/// Check if a reboot check is due
+///
+/// The first check runs as soon as the startup delay has passed, later ones after the check interval.
+pub fn check_due(config: &RebootConfig, started: DateTime<Utc>, last_check: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+    match last_check {
+        Some(last_check) => now - last_check >= check_interval(config),
+        None => now >= started + startup_check_delay(config),
+    }
+}
+
+/// Get how long to wait after the service starts before the first reboot check
+pub fn startup_check_delay(config: &RebootConfig) -> Duration {
+    timespan::parse_timespan(&config.startup_check_delay)
+        .ok()
+        .and_then(|delay| Duration::from_std(delay).ok())
+        .unwrap_or_else(|| Duration::seconds(30))
+}
+
+/// Source recorded in the reboot history for reboots run while nobody was signed in
+pub const UNATTENDED_SOURCE: &str = "unattended";
+
+/// Source recorded in the reboot history for reboots forced after the deadline passed
+pub const DEADLINE_SOURCE: &str = "deadline";
+
+/// Check if an unattended reboot is due
+///
+/// It is due once the reboot has been required for `unattendedRebootAfter` and nobody has been
+/// signed in since `no_sessions_since` for `noSessionsGrace`.
+pub fn unattended_reboot_due(
+    config: &RebootConfig,
+    state: &RebootState,
+    no_sessions_since: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> bool {
+    if !config.auto_reboot_when_no_sessions || !state.reboot_required {
+        return false;
+    }
+
+    let timespan = |value: &str, default: Duration| {
+        timespan::parse_timespan(value)
+            .ok()
+            .and_then(|value| Duration::from_std(value).ok())
+            .unwrap_or(default)
+    };
+    let required_long_enough = state
+        .reboot_required_since
+        .is_some_and(|since| now - since >= timespan(&config.unattended_reboot_after, Duration::hours(1)));
+    let empty_long_enough =
+        no_sessions_since.is_some_and(|since| now - since >= timespan(&config.no_sessions_grace, Duration::minutes(15)));
+
+    required_long_enough && empty_long_enough
+}
+
+/// Bring a reminder forward as the deadline approaches
+///
+/// Reminders are shown at least four times in the time left, but not more often than every 15 minutes.
+pub fn escalate_reminder_time(next_reminder: DateTime<Utc>, deadline: DateTime<Utc>, now: DateTime<Utc>) -> DateTime<Utc> {
+    let remaining = deadline.signed_duration_since(now);
+    if remaining <= Duration::zero() {
+        return next_reminder;
+    }
+
+    let interval = (remaining / 4).max(Duration::minutes(15));
+    next_reminder.min(now + interval)
+}
+
+/// Check if a time falls within one of the maintenance windows, evaluated in a time zone
+pub fn is_in_maintenance_window(windows: &[MaintenanceWindowConfig], zone: ScheduleZone, time: DateTime<Utc>) -> bool {
+    let (day, current_time) = zone.day_and_time(time);
+
+    windows.iter().any(|window| match parse_window(window) {
+        Some((start, end)) => schedule::in_range(&window.days_of_week, start, end, day, current_time),
+        None => false,
+    })
+}
+
+/// Get the first time at or after the given time that falls within a maintenance window
+///
+/// Returns the given time if no maintenance windows are configured.
+pub fn next_maintenance_window(windows: &[MaintenanceWindowConfig], zone: ScheduleZone, after: DateTime<Utc>) -> DateTime<Utc> {
+    if windows.is_empty() || is_in_maintenance_window(windows, zone, after) {
+        return after;
+    }
+
+    let mut next: Option<DateTime<Utc>> = None;
+    for offset in 0..=7 {
+        let date = zone.date(after) + Duration::days(offset);
+        let day = date.weekday().num_days_from_sunday() as u8;
+
+        for window in windows.iter().filter(|window| window.days_of_week.contains(&day)) {
+            let start = match parse_window(window) {
+                Some((start, _)) => start,
+                None => continue,
+            };
+
+            if let Some(start) = zone.to_utc(date.and_time(start)) {
+                if start > after && next.map_or(true, |next| start < next) {
+                    next = Some(start);
+                }
+            }
+        }
+    }
+
+    next.unwrap_or(after)
+}
+
+/// Get the users to notify when every source applies to specific users
+///
+/// Returns an empty list, meaning every session is notified, as soon as one source applies to
+/// the whole machine.
+pub fn affected_users(sources: &[RebootSource]) -> Vec<String> {
+    let mut users = Vec::new();
+    for source in sources {
+        match &source.user_name {
+            Some(user_name) => {
+                if !users.iter().any(|user: &String| user.eq_ignore_ascii_case(user_name)) {
+                    users.push(user_name.clone());
+                }
+            }
+            None => return Vec::new(),
+        }
+    }
+    users
+}
+
+/// Merge the sources found by a check into the sources stored before it
+///
+/// A source that was found before keeps its identity and the time it was first detected, and
+/// gets `now` as the time it was last seen. New sources count as detected at `now`. Sources that
+/// weren't found anymore are dropped.
+pub fn carry_over_sources(previous: &[RebootSource], found: Vec<RebootSource>, now: DateTime<Utc>) -> Vec<RebootSource> {
+    found
+        .into_iter()
+        .map(|mut source| {
+            if let Some(previous) = previous.iter().find(|previous| previous.same_source(&source)) {
+                source.id = previous.id;
+                source.detected_at = previous.detected_at;
+            } else {
+                source.detected_at = now;
+            }
+            source.last_seen = now;
+            source
+        })
+        .collect()
+}
+
+/// Add the stored sources of detection methods that failed or timed out to the sources found by a
+/// check
+///
+/// A method that couldn't run says nothing about its sources, so they stay as they were instead
+/// of counting as cleared until the method runs again.
+pub fn keep_unchecked_sources(previous: &[RebootSource], unchecked: &[String], mut found: Vec<RebootSource>) -> Vec<RebootSource> {
+    for source in previous {
+        if unchecked.contains(&source.name) && !found.iter().any(|found| found.same_source(source)) {
+            debug!("Keeping reboot source {} since its check did not complete", source.name);
+            found.push(source.clone());
+        }
+    }
+    found
+}
+
+/// Parse the start and end times of a maintenance window
+fn parse_window(window: &MaintenanceWindowConfig) -> Option<(NaiveTime, NaiveTime)> {
+    let start = NaiveTime::parse_from_str(&window.start_time, "%H:%M").ok()?;
+    let end = NaiveTime::parse_from_str(&window.end_time, "%H:%M").ok()?;
+    Some((start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(start: &str, end: &str, days: Vec<u8>) -> MaintenanceWindowConfig {
+        MaintenanceWindowConfig {
+            start_time: start.to_string(),
+            end_time: end.to_string(),
+            days_of_week: days,
+        }
+    }
+
+    fn local(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Local.with_ymd_and_hms(year, month, day, hour, minute, 0).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_maintenance_window() {
+        // 2024-01-07 is a Sunday
+        let windows = vec![window("22:00", "04:00", vec![6])];
+
+        let zone = ScheduleZone::Local;
+        assert!(is_in_maintenance_window(&windows, zone, local(2024, 1, 6, 23, 0)));
+        assert!(is_in_maintenance_window(&windows, zone, local(2024, 1, 7, 3, 59)));
+        assert!(!is_in_maintenance_window(&windows, zone, local(2024, 1, 7, 4, 0)));
+        assert!(!is_in_maintenance_window(&windows, zone, local(2024, 1, 5, 23, 0)));
+
+        let after = local(2024, 1, 3, 12, 0);
+        let next = next_maintenance_window(&windows, zone, after);
+        assert_eq!(next, local(2024, 1, 6, 22, 0));
+
+        let inside = local(2024, 1, 6, 23, 0);
+        assert_eq!(next_maintenance_window(&windows, zone, inside), inside);
+        assert_eq!(next_maintenance_window(&[], zone, after), after);
+    }
+
+    #[test]
+    fn test_maintenance_window_across_dst() {
+        // Saturday night 01:00 to 05:00 in Amsterdam, where the clocks went forward at 02:00 on
+        // Sunday 31 March 2024
+        let windows = vec![window("01:00", "05:00", vec![0])];
+        let zone = ScheduleZone::parse(Some("Europe/Amsterdam")).unwrap();
+
+        // 00:30 UTC is 01:30 CET, and 02:30 UTC is 04:30 CEST
+        let utc = |day: u32, hour: u32, minute: u32| Utc.with_ymd_and_hms(2024, 3, day, hour, minute, 0).unwrap();
+        assert!(is_in_maintenance_window(&windows, zone, utc(31, 0, 30)));
+        assert!(is_in_maintenance_window(&windows, zone, utc(31, 2, 30)));
+        assert!(!is_in_maintenance_window(&windows, zone, utc(31, 3, 0)));
+
+        // Saturday noon in UTC, the window opens at 01:00 CET, midnight UTC
+        assert_eq!(next_maintenance_window(&windows, zone, utc(30, 12, 0)), utc(31, 0, 0));
+    }
+
+    #[test]
+    fn test_deadline() {
+        let mut config = crate::config::default().reboot;
+        let mut state = RebootState::new(true, false);
+        let since = Utc::now() - Duration::days(1);
+        state.reboot_required_since = Some(since);
+
+        assert!(get_deadline(&config, &state).is_none());
+
+        config.deadline = Some("120h".to_string());
+        assert_eq!(get_deadline(&config, &state), Some(since + Duration::days(5)));
+
+        // A pending feature update gets its own deadline
+        config.upgrade_deadline = Some("336h".to_string());
+        assert_eq!(get_deadline(&config, &state), Some(since + Duration::days(5)));
+        state.sources.push(RebootSource::new(detector::OS_UPGRADE_SOURCE, None, "required"));
+        assert_eq!(get_deadline(&config, &state), Some(since + Duration::days(14)));
+
+        state.reboot_required = false;
+        assert!(get_deadline(&config, &state).is_none());
+    }
+
+    #[test]
+    fn test_unattended_reboot_due() {
+        let mut config = crate::config::default().reboot;
+        let now = Utc::now();
+        let mut state = RebootState::new(true, false);
+        state.reboot_required_since = Some(now - Duration::hours(2));
+        let empty_since = Some(now - Duration::minutes(20));
+
+        assert!(!unattended_reboot_due(&config, &state, empty_since, now));
+
+        config.auto_reboot_when_no_sessions = true;
+        assert!(unattended_reboot_due(&config, &state, empty_since, now));
+        assert!(!unattended_reboot_due(&config, &state, None, now));
+        assert!(!unattended_reboot_due(&config, &state, Some(now - Duration::minutes(5)), now));
+
+        state.reboot_required_since = Some(now - Duration::minutes(30));
+        assert!(!unattended_reboot_due(&config, &state, empty_since, now));
+    }
+
+    #[test]
+    fn test_check_due() {
+        let config = crate::config::default().reboot;
+        let started = Utc::now();
+
+        assert!(!check_due(&config, started, None, started + Duration::seconds(10)));
+        assert!(check_due(&config, started, None, started + Duration::seconds(30)));
+        assert!(!check_due(&config, started, Some(started), started + Duration::minutes(10)));
+        assert!(check_due(&config, started, Some(started), started + Duration::minutes(15)));
+    }
+
+    #[test]
+    fn test_next_time_of_day() {
+        let now = local(2024, 1, 3, 12, 0).with_timezone(&Utc);
+
+        // Later today
+        assert_eq!(next_time_of_day("17:30", now).unwrap(), local(2024, 1, 3, 17, 30).with_timezone(&Utc));
+
+        // Already passed, so tomorrow
+        assert_eq!(next_time_of_day("08:00", now).unwrap(), local(2024, 1, 4, 8, 0).with_timezone(&Utc));
+        assert_eq!(next_time_of_day("12:00", now).unwrap(), local(2024, 1, 4, 12, 0).with_timezone(&Utc));
+
+        // Across midnight
+        let late = local(2024, 1, 3, 23, 50).with_timezone(&Utc);
+        assert_eq!(next_time_of_day("00:15", late).unwrap(), local(2024, 1, 4, 0, 15).with_timezone(&Utc));
+
+        assert!(next_time_of_day("25:00", now).is_err());
+    }
+
+    #[test]
+    fn test_deferral_end() {
+        let now = local(2024, 1, 3, 12, 0).with_timezone(&Utc);
+        assert_eq!(deferral_end(&DeferralOption::from("4h"), now).unwrap(), now + Duration::hours(4));
+
+        let tomorrow = DeferralOption::Preset(crate::config::DeferralPreset {
+            id: Some("tomorrow".to_string()),
+            label: "Tomorrow morning".to_string(),
+            duration: None,
+            until_time: Some("08:00".to_string()),
+        });
+        assert_eq!(tomorrow.id(), "tomorrow");
+        assert_eq!(tomorrow.label(), "Tomorrow morning");
+        assert_eq!(deferral_end(&tomorrow, now).unwrap(), local(2024, 1, 4, 8, 0).with_timezone(&Utc));
+    }
+
+    #[test]
+    fn test_escalate_reminder_time() {
+        let now = Utc::now();
+        let next = now + Duration::hours(4);
+
+        // Far from the deadline the regular reminder is kept
+        assert_eq!(escalate_reminder_time(next, now + Duration::days(5), now), next);
+
+        // Close to the deadline reminders come more often
+        assert_eq!(escalate_reminder_time(next, now + Duration::hours(4), now), now + Duration::hours(1));
+
+        // But not more often than every 15 minutes
+        assert_eq!(escalate_reminder_time(next, now + Duration::minutes(20), now), now + Duration::minutes(15));
+    }
+
+    #[test]
+    fn test_affected_users() {
+        let user_source = |user_name: &str| {
+            let mut source = RebootSource::new("user_registry", None, "required");
+            source.user_name = Some(user_name.to_string());
+            source
+        };
+
+        assert_eq!(affected_users(&[user_source("alice"), user_source("ALICE"), user_source("bob")]), vec!["alice", "bob"]);
+        assert!(affected_users(&[user_source("alice"), RebootSource::new("windows_update", None, "required")]).is_empty());
+        assert!(affected_users(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_carry_over_sources() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 4, 12, 0, 0).unwrap();
+        let mut update = RebootSource::new("windows_update", None, "required");
+        update.detected_at = now - Duration::hours(30);
+        let previous = vec![update.clone(), RebootSource::new("sccm", None, "required")];
+
+        let found = vec![
+            RebootSource::new("windows_update", Some("Updates installed"), "required"),
+            RebootSource::new("registry", None, "required"),
+        ];
+        let sources = carry_over_sources(&previous, found, now);
+
+        // The update keeps its identity and first detection, SCCM was cleared
+        assert_eq!(sources.len(), 2);
+        assert_eq!((sources[0].id, sources[0].detected_at, sources[0].last_seen), (update.id, update.detected_at, now));
+        assert_eq!(sources[0].description.as_deref(), Some("Updates installed"));
+        assert_ne!(sources[1].id, previous[1].id);
+        assert_eq!((sources[1].detected_at, sources[1].last_seen), (now, now));
+    }
+
+    #[test]
+    fn test_keep_unchecked_sources() {
+        let previous = vec![
+            RebootSource::new("windows_update", None, "required"),
+            RebootSource::new("sccm", None, "required"),
+            RebootSource::new("registry", None, "required"),
+        ];
+        let found = vec![RebootSource::new("pending_file_operations", None, "required")];
+
+        // SCCM timed out and keeps its source, the registry check ran and cleared its own
+        let sources = keep_unchecked_sources(&previous, &["sccm".to_string(), "os_upgrade".to_string()], found);
+        let names: Vec<&str> = sources.iter().map(|source| source.name.as_str()).collect();
+        assert_eq!(names, vec!["pending_file_operations", "sccm"]);
+        assert_eq!(sources[1].id, previous[1].id);
+    }
+}
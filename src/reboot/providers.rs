@@ -0,0 +1,449 @@
+use crate::utils::secrets::Secret;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use log::{debug, warn};
+use once_cell::sync::Lazy;
+use serde_derive::Deserialize;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use windows::Win32::System::Registry::{HKEY_LOCAL_MACHINE, HKEY_USERS};
+
+/// Default WMI namespace
+pub const CIMV2_NAMESPACE: &str = "root\\cimv2";
+
+thread_local! {
+    /// COM library of the current thread, initialized once by the first WMI connection
+    static COM_LIBRARY: Cell<Option<wmi::COMLibrary>> = const { Cell::new(None) };
+}
+
+/// Query run on the WMI thread with its connections, by namespace path
+type WmiJob = Box<dyn FnOnce(&mut HashMap<String, wmi::WMIConnection>) + Send>;
+
+/// Queue of the thread that owns the WMI connections, started by the first query
+///
+/// Detection runs every check on a new thread each cycle, so connections kept by the querying
+/// threads would never be used twice.
+static WMI_THREAD: Lazy<Mutex<mpsc::Sender<WmiJob>>> = Lazy::new(|| {
+    let (sender, receiver) = mpsc::channel::<WmiJob>();
+    let worker = move || {
+        let mut connections = HashMap::new();
+        for job in receiver {
+            // A query that panics mustn't take the connections of later queries with it
+            if panic::catch_unwind(AssertUnwindSafe(|| job(&mut connections))).is_err() {
+                warn!("A WMI query panicked, reopening the WMI connections");
+                connections.clear();
+            }
+        }
+    };
+    if let Err(e) = thread::Builder::new().name("wmi".to_string()).spawn(worker) {
+        warn!("Failed to start the WMI thread: {}", e);
+    }
+    Mutex::new(sender)
+});
+
+/// Get the COM library of the current thread, initializing COM the first time
+///
+/// COM is initialized once per thread. When other code already initialized the thread's
+/// apartment, in another mode, it's used as it is instead of failing to initialize it again.
+fn com_library() -> wmi::COMLibrary {
+    COM_LIBRARY.with(|library| {
+        if let Some(library) = library.get() {
+            return library;
+        }
+
+        let initialized = wmi::COMLibrary::new().unwrap_or_else(|e| {
+            debug!("COM is already initialized on this thread, using it as is: {}", e);
+            // SAFETY: COM was initialized on this thread by the code that made initializing it fail
+            unsafe { wmi::COMLibrary::assume_initialized() }
+        });
+        library.set(Some(initialized));
+        initialized
+    })
+}
+
+/// Run queries against a WMI namespace, reusing the open connection to it
+///
+/// COM apartments and WMI connections belong to the thread that created them, so all queries run
+/// on one long-lived thread that keeps the connections open, and the caller waits for the result.
+/// A connection that fails a query is dropped and reopened by the next call.
+pub fn with_wmi_connection<T: Send + 'static>(
+    namespace: &str,
+    query: impl FnOnce(&wmi::WMIConnection) -> Result<T> + Send + 'static,
+) -> Result<T> {
+    let path = namespace.to_string();
+    let open = {
+        let path = path.clone();
+        move || wmi::WMIConnection::with_namespace_path(&path, com_library().into())
+    };
+    with_connection(path, open, query)
+}
+
+/// Run queries against a WMI namespace of a remote computer, reusing the open connection to it
+///
+/// The connection goes over DCOM, as the host's user when it has credentials and as the current
+/// user otherwise.
+pub fn with_remote_wmi_connection<T: Send + 'static>(
+    host: &RemoteHost,
+    namespace: &str,
+    query: impl FnOnce(&wmi::WMIConnection) -> Result<T> + Send + 'static,
+) -> Result<T> {
+    let path = format!("\\\\{}\\{}", host.computer, namespace);
+    let host = host.clone();
+    let namespace = namespace.to_string();
+    let open = {
+        let path = path.clone();
+        move || match &host.credentials {
+            Some(credentials) => {
+                let (domain, user) = credentials.user.split_once('\\').unwrap_or(("", credentials.user.as_str()));
+                wmi::WMIConnection::with_credentials_and_namespace(
+                    &host.computer,
+                    &namespace,
+                    user,
+                    credentials.password.expose(),
+                    domain,
+                    com_library().into(),
+                )
+            }
+            None => wmi::WMIConnection::with_namespace_path(&path, com_library().into()),
+        }
+    };
+    with_connection(path, open, query)
+}
+
+/// Run queries on the WMI thread's connection to a namespace path, opening it first if needed
+fn with_connection<T: Send + 'static>(
+    path: String,
+    open: impl FnOnce() -> wmi::WMIResult<wmi::WMIConnection> + Send + 'static,
+    query: impl FnOnce(&wmi::WMIConnection) -> Result<T> + Send + 'static,
+) -> Result<T> {
+    let (result_sender, result_receiver) = mpsc::channel();
+    let job: WmiJob = Box::new(move |connections| {
+        let _ = result_sender.send(query_connection(connections, &path, open, query));
+    });
+
+    WMI_THREAD
+        .lock()
+        .map_err(|_| anyhow::anyhow!("WMI thread lock is poisoned"))?
+        .send(job)
+        .map_err(|_| anyhow::anyhow!("The WMI thread isn't running"))?;
+    result_receiver
+        .recv()
+        .map_err(|_| anyhow::anyhow!("The WMI thread stopped before finishing the query"))?
+}
+
+/// Run a query on an open connection, opening it first if needed, on the WMI thread
+fn query_connection<T>(
+    connections: &mut HashMap<String, wmi::WMIConnection>,
+    path: &str,
+    open: impl FnOnce() -> wmi::WMIResult<wmi::WMIConnection>,
+    query: impl FnOnce(&wmi::WMIConnection) -> Result<T>,
+) -> Result<T> {
+    if !connections.contains_key(path) {
+        debug!("Opening WMI connection to {}", path);
+        let connection = open().with_context(|| format!("Failed to connect to WMI namespace {}", path))?;
+        connections.insert(path.to_string(), connection);
+    }
+
+    let result = query(&connections[path]);
+    if result.is_err() {
+        connections.remove(path);
+    }
+    result
+}
+
+/// Credentials for connecting to a remote computer
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    /// User name, as `DOMAIN\user` or `user`
+    pub user: String,
+
+    /// Password of the user
+    pub password: Secret,
+}
+
+/// Remote computer that detection runs against
+#[derive(Debug, Clone)]
+pub struct RemoteHost {
+    /// Name of the computer, without leading backslashes
+    pub computer: String,
+
+    /// Credentials to connect with, or None to use the current user's token
+    pub credentials: Option<Credentials>,
+}
+
+impl RemoteHost {
+    /// Create a remote host, accepting names written as UNC paths (e.g., `\\PC1`)
+    pub fn new(computer: &str, credentials: Option<Credentials>) -> Self {
+        Self {
+            computer: computer.trim().trim_start_matches('\\').to_string(),
+            credentials,
+        }
+    }
+}
+
+/// Read access to the HKEY_LOCAL_MACHINE registry hive
+pub trait RegistryProvider: Send + Sync {
+    /// Check if a key exists
+    fn key_exists(&self, key_path: &str) -> Result<bool>;
+
+    /// Get a string value, or None if the key or value doesn't exist
+    fn get_string_value(&self, key_path: &str, value_name: &str) -> Result<Option<String>>;
+}
+
+/// Read access to the registry hives of the users with active sessions
+pub trait UserRegistryProvider: Send + Sync {
+    /// Get the user name and SID of each user with an active session
+    fn active_users(&self) -> Result<Vec<(String, String)>>;
+
+    /// Check if a key exists in a user's hive
+    fn key_exists(&self, user_sid: &str, key_path: &str) -> Result<bool>;
+
+    /// Get a string value from a user's hive, or None if the key or value doesn't exist
+    fn get_string_value(&self, user_sid: &str, key_path: &str, value_name: &str) -> Result<Option<String>>;
+}
+
+/// System management queries answered by WMI and the service control manager
+pub trait WmiProvider: Send + Sync {
+    /// Get the last boot time of the operating system as a CIM datetime (e.g., "20230101000000.000000+000")
+    fn last_boot_up_time(&self) -> Result<String>;
+
+    /// Check if the SCCM client is installed
+    fn is_sccm_client_installed(&self) -> Result<bool>;
+}
+
+/// Access to the local file system
+pub trait FileSystemProvider: Send + Sync {
+    /// Check if a file or directory exists
+    fn exists(&self, path: &Path) -> bool;
+
+    /// Get when a file or directory was last modified, or None if it doesn't exist
+    fn modified(&self, path: &Path) -> Option<DateTime<Utc>>;
+}
+
+/// Registry provider backed by the Windows registry
+#[derive(Debug, Default)]
+pub struct WindowsRegistry;
+
+impl RegistryProvider for WindowsRegistry {
+    fn key_exists(&self, key_path: &str) -> Result<bool> {
+        crate::utils::registry::key_exists(HKEY_LOCAL_MACHINE, key_path)
+    }
+
+    fn get_string_value(&self, key_path: &str, value_name: &str) -> Result<Option<String>> {
+        crate::utils::registry::get_string_value(HKEY_LOCAL_MACHINE, key_path, value_name)
+    }
+}
+
+/// User registry provider reading the hives loaded under HKEY_USERS
+///
+/// Each session's user is resolved from its token, which is the hive HKEY_CURRENT_USER maps to
+/// when impersonating that user. Outside the service only the current user's hive can be resolved.
+#[derive(Debug, Default)]
+pub struct WindowsUserRegistry;
+
+impl UserRegistryProvider for WindowsUserRegistry {
+    fn active_users(&self) -> Result<Vec<(String, String)>> {
+        let current_user = std::env::var("USERNAME").unwrap_or_default();
+        let sessions = crate::impersonation::Impersonator::new().get_active_sessions()?;
+
+        Ok(sessions
+            .into_iter()
+            .filter_map(|session| {
+                let sid = crate::utils::sid::session_user_sid(&session.session_id, true).or_else(|| {
+                    session
+                        .user_name
+                        .eq_ignore_ascii_case(&current_user)
+                        .then(|| crate::utils::sid::session_user_sid(&session.session_id, false))
+                        .flatten()
+                });
+                if sid.is_none() {
+                    debug!("Failed to resolve the SID of {} in session {}", crate::logging::user(&session.user_name), session.session_id);
+                }
+                sid.map(|sid| (session.user_name, sid))
+            })
+            .collect())
+    }
+
+    fn key_exists(&self, user_sid: &str, key_path: &str) -> Result<bool> {
+        crate::utils::registry::key_exists(HKEY_USERS, &format!("{}\\{}", user_sid, key_path))
+    }
+
+    fn get_string_value(&self, user_sid: &str, key_path: &str, value_name: &str) -> Result<Option<String>> {
+        crate::utils::registry::get_string_value(HKEY_USERS, &format!("{}\\{}", user_sid, key_path), value_name)
+    }
+}
+
+/// WMI provider backed by WMI and the service control manager
+#[derive(Debug, Default)]
+pub struct WindowsWmi;
+
+impl WmiProvider for WindowsWmi {
+    fn last_boot_up_time(&self) -> Result<String> {
+        #[derive(Debug, Deserialize)]
+        #[serde(rename = "Win32_OperatingSystem")]
+        struct OSInfo {
+            #[serde(rename = "LastBootUpTime")]
+            last_boot_up_time: String,
+        }
+
+        let results: Vec<OSInfo> = with_wmi_connection(CIMV2_NAMESPACE, |wmi_con| {
+            wmi_con.query().context("Failed to query WMI for last boot time")
+        })?;
+
+        results
+            .into_iter()
+            .next()
+            .map(|info| info.last_boot_up_time)
+            .ok_or_else(|| anyhow::anyhow!("No OS information found in WMI"))
+    }
+
+    fn is_sccm_client_installed(&self) -> Result<bool> {
+        crate::impersonation::Impersonator::new().is_sccm_client_installed()
+    }
+}
+
+/// File system provider backed by the local file system
+#[derive(Debug, Default)]
+pub struct LocalFileSystem;
+
+impl FileSystemProvider for LocalFileSystem {
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn modified(&self, path: &Path) -> Option<DateTime<Utc>> {
+        std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok().map(DateTime::from)
+    }
+}
+
+/// In-memory providers for unit tests and the service test harness
+#[cfg(any(test, feature = "test-harness"))]
+pub mod mock {
+    use super::*;
+    use std::collections::{HashMap, HashSet};
+    use std::path::PathBuf;
+
+    /// Registry with a fixed set of keys and string values
+    #[derive(Debug, Default)]
+    pub struct MockRegistry {
+        pub keys: HashSet<String>,
+        pub values: HashMap<(String, String), String>,
+        pub fail: bool,
+    }
+
+    impl MockRegistry {
+        /// Add a key
+        pub fn with_key(mut self, key_path: &str) -> Self {
+            self.keys.insert(key_path.to_string());
+            self
+        }
+
+        /// Add a string value
+        pub fn with_value(mut self, key_path: &str, value_name: &str, value: &str) -> Self {
+            self.values.insert((key_path.to_string(), value_name.to_string()), value.to_string());
+            self
+        }
+    }
+
+    impl RegistryProvider for MockRegistry {
+        fn key_exists(&self, key_path: &str) -> Result<bool> {
+            if self.fail {
+                return Err(anyhow::anyhow!("Registry unavailable"));
+            }
+            Ok(self.keys.contains(key_path))
+        }
+
+        fn get_string_value(&self, key_path: &str, value_name: &str) -> Result<Option<String>> {
+            if self.fail {
+                return Err(anyhow::anyhow!("Registry unavailable"));
+            }
+            Ok(self.values.get(&(key_path.to_string(), value_name.to_string())).cloned())
+        }
+    }
+
+    /// User hives with fixed keys, by SID
+    #[derive(Debug, Default)]
+    pub struct MockUserRegistry {
+        pub users: Vec<(String, String)>,
+        pub registry: HashMap<String, MockRegistry>,
+    }
+
+    impl MockUserRegistry {
+        /// Add a user with an active session and the keys and values in their hive
+        pub fn with_user(mut self, user_name: &str, user_sid: &str, registry: MockRegistry) -> Self {
+            self.users.push((user_name.to_string(), user_sid.to_string()));
+            self.registry.insert(user_sid.to_string(), registry);
+            self
+        }
+    }
+
+    impl UserRegistryProvider for MockUserRegistry {
+        fn active_users(&self) -> Result<Vec<(String, String)>> {
+            Ok(self.users.clone())
+        }
+
+        fn key_exists(&self, user_sid: &str, key_path: &str) -> Result<bool> {
+            match self.registry.get(user_sid) {
+                Some(registry) => registry.key_exists(key_path),
+                None => Ok(false),
+            }
+        }
+
+        fn get_string_value(&self, user_sid: &str, key_path: &str, value_name: &str) -> Result<Option<String>> {
+            match self.registry.get(user_sid) {
+                Some(registry) => registry.get_string_value(key_path, value_name),
+                None => Ok(None),
+            }
+        }
+    }
+
+    /// WMI with fixed answers
+    #[derive(Debug, Default)]
+    pub struct MockWmi {
+        pub last_boot_up_time: String,
+        pub sccm_client_installed: bool,
+    }
+
+    impl WmiProvider for MockWmi {
+        fn last_boot_up_time(&self) -> Result<String> {
+            Ok(self.last_boot_up_time.clone())
+        }
+
+        fn is_sccm_client_installed(&self) -> Result<bool> {
+            Ok(self.sccm_client_installed)
+        }
+    }
+
+    /// File system with a fixed set of existing paths and the times they were modified
+    #[derive(Debug, Default)]
+    pub struct MockFileSystem {
+        pub paths: HashMap<PathBuf, DateTime<Utc>>,
+    }
+
+    impl MockFileSystem {
+        /// Add an existing path, modified now
+        pub fn with_path<P: AsRef<Path>>(self, path: P) -> Self {
+            self.with_modified_path(path, Utc::now())
+        }
+
+        /// Add an existing path, modified at the given time
+        pub fn with_modified_path<P: AsRef<Path>>(mut self, path: P, modified: DateTime<Utc>) -> Self {
+            self.paths.insert(path.as_ref().to_path_buf(), modified);
+            self
+        }
+    }
+
+    impl FileSystemProvider for MockFileSystem {
+        fn exists(&self, path: &Path) -> bool {
+            self.paths.contains_key(path)
+        }
+
+        fn modified(&self, path: &Path) -> Option<DateTime<Utc>> {
+            self.paths.get(path).copied()
+        }
+    }
+}
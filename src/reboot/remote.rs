@@ -0,0 +1,274 @@
+use super::detector::RebootDetector;
+use super::providers::{
+    with_remote_wmi_connection, FileSystemProvider, RegistryProvider, RemoteHost, UserRegistryProvider, WmiProvider,
+    CIMV2_NAMESPACE,
+};
+use crate::config::RebootConfig;
+use crate::utils::{registry, sid, to_wide};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use log::{debug, info, warn};
+use serde_derive::Deserialize;
+use std::path::{Component, Path, PathBuf, Prefix};
+use std::sync::Arc;
+use windows::core::{PCWSTR, PWSTR};
+use windows::Win32::Foundation::NO_ERROR;
+use windows::Win32::NetworkManagement::WNet::{
+    WNetAddConnection2W, WNetCancelConnection2W, NETRESOURCEW, NET_CONNECT_FLAGS, RESOURCETYPE_ANY,
+};
+use windows::Win32::System::Registry::{RegCloseKey, HKEY, HKEY_LOCAL_MACHINE, HKEY_USERS};
+use windows::Win32::System::Services::{
+    CloseServiceHandle, OpenSCManagerW, OpenServiceW, SC_MANAGER_CONNECT, SERVICE_QUERY_STATUS,
+};
+
+/// Prefix of the SIDs of user accounts, local or from a domain
+const USER_SID_PREFIX: &str = "S-1-5-21-";
+
+/// Create a detector that checks a remote computer instead of this one
+///
+/// The registry is read through the Remote Registry service, files through the administrative
+/// shares (e.g., `\\PC1\C$`) and the boot time and system information through WMI over DCOM. With
+/// credentials, a connection to the computer's `IPC$` share is made as that user first, which the
+/// registry, service control manager and shares reuse; without them the current user's token is
+/// used throughout.
+pub fn detector(config: &RebootConfig, host: RemoteHost) -> Result<RebootDetector> {
+    let session = Arc::new(RemoteSession::open(&host)?);
+    info!("Connected to {}", host.computer);
+
+    Ok(RebootDetector::with_providers(
+        config,
+        Arc::new(RemoteRegistry(session.clone())),
+        Arc::new(RemoteWmi(host.clone())),
+        Arc::new(RemoteFileSystem(host.computer.clone())),
+    )
+    .with_user_registry(Arc::new(RemoteUserRegistry(session)))
+    .with_remote_host(host))
+}
+
+/// Registry key handle of a remote hive
+///
+/// Registry handles can be used from any thread, so the detection methods can share them.
+struct RemoteKey(HKEY);
+
+unsafe impl Send for RemoteKey {}
+unsafe impl Sync for RemoteKey {}
+
+/// Connection to a remote computer, closed when the last provider using it is dropped
+struct RemoteSession {
+    /// `IPC$` share connected to with credentials, if any
+    ipc_share: Option<String>,
+
+    /// HKEY_LOCAL_MACHINE of the remote computer
+    machine: RemoteKey,
+
+    /// HKEY_USERS of the remote computer
+    users: RemoteKey,
+}
+
+impl RemoteSession {
+    /// Connect to a remote computer's registry, with the host's credentials if it has any
+    fn open(host: &RemoteHost) -> Result<Self> {
+        let ipc_share = match &host.credentials {
+            Some(credentials) => {
+                let share = format!("\\\\{}\\IPC$", host.computer);
+                connect_share(&share, &credentials.user, credentials.password.expose())?;
+                Some(share)
+            }
+            None => None,
+        };
+
+        // Build the session first so the share is disconnected if the registry can't be reached
+        let mut session = Self {
+            ipc_share,
+            machine: RemoteKey(HKEY::default()),
+            users: RemoteKey(HKEY::default()),
+        };
+        session.machine = RemoteKey(registry::connect(&host.computer, HKEY_LOCAL_MACHINE)?);
+        session.users = RemoteKey(registry::connect(&host.computer, HKEY_USERS)?);
+        Ok(session)
+    }
+}
+
+impl Drop for RemoteSession {
+    fn drop(&mut self) {
+        unsafe {
+            for key in [&self.machine, &self.users] {
+                if key.0 != HKEY::default() {
+                    let _ = RegCloseKey(key.0);
+                }
+            }
+
+            if let Some(share) = &self.ipc_share {
+                debug!("Disconnecting from {}", share);
+                let share_wide = to_wide(share);
+                let _ = WNetCancelConnection2W(PCWSTR::from_raw(share_wide.as_ptr()), NET_CONNECT_FLAGS(0), true);
+            }
+        }
+    }
+}
+
+/// Connect to a share as a user, so later connections to the same computer use their credentials
+fn connect_share(share: &str, user: &str, password: &str) -> Result<()> {
+    debug!("Connecting to {} as {}", share, user);
+
+    let mut share_wide = to_wide(share);
+    let user_wide = to_wide(user);
+    let password_wide = to_wide(password);
+    let resource = NETRESOURCEW {
+        dwType: RESOURCETYPE_ANY,
+        lpRemoteName: PWSTR(share_wide.as_mut_ptr()),
+        ..Default::default()
+    };
+
+    let result = unsafe {
+        WNetAddConnection2W(
+            &resource,
+            PCWSTR::from_raw(password_wide.as_ptr()),
+            PCWSTR::from_raw(user_wide.as_ptr()),
+            NET_CONNECT_FLAGS(0),
+        )
+    };
+    if result != NO_ERROR {
+        return Err(anyhow::anyhow!("Failed to connect to {} as {}: error code {}", share, user, result.0));
+    }
+    Ok(())
+}
+
+/// Registry provider reading HKEY_LOCAL_MACHINE of a remote computer
+struct RemoteRegistry(Arc<RemoteSession>);
+
+impl RegistryProvider for RemoteRegistry {
+    fn key_exists(&self, key_path: &str) -> Result<bool> {
+        registry::key_exists(self.0.machine.0, key_path)
+    }
+
+    fn get_string_value(&self, key_path: &str, value_name: &str) -> Result<Option<String>> {
+        registry::get_string_value(self.0.machine.0, key_path, value_name)
+    }
+}
+
+/// User registry provider reading the hives loaded under HKEY_USERS of a remote computer
+///
+/// Sessions can't be mapped to users remotely, so every loaded user hive counts as a signed-in
+/// user. A hive stays loaded briefly after its user signs out, and accounts running services have
+/// one too.
+struct RemoteUserRegistry(Arc<RemoteSession>);
+
+impl UserRegistryProvider for RemoteUserRegistry {
+    fn active_users(&self) -> Result<Vec<(String, String)>> {
+        Ok(registry::subkey_names(self.0.users.0, "")?
+            .into_iter()
+            .filter(|name| name.starts_with(USER_SID_PREFIX) && !name.ends_with("_Classes"))
+            .map(|user_sid| (sid::account_name(&user_sid).unwrap_or_else(|| user_sid.clone()), user_sid))
+            .collect())
+    }
+
+    fn key_exists(&self, user_sid: &str, key_path: &str) -> Result<bool> {
+        registry::key_exists(self.0.users.0, &format!("{}\\{}", user_sid, key_path))
+    }
+
+    fn get_string_value(&self, user_sid: &str, key_path: &str, value_name: &str) -> Result<Option<String>> {
+        registry::get_string_value(self.0.users.0, &format!("{}\\{}", user_sid, key_path), value_name)
+    }
+}
+
+/// WMI provider querying a remote computer's WMI and service control manager
+struct RemoteWmi(RemoteHost);
+
+impl WmiProvider for RemoteWmi {
+    fn last_boot_up_time(&self) -> Result<String> {
+        #[derive(Debug, Deserialize)]
+        #[serde(rename = "Win32_OperatingSystem")]
+        struct OSInfo {
+            #[serde(rename = "LastBootUpTime")]
+            last_boot_up_time: String,
+        }
+
+        let results: Vec<OSInfo> = with_remote_wmi_connection(&self.0, CIMV2_NAMESPACE, |wmi_con| {
+            wmi_con.query().context("Failed to query WMI for last boot time")
+        })?;
+
+        results
+            .into_iter()
+            .next()
+            .map(|info| info.last_boot_up_time)
+            .ok_or_else(|| anyhow::anyhow!("No OS information found in WMI on {}", self.0.computer))
+    }
+
+    fn is_sccm_client_installed(&self) -> Result<bool> {
+        let computer_wide = to_wide(&format!("\\\\{}", self.0.computer));
+        let service_name = to_wide("CCMExec");
+
+        unsafe {
+            let sc_manager = OpenSCManagerW(PCWSTR::from_raw(computer_wide.as_ptr()), PCWSTR::null(), SC_MANAGER_CONNECT)
+                .with_context(|| format!("Failed to open the service control manager of {}", self.0.computer))?;
+
+            let installed = match OpenServiceW(sc_manager, PCWSTR::from_raw(service_name.as_ptr()), SERVICE_QUERY_STATUS) {
+                Ok(service) => {
+                    let _ = CloseServiceHandle(service);
+                    true
+                }
+                Err(_) => false,
+            };
+
+            let _ = CloseServiceHandle(sc_manager);
+            Ok(installed)
+        }
+    }
+}
+
+/// File system provider checking paths through a remote computer's administrative shares
+struct RemoteFileSystem(String);
+
+impl FileSystemProvider for RemoteFileSystem {
+    fn exists(&self, path: &Path) -> bool {
+        match admin_share_path(&self.0, path) {
+            Some(remote_path) => remote_path.exists(),
+            None => {
+                warn!("Can't check {} on {}: it isn't on a drive", path.display(), self.0);
+                false
+            }
+        }
+    }
+
+    fn modified(&self, path: &Path) -> Option<DateTime<Utc>> {
+        let remote_path = admin_share_path(&self.0, path)?;
+        std::fs::metadata(remote_path).and_then(|metadata| metadata.modified()).ok().map(DateTime::from)
+    }
+}
+
+/// Map a local path to the administrative share of its drive on a remote computer
+/// (e.g., `C:\Windows` to `\\PC1\C$\Windows`), or None if the path isn't on a drive
+pub fn admin_share_path(computer: &str, path: &Path) -> Option<PathBuf> {
+    let mut components = path.components();
+    let drive = match components.next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::Disk(drive) | Prefix::VerbatimDisk(drive) => drive as char,
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    let mut remote_path = PathBuf::from(format!("\\\\{}\\{}$\\", computer, drive.to_ascii_uppercase()));
+    remote_path.extend(components.filter(|component| !matches!(component, Component::RootDir)));
+    Some(remote_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_admin_share_path() {
+        assert_eq!(
+            admin_share_path("PC1", Path::new("C:\\Windows\\Windows.~BT")),
+            Some(PathBuf::from("\\\\PC1\\C$\\Windows\\Windows.~BT"))
+        );
+        assert_eq!(
+            admin_share_path("PC1", Path::new("d:\\$WINDOWS.~BT\\Sources\\Panther\\setupact.log")),
+            Some(PathBuf::from("\\\\PC1\\D$\\$WINDOWS.~BT\\Sources\\Panther\\setupact.log"))
+        );
+        assert_eq!(admin_share_path("PC1", Path::new("\\\\server\\share\\file")), None);
+        assert_eq!(admin_share_path("PC1", Path::new("relative\\file")), None);
+    }
+}
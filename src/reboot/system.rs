@@ -1,10 +1,31 @@
+use crate::config::{RebootStrategy, SystemRebootConfig};
+use crate::database::{self, DbPool, RestartApplication};
+use crate::impersonation::{Impersonator, SessionCommand, SessionExit};
+use crate::reboot::apps::{self, OpenApplication};
+use crate::utils::to_wide;
 use anyhow::{Context, Result};
 use log::{debug, info, warn, error};
 use std::process::Command;
+use std::sync::mpsc;
 use std::thread;
 use std::time::Duration;
-use windows::Win32::System::Shutdown::{ExitWindowsEx, EWX_REBOOT, SHUTDOWN_REASON};
-use windows::Win32::UI::WindowsAndMessaging::{MessageBoxW, MB_ICONQUESTION, MB_YESNO, MB_DEFBUTTON2, IDYES};
+use windows::Win32::Foundation::{CloseHandle, GetLastError, ERROR_NOT_ALL_ASSIGNED, HANDLE, LUID, WIN32_ERROR};
+use windows::Win32::Security::{
+    AdjustTokenPrivileges, LookupPrivilegeValueW, SE_PRIVILEGE_ENABLED, SE_SHUTDOWN_NAME, TOKEN_ADJUST_PRIVILEGES,
+    TOKEN_PRIVILEGES, TOKEN_QUERY,
+};
+use windows::Win32::System::Diagnostics::ToolHelp::{
+    CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W, TH32CS_SNAPPROCESS,
+};
+use windows::Win32::System::Power::{GetSystemPowerStatus, SYSTEM_POWER_STATUS};
+use windows::Win32::System::Shutdown::{
+    InitiateShutdownW, InitiateSystemShutdownExW, SHTDN_REASON_FLAG_PLANNED, SHTDN_REASON_FLAG_USER_DEFINED,
+    SHUTDOWN_FORCE_OTHERS, SHUTDOWN_FORCE_SELF, SHUTDOWN_REASON, SHUTDOWN_RESTART, SHUTDOWN_RESTARTAPPS,
+};
+use windows::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+use windows::Win32::UI::WindowsAndMessaging::{
+    MessageBoxW, IDYES, MB_DEFBUTTON2, MB_ICONQUESTION, MB_SETFOREGROUND, MB_TOPMOST, MB_YESNO, MESSAGEBOX_STYLE,
+};
 use windows::core::PCWSTR;
 
 /// Configuration for system reboot
@@ -21,6 +42,80 @@ pub struct RebootConfig {
     
     /// Confirmation title
     pub confirmation_title: String,
+
+    /// How long users have to answer the confirmation before it counts as declined
+    pub confirmation_timeout: Duration,
+
+    /// Whether the system must be running on AC power
+    pub require_ac_power: bool,
+
+    /// Minimum battery charge in percent when running on battery power
+    pub min_battery_percent: Option<u8>,
+
+    /// How the reboot is executed
+    pub strategy: RebootStrategy,
+
+    /// Message shown by Windows while a scheduled reboot is pending
+    pub shutdown_message: String,
+
+    /// Whether applications are closed without asking to save their data
+    pub force_apps_closed: bool,
+
+    /// Whether the applications the reboot would close are listed in the confirmation
+    pub list_open_applications: bool,
+
+    /// Text shown above the list of open applications
+    pub open_applications_message: String,
+
+    /// Whether applications registered for restart are started again after sign-in
+    pub restart_applications: bool,
+
+    /// Shutdown reason in the `shutdown /d` format (e.g., "p:2:17")
+    pub reason_code: String,
+
+    /// Log what would happen instead of showing dialogs and rebooting
+    pub dry_run: bool,
+}
+
+/// Outcome of an attempt to reboot the system
+#[derive(Debug, Clone, Default)]
+pub struct RebootAttempt {
+    /// Whether the reboot was confirmed and initiated
+    pub proceeded: bool,
+
+    /// Applications that were open in the user sessions
+    pub open_applications: Vec<OpenApplication>,
+}
+
+impl RebootAttempt {
+    /// Get the labels of the open applications, for the reboot history
+    pub fn application_labels(&self) -> Vec<String> {
+        self.open_applications.iter().map(OpenApplication::label).collect()
+    }
+
+    /// Record the applications Windows will restart after an initiated reboot, for troubleshooting
+    pub fn record_restart_applications(&self, config: &RebootConfig, db_pool: &DbPool) {
+        if !self.proceeded || config.dry_run || !config.restart_applications {
+            return;
+        }
+
+        let initiated_at = chrono::Utc::now();
+        let applications: Vec<RestartApplication> = self
+            .open_applications
+            .iter()
+            .filter(|app| app.restartable)
+            .map(|app| RestartApplication::new(initiated_at, &app.name, app.process_id, app.session_id))
+            .collect();
+        if applications.is_empty() {
+            debug!("No open applications are registered for restart");
+            return;
+        }
+
+        info!("{} applications are registered to restart after the reboot", applications.len());
+        if let Err(e) = database::add_restart_applications(db_pool, &applications) {
+            warn!("Failed to record the applications registered for restart: {:#}", e);
+        }
+    }
 }
 
 impl Default for RebootConfig {
@@ -30,34 +125,242 @@ impl Default for RebootConfig {
             show_confirmation: true,
             confirmation_message: "The system needs to restart. Do you want to restart now?".to_string(),
             confirmation_title: "System Restart Required".to_string(),
+            confirmation_timeout: DEFAULT_CONFIRMATION_TIMEOUT,
+            require_ac_power: false,
+            min_battery_percent: None,
+            strategy: RebootStrategy::Api,
+            shutdown_message: "This computer will restart to finish applying updates. Please save your work.".to_string(),
+            force_apps_closed: false,
+            list_open_applications: true,
+            open_applications_message: "These applications will be closed:".to_string(),
+            restart_applications: false,
+            reason_code: DEFAULT_REASON_CODE.to_string(),
+            dry_run: false,
+        }
+    }
+}
+
+impl From<&SystemRebootConfig> for RebootConfig {
+    fn from(config: &SystemRebootConfig) -> Self {
+        let countdown_seconds = if let Some(countdown) = &config.countdown {
+            // Parse the timespan string
+            match crate::utils::timespan::parse_timespan(countdown) {
+                Ok(duration) => duration.as_secs() as u32,
+                Err(e) => {
+                    warn!("Failed to parse countdown timespan: {}", e);
+                    // Fall back to the legacy value or default
+                    config.countdown_seconds.unwrap_or(30)
+                }
+            }
+        } else {
+            // Use the legacy value or default
+            config.countdown_seconds.unwrap_or(30)
+        };
+
+        let confirmation_timeout = crate::utils::timespan::parse_timespan(&config.confirmation_timeout)
+            .unwrap_or_else(|e| {
+                warn!("Failed to parse confirmation timeout: {}", e);
+                DEFAULT_CONFIRMATION_TIMEOUT
+            });
+
+        Self {
+            countdown_seconds,
+            show_confirmation: config.show_confirmation,
+            confirmation_message: config.confirmation_message.clone(),
+            confirmation_title: config.confirmation_title.clone(),
+            confirmation_timeout,
+            require_ac_power: config.require_ac_power,
+            min_battery_percent: config.min_battery_percent,
+            strategy: config.strategy,
+            shutdown_message: config.shutdown_message.clone(),
+            force_apps_closed: config.force_apps_closed,
+            list_open_applications: config.list_open_applications,
+            open_applications_message: config.open_applications_message.clone(),
+            restart_applications: config.restart_applications,
+            reason_code: config.reason_code.clone(),
+            dry_run: false,
+        }
+    }
+}
+
+/// Time users have to answer the reboot confirmation by default
+const DEFAULT_CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Power source and battery state
+#[derive(Debug, Clone, Copy)]
+pub struct PowerStatus {
+    /// Whether the system is running on AC power (None if unknown)
+    pub on_ac_power: Option<bool>,
+
+    /// Remaining battery charge in percent (None if unknown or no battery)
+    pub battery_percent: Option<u8>,
+}
+
+/// Get the current power status
+pub fn get_power_status() -> Result<PowerStatus> {
+    let mut status = SYSTEM_POWER_STATUS::default();
+    unsafe { GetSystemPowerStatus(&mut status) }.context("Failed to get system power status")?;
+
+    let on_ac_power = match status.ACLineStatus {
+        0 => Some(false),
+        1 => Some(true),
+        _ => None,
+    };
+
+    // Battery flag 128 means there is no system battery, and 255 means the charge is unknown
+    let battery_percent = if status.BatteryFlag & 128 != 0 || status.BatteryLifePercent > 100 {
+        None
+    } else {
+        Some(status.BatteryLifePercent)
+    };
+
+    Ok(PowerStatus { on_ac_power, battery_percent })
+}
+
+/// Get the reason a reboot is blocked by the power status, if any
+pub fn power_block_reason(config: &RebootConfig, status: &PowerStatus) -> Option<String> {
+    if config.require_ac_power && status.on_ac_power == Some(false) {
+        return Some("System is running on battery power".to_string());
+    }
+
+    // The battery level only matters when the system is not plugged in
+    if let (Some(min_percent), Some(percent)) = (config.min_battery_percent, status.battery_percent) {
+        if status.on_ac_power != Some(true) && percent < min_percent {
+            return Some(format!("Battery level {}% is below the required {}%", percent, min_percent));
+        }
+    }
+
+    None
+}
+
+/// Check whether the current power status allows a reboot
+///
+/// Returns the reason the reboot should be deferred, or None if it can proceed.
+pub fn check_power_conditions(config: &RebootConfig) -> Option<String> {
+    if !config.require_ac_power && config.min_battery_percent.is_none() {
+        return None;
+    }
+
+    match get_power_status() {
+        Ok(status) => {
+            debug!("Power status: {:?}", status);
+            power_block_reason(config, &status)
+        },
+        Err(e) => {
+            warn!("Failed to check power status, not blocking reboot: {}", e);
+            None
+        }
+    }
+}
+
+/// Get the names of all running processes
+pub fn get_running_process_names() -> Result<Vec<String>> {
+    let snapshot = unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) }
+        .context("Failed to create process snapshot")?;
+
+    let mut names = Vec::new();
+    let mut entry = PROCESSENTRY32W {
+        dwSize: std::mem::size_of::<PROCESSENTRY32W>() as u32,
+        ..Default::default()
+    };
+
+    unsafe {
+        let mut found = Process32FirstW(snapshot, &mut entry).is_ok();
+        while found {
+            let len = entry.szExeFile.iter().position(|&c| c == 0).unwrap_or(entry.szExeFile.len());
+            names.push(String::from_utf16_lossy(&entry.szExeFile[..len]));
+            found = Process32NextW(snapshot, &mut entry).is_ok();
+        }
+
+        let _ = CloseHandle(snapshot);
+    }
+
+    Ok(names)
+}
+
+/// Find the first configured blocking process in a list of running process names
+///
+/// Names are compared case-insensitively, and the ".exe" extension is optional in the configuration.
+pub fn match_blocking_process(running: &[String], blocking: &[String]) -> Option<String> {
+    let normalize = |name: &str| {
+        let name = name.trim().to_lowercase();
+        match name.strip_suffix(".exe") {
+            Some(stem) => stem.to_string(),
+            None => name,
+        }
+    };
+
+    blocking.iter().find_map(|blocker| {
+        let blocker_name = normalize(blocker);
+        running
+            .iter()
+            .find(|process| normalize(process) == blocker_name)
+            .cloned()
+    })
+}
+
+/// Check whether any configured blocking process is running
+///
+/// Returns the name of the blocking process, or None if the reboot can proceed.
+pub fn find_blocking_process(blocking: &[String]) -> Option<String> {
+    if blocking.is_empty() {
+        return None;
+    }
+
+    match get_running_process_names() {
+        Ok(running) => match_blocking_process(&running, blocking),
+        Err(e) => {
+            warn!("Failed to enumerate running processes, not blocking reboot: {}", e);
+            None
         }
     }
 }
 
 /// Initiate a system reboot with confirmation and countdown
-pub fn reboot_system(config: &RebootConfig) -> Result<bool> {
+///
+/// The applications open in the user sessions are listed first, so users can see in the
+/// confirmation what the reboot will close.
+pub fn reboot_system(config: &RebootConfig) -> Result<RebootAttempt> {
     info!("Initiating system reboot process");
+
+    let open_applications = if config.list_open_applications || config.restart_applications {
+        apps::open_applications().unwrap_or_else(|e| {
+            warn!("Failed to list open applications: {:#}", e);
+            Vec::new()
+        })
+    } else {
+        Vec::new()
+    };
+    if !open_applications.is_empty() {
+        let labels: Vec<String> = open_applications.iter().map(OpenApplication::label).collect();
+        info!("Applications open before the reboot: {}", labels.join(", "));
+    }
+
+    let proceeded = confirm_and_reboot(config, &open_applications)?;
+    Ok(RebootAttempt { proceeded, open_applications })
+}
+
+/// Reboot once the power status allows it and the users confirm
+fn confirm_and_reboot(config: &RebootConfig, open_applications: &[OpenApplication]) -> Result<bool> {    
+    // Make sure the power status allows a reboot
+    if let Some(reason) = check_power_conditions(config) {
+        warn!("Not rebooting the system: {}", reason);
+        return Ok(false);
+    }
+    
+    // Only describe the reboot in dry-run mode
+    if config.dry_run {
+        info!(
+            "[dry-run] Would reboot the system: strategy={:?}, confirmation={}, countdown={}s",
+            config.strategy, config.show_confirmation, config.countdown_seconds
+        );
+        return Ok(true);
+    }
     
     // Show confirmation dialog if configured
     if config.show_confirmation {
         info!("Showing reboot confirmation dialog");
-        
-        // Convert strings to wide strings for Windows API
-        let message_wide: Vec<u16> = config.confirmation_message.encode_utf16().chain(std::iter::once(0)).collect();
-        let title_wide: Vec<u16> = config.confirmation_title.encode_utf16().chain(std::iter::once(0)).collect();
-        
-        // Show message box
-        let result = unsafe {
-            MessageBoxW(
-                None,
-                PCWSTR::from_raw(message_wide.as_ptr()),
-                PCWSTR::from_raw(title_wide.as_ptr()),
-                MB_YESNO | MB_ICONQUESTION | MB_DEFBUTTON2,
-            )
-        };
-        
-        // Check if user confirmed
-        if result != IDYES {
+        if !confirm_reboot(config, open_applications)? {
             info!("User declined system reboot");
             return Ok(false);
         }
@@ -65,70 +368,279 @@ pub fn reboot_system(config: &RebootConfig) -> Result<bool> {
         info!("User confirmed system reboot");
     }
     
-    // If countdown is enabled, show countdown dialog
-    if config.countdown_seconds > 0 {
-        info!("Starting reboot countdown: {} seconds", config.countdown_seconds);
-        
-        // Create countdown message
-        let countdown_message = format!(
-            "The system will restart in {} seconds. Please save your work and close applications.",
-            config.countdown_seconds
-        );
-        
-        // Show countdown notification
-        // This is a simple implementation - in a real app, you might want to show a GUI countdown
-        let countdown_wide: Vec<u16> = countdown_message.encode_utf16().chain(std::iter::once(0)).collect();
-        let title_wide: Vec<u16> = "System Restarting".encode_utf16().chain(std::iter::once(0)).collect();
-        
-        unsafe {
-            MessageBoxW(
-                None,
-                PCWSTR::from_raw(countdown_wide.as_ptr()),
-                PCWSTR::from_raw(title_wide.as_ptr()),
-                MB_ICONQUESTION,
-            );
+    // Let Windows run the countdown and show the reason if configured
+    if config.strategy == RebootStrategy::Shutdown {
+        return schedule_shutdown_reboot(config);
+    }
+    
+    // Windows shows the countdown and the message, and closes the session when it ends
+    match initiate_reboot(config) {
+        Ok(()) => Ok(true),
+        Err(e) => {
+            warn!("Failed to reboot using Windows API: {:#}", e);
+
+            // Fall back to using shutdown.exe command
+            info!("Attempting to reboot using shutdown.exe command");
+            schedule_shutdown_reboot(config)
         }
-        
-        // Wait for the countdown
-        for i in (1..=config.countdown_seconds).rev() {
-            if i % 5 == 0 || i <= 5 {
-                debug!("Reboot countdown: {} seconds remaining", i);
+    }
+}
+
+/// Ask the signed-in users to confirm the reboot
+///
+/// A message box shown by the service would only appear on the session 0 desktop, which users
+/// never see. From the service a copy of this program asks the question in every user session
+/// instead (`confirm-reboot`). Each user sees the applications open in their own session.
+///
+/// On a computer with several users signed in, the first user to answer yes confirms the reboot
+/// for everyone: the reboot was due anyway, and the others were warned by the same question.
+/// It proceeds right away, without waiting for the other questions to be answered or time out.
+fn confirm_reboot(config: &RebootConfig, open_applications: &[OpenApplication]) -> Result<bool> {
+    let impersonator = Impersonator::new();
+    let current_session = impersonator.current_session_id();
+    if current_session != Some(0) {
+        let message = confirmation_message(config, open_applications, current_session);
+        return Ok(ask_confirmation(&config.confirmation_title, &message));
+    }
+
+    let sessions = impersonator.get_active_sessions()?;
+    if sessions.is_empty() {
+        info!("Nobody is signed in to confirm the reboot");
+        return Ok(false);
+    }
+
+    // Ask every session at once, so one user's unanswered box doesn't hold up the others
+    let (sender, receiver) = mpsc::channel();
+    for session in sessions {
+        let title = config.confirmation_title.clone();
+        let message = confirmation_message(config, open_applications, session.session_id.parse().ok());
+        let timeout = config.confirmation_timeout;
+        let sender = sender.clone();
+        thread::spawn(move || {
+            // The question is closed after the timeout, which counts as declined
+            let answer = SessionCommand::this_program(["confirm-reboot", &title, &message])
+                .and_then(|command| Impersonator::new().run_in_session(&session.session_id, &command.timeout(timeout)));
+            let _ = sender.send((session, answer));
+        });
+    }
+    drop(sender);
+
+    // Ends once every question is answered, or a thread that asked one is gone
+    for (session, answer) in receiver {
+        match answer {
+            Ok(SessionExit::Exited(0)) => {
+                info!("User {} confirmed the reboot", crate::logging::user(&session.user_name));
+                return Ok(true);
             }
-            thread::sleep(Duration::from_secs(1));
+            Ok(exit) => debug!(
+                "User {} answered the reboot confirmation with {:?}",
+                crate::logging::user(&session.user_name),
+                exit
+            ),
+            Err(e) => warn!("Failed to ask session {} to confirm the reboot: {:#}", session.session_id, e),
         }
     }
-    
-    // Perform the actual reboot
-    info!("Executing system reboot");
-    
-    // Try using Windows API first
+
+    Ok(false)
+}
+
+/// Ask the user of this process's session to confirm the reboot in a message box
+///
+/// Returns whether they answered yes. Run by `confirm-reboot` in the users' sessions, which
+/// exits with 0 when they did.
+pub fn ask_confirmation(title: &str, message: &str) -> bool {
+    const STYLE: MESSAGEBOX_STYLE = MESSAGEBOX_STYLE(
+        MB_YESNO.0 | MB_ICONQUESTION.0 | MB_DEFBUTTON2.0 | MB_TOPMOST.0 | MB_SETFOREGROUND.0,
+    );
+
+    let message_wide = to_wide(message);
+    let title_wide = to_wide(title);
     let result = unsafe {
-        ExitWindowsEx(
-            EWX_REBOOT,
-            SHUTDOWN_REASON(0), // No specific reason code
+        MessageBoxW(
+            None,
+            PCWSTR::from_raw(message_wide.as_ptr()),
+            PCWSTR::from_raw(title_wide.as_ptr()),
+            STYLE,
         )
     };
-    
-    if let Err(e) = result {
-        warn!("Failed to reboot using Windows API: {}", e);
-        
-        // Fall back to using shutdown.exe command
-        info!("Attempting to reboot using shutdown.exe command");
-        match Command::new("shutdown")
-            .args(&["/r", "/t", "0", "/f"])
-            .output() {
-                Ok(_) => {
-                    info!("System reboot initiated successfully using shutdown.exe");
-                    Ok(true)
-                },
-                Err(e) => {
-                    error!("Failed to reboot using shutdown.exe: {}", e);
-                    Err(e).context("Failed to initiate system reboot")
+    result == IDYES
+}
+
+/// Get the confirmation message, listing the applications open in a session
+fn confirmation_message(config: &RebootConfig, open_applications: &[OpenApplication], session_id: Option<u32>) -> String {
+    let session_apps: Vec<OpenApplication> = open_applications
+        .iter()
+        .filter(|app| Some(app.session_id) == session_id)
+        .cloned()
+        .collect();
+    if session_apps.is_empty() {
+        return config.confirmation_message.clone();
+    }
+
+    format!(
+        "{}\n\n{}\n{}",
+        config.confirmation_message,
+        config.open_applications_message,
+        apps::describe(&session_apps)
+    )
+}
+
+/// Reboot the computer through the Windows shutdown service
+///
+/// Unlike `ExitWindowsEx`, this reboots the whole computer from the service session once the
+/// shutdown privilege is enabled, and Windows shows the countdown and message to every signed-in user.
+pub fn initiate_reboot(config: &RebootConfig) -> Result<()> {
+    let reason = parse_reason_code(&config.reason_code).or_else(|e| {
+        warn!("Invalid shutdown reason code, using {}: {:#}", DEFAULT_REASON_CODE, e);
+        parse_reason_code(DEFAULT_REASON_CODE)
+    })?;
+
+    info!(
+        "Executing system reboot in {} seconds (reason {}, force {})",
+        config.countdown_seconds, config.reason_code, config.force_apps_closed
+    );
+    enable_shutdown_privilege()?;
+
+    let message: String = config.shutdown_message.chars().take(MAX_SHUTDOWN_COMMENT_LENGTH).collect();
+    let message_wide = to_wide(&message);
+
+    // Only InitiateShutdownW can restart the applications registered with RegisterApplicationRestart
+    if config.restart_applications {
+        let mut flags = SHUTDOWN_RESTART | SHUTDOWN_RESTARTAPPS;
+        if config.force_apps_closed {
+            flags |= SHUTDOWN_FORCE_OTHERS | SHUTDOWN_FORCE_SELF;
+        }
+        let result = unsafe {
+            InitiateShutdownW(
+                PCWSTR::null(),
+                PCWSTR::from_raw(message_wide.as_ptr()),
+                config.countdown_seconds,
+                flags,
+                SHUTDOWN_REASON(reason),
+            )
+        };
+        WIN32_ERROR(result).ok().context("Failed to initiate system shutdown")?;
+
+        info!("System reboot initiated successfully using Windows API, restarting registered applications");
+        return Ok(());
+    }
+
+    unsafe {
+        InitiateSystemShutdownExW(
+            PCWSTR::null(),
+            PCWSTR::from_raw(message_wide.as_ptr()),
+            config.countdown_seconds,
+            config.force_apps_closed,
+            true,
+            SHUTDOWN_REASON(reason),
+        )
+    }
+    .context("Failed to initiate system shutdown")?;
+
+    info!("System reboot initiated successfully using Windows API");
+    Ok(())
+}
+
+/// Default shutdown reason: planned, operating system, hot fix
+pub const DEFAULT_REASON_CODE: &str = "p:2:17";
+
+/// Parse a shutdown reason in the `shutdown /d [p|u:]xx:yy` format into its reason flags
+pub fn parse_reason_code(code: &str) -> Result<u32> {
+    let parts: Vec<&str> = code.trim().split(':').collect();
+    let (flags, major, minor) = match parts.as_slice() {
+        [major, minor] => (0, major, minor),
+        [kind, major, minor] => {
+            let flags = match kind.to_lowercase().as_str() {
+                "p" => SHTDN_REASON_FLAG_PLANNED.0,
+                "u" => SHTDN_REASON_FLAG_USER_DEFINED.0,
+                other => anyhow::bail!("Unknown reason type '{}', expected 'p' or 'u'", other),
+            };
+            (flags, major, minor)
+        }
+        _ => anyhow::bail!("Reason code '{}' is not in the format [p|u:]major:minor", code),
+    };
+
+    let major: u8 = major.trim().parse().with_context(|| format!("Invalid major reason '{}' (0-255)", major))?;
+    let minor: u16 = minor.trim().parse().with_context(|| format!("Invalid minor reason '{}' (0-65535)", minor))?;
+    Ok(flags | (major as u32) << 16 | minor as u32)
+}
+
+/// Enable the shutdown privilege in the token of this process
+fn enable_shutdown_privilege() -> Result<()> {
+    unsafe {
+        let mut token = HANDLE::default();
+        OpenProcessToken(GetCurrentProcess(), TOKEN_ADJUST_PRIVILEGES | TOKEN_QUERY, &mut token)
+            .context("Failed to open process token")?;
+
+        let mut luid = LUID::default();
+        let result = LookupPrivilegeValueW(PCWSTR::null(), SE_SHUTDOWN_NAME, &mut luid)
+            .context("Failed to look up the shutdown privilege")
+            .and_then(|_| {
+                let mut privileges = TOKEN_PRIVILEGES {
+                    PrivilegeCount: 1,
+                    ..Default::default()
+                };
+                privileges.Privileges[0].Luid = luid;
+                privileges.Privileges[0].Attributes = SE_PRIVILEGE_ENABLED;
+                AdjustTokenPrivileges(token, false, Some(&privileges), 0, None, None)
+                    .context("Failed to enable the shutdown privilege")?;
+
+                // The call succeeds even when the account doesn't hold the privilege
+                if GetLastError() == ERROR_NOT_ALL_ASSIGNED {
+                    anyhow::bail!("The account doesn't hold the shutdown privilege");
                 }
-            }
+                Ok(())
+            });
+
+        let _ = CloseHandle(token);
+        result
+    }
+}
+
+/// Maximum length of the comment accepted by shutdown.exe
+const MAX_SHUTDOWN_COMMENT_LENGTH: usize = 512;
+
+/// Build the shutdown.exe arguments for a reboot scheduled by Windows
+fn shutdown_reboot_args(config: &RebootConfig) -> Vec<String> {
+    let comment: String = config.shutdown_message.chars().take(MAX_SHUTDOWN_COMMENT_LENGTH).collect();
+    let reason_code = if parse_reason_code(&config.reason_code).is_ok() {
+        config.reason_code.trim().to_string()
     } else {
-        info!("System reboot initiated successfully using Windows API");
+        DEFAULT_REASON_CODE.to_string()
+    };
+
+    // /g restarts the applications registered for restart after the user signs in
+    let mut args = vec![
+        if config.restart_applications { "/g" } else { "/r" }.to_string(),
+        "/t".to_string(),
+        config.countdown_seconds.to_string(),
+        "/c".to_string(),
+        comment,
+        "/d".to_string(),
+        reason_code,
+    ];
+    if config.force_apps_closed {
+        args.push("/f".to_string());
+    }
+    args
+}
+
+/// Schedule the reboot with shutdown.exe so Windows' own UI shows the countdown and reason
+fn schedule_shutdown_reboot(config: &RebootConfig) -> Result<bool> {
+    info!("Scheduling system reboot with shutdown.exe in {} seconds", config.countdown_seconds);
+
+    let output = Command::new("shutdown")
+        .args(shutdown_reboot_args(config))
+        .output()
+        .context("Failed to run shutdown.exe")?;
+
+    if output.status.success() {
+        info!("System reboot scheduled successfully using shutdown.exe");
         Ok(true)
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        error!("shutdown.exe failed to schedule the reboot: {}", stderr.trim());
+        Err(anyhow::anyhow!("Failed to schedule system reboot: {}", stderr.trim()))
     }
 }
 
@@ -150,3 +662,100 @@ pub fn cancel_reboot() -> Result<()> {
             }
         }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_power_block_reason() {
+        let mut config = RebootConfig::default();
+        let on_battery = PowerStatus { on_ac_power: Some(false), battery_percent: Some(5) };
+        let on_ac = PowerStatus { on_ac_power: Some(true), battery_percent: Some(5) };
+
+        // No requirements configured
+        assert!(power_block_reason(&config, &on_battery).is_none());
+
+        // AC power required
+        config.require_ac_power = true;
+        assert!(power_block_reason(&config, &on_battery).is_some());
+        assert!(power_block_reason(&config, &on_ac).is_none());
+
+        // Minimum battery level only applies on battery power
+        config.require_ac_power = false;
+        config.min_battery_percent = Some(20);
+        assert!(power_block_reason(&config, &on_battery).is_some());
+        assert!(power_block_reason(&config, &on_ac).is_none());
+    }
+
+    #[test]
+    fn test_match_blocking_process() {
+        let running = vec!["explorer.exe".to_string(), "HandBrake.exe".to_string()];
+
+        assert_eq!(
+            match_blocking_process(&running, &["handbrake.exe".to_string()]),
+            Some("HandBrake.exe".to_string())
+        );
+        assert_eq!(
+            match_blocking_process(&running, &["handbrake".to_string()]),
+            Some("HandBrake.exe".to_string())
+        );
+        assert_eq!(match_blocking_process(&running, &["robocopy.exe".to_string()]), None);
+        assert_eq!(match_blocking_process(&running, &[]), None);
+    }
+
+    #[test]
+    fn test_shutdown_reboot_args() {
+        let mut config = RebootConfig {
+            countdown_seconds: 300,
+            shutdown_message: "Restarting for updates".to_string(),
+            ..Default::default()
+        };
+        let args = shutdown_reboot_args(&config);
+        assert_eq!(args, ["/r", "/t", "300", "/c", "Restarting for updates", "/d", "p:2:17"]);
+
+        // Forced reboots close applications without asking
+        config.force_apps_closed = true;
+        config.reason_code = "u:4:1".to_string();
+        assert_eq!(shutdown_reboot_args(&config)[5..], ["/d", "u:4:1", "/f"]);
+
+        // Long comments are truncated to what shutdown.exe accepts
+        config.shutdown_message = "x".repeat(600);
+        let args = shutdown_reboot_args(&config);
+        assert_eq!(args[4].len(), MAX_SHUTDOWN_COMMENT_LENGTH);
+
+        config.restart_applications = true;
+        assert_eq!(shutdown_reboot_args(&config)[0], "/g");
+    }
+
+    #[test]
+    fn test_parse_reason_code() {
+        assert_eq!(parse_reason_code("p:2:17").unwrap(), SHTDN_REASON_FLAG_PLANNED.0 | 0x0002_0011);
+        assert_eq!(parse_reason_code("U:0:0").unwrap(), SHTDN_REASON_FLAG_USER_DEFINED.0);
+        assert_eq!(parse_reason_code("4:1").unwrap(), 0x0004_0001);
+        assert!(parse_reason_code("x:2:17").is_err());
+        assert!(parse_reason_code("p:256:1").is_err());
+        assert!(parse_reason_code("planned").is_err());
+    }
+
+    #[test]
+    fn test_confirmation_message() {
+        let config = RebootConfig {
+            confirmation_message: "Restart now?".to_string(),
+            ..Default::default()
+        };
+        let open_applications = [OpenApplication {
+            name: "*notes.txt - Notepad".to_string(),
+            process_id: 42,
+            session_id: 2,
+            unsaved: true,
+            restartable: false,
+        }];
+
+        assert_eq!(
+            confirmation_message(&config, &open_applications, Some(2)),
+            "Restart now?\n\nThese applications will be closed:\n  - *notes.txt - Notepad (unsaved changes)"
+        );
+        assert_eq!(confirmation_message(&config, &open_applications, Some(3)), "Restart now?");
+    }
+}
@@ -0,0 +1,159 @@
+use crate::config::SystemRebootConfig;
+use crate::database::{self, DbPool, RebootHistory, RebootLifecycle};
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use log::{error, info, warn};
+
+/// Source recorded in the reboot history for reboots that were initiated but didn't happen
+pub const VERIFICATION_SOURCE: &str = "verification";
+
+/// Outcome of a reboot that was initiated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The computer has started since the reboot was initiated
+    Rebooted,
+
+    /// The reboot may still happen
+    Pending,
+
+    /// The computer didn't restart within the verification window
+    Failed,
+}
+
+/// Decide what happened to a reboot initiated at `initiated_at`
+pub fn outcome(initiated_at: DateTime<Utc>, boot_time: DateTime<Utc>, window: Duration, now: DateTime<Utc>) -> Outcome {
+    if boot_time > initiated_at {
+        Outcome::Rebooted
+    } else if now - initiated_at < window {
+        Outcome::Pending
+    } else {
+        Outcome::Failed
+    }
+}
+
+/// Get the verification window of the system reboot configuration
+pub fn window(config: &SystemRebootConfig) -> Duration {
+    crate::utils::timespan::parse_timespan(&config.verify_window)
+        .ok()
+        .and_then(|window| Duration::from_std(window).ok())
+        .unwrap_or_else(|| Duration::minutes(10))
+}
+
+/// Remember that a reboot was initiated at `now`, so it can be verified later
+///
+/// Nothing is saved when the reboot state can't move on to the imminent reboot.
+pub fn mark_initiated(db_pool: &DbPool, now: DateTime<Utc>) -> Result<()> {
+    database::update_reboot_state(db_pool, |state| {
+        state.transition(RebootLifecycle::Imminent)?;
+        state.reboot_initiated_at = Some(now);
+        state.updated_at = now;
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// Check whether the last initiated reboot had happened by `now`
+///
+/// When it didn't within the window, the failure is logged and recorded in the reboot history,
+/// the reminders are rearmed, and unless `dry_run` is set the reboot is forced through
+/// `InitiateSystemShutdownExW`.
+pub fn verify(config: &SystemRebootConfig, db_pool: &DbPool, dry_run: bool, now: DateTime<Utc>) -> Result<Outcome> {
+    let base = match database::get_reboot_state(db_pool)? {
+        Some(state) => state,
+        None => return Ok(Outcome::Pending),
+    };
+    let mut state = base.clone();
+    let initiated_at = match state.reboot_initiated_at {
+        Some(initiated_at) => initiated_at,
+        None => return Ok(Outcome::Pending),
+    };
+
+    let result = outcome(initiated_at, boot_time(now), window(config), now);
+    match result {
+        Outcome::Pending => return Ok(result),
+        Outcome::Rebooted => {
+            // A reboot still required after this one is a new one
+            state.transition(RebootLifecycle::Completed)?;
+            info!("Verified the reboot initiated at {}", initiated_at);
+            crate::metrics::increment("reboot.verified");
+            database::close_reboot_periods(db_pool, now)?;
+        }
+        Outcome::Failed => {
+            // Remind the users again on the next check
+            state.transition(RebootLifecycle::Failed)?;
+            error!("The reboot initiated at {} did not happen", initiated_at);
+            crate::metrics::increment("reboot.verification_failed");
+
+            let mut history = RebootHistory::new(initiated_at, false);
+            history.source = Some(VERIFICATION_SOURCE.to_string());
+            history.reason = Some("Initiated reboot did not restart the computer".to_string());
+            history.computer_name = std::env::var("COMPUTERNAME").ok();
+            database::add_reboot_history(db_pool, &history)?;
+
+            state.next_reminder_time = None;
+            state.reboot_reason = Some("Initiated reboot did not happen".to_string());
+        }
+    }
+
+    state.reboot_initiated_at = None;
+    state.updated_at = now;
+    let state = database::save_reboot_state_merged(db_pool, &base, &state)?;
+
+    if result == Outcome::Failed && state.reboot_required {
+        if dry_run {
+            info!("[dry-run] Would force the reboot");
+        } else if let Err(e) = force_reboot(config) {
+            warn!("Failed to force the reboot: {:#}", e);
+        } else {
+            mark_initiated(db_pool, now)?;
+        }
+    }
+
+    Ok(result)
+}
+
+/// Reboot the computer, closing applications without asking
+fn force_reboot(config: &SystemRebootConfig) -> Result<()> {
+    let mut reboot_config = super::system::RebootConfig::from(config);
+    reboot_config.force_apps_closed = true;
+    super::system::initiate_reboot(&reboot_config)
+}
+
+/// Get the time the computer last started
+///
+/// This is worked out back from `now`, the time of the check, so it's consistent with the other
+/// times of the verification.
+fn boot_time(now: DateTime<Utc>) -> DateTime<Utc> {
+    let uptime = unsafe { windows::Win32::System::SystemInformation::GetTickCount64() };
+    now - Duration::milliseconds(uptime as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outcome() {
+        let now = Utc::now();
+        let initiated_at = now - Duration::minutes(5);
+        let window = Duration::minutes(10);
+
+        assert_eq!(outcome(initiated_at, now - Duration::minutes(1), window, now), Outcome::Rebooted);
+        assert_eq!(outcome(initiated_at, now - Duration::days(1), window, now), Outcome::Pending);
+        assert_eq!(outcome(initiated_at, now - Duration::days(1), window, now + Duration::minutes(6)), Outcome::Failed);
+    }
+
+    #[test]
+    fn test_mark_initiated_refuses_invalid_transition() {
+        let pool = database::init_in_memory().unwrap();
+        let mut state = database::RebootState::new(true, false);
+        state.transition(RebootLifecycle::Completed).unwrap();
+        database::save_reboot_state(&pool, &state).unwrap();
+
+        assert!(mark_initiated(&pool, Utc::now()).is_err());
+        let saved = database::get_reboot_state(&pool).unwrap().unwrap();
+        assert_eq!(saved.lifecycle, RebootLifecycle::Completed);
+        assert_eq!(saved.reboot_initiated_at, None);
+        assert_eq!(saved.version, 1);
+    }
+}
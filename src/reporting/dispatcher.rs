@@ -0,0 +1,304 @@
+use super::{create_sink, ReportEvent, ReportSink};
+use crate::config::{ReportEventKind, ReportSinkConfig};
+use crate::database::{self, DbPool};
+use crate::utils::timespan;
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use log::{info, warn};
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+/// Most events a sink keeps while it can't be reached; the oldest are dropped beyond this
+const MAX_QUEUED_EVENTS: usize = 5000;
+
+/// Longest wait before trying a sink again after sending failed
+const MAX_RETRY_MINUTES: i64 = 60;
+
+/// Fans events out to the configured report sinks
+///
+/// Every sink has its own queue, batching and retries, so a sink that can't be reached doesn't
+/// hold up the others. Detections, state changes and heartbeats are pushed by the service;
+/// notifications and reboots are picked up from the database. How far the recorded events were
+/// sent to each sink is kept in the database, so events recorded while the service was stopped
+/// are still sent afterwards.
+///
+/// The queues live on a worker thread of their own, so a sink that is slow to answer doesn't
+/// hold up the caller; the dispatcher only passes the events and requests on.
+#[derive(Default)]
+pub struct ReportDispatcher {
+    worker: Option<Sender<Command>>,
+    sinks: usize,
+}
+
+/// Request passed to the worker thread of the dispatcher
+enum Command {
+    Configure(Vec<ReportSinkConfig>),
+    Push(ReportEvent),
+    Flush { dry_run: bool },
+}
+
+/// Queues of the configured sinks, kept by the worker thread
+#[derive(Default)]
+struct SinkQueues {
+    queues: Vec<SinkQueue>,
+}
+
+/// Events waiting to be sent to one sink
+struct SinkQueue {
+    config: ReportSinkConfig,
+    sink: Box<dyn ReportSink>,
+    events: VecDeque<ReportEvent>,
+    collected_until: DateTime<Utc>,
+    last_sent: DateTime<Utc>,
+    failures: u32,
+    retry_at: Option<DateTime<Utc>>,
+}
+
+impl SinkQueue {
+    /// Create the queue of a sink, continuing after the recorded events sent to it before
+    ///
+    /// When nothing was sent to the sink yet, only events recorded from now on are sent.
+    fn new(config: &ReportSinkConfig, db: &DbPool, now: DateTime<Utc>) -> Self {
+        let sink = create_sink(&config.sink);
+        let collected_until = database::get_report_cursor(db, &sink.name())
+            .unwrap_or_else(|e| {
+                warn!("Failed to get the report cursor of the {}: {}", sink.name(), e);
+                None
+            })
+            .unwrap_or(now);
+
+        Self {
+            config: config.clone(),
+            sink,
+            events: VecDeque::new(),
+            collected_until,
+            last_sent: now,
+            failures: 0,
+            retry_at: None,
+        }
+    }
+
+    /// Queue an event, if its kind is sent to this sink
+    fn push(&mut self, event: &ReportEvent) {
+        if !self.config.events.contains(&event.event) {
+            return;
+        }
+
+        if self.events.len() >= MAX_QUEUED_EVENTS {
+            warn!("Queue of the {} is full, dropping the oldest event", self.sink.name());
+            self.events.pop_front();
+        }
+        self.events.push_back(event.clone());
+    }
+
+    /// Send the queued events in batches once the batch interval has passed or a batch is full
+    fn flush(&mut self, db: &DbPool, now: DateTime<Utc>, dry_run: bool) -> Result<()> {
+        if self.events.is_empty() || self.retry_at.is_some_and(|retry_at| now < retry_at) {
+            return Ok(());
+        }
+
+        let interval = timespan::parse_timespan(&self.config.batch_interval)
+            .ok()
+            .and_then(|interval| Duration::from_std(interval).ok())
+            .unwrap_or_else(|| Duration::minutes(5));
+        if self.events.len() < self.config.batch_size && now - self.last_sent < interval {
+            return Ok(());
+        }
+
+        while !self.events.is_empty() {
+            let count = self.events.len().min(self.config.batch_size.max(1));
+            let batch: Vec<ReportEvent> = self.events.iter().take(count).cloned().collect();
+
+            if dry_run {
+                info!("[dry-run] Would send {} events to the {}", batch.len(), self.sink.name());
+            } else if let Err(e) = self.sink.send(&batch) {
+                self.failures += 1;
+                let delay = retry_delay(self.failures);
+                self.retry_at = Some(now + delay);
+                return Err(e.context(format!(
+                    "{} events queued, trying again in {} minutes",
+                    self.events.len(),
+                    delay.num_minutes()
+                )));
+            }
+
+            self.events.drain(..count);
+            self.failures = 0;
+            self.retry_at = None;
+            self.last_sent = now;
+
+            // Remember how far the recorded events were sent
+            let recorded = batch
+                .iter()
+                .filter(|event| matches!(event.event, ReportEventKind::Notification | ReportEventKind::Reboot))
+                .map(|event| event.time)
+                .max();
+            if let Some(time) = recorded {
+                if let Err(e) = database::save_report_cursor(db, &self.sink.name(), time) {
+                    warn!("Failed to save the report cursor of the {}: {}", self.sink.name(), e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Get how long to wait before trying a sink again: 2, 4, 8 minutes and so on, up to an hour
+fn retry_delay(failures: u32) -> Duration {
+    Duration::minutes(2i64.saturating_pow(failures.min(6)).min(MAX_RETRY_MINUTES))
+}
+
+impl ReportDispatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start, keep or stop sinks to match the configuration
+    ///
+    /// A sink whose destination didn't change keeps its queue; its other settings are updated.
+    /// The worker thread is started with the first sink.
+    pub fn configure(&mut self, sinks: &[ReportSinkConfig], db: &DbPool) {
+        if self.worker.is_none() && !sinks.is_empty() {
+            let (sender, receiver) = mpsc::channel();
+            let db = db.clone();
+            match thread::Builder::new().name("reports".to_string()).spawn(move || run_worker(receiver, db)) {
+                Ok(_) => self.worker = Some(sender),
+                Err(e) => {
+                    warn!("Failed to start the report thread: {}", e);
+                    return;
+                }
+            }
+        }
+        self.sinks = sinks.len();
+        self.send(Command::Configure(sinks.to_vec()));
+    }
+
+    /// Whether any sink is configured
+    pub fn is_empty(&self) -> bool {
+        self.sinks == 0
+    }
+
+    /// Queue an event for the sinks it's sent to
+    pub fn push(&mut self, event: ReportEvent) {
+        if !self.is_empty() {
+            self.send(Command::Push(event));
+        }
+    }
+
+    /// Queue the notifications and reboots recorded since they were last collected, and send the
+    /// queued events of every sink that has a batch due
+    ///
+    /// Returns right away; the events are sent by the worker thread. Failures are logged per sink,
+    /// and don't keep the other sinks from sending.
+    pub fn flush(&mut self, dry_run: bool) {
+        if !self.is_empty() {
+            self.send(Command::Flush { dry_run });
+        }
+    }
+
+    /// Pass a request on to the worker thread
+    fn send(&mut self, command: Command) {
+        if let Some(worker) = &self.worker {
+            if worker.send(command).is_err() {
+                warn!("The report thread stopped, no longer sending events");
+                self.worker = None;
+                self.sinks = 0;
+            }
+        }
+    }
+}
+
+/// Carry out the requests of a dispatcher until it's dropped
+fn run_worker(receiver: Receiver<Command>, db: DbPool) {
+    let mut queues = SinkQueues::default();
+    for command in receiver {
+        match command {
+            Command::Configure(sinks) => queues.configure(&sinks, &db),
+            Command::Push(event) => queues.push(event),
+            Command::Flush { dry_run } => {
+                if let Err(e) = queues.collect(&db) {
+                    warn!("Failed to collect report events: {:#}", e);
+                }
+                queues.flush(&db, dry_run);
+            }
+        }
+    }
+}
+
+impl SinkQueues {
+    /// Start, keep or stop sinks to match the configuration
+    fn configure(&mut self, sinks: &[ReportSinkConfig], db: &DbPool) {
+        let now = Utc::now();
+        let mut previous = std::mem::take(&mut self.queues);
+
+        for config in sinks {
+            let sink = create_sink(&config.sink);
+            match previous.iter().position(|queue| queue.sink.name() == sink.name()) {
+                Some(index) => {
+                    let mut queue = previous.swap_remove(index);
+                    queue.config = config.clone();
+                    queue.sink = sink;
+                    self.queues.push(queue);
+                }
+                None => {
+                    info!("Sending events to the {}", sink.name());
+                    self.queues.push(SinkQueue::new(config, db, now));
+                }
+            }
+        }
+
+        for queue in previous {
+            info!("No longer sending events to the {}", queue.sink.name());
+        }
+    }
+
+    /// Queue an event for the sinks it's sent to
+    fn push(&mut self, event: ReportEvent) {
+        for queue in &mut self.queues {
+            queue.push(&event);
+        }
+    }
+
+    /// Queue the notifications and reboots recorded since they were last collected
+    fn collect(&mut self, db: &DbPool) -> Result<()> {
+        let Some(after) = self.queues.iter().map(|queue| queue.collected_until).min() else {
+            return Ok(());
+        };
+
+        let events = super::recorded_events(db, after)?;
+        for queue in &mut self.queues {
+            for event in events.iter().filter(|event| event.time > queue.collected_until) {
+                queue.push(event);
+            }
+            if let Some(last) = events.last() {
+                queue.collected_until = queue.collected_until.max(last.time);
+            }
+        }
+        Ok(())
+    }
+
+    /// Send the queued events of every sink that has a batch due
+    fn flush(&mut self, db: &DbPool, dry_run: bool) {
+        let now = Utc::now();
+        for queue in &mut self.queues {
+            if let Err(e) = queue.flush(db, now, dry_run) {
+                warn!("Failed to send events to the {}: {:#}", queue.sink.name(), e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(retry_delay(1), Duration::minutes(2));
+        assert_eq!(retry_delay(3), Duration::minutes(8));
+        assert_eq!(retry_delay(6), Duration::minutes(60));
+        assert_eq!(retry_delay(40), Duration::minutes(60));
+    }
+}
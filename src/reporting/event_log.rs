@@ -0,0 +1,72 @@
+use super::{ReportEvent, ReportSink};
+use crate::config::ReportEventKind;
+use crate::utils::to_wide;
+use anyhow::{Context, Result};
+use log::debug;
+use windows::core::PCWSTR;
+use windows::Win32::System::EventLog::{
+    DeregisterEventSource, RegisterEventSourceW, ReportEventW, EVENTLOG_INFORMATION_TYPE, EVENTLOG_WARNING_TYPE,
+};
+
+/// Event source the events are written with, registered by the installer
+const EVENT_SOURCE: &str = "RebootReminder";
+
+/// Writes events to the Application event log, where event forwarding and SIEM agents pick them up
+///
+/// Each event is one entry holding the event as JSON, with an event ID per kind of event.
+pub struct EventLogSink;
+
+/// Get the event ID entries of a kind of event are written with
+fn event_id(kind: ReportEventKind) -> u32 {
+    match kind {
+        ReportEventKind::Detection => 1000,
+        ReportEventKind::Notification => 1001,
+        ReportEventKind::Reboot => 1002,
+        ReportEventKind::StateChange => 1003,
+        ReportEventKind::Heartbeat => 1004,
+    }
+}
+
+impl ReportSink for EventLogSink {
+    fn name(&self) -> String {
+        "event log".to_string()
+    }
+
+    fn send(&mut self, events: &[ReportEvent]) -> Result<()> {
+        let source = to_wide(EVENT_SOURCE);
+
+        unsafe {
+            let handle = RegisterEventSourceW(PCWSTR::null(), PCWSTR::from_raw(source.as_ptr()))
+                .context("Failed to register the event source")?;
+
+            let result = events.iter().try_for_each(|event| {
+                let message = serde_json::to_string_pretty(event).context("Failed to serialize event")?;
+                let message = to_wide(&message);
+                // A pending reboot shows as a warning, except in the result of every check
+                let event_type = if event.reboot_required == Some(true) && event.event != ReportEventKind::Detection {
+                    EVENTLOG_WARNING_TYPE
+                } else {
+                    EVENTLOG_INFORMATION_TYPE
+                };
+
+                ReportEventW(
+                    handle,
+                    event_type,
+                    0,
+                    event_id(event.event),
+                    None,
+                    0,
+                    Some(&[PCWSTR::from_raw(message.as_ptr())]),
+                    None,
+                )
+                .context("Failed to write to the event log")
+            });
+
+            let _ = DeregisterEventSource(handle);
+            result?;
+        }
+
+        debug!("Wrote {} events to the event log", events.len());
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+use super::{ReportEvent, ReportSink};
+use anyhow::{Context, Result};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Appends events to a file, one JSON object per line, for log shippers to pick up
+pub struct FileSink {
+    path: PathBuf,
+}
+
+impl FileSink {
+    pub fn new(path: &str) -> Self {
+        Self { path: PathBuf::from(path) }
+    }
+}
+
+impl ReportSink for FileSink {
+    fn name(&self) -> String {
+        format!("file {}", self.path.display())
+    }
+
+    fn send(&mut self, events: &[ReportEvent]) -> Result<()> {
+        let mut lines = String::new();
+        for event in events {
+            lines.push_str(&serde_json::to_string(event).context("Failed to serialize event")?);
+            lines.push('\n');
+        }
+
+        if let Some(parent) = self.path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).with_context(|| format!("Failed to create directory {}", parent.display()))?;
+        }
+
+        // One write per batch, so a log shipper never reads half a batch
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(lines.as_bytes()))
+            .with_context(|| format!("Failed to write events to {}", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ReportEventKind;
+    use chrono::Utc;
+
+    #[test]
+    fn test_send() {
+        let path = std::env::temp_dir().join(format!("report-sink-{}", uuid::Uuid::new_v4())).join("events.ndjson");
+        let mut sink = FileSink::new(path.to_str().unwrap());
+        let event = ReportEvent::detection(Utc::now(), true, &[]);
+        sink.send(&[event.clone(), event]).unwrap();
+        sink.send(&[ReportEvent::detection(Utc::now(), false, &[])]).unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = content.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["event"], ReportEventKind::Detection.to_string());
+        assert_eq!(lines[2]["rebootRequired"], false);
+
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -0,0 +1,224 @@
+use crate::config::IntuneConfig;
+use crate::database::RebootState;
+use crate::utils::{http, timespan};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use log::{debug, info};
+use reqwest::blocking::Client;
+use serde_json::{json, Value};
+use std::process::Command;
+
+/// Microsoft Graph endpoint
+const GRAPH_URL: &str = "https://graph.microsoft.com/v1.0";
+
+/// Microsoft identity platform endpoint the access token is requested from
+const LOGIN_URL: &str = "https://login.microsoftonline.com";
+
+/// How long each request may take
+const REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// How long to wait before trying again after reporting failed
+pub const RETRY_MINUTES: i64 = 15;
+
+/// Reboot status as reported to Intune
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RebootStatus {
+    /// No reboot is required
+    Ok,
+
+    /// A reboot is required
+    Pending,
+
+    /// A reboot has been required for too long, or its deadline has passed
+    Overdue,
+}
+
+impl RebootStatus {
+    /// Get the status as written to the extension attribute
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Pending => "pending",
+            Self::Overdue => "overdue",
+        }
+    }
+}
+
+/// Get the reboot status of a state
+pub fn status(config: &IntuneConfig, state: &RebootState, deadline: Option<DateTime<Utc>>, now: DateTime<Utc>) -> RebootStatus {
+    if !state.reboot_required {
+        return RebootStatus::Ok;
+    }
+
+    let past_deadline = deadline.is_some_and(|deadline| now >= deadline);
+    let overdue_after = config
+        .overdue_after
+        .as_deref()
+        .and_then(|overdue_after| timespan::parse_timespan(overdue_after).ok())
+        .and_then(|overdue_after| Duration::from_std(overdue_after).ok());
+    let pending_too_long = match (state.reboot_required_since, overdue_after) {
+        (Some(since), Some(overdue_after)) => now - since >= overdue_after,
+        _ => false,
+    };
+
+    if past_deadline || pending_too_long {
+        RebootStatus::Overdue
+    } else {
+        RebootStatus::Pending
+    }
+}
+
+/// Get the value written to the extension attribute, e.g.
+/// `overdue;since=2024-03-04T08:00:00Z;postponed=3`
+///
+/// The status comes first, so dynamic groups can match it with `-startsWith`.
+pub fn attribute_value(status: RebootStatus, state: &RebootState) -> String {
+    match (status, state.reboot_required_since) {
+        (RebootStatus::Ok, _) | (_, None) => status.as_str().to_string(),
+        (_, Some(since)) => format!(
+            "{};since={};postponed={}",
+            status.as_str(),
+            since.format("%Y-%m-%dT%H:%M:%SZ"),
+            state.postpone_count
+        ),
+    }
+}
+
+/// Write the status to the extension attribute of this device in Entra ID
+pub fn report(config: &IntuneConfig, value: &str) -> Result<()> {
+    let client = http::client(REQUEST_TIMEOUT)?;
+    let token = access_token(&client, config)?;
+    let device = find_device(&client, &token)?;
+
+    let attribute = format!("extensionAttribute{}", config.extension_attribute);
+    let response = client
+        .patch(format!("{}/devices/{}", GRAPH_URL, device))
+        .bearer_auth(&token)
+        .json(&json!({ "extensionAttributes": { attribute.as_str(): value } }))
+        .send()
+        .context("Failed to update the device in Microsoft Graph")?;
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!(
+            "Microsoft Graph returned HTTP {} updating device {}: {}",
+            response.status(),
+            device,
+            response.text().unwrap_or_default()
+        ));
+    }
+
+    info!("Reported reboot status '{}' to Intune in {}", value, attribute);
+    Ok(())
+}
+
+/// Get an access token for Microsoft Graph with the client credentials of the app registration
+fn access_token(client: &Client, config: &IntuneConfig) -> Result<String> {
+    debug!("Requesting an access token for client {}", config.client_id);
+    let response = client
+        .post(format!("{}/{}/oauth2/v2.0/token", LOGIN_URL, config.tenant_id))
+        .form(&[
+            ("grant_type", "client_credentials"),
+            ("client_id", config.client_id.as_str()),
+            ("client_secret", config.client_secret.expose()),
+            ("scope", "https://graph.microsoft.com/.default"),
+        ])
+        .send()
+        .context("Failed to request an access token")?;
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!(
+            "Token request returned HTTP {}: {}",
+            response.status(),
+            response.text().unwrap_or_default()
+        ));
+    }
+
+    let body: Value = response.json().context("Invalid token response")?;
+    body["access_token"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow::anyhow!("Token response has no access token"))
+}
+
+/// Find the object ID of this device in Entra ID
+///
+/// The device is looked up by the device ID `dsregcmd /status` reports, or by the computer name
+/// when the device isn't joined to Entra ID.
+fn find_device(client: &Client, token: &str) -> Result<String> {
+    let filter = match query_device_id() {
+        Some(device_id) => format!("deviceId eq '{}'", device_id),
+        None => {
+            let computer_name = std::env::var("COMPUTERNAME").context("Failed to get the computer name")?;
+            format!("displayName eq '{}'", computer_name.replace('\'', "''"))
+        }
+    };
+    debug!("Looking up the device in Microsoft Graph: {}", filter);
+
+    let response = client
+        .get(format!("{}/devices", GRAPH_URL))
+        .bearer_auth(token)
+        .query(&[("$filter", filter.as_str()), ("$select", "id")])
+        .send()
+        .context("Failed to look up the device in Microsoft Graph")?;
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!("Microsoft Graph returned HTTP {} looking up the device", response.status()));
+    }
+
+    let body: Value = response.json().context("Invalid device lookup response")?;
+    match body["value"].as_array().map(Vec::as_slice) {
+        Some([device]) => device["id"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| anyhow::anyhow!("Device lookup response has no object ID")),
+        Some([]) | None => Err(anyhow::anyhow!("No device in Entra ID matches {}", filter)),
+        Some(devices) => Err(anyhow::anyhow!("{} devices in Entra ID match {}", devices.len(), filter)),
+    }
+}
+
+/// Get the Entra ID device ID of this computer from `dsregcmd /status`
+fn query_device_id() -> Option<String> {
+    let output = Command::new("dsregcmd").arg("/status").output().ok()?;
+    parse_device_id(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Find the device ID in the output of `dsregcmd /status`
+fn parse_device_id(output: &str) -> Option<String> {
+    output.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        let value = value.trim();
+        (name.trim() == "DeviceId" && !value.is_empty()).then(|| value.to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_status() {
+        let config: IntuneConfig = serde_json::from_value(json!({
+            "tenantId": "contoso.onmicrosoft.com",
+            "clientId": "00000000-0000-0000-0000-000000000000",
+            "clientSecret": "secret",
+            "overdueAfter": "72h"
+        }))
+        .unwrap();
+        let now = Utc.with_ymd_and_hms(2024, 3, 10, 12, 0, 0).unwrap();
+        let mut state = RebootState::new(false, false);
+        assert_eq!(status(&config, &state, None, now), RebootStatus::Ok);
+        assert_eq!(attribute_value(RebootStatus::Ok, &state), "ok");
+
+        state.reboot_required = true;
+        state.reboot_required_since = Some(now - Duration::hours(24));
+        state.postpone_count = 2;
+        assert_eq!(status(&config, &state, None, now), RebootStatus::Pending);
+        assert_eq!(status(&config, &state, Some(now), now), RebootStatus::Overdue);
+
+        state.reboot_required_since = Some(Utc.with_ymd_and_hms(2024, 3, 4, 8, 0, 0).unwrap());
+        let status = status(&config, &state, None, now);
+        assert_eq!(attribute_value(status, &state), "overdue;since=2024-03-04T08:00:00Z;postponed=2");
+
+        let output = "+------+\n| Device Details |\n\n             DeviceId : 3f6a1c2e-0000-4000-8000-000000000001\n           Thumbprint : ABC\n";
+        assert_eq!(parse_device_id(output).as_deref(), Some("3f6a1c2e-0000-4000-8000-000000000001"));
+        assert_eq!(parse_device_id("AzureAdJoined : NO\n"), None);
+    }
+}
@@ -0,0 +1,97 @@
+use super::{ReportEvent, ReportSink};
+use crate::utils::http;
+use crate::utils::secrets::Secret;
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use log::debug;
+use sha2::Sha256;
+
+/// Version of the HTTP Data Collector API
+const API_VERSION: &str = "2016-04-01";
+
+/// How long a request may take
+const REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Sends events to an Azure Log Analytics workspace through the HTTP Data Collector API
+pub struct LogAnalyticsSink {
+    workspace_id: String,
+    shared_key: Secret,
+    log_type: String,
+}
+
+impl LogAnalyticsSink {
+    pub fn new(workspace_id: &str, shared_key: Secret, log_type: &str) -> Self {
+        Self {
+            workspace_id: workspace_id.to_string(),
+            shared_key,
+            log_type: log_type.to_string(),
+        }
+    }
+}
+
+impl ReportSink for LogAnalyticsSink {
+    fn name(&self) -> String {
+        format!("Log Analytics workspace {}", self.workspace_id)
+    }
+
+    /// Post a batch of events to the HTTP Data Collector API
+    fn send(&mut self, events: &[ReportEvent]) -> Result<()> {
+        let body = serde_json::to_string(events).context("Failed to serialize events")?;
+        let date = Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+        let signature = signature(self.shared_key.expose(), &date, body.len())?;
+        let url = format!(
+            "https://{}.ods.opinsights.azure.com/api/logs?api-version={}",
+            self.workspace_id, API_VERSION
+        );
+
+        debug!("Sending {} events to Log Analytics workspace {}", events.len(), self.workspace_id);
+        let response = http::client(REQUEST_TIMEOUT)?
+            .post(&url)
+            .header("Content-Type", "application/json")
+            .header("Authorization", format!("SharedKey {}:{}", self.workspace_id, signature))
+            .header("Log-Type", &self.log_type)
+            .header("x-ms-date", &date)
+            .header("time-generated-field", "time")
+            .body(body)
+            .send()
+            .context("Failed to send events to Log Analytics")?;
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!(
+                "Log Analytics returned HTTP {}: {}",
+                response.status(),
+                response.text().unwrap_or_default()
+            ));
+        }
+
+        debug!("Sent {} events to Log Analytics", events.len());
+        Ok(())
+    }
+}
+
+/// Sign a request with the workspace key, as the HTTP Data Collector API expects
+fn signature(shared_key: &str, date: &str, content_length: usize) -> Result<String> {
+    let key = BASE64.decode(shared_key.trim()).context("The Log Analytics shared key is not valid base64")?;
+    let message = format!("POST\n{}\napplication/json\nx-ms-date:{}\n/api/logs", content_length, date);
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(&key).context("Invalid Log Analytics shared key")?;
+    mac.update(message.as_bytes());
+    Ok(BASE64.encode(mac.finalize().into_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature() {
+        let key = BASE64.encode(b"workspace key");
+        let first = signature(&key, "Mon, 04 Mar 2024 08:00:00 GMT", 42).unwrap();
+        assert_eq!(BASE64.decode(&first).unwrap().len(), 32);
+        assert_eq!(first, signature(&key, "Mon, 04 Mar 2024 08:00:00 GMT", 42).unwrap());
+        assert_ne!(first, signature(&key, "Mon, 04 Mar 2024 08:00:00 GMT", 43).unwrap());
+        assert!(signature("not base64!", "Mon, 04 Mar 2024 08:00:00 GMT", 42).is_err());
+    }
+}
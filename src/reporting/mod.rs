@@ -0,0 +1,168 @@
+pub mod dispatcher;
+pub mod event_log;
+pub mod file;
+pub mod intune;
+pub mod log_analytics;
+pub mod webhook;
+
+use crate::config::{HookEvent, ReportEventKind, ReportSinkKind};
+use crate::database::{self, DbPool, Heartbeat, Notification, RebootHistory, RebootSource, RebootState};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Destination reporting events are sent to
+///
+/// The dispatcher queues, batches and retries the events; a sink only sends one batch.
+pub trait ReportSink: Send {
+    /// Name of the sink, used in the logs and to remember how far recorded events were sent
+    fn name(&self) -> String;
+
+    /// Send a batch of events, oldest first
+    fn send(&mut self, events: &[ReportEvent]) -> Result<()>;
+}
+
+/// Create the sink for a report sink configuration
+pub fn create_sink(kind: &ReportSinkKind) -> Box<dyn ReportSink> {
+    match kind {
+        ReportSinkKind::Webhook { url, bearer_token } => Box::new(webhook::WebhookSink::new(url, bearer_token.clone())),
+        ReportSinkKind::EventLog => Box::new(event_log::EventLogSink),
+        ReportSinkKind::LogAnalytics { workspace_id, shared_key, log_type } => {
+            Box::new(log_analytics::LogAnalyticsSink::new(workspace_id, shared_key.clone(), log_type))
+        }
+        ReportSinkKind::File { path } => Box::new(file::FileSink::new(path)),
+    }
+}
+
+/// Structured event sent to reporting sinks
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportEvent {
+    /// Time of the event
+    pub time: DateTime<Utc>,
+
+    /// Computer the event happened on
+    pub computer_name: String,
+
+    /// What the event is about
+    pub event: ReportEventKind,
+
+    /// Whether a reboot is required, for detections, state changes and heartbeats
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reboot_required: Option<bool>,
+
+    /// Comma-separated names of the reboot sources, for detections, state changes and heartbeats
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sources: Option<String>,
+
+    /// User who was notified or rebooted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_name: Option<String>,
+
+    /// Notification type, what started the reboot, or `rebootRequired` or `rebootCleared` for state changes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action: Option<String>,
+
+    /// Delivery status of a notification, `succeeded` or `failed` for reboots, or the lifecycle for heartbeats
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+
+    /// Notification message, or reason of the reboot
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<String>,
+
+    /// Version of the service, for heartbeats
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+
+    /// Hash of the configuration in effect, for heartbeats
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config_hash: Option<String>,
+}
+
+impl ReportEvent {
+    fn new(time: DateTime<Utc>, event: ReportEventKind) -> Self {
+        Self {
+            time,
+            computer_name: std::env::var("COMPUTERNAME").unwrap_or_default(),
+            event,
+            reboot_required: None,
+            sources: None,
+            user_name: None,
+            action: None,
+            status: None,
+            details: None,
+            version: None,
+            config_hash: None,
+        }
+    }
+
+    /// Create the event for the result of a reboot check
+    pub fn detection(time: DateTime<Utc>, reboot_required: bool, sources: &[RebootSource]) -> Self {
+        let names: Vec<&str> = sources.iter().map(|source| source.name.as_str()).collect();
+        Self {
+            reboot_required: Some(reboot_required),
+            sources: Some(names.join(",")),
+            ..Self::new(time, ReportEventKind::Detection)
+        }
+    }
+
+    /// Create the event for a reboot becoming required, or no longer being required
+    pub fn state_change(time: DateTime<Utc>, change: HookEvent, state: &RebootState) -> Self {
+        Self {
+            event: ReportEventKind::StateChange,
+            action: Some(change.to_string()),
+            ..Self::detection(time, state.reboot_required, &state.sources)
+        }
+    }
+
+    /// Create the event for a heartbeat, showing the service is running
+    pub fn heartbeat(heartbeat: &Heartbeat) -> Self {
+        Self {
+            reboot_required: Some(heartbeat.reboot_required),
+            sources: Some(heartbeat.sources.clone()),
+            status: Some(heartbeat.lifecycle.as_str().to_string()),
+            version: Some(heartbeat.version.clone()),
+            config_hash: Some(heartbeat.config_hash.clone()),
+            ..Self::new(heartbeat.timestamp, ReportEventKind::Heartbeat)
+        }
+    }
+
+    /// Create the event for a recorded notification
+    pub fn notification(notification: &Notification) -> Self {
+        Self {
+            user_name: notification.user_name.clone(),
+            action: Some(notification.notification_type.clone()),
+            status: Some(notification.delivery_status.as_str().to_string()),
+            details: Some(notification.message.clone()),
+            ..Self::new(notification.timestamp, ReportEventKind::Notification)
+        }
+    }
+
+    /// Create the event for a recorded reboot
+    pub fn reboot(reboot: &RebootHistory) -> Self {
+        let mut event = Self {
+            user_name: reboot.user_name.clone(),
+            action: reboot.source.clone(),
+            status: Some(if reboot.success { "succeeded" } else { "failed" }.to_string()),
+            details: reboot.reason.clone(),
+            ..Self::new(reboot.reboot_time, ReportEventKind::Reboot)
+        };
+        if let Some(computer_name) = &reboot.computer_name {
+            event.computer_name = computer_name.clone();
+        }
+        event
+    }
+}
+
+/// Get the notifications and reboots recorded after a time, oldest first
+pub fn recorded_events(db: &DbPool, after: DateTime<Utc>) -> Result<Vec<ReportEvent>> {
+    let mut events: Vec<ReportEvent> = database::get_notifications_after(db, after)?
+        .iter()
+        .map(ReportEvent::notification)
+        .collect();
+    events.extend(database::get_reboot_history_after(db, after)?.iter().map(ReportEvent::reboot));
+
+    events.sort_by_key(|event| event.time);
+    Ok(events)
+}
@@ -0,0 +1,68 @@
+use super::{ReportEvent, ReportSink};
+use crate::utils::http;
+use crate::utils::secrets::Secret;
+use anyhow::{Context, Result};
+use log::debug;
+
+/// How long a request may take
+const REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Posts events as a JSON array to a URL
+pub struct WebhookSink {
+    url: String,
+    bearer_token: Option<Secret>,
+}
+
+impl WebhookSink {
+    pub fn new(url: &str, bearer_token: Option<Secret>) -> Self {
+        Self {
+            url: url.to_string(),
+            bearer_token,
+        }
+    }
+}
+
+impl ReportSink for WebhookSink {
+    /// Name the webhook by its host and a hash of its URL, since webhook URLs often carry a token
+    /// in the path or query
+    fn name(&self) -> String {
+        let host = url::Url::parse(&self.url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_else(|| "unknown host".to_string());
+        let hash = crate::update::sha256_hex(self.url.as_bytes());
+        format!("webhook {} ({})", host, &hash[..12])
+    }
+
+    fn send(&mut self, events: &[ReportEvent]) -> Result<()> {
+        let name = self.name();
+        debug!("Posting {} events to the {}", events.len(), name);
+        let mut request = http::client(REQUEST_TIMEOUT)?.post(&self.url).json(events);
+        if let Some(token) = &self.bearer_token {
+            request = request.bearer_auth(token.expose());
+        }
+
+        let response = request
+            .send()
+            .map_err(|e| e.without_url())
+            .with_context(|| format!("Failed to post events to the {}", name))?;
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("The {} returned HTTP {}", name, response.status()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_name_hides_the_url() {
+        let url = "https://hooks.contoso.com/services/T000/B000/s3cr3tt0k3n";
+        let name = WebhookSink::new(url, None).name();
+        assert!(name.starts_with("webhook hooks.contoso.com ("));
+        assert!(!name.contains("s3cr3tt0k3n"));
+        assert_ne!(name, WebhookSink::new("https://hooks.contoso.com/services/T000/B000/other", None).name());
+    }
+}
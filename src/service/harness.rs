@@ -0,0 +1,384 @@
+//! Test harness running the service loop against simulated detection, notifications and time
+//!
+//! The harness drives the same check cycle as the reboot check thread, with a detector reading a
+//! fake registry, an in-memory database and a manual clock that jumps ahead instead of sleeping, so
+//! days of reminders run in moments. Notifications are recorded the way dry-run mode records them and
+//! collected with the simulated time they were shown at. Built for this crate's tests, and for
+//! other crates with the `test-harness` feature.
+
+use super::shutdown::ShutdownSignal;
+use super::CheckCycle;
+use crate::config::{Config, NotificationSeverity};
+use crate::database::{self, DbPool, RebootState, UserSession};
+use crate::impersonation::Impersonator;
+use crate::notification::{NotificationManager, POSTPONE_ACTION_PREFIX};
+use crate::reboot::detector::RebootDetector;
+use crate::reboot::providers::mock::{MockFileSystem, MockWmi};
+use crate::reboot::providers::RegistryProvider;
+use crate::utils::clock::{Clock, ManualClock};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+use windows_service::service::ServiceState;
+
+/// Key Windows Update keeps while it needs a reboot, which the harness sets to require one
+pub const WINDOWS_UPDATE_REBOOT_REQUIRED_KEY: &str =
+    "SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\WindowsUpdate\\Auto Update\\RebootRequired";
+
+/// Registry whose keys can be added and removed while the service loop runs
+#[derive(Debug, Default)]
+pub struct FakeRegistry {
+    keys: Mutex<HashSet<String>>,
+}
+
+impl FakeRegistry {
+    /// Add or remove a key
+    pub fn set_key(&self, key_path: &str, present: bool) {
+        let mut keys = self.keys.lock().unwrap_or_else(|e| e.into_inner());
+        if present {
+            keys.insert(key_path.to_string());
+        } else {
+            keys.remove(key_path);
+        }
+    }
+}
+
+impl RegistryProvider for FakeRegistry {
+    fn key_exists(&self, key_path: &str) -> Result<bool> {
+        Ok(self.keys.lock().unwrap_or_else(|e| e.into_inner()).contains(key_path))
+    }
+
+    fn get_string_value(&self, _key_path: &str, _value_name: &str) -> Result<Option<String>> {
+        Ok(None)
+    }
+}
+
+/// Notification the service loop showed, at the simulated time it was shown
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShownNotification {
+    /// Simulated time of the iteration that showed it
+    pub at: DateTime<Utc>,
+
+    /// Notification type, e.g. `reboot_required`
+    pub notification_type: String,
+
+    /// Severity of the timeframe it was shown in
+    pub severity: NotificationSeverity,
+}
+
+/// Service loop running against simulated detection, notifications and time
+pub struct ServiceHarness {
+    config: Config,
+    db_pool: DbPool,
+    clock: ManualClock,
+    registry: Arc<FakeRegistry>,
+    notification_manager: Arc<Mutex<NotificationManager>>,
+    cycle: CheckCycle,
+    shutdown: ShutdownSignal,
+    metrics_path: PathBuf,
+    /// Service states reported so far, as the service control manager would have seen them
+    service_states: Vec<ServiceState>,
+    /// Notifications already collected, by ID
+    collected: HashSet<Uuid>,
+    shown: Vec<ShownNotification>,
+}
+
+impl ServiceHarness {
+    /// Start the service loop with a configuration, as the service would after starting
+    ///
+    /// Dry-run mode is forced so notifications are recorded instead of shown and nothing is
+    /// rebooted, and nothing is written to the registry.
+    pub fn start(mut config: Config) -> Result<Self> {
+        config.service.dry_run = true;
+        config.service.registry_status = false;
+        let mut service_states = vec![ServiceState::StartPending];
+
+        let db_pool = database::init_in_memory()?;
+        let clock = ManualClock::new(Utc::now());
+        let registry = Arc::new(FakeRegistry::default());
+
+        let mut notification_manager = NotificationManager::new(&config, db_pool.clone(), Arc::new(Impersonator::new()));
+        notification_manager.set_clock(Arc::new(clock.clone()));
+        notification_manager
+            .initialize()
+            .context("Failed to initialize notification manager")?;
+        let notification_manager = Arc::new(Mutex::new(notification_manager));
+
+        let detector = RebootDetector::with_providers(
+            &config.reboot,
+            registry.clone(),
+            Arc::new(MockWmi::default()),
+            Arc::new(MockFileSystem::default()),
+        );
+        let metrics_path = std::env::temp_dir().join(format!("rebootreminder-harness-{}.json", Uuid::new_v4()));
+        let cycle = CheckCycle::new(
+            db_pool.clone(),
+            notification_manager.clone(),
+            detector,
+            None,
+            metrics_path.clone(),
+            Arc::new(clock.clone()),
+        );
+        service_states.push(ServiceState::Running);
+
+        Ok(Self {
+            config,
+            db_pool,
+            clock,
+            registry,
+            notification_manager,
+            cycle,
+            shutdown: ShutdownSignal::default(),
+            metrics_path,
+            service_states,
+            collected: HashSet::new(),
+            shown: Vec::new(),
+        })
+    }
+
+    /// Get the simulated time
+    pub fn now(&self) -> DateTime<Utc> {
+        self.clock.now()
+    }
+
+    /// Get the clock the service loop and notification manager read, e.g. to jump ahead between
+    /// iterations
+    pub fn clock(&self) -> &ManualClock {
+        &self.clock
+    }
+
+    /// Get the database the service loop uses
+    pub fn db_pool(&self) -> &DbPool {
+        &self.db_pool
+    }
+
+    /// Get the registry the detector reads
+    pub fn registry(&self) -> &FakeRegistry {
+        &self.registry
+    }
+
+    /// Replace the configuration, as a configuration refresh would
+    pub fn set_config(&mut self, mut config: Config) {
+        config.service.dry_run = true;
+        config.service.registry_status = false;
+        self.config = config;
+    }
+
+    /// Make Windows Update require a reboot, or stop requiring one as if the computer had rebooted
+    pub fn require_reboot(&self, required: bool) {
+        self.registry.set_key(WINDOWS_UPDATE_REBOOT_REQUIRED_KEY, required);
+    }
+
+    /// Run one iteration of the reboot check thread, then move the clock on by the time the
+    /// thread would sleep
+    pub fn step(&mut self) -> Result<()> {
+        let now = self.clock.now();
+        self.cycle.run(&self.config, false);
+        self.collect_notifications(now)?;
+        self.clock.advance(Duration::from_std(self.cycle.sleep_time(&self.config))?);
+        Ok(())
+    }
+
+    /// Run iterations until a duration of simulated time has passed or the service is stopped
+    pub fn run_for(&mut self, duration: Duration) -> Result<()> {
+        let end = self.clock.now() + duration;
+        while self.clock.now() < end && !self.shutdown.is_requested() {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    /// Postpone reminders for a timespan (e.g., `90m`), as the postpone link of the last reminder
+    /// would
+    pub fn postpone(&self, duration: &str) -> Result<()> {
+        let reminder = database::get_last_notification(&self.db_pool, "dry_run:reboot_required")?
+            .ok_or_else(|| anyhow::anyhow!("No reminder has been shown yet"))?;
+        let session = UserSession::new("harness", "1", false, true);
+        self.notification_manager
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire lock on notification manager: {}", e))?
+            .record_interaction(reminder.id, &format!("{}{}", POSTPONE_ACTION_PREFIX, duration), &session)
+    }
+
+    /// Start the loop over, as the supervisor does when the reboot check thread panicked
+    pub fn restart(&mut self) {
+        self.cycle.restart();
+    }
+
+    /// Stop the service, so `run_for` returns right away
+    pub fn stop(&mut self) {
+        self.service_states.push(ServiceState::StopPending);
+        self.shutdown.request();
+        self.service_states.push(ServiceState::Stopped);
+    }
+
+    /// Get the service states reported so far
+    pub fn service_states(&self) -> &[ServiceState] {
+        &self.service_states
+    }
+
+    /// Get the saved reboot state
+    pub fn reboot_state(&self) -> Result<Option<RebootState>> {
+        database::get_reboot_state(&self.db_pool)
+    }
+
+    /// Get the notifications shown so far, oldest first
+    pub fn shown_notifications(&self) -> &[ShownNotification] {
+        &self.shown
+    }
+
+    /// Collect the notifications the last iteration recorded, at the time it ran
+    fn collect_notifications(&mut self, at: DateTime<Utc>) -> Result<()> {
+        let severity = self
+            .notification_manager
+            .lock()
+            .map(|manager| manager.severity())
+            .unwrap_or_default();
+
+        let mut notifications = database::get_notifications(&self.db_pool, None)?;
+        notifications.reverse();
+        for notification in notifications {
+            if !self.collected.insert(notification.id) {
+                continue;
+            }
+            let notification_type = notification.notification_type;
+            self.shown.push(ShownNotification {
+                at,
+                notification_type: notification_type.strip_prefix("dry_run:").unwrap_or(&notification_type).to_string(),
+                severity,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ServiceHarness {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.metrics_path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TimeframeConfig;
+    use crate::database::RebootLifecycle;
+
+    fn timeframe(min_hours: u32, max_hours: Option<u32>, interval: &str, severity: NotificationSeverity) -> TimeframeConfig {
+        TimeframeConfig {
+            min_hours: Some(min_hours),
+            max_hours,
+            min_timespan: None,
+            max_timespan: None,
+            reminder_interval_hours: None,
+            reminder_interval_minutes: None,
+            reminder_interval: Some(interval.to_string()),
+            deferrals: vec!["1h".into(), "4h".into()],
+            severity,
+            sound: None,
+            priority: Default::default(),
+        }
+    }
+
+    /// Configuration reminding hourly for two hours, then every 30 minutes, then every 10
+    fn config() -> Config {
+        let mut config = crate::config::default();
+        config.notification.quiet_hours.enabled = false;
+        config.notification.respect_user_availability = false;
+        config.notification.post_resume_grace = None;
+        config.reboot.timeframes = vec![
+            timeframe(0, Some(2), "1h", NotificationSeverity::Normal),
+            timeframe(2, Some(4), "30m", NotificationSeverity::High),
+            timeframe(4, None, "10m", NotificationSeverity::Critical),
+        ];
+        config
+    }
+
+    fn reminders(harness: &ServiceHarness) -> Vec<&ShownNotification> {
+        harness
+            .shown_notifications()
+            .iter()
+            .filter(|notification| notification.notification_type == "reboot_required")
+            .collect()
+    }
+
+    #[test]
+    fn test_reminders_escalate_through_timeframes() {
+        let mut harness = ServiceHarness::start(config()).unwrap();
+        let started = harness.now();
+        harness.require_reboot(true);
+        harness.run_for(Duration::hours(5)).unwrap();
+
+        let state = harness.reboot_state().unwrap().unwrap();
+        assert!(state.reboot_required);
+        assert_eq!(state.lifecycle, RebootLifecycle::ReminderActive);
+
+        let reminders = reminders(&harness);
+        let in_hours = |from: i64, to: i64| {
+            reminders
+                .iter()
+                .filter(|reminder| reminder.at >= started + Duration::hours(from) && reminder.at < started + Duration::hours(to))
+                .collect::<Vec<_>>()
+        };
+        let (normal, high, critical) = (in_hours(0, 2), in_hours(2, 4), in_hours(4, 5));
+        assert_eq!(normal.len(), 2);
+        assert!(normal.iter().all(|reminder| reminder.severity == NotificationSeverity::Normal));
+        assert!((4..=5).contains(&high.len()));
+        assert!(high.iter().all(|reminder| reminder.severity == NotificationSeverity::High));
+        assert!((6..=7).contains(&critical.len()));
+        assert!(critical.iter().all(|reminder| reminder.severity == NotificationSeverity::Critical));
+    }
+
+    #[test]
+    fn test_postponing_holds_reminders_back() {
+        let mut harness = ServiceHarness::start(config()).unwrap();
+        harness.require_reboot(true);
+        harness.run_for(Duration::minutes(10)).unwrap();
+        assert_eq!(reminders(&harness).len(), 1);
+
+        let postponed_until = harness.now() + Duration::minutes(90);
+        harness.postpone("90m").unwrap();
+        assert_eq!(harness.reboot_state().unwrap().unwrap().postpone_count, 1);
+
+        // Reminders can't be postponed for longer than the deferral options allow
+        assert!(harness.postpone("1d").is_err());
+
+        // Past the hourly reminder, but not the postponement
+        harness.run_for(Duration::minutes(80)).unwrap();
+        assert_eq!(reminders(&harness).len(), 1);
+
+        // The loop picks the postponement up and reminds as soon as it ends
+        harness.run_for(Duration::minutes(15)).unwrap();
+        let reminders = reminders(&harness);
+        assert_eq!(reminders.len(), 2);
+        assert!(reminders[1].at >= postponed_until && reminders[1].at <= postponed_until + Duration::minutes(2));
+    }
+
+    #[test]
+    fn test_reboot_completes_and_service_stops() {
+        let mut harness = ServiceHarness::start(config()).unwrap();
+        harness.require_reboot(true);
+        harness.run_for(Duration::minutes(30)).unwrap();
+        assert_eq!(reminders(&harness).len(), 1);
+
+        harness.require_reboot(false);
+        harness.run_for(Duration::hours(2)).unwrap();
+        let state = harness.reboot_state().unwrap().unwrap();
+        assert!(!state.reboot_required);
+        assert_eq!(state.lifecycle, RebootLifecycle::Completed);
+        assert_eq!(state.next_reminder_time, None);
+        assert_eq!(reminders(&harness).len(), 1);
+
+        harness.stop();
+        let stopped_at = harness.now();
+        harness.run_for(Duration::hours(1)).unwrap();
+        assert_eq!(harness.now(), stopped_at);
+        assert_eq!(
+            harness.service_states(),
+            [ServiceState::StartPending, ServiceState::Running, ServiceState::StopPending, ServiceState::Stopped]
+        );
+    }
+}
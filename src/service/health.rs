@@ -0,0 +1,238 @@
+use crate::config::{self, Config};
+use crate::database::{self, DbPool, DeliveryStatus};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::path::Path;
+use std::time;
+use windows_service::service::ServiceState;
+
+/// Exit code of the `health` command when something needs attention
+pub const EXIT_WARNING: i32 = 1;
+
+/// Exit code of the `health` command when the reminder system is broken
+pub const EXIT_CRITICAL: i32 = 2;
+
+/// Number of check intervals without a reboot check after which detection counts as stalled
+const STALE_CHECK_INTERVALS: u32 = 3;
+
+/// Number of recent notifications looked at for the last delivery
+const RECENT_NOTIFICATIONS: u32 = 50;
+
+/// Windows error returned when the service isn't installed
+const ERROR_SERVICE_DOES_NOT_EXIST: i32 = 1060;
+
+/// Outcome of a health check, ordered from best to worst
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Ok,
+    Warning,
+    Critical,
+}
+
+impl HealthStatus {
+    /// Get the exit code of the `health` command for this status, as monitoring agents expect it
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Self::Ok => 0,
+            Self::Warning => EXIT_WARNING,
+            Self::Critical => EXIT_CRITICAL,
+        }
+    }
+}
+
+/// Result of one health check
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthCheck {
+    /// What was checked: service, config, database, detection or delivery
+    pub name: String,
+
+    /// Outcome of the check
+    pub status: HealthStatus,
+
+    /// What was found
+    pub message: String,
+}
+
+impl HealthCheck {
+    fn new(name: &str, status: HealthStatus, message: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+/// Machine-readable result of the `health` command
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthReport {
+    /// Worst status of all checks
+    pub status: HealthStatus,
+
+    /// Time of the health check
+    pub checked_at: DateTime<Utc>,
+
+    /// Time of the last reboot check recorded by the service
+    pub last_check_time: Option<DateTime<Utc>>,
+
+    /// Time the last notification was delivered
+    pub last_delivery_time: Option<DateTime<Utc>>,
+
+    /// Individual checks
+    pub checks: Vec<HealthCheck>,
+}
+
+/// Check whether the reminder system itself works: the service, the configuration, the
+/// database, reboot detection and notification delivery
+///
+/// Reboot detection counts as stalled when the last check is older than `max_check_age`, by
+/// default three check intervals.
+pub fn check(config_path: &Path, max_check_age: Option<time::Duration>) -> HealthReport {
+    let mut report = HealthReport {
+        status: HealthStatus::Ok,
+        checked_at: Utc::now(),
+        last_check_time: None,
+        last_delivery_time: None,
+        checks: vec![check_service()],
+    };
+
+    match config::load(config_path) {
+        Ok(config) => {
+            report.checks.push(HealthCheck::new("config", HealthStatus::Ok, format!("Loaded {}", config_path.display())));
+            match database::init(&config.database).and_then(|db| database::get_reboot_state(&db).map(|_| db)) {
+                Ok(db) => {
+                    report.checks.push(HealthCheck::new("database", HealthStatus::Ok, format!("Opened {}", config.database.path)));
+                    report.checks.push(check_detection(&config, &db, max_check_age, &mut report.last_check_time));
+                    report.checks.push(check_delivery(&db, &mut report.last_delivery_time));
+                }
+                Err(e) => report.checks.push(HealthCheck::new("database", HealthStatus::Critical, format!("{:#}", e))),
+            }
+        }
+        Err(e) => report.checks.push(HealthCheck::new("config", HealthStatus::Critical, format!("{:#}", e))),
+    }
+
+    report.status = report.checks.iter().map(|check| check.status).max().unwrap_or(HealthStatus::Ok);
+    report
+}
+
+/// Check that the service is installed and running
+///
+/// A missing service is only a warning, since the scheduled task can run the checks instead.
+fn check_service() -> HealthCheck {
+    match query_service_state() {
+        Ok(Some(ServiceState::Running)) => HealthCheck::new("service", HealthStatus::Ok, "Running"),
+        Ok(Some(state)) => HealthCheck::new("service", HealthStatus::Critical, format!("Not running ({:?})", state)),
+        Ok(None) => HealthCheck::new("service", HealthStatus::Warning, "Not installed"),
+        Err(e) => HealthCheck::new("service", HealthStatus::Critical, format!("{:#}", e)),
+    }
+}
+
+/// Get the state of the service from the service control manager, or None if it isn't installed
+fn query_service_state() -> Result<Option<ServiceState>> {
+    use windows_service::service::ServiceAccess;
+    use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+    let service = match manager.open_service(super::SERVICE_NAME, ServiceAccess::QUERY_STATUS) {
+        Ok(service) => service,
+        Err(windows_service::Error::Winapi(e)) if e.raw_os_error() == Some(ERROR_SERVICE_DOES_NOT_EXIST) => {
+            return Ok(None)
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    Ok(Some(service.query_status()?.current_state))
+}
+
+/// Check that reboot checks have run recently
+fn check_detection(
+    config: &Config,
+    db: &DbPool,
+    max_check_age: Option<time::Duration>,
+    last_check_time: &mut Option<DateTime<Utc>>,
+) -> HealthCheck {
+    let state = match database::get_reboot_state(db) {
+        Ok(Some(state)) => state,
+        Ok(None) => return HealthCheck::new("detection", HealthStatus::Warning, "No reboot check has been recorded yet"),
+        Err(e) => return HealthCheck::new("detection", HealthStatus::Critical, format!("{:#}", e)),
+    };
+    *last_check_time = Some(state.last_check_time);
+
+    let max_age = match max_check_age {
+        Some(max_age) => max_age,
+        None => match crate::utils::timespan::parse_timespan(&config.reboot.check_interval) {
+            Ok(interval) => interval * STALE_CHECK_INTERVALS,
+            Err(e) => return HealthCheck::new("detection", HealthStatus::Critical, format!("{:#}", e)),
+        },
+    };
+
+    let age = (Utc::now() - state.last_check_time).to_std().unwrap_or_default();
+    if age > max_age {
+        HealthCheck::new(
+            "detection",
+            HealthStatus::Critical,
+            format!("Last reboot check was {} minutes ago, expected within {} minutes", age.as_secs() / 60, max_age.as_secs() / 60),
+        )
+    } else {
+        HealthCheck::new("detection", HealthStatus::Ok, format!("Last reboot check at {}", state.last_check_time.to_rfc3339()))
+    }
+}
+
+/// Check that the latest notification shown to users was delivered
+///
+/// Skipped notifications (dry run and suppressed accounts) are never shown, so they're ignored.
+fn check_delivery(db: &DbPool, last_delivery_time: &mut Option<DateTime<Utc>>) -> HealthCheck {
+    let notifications = match database::get_notifications(db, Some(RECENT_NOTIFICATIONS)) {
+        Ok(notifications) => notifications,
+        Err(e) => return HealthCheck::new("delivery", HealthStatus::Critical, format!("{:#}", e)),
+    };
+    *last_delivery_time = notifications
+        .iter()
+        .find(|notification| notification.delivery_status == DeliveryStatus::Delivered)
+        .map(|notification| notification.timestamp);
+
+    let latest = notifications.iter().find(|notification| notification.delivery_status != DeliveryStatus::Skipped);
+    match latest {
+        None => HealthCheck::new("delivery", HealthStatus::Ok, "No notifications have been sent yet"),
+        Some(notification) => match notification.delivery_status {
+            DeliveryStatus::Failed => HealthCheck::new(
+                "delivery",
+                HealthStatus::Warning,
+                format!(
+                    "Last notification could not be delivered: {}",
+                    notification.delivery_error.as_deref().unwrap_or("unknown error")
+                ),
+            ),
+            DeliveryStatus::Pending if notification.delivery_attempts > 0 => HealthCheck::new(
+                "delivery",
+                HealthStatus::Warning,
+                format!("Last notification is being retried after {} attempts", notification.delivery_attempts),
+            ),
+            _ => HealthCheck::new(
+                "delivery",
+                HealthStatus::Ok,
+                match last_delivery_time {
+                    Some(time) => format!("Last notification delivered at {}", time.to_rfc3339()),
+                    None => "Last notification is waiting to be delivered".to_string(),
+                },
+            ),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_order() {
+        let statuses = [HealthStatus::Ok, HealthStatus::Critical, HealthStatus::Warning];
+        assert_eq!(statuses.iter().max(), Some(&HealthStatus::Critical));
+        assert_eq!(HealthStatus::Ok.exit_code(), 0);
+        assert_eq!(HealthStatus::Warning.exit_code(), EXIT_WARNING);
+        assert_eq!(serde_json::to_string(&HealthStatus::Critical).unwrap(), "\"critical\"");
+    }
+}
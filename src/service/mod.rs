@@ -1,10 +1,23 @@
-use crate::config::{self, Config};
-use crate::database::{self, DbPool, RebootState};
+#[cfg(any(test, feature = "test-harness"))]
+pub mod harness;
+pub mod health;
+mod shutdown;
+mod supervisor;
+pub mod task;
+
+use crate::config::{self, AdminAlertsConfig, Config, NotificationConfig, WeeklySummaryConfig};
+use crate::database::{self, DbPool, RebootLifecycle, RebootState};
 use crate::impersonation::Impersonator;
-use crate::notification::NotificationManager;
-use crate::reboot::{self, detector::RebootDetector, history::RebootHistoryManager};
+use crate::ipc;
+use crate::notification::{admin, summary, template::TemplateContext, toast::ToastStyle, NotificationManager};
+use crate::reboot::{self, detector::{RebootDetector, SystemInfo}, history::RebootHistoryManager};
+use crate::reporting;
+use crate::watchdog::power_events::{PowerEvent, PowerEventChecker, PowerMonitor};
+use crate::utils::clock::{self, Clock};
+use crate::utils::schedule::ScheduleZone;
+use shutdown::ShutdownSignal;
 use anyhow::{Context, Result};
-use chrono::{Duration, Utc};
+use chrono::{DateTime, Duration, Local, Utc};
 use log::{debug, error, info, warn};
 use std::path::{Path, PathBuf};
 use std::ffi::OsString;
@@ -13,17 +26,19 @@ use std::process::Command;
 use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
 use std::time;
+use windows::Win32::System::Registry::HKEY_LOCAL_MACHINE;
 use windows_service::{
     define_windows_service,
     service::{
         ServiceControl, ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus,
         ServiceType,
     },
-    service_control_handler::{self, ServiceControlHandlerResult},
+    service_control_handler::{self, ServiceControlHandlerResult, ServiceStatusHandle},
     service_dispatcher,
 };
 
-const SERVICE_NAME: &str = "RebootReminder";
+/// Name the service, and the scheduled task used instead of it, are installed under
+pub const SERVICE_NAME: &str = "RebootReminder";
 // These constants are used when installing the service
 #[allow(dead_code)]
 const SERVICE_DISPLAY_NAME: &str = "Reboot Reminder Service";
@@ -32,29 +47,64 @@ const SERVICE_DESCRIPTION: &str = "Provides notifications when system reboots ar
 
 // Global state
 static mut CONFIG_PATH: Option<PathBuf> = None;
-static mut SERVICE_RUNNING: bool = false;
 static mut RUNNING_AS_SERVICE: bool = false;
+static mut DRY_RUN: bool = false;
 
 /// Set the configuration file path for the service
 pub unsafe fn set_config_path(path: PathBuf) {
     CONFIG_PATH = Some(path);
 }
 
-// Service entry point
-// Fix service_main signature to match what define_windows_service! expects
-fn service_main(_arguments: Vec<OsString>) {
-    // Implementation will be provided by define_windows_service! macro
+/// Force dry-run mode regardless of the configuration file
+pub unsafe fn set_dry_run(dry_run: bool) {
+    DRY_RUN = dry_run;
+}
+
+/// Apply the command line dry-run override to a loaded configuration
+fn apply_dry_run_override(config: &mut Config) {
+    if unsafe { DRY_RUN } {
+        config.service.dry_run = true;
+    }
 }
 
+// Service entry point
 define_windows_service!(ffi_service_main, service_main);
 
+/// Service security descriptor applied at install time
+///
+/// SYSTEM and Administrators keep full control; interactive, service and authenticated users
+/// can only query the service, so they cannot stop, pause or reconfigure it.
+const SERVICE_SDDL: &str = "D:(A;;CCLCSWRPWPDTLOCRRC;;;SY)(A;;CCDCLCSWRPWPDTLOCRSDRCWDWO;;;BA)(A;;CCLCSWLOCRRC;;;IU)(A;;CCLCSWLOCRRC;;;SU)(A;;CCLCSWLOCRRC;;;AU)S:(AU;FA;CCDCLCSWRPWPDTLOCRSDRCWDWO;;;WD)";
+
 /// Install the service
-pub fn install(name: &str, display_name: &str, description: &str) -> Result<()> {
+pub fn install(name: &str, display_name: &str, description: &str, config: &Config, options: &InstallOptions) -> Result<()> {
     info!("Installing service: {}", name);
 
     // Get the path to the executable
     let exe_path = std::env::current_exe().context("Failed to get executable path")?;
 
+    // Pass the configuration path to the service, which otherwise looks next to the executable
+    let mut launch_arguments: Vec<OsString> = Vec::new();
+    if let Some(config_path) = &options.config_path {
+        let config_path = if config_path.is_absolute() {
+            config_path.clone()
+        } else {
+            std::env::current_dir().context("Failed to get current directory")?.join(config_path)
+        };
+        info!("Service will load configuration from {:?}", config_path);
+        launch_arguments.push("--config".into());
+        launch_arguments.push(config_path.into_os_string());
+    }
+    launch_arguments.push("run".into());
+
+    if options.password.is_some() && options.account.is_none() {
+        return Err(anyhow::anyhow!("A password can only be given together with an account"));
+    }
+    let account_password = match &options.password {
+        Some(password) => Some(crate::utils::secrets::resolve(password).context("Failed to read the account password")?.into()),
+        None => None,
+    };
+
     // Create the service
     let service_info = windows_service::service::ServiceInfo {
         name: name.to_string().into(),
@@ -62,11 +112,11 @@ pub fn install(name: &str, display_name: &str, description: &str) -> Result<()>
         service_type: ServiceType::OWN_PROCESS,
         start_type: windows_service::service::ServiceStartType::AutoStart,
         error_control: windows_service::service::ServiceErrorControl::Normal,
-        executable_path: exe_path,
-        launch_arguments: vec!["run".to_string().into()],
+        executable_path: exe_path.clone(),
+        launch_arguments,
         dependencies: vec![],
-        account_name: None, // Use LocalSystem account by default
-        account_password: None,
+        account_name: options.account.as_ref().map(|account| account.into()), // LocalSystem when not set
+        account_password,
     };
 
     // Create the service manager
@@ -76,9 +126,12 @@ pub fn install(name: &str, display_name: &str, description: &str) -> Result<()>
     )
     .context("Failed to create service manager")?;
 
-    // Create the service
+    // Create the service, with start access so restart recovery actions can be configured
     let service = service_manager
-        .create_service(&service_info, windows_service::service::ServiceAccess::CHANGE_CONFIG)
+        .create_service(
+            &service_info,
+            windows_service::service::ServiceAccess::CHANGE_CONFIG | windows_service::service::ServiceAccess::START,
+        )
         .context("Failed to create service")?;
 
     // Set the service description
@@ -86,33 +139,182 @@ pub fn install(name: &str, display_name: &str, description: &str) -> Result<()>
         .set_description(description)
         .context("Failed to set service description")?;
 
-    // Configure service recovery options using SC.exe
-    // This sets the service to restart on the first, second, and subsequent failures
+    if options.delayed_start {
+        info!("Configuring delayed automatic start");
+        service
+            .set_delayed_auto_start(true)
+            .context("Failed to configure delayed automatic start")?;
+    }
+
+    // Restart the service after failures
     info!("Configuring service recovery options");
+    match configure_recovery(&service, options) {
+        Ok(_) => info!("Service recovery options configured successfully"),
+        // Continue even if this fails, as it's not critical
+        Err(e) => warn!("Failed to set service recovery options: {:#}", e),
+    }
+
+    // Restrict who can stop or reconfigure the service
+    info!("Configuring service security descriptor");
     let output = Command::new("sc")
-        .args([
-            "failure",
-            &name,
-            "reset=0",
-            "actions=restart/60000/restart/60000/restart/60000"
-        ])
+        .args(["sdset", name, SERVICE_SDDL])
         .output()
-        .context("Failed to execute SC command for recovery options")?;
+        .context("Failed to execute SC command for service security descriptor")?;
 
     if !output.status.success() {
         let error = String::from_utf8_lossy(&output.stderr);
-        warn!("Failed to set service recovery options: {}", error);
-        // Continue even if this fails, as it's not critical
+        warn!("Failed to set service security descriptor: {}", error);
     } else {
-        info!("Service recovery options configured successfully");
+        info!("Service security descriptor configured successfully");
+    }
+
+    // Restrict access to the database and log directories
+    if let Err(e) = protect_data_directories(config, options.account.as_deref()) {
+        warn!("Failed to restrict access to data directories: {}", e);
+    }
+
+    // Let toast buttons hand their actions to the service
+    if let Err(e) = crate::notification::protocol::register(&exe_path) {
+        warn!("Failed to register the protocol handler: {:#}", e);
     }
 
     info!("Service installed successfully");
     Ok(())
 }
 
-/// Uninstall the service
+/// Options for installing the service
+#[derive(Debug, Clone)]
+pub struct InstallOptions {
+    /// Account the service runs as (None = LocalSystem)
+    pub account: Option<String>,
+
+    /// Password of the account, plain text or "dpapi:" protected (not needed for virtual and managed service accounts)
+    pub password: Option<String>,
+
+    /// Start the service shortly after boot instead of with the other automatic services
+    pub delayed_start: bool,
+
+    /// Configuration file the service loads (None = config.json next to the executable)
+    pub config_path: Option<PathBuf>,
+
+    /// Delay before each restart after a failure; the last delay is used for any further failures
+    pub restart_delays: Vec<time::Duration>,
+
+    /// Time without failures after which the failure count is reset
+    pub reset_period: time::Duration,
+}
+
+impl Default for InstallOptions {
+    fn default() -> Self {
+        Self {
+            account: None,
+            password: None,
+            delayed_start: false,
+            config_path: None,
+            restart_delays: vec![time::Duration::from_secs(60); 3],
+            reset_period: time::Duration::from_secs(24 * 60 * 60),
+        }
+    }
+}
+
+/// Configure the service to restart after failures, including when it stops with an error
+///
+/// Uses ChangeServiceConfig2 rather than `sc failure`, whose output and argument quoting vary
+/// between Windows versions and languages.
+fn configure_recovery(service: &windows_service::service::Service, options: &InstallOptions) -> Result<()> {
+    use windows_service::service::{
+        ServiceAction, ServiceActionType, ServiceFailureActions, ServiceFailureResetPeriod,
+    };
+
+    let actions = options
+        .restart_delays
+        .iter()
+        .map(|delay| ServiceAction {
+            action_type: ServiceActionType::Restart,
+            delay: *delay,
+        })
+        .collect();
+
+    service
+        .update_failure_actions(ServiceFailureActions {
+            reset_period: ServiceFailureResetPeriod::After(options.reset_period),
+            reboot_msg: None,
+            command: None,
+            actions: Some(actions),
+        })
+        .context("Failed to set service failure actions")?;
+
+    service
+        .set_failure_actions_on_non_crash_failures(true)
+        .context("Failed to enable failure actions for non-crash failures")?;
+
+    Ok(())
+}
+
+/// Restrict the database and log directories to SYSTEM, Administrators and the service account
+///
+/// Signed-in users keep read access to the database directory, since the tray, the `details`
+/// command and the recent notifications menu read the database in the user's context. Only
+/// SYSTEM, Administrators and the service account can change it. The executable's own directory
+/// is left alone so users can still start the application.
+fn protect_data_directories(config: &Config, account: Option<&str>) -> Result<()> {
+    let exe_dir = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|parent| parent.to_path_buf()));
+    let database_dir = Path::new(&config.database.path).parent().map(|dir| dir.to_path_buf());
+
+    let mut directories: Vec<PathBuf> = Vec::new();
+    for path in [&config.database.path, &config.logging.path] {
+        let dir = match Path::new(path).parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => continue,
+        };
+
+        if Some(&dir) == exe_dir.as_ref() || directories.contains(&dir) {
+            continue;
+        }
+
+        directories.push(dir);
+    }
+
+    for dir in directories {
+        std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create directory: {:?}", dir))?;
+
+        info!("Restricting access to {:?}", dir);
+        let mut command = Command::new("icacls");
+        command.arg(&dir).args([
+            "/inheritance:r",
+            "/grant:r",
+            "*S-1-5-18:(OI)(CI)F",
+            "*S-1-5-32-544:(OI)(CI)F",
+        ]);
+        if let Some(account) = account {
+            // icacls doesn't understand the ".\user" form for local accounts
+            let account = account.strip_prefix(".\\").unwrap_or(account);
+            command.arg(format!("{}:(OI)(CI)M", account));
+        }
+        if Some(&dir) == database_dir.as_ref() {
+            // INTERACTIVE covers console and Remote Desktop sign-ins
+            command.arg("*S-1-5-4:(OI)(CI)RX");
+        }
+        let output = command.output().context("Failed to execute icacls")?;
+
+        if !output.status.success() {
+            let error = String::from_utf8_lossy(&output.stdout);
+            return Err(anyhow::anyhow!("Failed to restrict access to {:?}: {}", dir, error.trim()));
+        }
+    }
+
+    Ok(())
+}
+
+/// Uninstall the service, stopping it first if it's running
+///
+/// Deleting a running service only marks it for deletion, so the service is stopped and waited
+/// for before it's deleted.
 pub fn uninstall() -> Result<()> {
+    use windows_service::service::ServiceAccess;
+
     info!("Uninstalling service");
 
     // Create the service manager
@@ -126,17 +328,147 @@ pub fn uninstall() -> Result<()> {
     let service = service_manager
         .open_service(
             SERVICE_NAME,
-            windows_service::service::ServiceAccess::DELETE,
+            ServiceAccess::DELETE | ServiceAccess::STOP | ServiceAccess::QUERY_STATUS,
         )
         .context("Failed to open service")?;
 
+    stop_and_wait(&service)?;
+
     // Delete the service
+    println!("Deleting service {}...", SERVICE_NAME);
     service.delete().context("Failed to delete service")?;
+    println!("Service deleted");
+
+    if let Err(e) = crate::notification::protocol::unregister() {
+        warn!("Failed to remove the protocol handler: {:#}", e);
+    }
 
     info!("Service uninstalled successfully");
     Ok(())
 }
 
+/// Carry out the action of a `rebootreminder:` link opened in a user session
+///
+/// Links that don't name a notification act on the latest one, so a link typed by hand still
+/// does something sensible.
+fn handle_protocol_request(
+    request: &ipc::IpcRequest,
+    session_id: u32,
+    notification_manager: &Arc<Mutex<NotificationManager>>,
+    db_pool: &DbPool,
+) -> Result<String> {
+    let action = crate::notification::protocol::parse(&request.uri)?;
+
+    let session = Impersonator::new()
+        .get_active_sessions()?
+        .into_iter()
+        .find(|session| session.session_id == session_id.to_string())
+        .ok_or_else(|| anyhow::anyhow!("No user is logged on to session {}", session_id))?;
+
+    let notification_id = match action.notification_id {
+        Some(id) => id,
+        None => crate::database::get_notifications(db_pool, Some(1))?
+            .first()
+            .map(|notification| notification.id)
+            .ok_or_else(|| anyhow::anyhow!("No notification to act on"))?,
+    };
+
+    notification_manager
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Notification manager lock is poisoned"))?
+        .record_interaction(notification_id, &action.action, &session)?;
+
+    Ok(format!("Recorded {} for notification {}", action.action, notification_id))
+}
+
+/// How long uninstall waits for the service to stop
+const STOP_TIMEOUT: time::Duration = time::Duration::from_secs(30);
+
+/// Registry key registering the service as an event log source
+const EVENT_LOG_SOURCE_KEY: &str = "SYSTEM\\CurrentControlSet\\Services\\EventLog\\Application\\RebootReminder";
+
+/// Stop a service if it's running and wait for it to stop
+fn stop_and_wait(service: &windows_service::service::Service) -> Result<()> {
+    use windows_service::service::ServiceState;
+
+    let status = service.query_status().context("Failed to query service status")?;
+    if status.current_state == ServiceState::Stopped {
+        debug!("Service is not running");
+        return Ok(());
+    }
+
+    println!("Stopping service {}...", SERVICE_NAME);
+    if status.current_state != ServiceState::StopPending {
+        service.stop().context("Failed to stop service")?;
+    }
+
+    let started = time::Instant::now();
+    loop {
+        let status = service.query_status().context("Failed to query service status")?;
+        if status.current_state == ServiceState::Stopped {
+            println!("Service stopped");
+            return Ok(());
+        }
+        if started.elapsed() >= STOP_TIMEOUT {
+            return Err(anyhow::anyhow!(
+                "Timed out after {:?} waiting for service {} to stop",
+                STOP_TIMEOUT,
+                SERVICE_NAME
+            ));
+        }
+        thread::sleep(time::Duration::from_millis(500));
+    }
+}
+
+/// Remove the database, logs and registry keys left behind by the service
+///
+/// Directories are only removed when they are empty afterwards, and never when they hold the
+/// executable.
+pub fn purge(config: &Config) -> Result<()> {
+    info!("Purging service data");
+    let database = PathBuf::from(&config.database.path);
+    let mut files = vec![
+        crate::metrics::snapshot_path(&config.database.path),
+        PathBuf::from(format!("{}-wal", config.database.path)),
+        PathBuf::from(format!("{}-shm", config.database.path)),
+        database.clone(),
+    ];
+    files.push(PathBuf::from(&config.logging.path));
+    files.extend((0..config.logging.max_files).map(|index| PathBuf::from(format!("{}.{}", config.logging.path, index))));
+
+    for file in &files {
+        if !file.exists() {
+            continue;
+        }
+        match std::fs::remove_file(file) {
+            Ok(()) => println!("Removed {}", file.display()),
+            Err(e) => warn!("Failed to remove {:?}: {}", file, e),
+        }
+    }
+
+    let exe_dir = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|parent| parent.to_path_buf()));
+    for path in [&config.logging.path, &config.database.path] {
+        if let Some(dir) = Path::new(path).parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            if Some(dir) != exe_dir.as_deref() && std::fs::remove_dir(dir).is_ok() {
+                println!("Removed {}", dir.display());
+            }
+        }
+    }
+
+    for key in [EVENT_LOG_SOURCE_KEY, crate::config::policy::POLICY_KEY, crate::support::inventory::PRODUCT_KEY] {
+        match crate::utils::registry::delete_key_tree(HKEY_LOCAL_MACHINE, key) {
+            Ok(true) => println!("Removed HKLM\\{}", key),
+            Ok(false) => debug!("Registry key HKLM\\{} doesn't exist", key),
+            Err(e) => warn!("{}", e),
+        }
+    }
+
+    info!("Service data purged");
+    Ok(())
+}
+
 /// Check if running as a service
 pub fn is_running_as_service() -> bool {
     unsafe { RUNNING_AS_SERVICE }
@@ -154,8 +486,8 @@ fn run_service_directly(_config: Config, _db_pool: DbPool) -> Result<()> {
     // The database is already initialized in the main function
     // We just need to make sure the configuration is set correctly
 
-    // Call the run_service function directly
-    run_service()
+    // Call the run_service function directly; nothing asks it to stop but a failing worker
+    run_service(None, ShutdownSignal::default())
 }
 
 /// Run the service
@@ -164,7 +496,6 @@ pub fn run(config: Config, db_pool: DbPool) -> Result<()> {
 
     // Set global state
     unsafe {
-        SERVICE_RUNNING = true;
         RUNNING_AS_SERVICE = true;
     }
 
@@ -206,21 +537,86 @@ pub fn run(config: Config, db_pool: DbPool) -> Result<()> {
     }
 }
 
+/// Run a single reboot check cycle and return, for scheduled task deployments
+///
+/// The reboot requirement is checked right away and saved to the database the service would
+/// use, and any notification or reboot that is due is shown or started. Returns whether a
+/// reboot is required afterwards.
+pub fn run_once(config: Config, db_pool: DbPool) -> Result<bool> {
+    info!("Running a single reboot check cycle");
+    ensure_directories_exist(&config)?;
+
+    // The scheduled task runs as SYSTEM in session 0 like the service, so notifications have to
+    // go to the users' sessions the same way
+    let impersonator = Impersonator::new();
+    if impersonator.current_session_id() == Some(0) {
+        info!("Running in session 0, notifications are shown in the users' sessions");
+        unsafe {
+            RUNNING_AS_SERVICE = true;
+        }
+    }
+
+    let mut notification_manager = NotificationManager::new(&config, db_pool.clone(), Arc::new(impersonator));
+    // No service is listening for the actions of toast buttons
+    notification_manager.disable_link_actions();
+    notification_manager
+        .initialize()
+        .context("Failed to initialize notification manager")?;
+
+    // Record reboots logged since the last run
+    let history_manager = RebootHistoryManager::new(config.reboot.clone(), db_pool.clone());
+    if let Err(e) = history_manager.get_reboot_history(10) {
+        warn!("Failed to scan event log for reboot history: {}", e);
+    }
+
+    let mut cycle = CheckCycle::new(
+        db_pool.clone(),
+        Arc::new(Mutex::new(notification_manager)),
+        RebootDetector::new(&config.reboot),
+        None,
+        crate::metrics::snapshot_path(&config.database.path),
+        clock::system(),
+    );
+    cycle.run(&config, true);
+
+    // The cycle only records the check when the reboot requirement could be detected
+    if cycle.last_check.is_none() {
+        return Err(anyhow::anyhow!("Failed to check if a reboot is required"));
+    }
+    let required = database::get_reboot_state(&db_pool)?.is_some_and(|state| state.reboot_required);
+
+    info!("Reboot check cycle completed, reboot required: {}", required);
+    Ok(required)
+}
+
 /// Helper function to update service status with checkpoint
 fn update_service_status(
-    status_handle: &windows_service::service_control_handler::ServiceStatusHandle,
+    status_handle: &ServiceStatusHandle,
+    current_state: ServiceState,
+    checkpoint: u32,
+    wait_hint_secs: u32,
+    controls_accepted: ServiceControlAccept,
+) -> Result<()> {
+    set_service_status(status_handle, current_state, checkpoint, wait_hint_secs, controls_accepted, ServiceExitCode::Win32(0))
+}
+
+/// Update the service status with an exit code, which the service manager reads once the service
+/// has stopped
+fn set_service_status(
+    status_handle: &ServiceStatusHandle,
     current_state: ServiceState,
     checkpoint: u32,
     wait_hint_secs: u32,
     controls_accepted: ServiceControlAccept,
+    exit_code: ServiceExitCode,
 ) -> Result<()> {
-    info!("Updating service status to {:?} (checkpoint: {}, wait_hint: {}s)", current_state, checkpoint, wait_hint_secs);
+    info!("Updating service status to {:?} (checkpoint: {}, wait_hint: {}s, exit code: {:?})", current_state, checkpoint, wait_hint_secs, exit_code);
     status_handle
         .set_service_status(ServiceStatus {
             service_type: ServiceType::OWN_PROCESS,
             current_state,
             controls_accepted,
-            exit_code: ServiceExitCode::Win32(0),
+            exit_code,
             checkpoint,
             wait_hint: std::time::Duration::from_secs(wait_hint_secs as u64),
             process_id: None,
@@ -228,11 +624,11 @@ fn update_service_status(
         .context("Failed to set service status")
 }
 
-/// Service main function
-// This function is replaced by the define_windows_service! macro
-// The actual implementation is below, but it's not used directly
-#[allow(dead_code)]
-fn service_main_impl(arguments: Vec<OsString>) {
+/// Service main function, called by the service dispatcher
+///
+/// Registers the only service control handler, which owns the stop request, and hands its status
+/// handle to `run_service` for the start checkpoints.
+fn service_main(arguments: Vec<OsString>) {
     info!("Service main function started with {} arguments", arguments.len());
 
     // Log the arguments
@@ -242,27 +638,36 @@ fn service_main_impl(arguments: Vec<OsString>) {
 
     // Register the service control handler
     info!("Registering service control handler");
-    let event_handler = move |control_event| -> ServiceControlHandlerResult {
-        match control_event {
-            ServiceControl::Stop => {
-                info!("Service stop requested");
-                unsafe {
-                    SERVICE_RUNNING = false;
+    let shutdown = ShutdownSignal::default();
+    let event_handler = {
+        let shutdown = shutdown.clone();
+        move |control_event| -> ServiceControlHandlerResult {
+            match control_event {
+                ServiceControl::Stop => {
+                    info!("Service stop requested");
+                    shutdown.request();
+                    ServiceControlHandlerResult::NoError
                 }
-                ServiceControlHandlerResult::NoError
+                ServiceControl::Interrogate => {
+                    debug!("Service interrogate requested");
+                    ServiceControlHandlerResult::NoError
+                },
+                ServiceControl::PowerEvent(event_type) => {
+                    debug!("Power event received: {:?}", event_type);
+                    ServiceControlHandlerResult::NoError
+                },
+                ServiceControl::SessionChange(session_change) => {
+                    debug!("Session change event received: {:?}", session_change);
+                    ServiceControlHandlerResult::NoError
+                },
+                _ => {
+                    debug!("Unhandled service control event: {:?}", control_event);
+                    ServiceControlHandlerResult::NotImplemented
+                },
             }
-            ServiceControl::Interrogate => {
-                info!("Service interrogate requested");
-                ServiceControlHandlerResult::NoError
-            },
-            _ => {
-                info!("Unhandled service control event: {:?}", control_event);
-                ServiceControlHandlerResult::NotImplemented
-            },
         }
     };
 
-    info!("Calling service_control_handler::register");
     let status_handle = match service_control_handler::register(SERVICE_NAME, event_handler) {
         Ok(handle) => {
             info!("Service control handler registered successfully");
@@ -275,27 +680,35 @@ fn service_main_impl(arguments: Vec<OsString>) {
     };
 
     // Tell the service manager we are starting
-    if let Err(e) = update_service_status(&status_handle, ServiceState::StartPending, 0, 60, ServiceControlAccept::empty()) {
+    if let Err(e) = update_service_status(&status_handle, ServiceState::StartPending, 0, 120, ServiceControlAccept::empty()) {
         error!("Failed to set service status to StartPending: {}", e);
-        return;
     }
 
     // Run the service
-    match run_service() {
+    let exit_code = match run_service(Some(&status_handle), shutdown) {
         Ok(_) => {
             info!("Service completed successfully");
+            ServiceExitCode::Win32(0)
         }
         Err(e) => {
             error!("Service failed: {}", e);
+            ServiceExitCode::ServiceSpecific(1)
         }
-    }
+    };
 
-    // Tell the service manager we are stopped
-    if let Err(e) = update_service_status(&status_handle, ServiceState::Stopped, 0, 0, ServiceControlAccept::empty()) {
+    // Tell the service manager we are stopped, with the error so its recovery options apply
+    if let Err(e) = set_service_status(&status_handle, ServiceState::Stopped, 0, 0, ServiceControlAccept::empty(), exit_code) {
         error!("Failed to set service status to Stopped: {}", e);
     }
 }
 
+/// Report progress while the service is starting, when it runs under the service manager
+fn report_start_progress(status_handle: Option<&ServiceStatusHandle>, checkpoint: u32) {
+    if let Some(status_handle) = status_handle {
+        let _ = update_service_status(status_handle, ServiceState::StartPending, checkpoint, 120, ServiceControlAccept::empty());
+    }
+}
+
 /// Ensure that all necessary directories exist
 fn ensure_directories_exist(config: &Config) -> Result<()> {
     debug!("Ensuring necessary directories exist");
@@ -330,65 +743,18 @@ fn ensure_directories_exist(config: &Config) -> Result<()> {
     Ok(())
 }
 
-/// Run the service
-fn run_service() -> Result<()> {
+/// Run the service until it is asked to stop
+///
+/// `status_handle` is the handle of the service control handler registered by `service_main`,
+/// or None when running outside of the service manager.
+fn run_service(status_handle: Option<&ServiceStatusHandle>, shutdown: ShutdownSignal) -> Result<()> {
     info!("Starting service initialization in run_service");
-
-    // Create a status handle for updating service status
-    let status_handle = match service_control_handler::register(SERVICE_NAME, |control_event| {
-        match control_event {
-            ServiceControl::Stop => {
-                info!("Service stop requested");
-                unsafe {
-                    SERVICE_RUNNING = false;
-                }
-                ServiceControlHandlerResult::NoError
-            }
-            ServiceControl::Interrogate => {
-                debug!("Service interrogate requested");
-                ServiceControlHandlerResult::NoError
-            },
-            ServiceControl::PowerEvent(event_type) => {
-                debug!("Power event received: {:?}", event_type);
-                // Handle power events like sleep/resume
-                ServiceControlHandlerResult::NoError
-            },
-            ServiceControl::SessionChange(session_change) => {
-                debug!("Session change event received: {:?}", session_change);
-                // Handle session changes like user logon/logoff
-                ServiceControlHandlerResult::NoError
-            },
-            _ => {
-                debug!("Unhandled service control event: {:?}", control_event);
-                ServiceControlHandlerResult::NotImplemented
-            },
-        }
-    }) {
-        Ok(handle) => {
-            info!("Service control handler registered successfully");
-            handle
-        },
-        Err(e) => {
-            error!("Failed to register service control handler in run_service: {}", e);
-            // If we're not running as a service, we can continue without the service control handler
-            if !unsafe { RUNNING_AS_SERVICE } {
-                info!("Not running as a service, continuing without service control handler");
-                return Ok(());
-            }
-            return Err(anyhow::anyhow!("Failed to register service control handler: {}", e));
-        }
-    };
-
-    // Set initial status to StartPending
-    if let Err(e) = update_service_status(&status_handle, ServiceState::StartPending, 1, 120, ServiceControlAccept::empty()) {
-        error!("Failed to set initial service status: {}", e);
-        // Continue anyway, as this might not be fatal
-    }
+    report_start_progress(status_handle, 1);
 
     // Load configuration
     info!("Determining configuration path");
     // Update status to indicate progress
-    let _ = update_service_status(&status_handle, ServiceState::StartPending, 2, 120, ServiceControlAccept::empty());
+    report_start_progress(status_handle, 2);
     #[allow(static_mut_refs)]
     let config_path = unsafe { CONFIG_PATH.clone() }.unwrap_or_else(|| {
         info!("No configuration path set, using default");
@@ -418,8 +784,9 @@ fn run_service() -> Result<()> {
 
     info!("Loading configuration from {:?}", config_path);
     let config = match config::load(&config_path) {
-        Ok(cfg) => {
+        Ok(mut cfg) => {
             info!("Configuration loaded successfully");
+            apply_dry_run_override(&mut cfg);
             cfg
         },
         Err(e) => {
@@ -428,8 +795,11 @@ fn run_service() -> Result<()> {
         }
     };
     info!("Configuration loaded from {:?}", config_path);
+    if config.service.dry_run {
+        warn!("Dry-run mode is enabled: no notifications will be shown and the system will not be rebooted");
+    }
     // Update status to indicate progress
-    let _ = update_service_status(&status_handle, ServiceState::StartPending, 3, 120, ServiceControlAccept::empty());
+    report_start_progress(status_handle, 3);
 
     // Create necessary directories
     info!("Creating necessary directories");
@@ -444,7 +814,7 @@ fn run_service() -> Result<()> {
     // Initialize database
     info!("Initializing database at {}", config.database.path);
     // Update status to indicate progress
-    let _ = update_service_status(&status_handle, ServiceState::StartPending, 4, 120, ServiceControlAccept::empty());
+    report_start_progress(status_handle, 4);
     let db_pool = match database::init(&config.database) {
         Ok(pool) => {
             info!("Database initialized successfully");
@@ -458,8 +828,9 @@ fn run_service() -> Result<()> {
 
     // Create impersonator
     let impersonator = Arc::new(Impersonator::new());
+    let clock = clock::system();
     // Update status to indicate progress
-    let _ = update_service_status(&status_handle, ServiceState::StartPending, 5, 120, ServiceControlAccept::empty());
+    report_start_progress(status_handle, 5);
 
     // Create notification manager
     let mut notification_manager = NotificationManager::new(
@@ -468,7 +839,7 @@ fn run_service() -> Result<()> {
         impersonator.clone(),
     );
     // Update status to indicate progress
-    let _ = update_service_status(&status_handle, ServiceState::StartPending, 6, 120, ServiceControlAccept::empty());
+    report_start_progress(status_handle, 6);
     notification_manager
         .initialize()
         .context("Failed to initialize notification manager")?;
@@ -477,16 +848,16 @@ fn run_service() -> Result<()> {
     // Create reboot detector
     let detector = RebootDetector::new(&config.reboot);
     // Update status to indicate progress
-    let _ = update_service_status(&status_handle, ServiceState::StartPending, 7, 120, ServiceControlAccept::empty());
+    report_start_progress(status_handle, 7);
 
     // Create reboot history manager
     let history_manager = RebootHistoryManager::new(config.reboot.clone(), db_pool.clone());
     // Update status to indicate progress
-    let _ = update_service_status(&status_handle, ServiceState::StartPending, 8, 120, ServiceControlAccept::empty());
+    report_start_progress(status_handle, 8);
 
     // Create and start watchdog if enabled
     // Update status to indicate progress
-    let _ = update_service_status(&status_handle, ServiceState::StartPending, 9, 120, ServiceControlAccept::empty());
+    report_start_progress(status_handle, 9);
     if config.watchdog.enabled {
         info!("Initializing watchdog service");
         // Get check interval from either timespan or legacy field
@@ -530,6 +901,9 @@ fn run_service() -> Result<()> {
             restart_delay: config.watchdog.restart_delay.clone(),
             service_path: PathBuf::from(config.watchdog.service_path.clone()),
             service_name: config.watchdog.service_name.clone(),
+            restore_start_type: config.watchdog.restore_start_type,
+            crash_report: config.watchdog.crash_report.clone(),
+            log_path: config.logging.path.clone(),
             power_checker: None,
         };
 
@@ -539,7 +913,7 @@ fn run_service() -> Result<()> {
                 .expect("Failed to get executable path");
         }
 
-        let mut watchdog = crate::watchdog::Watchdog::new(watchdog_config);
+        let mut watchdog = crate::watchdog::Watchdog::new(watchdog_config).with_database(db_pool.clone());
         if let Err(e) = watchdog.start() {
             warn!("Failed to start watchdog service: {}", e);
         } else {
@@ -549,30 +923,29 @@ fn run_service() -> Result<()> {
         debug!("Watchdog service is disabled");
     }
 
-    // Scan event log for reboot history
-    if let Err(e) = history_manager.get_reboot_history_from_event_log(10) {
+    // Record reboots logged while the service wasn't running, then follow new ones as they happen
+    if let Err(e) = history_manager.get_reboot_history(10) {
         warn!("Failed to scan event log for reboot history: {}", e);
     }
-
-    // Get system info
-    match detector.get_system_info() {
-        Ok(info) => {
-            info!("System info: {:?}", info);
-        }
+    let _event_log_listener = match history_manager.start_event_log_listener() {
+        Ok(subscription) => Some(subscription),
         Err(e) => {
-            warn!("Failed to get system info: {}", e);
+            warn!("Failed to subscribe to the event log for reboot history: {}", e);
+            None
         }
-    }
+    };
 
     // Update status to indicate progress - final checkpoint before Running
-    let _ = update_service_status(&status_handle, ServiceState::StartPending, 10, 120, ServiceControlAccept::empty());
+    report_start_progress(status_handle, 10);
 
     // Set service status to Running
-    if let Err(e) = update_service_status(&status_handle, ServiceState::Running, 0, 0, ServiceControlAccept::STOP) {
-        error!("Failed to set service status to Running: {}", e);
-        // Continue anyway, as this might not be fatal
-    } else {
-        info!("Service status set to Running successfully");
+    if let Some(status_handle) = status_handle {
+        if let Err(e) = update_service_status(status_handle, ServiceState::Running, 0, 0, ServiceControlAccept::STOP) {
+            error!("Failed to set service status to Running: {}", e);
+            // Continue anyway, as this might not be fatal
+        } else {
+            info!("Service status set to Running successfully");
+        }
     }
 
     // Create shared configuration
@@ -583,24 +956,28 @@ fn run_service() -> Result<()> {
         let shared_config = shared_config.clone();
         let config_path = config_path.clone();
         let config_refresh_minutes = config.service.config_refresh_minutes;
+        let worker_shutdown = shutdown.clone();
+        let clock = clock.clone();
 
-        thread::spawn(move || {
-            let mut last_refresh = Utc::now();
+        supervisor::spawn("config-refresh", move || {
+            let mut last_refresh = clock.now();
 
             loop {
                 // Check if service is still running
-                if unsafe { !SERVICE_RUNNING } {
+                if worker_shutdown.is_requested() {
                     break;
                 }
 
                 // Check if it's time to refresh the configuration
-                let now = Utc::now();
+                let now = clock.now();
                 if now - last_refresh >= Duration::minutes(config_refresh_minutes as i64) {
                     debug!("Refreshing configuration");
 
                     // Load configuration
                     match config::load(&config_path) {
-                        Ok(new_config) => {
+                        Ok(mut new_config) => {
+                            apply_dry_run_override(&mut new_config);
+
                             // Update shared configuration
                             if let Ok(mut config) = shared_config.write() {
                                 *config = new_config;
@@ -618,23 +995,61 @@ fn run_service() -> Result<()> {
                 }
 
                 // Sleep for a minute
-                thread::sleep(time::Duration::from_secs(60));
+                worker_shutdown.wait(time::Duration::from_secs(60));
             }
-        })
+        }, stop_after_worker_failure(&shutdown))
+        .context("Failed to start configuration refresh thread")?
+    };
+
+    // Serve the status page for support sessions; it isn't restarted when the configuration changes
+    if let Some(status_page) = &config.status_page {
+        if let Err(e) = crate::support::status_page::start(status_page.port, shared_config.clone(), db_pool.clone()) {
+            warn!("Failed to start status page: {:#}", e);
+        }
+    }
+
+    // Accept toast actions from rebootreminder: links opened in user sessions
+    {
+        let notification_manager = notification_manager.clone();
+        let db_pool = db_pool.clone();
+        let handler = move |request: &ipc::IpcRequest, session_id: u32| {
+            handle_protocol_request(request, session_id, &notification_manager, &db_pool)
+        };
+        if let Err(e) = ipc::start(handler) {
+            warn!("Failed to start IPC server: {:#}", e);
+        }
+    }
+
+    // Start power event monitoring so reminders can be held back right after resume
+    let mut power_monitor = PowerMonitor::new();
+    let power_checker = match power_monitor.start() {
+        Ok(_) => Some(power_monitor.create_checker()),
+        Err(e) => {
+            warn!("Failed to start power monitor: {}", e);
+            None
+        }
     };
 
     // Create thread for checking if a reboot is required
     let reboot_check_thread = {
         let shared_config = shared_config.clone();
-        let db_pool = db_pool.clone();
-        let notification_manager = notification_manager.clone();
-
-        thread::spawn(move || {
-            let mut last_check = Utc::now();
+        let mut cycle = CheckCycle::new(
+            db_pool.clone(),
+            notification_manager.clone(),
+            detector,
+            power_checker,
+            crate::metrics::snapshot_path(&config.database.path),
+            clock.clone(),
+        );
+        let worker_shutdown = shutdown.clone();
+
+        supervisor::spawn("reboot-check", move || {
+            // Start over when the thread is restarted after a panic
+            cycle.restart();
 
             loop {
                 // Check if service is still running
-                if unsafe { !SERVICE_RUNNING } {
+                if worker_shutdown.is_requested() {
                     break;
                 }
 
@@ -643,257 +1058,1162 @@ fn run_service() -> Result<()> {
                     Ok(config) => config.clone(),
                     Err(e) => {
                         error!("Failed to acquire read lock for configuration: {}", e);
-                        thread::sleep(time::Duration::from_secs(60));
+                        worker_shutdown.wait(time::Duration::from_secs(60));
                         continue;
                     }
                 };
 
-                // Check if it's time to check if a reboot is required
-                let now = Utc::now();
-                // Get min hours from the first timeframe
-                let min_hours = if let Some(min_timespan) = &config.reboot.timeframes[0].min_timespan {
-                    match crate::utils::timespan::parse_timespan(min_timespan) {
-                        Ok(duration) => (duration.as_secs() / 3600) as i64,
-                        Err(_) => config.reboot.timeframes[0].min_hours.unwrap_or(24) as i64
+                cycle.run(&config, false);
+                worker_shutdown.wait(cycle.sleep_time(&config));
+            }
+        }, stop_after_worker_failure(&shutdown))
+        .context("Failed to start reboot check thread")?
+    };
+
+    // Wait for service to stop
+    while !shutdown.wait(time::Duration::from_secs(60)) {}
+
+    // Give the threads time to finish their current iteration
+    if let Some(status_handle) = status_handle {
+        let _ = update_service_status(status_handle, ServiceState::StopPending, 1, 60, ServiceControlAccept::empty());
+    }
+
+    // Wait for threads to finish
+    config_refresh_thread
+        .join()
+        .map_err(|_| anyhow::anyhow!("Failed to join configuration refresh thread"))?;
+
+    reboot_check_thread
+        .join()
+        .map_err(|_| anyhow::anyhow!("Failed to join reboot check thread"))?;
+
+    if shutdown.has_failed() {
+        return Err(anyhow::anyhow!("Service stopped after a worker thread failed"));
+    }
+
+    info!("Service stopped");
+    Ok(())
+}
+
+/// How long to wait before trying a reboot check again when it couldn't be completed
+const FAILED_CHECK_RETRY: time::Duration = time::Duration::from_secs(60);
+
+/// Reboot check cycle run by the reboot check thread, and once by `run --once`
+///
+/// Keeps what one iteration needs from the previous ones.
+struct CheckCycle {
+    db_pool: DbPool,
+    notification_manager: Arc<Mutex<NotificationManager>>,
+    detector: RebootDetector,
+    system_info: Option<SystemInfo>,
+    power_checker: Option<PowerEventChecker>,
+    metrics_path: PathBuf,
+    started: DateTime<Utc>,
+    last_check: Option<DateTime<Utc>>,
+    /// Time of the last check that couldn't be completed, to wait a while before the next one
+    last_failed_check: Option<DateTime<Utc>>,
+    last_resume: Option<DateTime<Utc>>,
+    last_countdown: Option<DateTime<Utc>>,
+    last_maintenance: Option<DateTime<Utc>>,
+    last_update_check: Option<DateTime<Utc>>,
+    /// A reboot that works restarts the service, so this counts the attempts that didn't
+    reboot_attempts: u32,
+    no_sessions_since: Option<DateTime<Utc>>,
+    last_summary: Option<DateTime<Utc>>,
+    /// Identity, version and deadline of the reboot state last mirrored to the registry
+    published_status: Option<(uuid::Uuid, u32, Option<DateTime<Utc>>)>,
+    /// Status last reported to Intune and when
+    intune_reported: Option<(String, DateTime<Utc>)>,
+    /// Time of the last failed attempt to report to Intune
+    intune_failed: Option<DateTime<Utc>>,
+    /// Status being reported to Intune in the background, and when the report started
+    intune_pending: Option<(String, DateTime<Utc>, thread::JoinHandle<Result<()>>)>,
+    /// Events waiting to be sent to the report sinks
+    reports: reporting::dispatcher::ReportDispatcher,
+    last_heartbeat: Option<DateTime<Utc>>,
+    /// Version of the reboot state this loop last saved or saw
+    state_version: Option<u32>,
+    clock: Arc<dyn Clock>,
+}
+
+impl CheckCycle {
+    fn new(
+        db_pool: DbPool,
+        notification_manager: Arc<Mutex<NotificationManager>>,
+        detector: RebootDetector,
+        power_checker: Option<PowerEventChecker>,
+        metrics_path: PathBuf,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        let mut cycle = Self {
+            db_pool,
+            notification_manager,
+            detector: detector.with_clock(clock.clone()),
+            system_info: None,
+            power_checker,
+            metrics_path,
+            started: clock.now(),
+            last_check: None,
+            last_failed_check: None,
+            last_resume: None,
+            last_countdown: None,
+            last_maintenance: None,
+            last_update_check: None,
+            reboot_attempts: 0,
+            no_sessions_since: None,
+            last_summary: None,
+            published_status: None,
+            intune_reported: None,
+            intune_failed: None,
+            intune_pending: None,
+            reports: reporting::dispatcher::ReportDispatcher::new(),
+            last_heartbeat: None,
+            state_version: None,
+            clock,
+        };
+        cycle.restart();
+        cycle
+    }
+
+    /// Get the current time
+    fn now(&self) -> DateTime<Utc> {
+        self.clock.now()
+    }
+
+    /// Get the system information again, keeping the previous one when it can't be read
+    fn refresh_system_info(&mut self) {
+        match self.detector.get_system_info() {
+            Ok(info) => {
+                if self.system_info.is_none() {
+                    info!("System info: {:?}", info);
+                }
+                self.system_info = Some(info);
+            }
+            Err(e) => warn!("Failed to get system info: {}", e),
+        }
+    }
+
+    /// Start over, checking right after startup instead of waiting for the check interval
+    ///
+    /// The detector and the system information it caches are kept.
+    fn restart(&mut self) {
+        // A panic while the notification manager was locked poisons the lock
+        self.notification_manager.clear_poison();
+
+        self.started = self.now();
+        self.last_check = None;
+        self.last_failed_check = None;
+        self.last_resume = None;
+        self.last_countdown = None;
+        self.last_maintenance = None;
+        self.last_update_check = None;
+        self.reboot_attempts = 0;
+        self.no_sessions_since = None;
+        self.last_summary = database::get_last_notification_time(&self.db_pool, summary::SUMMARY_NOTIFICATION_TYPE)
+            .unwrap_or_else(|e| {
+                warn!("Failed to get the time of the last weekly summary: {}", e);
+                None
+            });
+    }
+
+    /// Hold back debounced sources until they've lasted for enough checks
+    ///
+    /// The counters are kept in the database so they carry over between `run --once` runs. When
+    /// they can't be read, the sources are counted as they are.
+    fn debounce_sources(
+        &self,
+        checks: &std::collections::HashMap<String, u32>,
+        sources: Vec<database::RebootSource>,
+    ) -> Vec<database::RebootSource> {
+        let mut counters = match database::get_source_debounce(&self.db_pool) {
+            Ok(counters) => counters,
+            Err(e) => {
+                warn!("Failed to get the reboot source debounce counters: {}", e);
+                return sources;
+            }
+        };
+
+        let sources = reboot::debounce::apply(&mut counters, checks, sources);
+        if let Err(e) = database::save_source_debounce(&self.db_pool, &counters) {
+            warn!("Failed to save the reboot source debounce counters: {}", e);
+        }
+        sources
+    }
+
+    /// Run one iteration: deliver due notifications, run due reboots and check whether a
+    /// reboot is required when the check is due, or always when `force_check` is set
+    fn run(&mut self, config: &Config, force_check: bool) {
+        let iteration_started = time::Instant::now();
+
+        // Query WMI for the system information on this thread when it's first needed, instead of
+        // holding up the service start
+        if self.system_info.is_none() {
+            self.refresh_system_info();
+        }
+
+        // Start or stop sending events to report sinks as the configuration changed
+        self.reports.configure(&config.configured_report_sinks(), &self.db_pool);
+
+        // Record resume events so reminders can be held back afterwards
+        if let Some(checker) = &self.power_checker {
+            if checker.drain_events().contains(&PowerEvent::Resume) {
+                info!("System resume detected");
+                self.last_resume = Some(self.now());
+            }
+        }
+
+        // Deliver any notification held back while the user was busy
+        if let Ok(manager) = self.notification_manager.lock() {
+            if let Err(e) = manager.deliver_pending_notification() {
+                error!("Failed to deliver pending notification: {}", e);
+            }
+
+            // Retry notifications whose delivery failed
+            if let Err(e) = manager.retry_failed_deliveries() {
+                error!("Failed to retry notification deliveries: {}", e);
+            }
+        }
+
+        // Count down to a scheduled reboot during its last hour
+        if let Ok(manager) = self.notification_manager.lock() {
+            if let Err(e) = show_reboot_countdown(config, &self.db_pool, &manager, self.system_info.as_ref(), &mut self.last_countdown, self.now()) {
+                error!("Failed to show reboot countdown: {}", e);
+            }
+        }
+
+        // Reboot right away while every signed-in user is away
+        if let Err(e) = schedule_idle_reboot(config, &self.db_pool, self.now()) {
+            warn!("Failed to check for an idle reboot: {:#}", e);
+        }
+
+        // Make sure an initiated reboot actually happened
+        if let Err(e) = reboot::verify::verify(&config.reboot.system_reboot, &self.db_pool, config.service.dry_run, self.now()) {
+            error!("Failed to verify the initiated reboot: {:#}", e);
+        }
+
+        // Reboot while nobody is signed in
+        match run_unattended_reboot(config, &self.db_pool, &mut self.no_sessions_since, self.now()) {
+            Ok(true) => self.reboot_attempts += 1,
+            Ok(false) => {}
+            Err(e) => error!("Failed to run unattended reboot: {:#}", e),
+        }
+
+        // Run a scheduled reboot if one is due
+        match run_scheduled_reboot(config, &self.db_pool, self.now()) {
+            Ok(true) => self.reboot_attempts += 1,
+            Ok(false) => {}
+            Err(e) => error!("Failed to run scheduled reboot: {}", e),
+        }
+
+        // Tell IT about computers that need a follow-up
+        if let Some(alerts) = &config.admin_alerts {
+            if let Err(e) = send_admin_alerts(config, alerts, &self.db_pool, &self.notification_manager, self.reboot_attempts, self.now()) {
+                warn!("Failed to send admin alert: {:#}", e);
+            }
+        }
+
+        // Show the weekly summary when it's due
+        if let Some(summary_config) = &config.notification.weekly_summary {
+            if summary::is_due(summary_config, self.last_summary, self.now().with_timezone(&Local)) {
+                if let Ok(manager) = self.notification_manager.lock() {
+                    match show_weekly_summary(config, summary_config, &self.db_pool, &manager, self.system_info.as_ref(), self.now()) {
+                        Ok(true) => self.last_summary = Some(self.now()),
+                        Ok(false) => debug!("Weekly summary was held back, trying again on the next check"),
+                        Err(e) => error!("Failed to show weekly summary: {}", e),
                     }
-                } else {
-                    config.reboot.timeframes[0].min_hours.unwrap_or(24) as i64
-                };
+                }
+            }
+        }
 
-                if now - last_check >= Duration::minutes(min_hours * 60) {
-                    debug!("Checking if a reboot is required");
-
-                    // Create detector with current configuration
-                    let detector = RebootDetector::new(&config.reboot);
-
-                    // Check if a reboot is required
-                    match detector.check_reboot_required() {
-                        Ok((required, sources)) => {
-                            // Get current reboot state
-                            let state = match database::get_reboot_state(&db_pool) {
-                                Ok(Some(state)) => state,
-                                Ok(None) => {
-                                    // Create new state
-                                    RebootState::new(required, false)
-                                }
-                                Err(e) => {
-                                    error!("Failed to get reboot state: {}", e);
-                                    continue;
-                                }
-                            };
-
-                            // Update reboot state
-                            let mut new_state = state.clone();
-
-                            // If reboot status changed, update accordingly
-                            if !new_state.reboot_required && required {
-                                // Reboot is now required but wasn't before
-                                info!("Reboot requirement detected for the first time");
-                                new_state.reboot_required_since = Some(now);
-                            } else if new_state.reboot_required && !required {
-                                // Reboot is no longer required (likely after a reboot)
-                                info!("Reboot is no longer required - system was likely rebooted");
-                                new_state.reboot_required_since = None;
-                            }
+        // Clean up the database periodically
+        let maintenance_due = self.last_maintenance.map_or(true, |last| {
+            self.now() - last >= database::maintenance_interval(&config.database.retention)
+        });
+        if maintenance_due {
+            self.last_maintenance = Some(self.now());
+            if let Err(e) = database::run_maintenance(&self.db_pool, &config.database) {
+                error!("Failed to run database maintenance: {}", e);
+            }
+        }
 
-                            new_state.reboot_required = required;
-                            new_state.last_check_time = now;
-                            new_state.updated_at = now;
-
-                            // Update sources
-                            new_state.sources = sources;
-
-                            // Log how long reboot has been required if applicable
-                            if required {
-                                if let Some(required_since) = new_state.reboot_required_since {
-                                    let duration = now.signed_duration_since(required_since);
-                                    let hours = duration.num_hours();
-                                    let minutes = duration.num_minutes() % 60;
-                                    info!("Reboot has been required for {} hours and {} minutes (since {})",
-                                          hours, minutes, required_since);
-                                }
-                            }
+        // Check for updates periodically
+        if config.update.enabled {
+            let interval = crate::update::check_interval(&config.update);
+            if self.last_update_check.map_or(true, |last| self.now() - last >= interval) {
+                self.last_update_check = Some(self.now());
+                if let Err(e) = run_update_check(config) {
+                    error!("Failed to update: {:#}", e);
+                }
+            }
+        }
 
-                            // If reboot is required, show notification
-                            if required && now >= state.next_reminder_time.unwrap_or(now) {
-                                // Get appropriate timeframe
-                                if let Some(timeframe) = reboot::get_timeframe(&config.reboot, &new_state) {
-                                    // Calculate next reminder time
-                                    let next_reminder_time = if let Some(hours) = timeframe.reminder_interval_hours {
-                                        now + Duration::hours(hours as i64)
-                                    } else if let Some(minutes) = timeframe.reminder_interval_minutes {
-                                        now + Duration::minutes(minutes as i64)
-                                    } else {
-                                        now + Duration::hours(1)
-                                    };
-
-                                    new_state.next_reminder_time = Some(next_reminder_time);
-
-                                    // Show notification
-                                    if let Ok(manager) = notification_manager.lock() {
-                                        let message = config.notification.messages.reboot_required.clone();
-
-                                        // Create reboot action if system reboots are enabled
-                                        let action = if config.reboot.system_reboot.enabled {
-                                            Some("reboot:now".to_string())
-                                        } else {
-                                            Some(config.notification.messages.action_required.clone())
-                                        };
-
-                                        if let Err(e) = manager.show_notification("reboot_required", &message, action.as_deref()) {
-                                            error!("Failed to show notification: {}", e);
-                                        }
-
-                                        // Update tray status
-                                        if let Err(e) = manager.update_tray_status("Reboot Required") {
-                                            error!("Failed to update tray status: {}", e);
-                                        }
-
-                                        // Enable reboot and postpone options
-                                        if let Err(e) = manager.enable_reboot_option(true) {
-                                            error!("Failed to enable reboot option: {}", e);
-                                        }
-
-                                        if let Err(e) = manager.enable_postpone_option(true) {
-                                            error!("Failed to enable postpone option: {}", e);
-                                        }
-
-                                        // Set deferral options
-                                        if let Err(e) = manager.set_deferral_options(&timeframe.deferrals) {
-                                            error!("Failed to set deferral options: {}", e);
-                                        }
-                                    }
-                                }
-                            } else if !required {
-                                // Reset next reminder time
-                                new_state.next_reminder_time = None;
-
-                                // Update tray status
-                                if let Ok(manager) = notification_manager.lock() {
-                                    if let Err(e) = manager.update_tray_status("No Reboot Required") {
-                                        error!("Failed to update tray status: {}", e);
-                                    }
-
-                                    // Disable reboot and postpone options
-                                    if let Err(e) = manager.enable_reboot_option(false) {
-                                        error!("Failed to disable reboot option: {}", e);
-                                    }
-
-                                    if let Err(e) = manager.enable_postpone_option(false) {
-                                        error!("Failed to disable postpone option: {}", e);
-                                    }
-                                }
-                            }
+        // Pick up postponements and other changes saved outside this loop
+        let reminder_due = match self.sync_reboot_state(config) {
+            Ok(due) => due,
+            Err(e) => {
+                warn!("Failed to read the reboot state: {:#}", e);
+                false
+            }
+        };
 
-                            // Save reboot state
-                            if let Err(e) = database::save_reboot_state(&db_pool, &new_state) {
-                                error!("Failed to save reboot state: {}", e);
-                            }
+        // Check if it's time to check if a reboot is required
+        let now = self.now();
+        let check_due = match self.last_failed_check {
+            Some(failed) if self.last_check.is_none() => now - failed >= Duration::seconds(FAILED_CHECK_RETRY.as_secs() as i64),
+            _ => reboot::check_due(&config.reboot, self.started, self.last_check, now),
+        };
+        if force_check || reminder_due || check_due {
+            debug!("Checking if a reboot is required");
+
+            // Keep the detector, and the system information it caches, across checks
+            self.detector.set_config(&config.reboot);
+            self.refresh_system_info();
+
+            // Check if a reboot is required
+            let detection = self.detector.detect();
+            let (required, sources) = (detection.required, detection.sources);
+            // Hold back sources that come and go, like pending file renames from antivirus updates
+            let checks = &config.reboot.detection_methods.debounce_checks;
+            let (required, sources) = if checks.values().any(|checks| *checks > 1) {
+                let sources = self.debounce_sources(checks, sources);
+                (reboot::detector::aggregate_severity(&sources).0, sources)
+            } else {
+                (required, sources)
+            };
+
+            // Get current reboot state
+            let state = match database::get_reboot_state(&self.db_pool) {
+                Ok(Some(state)) => state,
+                Ok(None) => {
+                    // Create new state, which becomes required below so the
+                    // first detection counts as a transition
+                    RebootState::new(false, false)
+                }
+                Err(e) => {
+                    error!("Failed to get reboot state: {}", e);
+                    self.last_failed_check = Some(now);
+                    return;
+                }
+            };
+
+            // Methods that failed or timed out keep the sources they reported before
+            let (required, sources) = if detection.unchecked_sources.is_empty() {
+                (required, sources)
+            } else {
+                let sources = reboot::keep_unchecked_sources(&state.sources, &detection.unchecked_sources, sources);
+                (reboot::detector::aggregate_severity(&sources).0, sources)
+            };
 
-                            last_check = now;
+            // Update reboot state
+            let mut new_state = state.clone();
+            let transition = reboot::hooks::transition(state.lifecycle.is_required(), required);
+
+            // If reboot status changed, update accordingly
+            if !new_state.lifecycle.is_required() && required {
+                // Reboot is now required but wasn't before
+                info!("Reboot requirement detected for the first time");
+                if let Err(e) = transition_lifecycle(&mut new_state, RebootLifecycle::RebootPending) {
+                    error!("Failed to update reboot state: {:#}", e);
+                    self.last_failed_check = Some(now);
+                    return;
+                }
+                new_state.reboot_required_since = Some(now);
+                if let Err(e) = database::open_reboot_period(&self.db_pool, now) {
+                    warn!("Failed to record reboot period: {}", e);
+                }
+            } else if new_state.lifecycle.is_required() && !required {
+                // Reboot is no longer required (likely after a reboot)
+                info!("Reboot is no longer required - system was likely rebooted");
+                if let Err(e) = transition_lifecycle(&mut new_state, RebootLifecycle::Completed) {
+                    error!("Failed to update reboot state: {:#}", e);
+                    self.last_failed_check = Some(now);
+                    return;
+                }
+                new_state.reboot_required_since = None;
+                if let Err(e) = database::close_reboot_periods(&self.db_pool, now) {
+                    warn!("Failed to record end of reboot period: {}", e);
+                }
+            }
+
+            new_state.reboot_required = required;
+            new_state.reboot_recommended = reboot::detector::aggregate_severity(&sources).1;
+            new_state.last_check_time = now;
+            new_state.updated_at = now;
+
+            // Update sources, keeping when the ones still found were first detected
+            new_state.sources = reboot::carry_over_sources(&state.sources, sources, now);
+
+            // Only notify the users a per-user reboot applies to
+            if let Ok(manager) = self.notification_manager.lock() {
+                manager.set_affected_users(reboot::affected_users(&new_state.sources));
+            }
+
+            // Let external systems open or close their records
+            if let Some(event) = transition {
+                reboot::hooks::fire(&config.hooks, event, &new_state, config.service.dry_run);
+                self.reports.push(reporting::ReportEvent::state_change(now, event, &new_state));
+            }
+
+            // Log how long reboot has been required if applicable
+            if required {
+                if let Some(required_since) = new_state.reboot_required_since {
+                    let duration = now.signed_duration_since(required_since);
+                    let hours = duration.num_hours();
+                    let minutes = duration.num_minutes() % 60;
+                    info!("Reboot has been required for {} hours and {} minutes (since {})",
+                          hours, minutes, required_since);
+                }
+            }
+
+            // Schedule the forced reboot once the deadline is known
+            let deadline = reboot::get_deadline(&config.reboot, &new_state);
+            if let Some(deadline) = deadline {
+                if new_state.scheduled_reboot_time.is_none()
+                    && new_state.lifecycle.can_transition_to(RebootLifecycle::Scheduled)
+                {
+                    if let Err(e) = transition_lifecycle(&mut new_state, RebootLifecycle::Scheduled) {
+                        error!("Failed to update reboot state: {:#}", e);
+                        self.last_failed_check = Some(now);
+                        return;
+                    }
+                    let forced = reboot::next_maintenance_window(
+                        &config.reboot.maintenance_windows,
+                        ScheduleZone::from_config(&config.service),
+                        deadline,
+                    );
+                    info!("Reboot deadline is {}, forced reboot scheduled for {}", deadline, forced);
+                    new_state.scheduled_reboot_time = Some(forced);
+                    new_state.reboot_reason = Some("Reboot deadline".to_string());
+                }
+            }
+
+            // If reboot is required, show notification, unless the reboot is already underway
+            if required && new_state.lifecycle.allows_reminders() && now >= state.next_reminder_time.unwrap_or(now) {
+                // Hold the reminder back if the system has only just resumed
+                let grace_end = post_resume_grace_end(&config.notification, self.last_resume)
+                    .filter(|end| now < *end);
+
+                if let Some(grace_end) = grace_end {
+                    info!("System resumed recently, rescheduling reminder for {}", grace_end);
+                    new_state.next_reminder_time = Some(grace_end);
+                } else if let Some(timeframe) = reboot::get_timeframe(&config.reboot, &new_state, now) {
+                    // Calculate next reminder time
+                    let next_reminder_time = if let Some(hours) = timeframe.reminder_interval_hours {
+                        now + Duration::hours(hours as i64)
+                    } else if let Some(minutes) = timeframe.reminder_interval_minutes {
+                        now + Duration::minutes(minutes as i64)
+                    } else {
+                        now + Duration::hours(1)
+                    };
+
+                    // Remind more often as the deadline approaches
+                    let next_reminder_time = match deadline {
+                        Some(deadline) => reboot::escalate_reminder_time(next_reminder_time, deadline, now),
+                        None => next_reminder_time,
+                    };
+
+                    new_state.next_reminder_time = Some(next_reminder_time);
+                    if matches!(new_state.lifecycle, RebootLifecycle::RebootPending | RebootLifecycle::Failed) {
+                        if let Err(e) = transition_lifecycle(&mut new_state, RebootLifecycle::ReminderActive) {
+                            error!("Failed to update reboot state: {:#}", e);
+                            self.last_failed_check = Some(now);
+                            return;
                         }
-                        Err(e) => {
-                            error!("Failed to check if reboot is required: {}", e);
+                    }
+
+                    // Show notification
+                    if let Ok(manager) = self.notification_manager.lock() {
+                        manager.set_template_context(TemplateContext::new(
+                            &new_state,
+                            self.system_info.as_ref(),
+                            config.reboot.max_deferrals,
+                        ));
+                        manager.set_severity(timeframe.severity);
+                        manager.set_toast_style(ToastStyle {
+                            sound: timeframe.sound,
+                            priority: timeframe.priority,
+                        });
+
+                        // Deferral options are offered until the reboot has been postponed as often as allowed
+                        let exhausted = reboot::deferrals_exhausted(config.reboot.max_deferrals, &new_state);
+                        let (deferrals, snooze_until_times) = if exhausted {
+                            info!("The reboot has been postponed {} times, no deferral options are offered", new_state.postpone_count);
+                            (&[][..], &[][..])
+                        } else {
+                            (&timeframe.deferrals[..], &config.reboot.snooze_until_times[..])
+                        };
+                        if let Err(e) = manager.set_deferral_options(deferrals, snooze_until_times) {
+                            error!("Failed to set deferral options: {}", e);
+                        }
+
+                        // Create reboot action if system reboots are enabled
+                        let action = if config.reboot.system_reboot.enabled {
+                            Some("reboot:now".to_string())
+                        } else {
+                            Some(config.notification.messages.action_required.clone())
+                        };
+
+                        // A feature update takes much longer to install, so users are warned differently
+                        let result = if reboot::upgrade_pending(&new_state) {
+                            manager.show_message("reboot_required", |messages| &messages.reboot_required_upgrade, action.as_deref())
+                        } else if deadline.is_some() {
+                            manager.show_message("reboot_required", |messages| &messages.reboot_required_deadline, action.as_deref())
+                        } else {
+                            manager.show_message("reboot_required", |messages| &messages.reboot_required, action.as_deref())
+                        };
+                        if let Err(e) = result {
+                            error!("Failed to show notification: {}", e);
                         }
+
+                        // Update tray status
+                        if let Err(e) = manager.update_tray_status("Reboot Required") {
+                            error!("Failed to update tray status: {}", e);
+                        }
+
+                        // Enable reboot and postpone options
+                        if let Err(e) = manager.enable_reboot_option(true) {
+                            error!("Failed to enable reboot option: {}", e);
+                        }
+
+                        if let Err(e) = manager.enable_postpone_option(!exhausted) {
+                            error!("Failed to enable postpone option: {}", e);
+                        }
+                    }
+                }
+            } else if required && new_state.lifecycle.allows_reminders() && config.notification.acknowledgment.required {
+                // Show the last reminder again until it is acknowledged
+                if let Ok(manager) = self.notification_manager.lock() {
+                    if let Err(e) = manager.reprompt_unacknowledged() {
+                        error!("Failed to show unacknowledged reminder again: {}", e);
                     }
                 }
+            } else if !required {
+                // Reset next reminder time
+                new_state.next_reminder_time = None;
+
+                // Update tray status
+                if let Ok(manager) = self.notification_manager.lock() {
+                    if let Err(e) = manager.update_tray_status("No Reboot Required") {
+                        error!("Failed to update tray status: {}", e);
+                    }
 
-                // Sleep for a minute
-                thread::sleep(time::Duration::from_secs(60));
+                    // Disable reboot and postpone options
+                    if let Err(e) = manager.enable_reboot_option(false) {
+                        error!("Failed to disable reboot option: {}", e);
+                    }
+
+                    if let Err(e) = manager.enable_postpone_option(false) {
+                        error!("Failed to disable postpone option: {}", e);
+                    }
+                }
             }
-        })
-    };
 
-    // Tell the service manager we are running - this is a second registration
-    // that will be used for the main service loop, not the initialization
-    let status_handle = match service_control_handler::register(SERVICE_NAME, |control_event| {
-        match control_event {
-            ServiceControl::Stop => {
-                info!("Service stop requested");
-                unsafe {
-                    SERVICE_RUNNING = false;
+            // Show the state in the tray icon
+            if let Ok(manager) = self.notification_manager.lock() {
+                if let Err(e) = manager.update_tray_state(&new_state, reboot::get_deadline(&config.reboot, &new_state)) {
+                    warn!("Failed to update tray state: {}", e);
                 }
-                ServiceControlHandlerResult::NoError
             }
-            ServiceControl::Interrogate => {
-                debug!("Service interrogate requested");
-                ServiceControlHandlerResult::NoError
-            },
-            ServiceControl::PowerEvent(event_type) => {
-                debug!("Power event received: {:?}", event_type);
-                // Handle power events like sleep/resume
-                ServiceControlHandlerResult::NoError
-            },
-            ServiceControl::SessionChange(session_change) => {
-                debug!("Session change event received: {:?}", session_change);
-                // Handle session changes like user logon/logoff
-                ServiceControlHandlerResult::NoError
-            },
-            _ => {
-                debug!("Unhandled service control event: {:?}", control_event);
-                ServiceControlHandlerResult::NotImplemented
-            },
+
+            // Save reboot state, keeping changes like postponements saved during the check
+            match database::save_reboot_state_merged(&self.db_pool, &state, &new_state) {
+                Ok(saved) => self.state_version = Some(saved.version),
+                Err(e) => error!("Failed to save reboot state: {}", e),
+            }
+
+            self.reports.push(reporting::ReportEvent::detection(now, required, &new_state.sources));
+
+            self.last_check = Some(now);
+            self.last_failed_check = None;
         }
-    }) {
-        Ok(handle) => {
-            info!("Service control handler registered successfully");
-            handle
-        },
-        Err(e) => {
-            error!("Failed to register service control handler: {}", e);
-            // If we're not running as a service, we can continue without the service control handler
-            if !unsafe { RUNNING_AS_SERVICE } {
-                info!("Not running as a service, continuing without service control handler");
-                // When not running as a service, we don't need a status handle
-                // Just continue with the main service loop
-
-                // Wait for service to stop
-                while unsafe { SERVICE_RUNNING } {
-                    thread::sleep(time::Duration::from_secs(1));
+
+        // Let inventory tools see the reboot state once it has changed
+        if config.service.registry_status {
+            if let Err(e) = self.publish_status(config) {
+                warn!("Failed to mirror the reboot state to the registry: {:#}", e);
+            }
+        }
+
+        // Let the Intune console show whether the reboot is overdue
+        if let Some(intune) = &config.intune {
+            if let Err(e) = self.report_to_intune(config, intune) {
+                warn!("Failed to report the reboot status to Intune: {:#}", e);
+            }
+        }
+
+        // Record that the service is running, for the status command and the report sinks
+        if let Err(e) = self.record_heartbeat(config) {
+            warn!("Failed to record heartbeat: {:#}", e);
+        }
+
+        // Send the events of this iteration to the report sinks, in batches, in the background
+        self.reports.flush(config.service.dry_run);
+
+        // Keep a snapshot of the metrics for status --verbose
+        crate::metrics::record("service.loop", iteration_started.elapsed());
+        if let Err(e) = crate::metrics::save_snapshot(&self.metrics_path) {
+            debug!("Failed to save metrics: {}", e);
+        }
+    }
+
+    /// Pick up changes other components saved to the reboot state, like postponements chosen in
+    /// notifications or the tray, and show them in the tray
+    ///
+    /// Returns whether a reminder has become due since the last check, so it's shown without
+    /// waiting for the next one.
+    fn sync_reboot_state(&mut self, config: &Config) -> Result<bool> {
+        let state = match database::get_reboot_state(&self.db_pool)? {
+            Some(state) => state,
+            None => return Ok(false),
+        };
+
+        if self.state_version != Some(state.version) {
+            if self.state_version.is_some() {
+                info!(
+                    "Reboot state was changed outside the service loop (version {}, {} postponements, next reminder at {:?})",
+                    state.version, state.postpone_count, state.next_reminder_time
+                );
+            }
+            self.state_version = Some(state.version);
+
+            if let Ok(manager) = self.notification_manager.lock() {
+                if let Err(e) = manager.update_tray_state(&state, reboot::get_deadline(&config.reboot, &state)) {
+                    warn!("Failed to update tray state: {}", e);
                 }
+            }
+        }
+
+        Ok(reminder_due(&state, self.last_check, self.now()))
+    }
 
-                // Wait for threads to finish
-                config_refresh_thread
-                    .join()
-                    .map_err(|_| anyhow::anyhow!("Failed to join configuration refresh thread"))?;
+    /// Save a heartbeat and queue it for the report sinks when one is due; the first one is
+    /// recorded right after startup
+    fn record_heartbeat(&mut self, config: &Config) -> Result<()> {
+        let now = self.now();
+        let interval = crate::utils::timespan::parse_timespan(&config.service.heartbeat_interval)
+            .ok()
+            .and_then(|interval| Duration::from_std(interval).ok())
+            .unwrap_or_else(|| Duration::hours(1));
+        if self.last_heartbeat.is_some_and(|last| now - last < interval) {
+            return Ok(());
+        }
 
-                reboot_check_thread
-                    .join()
-                    .map_err(|_| anyhow::anyhow!("Failed to join reboot check thread"))?;
+        let state = database::get_reboot_state(&self.db_pool)?.unwrap_or_else(|| RebootState::new(false, false));
+        let heartbeat = database::Heartbeat::new(now, &config::config_hash(config), &state);
+        database::save_heartbeat(&self.db_pool, &heartbeat)?;
+        self.reports.push(reporting::ReportEvent::heartbeat(&heartbeat));
+        self.last_heartbeat = Some(now);
+        Ok(())
+    }
 
-                info!("Service stopped");
+    /// Mirror the reboot state to the registry when it was saved, or its deadline moved, since
+    /// it was last mirrored
+    ///
+    /// Changes made outside the service loop, like postponements from the tray, are picked up
+    /// by the next iteration.
+    fn publish_status(&mut self, config: &Config) -> Result<()> {
+        let state = match database::get_reboot_state(&self.db_pool)? {
+            Some(state) => state,
+            None => return Ok(()),
+        };
+
+        let deadline = reboot::get_deadline(&config.reboot, &state);
+        let status = Some((state.id, state.version, deadline));
+        if status != self.published_status {
+            crate::support::inventory::publish(&state, deadline)?;
+            self.published_status = status;
+        }
+        Ok(())
+    }
+
+    /// Report the reboot status to Intune when it changed or the refresh interval has passed
+    ///
+    /// The report is sent on a thread of its own, since Microsoft Graph and `dsregcmd` can take a
+    /// while to answer, and its outcome is picked up by a later iteration. A failed report is
+    /// tried again after a while rather than on every iteration.
+    fn report_to_intune(&mut self, config: &Config, intune: &config::IntuneConfig) -> Result<()> {
+        let now = self.now();
+        if let Some((value, started, report)) = self.intune_pending.take() {
+            if !report.is_finished() {
+                self.intune_pending = Some((value, started, report));
                 return Ok(());
             }
-            return Err(anyhow::anyhow!("Failed to register service control handler: {}", e));
+            match report.join() {
+                Ok(Ok(())) => {
+                    self.intune_failed = None;
+                    self.intune_reported = Some((value, started));
+                }
+                Ok(Err(e)) => {
+                    self.intune_failed = Some(now);
+                    return Err(e);
+                }
+                Err(_) => {
+                    self.intune_failed = Some(now);
+                    return Err(anyhow::anyhow!("The Intune report thread panicked"));
+                }
+            }
+        }
+
+        if self.intune_failed.is_some_and(|failed| now - failed < Duration::minutes(reporting::intune::RETRY_MINUTES)) {
+            return Ok(());
+        }
+
+        let state = match database::get_reboot_state(&self.db_pool)? {
+            Some(state) => state,
+            None => return Ok(()),
+        };
+        let deadline = reboot::get_deadline(&config.reboot, &state);
+        let value = reporting::intune::attribute_value(reporting::intune::status(intune, &state, deadline, now), &state);
+
+        let refresh = crate::utils::timespan::parse_timespan(&intune.refresh_interval)
+            .ok()
+            .and_then(|refresh| Duration::from_std(refresh).ok())
+            .unwrap_or_else(|| Duration::hours(24));
+        let due = match &self.intune_reported {
+            Some((reported, at)) => *reported != value || now - *at >= refresh,
+            None => true,
+        };
+        if !due {
+            return Ok(());
+        }
+
+        if config.service.dry_run {
+            info!("[dry-run] Would report reboot status '{}' to Intune", value);
+            self.intune_reported = Some((value, now));
+            return Ok(());
+        }
+
+        let report = {
+            let intune = intune.clone();
+            let value = value.clone();
+            thread::Builder::new()
+                .name("intune".to_string())
+                .spawn(move || reporting::intune::report(&intune, &value))
+                .context("Failed to start the Intune report thread")?
+        };
+        self.intune_pending = Some((value, now, report));
+        Ok(())
+    }
+
+    /// Get how long to wait before the next iteration: a minute, or until the first check is due
+    ///
+    /// While the first check keeps failing, it's tried again every `FAILED_CHECK_RETRY` instead.
+    fn sleep_time(&self, config: &Config) -> time::Duration {
+        match self.last_check {
+            None if self.last_failed_check.is_some() => FAILED_CHECK_RETRY,
+            None => (self.started + reboot::startup_check_delay(&config.reboot) - self.now())
+                .to_std()
+                .unwrap_or(time::Duration::from_secs(1))
+                .clamp(time::Duration::from_secs(1), time::Duration::from_secs(60)),
+            Some(_) => time::Duration::from_secs(60),
         }
+    }
+}
+
+/// Stop the service when a worker thread keeps panicking, so the watchdog or the service
+/// recovery options can start it again
+fn stop_after_worker_failure(shutdown: &ShutdownSignal) -> impl FnOnce() + Send + 'static {
+    let shutdown = shutdown.clone();
+    move || shutdown.fail()
+}
+
+/// Move a reboot state to another lifecycle stage
+///
+/// A transition the lifecycle doesn't allow leaves the state as it was and is returned as an
+/// error, so the caller neither saves the state nor starts what the new stage stands for.
+fn transition_lifecycle(state: &mut RebootState, next: RebootLifecycle) -> Result<()> {
+    state
+        .transition(next)
+        .with_context(|| format!("Refusing to update reboot state version {}", state.version))
+}
+
+/// Run the scheduled reboot if it is due at `now`, unless a blocking process is running
+///
+/// A blocked, declined or failed reboot is pushed forward by the configured delay, and the
+/// reason is recorded in the reboot state. Returns whether a reboot was attempted.
+fn run_scheduled_reboot(config: &Config, db_pool: &DbPool, now: DateTime<Utc>) -> Result<bool> {
+    let base = match database::get_reboot_state(db_pool)? {
+        Some(state) => state,
+        None => return Ok(false),
     };
+    let mut state = base.clone();
 
-    // Set the service status to Running using our helper function
-    update_service_status(&status_handle, ServiceState::Running, 0, 0, ServiceControlAccept::STOP)
-        .context("Failed to set service status to Running")?;
+    match state.scheduled_reboot_time {
+        Some(scheduled) if state.reboot_required && scheduled <= now => {}
+        _ => return Ok(false),
+    }
 
-    // Wait for service to stop
-    while unsafe { SERVICE_RUNNING } {
-        thread::sleep(time::Duration::from_secs(1));
+    let system_reboot = &config.reboot.system_reboot;
+    if !system_reboot.enabled {
+        debug!("Scheduled reboot is due but system reboots are disabled");
+        return Ok(false);
     }
 
-    // Wait for threads to finish
-    config_refresh_thread
-        .join()
-        .map_err(|_| anyhow::anyhow!("Failed to join configuration refresh thread"))?;
+    let delay = crate::utils::timespan::parse_timespan(&system_reboot.blocked_reboot_delay)
+        .ok()
+        .and_then(|delay| Duration::from_std(delay).ok())
+        .unwrap_or_else(|| Duration::minutes(30));
 
-    reboot_check_thread
-        .join()
-        .map_err(|_| anyhow::anyhow!("Failed to join reboot check thread"))?;
+    if let Some(process) = reboot::system::find_blocking_process(&system_reboot.blocking_processes) {
+        let next_time = now + delay;
+        info!("Scheduled reboot blocked by running process {}, deferring until {}", process, next_time);
+
+        state.scheduled_reboot_time = Some(next_time);
+        state.reboot_reason = Some(format!("Scheduled reboot deferred: {} is running", process));
+        state.updated_at = now;
+        database::save_reboot_state_merged(db_pool, &base, &state)?;
+        return Ok(false);
+    }
+
+    // Refuse before rebooting rather than after, when it's too late to undo
+    if !config.service.dry_run {
+        state.check_transition(RebootLifecycle::Imminent)?;
+    }
+
+    info!("Running scheduled reboot");
+    let mut reboot_config = reboot::system::RebootConfig::from(system_reboot);
+    reboot_config.dry_run = config.service.dry_run;
+
+    // Past the deadline the user can no longer cancel the reboot
+    let forced = reboot::get_deadline(&config.reboot, &state).is_some_and(|deadline| deadline <= now);
+    if forced {
+        info!("Reboot deadline has passed, forcing the reboot");
+        reboot_config.show_confirmation = false;
+    }
+    let attempt = reboot::system::reboot_system(&reboot_config).unwrap_or_else(|e| {
+        error!("Scheduled reboot failed: {:#}", e);
+        reboot::system::RebootAttempt::default()
+    });
+    let proceeded = attempt.proceeded;
+    attempt.record_restart_applications(&reboot_config, db_pool);
+
+    // Users couldn't save their work, so record what the forced reboot closed
+    if forced {
+        let mut history = database::RebootHistory::new(now, proceeded);
+        history.source = Some(reboot::DEADLINE_SOURCE.to_string());
+        history.reason = Some(if config.service.dry_run {
+            "Dry run: reboot would have been forced after the deadline".to_string()
+        } else {
+            "Reboot deadline passed".to_string()
+        });
+        history.computer_name = std::env::var("COMPUTERNAME").ok();
+        history.closed_applications = attempt.application_labels();
+        database::add_reboot_history(db_pool, &history)?;
+    }
+    if proceeded {
+        state.scheduled_reboot_time = None;
+        if !config.service.dry_run {
+            state.reboot_initiated_at = Some(now);
+            transition_lifecycle(&mut state, RebootLifecycle::Imminent)?;
+        }
+        state.reboot_reason = Some(if config.service.dry_run {
+            "Dry run: scheduled reboot would have run".to_string()
+        } else {
+            "Scheduled reboot".to_string()
+        });
+    } else {
+        let next_time = now + delay;
+        info!("Scheduled reboot did not proceed, deferring until {}", next_time);
+        state.scheduled_reboot_time = Some(next_time);
+        state.reboot_reason = Some("Scheduled reboot deferred".to_string());
+    }
+
+    state.updated_at = now;
+    database::save_reboot_state_merged(db_pool, &base, &state)?;
+    Ok(!config.service.dry_run)
+}
+
+/// Reboot when nobody has been signed in for the grace period, recording it in the history
+///
+/// Keeps track of when the last session ended in `no_sessions_since`. Returns whether a reboot
+/// was attempted.
+fn run_unattended_reboot(
+    config: &Config,
+    db_pool: &DbPool,
+    no_sessions_since: &mut Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> Result<bool> {
+    if !config.reboot.auto_reboot_when_no_sessions || !config.reboot.system_reboot.enabled {
+        *no_sessions_since = None;
+        return Ok(false);
+    }
+
+    // Users who disconnected are still signed in, with their work open
+    let sessions = Impersonator::new().get_signed_in_sessions()?;
+    if sessions.is_empty() {
+        no_sessions_since.get_or_insert(now);
+    } else {
+        if sessions.iter().all(|session| !session.is_active) {
+            debug!("Unattended reboot held back by {} disconnected session(s)", sessions.len());
+        }
+        *no_sessions_since = None;
+        return Ok(false);
+    }
+
+    let base = match database::get_reboot_state(db_pool)? {
+        Some(state) => state,
+        None => return Ok(false),
+    };
+    if !reboot::unattended_reboot_due(&config.reboot, &base, *no_sessions_since, now) {
+        return Ok(false);
+    }
+    if let Some(process) = reboot::system::find_blocking_process(&config.reboot.system_reboot.blocking_processes) {
+        debug!("Unattended reboot blocked by running process {}", process);
+        return Ok(false);
+    }
+
+    // Refuse before rebooting rather than after, when it's too late to undo
+    if !config.service.dry_run {
+        base.check_transition(RebootLifecycle::Imminent)?;
+    }
+
+    info!("Nobody has been signed in since {}, rebooting", reboot::format_time(no_sessions_since.unwrap_or(now)));
+    let mut reboot_config = reboot::system::RebootConfig::from(&config.reboot.system_reboot);
+    reboot_config.dry_run = config.service.dry_run;
+    reboot_config.show_confirmation = false;
+    let attempt = reboot::system::reboot_system(&reboot_config).unwrap_or_else(|e| {
+        error!("Unattended reboot failed: {:#}", e);
+        reboot::system::RebootAttempt::default()
+    });
+    let proceeded = attempt.proceeded;
+    attempt.record_restart_applications(&reboot_config, db_pool);
+
+    let mut history = database::RebootHistory::new(now, proceeded);
+    history.source = Some(reboot::UNATTENDED_SOURCE.to_string());
+    history.reason = Some(if config.service.dry_run {
+        "Dry run: unattended reboot would have run".to_string()
+    } else {
+        "No users signed in".to_string()
+    });
+    history.computer_name = std::env::var("COMPUTERNAME").ok();
+    history.closed_applications = attempt.application_labels();
+    database::add_reboot_history(db_pool, &history)?;
+
+    let mut state = base.clone();
+    state.reboot_reason = history.reason.clone();
+    if proceeded && !config.service.dry_run {
+        state.reboot_initiated_at = Some(now);
+        transition_lifecycle(&mut state, RebootLifecycle::Imminent)?;
+    }
+    state.updated_at = now;
+    database::save_reboot_state_merged(db_pool, &base, &state)?;
+
+    // Wait for another grace period before trying again
+    *no_sessions_since = None;
+    Ok(proceeded && !config.service.dry_run)
+}
+
+/// Schedule the reboot for `now` when every signed-in user has been idle longer than configured
+///
+/// Sessions whose idle time is unknown count as active, and without sessions nothing is scheduled.
+fn schedule_idle_reboot(config: &Config, db_pool: &DbPool, now: DateTime<Utc>) -> Result<()> {
+    let threshold = match &config.reboot.idle_reboot_after {
+        Some(after) => crate::utils::timespan::parse_timespan(after)?,
+        None => return Ok(()),
+    };
+
+    let base = match database::get_reboot_state(db_pool)? {
+        Some(state) if state.reboot_required => state,
+        _ => return Ok(()),
+    };
+    if base.scheduled_reboot_time.is_some_and(|time| time <= now)
+        || !base.lifecycle.can_transition_to(RebootLifecycle::Scheduled)
+    {
+        return Ok(());
+    }
+
+    let impersonator = Impersonator::new();
+    let sessions = impersonator.get_active_sessions()?;
+    let idle = match impersonator.shortest_idle_time(&sessions) {
+        Some(idle) if idle >= threshold => idle,
+        _ => return Ok(()),
+    };
+
+    let idle = reboot::format_duration(Duration::from_std(idle).unwrap_or_else(|_| Duration::zero()));
+    info!("All users have been idle for {}, scheduling the reboot now", idle);
+    let mut state = base.clone();
+    transition_lifecycle(&mut state, RebootLifecycle::Scheduled)?;
+    state.scheduled_reboot_time = Some(now);
+    state.reboot_reason = Some(format!("Users idle for {}", idle));
+    state.updated_at = now;
+    database::save_reboot_state_merged(db_pool, &base, &state).map(|_| ())
+}
+
+/// Alert IT when a reboot has been pending too long or forced reboots keep failing
+///
+/// Each alert is sent once: the pending alert once per pending reboot, and the failed reboot
+/// alert once the attempts reach the configured number.
+fn send_admin_alerts(
+    config: &Config,
+    alerts: &AdminAlertsConfig,
+    db_pool: &DbPool,
+    notification_manager: &Arc<Mutex<NotificationManager>>,
+    reboot_attempts: u32,
+    now: DateTime<Utc>,
+) -> Result<()> {
+    let state = match database::get_reboot_state(db_pool)? {
+        Some(state) if state.reboot_required => state,
+        _ => return Ok(()),
+    };
+
+    let last_alert = database::get_last_notification_time(db_pool, admin::PENDING_ALERT_TYPE)?;
+    let pending_due = admin::pending_alert_due(alerts, &state, last_alert, now);
+    let reboots_failed = reboot_attempts == alerts.max_failed_reboots
+        && database::get_last_notification_time(db_pool, admin::REBOOT_FAILED_ALERT_TYPE)?
+            .map_or(true, |last| state.reboot_required_since.map_or(false, |since| last < since));
+    if !pending_due && !reboots_failed {
+        return Ok(());
+    }
+
+    let users = notification_manager
+        .lock()
+        .map(|manager| manager.active_user_names())
+        .unwrap_or_default();
+
+    if pending_due {
+        let pending = state
+            .reboot_required_since
+            .map(|since| reboot::format_duration(now.signed_duration_since(since)))
+            .unwrap_or_default();
+        let text = format!("A reboot has been pending for {}.", pending);
+        let alert = admin::AdminAlert::new(admin::PENDING_ALERT_TYPE, &text, &state, &users);
+        admin::send(alerts, db_pool, &alert, config.service.dry_run)?;
+    }
+
+    if reboots_failed {
+        let text = format!("{} forced reboot attempts did not restart the computer.", reboot_attempts);
+        let alert = admin::AdminAlert::new(admin::REBOOT_FAILED_ALERT_TYPE, &text, &state, &users);
+        admin::send(alerts, db_pool, &alert, config.service.dry_run)?;
+    }
 
-    info!("Service stopped");
     Ok(())
 }
 
+/// Download an update if one is available and hand over to the updater to install it
+fn run_update_check(config: &Config) -> Result<()> {
+    let manifest = match crate::update::check_for_update(&config.update)? {
+        Some(manifest) => manifest,
+        None => return Ok(()),
+    };
+
+    if config.service.dry_run {
+        info!("[dry-run] Would install update {}", manifest.version);
+        return Ok(());
+    }
+
+    let staged = crate::update::download_update(&config.update, &manifest)?;
+    crate::update::launch_apply(&staged, &config.service.name)
+}
+
+/// Interval between countdown notifications before a scheduled reboot
+const COUNTDOWN_INTERVAL_MINUTES: i64 = 10;
+
+/// Show a countdown notification when a scheduled reboot is less than an hour away
+fn show_reboot_countdown(
+    config: &Config,
+    db_pool: &DbPool,
+    manager: &NotificationManager,
+    system_info: Option<&reboot::detector::SystemInfo>,
+    last_countdown: &mut Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> Result<()> {
+    let state = match database::get_reboot_state(db_pool)? {
+        Some(state) => state,
+        None => return Ok(()),
+    };
+
+    let remaining = match state.scheduled_reboot_time {
+        Some(scheduled) if state.reboot_required && config.reboot.system_reboot.enabled => scheduled.signed_duration_since(now),
+        _ => return Ok(()),
+    };
+
+    if remaining <= Duration::zero() || remaining > Duration::minutes(reboot::DEADLINE_COUNTDOWN_MINUTES) {
+        return Ok(());
+    }
+
+    if last_countdown.is_some_and(|last| now.signed_duration_since(last) < Duration::minutes(COUNTDOWN_INTERVAL_MINUTES)) {
+        return Ok(());
+    }
+
+    let minutes_remaining = (remaining.num_seconds() + 59) / 60;
+    debug!("Scheduled reboot in {} minutes, showing countdown", minutes_remaining);
+
+    let mut context = TemplateContext::new(&state, system_info, config.reboot.max_deferrals);
+    context.set("minutes_remaining", minutes_remaining.to_string());
+    manager.set_template_context(context);
+
+    *last_countdown = Some(now);
+    manager.show_message("reboot_countdown", |messages| &messages.reboot_countdown, None)?;
+    manager.update_tray_status(&format!("Reboot in {} minutes", minutes_remaining))
+}
+
+/// Show the weekly summary of the pending reboot, if a reboot is required
+///
+/// Returns whether the summary is done for the week: shown, or not needed since no reboot is
+/// required. A summary held back by quiet hours, a busy user or an empty desktop isn't recorded,
+/// so it's tried again while it's still due.
+fn show_weekly_summary(
+    config: &Config,
+    summary_config: &WeeklySummaryConfig,
+    db_pool: &DbPool,
+    manager: &NotificationManager,
+    system_info: Option<&reboot::detector::SystemInfo>,
+    now: DateTime<Utc>,
+) -> Result<bool> {
+    let state = match database::get_reboot_state(db_pool)? {
+        Some(state) if state.reboot_required => state,
+        _ => {
+            debug!("No reboot required, skipping weekly summary");
+            return Ok(true);
+        }
+    };
+    let history = database::get_reboot_history(db_pool, Some(50))?;
+
+    let mut context = TemplateContext::new(&state, system_info, config.reboot.max_deferrals);
+    summary::add_summary_values(&mut context, &state, &history, now);
+    manager.set_template_context(context);
+
+    info!("Showing weekly summary");
+    let last_id = || -> Result<Option<uuid::Uuid>> {
+        Ok(database::get_last_notification(db_pool, summary::SUMMARY_NOTIFICATION_TYPE)?
+            .filter(|notification| !matches!(notification.delivery_status, database::DeliveryStatus::Skipped))
+            .map(|notification| notification.id))
+    };
+    let before = last_id()?;
+    manager.show_notification(summary::SUMMARY_NOTIFICATION_TYPE, &summary_config.message, None)?;
+    Ok(config.service.dry_run || last_id()?.is_some_and(|id| Some(id) != before))
+}
+
+/// Check if the reminder of a reboot state came due after the last check, which hasn't shown it yet
+fn reminder_due(state: &RebootState, last_check: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+    let Some(next_reminder_time) = state.next_reminder_time else {
+        return false;
+    };
+    state.reboot_required
+        && state.lifecycle.allows_reminders()
+        && next_reminder_time <= now
+        && last_check.is_some_and(|last_check| last_check < next_reminder_time)
+}
+
+/// Get the end of the post-resume grace period, if one is configured and a resume has been seen
+fn post_resume_grace_end(config: &NotificationConfig, last_resume: Option<DateTime<Utc>>) -> Option<DateTime<Utc>> {
+    let last_resume = last_resume?;
+    let grace = config.post_resume_grace.as_deref()?;
+
+    match reboot::parse_deferral(grace) {
+        Ok(duration) => Some(last_resume + duration),
+        Err(e) => {
+            warn!("Failed to parse post-resume grace timespan: {}", e);
+            None
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::{Config, DatabaseConfig, LoggingConfig, NotificationConfig, BrandingConfig, ServiceConfig, RebootConfig, DetectionMethodsConfig, NotificationType, QuietHoursConfig, MessagesConfig, WatchdogConfig};
+    use crate::config::{Config, DatabaseConfig, LoggingConfig, NotificationConfig, BrandingConfig, ServiceConfig, RebootConfig, DetectionMethodsConfig, NotificationType, QuietHoursConfig, MessagesConfig, WatchdogConfig, NetworkConfig, UpdateConfig, DeliveryRetryConfig, StateIconsConfig, MessageBoxConfig, AcknowledgmentConfig, WindowPlacement};
+    use std::collections::HashMap;
     use tempfile::tempdir;
 
     #[test]
@@ -918,6 +2238,10 @@ mod tests {
                 display_name: "Test Service".to_string(),
                 description: "Test service description".to_string(),
                 config_refresh_minutes: 60,
+                dry_run: false,
+                time_zone: None,
+                registry_status: true,
+                heartbeat_interval: "1h".to_string(),
             },
             notification: NotificationConfig {
                 notification_type: Some(NotificationType::Both),
@@ -928,23 +2252,58 @@ mod tests {
                     title: "Test Title".to_string(),
                     icon_path: icon_path,
                     company: "Test Company".to_string(),
+                    state_icons: StateIconsConfig::default(),
+                    subtitle_template: None,
+                    asset_tag_registry_key: None,
+                    asset_tag_registry_value: "AssetTag".to_string(),
                 },
                 messages: MessagesConfig::default(),
+                locales: HashMap::new(),
                 quiet_hours: QuietHoursConfig::default(),
+                post_resume_grace: None,
+                respect_user_availability: true,
+                weekly_summary: None,
+                suppressed_accounts: Vec::new(),
+                delivery_retry: DeliveryRetryConfig::default(),
+                max_idle_time: None,
+                duplicate_interval: None,
+                message_box: MessageBoxConfig::default(),
+                acknowledgment: AcknowledgmentConfig::default(),
+                window_placement: WindowPlacement::default(),
             },
             reboot: RebootConfig {
                 timeframes: vec![],
                 detection_methods: DetectionMethodsConfig::default(),
                 system_reboot: config::models::default_system_reboot_config(),
+                max_deferrals: None,
+                deadline: None,
+                upgrade_deadline: None,
+                use_wmi: true,
+                maintenance_windows: Vec::new(),
+                snooze_until_times: Vec::new(),
+                check_interval: "15m".to_string(),
+                startup_check_delay: "30s".to_string(),
+                idle_reboot_after: None,
+                auto_reboot_when_no_sessions: false,
+                no_sessions_grace: "15m".to_string(),
+                unattended_reboot_after: "1h".to_string(),
             },
             database: DatabaseConfig {
                 path: db_path,
+                retention: Default::default(),
+                busy_timeout_ms: 5000,
+                max_connections: 4,
+                min_idle: None,
+                connection_timeout_seconds: 30,
             },
             logging: LoggingConfig {
                 path: log_path,
                 level: "info".to_string(),
                 max_files: 5,
                 max_size: 10,
+                log_sql: false,
+                redact_usernames: false,
+                log_level_overrides: std::collections::HashMap::new(),
             },
             watchdog: WatchdogConfig {
                 enabled: true,
@@ -955,7 +2314,19 @@ mod tests {
                 restart_delay: Some("10s".to_string()),
                 service_path: "".to_string(),
                 service_name: "TestService".to_string(),
+                restore_start_type: false,
+                crash_report: None,
             },
+            network: NetworkConfig::default(),
+            update: UpdateConfig::default(),
+            profiles: Vec::new(),
+            hooks: Vec::new(),
+            admin_alerts: None,
+            status_page: None,
+            intune: None,
+            log_analytics: None,
+            report_sinks: Vec::new(),
+            includes: Vec::new(),
         };
 
         // Ensure directories exist
@@ -967,4 +2338,58 @@ mod tests {
         assert!(log_dir.exists());
         assert!(icon_dir.exists());
     }
+
+    #[test]
+    fn test_post_resume_grace_end() {
+        let mut notification = config::default().notification;
+        notification.post_resume_grace = Some("10m".to_string());
+        let resumed = Utc::now();
+
+        // No resume seen yet
+        assert!(post_resume_grace_end(&notification, None).is_none());
+
+        // Grace period ends ten minutes after the resume
+        assert_eq!(post_resume_grace_end(&notification, Some(resumed)), Some(resumed + Duration::minutes(10)));
+
+        // No grace period configured
+        notification.post_resume_grace = None;
+        assert!(post_resume_grace_end(&notification, Some(resumed)).is_none());
+    }
+
+    #[test]
+    fn test_reminder_due() {
+        let now = Utc::now();
+        let mut state = RebootState::new(true, false);
+        state.lifecycle = RebootLifecycle::ReminderActive;
+        let last_check = Some(now - Duration::minutes(30));
+
+        // Postponed for 15 minutes after the last check
+        state.next_reminder_time = Some(now - Duration::minutes(5));
+        assert!(reminder_due(&state, last_check, now));
+
+        // Not due yet, or already handled by a later check
+        state.next_reminder_time = Some(now + Duration::minutes(5));
+        assert!(!reminder_due(&state, last_check, now));
+        state.next_reminder_time = Some(now - Duration::hours(1));
+        assert!(!reminder_due(&state, last_check, now));
+
+        // Before the first check, the check runs anyway
+        state.next_reminder_time = Some(now - Duration::minutes(5));
+        assert!(!reminder_due(&state, None, now));
+
+        // No reminders once no reboot is required
+        state.reboot_required = false;
+        assert!(!reminder_due(&state, last_check, now));
+    }
+
+    #[test]
+    fn test_transition_lifecycle() {
+        let mut state = RebootState::new(true, false);
+        transition_lifecycle(&mut state, RebootLifecycle::Scheduled).unwrap();
+        transition_lifecycle(&mut state, RebootLifecycle::Completed).unwrap();
+
+        // A completed reboot can't be scheduled again, and the state stays as it was
+        assert!(transition_lifecycle(&mut state, RebootLifecycle::Scheduled).is_err());
+        assert_eq!(state.lifecycle, RebootLifecycle::Completed);
+    }
 }
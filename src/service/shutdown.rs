@@ -0,0 +1,82 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// Stop request shared by the service control handler and the worker threads
+///
+/// The control handler requests the stop, and workers wait on the signal instead of sleeping so
+/// they notice it right away. A worker that gives up requests the stop as a failure, so the
+/// service reports an error to the service manager when it stops.
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownSignal(Arc<(Mutex<bool>, Condvar)>, Arc<AtomicBool>);
+
+impl ShutdownSignal {
+    /// Ask the service to stop, waking up the threads waiting on the signal
+    pub fn request(&self) {
+        let (requested, condvar) = &*self.0;
+        *requested.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        condvar.notify_all();
+    }
+
+    /// Ask the service to stop because it can't go on
+    pub fn fail(&self) {
+        self.1.store(true, Ordering::SeqCst);
+        self.request();
+    }
+
+    /// Check whether the service was asked to stop because it can't go on
+    pub fn has_failed(&self) -> bool {
+        self.1.load(Ordering::SeqCst)
+    }
+
+    /// Check whether the service has been asked to stop
+    pub fn is_requested(&self) -> bool {
+        *self.0 .0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Wait until the timeout elapses or the service is asked to stop, returning whether it was
+    pub fn wait(&self, timeout: Duration) -> bool {
+        let (requested, condvar) = &*self.0;
+        let requested = requested.lock().unwrap_or_else(|e| e.into_inner());
+        let (requested, _) = condvar
+            .wait_timeout_while(requested, timeout, |requested| !*requested)
+            .unwrap_or_else(|e| e.into_inner());
+        *requested
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Instant;
+
+    #[test]
+    fn test_wait_wakes_up_on_request() {
+        let shutdown = ShutdownSignal::default();
+        assert!(!shutdown.wait(Duration::from_millis(1)));
+
+        let waiter = {
+            let shutdown = shutdown.clone();
+            thread::spawn(move || {
+                let started = Instant::now();
+                (shutdown.wait(Duration::from_secs(60)), started.elapsed())
+            })
+        };
+        shutdown.request();
+
+        let (requested, waited) = waiter.join().unwrap();
+        assert!(requested);
+        assert!(waited < Duration::from_secs(60));
+        assert!(shutdown.is_requested());
+        assert!(!shutdown.has_failed());
+    }
+
+    #[test]
+    fn test_fail_requests_the_stop() {
+        let shutdown = ShutdownSignal::default();
+        shutdown.clone().fail();
+        assert!(shutdown.is_requested());
+        assert!(shutdown.has_failed());
+    }
+}
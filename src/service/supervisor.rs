@@ -0,0 +1,124 @@
+use log::{error, info, warn};
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Once;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Restarts after which a worker that keeps panicking stops the service
+const MAX_RESTARTS: u32 = 5;
+
+/// How long a worker has to run before its earlier panics are forgiven
+const STABLE_RUN: Duration = Duration::from_secs(3600);
+
+/// Delay before a worker is restarted, so a panic on every iteration doesn't spin
+const RESTART_DELAY: Duration = if cfg!(test) { Duration::from_millis(10) } else { Duration::from_secs(10) };
+
+static PANIC_HOOK: Once = Once::new();
+
+/// Run a worker in a named thread, restarting it when it panics
+///
+/// The worker is expected to return once the service is stopping. When it panics more than
+/// `MAX_RESTARTS` times without running stably in between, `give_up` is called to stop the
+/// service instead of leaving it running without the worker.
+pub fn spawn<F, G>(name: &str, mut worker: F, give_up: G) -> std::io::Result<JoinHandle<()>>
+where
+    F: FnMut() + Send + 'static,
+    G: FnOnce() + Send + 'static,
+{
+    install_panic_hook();
+
+    let name = name.to_string();
+    thread::Builder::new().name(name.clone()).spawn(move || {
+        let mut restarts = 0;
+        loop {
+            let started = Instant::now();
+            let payload = match panic::catch_unwind(AssertUnwindSafe(&mut worker)) {
+                Ok(()) => break,
+                Err(payload) => payload,
+            };
+
+            crate::metrics::increment("worker.restart");
+            if started.elapsed() >= STABLE_RUN {
+                restarts = 0;
+            }
+            restarts += 1;
+
+            if restarts > MAX_RESTARTS {
+                error!(
+                    "{} thread panicked {} times ({}), stopping the service",
+                    name,
+                    restarts,
+                    panic_message(payload.as_ref())
+                );
+                give_up();
+                break;
+            }
+
+            warn!(
+                "{} thread panicked ({}), restarting it in {:?} (restart {} of {})",
+                name,
+                panic_message(payload.as_ref()),
+                RESTART_DELAY,
+                restarts,
+                MAX_RESTARTS
+            );
+            thread::sleep(RESTART_DELAY);
+            info!("Restarting {} thread", name);
+        }
+    })
+}
+
+/// Log panics with their backtrace, which is no longer available once the panic is caught
+fn install_panic_hook() {
+    PANIC_HOOK.call_once(|| {
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let thread = thread::current();
+            error!(
+                "Thread {} panicked: {}\n{}",
+                thread.name().unwrap_or("<unnamed>"),
+                info,
+                Backtrace::force_capture()
+            );
+            default_hook(info);
+        }));
+    });
+}
+
+/// Get the message a panic was raised with
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_spawn_restarts_after_panic() {
+        let runs = Arc::new(AtomicU32::new(0));
+        let worker_runs = runs.clone();
+        let handle = spawn(
+            "test-worker",
+            move || {
+                if worker_runs.fetch_add(1, Ordering::SeqCst) == 0 {
+                    panic!("first run fails");
+                }
+            },
+            || panic!("should not give up"),
+        )
+        .unwrap();
+
+        handle.join().unwrap();
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+        assert_eq!(panic_message(&"message"), "message");
+    }
+}
@@ -0,0 +1,166 @@
+use crate::config::policy::escape_xml;
+use crate::config::Config;
+use anyhow::{Context, Result};
+use log::{info, warn};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time;
+
+/// Longest a single run of the task may take before Task Scheduler stops it
+const EXECUTION_TIME_LIMIT: &str = "PT30M";
+
+/// Options for installing the scheduled task
+#[derive(Debug, Clone)]
+pub struct TaskOptions {
+    /// Configuration file the task loads (None = config.json next to the executable)
+    pub config_path: Option<PathBuf>,
+
+    /// How often the task runs while the computer is on, besides at every logon
+    pub interval: time::Duration,
+}
+
+/// Register a scheduled task that runs `run --once` at logon and then periodically
+///
+/// For environments that don't allow extra services. The task runs as SYSTEM like the service
+/// and shares its configuration and database, but only runs one reboot check cycle at a time.
+pub fn install(name: &str, description: &str, config: &Config, options: &TaskOptions) -> Result<()> {
+    info!("Installing scheduled task: {}", name);
+
+    let exe_path = std::env::current_exe().context("Failed to get executable path")?;
+    let config_path = match &options.config_path {
+        Some(path) if path.is_absolute() => Some(path.clone()),
+        Some(path) => Some(std::env::current_dir().context("Failed to get current directory")?.join(path)),
+        None => None,
+    };
+    let xml = task_xml(&exe_path, config_path.as_deref(), description, options.interval)?;
+
+    // schtasks only reads task definitions from files, and expects them in UTF-16
+    let xml_path = std::env::temp_dir().join(format!("{}-task.xml", name));
+    let mut bytes = vec![0xFF, 0xFE];
+    bytes.extend(xml.encode_utf16().flat_map(u16::to_le_bytes));
+    std::fs::write(&xml_path, bytes).with_context(|| format!("Failed to write task definition {:?}", xml_path))?;
+
+    let output = Command::new("schtasks")
+        .args(["/Create", "/TN", name, "/XML"])
+        .arg(&xml_path)
+        .arg("/F")
+        .output();
+    let _ = std::fs::remove_file(&xml_path);
+    let output = output.context("Failed to execute schtasks")?;
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("Failed to create scheduled task: {}", error.trim()));
+    }
+
+    // Restrict access to the database and log directories
+    if let Err(e) = super::protect_data_directories(config, None) {
+        warn!("Failed to restrict access to data directories: {}", e);
+    }
+
+    info!("Scheduled task installed successfully");
+    Ok(())
+}
+
+/// Delete the scheduled task
+pub fn uninstall(name: &str) -> Result<()> {
+    info!("Deleting scheduled task: {}", name);
+
+    let output = Command::new("schtasks")
+        .args(["/Delete", "/TN", name, "/F"])
+        .output()
+        .context("Failed to execute schtasks")?;
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("Failed to delete scheduled task: {}", error.trim()));
+    }
+
+    println!("Scheduled task {} deleted", name);
+    Ok(())
+}
+
+/// Build the Task Scheduler definition of the task
+fn task_xml(exe_path: &Path, config_path: Option<&Path>, description: &str, interval: time::Duration) -> Result<String> {
+    let minutes = interval.as_secs() / 60;
+    if minutes == 0 {
+        return Err(anyhow::anyhow!("The task interval must be at least 1 minute"));
+    }
+
+    let mut arguments = String::new();
+    if let Some(config_path) = config_path {
+        arguments.push_str(&format!("--config \"{}\" ", config_path.display()));
+    }
+    arguments.push_str("run --once");
+
+    Ok(format!(
+        r#"<?xml version="1.0" encoding="UTF-16"?>
+<Task version="1.2" xmlns="http://schemas.microsoft.com/windows/2004/02/mit/task">
+  <RegistrationInfo>
+    <Description>{description}</Description>
+  </RegistrationInfo>
+  <Triggers>
+    <LogonTrigger>
+      <Enabled>true</Enabled>
+    </LogonTrigger>
+    <TimeTrigger>
+      <Repetition>
+        <Interval>PT{minutes}M</Interval>
+      </Repetition>
+      <StartBoundary>2000-01-01T00:00:00</StartBoundary>
+      <Enabled>true</Enabled>
+    </TimeTrigger>
+  </Triggers>
+  <Principals>
+    <Principal id="System">
+      <UserId>S-1-5-18</UserId>
+      <RunLevel>HighestAvailable</RunLevel>
+    </Principal>
+  </Principals>
+  <Settings>
+    <MultipleInstancesPolicy>IgnoreNew</MultipleInstancesPolicy>
+    <DisallowStartIfOnBatteries>false</DisallowStartIfOnBatteries>
+    <StopIfGoingOnBatteries>false</StopIfGoingOnBatteries>
+    <StartWhenAvailable>true</StartWhenAvailable>
+    <ExecutionTimeLimit>{limit}</ExecutionTimeLimit>
+    <Enabled>true</Enabled>
+  </Settings>
+  <Actions Context="System">
+    <Exec>
+      <Command>{command}</Command>
+      <Arguments>{arguments}</Arguments>
+    </Exec>
+  </Actions>
+</Task>
+"#,
+        description = escape_xml(description),
+        minutes = minutes,
+        limit = EXECUTION_TIME_LIMIT,
+        command = escape_xml(&exe_path.display().to_string()),
+        arguments = escape_xml(&arguments),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_task_xml() {
+        let xml = task_xml(
+            Path::new(r"C:\Program Files\RebootReminder\reboot_reminder.exe"),
+            Some(Path::new(r"C:\ProgramData\RebootReminder\config.json")),
+            "Reminds users to restart & more",
+            time::Duration::from_secs(3600),
+        )
+        .unwrap();
+
+        assert!(xml.contains("<LogonTrigger>"));
+        assert!(xml.contains("<Interval>PT60M</Interval>"));
+        assert!(xml.contains("<Command>C:\\Program Files\\RebootReminder\\reboot_reminder.exe</Command>"));
+        assert!(xml.contains(
+            "<Arguments>--config &quot;C:\\ProgramData\\RebootReminder\\config.json&quot; run --once</Arguments>"
+        ));
+        assert!(xml.contains("restart &amp; more"));
+
+        assert!(task_xml(Path::new("reboot_reminder.exe"), None, "", time::Duration::from_secs(30)).is_err());
+    }
+}
@@ -0,0 +1,263 @@
+use super::report::ReportFormat;
+use crate::database::{self, DbPool, Notification, NotificationInteraction, RebootHistory, RebootPeriod};
+use anyhow::Result;
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+/// What happened in an audit trail entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEventKind {
+    /// A reboot became required
+    RebootRequired,
+
+    /// A reboot was no longer required
+    RebootResolved,
+
+    /// A notification was shown, suppressed or skipped
+    Notified,
+
+    /// A user acted on a notification
+    Interaction,
+
+    /// The computer rebooted
+    Rebooted,
+}
+
+impl AuditEventKind {
+    /// Get the name used in CSV output
+    fn name(self) -> &'static str {
+        match self {
+            Self::RebootRequired => "reboot_required",
+            Self::RebootResolved => "reboot_resolved",
+            Self::Notified => "notified",
+            Self::Interaction => "interaction",
+            Self::Rebooted => "rebooted",
+        }
+    }
+}
+
+/// Entry of the audit trail
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEvent {
+    /// Time of the event
+    pub time: DateTime<Utc>,
+
+    /// Computer the event happened on
+    pub computer_name: String,
+
+    /// What happened
+    pub event: AuditEventKind,
+
+    /// User who was notified, acted or rebooted
+    pub user_name: Option<String>,
+
+    /// Session the notification was shown or acted on in
+    pub session_id: Option<String>,
+
+    /// Notification the event belongs to
+    pub notification_id: Option<Uuid>,
+
+    /// Notification type, action the user chose, or reboot source
+    pub action: Option<String>,
+
+    /// Notification message, delivery status or reboot reason
+    pub detail: Option<String>,
+
+    /// Time of the first successful reboot after the event, linking it to the reboot it led to
+    pub rebooted_at: Option<DateTime<Utc>>,
+}
+
+/// Build the audit trail from the database, oldest first
+pub fn build(db: &DbPool, since: Option<DateTime<Utc>>) -> Result<Vec<AuditEvent>> {
+    let periods = database::get_reboot_periods(db)?;
+    let notifications = database::get_notifications(db, None)?;
+    let interactions = database::get_notification_interactions(db)?;
+    let history = database::get_reboot_history(db, None)?;
+    let computer_name = std::env::var("COMPUTERNAME").unwrap_or_default();
+    Ok(compute(&computer_name, &periods, &notifications, &interactions, &history, since))
+}
+
+/// Parse the start of the audit trail: a timespan back from now (e.g., "720h"), a date
+/// (e.g., "2024-03-01") or an RFC 3339 time
+pub fn parse_since(value: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap_or_default()));
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Utc));
+    }
+
+    let span = crate::utils::timespan::parse_timespan(value)
+        .map_err(|e| anyhow::anyhow!("Invalid start '{}', expected a timespan, date or time: {}", value, e))?;
+    Ok(now - chrono::Duration::from_std(span)?)
+}
+
+/// Merge reboot periods, notifications, interactions and reboots into one chronological trail
+fn compute(
+    computer_name: &str,
+    periods: &[RebootPeriod],
+    notifications: &[Notification],
+    interactions: &[NotificationInteraction],
+    history: &[RebootHistory],
+    since: Option<DateTime<Utc>>,
+) -> Vec<AuditEvent> {
+    let mut reboots: Vec<DateTime<Utc>> =
+        history.iter().filter(|reboot| reboot.success).map(|reboot| reboot.reboot_time).collect();
+    reboots.sort();
+    let next_reboot = |time: DateTime<Utc>| reboots.iter().find(|reboot| **reboot >= time).copied();
+
+    let event = |time: DateTime<Utc>, event: AuditEventKind| AuditEvent {
+        time,
+        computer_name: computer_name.to_string(),
+        event,
+        user_name: None,
+        session_id: None,
+        notification_id: None,
+        action: None,
+        detail: None,
+        rebooted_at: next_reboot(time),
+    };
+
+    let mut events = Vec::new();
+    for period in periods {
+        events.push(event(period.required_since, AuditEventKind::RebootRequired));
+        if let Some(resolved_at) = period.resolved_at {
+            events.push(event(resolved_at, AuditEventKind::RebootResolved));
+        }
+    }
+
+    for notification in notifications {
+        let status = match &notification.suppression_reason {
+            Some(reason) => format!("{} ({})", notification.delivery_status.as_str(), reason),
+            None => notification.delivery_status.as_str().to_string(),
+        };
+        events.push(AuditEvent {
+            user_name: notification.user_name.clone(),
+            session_id: notification.session_id.clone(),
+            notification_id: Some(notification.id),
+            action: Some(notification.notification_type.clone()),
+            detail: Some(format!("{}: {}", status, notification.message)),
+            ..event(notification.timestamp, AuditEventKind::Notified)
+        });
+    }
+
+    for interaction in interactions {
+        events.push(AuditEvent {
+            user_name: interaction.user_name.clone(),
+            session_id: interaction.session_id.clone(),
+            notification_id: Some(interaction.notification_id),
+            action: Some(interaction.action.clone()),
+            detail: interaction.details.clone(),
+            ..event(interaction.timestamp, AuditEventKind::Interaction)
+        });
+    }
+
+    for reboot in history {
+        events.push(AuditEvent {
+            computer_name: reboot.computer_name.clone().unwrap_or_else(|| computer_name.to_string()),
+            user_name: reboot.user_name.clone(),
+            action: reboot.source.clone(),
+            detail: Some(match &reboot.reason {
+                Some(reason) if reboot.success => reason.clone(),
+                Some(reason) => format!("failed: {}", reason),
+                None if reboot.success => String::new(),
+                None => "failed".to_string(),
+            })
+            .filter(|detail| !detail.is_empty()),
+            rebooted_at: None,
+            ..event(reboot.reboot_time, AuditEventKind::Rebooted)
+        });
+    }
+
+    events.retain(|event| !since.is_some_and(|since| event.time < since));
+    events.sort_by_key(|event| event.time);
+    events
+}
+
+/// Write the audit trail in a format
+pub fn render(events: &[AuditEvent], format: ReportFormat) -> Result<String> {
+    match format {
+        ReportFormat::Json => Ok(serde_json::to_string_pretty(events)? + "\n"),
+        ReportFormat::Csv => Ok(to_csv(events)),
+    }
+}
+
+/// Write the audit trail as CSV with a header row
+fn to_csv(events: &[AuditEvent]) -> String {
+    let optional = |value: Option<&str>| field(value.unwrap_or_default());
+    let mut csv =
+        String::from("time,computer_name,event,user_name,session_id,notification_id,action,detail,rebooted_at\r\n");
+    for event in events {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{},{},{}\r\n",
+            event.time.to_rfc3339(),
+            field(&event.computer_name),
+            event.event.name(),
+            optional(event.user_name.as_deref()),
+            optional(event.session_id.as_deref()),
+            event.notification_id.map(|id| id.to_string()).unwrap_or_default(),
+            optional(event.action.as_deref()),
+            optional(event.detail.as_deref()),
+            event.rebooted_at.map(|time| time.to_rfc3339()).unwrap_or_default()
+        ));
+    }
+    csv
+}
+
+/// Quote a CSV field if it contains separators, quotes or line breaks
+fn field(value: &str) -> String {
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compute() {
+        let at = |day: u32, hour: u32| Utc.with_ymd_and_hms(2024, 3, day, hour, 0, 0).unwrap();
+
+        let mut period = RebootPeriod::new(at(4, 8));
+        period.resolved_at = Some(at(5, 14));
+        let mut notification = Notification::new("reboot_required", "Restart, please", Some("jdoe"));
+        notification.timestamp = at(4, 9);
+        let mut interaction = NotificationInteraction::new_detailed(notification.id, "defer:4h", Some("jdoe"), Some("2"), None);
+        interaction.timestamp = at(4, 10);
+        let mut reboot = RebootHistory::new(at(5, 13), true);
+        reboot.source = Some("user".to_string());
+
+        let events = compute("PC-042", &[period], &[notification.clone()], &[interaction], &[reboot], None);
+        let kinds: Vec<AuditEventKind> = events.iter().map(|event| event.event).collect();
+        assert_eq!(
+            kinds,
+            [
+                AuditEventKind::RebootRequired,
+                AuditEventKind::Notified,
+                AuditEventKind::Interaction,
+                AuditEventKind::Rebooted,
+                AuditEventKind::RebootResolved
+            ]
+        );
+        assert_eq!(events[2].action.as_deref(), Some("defer:4h"));
+        assert_eq!(events[2].notification_id, Some(notification.id));
+        assert_eq!(events[1].rebooted_at, Some(at(5, 13)));
+        assert_eq!(events[4].rebooted_at, None);
+
+        let csv = to_csv(&events);
+        assert_eq!(csv.lines().count(), 6);
+        assert!(csv.contains(",notified,jdoe,,"));
+        assert!(csv.contains("\"pending: Restart, please\""));
+
+        let since = parse_since("2024-03-05", at(20, 0)).unwrap();
+        let events = compute("PC-042", &[], &[notification], &[], &[], Some(since));
+        assert!(events.is_empty());
+        assert_eq!(parse_since("168h", at(20, 0)).unwrap(), at(13, 0));
+    }
+}
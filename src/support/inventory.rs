@@ -0,0 +1,77 @@
+use crate::database::RebootState;
+use crate::utils::registry;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use log::debug;
+use windows::Win32::System::Registry::HKEY_LOCAL_MACHINE;
+
+/// Key under HKLM the reboot state is mirrored to, for inventory tools that can't read the database
+pub const STATUS_KEY: &str = "SOFTWARE\\RebootReminder\\Status";
+
+/// Key holding the status key, removed when the data is purged
+pub const PRODUCT_KEY: &str = "SOFTWARE\\RebootReminder";
+
+/// Value written to the status key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StatusValue {
+    Dword(u32),
+    Text(String),
+}
+
+/// Get the values mirrored for a reboot state
+///
+/// Times are RFC 3339 strings in UTC, and empty when they aren't set.
+pub fn values(state: &RebootState, deadline: Option<DateTime<Utc>>) -> Vec<(&'static str, StatusValue)> {
+    let time = |time: Option<DateTime<Utc>>| StatusValue::Text(time.map(|time| time.to_rfc3339()).unwrap_or_default());
+    let sources: Vec<&str> = state.sources.iter().map(|source| source.name.as_str()).collect();
+
+    vec![
+        ("RebootRequired", StatusValue::Dword(state.reboot_required.into())),
+        ("RebootRecommended", StatusValue::Dword(state.reboot_recommended.into())),
+        ("RebootRequiredSince", time(state.reboot_required_since)),
+        ("PostponeCount", StatusValue::Dword(state.postpone_count)),
+        ("ScheduledRebootTime", time(state.scheduled_reboot_time)),
+        ("Deadline", time(deadline)),
+        ("Lifecycle", StatusValue::Text(state.lifecycle.as_str().to_string())),
+        ("Sources", StatusValue::Text(sources.join(","))),
+        ("LastCheckTime", time(Some(state.last_check_time))),
+        ("UpdatedAt", time(Some(state.updated_at))),
+    ]
+}
+
+/// Write the reboot state to the status key
+pub fn publish(state: &RebootState, deadline: Option<DateTime<Utc>>) -> Result<()> {
+    debug!("Mirroring reboot state version {} to HKLM\\{}", state.version, STATUS_KEY);
+    for (name, value) in values(state, deadline) {
+        match value {
+            StatusValue::Dword(value) => registry::set_dword_value(HKEY_LOCAL_MACHINE, STATUS_KEY, name, value)?,
+            StatusValue::Text(value) => registry::set_string_value(HKEY_LOCAL_MACHINE, STATUS_KEY, name, &value)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::RebootSource;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_values() {
+        let since = Utc.with_ymd_and_hms(2024, 3, 4, 8, 0, 0).unwrap();
+        let mut state = RebootState::new(true, false);
+        state.reboot_required_since = Some(since);
+        state.postpone_count = 3;
+        state.sources.push(RebootSource::new("windows_update", None, "required"));
+        state.sources.push(RebootSource::new("registry", None, "required"));
+
+        let values = values(&state, None);
+        let value = |name: &str| values.iter().find(|(value_name, _)| *value_name == name).map(|(_, value)| value.clone());
+        assert_eq!(value("RebootRequired"), Some(StatusValue::Dword(1)));
+        assert_eq!(value("PostponeCount"), Some(StatusValue::Dword(3)));
+        assert_eq!(value("RebootRequiredSince"), Some(StatusValue::Text("2024-03-04T08:00:00+00:00".to_string())));
+        assert_eq!(value("Deadline"), Some(StatusValue::Text(String::new())));
+        assert_eq!(value("Sources"), Some(StatusValue::Text("windows_update,registry".to_string())));
+    }
+}
@@ -0,0 +1,294 @@
+use crate::database::{self, DbPool, Notification, NotificationInteraction, RebootPeriod};
+use crate::notification::{DEFER_ACTION_PREFIX, SNOOZE_UNTIL_ACTION_PREFIX};
+use anyhow::Result;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::str::FromStr;
+
+/// Output format of the report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Csv,
+    Json,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "csv" => Ok(Self::Csv),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("Unknown report format '{}', expected csv or json", value)),
+        }
+    }
+}
+
+/// Length of the periods the report is grouped by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportPeriod {
+    Day,
+    Week,
+    Month,
+}
+
+impl FromStr for ReportPeriod {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "day" => Ok(Self::Day),
+            "week" => Ok(Self::Week),
+            "month" => Ok(Self::Month),
+            _ => Err(format!("Unknown report period '{}', expected day, week or month", value)),
+        }
+    }
+}
+
+impl ReportPeriod {
+    /// Get the first day of the period a date falls in; weeks start on Monday
+    fn start(self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Self::Day => date,
+            Self::Week => date - Duration::days(date.weekday().num_days_from_monday() as i64),
+            Self::Month => date.with_day(1).unwrap_or(date),
+        }
+    }
+}
+
+/// Reboot compliance statistics of one period
+///
+/// Reboots are counted in the period they became required in, notifications in the period they
+/// were shown in.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeriodStats {
+    /// First day of the period (UTC)
+    pub period_start: NaiveDate,
+
+    /// Number of times a reboot became required
+    pub reboots_required: u32,
+
+    /// Number of those reboots that have happened
+    pub reboots_completed: u32,
+
+    /// Average hours from the reboot becoming required to the reboot, of the completed ones
+    pub average_hours_to_reboot: Option<f64>,
+
+    /// Reboots that were never postponed
+    pub postponed_0: u32,
+
+    /// Reboots postponed once
+    pub postponed_1: u32,
+
+    /// Reboots postponed twice
+    pub postponed_2: u32,
+
+    /// Reboots postponed three or more times
+    pub postponed_3_plus: u32,
+
+    /// Reboot reminders shown
+    pub notifications: u32,
+
+    /// Reboot reminders the user acted on
+    pub notifications_actioned: u32,
+
+    /// Share of reminders the user acted on, from 0 to 1
+    pub conversion_rate: Option<f64>,
+
+    /// Reboots pending for longer than the SLA, whether they have happened since or not
+    pub sla_breaches: u32,
+}
+
+/// Compute the compliance report from the database
+pub fn build(db: &DbPool, period: ReportPeriod, sla: Duration, now: DateTime<Utc>) -> Result<Vec<PeriodStats>> {
+    let periods = database::get_reboot_periods(db)?;
+    let notifications = database::get_notifications(db, None)?;
+    let interactions = database::get_notification_interactions(db)?;
+    Ok(compute(&periods, &notifications, &interactions, period, sla, now))
+}
+
+/// Compute per-period statistics of reboot periods, reminders and interactions
+fn compute(
+    periods: &[RebootPeriod],
+    notifications: &[Notification],
+    interactions: &[NotificationInteraction],
+    period: ReportPeriod,
+    sla: Duration,
+    now: DateTime<Utc>,
+) -> Vec<PeriodStats> {
+    let mut stats: BTreeMap<NaiveDate, PeriodStats> = BTreeMap::new();
+    let mut hours: BTreeMap<NaiveDate, Vec<f64>> = BTreeMap::new();
+
+    for reboot in periods {
+        let row = row_for(&mut stats, period, reboot.required_since);
+        let end = reboot.resolved_at.unwrap_or(now);
+        row.reboots_required += 1;
+
+        if let Some(resolved_at) = reboot.resolved_at {
+            row.reboots_completed += 1;
+            hours
+                .entry(row.period_start)
+                .or_default()
+                .push((resolved_at - reboot.required_since).num_seconds() as f64 / 3600.0);
+        }
+        if end - reboot.required_since > sla {
+            row.sla_breaches += 1;
+        }
+
+        let postpones = interactions
+            .iter()
+            .filter(|interaction| interaction.timestamp >= reboot.required_since && interaction.timestamp <= end)
+            .filter(|interaction| is_postpone(&interaction.action))
+            .count();
+        match postpones {
+            0 => row.postponed_0 += 1,
+            1 => row.postponed_1 += 1,
+            2 => row.postponed_2 += 1,
+            _ => row.postponed_3_plus += 1,
+        }
+    }
+
+    let actioned: HashSet<_> = interactions
+        .iter()
+        .filter(|interaction| is_user_action(&interaction.action))
+        .map(|interaction| interaction.notification_id)
+        .collect();
+    for notification in notifications
+        .iter()
+        .filter(|notification| notification.notification_type == "reboot_required")
+        .filter(|notification| notification.suppression_reason.is_none())
+    {
+        let row = row_for(&mut stats, period, notification.timestamp);
+        row.notifications += 1;
+        if actioned.contains(&notification.id) {
+            row.notifications_actioned += 1;
+        }
+    }
+
+    stats
+        .into_values()
+        .map(|mut row| {
+            if let Some(hours) = hours.get(&row.period_start) {
+                row.average_hours_to_reboot = Some(round(hours.iter().sum::<f64>() / hours.len() as f64));
+            }
+            if row.notifications > 0 {
+                row.conversion_rate = Some(round(row.notifications_actioned as f64 / row.notifications as f64));
+            }
+            row
+        })
+        .collect()
+}
+
+/// Get the statistics of the period a time falls in
+fn row_for(stats: &mut BTreeMap<NaiveDate, PeriodStats>, period: ReportPeriod, time: DateTime<Utc>) -> &mut PeriodStats {
+    let start = period.start(time.date_naive());
+    stats.entry(start).or_insert_with(|| PeriodStats { period_start: start, ..Default::default() })
+}
+
+/// Check whether an interaction postponed the reminders
+fn is_postpone(action: &str) -> bool {
+    action.starts_with(DEFER_ACTION_PREFIX) || action.starts_with(SNOOZE_UNTIL_ACTION_PREFIX)
+}
+
+/// Check whether an interaction was made by the user, rather than a dialog timing out or closing
+fn is_user_action(action: &str) -> bool {
+    !action.ends_with(":timeout") && !action.ends_with(":closed")
+}
+
+/// Round a statistic to two decimals
+fn round(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+/// Write the report in a format
+pub fn render(stats: &[PeriodStats], format: ReportFormat) -> Result<String> {
+    match format {
+        ReportFormat::Json => Ok(serde_json::to_string_pretty(stats)? + "\n"),
+        ReportFormat::Csv => Ok(to_csv(stats)),
+    }
+}
+
+/// Write the report as CSV with a header row
+fn to_csv(stats: &[PeriodStats]) -> String {
+    let optional = |value: Option<f64>| value.map(|value| value.to_string()).unwrap_or_default();
+    let mut csv = String::from(
+        "period_start,reboots_required,reboots_completed,average_hours_to_reboot,postponed_0,postponed_1,\
+         postponed_2,postponed_3_plus,notifications,notifications_actioned,conversion_rate,sla_breaches\r\n",
+    );
+    for row in stats {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{}\r\n",
+            row.period_start,
+            row.reboots_required,
+            row.reboots_completed,
+            optional(row.average_hours_to_reboot),
+            row.postponed_0,
+            row.postponed_1,
+            row.postponed_2,
+            row.postponed_3_plus,
+            row.notifications,
+            row.notifications_actioned,
+            optional(row.conversion_rate),
+            row.sla_breaches
+        ));
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_compute() {
+        let at = |day: u32, hour: u32| Utc.with_ymd_and_hms(2024, 3, day, hour, 0, 0).unwrap();
+        let now = at(20, 0);
+
+        // Monday 4 March: rebooted after 30 hours with one deferral
+        let mut first = RebootPeriod::new(at(4, 8));
+        first.resolved_at = Some(at(5, 14));
+        // Tuesday 12 March: still pending after more than the 7 day SLA
+        let second = RebootPeriod::new(at(12, 0));
+
+        let mut shown = Notification::new("reboot_required", "Restart", None);
+        shown.timestamp = at(4, 9);
+        let mut ignored = Notification::new("reboot_required", "Restart", None);
+        ignored.timestamp = at(4, 12);
+        let mut deferral = NotificationInteraction::new(shown.id, &format!("{}1h", DEFER_ACTION_PREFIX));
+        deferral.timestamp = at(4, 10);
+        let mut timeout = NotificationInteraction::new(ignored.id, "messagebox:timeout");
+        timeout.timestamp = at(4, 13);
+
+        let stats = compute(
+            &[first, second],
+            &[shown, ignored],
+            &[deferral, timeout],
+            ReportPeriod::Week,
+            Duration::days(7),
+            now,
+        );
+        assert_eq!(stats.len(), 2);
+
+        let week = &stats[0];
+        assert_eq!(week.period_start, NaiveDate::from_ymd_opt(2024, 3, 4).unwrap());
+        assert_eq!((week.reboots_required, week.reboots_completed), (1, 1));
+        assert_eq!(week.average_hours_to_reboot, Some(30.0));
+        assert_eq!((week.postponed_0, week.postponed_1), (0, 1));
+        assert_eq!((week.notifications, week.notifications_actioned), (2, 1));
+        assert_eq!(week.conversion_rate, Some(0.5));
+        assert_eq!(week.sla_breaches, 0);
+
+        let week = &stats[1];
+        assert_eq!(week.period_start, NaiveDate::from_ymd_opt(2024, 3, 11).unwrap());
+        assert_eq!((week.reboots_required, week.reboots_completed), (1, 0));
+        assert_eq!(week.average_hours_to_reboot, None);
+        assert_eq!(week.sla_breaches, 1);
+
+        let csv = to_csv(&stats);
+        assert_eq!(csv.lines().count(), 3);
+        assert!(csv.lines().nth(1).unwrap().starts_with("2024-03-04,1,1,30,0,1,0,0,2,1,0.5,0"));
+    }
+}
@@ -0,0 +1,168 @@
+use crate::config::Config;
+use crate::database::{self, DbPool};
+use crate::notification::details::escape;
+use crate::reboot;
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+
+/// Number of reboots and notifications listed on the page
+const RECENT_ENTRIES: u32 = 20;
+
+/// How long a client may take to send its request
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serve the status page on the loopback interface in the background
+///
+/// Only `GET /` is answered. The page is generated from the database on every request, so it
+/// always shows the current state.
+pub fn start(port: u16, config: Arc<RwLock<Config>>, db: DbPool) -> Result<()> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+        .with_context(|| format!("Failed to listen on port {}", port))?;
+    info!("Status page available at http://localhost:{}/", port);
+
+    thread::Builder::new()
+        .name("status-page".to_string())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        if let Err(e) = handle(stream, &config, &db) {
+                            debug!("Failed to answer status page request: {:#}", e);
+                        }
+                    }
+                    Err(e) => warn!("Failed to accept status page connection: {}", e),
+                }
+            }
+        })
+        .context("Failed to start the status page thread")?;
+
+    Ok(())
+}
+
+/// Answer one request
+fn handle(mut stream: TcpStream, config: &Arc<RwLock<Config>>, db: &DbPool) -> Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+    debug!("Status page request: {}", request_line.trim());
+
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/")) => {
+            let config = config
+                .read()
+                .map_err(|e| anyhow::anyhow!("Failed to acquire read lock for configuration: {}", e))?
+                .clone();
+            match render(&config, db) {
+                Ok(page) => ("200 OK", page),
+                Err(e) => ("500 Internal Server Error", format!("Failed to read the reboot state: {}", escape(&format!("{:#}", e)))),
+            }
+        }
+        (Some("GET"), Some(_)) => ("404 Not Found", "Not found".to_string()),
+        _ => ("405 Method Not Allowed", "Only GET is supported".to_string()),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// Render the status page from the database
+pub fn render(config: &Config, db: &DbPool) -> Result<String> {
+    let status = super::status_report(config, db)?;
+
+    let mut sources = String::new();
+    if let Some(state) = database::get_reboot_state(db)? {
+        for source in &state.sources {
+            sources.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                escape(&source.name),
+                escape(&source.severity),
+                escape(source.details.as_deref().or(source.description.as_deref()).unwrap_or("")),
+                escape(&reboot::format_time(source.detected_at))
+            ));
+        }
+    }
+    if sources.is_empty() {
+        sources.push_str("<tr><td colspan=\"4\">No pending reboot sources</td></tr>\n");
+    }
+
+    let mut history = String::new();
+    for entry in database::get_reboot_history(db, Some(RECENT_ENTRIES))? {
+        history.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            escape(&reboot::format_time(entry.reboot_time)),
+            escape(entry.reason.as_deref().unwrap_or("")),
+            escape(entry.user_name.as_deref().unwrap_or("")),
+            if entry.success { "Yes" } else { "No" }
+        ));
+    }
+    if history.is_empty() {
+        history.push_str("<tr><td colspan=\"4\">No reboots recorded</td></tr>\n");
+    }
+
+    let mut notifications = String::new();
+    for notification in database::get_notifications(db, Some(RECENT_ENTRIES))? {
+        notifications.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            escape(&reboot::format_time(notification.timestamp)),
+            escape(&notification.notification_type),
+            escape(notification.user_name.as_deref().unwrap_or("")),
+            escape(notification.delivery_status.as_str()),
+            escape(&notification.message)
+        ));
+    }
+    if notifications.is_empty() {
+        notifications.push_str("<tr><td colspan=\"5\">No notifications recorded</td></tr>\n");
+    }
+
+    Ok(format!(
+        "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>Reboot Reminder status</title>
+<style>
+body {{ font-family: 'Segoe UI', sans-serif; margin: 2em; color: #222; }}
+table {{ border-collapse: collapse; width: 100%; margin-bottom: 2em; }}
+th, td {{ text-align: left; padding: 0.4em; border-bottom: 1px solid #ddd; }}
+</style>
+</head>
+<body>
+<h1>Reboot Reminder status</h1>
+<h2>Reboot state</h2>
+<pre>{status}</pre>
+<h2>Pending reboot sources</h2>
+<table>
+<tr><th>Source</th><th>Severity</th><th>Details</th><th>Detected</th></tr>
+{sources}</table>
+<h2>Reboot history</h2>
+<table>
+<tr><th>Time</th><th>Reason</th><th>User</th><th>Successful</th></tr>
+{history}</table>
+<h2>Recent notifications</h2>
+<table>
+<tr><th>Time</th><th>Type</th><th>User</th><th>Delivery</th><th>Message</th></tr>
+{notifications}</table>
+<p>Version {version}</p>
+</body>
+</html>
+",
+        status = escape(&status),
+        sources = sources,
+        history = history,
+        notifications = notifications,
+        version = env!("CARGO_PKG_VERSION"),
+    ))
+}
@@ -0,0 +1,302 @@
+pub mod signature;
+
+use crate::config::security::{verify_minisign, SIGNATURE_EXTENSION};
+use crate::config::UpdateConfig;
+use crate::utils::http;
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::os::windows::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+use url::Url;
+
+/// Process creation flags for the detached updater process
+const DETACHED_PROCESS: u32 = 0x0000_0008;
+const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+
+/// How long the updated service has to reach the running state before it's rolled back
+const START_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long to wait for the service to stop
+const STOP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Release manifest published next to the executables
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateManifest {
+    /// Version of the release
+    pub version: String,
+
+    /// URL of the executable, absolute or relative to the manifest
+    pub url: String,
+
+    /// SHA-256 hash of the executable as a hex string
+    pub sha256: String,
+}
+
+/// Check the manifest for a version newer than the running one
+///
+/// The manifest must carry a minisign signature (`manifest.json.minisig`) made with the
+/// configured public key, since it decides which executable gets installed.
+pub fn check_for_update(config: &UpdateConfig) -> Result<Option<UpdateManifest>> {
+    let manifest_url = manifest_url(config)?;
+    debug!("Checking for updates at {}", manifest_url);
+
+    let client = http::client(Duration::from_secs(30))?;
+    let content = fetch_text(&client, manifest_url.as_str()).context("Failed to fetch update manifest")?;
+    let signature_url = format!("{}{}", manifest_url, SIGNATURE_EXTENSION);
+    let signature = fetch_text(&client, &signature_url).context("Failed to fetch update manifest signature")?;
+    let public_key = config
+        .public_key
+        .as_deref()
+        .ok_or_else(|| anyhow::anyhow!("No update manifest public key configured"))?;
+    verify_minisign(public_key, content.as_bytes(), &signature)
+        .context("Signature of the update manifest is invalid")?;
+    debug!("Signature of the update manifest verified");
+
+    let manifest: UpdateManifest = serde_json::from_str(&content).context("Failed to parse update manifest")?;
+    let current = env!("CARGO_PKG_VERSION");
+
+    if is_newer_version(&manifest.version, current) {
+        info!("Update available: {} (running {})", manifest.version, current);
+        Ok(Some(manifest))
+    } else {
+        debug!("No update available (latest {}, running {})", manifest.version, current);
+        Ok(None)
+    }
+}
+
+/// Fetch a text document
+fn fetch_text(client: &reqwest::blocking::Client, url: &str) -> Result<String> {
+    let response = client.get(url).send()?;
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!("HTTP {}", response.status()));
+    }
+    Ok(response.text()?)
+}
+
+/// Download an update to the staging directory and verify its hash and signature
+pub fn download_update(config: &UpdateConfig, manifest: &UpdateManifest) -> Result<PathBuf> {
+    let url = manifest_url(config)?
+        .join(&manifest.url)
+        .with_context(|| format!("Invalid update URL: {}", manifest.url))?;
+    info!("Downloading update {} from {}", manifest.version, url);
+
+    let client = http::client(Duration::from_secs(300))?;
+    let response = client.get(url.as_str()).send().context("Failed to download update")?;
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!("Failed to download update: HTTP {}", response.status()));
+    }
+    let bytes = response.bytes().context("Failed to read update")?;
+
+    let hash = sha256_hex(&bytes);
+    if !hash.eq_ignore_ascii_case(manifest.sha256.trim()) {
+        return Err(anyhow::anyhow!(
+            "Update hash mismatch: expected {}, got {}",
+            manifest.sha256, hash
+        ));
+    }
+
+    let staging_dir = staging_dir()?;
+    fs::create_dir_all(&staging_dir).context("Failed to create update staging directory")?;
+    let staged = staging_dir.join(format!("reboot_reminder-{}.exe", sanitize_version(&manifest.version)));
+    fs::write(&staged, &bytes).context("Failed to write staged update")?;
+
+    if config.require_signature {
+        if let Err(e) = signature::verify_authenticode(&staged, &config.signer_thumbprints) {
+            let _ = fs::remove_file(&staged);
+            return Err(e.context("Update is not signed by a pinned Authenticode signer"));
+        }
+        debug!("Authenticode signature of the update verified");
+    }
+
+    info!("Update staged at {:?}", staged);
+    Ok(staged)
+}
+
+/// Start the staged executable to swap the binaries once this service has stopped
+pub fn launch_apply(staged: &Path, service_name: &str) -> Result<()> {
+    let target = std::env::current_exe().context("Failed to get executable path")?;
+    info!("Starting updater to replace {:?} with {:?}", target, staged);
+
+    Command::new(staged)
+        .arg("apply-update")
+        .arg("--service-name")
+        .arg(service_name)
+        .arg("--target")
+        .arg(&target)
+        .creation_flags(DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP)
+        .spawn()
+        .context("Failed to start updater")?;
+
+    Ok(())
+}
+
+/// Replace the installed executable with the running one and restart the service
+///
+/// Runs from the staged executable. If the service doesn't reach the running state with the new
+/// executable, the previous executable is restored and the service is started again.
+pub fn apply_update(service_name: &str, target: &Path) -> Result<()> {
+    let staged = std::env::current_exe().context("Failed to get executable path")?;
+    let backup = target.with_extension("exe.old");
+    info!("Applying update {:?} to {:?}", staged, target);
+
+    stop_service(service_name)?;
+
+    if backup.exists() {
+        fs::remove_file(&backup).context("Failed to remove previous backup")?;
+    }
+    fs::rename(target, &backup).context("Failed to back up the current executable")?;
+    if let Err(e) = fs::copy(&staged, target) {
+        fs::rename(&backup, target).context("Failed to restore the previous executable")?;
+        start_service(service_name)?;
+        return Err(anyhow::anyhow!("Failed to install the new executable: {}", e));
+    }
+
+    if start_service(service_name).is_ok() && wait_for_running(service_name, START_TIMEOUT) {
+        info!("Update applied, service {} is running", service_name);
+        return Ok(());
+    }
+
+    warn!("Service {} did not start after the update, rolling back", service_name);
+    let _ = stop_service(service_name);
+    let failed = target.with_extension("exe.failed");
+    let _ = fs::remove_file(&failed);
+    fs::rename(target, &failed).context("Failed to remove the new executable")?;
+    fs::rename(&backup, target).context("Failed to restore the previous executable")?;
+    start_service(service_name)?;
+
+    Err(anyhow::anyhow!("Update rolled back because the service failed to start"))
+}
+
+/// Get the interval between update checks
+pub fn check_interval(config: &UpdateConfig) -> chrono::Duration {
+    crate::utils::timespan::parse_timespan(&config.check_interval)
+        .ok()
+        .and_then(|interval| chrono::Duration::from_std(interval).ok())
+        .unwrap_or_else(|| chrono::Duration::hours(24))
+}
+
+/// Get the manifest URL from the configuration
+fn manifest_url(config: &UpdateConfig) -> Result<Url> {
+    let url = config
+        .manifest_url
+        .as_deref()
+        .ok_or_else(|| anyhow::anyhow!("No update manifest URL configured"))?;
+    let url = Url::parse(url).with_context(|| format!("Invalid update manifest URL: {}", url))?;
+    if url.scheme() != "https" {
+        return Err(anyhow::anyhow!("Update manifest URL {} must use HTTPS", url));
+    }
+    Ok(url)
+}
+
+/// Get the directory updates are staged in
+fn staging_dir() -> Result<PathBuf> {
+    let exe = std::env::current_exe().context("Failed to get executable path")?;
+    let dir = exe.parent().ok_or_else(|| anyhow::anyhow!("Failed to get executable directory"))?;
+    Ok(dir.join("update"))
+}
+
+/// Stop a service and wait for it to stop
+fn stop_service(service_name: &str) -> Result<()> {
+    info!("Stopping service {}", service_name);
+    let _ = Command::new("sc").args(["stop", service_name]).output();
+
+    let mut waited = Duration::ZERO;
+    while crate::watchdog::is_service_running(service_name).unwrap_or(false) {
+        if waited >= STOP_TIMEOUT {
+            return Err(anyhow::anyhow!("Timeout waiting for service {} to stop", service_name));
+        }
+        thread::sleep(Duration::from_secs(1));
+        waited += Duration::from_secs(1);
+    }
+
+    // Give the process time to exit and release the executable
+    thread::sleep(Duration::from_secs(2));
+    Ok(())
+}
+
+/// Start a service
+fn start_service(service_name: &str) -> Result<()> {
+    info!("Starting service {}", service_name);
+    let output = Command::new("sc")
+        .args(["start", service_name])
+        .output()
+        .context("Failed to execute SC command to start the service")?;
+
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stdout);
+        return Err(anyhow::anyhow!("Failed to start service {}: {}", service_name, error.trim()));
+    }
+
+    Ok(())
+}
+
+/// Wait for a service to reach the running state
+fn wait_for_running(service_name: &str, timeout: Duration) -> bool {
+    let mut waited = Duration::ZERO;
+    while waited < timeout {
+        if crate::watchdog::is_service_running(service_name).unwrap_or(false) {
+            return true;
+        }
+        thread::sleep(Duration::from_secs(1));
+        waited += Duration::from_secs(1);
+    }
+    false
+}
+
+/// Compute the SHA-256 hash of data as a lowercase hex string
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Check if a version is newer than another
+///
+/// Versions are compared by their numeric parts, so "2025.4.13-1240" is older than "2025.5.1-0900".
+pub fn is_newer_version(candidate: &str, current: &str) -> bool {
+    version_parts(candidate) > version_parts(current)
+}
+
+/// Split a version into its numeric parts
+fn version_parts(version: &str) -> Vec<u64> {
+    version
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|part| !part.is_empty())
+        .filter_map(|part| part.parse().ok())
+        .collect()
+}
+
+/// Make a version safe to use in a file name
+fn sanitize_version(version: &str) -> String {
+    version
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_newer_version() {
+        assert!(is_newer_version("2025.5.1-0900", "2025.4.13-1240"));
+        assert!(is_newer_version("2025.4.13-1241", "2025.4.13-1240"));
+        assert!(is_newer_version("1.10.0", "1.9.9"));
+        assert!(!is_newer_version("2025.4.13-1240", "2025.4.13-1240"));
+        assert!(!is_newer_version("2024.12.31-2359", "2025.4.13-1240"));
+    }
+
+    #[test]
+    fn test_sha256_hex() {
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
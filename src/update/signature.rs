@@ -0,0 +1,121 @@
+use crate::utils::to_wide;
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use std::ffi::c_void;
+use std::path::Path;
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{HANDLE, HWND};
+use windows::Win32::Security::WinTrust::{
+    WTHelperGetProvCertFromChain, WTHelperGetProvSignerFromChain, WTHelperProvDataFromStateData,
+    WinVerifyTrust, WINTRUST_ACTION_GENERIC_VERIFY_V2, WINTRUST_DATA, WINTRUST_DATA_0,
+    WINTRUST_FILE_INFO, WTD_CHOICE_FILE, WTD_REVOKE_NONE, WTD_STATEACTION_CLOSE,
+    WTD_STATEACTION_VERIFY, WTD_UI_NONE,
+};
+
+/// Verify that a file has a valid Authenticode signature from one of the pinned signers
+///
+/// Any publisher trusted by the machine passes `WinVerifyTrust`, so the SHA-256 thumbprint of
+/// the signing certificate must also be one of `thumbprints`. Revocation isn't checked so
+/// verification also works on machines without internet access.
+pub fn verify_authenticode(path: &Path, thumbprints: &[String]) -> Result<()> {
+    let path_wide = to_wide(&path.as_os_str().to_string_lossy());
+
+    let mut file_info = WINTRUST_FILE_INFO {
+        cbStruct: std::mem::size_of::<WINTRUST_FILE_INFO>() as u32,
+        pcwszFilePath: PCWSTR::from_raw(path_wide.as_ptr()),
+        hFile: HANDLE::default(),
+        pgKnownSubject: std::ptr::null_mut(),
+    };
+
+    let mut data = WINTRUST_DATA {
+        cbStruct: std::mem::size_of::<WINTRUST_DATA>() as u32,
+        dwUIChoice: WTD_UI_NONE,
+        fdwRevocationChecks: WTD_REVOKE_NONE,
+        dwUnionChoice: WTD_CHOICE_FILE,
+        Anonymous: WINTRUST_DATA_0 { pFile: &mut file_info },
+        dwStateAction: WTD_STATEACTION_VERIFY,
+        ..Default::default()
+    };
+
+    let mut action = WINTRUST_ACTION_GENERIC_VERIFY_V2;
+
+    unsafe {
+        let status = WinVerifyTrust(
+            HWND::default(),
+            &mut action,
+            &mut data as *mut WINTRUST_DATA as *mut c_void,
+        );
+
+        // Read the signer while the state data is still held
+        let signer = if status == 0 { signer_thumbprint(data.hWVTStateData) } else { None };
+
+        // Release the state data held by the verification
+        data.dwStateAction = WTD_STATEACTION_CLOSE;
+        WinVerifyTrust(
+            HWND::default(),
+            &mut action,
+            &mut data as *mut WINTRUST_DATA as *mut c_void,
+        );
+
+        if status != 0 {
+            return Err(anyhow::anyhow!(
+                "Authenticode verification of {:?} failed: 0x{:08X}",
+                path,
+                status as u32
+            ));
+        }
+
+        let signer = signer.ok_or_else(|| anyhow::anyhow!("Failed to read the signing certificate of {:?}", path))?;
+        if !thumbprints.iter().any(|thumbprint| normalize_thumbprint(thumbprint) == signer) {
+            return Err(anyhow::anyhow!(
+                "{:?} is signed by certificate {}, which is not one of the pinned signers",
+                path,
+                signer
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Get the SHA-256 thumbprint of the leaf certificate of the first signer
+unsafe fn signer_thumbprint(state: HANDLE) -> Option<String> {
+    let provider = WTHelperProvDataFromStateData(state);
+    if provider.is_null() {
+        return None;
+    }
+    let signer = WTHelperGetProvSignerFromChain(provider, 0, false, 0);
+    if signer.is_null() {
+        return None;
+    }
+    let cert = WTHelperGetProvCertFromChain(signer, 0);
+    if cert.is_null() || (*cert).pCert.is_null() {
+        return None;
+    }
+
+    let context = &*(*cert).pCert;
+    let encoded = std::slice::from_raw_parts(context.pbCertEncoded, context.cbCertEncoded as usize);
+    Some(Sha256::digest(encoded).iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Normalize a thumbprint copied from the certificate dialog or PowerShell
+///
+/// Spaces and colons are removed and the hex digits are lowercased.
+pub fn normalize_thumbprint(thumbprint: &str) -> String {
+    thumbprint
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != ':' && *c != '\u{200e}')
+        .collect::<String>()
+        .to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_thumbprint() {
+        assert_eq!(normalize_thumbprint("AB CD:EF 01"), "abcdef01");
+        assert_eq!(normalize_thumbprint("\u{200e}ABCDEF"), "abcdef");
+    }
+}
@@ -0,0 +1,76 @@
+use chrono::{DateTime, Duration, Utc};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Source of the current time for scheduling decisions
+///
+/// The service loop, the reboot checks and the notification manager take the time from a clock
+/// instead of the system, so tests can move it on by hand and check what happens hours or days
+/// later without waiting.
+pub trait Clock: Send + Sync + fmt::Debug {
+    /// Get the current time
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// Clock following the system time
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Get the system clock, to share between the components that use it
+pub fn system() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
+
+/// Clock that only moves when it's told to, for tests
+///
+/// Clones share the same time, so a test can keep one and advance the clock it handed out.
+#[derive(Debug, Clone)]
+pub struct ManualClock(Arc<Mutex<DateTime<Utc>>>);
+
+impl ManualClock {
+    /// Create a clock showing a time
+    pub fn new(start: DateTime<Utc>) -> Self {
+        Self(Arc::new(Mutex::new(start)))
+    }
+
+    /// Move the clock forward, or back with a negative duration
+    pub fn advance(&self, duration: Duration) {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner()) += duration;
+    }
+
+    /// Set the clock to a time
+    pub fn set(&self, time: DateTime<Utc>) {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner()) = time;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_manual_clock() {
+        let start = Utc.with_ymd_and_hms(2024, 3, 30, 23, 0, 0).unwrap();
+        let clock = ManualClock::new(start);
+        let shared: Arc<dyn Clock> = Arc::new(clock.clone());
+
+        clock.advance(Duration::hours(2));
+        assert_eq!(shared.now(), start + Duration::hours(2));
+
+        clock.set(start);
+        assert_eq!(shared.now(), start);
+    }
+}
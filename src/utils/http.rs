@@ -0,0 +1,107 @@
+use crate::config::NetworkConfig;
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use once_cell::sync::Lazy;
+use reqwest::blocking::Client;
+use reqwest::{Certificate, Proxy};
+use std::fs;
+use std::sync::RwLock;
+use std::time::Duration;
+
+/// Network settings from the most recently loaded configuration
+static NETWORK_CONFIG: Lazy<RwLock<NetworkConfig>> = Lazy::new(|| RwLock::new(NetworkConfig::default()));
+
+/// Remember the network settings used for HTTP clients created without an explicit configuration
+pub fn set_network_config(config: &NetworkConfig) {
+    match NETWORK_CONFIG.write() {
+        Ok(mut current) => *current = config.clone(),
+        Err(e) => warn!("Failed to update network configuration: {}", e),
+    }
+}
+
+/// Get the network settings of the most recently loaded configuration
+pub fn network_config() -> NetworkConfig {
+    match NETWORK_CONFIG.read() {
+        Ok(network) => network.clone(),
+        Err(e) => {
+            warn!("Failed to read network configuration, using defaults: {}", e);
+            NetworkConfig::default()
+        }
+    }
+}
+
+/// Create an HTTP client using the current network settings
+pub fn client(timeout: Duration) -> Result<Client> {
+    client_with(&network_config(), timeout)
+}
+
+/// Create an HTTP client using the given network settings
+pub fn client_with(network: &NetworkConfig, timeout: Duration) -> Result<Client> {
+    let mut builder = Client::builder().timeout(timeout);
+
+    // Proxy
+    if let Some(proxy_url) = network.proxy_url.as_deref().filter(|url| !url.is_empty()) {
+        debug!("Using proxy: {}", proxy_url);
+        let mut proxy = Proxy::all(proxy_url).with_context(|| format!("Invalid proxy URL: {}", proxy_url))?;
+        if let Some(username) = &network.proxy_username {
+            let password = network.proxy_password.as_ref().map(|p| p.expose()).unwrap_or("");
+            proxy = proxy.basic_auth(username, password);
+        }
+        builder = builder.proxy(proxy);
+    }
+
+    // Custom CA bundle
+    if let Some(ca_path) = network.ca_bundle_path.as_deref().filter(|path| !path.is_empty()) {
+        debug!("Loading CA bundle: {}", ca_path);
+        let bundle = fs::read(ca_path).with_context(|| format!("Failed to read CA bundle: {}", ca_path))?;
+        for certificate in parse_pem_bundle(&bundle)? {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+
+    // TLS verification
+    if !network.verify_tls {
+        warn!("TLS certificate verification is disabled");
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+
+    builder.build().context("Failed to create HTTP client")
+}
+
+/// Parse every certificate in a PEM bundle
+fn parse_pem_bundle(bundle: &[u8]) -> Result<Vec<Certificate>> {
+    const END_MARKER: &str = "-----END CERTIFICATE-----";
+
+    let text = String::from_utf8_lossy(bundle);
+    let certificates = text
+        .split_inclusive(END_MARKER)
+        .filter(|block| block.contains(END_MARKER))
+        .map(|block| Certificate::from_pem(block.trim().as_bytes()).context("Failed to parse certificate in CA bundle"))
+        .collect::<Result<Vec<_>>>()?;
+
+    if certificates.is_empty() {
+        return Err(anyhow::anyhow!("CA bundle does not contain any PEM certificates"));
+    }
+
+    Ok(certificates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_with_invalid_proxy() {
+        let network = NetworkConfig {
+            proxy_url: Some("not a url".to_string()),
+            ..NetworkConfig::default()
+        };
+        assert!(client_with(&network, Duration::from_secs(5)).is_err());
+        assert!(client_with(&NetworkConfig::default(), Duration::from_secs(5)).is_ok());
+    }
+
+    #[test]
+    fn test_parse_pem_bundle_without_certificates() {
+        assert!(parse_pem_bundle(b"not a certificate").is_err());
+    }
+}
@@ -5,18 +5,22 @@ use std::os::windows::ffi::OsStringExt;
 use windows::Win32::System::Environment::{ExpandEnvironmentStringsW, GetEnvironmentVariableW};
 use windows::core::PCWSTR;
 
+pub mod clock;
 pub mod timespan;
+pub mod schedule;
 pub mod registry;
+pub mod secrets;
+pub mod http;
+pub mod sid;
 
 /// Expand Windows environment variables in a string
 ///
 /// This function expands environment variables in the format %VARIABLE% to their values.
-/// For example, %USERPROFILE% might expand to C:\Users\Username
+/// For example, %USERPROFILE% might expand to C:\Users\Username. Neither the input nor the
+/// result is logged, since configuration values with secrets are expanded too.
 pub fn expand_env_vars(input: &str) -> Result<String> {
-    debug!("Expanding environment variables in: {}", input);
-
     // Convert input to wide string (UTF-16)
-    let input_wide: Vec<u16> = input.encode_utf16().chain(std::iter::once(0)).collect();
+    let input_wide = to_wide(input);
 
     unsafe {
         // First call to get required buffer size
@@ -50,7 +54,6 @@ pub fn expand_env_vars(input: &str) -> Result<String> {
             Err(_) => return Err(anyhow::anyhow!("Failed to convert expanded environment variables to string")),
         };
 
-        debug!("Expanded to: {}", expanded);
         Ok(expanded)
     }
 }
@@ -60,7 +63,7 @@ pub fn get_env_var(name: &str) -> Result<String> {
     debug!("Getting environment variable: {}", name);
 
     // Convert name to wide string (UTF-16)
-    let name_wide: Vec<u16> = name.encode_utf16().chain(std::iter::once(0)).collect();
+    let name_wide = to_wide(name);
 
     unsafe {
         // First call to get required buffer size
@@ -102,6 +105,22 @@ pub fn get_env_var(name: &str) -> Result<String> {
     }
 }
 
+/// Convert a string to a null-terminated wide string
+pub fn to_wide(value: &str) -> Vec<u16> {
+    value.encode_utf16().chain(std::iter::once(0)).collect()
+}
+
+/// Copy a string into a fixed-size wide string buffer, truncating it to fit
+pub fn copy_wide(buffer: &mut [u16], value: &str) {
+    let len = buffer.len().saturating_sub(1);
+    let mut written = 0;
+    for (slot, c) in buffer.iter_mut().zip(value.encode_utf16().take(len)) {
+        *slot = c;
+        written += 1;
+    }
+    buffer[written] = 0;
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -138,4 +157,16 @@ mod tests {
         let result = get_env_var("NON_EXISTENT_VAR");
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_copy_wide() {
+        let mut buffer = [0xFFFFu16; 4];
+        copy_wide(&mut buffer, "Restart now");
+        assert_eq!(String::from_utf16_lossy(&buffer[..3]), "Res");
+        assert_eq!(buffer[3], 0);
+
+        let mut buffer = [0xFFFFu16; 8];
+        copy_wide(&mut buffer, "Hi");
+        assert_eq!(&buffer[..3], &[b'H' as u16, b'i' as u16, 0]);
+    }
 }
@@ -1,19 +1,101 @@
+use super::to_wide;
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use log::{debug, warn};
 use std::ffi::OsString;
 use std::os::windows::ffi::OsStringExt;
-use windows::core::PCWSTR;
-use windows::Win32::Foundation::ERROR_SUCCESS;
+use windows::core::{PCWSTR, PWSTR};
+use windows::Win32::Foundation::{ERROR_FILE_NOT_FOUND, ERROR_NO_MORE_ITEMS, ERROR_SUCCESS, FILETIME};
 use windows::Win32::System::Registry::{
-    HKEY, HKEY_LOCAL_MACHINE, HKEY_CURRENT_USER, KEY_QUERY_VALUE, KEY_READ, REG_DWORD, REG_MULTI_SZ, REG_SZ,
-    RegCloseKey, RegOpenKeyExW, RegQueryValueExW,
+    HKEY, HKEY_LOCAL_MACHINE, HKEY_CURRENT_USER, HKEY_USERS, KEY_QUERY_VALUE, KEY_READ, KEY_WRITE, REG_DWORD,
+    REG_MULTI_SZ, REG_OPTION_NON_VOLATILE, REG_SZ, RegCloseKey, RegConnectRegistryW, RegCreateKeyExW, RegDeleteTreeW,
+    RegDeleteValueW, RegEnumKeyExW, RegOpenKeyExW, RegQueryInfoKeyW, RegQueryValueExW, RegSetValueExW,
 };
 
+/// Connect to a hive (HKEY_LOCAL_MACHINE or HKEY_USERS) of a remote computer through the Remote
+/// Registry service; the returned key must be closed with `RegCloseKey`
+pub fn connect(computer: &str, hive: HKEY) -> Result<HKEY> {
+    debug!("Connecting to {} on {}", hive_to_string(hive), computer);
+
+    let computer_wide = to_wide(&format!("\\\\{}", computer));
+    let mut h_key = HKEY::default();
+
+    unsafe {
+        let result = RegConnectRegistryW(PCWSTR::from_raw(computer_wide.as_ptr()), hive, &mut h_key);
+        if result != ERROR_SUCCESS {
+            return Err(anyhow::anyhow!(
+                "Failed to connect to the registry of {}: error code {}",
+                computer,
+                result.0
+            ));
+        }
+    }
+
+    Ok(h_key)
+}
+
+/// Get the names of the subkeys of a registry key, or an empty list if the key doesn't exist
+pub fn subkey_names(hive: HKEY, key_path: &str) -> Result<Vec<String>> {
+    debug!("Enumerating registry subkeys: {}\\{}", hive_to_string(hive), key_path);
+
+    let key_path_wide = to_wide(key_path);
+    let mut h_key = HKEY::default();
+    let mut names = Vec::new();
+
+    unsafe {
+        let result = RegOpenKeyExW(
+            hive,
+            PCWSTR::from_raw(key_path_wide.as_ptr()),
+            Some(0),
+            KEY_READ,
+            &mut h_key,
+        );
+
+        if result != ERROR_SUCCESS {
+            debug!("Registry key does not exist: {}\\{}", hive_to_string(hive), key_path);
+            return Ok(names);
+        }
+
+        // Key names are at most 255 characters
+        let mut buffer = vec![0u16; 256];
+        for index in 0.. {
+            let mut length = buffer.len() as u32;
+            let result = RegEnumKeyExW(
+                h_key,
+                index,
+                Some(PWSTR(buffer.as_mut_ptr())),
+                &mut length,
+                None,
+                None,
+                None,
+                None,
+            );
+            if result == ERROR_NO_MORE_ITEMS {
+                break;
+            }
+            if result != ERROR_SUCCESS {
+                let _ = RegCloseKey(h_key);
+                return Err(anyhow::anyhow!(
+                    "Failed to enumerate registry key {}\\{}: error code {}",
+                    hive_to_string(hive),
+                    key_path,
+                    result.0
+                ));
+            }
+            names.push(String::from_utf16_lossy(&buffer[..length as usize]));
+        }
+
+        let _ = RegCloseKey(h_key);
+    }
+
+    Ok(names)
+}
+
 /// Check if a registry key exists
 pub fn key_exists(hive: HKEY, key_path: &str) -> Result<bool> {
     debug!("Checking if registry key exists: {}\\{}", hive_to_string(hive), key_path);
 
-    let key_path_wide: Vec<u16> = key_path.encode_utf16().chain(std::iter::once(0)).collect();
+    let key_path_wide = to_wide(key_path);
     let mut h_key = HKEY::default();
 
     unsafe {
@@ -38,8 +120,8 @@ pub fn key_exists(hive: HKEY, key_path: &str) -> Result<bool> {
 pub fn value_exists(hive: HKEY, key_path: &str, value_name: &str) -> Result<bool> {
     debug!("Checking if registry value exists: {}\\{}\\{}", hive_to_string(hive), key_path, value_name);
 
-    let key_path_wide: Vec<u16> = key_path.encode_utf16().chain(std::iter::once(0)).collect();
-    let value_name_wide: Vec<u16> = value_name.encode_utf16().chain(std::iter::once(0)).collect();
+    let key_path_wide = to_wide(key_path);
+    let value_name_wide = to_wide(value_name);
     let mut h_key = HKEY::default();
 
     unsafe {
@@ -82,8 +164,8 @@ pub fn value_exists(hive: HKEY, key_path: &str, value_name: &str) -> Result<bool
 pub fn get_string_value(hive: HKEY, key_path: &str, value_name: &str) -> Result<Option<String>> {
     debug!("Getting string value from registry: {}\\{}\\{}", hive_to_string(hive), key_path, value_name);
 
-    let key_path_wide: Vec<u16> = key_path.encode_utf16().chain(std::iter::once(0)).collect();
-    let value_name_wide: Vec<u16> = value_name.encode_utf16().chain(std::iter::once(0)).collect();
+    let key_path_wide = to_wide(key_path);
+    let value_name_wide = to_wide(value_name);
     let mut h_key = HKEY::default();
 
     unsafe {
@@ -163,12 +245,26 @@ pub fn get_string_value(hive: HKEY, key_path: &str, value_name: &str) -> Result<
     }
 }
 
+/// Get a REG_MULTI_SZ value as its strings, keeping the empty strings inside the list
+pub fn get_multi_string_value(hive: HKEY, key_path: &str, value_name: &str) -> Result<Option<Vec<String>>> {
+    Ok(get_string_value(hive, key_path, value_name)?.map(|value| split_multi_string(&value)))
+}
+
+/// Split a REG_MULTI_SZ value, read without its final terminator, into its strings
+fn split_multi_string(value: &str) -> Vec<String> {
+    match value.strip_suffix('\0') {
+        Some(value) => value.split('\0').map(str::to_string).collect(),
+        None if value.is_empty() => Vec::new(),
+        None => value.split('\0').map(str::to_string).collect(),
+    }
+}
+
 /// Get a DWORD value from the registry
 pub fn get_dword_value(hive: HKEY, key_path: &str, value_name: &str) -> Result<Option<u32>> {
     debug!("Getting DWORD value from registry: {}\\{}\\{}", hive_to_string(hive), key_path, value_name);
 
-    let key_path_wide: Vec<u16> = key_path.encode_utf16().chain(std::iter::once(0)).collect();
-    let value_name_wide: Vec<u16> = value_name.encode_utf16().chain(std::iter::once(0)).collect();
+    let key_path_wide = to_wide(key_path);
+    let value_name_wide = to_wide(value_name);
     let mut h_key = HKEY::default();
 
     unsafe {
@@ -214,6 +310,273 @@ pub fn get_dword_value(hive: HKEY, key_path: &str, value_name: &str) -> Result<O
     }
 }
 
+/// Set a string value in the registry, creating the key if needed
+///
+/// An empty value name sets the default value of the key.
+pub fn set_string_value(hive: HKEY, key_path: &str, value_name: &str, value: &str) -> Result<()> {
+    debug!("Setting string value in registry: {}\\{}\\{}", hive_to_string(hive), key_path, value_name);
+
+    let key_path_wide = to_wide(key_path);
+    let value_name_wide = to_wide(value_name);
+    let data: Vec<u8> = to_wide(value).into_iter().flat_map(u16::to_le_bytes).collect();
+    let mut h_key = HKEY::default();
+
+    unsafe {
+        let result = RegCreateKeyExW(
+            hive,
+            PCWSTR::from_raw(key_path_wide.as_ptr()),
+            None,
+            PCWSTR::null(),
+            REG_OPTION_NON_VOLATILE,
+            KEY_WRITE,
+            None,
+            &mut h_key,
+            None,
+        );
+        if result != ERROR_SUCCESS {
+            return Err(anyhow::anyhow!(
+                "Failed to create registry key {}\\{}: error {}",
+                hive_to_string(hive),
+                key_path,
+                result.0
+            ));
+        }
+
+        let result = RegSetValueExW(h_key, PCWSTR::from_raw(value_name_wide.as_ptr()), None, REG_SZ, Some(&data));
+        let _ = RegCloseKey(h_key);
+        if result != ERROR_SUCCESS {
+            return Err(anyhow::anyhow!(
+                "Failed to set registry value {}\\{}\\{}: error {}",
+                hive_to_string(hive),
+                key_path,
+                value_name,
+                result.0
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Set a DWORD value in the registry, creating the key if needed
+pub fn set_dword_value(hive: HKEY, key_path: &str, value_name: &str, value: u32) -> Result<()> {
+    debug!("Setting DWORD value in registry: {}\\{}\\{} = {}", hive_to_string(hive), key_path, value_name, value);
+
+    let key_path_wide = to_wide(key_path);
+    let value_name_wide = to_wide(value_name);
+    let mut h_key = HKEY::default();
+
+    unsafe {
+        let result = RegCreateKeyExW(
+            hive,
+            PCWSTR::from_raw(key_path_wide.as_ptr()),
+            None,
+            PCWSTR::null(),
+            REG_OPTION_NON_VOLATILE,
+            KEY_WRITE,
+            None,
+            &mut h_key,
+            None,
+        );
+        if result != ERROR_SUCCESS {
+            return Err(anyhow::anyhow!(
+                "Failed to create registry key {}\\{}: error {}",
+                hive_to_string(hive),
+                key_path,
+                result.0
+            ));
+        }
+
+        let result = RegSetValueExW(
+            h_key,
+            PCWSTR::from_raw(value_name_wide.as_ptr()),
+            None,
+            REG_DWORD,
+            Some(&value.to_le_bytes()),
+        );
+        let _ = RegCloseKey(h_key);
+        if result != ERROR_SUCCESS {
+            return Err(anyhow::anyhow!(
+                "Failed to set registry value {}\\{}\\{}: error {}",
+                hive_to_string(hive),
+                key_path,
+                value_name,
+                result.0
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Set a REG_MULTI_SZ value in an existing registry key
+pub fn set_multi_string_value(hive: HKEY, key_path: &str, value_name: &str, values: &[String]) -> Result<()> {
+    debug!("Setting multi-string value in registry: {}\\{}\\{}", hive_to_string(hive), key_path, value_name);
+
+    let key_path_wide = to_wide(key_path);
+    let value_name_wide = to_wide(value_name);
+    let data: Vec<u8> = values
+        .iter()
+        .flat_map(|value| to_wide(value))
+        .chain(std::iter::once(0))
+        .flat_map(u16::to_le_bytes)
+        .collect();
+    let mut h_key = HKEY::default();
+
+    unsafe {
+        let result = RegOpenKeyExW(
+            hive,
+            PCWSTR::from_raw(key_path_wide.as_ptr()),
+            Some(0),
+            KEY_WRITE,
+            &mut h_key,
+        );
+        if result != ERROR_SUCCESS {
+            return Err(anyhow::anyhow!(
+                "Failed to open registry key {}\\{}: error {}",
+                hive_to_string(hive),
+                key_path,
+                result.0
+            ));
+        }
+
+        let result = RegSetValueExW(h_key, PCWSTR::from_raw(value_name_wide.as_ptr()), None, REG_MULTI_SZ, Some(&data));
+        let _ = RegCloseKey(h_key);
+        if result != ERROR_SUCCESS {
+            return Err(anyhow::anyhow!(
+                "Failed to set registry value {}\\{}\\{}: error {}",
+                hive_to_string(hive),
+                key_path,
+                value_name,
+                result.0
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Delete a registry value
+///
+/// Returns false if the key or value doesn't exist.
+pub fn delete_value(hive: HKEY, key_path: &str, value_name: &str) -> Result<bool> {
+    debug!("Deleting registry value: {}\\{}\\{}", hive_to_string(hive), key_path, value_name);
+
+    let key_path_wide = to_wide(key_path);
+    let value_name_wide = to_wide(value_name);
+    let mut h_key = HKEY::default();
+
+    unsafe {
+        let result = RegOpenKeyExW(
+            hive,
+            PCWSTR::from_raw(key_path_wide.as_ptr()),
+            Some(0),
+            KEY_WRITE,
+            &mut h_key,
+        );
+        if result == ERROR_FILE_NOT_FOUND {
+            return Ok(false);
+        }
+        if result != ERROR_SUCCESS {
+            return Err(anyhow::anyhow!(
+                "Failed to open registry key {}\\{}: error {}",
+                hive_to_string(hive),
+                key_path,
+                result.0
+            ));
+        }
+
+        let result = RegDeleteValueW(h_key, PCWSTR::from_raw(value_name_wide.as_ptr()));
+        let _ = RegCloseKey(h_key);
+        if result == ERROR_SUCCESS {
+            Ok(true)
+        } else if result == ERROR_FILE_NOT_FOUND {
+            Ok(false)
+        } else {
+            Err(anyhow::anyhow!(
+                "Failed to delete registry value {}\\{}\\{}: error {}",
+                hive_to_string(hive),
+                key_path,
+                value_name,
+                result.0
+            ))
+        }
+    }
+}
+
+/// Get the time a registry key was last written, or None if it doesn't exist
+pub fn key_last_write_time(hive: HKEY, key_path: &str) -> Result<Option<DateTime<Utc>>> {
+    let key_path_wide = to_wide(key_path);
+    let mut h_key = HKEY::default();
+    let mut last_write = FILETIME::default();
+
+    unsafe {
+        let result = RegOpenKeyExW(
+            hive,
+            PCWSTR::from_raw(key_path_wide.as_ptr()),
+            Some(0),
+            KEY_QUERY_VALUE,
+            &mut h_key,
+        );
+        if result != ERROR_SUCCESS {
+            debug!("Registry key does not exist: {}\\{}", hive_to_string(hive), key_path);
+            return Ok(None);
+        }
+
+        let result = RegQueryInfoKeyW(
+            h_key,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(&mut last_write),
+        );
+        let _ = RegCloseKey(h_key);
+        if result != ERROR_SUCCESS {
+            return Err(anyhow::anyhow!(
+                "Failed to query registry key {}\\{}: error {}",
+                hive_to_string(hive),
+                key_path,
+                result.0
+            ));
+        }
+    }
+
+    // FILETIME counts 100 nanosecond intervals since 1601-01-01
+    let intervals = ((last_write.dwHighDateTime as u64) << 32) | last_write.dwLowDateTime as u64;
+    let unix_seconds = (intervals / 10_000_000) as i64 - 11_644_473_600;
+    Ok(DateTime::from_timestamp(unix_seconds, 0))
+}
+
+/// Delete a registry key with all its subkeys and values
+///
+/// Returns false if the key doesn't exist.
+pub fn delete_key_tree(hive: HKEY, key_path: &str) -> Result<bool> {
+    debug!("Deleting registry key: {}\\{}", hive_to_string(hive), key_path);
+
+    let key_path_wide = to_wide(key_path);
+    let result = unsafe { RegDeleteTreeW(hive, PCWSTR::from_raw(key_path_wide.as_ptr())) };
+
+    if result == ERROR_SUCCESS {
+        Ok(true)
+    } else if result == ERROR_FILE_NOT_FOUND {
+        Ok(false)
+    } else {
+        Err(anyhow::anyhow!(
+            "Failed to delete registry key {}\\{}: error {}",
+            hive_to_string(hive),
+            key_path,
+            result.0
+        ))
+    }
+}
+
 /// Compare two computer names from registry
 pub fn compare_computer_names(active_name: &str, pending_name: &str) -> bool {
     active_name.eq_ignore_ascii_case(pending_name)
@@ -225,7 +588,21 @@ fn hive_to_string(hive: HKEY) -> &'static str {
         "HKLM"
     } else if hive == HKEY_CURRENT_USER {
         "HKCU"
+    } else if hive == HKEY_USERS {
+        "HKU"
     } else {
         "Unknown"
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_multi_string() {
+        assert_eq!(split_multi_string("a\0b\0c\0\0"), vec!["a", "b", "c", ""]);
+        assert_eq!(split_multi_string("a\0b"), vec!["a", "b"]);
+        assert!(split_multi_string("").is_empty());
+    }
+}
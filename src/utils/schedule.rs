@@ -0,0 +1,139 @@
+use crate::config::ServiceConfig;
+use anyhow::Result;
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use log::warn;
+
+/// Longest time the clocks jump forward by, in minutes, when daylight saving time starts
+const MAX_DST_GAP_MINUTES: i64 = 180;
+
+/// Time zone that schedules like quiet hours and maintenance windows are evaluated in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScheduleZone {
+    /// The time zone of the computer
+    #[default]
+    Local,
+
+    /// An IANA time zone (e.g., "Europe/Amsterdam")
+    Named(Tz),
+}
+
+impl ScheduleZone {
+    /// Parse an IANA time zone name, where None is the time zone of the computer
+    pub fn parse(name: Option<&str>) -> Result<Self> {
+        match name {
+            None => Ok(Self::Local),
+            Some(name) => name
+                .parse::<Tz>()
+                .map(Self::Named)
+                .map_err(|e| anyhow::anyhow!("Unknown time zone '{}': {}", name, e)),
+        }
+    }
+
+    /// Get the time zone configured for the service, falling back to the time zone of the computer
+    pub fn from_config(config: &ServiceConfig) -> Self {
+        Self::parse(config.time_zone.as_deref()).unwrap_or_else(|e| {
+            warn!("{}, using the time zone of the computer", e);
+            Self::Local
+        })
+    }
+
+    /// Get the day of the week (0 = Sunday, 6 = Saturday) and time of day of a moment
+    pub fn day_and_time(&self, time: DateTime<Utc>) -> (u8, NaiveTime) {
+        match self {
+            Self::Local => day_and_time(time.with_timezone(&Local)),
+            Self::Named(tz) => day_and_time(time.with_timezone(tz)),
+        }
+    }
+
+    /// Get the date of a moment
+    pub fn date(&self, time: DateTime<Utc>) -> NaiveDate {
+        match self {
+            Self::Local => time.with_timezone(&Local).date_naive(),
+            Self::Named(tz) => time.with_timezone(tz).date_naive(),
+        }
+    }
+
+    /// Get the moment a date and time of day in this time zone happen
+    ///
+    /// A time that happens twice when the clocks go back is taken the first time, and a time
+    /// skipped when the clocks go forward is moved to the end of the skipped hour.
+    pub fn to_utc(&self, local: NaiveDateTime) -> Option<DateTime<Utc>> {
+        (0..=MAX_DST_GAP_MINUTES).find_map(|minutes| {
+            let local = local + Duration::minutes(minutes);
+            match self {
+                Self::Local => Local.from_local_datetime(&local).earliest().map(|time| time.with_timezone(&Utc)),
+                Self::Named(tz) => tz.from_local_datetime(&local).earliest().map(|time| time.with_timezone(&Utc)),
+            }
+        })
+    }
+}
+
+/// Get the day of the week (0 = Sunday) and time of day of a moment in its own time zone
+fn day_and_time<T: TimeZone>(time: DateTime<T>) -> (u8, NaiveTime) {
+    (time.weekday().num_days_from_sunday() as u8, time.time())
+}
+
+/// Check whether a day of the week and time of day fall within a range that starts on one of
+/// `days` (0 = Sunday, 6 = Saturday)
+///
+/// A range that ends before it starts spans midnight, so its early hours belong to the day
+/// before, and a range that ends when it starts lasts the whole day.
+pub fn in_range(days: &[u8], start: NaiveTime, end: NaiveTime, day: u8, time: NaiveTime) -> bool {
+    if start == end {
+        days.contains(&day)
+    } else if start > end {
+        let previous_day = (day + 6) % 7;
+        (days.contains(&day) && time >= start) || (days.contains(&previous_day) && time < end)
+    } else {
+        days.contains(&day) && time >= start && time < end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn test_in_range() {
+        // Friday night until Saturday morning
+        let days = [5];
+        assert!(in_range(&days, time(22, 0), time(7, 0), 5, time(23, 0)));
+        assert!(in_range(&days, time(22, 0), time(7, 0), 6, time(6, 59)));
+        assert!(!in_range(&days, time(22, 0), time(7, 0), 6, time(23, 0)));
+        assert!(!in_range(&days, time(22, 0), time(7, 0), 5, time(6, 0)));
+
+        assert!(in_range(&days, time(9, 0), time(17, 0), 5, time(9, 0)));
+        assert!(!in_range(&days, time(9, 0), time(17, 0), 5, time(17, 0)));
+
+        // A whole day
+        assert!(in_range(&days, time(0, 0), time(0, 0), 5, time(23, 59)));
+        assert!(!in_range(&days, time(0, 0), time(0, 0), 6, time(0, 0)));
+    }
+
+    #[test]
+    fn test_named_zone_across_dst() {
+        let zone = ScheduleZone::parse(Some("Europe/Amsterdam")).unwrap();
+        assert!(ScheduleZone::parse(Some("Mars/Olympus_Mons")).is_err());
+        assert_eq!(ScheduleZone::parse(None).unwrap(), ScheduleZone::Local);
+
+        // 23:30 UTC on Saturday 30 March 2024 is already Sunday in Amsterdam
+        let saturday_night = Utc.with_ymd_and_hms(2024, 3, 30, 23, 30, 0).unwrap();
+        assert_eq!(zone.day_and_time(saturday_night), (0, time(0, 30)));
+
+        // The clocks went forward from 02:00 to 03:00 that night, and back from 03:00 to 02:00
+        // on 27 October
+        let after_change = Utc.with_ymd_and_hms(2024, 3, 31, 1, 30, 0).unwrap();
+        assert_eq!(zone.day_and_time(after_change), (0, time(3, 30)));
+
+        let skipped = NaiveDate::from_ymd_opt(2024, 3, 31).unwrap().and_time(time(2, 30));
+        assert_eq!(zone.to_utc(skipped), Some(Utc.with_ymd_and_hms(2024, 3, 31, 1, 0, 0).unwrap()));
+
+        let repeated = NaiveDate::from_ymd_opt(2024, 10, 27).unwrap().and_time(time(2, 30));
+        assert_eq!(zone.to_utc(repeated), Some(Utc.with_ymd_and_hms(2024, 10, 27, 0, 30, 0).unwrap()));
+    }
+}
@@ -0,0 +1,195 @@
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use log::debug;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{LocalFree, HLOCAL};
+use windows::Win32::Security::Cryptography::{
+    CryptProtectData, CryptUnprotectData, CRYPTPROTECT_LOCAL_MACHINE, CRYPTPROTECT_UI_FORBIDDEN,
+    CRYPT_INTEGER_BLOB,
+};
+
+/// Prefix marking a value encrypted with the DPAPI machine key
+pub const DPAPI_PREFIX: &str = "dpapi:";
+
+/// Check if a value is encrypted with DPAPI
+pub fn is_protected(value: &str) -> bool {
+    value.starts_with(DPAPI_PREFIX)
+}
+
+/// Encrypt a value with the DPAPI machine key
+///
+/// The result is prefixed with `dpapi:` and can be decrypted by any account on this machine.
+pub fn protect(plaintext: &str) -> Result<String> {
+    let mut data = plaintext.as_bytes().to_vec();
+    let input = CRYPT_INTEGER_BLOB {
+        cbData: data.len() as u32,
+        pbData: data.as_mut_ptr(),
+    };
+    let mut output = CRYPT_INTEGER_BLOB::default();
+
+    unsafe {
+        CryptProtectData(
+            &input,
+            PCWSTR::null(),
+            None,
+            None,
+            None,
+            CRYPTPROTECT_LOCAL_MACHINE | CRYPTPROTECT_UI_FORBIDDEN,
+            &mut output,
+        )
+        .context("Failed to encrypt value with DPAPI")?;
+    }
+
+    let encrypted = take_blob(output);
+    Ok(format!("{}{}", DPAPI_PREFIX, BASE64.encode(encrypted)))
+}
+
+/// Decrypt a `dpapi:` value with the DPAPI machine key
+pub fn unprotect(value: &str) -> Result<String> {
+    let encoded = value
+        .strip_prefix(DPAPI_PREFIX)
+        .ok_or_else(|| anyhow::anyhow!("Value is not DPAPI protected"))?;
+    let mut data = BASE64
+        .decode(encoded.trim())
+        .context("Failed to decode DPAPI protected value")?;
+
+    let input = CRYPT_INTEGER_BLOB {
+        cbData: data.len() as u32,
+        pbData: data.as_mut_ptr(),
+    };
+    let mut output = CRYPT_INTEGER_BLOB::default();
+
+    unsafe {
+        CryptUnprotectData(
+            &input,
+            None,
+            None,
+            None,
+            None,
+            CRYPTPROTECT_UI_FORBIDDEN,
+            &mut output,
+        )
+        .context("Failed to decrypt value with DPAPI")?;
+    }
+
+    let decrypted = take_blob(output);
+    String::from_utf8(decrypted).context("Decrypted value is not valid UTF-8")
+}
+
+/// Resolve a configuration value, decrypting it if it is DPAPI protected
+pub fn resolve(value: &str) -> Result<String> {
+    if is_protected(value) {
+        debug!("Decrypting DPAPI protected value");
+        unprotect(value)
+    } else {
+        Ok(value.to_string())
+    }
+}
+
+/// Copy a blob allocated by DPAPI and free the original
+fn take_blob(blob: CRYPT_INTEGER_BLOB) -> Vec<u8> {
+    if blob.pbData.is_null() {
+        return Vec::new();
+    }
+
+    unsafe {
+        let data = std::slice::from_raw_parts(blob.pbData, blob.cbData as usize).to_vec();
+        let _ = LocalFree(Some(HLOCAL(blob.pbData as *mut _)));
+        data
+    }
+}
+
+/// Secret configuration value
+///
+/// Accepts plain text or `dpapi:` protected values. Protected values are decrypted when the
+/// configuration is loaded, are written back in their protected form, and never appear in logs.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Secret {
+    raw: String,
+    value: String,
+}
+
+impl Secret {
+    /// Create a secret from a plain text or `dpapi:` protected value
+    pub fn new(raw: &str) -> Result<Self> {
+        Ok(Self {
+            raw: raw.to_string(),
+            value: resolve(raw)?,
+        })
+    }
+
+    /// Get the decrypted value
+    pub fn expose(&self) -> &str {
+        &self.value
+    }
+
+    /// Check if the secret was stored DPAPI protected
+    pub fn is_protected(&self) -> bool {
+        is_protected(&self.raw)
+    }
+
+    /// Check if the secret is empty
+    pub fn is_empty(&self) -> bool {
+        self.value.is_empty()
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(***)")
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.raw)
+    }
+}
+
+impl<'de> Deserialize<'de> for Secret {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        Secret::new(&raw).map_err(|e| serde::de::Error::custom(format!("{:#}", e)))
+    }
+}
+
+impl JsonSchema for Secret {
+    fn schema_name() -> String {
+        "Secret".to_string()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        String::json_schema(gen)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_protect_roundtrip() {
+        // This test will only work on Windows
+        let protected = protect("s3cret").unwrap();
+        assert!(protected.starts_with(DPAPI_PREFIX));
+        assert!(!protected.contains("s3cret"));
+        assert_eq!(unprotect(&protected).unwrap(), "s3cret");
+    }
+
+    #[test]
+    fn test_secret() {
+        let secret: Secret = serde_json::from_str("\"plain\"").unwrap();
+        assert_eq!(secret.expose(), "plain");
+        assert!(!secret.is_protected());
+        assert_eq!(format!("{:?}", secret), "Secret(***)");
+
+        // Invalid protected values fail to load
+        assert!(serde_json::from_str::<Secret>("\"dpapi:not base64!\"").is_err());
+    }
+}
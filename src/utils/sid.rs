@@ -0,0 +1,147 @@
+use super::to_wide;
+use crate::impersonation::SessionToken;
+use anyhow::{Context, Result};
+use log::debug;
+use once_cell::sync::Lazy;
+use std::ffi::c_void;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use windows::core::{PCWSTR, PWSTR};
+use windows::Win32::Foundation::{CloseHandle, LocalFree, HANDLE, HLOCAL};
+use windows::Win32::Security::Authorization::{ConvertSidToStringSidW, ConvertStringSidToSidW};
+use windows::Win32::Security::{
+    GetTokenInformation, LookupAccountSidW, TokenUser, PSID, SID_NAME_USE, TOKEN_QUERY, TOKEN_USER,
+};
+use windows::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+
+/// Account names resolved so far by SID, including SIDs that couldn't be resolved
+static ACCOUNT_NAMES: Lazy<Mutex<HashMap<String, Option<String>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Resolve a SID string (e.g., "S-1-5-18") to a `DOMAIN\user` account name
+///
+/// Lookups, including failed ones, are cached for the lifetime of the process.
+pub fn account_name(sid: &str) -> Option<String> {
+    if let Ok(cache) = ACCOUNT_NAMES.lock() {
+        if let Some(name) = cache.get(sid) {
+            return name.clone();
+        }
+    }
+
+    let name = match lookup_account_sid(sid) {
+        Ok(name) => Some(name),
+        Err(e) => {
+            debug!("Failed to resolve SID {}: {:#}", sid, e);
+            None
+        }
+    };
+
+    if let Ok(mut cache) = ACCOUNT_NAMES.lock() {
+        cache.insert(sid.to_string(), name.clone());
+    }
+    name
+}
+
+/// Format an account name as `DOMAIN\user`, or just the user when there is no domain
+pub fn format_account(domain: &str, name: &str) -> String {
+    if domain.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}\\{}", domain, name)
+    }
+}
+
+/// Get the SID of the user logged on to a session (e.g., "S-1-5-21-...-1001")
+///
+/// When not running as a service the SID of the current user is returned instead.
+pub fn session_user_sid(session_id: &str, running_as_service: bool) -> Option<String> {
+    if running_as_service {
+        return match SessionToken::query(session_id) {
+            Ok(token) => unsafe { token_user_sid(token.handle()) },
+            Err(e) => {
+                debug!("{:#}", e);
+                None
+            }
+        };
+    }
+
+    let mut token = HANDLE::default();
+    unsafe {
+        if let Err(e) = OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) {
+            debug!("Failed to open process token: {}", e);
+            return None;
+        }
+
+        let sid = token_user_sid(token);
+        let _ = CloseHandle(token);
+        sid
+    }
+}
+
+/// Get the SID of the user a token belongs to
+unsafe fn token_user_sid(token: HANDLE) -> Option<String> {
+    let mut length = 0;
+    let _ = GetTokenInformation(token, TokenUser, None, 0, &mut length);
+    if length == 0 {
+        return None;
+    }
+
+    // Use u64 elements so the buffer is aligned for TOKEN_USER
+    let mut buffer = vec![0u64; (length as usize).div_ceil(8)];
+    GetTokenInformation(token, TokenUser, Some(buffer.as_mut_ptr() as *mut c_void), length, &mut length).ok()?;
+    let user = &*(buffer.as_ptr() as *const TOKEN_USER);
+
+    let mut string_sid = PWSTR::null();
+    ConvertSidToStringSidW(user.User.Sid, &mut string_sid).ok()?;
+    let sid = string_sid.to_string().ok();
+    let _ = LocalFree(Some(HLOCAL(string_sid.0 as *mut c_void)));
+    sid
+}
+
+/// Look up the account a SID string belongs to
+fn lookup_account_sid(sid: &str) -> Result<String> {
+    let sid_wide = to_wide(sid);
+
+    unsafe {
+        let mut psid = PSID::default();
+        ConvertStringSidToSidW(PCWSTR::from_raw(sid_wide.as_ptr()), &mut psid)
+            .with_context(|| format!("Invalid SID: {}", sid))?;
+
+        let mut name = vec![0u16; 256];
+        let mut domain = vec![0u16; 256];
+        let mut name_len = name.len() as u32;
+        let mut domain_len = domain.len() as u32;
+        let mut sid_use = SID_NAME_USE::default();
+
+        let result = LookupAccountSidW(
+            PCWSTR::null(),
+            psid,
+            Some(PWSTR(name.as_mut_ptr())),
+            &mut name_len,
+            Some(PWSTR(domain.as_mut_ptr())),
+            &mut domain_len,
+            &mut sid_use,
+        );
+        let _ = LocalFree(Some(HLOCAL(psid.0)));
+        result.context("Failed to look up account")?;
+
+        Ok(format_account(
+            &String::from_utf16_lossy(&domain[..domain_len as usize]),
+            &String::from_utf16_lossy(&name[..name_len as usize]),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_account_name() {
+        assert_eq!(format_account("EXAMPLE", "jdoe"), "EXAMPLE\\jdoe");
+        assert_eq!(format_account("", "Everyone"), "Everyone");
+
+        // This test will only work on Windows
+        assert_eq!(account_name("S-1-5-18").as_deref(), Some("NT AUTHORITY\\SYSTEM"));
+        assert_eq!(account_name("not a sid"), None);
+    }
+}
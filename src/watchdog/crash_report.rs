@@ -0,0 +1,154 @@
+use crate::config::CrashReportConfig;
+use crate::database::{self, DbPool, DeliveryStatus, Notification, RebootState};
+use crate::utils::http;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use log::{debug, info, warn};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::time::Instant;
+
+/// Notification type uploaded crash reports are recorded under, for the rate limit
+pub const CRASH_REPORT_TYPE: &str = "crash_report";
+
+/// Most of the log file that is uploaded, counted from its end
+const MAX_LOG_BYTES: u64 = 1024 * 1024;
+
+/// How long the upload may take
+const UPLOAD_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Diagnostics uploaded when the service keeps restarting
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CrashReport {
+    pub computer_name: String,
+    pub version: String,
+    pub timestamp: DateTime<Utc>,
+
+    /// Restarts within the configured window
+    pub restarts: usize,
+
+    /// Reboot state stored in the database, if it could be read
+    pub state: Option<RebootState>,
+
+    /// End of the current log file
+    pub log: String,
+}
+
+/// Restarts of the service within a sliding time window
+#[derive(Debug, Default)]
+pub struct RestartTracker {
+    restarts: VecDeque<Instant>,
+}
+
+impl RestartTracker {
+    /// Record a restart and get the number of restarts within the window
+    pub fn record(&mut self, now: Instant, window: std::time::Duration) -> usize {
+        self.restarts.push_back(now);
+        while self
+            .restarts
+            .front()
+            .is_some_and(|restart| now.duration_since(*restart) > window)
+        {
+            self.restarts.pop_front();
+        }
+        self.restarts.len()
+    }
+}
+
+/// Get the window restarts are counted in
+pub fn window(config: &CrashReportConfig) -> std::time::Duration {
+    crate::utils::timespan::parse_timespan(&config.window).unwrap_or(std::time::Duration::from_secs(3600))
+}
+
+/// Check if the rate limit allows another upload
+pub fn upload_allowed(config: &CrashReportConfig, last_upload: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+    let min_interval = crate::utils::timespan::parse_timespan(&config.min_interval)
+        .ok()
+        .and_then(|interval| Duration::from_std(interval).ok())
+        .unwrap_or_else(|| Duration::hours(24));
+
+    last_upload.map_or(true, |last| now - last >= min_interval)
+}
+
+/// Upload the end of the log file and a state snapshot, unless one was uploaded recently
+pub fn upload(config: &CrashReportConfig, db_pool: &DbPool, log_path: &str, restarts: usize) -> Result<()> {
+    let now = Utc::now();
+    let last_upload = database::get_last_notification_time(db_pool, CRASH_REPORT_TYPE)?;
+    if !upload_allowed(config, last_upload, now) {
+        info!("Not uploading crash report, the last one was uploaded at {:?}", last_upload);
+        return Ok(());
+    }
+
+    let state = database::get_reboot_state(db_pool).unwrap_or_else(|e| {
+        warn!("Failed to read reboot state for crash report: {}", e);
+        None
+    });
+    let log = read_log_tail(log_path).unwrap_or_else(|e| format!("Failed to read log file: {:#}", e));
+    let report = CrashReport {
+        computer_name: std::env::var("COMPUTERNAME").unwrap_or_default(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        timestamp: now,
+        restarts,
+        state,
+        log,
+    };
+
+    info!("Uploading crash report after {} restarts to {}", restarts, config.url);
+    let client = http::client(UPLOAD_TIMEOUT)?;
+    let mut request = client.post(&config.url).json(&report);
+    if let Some(token) = &config.bearer_token {
+        request = request.bearer_auth(token.expose());
+    }
+    let response = request.send().context("Failed to upload crash report")?;
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!("Crash report upload returned HTTP {}", response.status()));
+    }
+    debug!("Crash report uploaded");
+
+    let mut notification = Notification::new(CRASH_REPORT_TYPE, &format!("Crash report after {} restarts", restarts), None);
+    notification.delivery_status = DeliveryStatus::Delivered;
+    database::add_notification(db_pool, &notification)
+}
+
+/// Read the end of the log file
+fn read_log_tail(log_path: &str) -> Result<String> {
+    let path = crate::utils::expand_env_vars(log_path).unwrap_or_else(|_| log_path.to_string());
+    let mut file = File::open(&path).with_context(|| format!("Failed to open {}", path))?;
+    let len = file.metadata()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(MAX_LOG_BYTES)))?;
+
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    Ok(String::from_utf8_lossy(&data).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restart_tracker() {
+        let window = std::time::Duration::from_secs(3600);
+        let start = Instant::now();
+        let mut tracker = RestartTracker::default();
+
+        assert_eq!(tracker.record(start, window), 1);
+        assert_eq!(tracker.record(start + std::time::Duration::from_secs(600), window), 2);
+        assert_eq!(tracker.record(start + std::time::Duration::from_secs(3900), window), 2);
+
+        let config = CrashReportConfig {
+            url: "https://reports.example.com/crash".to_string(),
+            bearer_token: None,
+            restart_threshold: 3,
+            window: "1h".to_string(),
+            min_interval: "24h".to_string(),
+        };
+        let now = Utc::now();
+        assert!(upload_allowed(&config, None, now));
+        assert!(!upload_allowed(&config, Some(now - Duration::hours(2)), now));
+        assert!(upload_allowed(&config, Some(now - Duration::hours(25)), now));
+    }
+}
@@ -1,3 +1,6 @@
+use crate::config::CrashReportConfig;
+use crate::database::DbPool;
+use crate::utils::to_wide;
 use anyhow::Result;
 use log::{debug, error, info, warn};
 use std::path::PathBuf;
@@ -6,8 +9,10 @@ use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 use windows::Win32::System::Services::{
-    CloseServiceHandle, ControlService, OpenSCManagerW, OpenServiceW, QueryServiceStatus,
-    SC_MANAGER_CONNECT, SERVICE_CONTROL_STOP, SERVICE_QUERY_STATUS, SERVICE_START,
+    ChangeServiceConfigW, CloseServiceHandle, ControlService, OpenSCManagerW, OpenServiceW,
+    QueryServiceConfigW, QueryServiceStatus, ENUM_SERVICE_TYPE, QUERY_SERVICE_CONFIGW,
+    SC_MANAGER_CONNECT, SERVICE_AUTO_START, SERVICE_CHANGE_CONFIG, SERVICE_CONTROL_STOP,
+    SERVICE_ERROR, SERVICE_NO_CHANGE, SERVICE_QUERY_CONFIG, SERVICE_QUERY_STATUS, SERVICE_START,
     SERVICE_STATUS, StartServiceW,
 };
 use windows::core::PCWSTR;
@@ -39,6 +44,15 @@ pub struct WatchdogConfig {
     /// Name of the main service
     pub service_name: String,
 
+    /// Whether to set the service back to automatic start if its start type was changed
+    pub restore_start_type: bool,
+
+    /// Diagnostics uploaded when the service keeps restarting
+    pub crash_report: Option<CrashReportConfig>,
+
+    /// Log file included in crash reports
+    pub log_path: String,
+
     /// Power monitor for detecting system power events
     #[allow(dead_code)]
     pub power_checker: Option<PowerEventChecker>,
@@ -55,6 +69,9 @@ impl Clone for WatchdogConfig {
             restart_delay: self.restart_delay.clone(),
             service_path: self.service_path.clone(),
             service_name: self.service_name.clone(),
+            restore_start_type: self.restore_start_type,
+            crash_report: self.crash_report.clone(),
+            log_path: self.log_path.clone(),
             power_checker: None, // Don't clone the power checker
         }
     }
@@ -71,12 +88,16 @@ impl Default for WatchdogConfig {
             restart_delay: Some("10s".to_string()),
             service_path: PathBuf::new(),
             service_name: "RebootReminder".to_string(),
+            restore_start_type: false,
+            crash_report: None,
+            log_path: String::new(),
             power_checker: None,
         }
     }
 }
 
-mod power_events;
+pub mod crash_report;
+pub mod power_events;
 use power_events::{PowerMonitor, PowerEvent, PowerEventChecker};
 
 /// Watchdog service
@@ -87,6 +108,7 @@ pub struct Watchdog {
     power_monitor: Option<PowerMonitor>,
     power_checker: Option<PowerEventChecker>,
     last_service_check: Option<std::time::Instant>,
+    db_pool: Option<DbPool>,
 }
 
 impl Watchdog {
@@ -98,9 +120,16 @@ impl Watchdog {
             power_monitor: None,
             power_checker: None,
             last_service_check: None,
+            db_pool: None,
         }
     }
 
+    /// Use a database for the state snapshot and rate limit of crash reports
+    pub fn with_database(mut self, db_pool: DbPool) -> Self {
+        self.db_pool = Some(db_pool);
+        self
+    }
+
     /// Start the watchdog
     pub fn start(&mut self) -> Result<()> {
         if !self.config.enabled {
@@ -120,6 +149,7 @@ impl Watchdog {
         } else {
             info!("Power monitor started successfully");
             self.power_checker = Some(power_monitor.create_checker());
+            self.power_monitor = Some(power_monitor);
         }
 
         // Initialize last service check time
@@ -128,11 +158,13 @@ impl Watchdog {
         // Clone values for the thread
         let config = self.config.clone();
         let running = self.running.clone();
+        let db_pool = self.db_pool.clone();
 
         // Start watchdog thread
         thread::spawn(move || {
             let mut restart_attempts = 0;
             let mut last_check = std::time::Instant::now();
+            let mut restarts = crash_report::RestartTracker::default();
 
             while running.load(Ordering::SeqCst) {
                 let now = std::time::Instant::now();
@@ -169,6 +201,15 @@ impl Watchdog {
 
                 // Check if it's time to check the service status or if we need to force a check
                 if elapsed.as_secs() >= config.check_interval_seconds || force_check {
+                    // Put the start type back if someone disabled the service
+                    if config.restore_start_type {
+                        match ensure_auto_start(&config.service_name) {
+                            Ok(true) => warn!("Service start type was changed, restored automatic start"),
+                            Ok(false) => debug!("Service start type is automatic"),
+                            Err(e) => error!("Failed to check service start type: {}", e),
+                        }
+                    }
+
                     // Check if the main service is running
                     match is_service_running(&config.service_name) {
                         Ok(true) => {
@@ -200,6 +241,16 @@ impl Watchdog {
                                 }
                             }
 
+                            // Send diagnostics if the service keeps going down
+                            if let (Some(report), Some(db_pool)) = (&config.crash_report, &db_pool) {
+                                let count = restarts.record(std::time::Instant::now(), crash_report::window(report));
+                                if count > report.restart_threshold as usize {
+                                    if let Err(e) = crash_report::upload(report, db_pool, &config.log_path, count) {
+                                        warn!("Failed to upload crash report: {:#}", e);
+                                    }
+                                }
+                            }
+
                             // Wait before checking again
                             thread::sleep(Duration::from_secs(config.restart_delay_seconds));
                         }
@@ -235,7 +286,7 @@ impl Watchdog {
 }
 
 /// Check if a service is running
-fn is_service_running(service_name: &str) -> Result<bool> {
+pub(crate) fn is_service_running(service_name: &str) -> Result<bool> {
     unsafe {
         // Open the service control manager
         let sc_manager = OpenSCManagerW(
@@ -245,7 +296,7 @@ fn is_service_running(service_name: &str) -> Result<bool> {
         )?;
 
         // Convert service name to wide string
-        let service_name_wide: Vec<u16> = service_name.encode_utf16().chain(std::iter::once(0)).collect();
+        let service_name_wide = to_wide(service_name);
 
         // Open the service
         let service = OpenServiceW(
@@ -277,6 +328,71 @@ fn is_service_running(service_name: &str) -> Result<bool> {
     }
 }
 
+/// Make sure a service is set to start automatically
+///
+/// Returns true if the start type had to be restored.
+fn ensure_auto_start(service_name: &str) -> Result<bool> {
+    unsafe {
+        // Open the service control manager
+        let sc_manager = OpenSCManagerW(
+            PCWSTR::null(),
+            PCWSTR::null(),
+            SC_MANAGER_CONNECT,
+        )?;
+
+        // Convert service name to wide string
+        let service_name_wide = to_wide(service_name);
+
+        // Open the service with permission to read and change its configuration
+        let service = OpenServiceW(
+            sc_manager,
+            PCWSTR::from_raw(service_name_wide.as_ptr()),
+            SERVICE_QUERY_CONFIG | SERVICE_CHANGE_CONFIG,
+        );
+
+        // Close the service control manager when we're done with it
+        let _ = CloseServiceHandle(sc_manager);
+        let service = service?;
+
+        // Get the required buffer size, then query the configuration
+        let mut bytes_needed = 0u32;
+        let _ = QueryServiceConfigW(service, None, 0, &mut bytes_needed);
+
+        let mut buffer = vec![0u8; bytes_needed as usize];
+        let service_config = buffer.as_mut_ptr() as *mut QUERY_SERVICE_CONFIGW;
+        if let Err(e) = QueryServiceConfigW(service, Some(service_config), bytes_needed, &mut bytes_needed) {
+            let _ = CloseServiceHandle(service);
+            return Err(anyhow::anyhow!("Failed to query service configuration: {}", e));
+        }
+
+        if (*service_config).dwStartType == SERVICE_AUTO_START {
+            let _ = CloseServiceHandle(service);
+            return Ok(false);
+        }
+
+        // Only change the start type
+        let result = ChangeServiceConfigW(
+            service,
+            ENUM_SERVICE_TYPE(SERVICE_NO_CHANGE),
+            SERVICE_AUTO_START,
+            SERVICE_ERROR(SERVICE_NO_CHANGE),
+            PCWSTR::null(),
+            PCWSTR::null(),
+            None,
+            PCWSTR::null(),
+            PCWSTR::null(),
+            PCWSTR::null(),
+            PCWSTR::null(),
+        );
+
+        // Close the service handle when we're done with it
+        let _ = CloseServiceHandle(service);
+
+        result.map_err(|e| anyhow::anyhow!("Failed to restore service start type: {}", e))?;
+        Ok(true)
+    }
+}
+
 /// Restart a service
 fn restart_service(service_name: &str) -> Result<()> {
     unsafe {
@@ -288,7 +404,7 @@ fn restart_service(service_name: &str) -> Result<()> {
         )?;
 
         // Convert service name to wide string
-        let service_name_wide: Vec<u16> = service_name.encode_utf16().chain(std::iter::once(0)).collect();
+        let service_name_wide = to_wide(service_name);
 
         // Open the service with stop and start permissions
         let service = OpenServiceW(
@@ -1,5 +1,7 @@
-use log::info;
+use log::{debug, info};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{channel, Sender, Receiver};
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::{Duration, Instant};
 use anyhow::Result;
@@ -36,45 +38,54 @@ impl PowerEventChecker {
             Err(_) => None,
         }
     }
+
+    /// Drain all pending power events
+    pub fn drain_events(&self) -> Vec<PowerEvent> {
+        let mut events = Vec::new();
+        while let Some(event) = self.check_events() {
+            events.push(event);
+        }
+        events
+    }
 }
 
 /// Power event monitor
 #[derive(Debug)]
 pub struct PowerMonitor {
-    sender: Sender<PowerEvent>,
-    running: bool,
+    subscribers: Arc<Mutex<Vec<Sender<PowerEvent>>>>,
+    running: Arc<AtomicBool>,
     last_check: Instant,
 }
 
 impl PowerMonitor {
     /// Create a new power monitor
     pub fn new() -> Self {
-        let (sender, _) = channel();
         Self {
-            sender,
-            running: false,
+            subscribers: Arc::new(Mutex::new(Vec::new())),
+            running: Arc::new(AtomicBool::new(false)),
             last_check: Instant::now(),
         }
     }
 
     /// Start monitoring power events
     pub fn start(&mut self) -> Result<()> {
-        if self.running {
+        if self.running.load(Ordering::SeqCst) {
             return Ok(());
         }
 
         info!("Starting power event monitoring");
-        self.running = true;
+        self.running.store(true, Ordering::SeqCst);
         self.last_check = Instant::now();
 
-        // Clone the sender for the thread
-        let sender = self.sender.clone();
+        // Clone the shared state for the thread
+        let subscribers = self.subscribers.clone();
+        let running = self.running.clone();
 
         // Start a thread to monitor power events
         thread::spawn(move || {
             let mut last_check = Instant::now();
 
-            loop {
+            while running.load(Ordering::SeqCst) {
                 thread::sleep(Duration::from_secs(5));
 
                 let now = Instant::now();
@@ -84,11 +95,13 @@ impl PowerMonitor {
                 // it's likely the system was suspended
                 if elapsed.as_secs() > 30 {
                     info!("Detected possible system resume (elapsed: {}s)", elapsed.as_secs());
-                    let _ = sender.send(PowerEvent::Resume);
+                    broadcast(&subscribers, PowerEvent::Resume);
                 }
 
                 last_check = now;
             }
+
+            debug!("Power event monitoring thread exiting");
         });
 
         info!("Power event monitoring started successfully");
@@ -96,30 +109,30 @@ impl PowerMonitor {
     }
 
     /// Create a new event checker
+    ///
+    /// Every checker receives its own copy of each event detected by the monitor.
     pub fn create_checker(&self) -> PowerEventChecker {
         let (sender, receiver) = channel();
 
-        // Clone the sender for the thread
-        let new_sender = sender;
-
-        // Create a thread to forward events
-        thread::spawn(move || {
-            loop {
-                // We can't directly receive from a sender, so we'll just send a resume event periodically
-                // This is a simplified approach - in a real implementation, you would use a shared queue
-                thread::sleep(Duration::from_secs(10));
-                let _ = new_sender.send(PowerEvent::Resume);
-                thread::sleep(Duration::from_millis(100));
-            }
-        });
+        match self.subscribers.lock() {
+            Ok(mut subscribers) => subscribers.push(sender),
+            Err(e) => debug!("Failed to register power event checker: {}", e),
+        }
 
         PowerEventChecker { receiver }
     }
 }
 
+/// Send an event to every registered checker, dropping checkers that have gone away
+fn broadcast(subscribers: &Arc<Mutex<Vec<Sender<PowerEvent>>>>, event: PowerEvent) {
+    if let Ok(mut subscribers) = subscribers.lock() {
+        subscribers.retain(|sender| sender.send(event).is_ok());
+    }
+}
+
 impl Drop for PowerMonitor {
     fn drop(&mut self) {
         info!("Stopping power event monitoring");
-        self.running = false;
+        self.running.store(false, Ordering::SeqCst);
     }
 }